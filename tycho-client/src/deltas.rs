@@ -20,7 +20,7 @@
 //! Therefore, sharing one client among multiple tasks ensures optimal performance, reduces resource
 //! consumption, and enhances overall software scalability.
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -58,7 +58,13 @@ use tokio_tungstenite::{
     MaybeTlsStream, WebSocketStream,
 };
 use tracing::{debug, error, info, instrument, trace, warn};
-use tycho_common::dto::{BlockChanges, Command, ExtractorIdentity, Response, WebSocketMessage};
+use tycho_common::{
+    dto::{
+        BlockChanges, Command, ExtractorIdentity, MessageGranularity, Response, SubscriptionFilter,
+        WebSocketMessage, SCHEMA_VERSION,
+    },
+    serde_primitives::ValueEncoding,
+};
 use uuid::Uuid;
 
 use crate::TYCHO_SERVER_VERSION;
@@ -110,11 +116,21 @@ pub enum DeltasError {
 #[derive(Clone, Debug)]
 pub struct SubscriptionOptions {
     include_state: bool,
+    filter: Option<SubscriptionFilter>,
+    heartbeats: bool,
+    attributes: Option<HashSet<String>>,
+    coalesce_window: Option<Duration>,
 }
 
 impl Default for SubscriptionOptions {
     fn default() -> Self {
-        Self { include_state: true }
+        Self {
+            include_state: true,
+            filter: None,
+            heartbeats: false,
+            attributes: None,
+            coalesce_window: None,
+        }
     }
 }
 
@@ -126,6 +142,29 @@ impl SubscriptionOptions {
         self.include_state = val;
         self
     }
+    /// Only receive changes of the components, tokens and contracts matched by the filter.
+    pub fn with_filter(mut self, filter: SubscriptionFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+    /// Also receive a message for blocks without any tracked changes. These carry only the block
+    /// and confirm that the extractor is keeping up with the chain.
+    pub fn with_heartbeats(mut self, val: bool) -> Self {
+        self.heartbeats = val;
+        self
+    }
+    /// Only receive changes of the given component attributes.
+    pub fn with_attributes(mut self, attributes: HashSet<String>) -> Self {
+        self.attributes = Some(attributes);
+        self
+    }
+    /// Receive at most one message per window, carrying the merged changes of all blocks since
+    /// the previous one. Intermediate blocks are skipped, so this is only suitable for consumers
+    /// that don't need to observe every block.
+    pub fn with_coalesce_window(mut self, window: Duration) -> Self {
+        self.coalesce_window = Some(window);
+        self
+    }
 }
 
 #[cfg_attr(test, automock)]
@@ -496,11 +535,32 @@ impl WsDeltasClient {
                                 _ => { /* Do nothing */ }
                             }
                         }
+                        WebSocketMessage::TransactionChanges { subscription_id, .. } => {
+                            // This client only subscribes with block granularity.
+                            warn!(?subscription_id, "Unexpected transaction message, ignoring");
+                        }
+                        WebSocketMessage::ComponentSnapshots { subscription_id, .. } => {
+                            // This client fetches snapshots over rpc and never requests them.
+                            warn!(?subscription_id, "Unexpected snapshot message, ignoring");
+                        }
+                        WebSocketMessage::Gap { subscription_id, dropped_messages } => {
+                            // Deltas are missing, the state kept by the consumer may be stale.
+                            error!(
+                                ?subscription_id,
+                                dropped_messages, "Server dropped messages of a slow subscription"
+                            );
+                        }
                         WebSocketMessage::Response(Response::NewSubscription {
                             extractor_id,
                             subscription_id,
+                            schema_version,
                         }) => {
-                            info!(?extractor_id, ?subscription_id, "Received a new subscription");
+                            info!(
+                                ?extractor_id,
+                                ?subscription_id,
+                                ?schema_version,
+                                "Received a new subscription"
+                            );
                             let inner = guard
                                 .as_mut()
                                 .ok_or_else(|| DeltasError::NotConnected)?;
@@ -612,7 +672,21 @@ impl DeltasClient for WsDeltasClient {
                 .ok_or_else(|| DeltasError::NotConnected)?;
             trace!("Sending subscribe command");
             inner.new_subscription(&extractor_id, ready_tx)?;
-            let cmd = Command::Subscribe { extractor_id, include_state: options.include_state };
+            let cmd = Command::Subscribe {
+                extractor_id,
+                include_state: options.include_state,
+                schema_versions: Some(vec![SCHEMA_VERSION]),
+                granularity: MessageGranularity::Block,
+                filter: options.filter,
+                heartbeats: options.heartbeats,
+                snapshots: false,
+                // Deserialization of the messages only supports hex
+                value_encoding: ValueEncoding::Hex,
+                attributes: options.attributes,
+                coalesce_window_ms: options
+                    .coalesce_window
+                    .map(|window| window.as_millis() as u64),
+            };
             inner
                 .ws_send(tungstenite::protocol::Message::Text(
                     serde_json::to_string(&cmd).map_err(|e| {
@@ -887,7 +961,8 @@ mod tests {
                         "chain":"ethereum",
                         "name":"vm:ambient"
                     },
-                    "include_state": true
+                    "include_state": true,
+                    "schema_versions": [2]
                 }"#.to_owned().replace(|c: char| c.is_whitespace(), "")
             )),
             ExpectedComm::Send(tungstenite::protocol::Message::Text(r#"
@@ -1027,7 +1102,8 @@ mod tests {
                         "chain": "ethereum",
                         "name": "vm:ambient"
                     },
-                    "include_state": true
+                    "include_state": true,
+                    "schema_versions": [2]
                 }"#
                     .to_owned()
                     .replace(|c: char| c.is_whitespace(), ""),
@@ -1122,7 +1198,8 @@ mod tests {
                         "chain":"ethereum",
                         "name":"vm:ambient"
                     },
-                    "include_state": true
+                    "include_state": true,
+                    "schema_versions": [2]
                 }"#
                     .to_owned()
                     .replace(|c: char| c.is_whitespace(), ""),
@@ -1195,7 +1272,8 @@ mod tests {
                         "chain":"ethereum",
                         "name":"vm:ambient"
                     },
-                    "include_state": true
+                    "include_state": true,
+                    "schema_versions": [2]
                 }"#.to_owned().replace(|c: char| c.is_whitespace(), "")
             )),
             ExpectedComm::Send(tungstenite::protocol::Message::Text(r#"
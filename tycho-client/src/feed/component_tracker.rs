@@ -414,6 +414,7 @@ mod test {
                 Ok(ProtocolComponentRequestResponse {
                     protocol_components: vec![component.clone()],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 1 },
+                    next_cursor: None,
                 })
             });
 
@@ -446,6 +447,7 @@ mod test {
                 Ok(ProtocolComponentRequestResponse {
                     protocol_components: vec![component.clone()],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 1 },
+                    next_cursor: None,
                 })
             });
 
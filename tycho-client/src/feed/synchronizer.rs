@@ -1013,6 +1013,7 @@ mod test {
                 ResponseAccount { address: Bytes::from("0xbabe42"), ..Default::default() },
             ],
             pagination: PaginationResponse { page: 0, page_size: 20, total: 1 },
+            slot_annotations: Vec::new(),
         }
     }
 
@@ -1212,6 +1213,7 @@ mod test {
                         ProtocolComponent { id: "Component3".to_string(), ..Default::default() },
                     ],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 1 },
+                    next_cursor: None,
                 })
             });
         rpc_client
@@ -1249,6 +1251,7 @@ mod test {
                         // a third component will have a tvl update above threshold
                     ],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 1 },
+                    next_cursor: None,
                 })
             });
         rpc_client
@@ -1329,6 +1332,7 @@ mod test {
                     parent_hash: Bytes::from("0x00"),
                     chain: Chain::Ethereum,
                     ts: Default::default(),
+                    finality_status: None,
                 },
                 revert: false,
                 dci_update: DCIUpdate {
@@ -1375,6 +1379,7 @@ mod test {
                     parent_hash: Bytes::from("0x01"),
                     chain: Chain::Ethereum,
                     ts: Default::default(),
+                    finality_status: None,
                 },
                 revert: false,
                 component_tvl: [
@@ -1510,6 +1515,7 @@ mod test {
                     parent_hash: Bytes::from("0x01"),
                     chain: Chain::Ethereum,
                     ts: Default::default(),
+                    finality_status: None,
                 },
                 revert: false,
                 component_tvl: [
@@ -1561,6 +1567,7 @@ mod test {
                         ..Default::default()
                     }],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 1 },
+                    next_cursor: None,
                 })
             });
         rpc_client
@@ -1593,6 +1600,7 @@ mod test {
                         ProtocolComponent { id: "Component2".to_string(), ..Default::default() },
                     ],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 1 },
+                    next_cursor: None,
                 })
             });
         rpc_client
@@ -1688,6 +1696,7 @@ mod test {
                     parent_hash: Bytes::from("0x00"),
                     chain: Chain::Ethereum,
                     ts: Default::default(),
+                    finality_status: None,
                 },
                 revert: false,
                 ..Default::default()
@@ -1701,6 +1710,7 @@ mod test {
                     parent_hash: Bytes::from("0x01"),
                     chain: Chain::Ethereum,
                     ts: Default::default(),
+                    finality_status: None,
                 },
                 revert: false,
                 component_tvl: [
@@ -1782,6 +1792,7 @@ mod test {
                     parent_hash: Bytes::from("0x01"),
                     chain: Chain::Ethereum,
                     ts: Default::default(),
+                    finality_status: None,
                 },
                 revert: false,
                 component_tvl: [
@@ -1822,6 +1833,7 @@ mod test {
                 Ok(ProtocolComponentRequestResponse {
                     protocol_components: vec![],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 0 },
+                    next_cursor: None,
                 })
             });
 
@@ -1888,6 +1900,7 @@ mod test {
                 Ok(ProtocolComponentRequestResponse {
                     protocol_components: vec![],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 0 },
+                    next_cursor: None,
                 })
             });
 
@@ -1913,6 +1926,7 @@ mod test {
                         parent_hash: Bytes::from("0x0000"),
                         chain: Chain::Ethereum,
                         ts: chrono::NaiveDateTime::from_timestamp_opt(1234567890, 0).unwrap(),
+                        finality_status: None,
                     },
                     revert: false,
                     // Add a new component to trigger snapshot request
@@ -1929,6 +1943,9 @@ mod test {
                             creation_tx: Default::default(),
                             created_at: Default::default(),
                             change: Default::default(),
+                            inactive_since: None,
+                            tvl_usd: None,
+                            extractor: None,
                         },
                     )]
                     .into_iter()
@@ -2007,6 +2024,7 @@ mod test {
                 Ok(ProtocolComponentRequestResponse {
                     protocol_components: vec![],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 0 },
+                    next_cursor: None,
                 })
             });
 
@@ -2078,6 +2096,7 @@ mod test {
                 Ok(ProtocolComponentRequestResponse {
                     protocol_components: vec![],
                     pagination: PaginationResponse { page: 0, page_size: 20, total: 0 },
+                    next_cursor: None,
                 })
             });
 
@@ -2124,6 +2143,7 @@ mod test {
                         parent_hash: Bytes::from("0x0000"),
                         chain: Chain::Ethereum,
                         ts: chrono::NaiveDateTime::from_timestamp_opt(1234567890, 0).unwrap(),
+                        finality_status: None,
                     },
                     revert: false,
                     ..Default::default()
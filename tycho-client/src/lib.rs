@@ -23,17 +23,31 @@
 //!
 //! - `rpc` module provides utilities for retrieving snapshots, and associated data such as tokens.
 //! - `updates` module handles receiving and processing updates messages from the server.
+//!
+//! ## Features
+//!
+//! - `stream` (default): the websocket deltas client, the feed and the stream builder. They need a
+//!   native tokio runtime.
+//! - `cli` (default): the command line client, implies `stream`.
+//!
+//! Without default features only the [`rpc`] client is built, which together with the dto types
+//! of `tycho-common` also compiles to `wasm32-unknown-unknown`.
 const TYCHO_SERVER_VERSION: &str = "v1";
 
+#[cfg(feature = "cli")]
 pub mod cli;
+#[cfg(feature = "stream")]
 pub mod deltas;
+#[cfg(feature = "stream")]
 pub mod feed;
 pub mod rpc;
+#[cfg(feature = "stream")]
 pub mod stream;
 
 #[cfg(test)]
 #[macro_use]
 extern crate pretty_assertions;
 
+#[cfg(feature = "stream")]
 pub use deltas::{DeltasError, WsDeltasClient};
 pub use rpc::{HttpRPCClient, RPCError};
@@ -15,7 +15,7 @@ use tokio::sync::Semaphore;
 use tracing::{debug, error, instrument, trace, warn};
 use tycho_common::{
     dto::{
-        Chain, ComponentTvlRequestBody, ComponentTvlRequestResponse, PaginationParams,
+        Chain, CodeMode, ComponentTvlRequestBody, ComponentTvlRequestResponse, PaginationParams,
         PaginationResponse, ProtocolComponentRequestResponse, ProtocolComponentsRequestBody,
         ProtocolStateRequestBody, ProtocolStateRequestResponse, ProtocolSystemsRequestBody,
         ProtocolSystemsRequestResponse, ResponseToken, StateRequestBody, StateRequestResponse,
@@ -51,7 +51,8 @@ pub enum RPCError {
 }
 
 #[cfg_attr(test, automock)]
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait RPCClient: Send + Sync {
     /// Retrieves a snapshot of contract state.
     async fn get_contract_state(
@@ -78,6 +79,14 @@ pub trait RPCClient: Send + Sync {
                 chain,
                 version: version.clone(),
                 pagination: PaginationParams { page: 0, page_size: chunk_size as i64 },
+                code_hash: None,
+                min_balance: None,
+                max_balance: None,
+                order_by_balance: None,
+                has_code: None,
+                fields: None,
+                code: Some(CodeMode::Full),
+                annotate: false,
             })
             .collect::<Vec<_>>();
 
@@ -109,6 +118,7 @@ pub trait RPCClient: Send + Sync {
         Ok(StateRequestResponse {
             accounts,
             pagination: PaginationResponse { page: 0, page_size: chunk_size as i64, total },
+            slot_annotations: Vec::new(),
         })
     }
 
@@ -137,11 +147,16 @@ pub trait RPCClient: Send + Sync {
                         protocol_system: request.protocol_system.clone(),
                         component_ids: request.component_ids.clone(),
                         tvl_gt: request.tvl_gt,
+                        min_tvl_usd: request.min_tvl_usd,
                         chain: request.chain,
                         pagination: PaginationParams {
                             page: index as i64,
                             page_size: chunk_size as i64,
                         },
+                        fields: request.fields.clone(),
+                        extractor: request.extractor.clone(),
+                        tokens: request.tokens.clone(),
+                        cursor: None,
                     })
                     .collect::<Vec<_>>();
 
@@ -169,6 +184,7 @@ pub trait RPCClient: Send + Sync {
                             page_size: chunk_size as i64,
                             total: ids.len() as i64,
                         },
+                        next_cursor: None,
                     })
             }
             _ => {
@@ -179,8 +195,13 @@ pub trait RPCClient: Send + Sync {
                     protocol_system: request.protocol_system.clone(),
                     component_ids: request.component_ids.clone(),
                     tvl_gt: request.tvl_gt,
+                    min_tvl_usd: request.min_tvl_usd,
                     chain: request.chain,
                     pagination: PaginationParams { page: 0, page_size: chunk_size as i64 },
+                    fields: request.fields.clone(),
+                    extractor: request.extractor.clone(),
+                    tokens: request.tokens.clone(),
+                    cursor: None,
                 };
                 let first_response = self
                     .get_protocol_components(&initial_request)
@@ -198,6 +219,7 @@ pub trait RPCClient: Send + Sync {
                         page_size: chunk_size as i64,
                         total: total_items,
                     },
+                    next_cursor: None,
                 };
 
                 let mut page = 1;
@@ -210,11 +232,16 @@ pub trait RPCClient: Send + Sync {
                             protocol_system: request.protocol_system.clone(),
                             component_ids: request.component_ids.clone(),
                             tvl_gt: request.tvl_gt,
+                            min_tvl_usd: request.min_tvl_usd,
                             chain: request.chain,
                             pagination: PaginationParams {
                                 page: page + iter,
                                 page_size: chunk_size as i64,
                             },
+                            fields: request.fields.clone(),
+                            extractor: request.extractor.clone(),
+                            tokens: request.tokens.clone(),
+                            cursor: None,
                         })
                         .collect::<Vec<_>>();
 
@@ -245,6 +272,7 @@ pub trait RPCClient: Send + Sync {
                                     page_size: chunk_size as i64,
                                     total,
                                 },
+                                next_cursor: None,
                             }
                         });
 
@@ -599,16 +627,19 @@ impl HttpRPCClient {
             headers.insert(header::AUTHORIZATION, auth_value);
         }
 
-        let client = ClientBuilder::new()
-            .default_headers(headers)
-            .http2_prior_knowledge()
+        let builder = ClientBuilder::new().default_headers(headers);
+        // Browsers negotiate the protocol themselves.
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = builder.http2_prior_knowledge();
+        let client = builder
             .build()
             .map_err(|e| RPCError::HttpClient(e.to_string()))?;
         Ok(Self { http_client: client, url: uri })
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl RPCClient for HttpRPCClient {
     #[instrument(skip(self, request))]
     async fn get_contract_state(
@@ -656,6 +687,7 @@ impl RPCClient for HttpRPCClient {
                     page_size: request.pagination.page,
                     total: 0,
                 },
+                slot_annotations: Vec::new(),
             });
         }
 
@@ -1226,6 +1258,7 @@ mod tests {
                 tax: 0,
                 gas: vec![Some(29962)],
                 quality: 100,
+                ..Default::default()
             },
             ResponseToken {
                 chain: Chain::Ethereum,
@@ -1235,6 +1268,7 @@ mod tests {
                 tax: 0,
                 gas: vec![Some(40652)],
                 quality: 100,
+                ..Default::default()
             },
         ];
 
@@ -6,7 +6,7 @@
 //! Structs in here implement utoipa traits so they can be used to derive an OpenAPI schema.
 #![allow(deprecated)]
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt,
     hash::{Hash, Hasher},
 };
@@ -20,7 +20,9 @@ use uuid::Uuid;
 use crate::{
     models::{self, blockchain::BlockAggregatedChanges, Address, ComponentId, StoreKey, StoreVal},
     serde_primitives::{
-        hex_bytes, hex_bytes_option, hex_hashmap_key, hex_hashmap_key_value, hex_hashmap_value,
+        encoded_bytes, encoded_bytes_option, hex_bytes, hex_bytes_option,
+        hex_hashmap_encoded_value, hex_hashmap_key, hex_hashmap_key_encoded_value,
+        hex_hashmap_value, sorted_map, sorted_map_of_sets, sorted_set, ValueEncoding,
     },
     Bytes,
 };
@@ -136,20 +138,128 @@ impl fmt::Display for ExtractorIdentity {
     }
 }
 
+/// Latest schema version of the messages streamed over the websocket.
+///
+/// Version history:
+/// - 1: `BlockChanges` without `account_balances` and `dci_update`.
+/// - 2: current format.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Oldest schema version the server is still able to down-convert to.
+pub const MIN_SCHEMA_VERSION: u32 = 1;
+
+/// Picks the newest schema version that both the client and this server support.
+///
+/// Clients that don't declare any versions predate the negotiation and receive the latest
+/// schema.
+pub fn negotiate_schema_version(client_versions: Option<&[u32]>) -> Option<u32> {
+    match client_versions {
+        None => Some(SCHEMA_VERSION),
+        Some(versions) => versions
+            .iter()
+            .copied()
+            .filter(|v| (MIN_SCHEMA_VERSION..=SCHEMA_VERSION).contains(v))
+            .max(),
+    }
+}
+
 /// A command sent from the client to the server
+#[allow(clippy::large_enum_variant)]
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
 #[serde(tag = "method", rename_all = "lowercase")]
 pub enum Command {
-    Subscribe { extractor_id: ExtractorIdentity, include_state: bool },
-    Unsubscribe { subscription_id: Uuid },
+    Subscribe {
+        extractor_id: ExtractorIdentity,
+        include_state: bool,
+        /// Message schema versions supported by the client. If omitted the latest version is
+        /// used.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        schema_versions: Option<Vec<u32>>,
+        /// Whether to receive one message per block or per transaction. Defaults to blocks.
+        #[serde(default, skip_serializing_if = "MessageGranularity::is_block")]
+        granularity: MessageGranularity,
+        /// Only receive changes touching the given components, tokens or contracts. If omitted
+        /// all changes of the extractor are sent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        filter: Option<SubscriptionFilter>,
+        /// Whether to receive a message carrying only the block for blocks the extractor saw no
+        /// tracked changes in. Lets clients tell an idle extractor from a stalled one.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        heartbeats: bool,
+        /// Whether to receive the current state of filtered components before their first deltas.
+        /// Covers components given by id and existing components the filter starts matching
+        /// mid-stream. Components created after subscribing are never snapshotted, their creation
+        /// already carries their full state. Has no effect without a filter.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        snapshots: bool,
+        /// How balances, storage values and attributes are encoded in messages of this
+        /// subscription. Defaults to hex, which is the only encoding this crate can deserialize.
+        #[serde(default, skip_serializing_if = "ValueEncoding::is_hex")]
+        value_encoding: ValueEncoding,
+        /// Only receive changes of these component attributes. State updates left without any
+        /// of them are dropped. If omitted all attributes are sent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attributes: Option<HashSet<String>>,
+        /// Buffer the changes of this many milliseconds and send them merged into a single
+        /// message carrying the latest block. Reverts flush the buffer and are sent on their
+        /// own. Only applies to block granularity.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        coalesce_window_ms: Option<u64>,
+    },
+    Unsubscribe {
+        subscription_id: Uuid,
+    },
+}
+
+/// Granularity of the change messages sent on a subscription.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageGranularity {
+    /// One [`BlockChanges`] message per block with the changes of all transactions aggregated.
+    #[default]
+    Block,
+    /// One [`TransactionChangesMsg`] per transaction, in the order of execution. Reverts are
+    /// still sent as aggregated [`BlockChanges`].
+    Transaction,
+}
+
+impl MessageGranularity {
+    pub fn is_block(&self) -> bool {
+        *self == MessageGranularity::Block
+    }
+}
+
+/// Server side filter of the changes sent on a subscription.
+///
+/// A component is included if it matches any of the criteria. Changes to contracts are included
+/// if the contract is listed or belongs to an included component.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct SubscriptionFilter {
+    /// Include these components.
+    #[serde(default)]
+    pub component_ids: HashSet<String>,
+    /// Include components that hold any of these tokens.
+    #[serde(default)]
+    pub tokens: HashSet<Bytes>,
+    /// Include components using any of these contracts, as well as the contracts themselves.
+    #[serde(default)]
+    pub contracts: HashSet<Bytes>,
 }
 
 /// A response sent from the server to the client
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 #[serde(tag = "method", rename_all = "lowercase")]
 pub enum Response {
-    NewSubscription { extractor_id: ExtractorIdentity, subscription_id: Uuid },
-    SubscriptionEnded { subscription_id: Uuid },
+    NewSubscription {
+        extractor_id: ExtractorIdentity,
+        subscription_id: Uuid,
+        /// Schema version of the messages sent on this subscription.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        schema_version: Option<u32>,
+    },
+    SubscriptionEnded {
+        subscription_id: Uuid,
+    },
 }
 
 /// A message sent from the server to the client
@@ -157,10 +267,41 @@ pub enum Response {
 #[derive(Serialize, Deserialize, Debug, Display, Clone)]
 #[serde(untagged)]
 pub enum WebSocketMessage {
-    BlockChanges { subscription_id: Uuid, deltas: BlockChanges },
+    BlockChanges {
+        subscription_id: Uuid,
+        deltas: BlockChanges,
+    },
+    TransactionChanges {
+        subscription_id: Uuid,
+        changes: TransactionChangesMsg,
+    },
+    /// State of components a subscription starts tracking, sent ahead of their deltas.
+    ComponentSnapshots {
+        subscription_id: Uuid,
+        snapshots: Vec<ResponseProtocolState>,
+    },
+    /// Messages of a subscription the server dropped because the client didn't keep up. The
+    /// subscription continues, but the client missed `dropped_messages` messages before this one.
+    Gap {
+        subscription_id: Uuid,
+        dropped_messages: u64,
+    },
     Response(Response),
 }
 
+impl WebSocketMessage {
+    /// The subscription a message belongs to, `None` for responses to commands.
+    pub fn subscription_id(&self) -> Option<Uuid> {
+        match self {
+            WebSocketMessage::BlockChanges { subscription_id, .. } |
+            WebSocketMessage::TransactionChanges { subscription_id, .. } |
+            WebSocketMessage::ComponentSnapshots { subscription_id, .. } |
+            WebSocketMessage::Gap { subscription_id, .. } => Some(*subscription_id),
+            WebSocketMessage::Response(_) => None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize, Default, ToSchema)]
 pub struct Block {
     pub number: u64,
@@ -170,6 +311,31 @@ pub struct Block {
     pub parent_hash: Bytes,
     pub chain: Chain,
     pub ts: NaiveDateTime,
+    /// Finality of the block at the time the message was sent, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finality_status: Option<FinalityStatus>,
+}
+
+/// Finality of a block on its chain.
+#[derive(
+    Debug, PartialEq, Eq, Copy, Clone, Hash, Deserialize, Serialize, ToSchema, EnumString, Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum FinalityStatus {
+    Pending,
+    Safe,
+    Finalized,
+}
+
+impl From<models::blockchain::FinalityStatus> for FinalityStatus {
+    fn from(value: models::blockchain::FinalityStatus) -> Self {
+        match value {
+            models::blockchain::FinalityStatus::Pending => Self::Pending,
+            models::blockchain::FinalityStatus::Safe => Self::Safe,
+            models::blockchain::FinalityStatus::Finalized => Self::Finalized,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, ToSchema, Eq, Hash)]
@@ -195,10 +361,16 @@ impl From<&Block> for BlockParam {
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
-pub struct TokenBalances(#[serde(with = "hex_hashmap_key")] pub HashMap<Bytes, ComponentBalance>);
+pub struct TokenBalances(#[serde(with = "hex_hashmap_key")] pub BTreeMap<Bytes, ComponentBalance>);
 
 impl From<HashMap<Bytes, ComponentBalance>> for TokenBalances {
     fn from(value: HashMap<Bytes, ComponentBalance>) -> Self {
+        TokenBalances(value.into_iter().collect())
+    }
+}
+
+impl From<BTreeMap<Bytes, ComponentBalance>> for TokenBalances {
+    fn from(value: BTreeMap<Bytes, ComponentBalance>) -> Self {
         TokenBalances(value)
     }
 }
@@ -223,6 +395,8 @@ impl Transaction {
 }
 
 /// A container for updates grouped by account/component.
+///
+/// All collections are ordered so that equal messages serialize to identical bytes.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
 pub struct BlockChanges {
     pub extractor: String,
@@ -231,16 +405,16 @@ pub struct BlockChanges {
     pub finalized_block_height: u64,
     pub revert: bool,
     #[serde(with = "hex_hashmap_key", default)]
-    pub new_tokens: HashMap<Bytes, ResponseToken>,
+    pub new_tokens: BTreeMap<Bytes, ResponseToken>,
     #[serde(alias = "account_deltas", with = "hex_hashmap_key")]
-    pub account_updates: HashMap<Bytes, AccountUpdate>,
+    pub account_updates: BTreeMap<Bytes, AccountUpdate>,
     #[serde(alias = "state_deltas")]
-    pub state_updates: HashMap<String, ProtocolStateDelta>,
-    pub new_protocol_components: HashMap<String, ProtocolComponent>,
-    pub deleted_protocol_components: HashMap<String, ProtocolComponent>,
-    pub component_balances: HashMap<String, TokenBalances>,
-    pub account_balances: HashMap<Bytes, HashMap<Bytes, AccountBalance>>,
-    pub component_tvl: HashMap<String, f64>,
+    pub state_updates: BTreeMap<String, ProtocolStateDelta>,
+    pub new_protocol_components: BTreeMap<String, ProtocolComponent>,
+    pub deleted_protocol_components: BTreeMap<String, ProtocolComponent>,
+    pub component_balances: BTreeMap<String, TokenBalances>,
+    pub account_balances: BTreeMap<Bytes, BTreeMap<Bytes, AccountBalance>>,
+    pub component_tvl: BTreeMap<String, f64>,
     pub dci_update: DCIUpdate,
 }
 
@@ -266,21 +440,40 @@ impl BlockChanges {
             block,
             finalized_block_height,
             revert,
-            new_tokens: HashMap::new(),
-            account_updates,
-            state_updates,
-            new_protocol_components,
-            deleted_protocol_components,
+            new_tokens: BTreeMap::new(),
+            account_updates: account_updates.into_iter().collect(),
+            state_updates: state_updates.into_iter().collect(),
+            new_protocol_components: new_protocol_components
+                .into_iter()
+                .collect(),
+            deleted_protocol_components: deleted_protocol_components
+                .into_iter()
+                .collect(),
             component_balances: component_balances
                 .into_iter()
                 .map(|(k, v)| (k, v.into()))
                 .collect(),
-            account_balances,
-            component_tvl: HashMap::new(),
+            account_balances: account_balances
+                .into_iter()
+                .map(|(k, v)| (k, v.into_iter().collect()))
+                .collect(),
+            component_tvl: BTreeMap::new(),
             dci_update,
         }
     }
 
+    /// Down-converts the message to an older schema version.
+    ///
+    /// Fields that did not exist in the requested version are cleared. Versions newer than
+    /// [`SCHEMA_VERSION`] leave the message untouched.
+    pub fn into_schema_version(mut self, version: u32) -> Self {
+        if version < 2 {
+            self.account_balances = BTreeMap::new();
+            self.dci_update = DCIUpdate::default();
+        }
+        self
+    }
+
     pub fn merge(mut self, other: Self) -> Self {
         other
             .account_updates
@@ -328,12 +521,15 @@ impl BlockChanges {
 
         self.component_tvl
             .extend(other.component_tvl);
+        self.new_tokens.extend(other.new_tokens);
         self.new_protocol_components
             .extend(other.new_protocol_components);
         self.deleted_protocol_components
             .extend(other.deleted_protocol_components);
+        self.dci_update.merge(other.dci_update);
         self.revert = other.revert;
         self.block = other.block;
+        self.finalized_block_height = other.finalized_block_height;
 
         self
     }
@@ -374,8 +570,8 @@ impl BlockChanges {
             finalized_block_height: self.finalized_block_height,
             revert: self.revert,
             new_tokens: self.new_tokens.clone(),
-            account_updates: HashMap::new(),
-            state_updates: HashMap::new(),
+            account_updates: BTreeMap::new(),
+            state_updates: BTreeMap::new(),
             new_protocol_components: self.new_protocol_components.clone(),
             deleted_protocol_components: self.deleted_protocol_components.clone(),
             component_balances: self.component_balances.clone(),
@@ -394,6 +590,7 @@ impl From<models::blockchain::Block> for Block {
             parent_hash: value.parent_hash,
             chain: value.chain.into(),
             ts: value.ts,
+            finality_status: None,
         }
     }
 }
@@ -426,7 +623,14 @@ impl From<BlockAggregatedChanges> for BlockChanges {
         Self {
             extractor: value.extractor,
             chain: value.chain.into(),
-            block: value.block.into(),
+            block: Block {
+                finality_status: Some(if value.block.number <= value.finalized_block_height {
+                    FinalityStatus::Finalized
+                } else {
+                    FinalityStatus::Pending
+                }),
+                ..value.block.into()
+            },
             finalized_block_height: value.finalized_block_height,
             revert: value.revert,
             account_updates: value
@@ -453,7 +657,7 @@ impl From<BlockAggregatedChanges> for BlockChanges {
                 .component_balances
                 .into_iter()
                 .map(|(component_id, v)| {
-                    let balances: HashMap<Bytes, ComponentBalance> = v
+                    let balances: BTreeMap<Bytes, ComponentBalance> = v
                         .into_iter()
                         .map(|(k, v)| (k, ComponentBalance::from(v)))
                         .collect();
@@ -478,8 +682,132 @@ impl From<BlockAggregatedChanges> for BlockChanges {
                 .into_iter()
                 .map(|(k, v)| (k, v.into()))
                 .collect(),
-            component_tvl: value.component_tvl,
+            component_tvl: value
+                .component_tvl
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+/// The changes of a single transaction.
+///
+/// Sent on subscriptions with [`MessageGranularity::Transaction`]. A block results in
+/// `tx_count` messages; block level data that can't be attributed to a single transaction is
+/// attached to the first (`new_tokens`) or the last (`component_tvl`) message of the block.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
+pub struct TransactionChangesMsg {
+    pub extractor: String,
+    pub chain: Chain,
+    pub block: Block,
+    pub finalized_block_height: u64,
+    pub tx: Transaction,
+    /// Position of this message within the block, starting at 0.
+    pub position: usize,
+    /// Number of transaction messages sent for the block.
+    pub tx_count: usize,
+    #[serde(with = "hex_hashmap_key", default)]
+    pub new_tokens: BTreeMap<Bytes, ResponseToken>,
+    #[serde(with = "hex_hashmap_key")]
+    pub account_updates: BTreeMap<Bytes, AccountUpdate>,
+    pub state_updates: BTreeMap<String, ProtocolStateDelta>,
+    pub new_protocol_components: BTreeMap<String, ProtocolComponent>,
+    pub component_balances: BTreeMap<String, TokenBalances>,
+    pub account_balances: BTreeMap<Bytes, BTreeMap<Bytes, AccountBalance>>,
+    #[serde(default)]
+    pub component_tvl: BTreeMap<String, f64>,
+}
+
+impl TransactionChangesMsg {
+    /// Splits a block into per transaction messages.
+    ///
+    /// Returns an empty vec if the block carries no transaction level changes, e.g. for reverts.
+    pub fn from_block(value: BlockAggregatedChanges) -> Vec<Self> {
+        let tx_count = value.txs_with_update.len();
+        let block: Block = value.block.into();
+        let chain: Chain = value.chain.into();
+        let mut new_tokens = Some(value.new_tokens);
+        let mut component_tvl = Some(value.component_tvl);
+
+        value
+            .txs_with_update
+            .into_iter()
+            .enumerate()
+            .map(|(position, changes)| {
+                let tx = changes.tx;
+                Self {
+                    extractor: value.extractor.clone(),
+                    chain,
+                    block: block.clone(),
+                    finalized_block_height: value.finalized_block_height,
+                    tx: Transaction::new(tx.hash, tx.block_hash, tx.from, tx.to, tx.index),
+                    position,
+                    tx_count,
+                    new_tokens: new_tokens
+                        .take()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(k, v)| (k, v.into()))
+                        .collect(),
+                    account_updates: changes
+                        .account_deltas
+                        .into_iter()
+                        .map(|(k, v)| (k, v.into()))
+                        .collect(),
+                    state_updates: changes
+                        .state_updates
+                        .into_iter()
+                        .map(|(k, v)| (k, v.into()))
+                        .collect(),
+                    new_protocol_components: changes
+                        .protocol_components
+                        .into_iter()
+                        .map(|(k, v)| (k, v.into()))
+                        .collect(),
+                    component_balances: changes
+                        .balance_changes
+                        .into_iter()
+                        .map(|(component_id, v)| {
+                            let balances: BTreeMap<Bytes, ComponentBalance> = v
+                                .into_iter()
+                                .map(|(k, v)| (k, ComponentBalance::from(v)))
+                                .collect();
+                            (component_id, balances.into())
+                        })
+                        .collect(),
+                    account_balances: changes
+                        .account_balance_changes
+                        .into_iter()
+                        .map(|(k, v)| {
+                            (
+                                k,
+                                v.into_iter()
+                                    .map(|(k, v)| (k, v.into()))
+                                    .collect(),
+                            )
+                        })
+                        .collect(),
+                    component_tvl: if position + 1 == tx_count {
+                        component_tvl
+                            .take()
+                            .unwrap_or_default()
+                            .into_iter()
+                            .collect()
+                    } else {
+                        BTreeMap::new()
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Down-converts the message to an older schema version, see
+    /// [`BlockChanges::into_schema_version`].
+    pub fn into_schema_version(mut self, version: u32) -> Self {
+        if version < 2 {
+            self.account_balances = BTreeMap::new();
         }
+        self
     }
 }
 
@@ -489,10 +817,10 @@ pub struct AccountUpdate {
     #[schema(value_type=Vec<String>)]
     pub address: Bytes,
     pub chain: Chain,
-    #[serde(with = "hex_hashmap_key_value")]
+    #[serde(with = "hex_hashmap_key_encoded_value")]
     #[schema(value_type=HashMap<String, String>)]
     pub slots: HashMap<Bytes, Bytes>,
-    #[serde(with = "hex_bytes_option")]
+    #[serde(with = "encoded_bytes_option")]
     #[schema(value_type=Option<String>)]
     pub balance: Option<Bytes>,
     #[serde(with = "hex_bytes_option")]
@@ -574,6 +902,15 @@ pub struct ProtocolComponent {
     pub creation_tx: Bytes,
     /// Date time of creation in UTC time
     pub created_at: NaiveDateTime,
+    /// Set if the component is flagged inactive: date time of its last state or balance change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inactive_since: Option<NaiveDateTime>,
+    /// Total value locked in USD, if both the component's TVL and the USD price are known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvl_usd: Option<f64>,
+    /// Name of the extractor that created this component, if recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extractor: Option<String>,
 }
 
 impl From<models::protocol::ProtocolComponent> for ProtocolComponent {
@@ -589,6 +926,9 @@ impl From<models::protocol::ProtocolComponent> for ProtocolComponent {
             change: value.change.into(),
             creation_tx: value.creation_tx,
             created_at: value.created_at,
+            inactive_since: None,
+            tvl_usd: None,
+            extractor: None,
         }
     }
 }
@@ -597,6 +937,7 @@ impl From<models::protocol::ProtocolComponent> for ProtocolComponent {
 pub struct ComponentBalance {
     #[serde(with = "hex_bytes")]
     pub token: Bytes,
+    #[serde(with = "encoded_bytes")]
     pub balance: Bytes,
     pub balance_float: f64,
     #[serde(with = "hex_bytes")]
@@ -609,7 +950,9 @@ pub struct ComponentBalance {
 pub struct ProtocolStateDelta {
     pub component_id: String,
     #[schema(value_type=HashMap<String, String>)]
+    #[serde(with = "hex_hashmap_encoded_value")]
     pub updated_attributes: HashMap<String, Bytes>,
+    #[serde(serialize_with = "sorted_set::serialize")]
     pub deleted_attributes: HashSet<String>,
 }
 
@@ -686,6 +1029,68 @@ pub struct StateRequestBody {
     pub chain: Chain,
     #[serde(default)]
     pub pagination: PaginationParams,
+    /// Filters response to contracts whose code has this hash.
+    ///
+    /// Note: this and the other account filters are evaluated against persisted state only,
+    /// unconfirmed state from ReorgBuffers is applied afterwards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type=Option<String>)]
+    pub code_hash: Option<Bytes>,
+    /// Filters response to accounts holding at least this native balance (big endian hex)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type=Option<String>)]
+    pub min_balance: Option<Bytes>,
+    /// Filters response to accounts holding at most this native balance (big endian hex)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type=Option<String>)]
+    pub max_balance: Option<Bytes>,
+    /// Orders the response by native balance instead of by insertion. Accounts without a native
+    /// balance come last.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_by_balance: Option<SortOrder>,
+    /// Filters response to accounts with (`true`) or without (`false`) code. If unset, only
+    /// accounts with code are returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_code: Option<bool>,
+    /// Optional account fields to return. If unset, all fields are returned. Fields not listed
+    /// are returned empty and are not loaded from the database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<AccountField>>,
+    /// How to return the accounts' code. If unset, requests for a single contract return the full
+    /// code and all other requests only its hash, unless `fields` lists the code explicitly. Code
+    /// can be fetched separately by its hash from the `contract_code` endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<CodeMode>,
+    /// Adds the known meaning of the returned accounts' storage slots to the response.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub annotate: bool,
+}
+
+/// Optional fields of a [`ResponseAccount`].
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountField {
+    Code,
+    Slots,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// How code is returned in a [`ResponseAccount`].
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeMode {
+    /// Neither code nor code hash are returned.
+    Omit,
+    /// Only the code hash is returned, code is empty.
+    HashOnly,
+    /// Code and code hash are returned.
+    Full,
 }
 
 impl StateRequestBody {
@@ -696,26 +1101,85 @@ impl StateRequestBody {
         chain: Chain,
         pagination: PaginationParams,
     ) -> Self {
-        Self { contract_ids, protocol_system, version, chain, pagination }
+        Self {
+            contract_ids,
+            protocol_system,
+            version,
+            chain,
+            pagination,
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: None,
+            annotate: false,
+        }
     }
 
     pub fn from_block(protocol_system: &str, block: BlockParam) -> Self {
         Self {
             contract_ids: None,
             protocol_system: protocol_system.to_string(),
-            version: VersionParam { timestamp: None, block: Some(block.clone()) },
+            version: VersionParam::new(None, Some(block.clone())),
             chain: block.chain.unwrap_or_default(),
             pagination: PaginationParams::default(),
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: None,
+            annotate: false,
+        }
+    }
+
+    /// Whether the requested accounts should include `field`.
+    pub fn includes(&self, field: AccountField) -> bool {
+        self.fields
+            .as_ref()
+            .is_none_or(|fields| fields.contains(&field))
+    }
+
+    /// How the requested accounts should return their code, see [`Self::code`].
+    pub fn code_mode(&self) -> CodeMode {
+        if let Some(mode) = self.code {
+            return mode;
         }
+        let single_contract = self
+            .contract_ids
+            .as_ref()
+            .is_some_and(|ids| ids.len() == 1);
+        match &self.fields {
+            Some(fields) if fields.contains(&AccountField::Code) => CodeMode::Full,
+            None if single_contract => CodeMode::Full,
+            _ => CodeMode::HashOnly,
+        }
+    }
+
+    /// Requests the accounts' code in the given mode.
+    pub fn with_code(mut self, mode: CodeMode) -> Self {
+        self.code = Some(mode);
+        self
     }
 
     pub fn from_timestamp(protocol_system: &str, timestamp: NaiveDateTime, chain: Chain) -> Self {
         Self {
             contract_ids: None,
             protocol_system: protocol_system.to_string(),
-            version: VersionParam { timestamp: Some(timestamp), block: None },
+            version: VersionParam::new(Some(timestamp), None),
             chain,
             pagination: PaginationParams::default(),
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: None,
+            annotate: false,
         }
     }
 }
@@ -725,11 +1189,42 @@ impl StateRequestBody {
 pub struct StateRequestResponse {
     pub accounts: Vec<ResponseAccount>,
     pub pagination: PaginationResponse,
+    /// Annotations of the returned accounts' slots, only set if requested with `annotate`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slot_annotations: Vec<SlotAnnotation>,
 }
 
 impl StateRequestResponse {
     pub fn new(accounts: Vec<ResponseAccount>, pagination: PaginationResponse) -> Self {
-        Self { accounts, pagination }
+        Self { accounts, pagination, slot_annotations: Vec::new() }
+    }
+}
+
+/// Known meaning of a contract's storage slot, e.g. that slot `0x0` holds the owner.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct SlotAnnotation {
+    /// Address of the annotated contract
+    #[schema(value_type=String)]
+    #[serde(with = "hex_bytes")]
+    pub address: Bytes,
+    /// The annotated slot, left padded to 32 bytes like the keys of `slots`
+    #[schema(value_type=String)]
+    #[serde(with = "hex_bytes")]
+    pub slot: Bytes,
+    #[schema(example = "owner")]
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl From<models::contract::SlotAnnotation> for SlotAnnotation {
+    fn from(value: models::contract::SlotAnnotation) -> Self {
+        Self {
+            address: value.address,
+            slot: value.slot,
+            label: value.label,
+            description: value.description,
+        }
     }
 }
 
@@ -749,16 +1244,16 @@ pub struct ResponseAccount {
     pub title: String,
     /// Contract storage map of hex encoded string values
     #[schema(value_type=HashMap<String, String>, example=json!({"0x....": "0x...."}))]
-    #[serde(with = "hex_hashmap_key_value")]
+    #[serde(with = "hex_hashmap_key_encoded_value")]
     pub slots: HashMap<Bytes, Bytes>,
     /// The balance of the account in the native token
     #[schema(value_type=String, example="0x00")]
-    #[serde(with = "hex_bytes")]
+    #[serde(with = "encoded_bytes")]
     pub native_balance: Bytes,
     /// Balances of this account in other tokens (only tokens balance that are
     /// relevant to the protocol are returned here)
     #[schema(value_type=HashMap<String, String>, example=json!({"0x....": "0x...."}))]
-    #[serde(with = "hex_hashmap_key_value")]
+    #[serde(with = "hex_hashmap_key_encoded_value")]
     pub token_balances: HashMap<Bytes, Bytes>,
     /// The accounts code as hex encoded string
     #[schema(value_type=String, example="0xBADBABE")]
@@ -839,7 +1334,7 @@ pub struct AccountBalance {
     pub account: Bytes,
     #[serde(with = "hex_bytes")]
     pub token: Bytes,
-    #[serde(with = "hex_bytes")]
+    #[serde(with = "encoded_bytes")]
     pub balance: Bytes,
     #[serde(with = "hex_bytes")]
     pub modify_tx: Bytes,
@@ -871,31 +1366,138 @@ impl fmt::Display for ContractId {
     }
 }
 
-/// The version of the requested state, given as either a timestamp or a block.
+/// Request of a contract's storage at a block, optionally with a proof of the values.
+#[derive(Clone, Serialize, Debug, Default, Deserialize, PartialEq, ToSchema, Eq, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct ContractStorageProofRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    #[schema(value_type=String)]
+    pub contract_id: Bytes,
+    /// Slots to return and prove. If unset, all slots of the contract are returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type=Option<Vec<String>>)]
+    pub slots: Option<Vec<Bytes>>,
+    /// Must resolve to a block stored by Tycho, timestamps are not accepted. Defaults to the
+    /// latest stored block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<VersionParam>,
+    /// Whether to fetch and attach an `eth_getProof` result from the upstream node.
+    #[serde(default)]
+    pub include_proof: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct ContractStorageProofResponse {
+    #[schema(value_type=String)]
+    pub address: Bytes,
+    /// The block the storage was read at, proofs are against its state root.
+    pub block: Block,
+    /// Storage values by slot. Empty slots are omitted.
+    #[schema(value_type=HashMap<String, String>)]
+    pub slots: BTreeMap<Bytes, Bytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<AccountProof>,
+}
+
+/// Merkle proof of an account and its storage as returned by `eth_getProof`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default, ToSchema)]
+pub struct AccountProof {
+    #[schema(value_type=String)]
+    pub address: Bytes,
+    #[schema(value_type=String)]
+    pub balance: Bytes,
+    pub nonce: u64,
+    #[schema(value_type=String)]
+    pub code_hash: Bytes,
+    #[schema(value_type=String)]
+    pub storage_hash: Bytes,
+    #[schema(value_type=Vec<String>)]
+    pub account_proof: Vec<Bytes>,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default, ToSchema)]
+pub struct StorageProof {
+    #[schema(value_type=String)]
+    pub key: Bytes,
+    #[schema(value_type=String)]
+    pub value: Bytes,
+    #[schema(value_type=Vec<String>)]
+    pub proof: Vec<Bytes>,
+}
+
+impl From<models::contract::AccountProof> for AccountProof {
+    fn from(value: models::contract::AccountProof) -> Self {
+        Self {
+            address: value.address,
+            balance: value.balance,
+            nonce: value.nonce,
+            code_hash: value.code_hash,
+            storage_hash: value.storage_hash,
+            account_proof: value.account_proof,
+            storage_proof: value
+                .storage_proof
+                .into_iter()
+                .map(|p| StorageProof { key: p.key, value: p.value, proof: p.proof })
+                .collect(),
+        }
+    }
+}
+
+/// The version of the requested state, given as either a timestamp, a block or relative to the
+/// latest block.
 ///
 /// If block is provided, the state at that exact block is returned. Will error if the block
 /// has not been processed yet. If timestamp is provided, the state at the latest block before
-/// that timestamp is returned.
+/// that timestamp is returned. A relative version takes precedence over both and is resolved by
+/// the server against the latest stored block of the requested chain.
 /// Defaults to the current time.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, ToSchema, Eq, Hash)]
 #[serde(deny_unknown_fields)]
 pub struct VersionParam {
     pub timestamp: Option<NaiveDateTime>,
     pub block: Option<BlockParam>,
+    /// Only return state of finalized blocks. Timestamps past the latest finalized block resolve
+    /// to that block, blocks that are not finalized yet are rejected.
+    #[serde(default)]
+    pub finalized_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative: Option<RelativeVersion>,
 }
 
 impl VersionParam {
     pub fn new(timestamp: Option<NaiveDateTime>, block: Option<BlockParam>) -> Self {
-        Self { timestamp, block }
+        Self { timestamp, block, finalized_only: false, relative: None }
+    }
+
+    pub fn relative(relative: RelativeVersion) -> Self {
+        Self { timestamp: None, block: None, finalized_only: false, relative: Some(relative) }
     }
 }
 
 impl Default for VersionParam {
     fn default() -> Self {
-        VersionParam { timestamp: Some(Utc::now().naive_utc()), block: None }
+        VersionParam {
+            timestamp: Some(Utc::now().naive_utc()),
+            block: None,
+            finalized_only: false,
+            relative: None,
+        }
     }
 }
 
+/// A version relative to the latest block, e.g. `{"blocks_ago": 100}` or `{"duration": "24h"}`.
+///
+/// Durations are a number followed by one of the units `s`, `m`, `h`, `d` or `w`. They are
+/// subtracted from the timestamp of the latest block.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, ToSchema, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RelativeVersion {
+    BlocksAgo(u64),
+    Duration(String),
+}
+
 #[deprecated(note = "Use StateRequestBody instead")]
 #[derive(Serialize, Deserialize, Default, Debug, IntoParams)]
 pub struct StateRequestParameters {
@@ -981,6 +1583,29 @@ impl TokensRequestResponse {
     }
 }
 
+/// How addresses are rendered in responses.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayFormat {
+    /// Lowercase hex encoding of the raw address bytes.
+    #[default]
+    Raw,
+    /// The chain's customary format, e.g. EIP-55 checksums on EVM chains.
+    Chain,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, IntoParams)]
+pub struct DisplayParams {
+    /// How addresses are rendered in the response, defaults to `raw`.
+    #[serde(default)]
+    #[param(inline)]
+    pub display_format: DisplayFormat,
+    /// How balances, storage values and attributes are encoded, defaults to `hex`.
+    #[serde(default)]
+    #[param(inline)]
+    pub encoding: ValueEncoding,
+}
+
 /// Pagination parameter
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema, Eq, Hash)]
 #[serde(deny_unknown_fields)]
@@ -1053,6 +1678,31 @@ pub struct ResponseToken {
     ///  - 5: Token analysis failed multiple times (after creation)
     ///  - 0: Failed to extract attributes, like Decimal or Symbol
     pub quality: u32,
+    /// Decimals to display amounts with if they differ from `decimals`. Amounts are always
+    /// denominated in `decimals`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_decimals: Option<u32>,
+    /// URI of an image representing the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+}
+
+impl ResponseToken {
+    /// Merges a metadata override over the token.
+    pub fn apply_override(&mut self, token_override: &TokenOverride) {
+        if let Some(symbol) = &token_override.symbol {
+            self.symbol = symbol.clone();
+        }
+        if token_override
+            .display_decimals
+            .is_some()
+        {
+            self.display_decimals = token_override.display_decimals;
+        }
+        if token_override.logo_uri.is_some() {
+            self.logo_uri = token_override.logo_uri.clone();
+        }
+    }
 }
 
 impl From<models::token::Token> for ResponseToken {
@@ -1065,10 +1715,72 @@ impl From<models::token::Token> for ResponseToken {
             tax: value.tax,
             gas: value.gas,
             quality: value.quality,
+            display_decimals: None,
+            logo_uri: None,
+        }
+    }
+}
+
+/// Corrected metadata of a token, served instead of what was read from chain. Unset fields keep
+/// the stored value.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct TokenOverride {
+    #[schema(value_type=String)]
+    pub address: Bytes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// Decimals to display amounts with, does not change how amounts are denominated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_decimals: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+}
+
+impl TokenOverride {
+    pub fn into_model(self, chain: Chain) -> models::token::TokenOverride {
+        models::token::TokenOverride {
+            chain: chain.into(),
+            address: self.address,
+            symbol: self.symbol,
+            display_decimals: self.display_decimals,
+            logo_uri: self.logo_uri,
+        }
+    }
+}
+
+impl From<models::token::TokenOverride> for TokenOverride {
+    fn from(value: models::token::TokenOverride) -> Self {
+        Self {
+            address: value.address,
+            symbol: value.symbol,
+            display_decimals: value.display_decimals,
+            logo_uri: value.logo_uri,
         }
     }
 }
 
+/// Creates, replaces and deletes token metadata overrides.
+///
+/// Upserts replace an existing override of the token as a whole. A request without updates
+/// returns the overrides of the chain unchanged.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct TokenOverridesRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    #[serde(default)]
+    pub upsert: Vec<TokenOverride>,
+    /// Addresses of the tokens whose overrides to delete
+    #[schema(value_type=Vec<String>)]
+    #[serde(default)]
+    pub delete: Vec<Bytes>,
+}
+
+/// All token metadata overrides of a chain, sorted by address.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct TokenOverridesRequestResponse {
+    pub overrides: Vec<TokenOverride>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, ToSchema, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct ProtocolComponentsRequestBody {
@@ -1082,17 +1794,47 @@ pub struct ProtocolComponentsRequestBody {
     /// native token.
     #[serde(default)]
     pub tvl_gt: Option<f64>,
+    /// The minimum TVL of the protocol components to return, denoted in USD. Converted using the
+    /// indexed price of the chain's USD reference token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_tvl_usd: Option<f64>,
     #[serde(default)]
     pub chain: Chain,
     /// Max page size supported is 500
     #[serde(default)]
     pub pagination: PaginationParams,
+    /// Optional component fields to return. If unset, all fields are returned. Fields not listed
+    /// are returned empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<ComponentField>>,
+    /// Only return components created by this extractor. Components still in the reorg buffer
+    /// have no recorded extractor and are excluded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extractor: Option<String>,
+    /// Only return components holding exactly these tokens, in any order. Components still in the
+    /// reorg buffer are not matched.
+    #[schema(value_type=Option<Vec<String>>)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<Bytes>>,
+    /// Paginates over a snapshot of the components instead of by offset. Pass an empty cursor to
+    /// pin the first page to the latest stored block and the `next_cursor` of the previous
+    /// response for the following pages, `pagination.page` is ignored then. Components inserted
+    /// meanwhile and components still in the reorg buffer are not listed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Optional fields of a [`ProtocolComponent`].
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentField {
+    StaticAttributes,
 }
 
 // Implement PartialEq where tvl is considered equal if the difference is less than 1e-6
 impl PartialEq for ProtocolComponentsRequestBody {
     fn eq(&self, other: &Self) -> bool {
-        let tvl_close_enough = match (self.tvl_gt, other.tvl_gt) {
+        let close_enough = |a: Option<f64>, b: Option<f64>| match (a, b) {
             (Some(a), Some(b)) => (a - b).abs() < 1e-6,
             (None, None) => true,
             _ => false,
@@ -1100,9 +1842,14 @@ impl PartialEq for ProtocolComponentsRequestBody {
 
         self.protocol_system == other.protocol_system &&
             self.component_ids == other.component_ids &&
-            tvl_close_enough &&
+            close_enough(self.tvl_gt, other.tvl_gt) &&
+            close_enough(self.min_tvl_usd, other.min_tvl_usd) &&
             self.chain == other.chain &&
-            self.pagination == other.pagination
+            self.pagination == other.pagination &&
+            self.fields == other.fields &&
+            self.extractor == other.extractor &&
+            self.tokens == other.tokens &&
+            self.cursor == other.cursor
     }
 }
 
@@ -1122,9 +1869,17 @@ impl Hash for ProtocolComponentsRequestBody {
             // Use a constant value to represent None
             state.write_u8(0);
         }
+        match self.min_tvl_usd {
+            Some(tvl) => tvl.to_bits().hash(state),
+            None => state.write_u8(0),
+        }
 
         self.chain.hash(state);
         self.pagination.hash(state);
+        self.fields.hash(state);
+        self.extractor.hash(state);
+        self.tokens.hash(state);
+        self.cursor.hash(state);
     }
 }
 
@@ -1134,8 +1889,13 @@ impl ProtocolComponentsRequestBody {
             protocol_system: system.to_string(),
             component_ids: None,
             tvl_gt,
+            min_tvl_usd: None,
             chain,
             pagination: Default::default(),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         }
     }
 
@@ -1144,10 +1904,22 @@ impl ProtocolComponentsRequestBody {
             protocol_system: system.to_string(),
             component_ids: Some(ids),
             tvl_gt: None,
+            min_tvl_usd: None,
             chain,
             pagination: Default::default(),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         }
     }
+
+    /// Whether the requested components should include `field`.
+    pub fn includes(&self, field: ComponentField) -> bool {
+        self.fields
+            .as_ref()
+            .is_none_or(|fields| fields.contains(&field))
+    }
 }
 
 impl ProtocolComponentsRequestBody {
@@ -1158,7 +1930,18 @@ impl ProtocolComponentsRequestBody {
         chain: Chain,
         pagination: PaginationParams,
     ) -> Self {
-        Self { protocol_system, component_ids, tvl_gt, chain, pagination }
+        Self {
+            protocol_system,
+            component_ids,
+            tvl_gt,
+            min_tvl_usd: None,
+            chain,
+            pagination,
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
+        }
     }
 }
 
@@ -1190,6 +1973,10 @@ impl ProtocolComponentRequestParameters {
 pub struct ProtocolComponentRequestResponse {
     pub protocol_components: Vec<ProtocolComponent>,
     pub pagination: PaginationResponse,
+    /// Cursor of the next page, only set for requests with a cursor and if more components
+    /// follow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 impl ProtocolComponentRequestResponse {
@@ -1197,7 +1984,7 @@ impl ProtocolComponentRequestResponse {
         protocol_components: Vec<ProtocolComponent>,
         pagination: PaginationResponse,
     ) -> Self {
-        Self { protocol_components, pagination }
+        Self { protocol_components, pagination, next_cursor: None }
     }
 }
 
@@ -1229,12 +2016,17 @@ pub struct ResponseProtocolState {
     /// Attributes of the component. If an attribute's value is a `bigint`,
     /// it will be encoded as a big endian signed hex string.
     #[schema(value_type=HashMap<String, String>)]
-    #[serde(with = "hex_hashmap_value")]
+    #[serde(with = "hex_hashmap_encoded_value")]
     pub attributes: HashMap<String, Bytes>,
     /// Sum aggregated balances of the component
     #[schema(value_type=HashMap<String, String>)]
-    #[serde(with = "hex_hashmap_key_value")]
+    #[serde(with = "hex_hashmap_key_encoded_value")]
     pub balances: HashMap<Bytes, Bytes>,
+    /// Fraction of each token's total supply held by the component, according to the latest
+    /// supply snapshot at the requested version. Tokens without a supply snapshot are omitted.
+    #[schema(value_type=HashMap<String, f64>)]
+    #[serde(with = "hex_hashmap_key", default, skip_serializing_if = "HashMap::is_empty")]
+    pub balance_shares: HashMap<Bytes, f64>,
 }
 
 impl From<models::protocol::ProtocolComponentState> for ResponseProtocolState {
@@ -1243,6 +2035,7 @@ impl From<models::protocol::ProtocolComponentState> for ResponseProtocolState {
             component_id: value.component_id,
             attributes: value.attributes,
             balances: value.balances,
+            balance_shares: HashMap::new(),
         }
     }
 }
@@ -1443,17 +2236,97 @@ impl ProtocolSystemsRequestResponse {
     }
 }
 
+/// How the components of a protocol type are simulated.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImplementationType {
+    /// Simulated by executing the protocol's contracts.
+    Vm,
+    /// Simulated by a native implementation.
+    Custom,
+}
+
+impl From<models::ImplementationType> for ImplementationType {
+    fn from(value: models::ImplementationType) -> Self {
+        match value {
+            models::ImplementationType::Vm => Self::Vm,
+            models::ImplementationType::Custom => Self::Custom,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ProtocolSystemSummary {
+    pub name: String,
+    /// Number of components that were not deleted.
+    pub component_count: u64,
+    /// Implementation types of the system's protocol types, empty without components.
+    pub implementation_types: Vec<ImplementationType>,
+    /// Latest block in which a component was created or its state or balances changed. Storage
+    /// changes of VM components are not considered.
+    pub last_updated_block: Option<u64>,
+    /// Timestamp of `last_updated_block`.
+    pub last_updated_ts: Option<NaiveDateTime>,
+}
+
+impl From<models::protocol::ProtocolSystemSummary> for ProtocolSystemSummary {
+    fn from(value: models::protocol::ProtocolSystemSummary) -> Self {
+        Self {
+            name: value.name,
+            component_count: value.component_count,
+            implementation_types: value
+                .implementation_types
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            last_updated_block: value.last_updated_block,
+            last_updated_ts: value.last_updated_ts,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ProtocolSystemSummariesResponse {
+    pub chain: Chain,
+    /// Every known protocol system ordered by name, including those without components on the
+    /// chain.
+    pub protocol_systems: Vec<ProtocolSystemSummary>,
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
 pub struct DCIUpdate {
     /// Map of component id to the new entrypoints associated with the component
+    #[serde(serialize_with = "sorted_map_of_sets::serialize")]
     pub new_entrypoints: HashMap<ComponentId, HashSet<EntryPoint>>,
     /// Map of entrypoint id to the new entrypoint params associtated with it (and optionally the
     /// component linked to those params)
+    #[serde(serialize_with = "sorted_map_of_sets::serialize")]
     pub new_entrypoint_params: HashMap<String, HashSet<(TracingParams, Option<String>)>>,
     /// Map of entrypoint id to its trace result
+    #[serde(serialize_with = "sorted_map::serialize")]
     pub trace_results: HashMap<String, TracingResult>,
 }
 
+impl DCIUpdate {
+    /// Adds the entrypoints and params of `other`. Its trace results replace older ones.
+    pub fn merge(&mut self, other: Self) {
+        for (component_id, entrypoints) in other.new_entrypoints {
+            self.new_entrypoints
+                .entry(component_id)
+                .or_default()
+                .extend(entrypoints);
+        }
+        for (entrypoint_id, params) in other.new_entrypoint_params {
+            self.new_entrypoint_params
+                .entry(entrypoint_id)
+                .or_default()
+                .extend(params);
+        }
+        self.trace_results
+            .extend(other.trace_results);
+    }
+}
+
 impl From<models::blockchain::DCIUpdate> for DCIUpdate {
     fn from(value: models::blockchain::DCIUpdate) -> Self {
         Self {
@@ -1537,7 +2410,161 @@ impl ComponentTvlRequestResponse {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Hash, Clone)]
+/// Interval at which historical values are sampled.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleInterval {
+    #[default]
+    Hourly,
+    Daily,
+}
+
+impl From<SampleInterval> for models::protocol::SampleInterval {
+    fn from(value: SampleInterval) -> Self {
+        match value {
+            SampleInterval::Hourly => Self::Hourly,
+            SampleInterval::Daily => Self::Daily,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BalanceHistoryRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    pub component_id: ComponentId,
+    #[serde(with = "hex_bytes")]
+    #[schema(value_type=String)]
+    pub token: Bytes,
+    /// Start of the first sample.
+    pub from: NaiveDateTime,
+    /// Samples starting after this timestamp are not returned.
+    pub to: NaiveDateTime,
+    #[serde(default)]
+    pub granularity: SampleInterval,
+}
+
+/// A token balance over one sample interval, as floating point values.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Clone)]
+pub struct BalanceSample {
+    /// Start of the interval.
+    pub ts: NaiveDateTime,
+    /// Balance at the start of the interval.
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    /// Balance at the end of the interval.
+    pub close: f64,
+}
+
+impl From<models::protocol::BalanceSample> for BalanceSample {
+    fn from(value: models::protocol::BalanceSample) -> Self {
+        Self {
+            ts: value.ts,
+            open: value.open,
+            high: value.high,
+            low: value.low,
+            close: value.close,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Clone)]
+pub struct BalanceHistoryRequestResponse {
+    pub samples: Vec<BalanceSample>,
+}
+
+impl BalanceHistoryRequestResponse {
+    pub fn new(samples: Vec<BalanceSample>) -> Self {
+        Self { samples }
+    }
+}
+
+/// Block range to retrieve the change digests of.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BlockDigestsRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    /// First block number of the range.
+    pub start_block: u64,
+    /// Last block number of the range, inclusive. Max range supported is 10000 blocks.
+    pub end_block: u64,
+}
+
+/// Digest of the changes stored for a block. Deployments that stored the same changes for a
+/// block report the same digest.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Clone)]
+pub struct BlockDigest {
+    pub number: u64,
+    #[serde(with = "hex_bytes")]
+    #[schema(value_type=String)]
+    pub hash: Bytes,
+    #[serde(with = "hex_bytes")]
+    #[schema(value_type=String)]
+    pub digest: Bytes,
+}
+
+impl From<models::blockchain::BlockDigest> for BlockDigest {
+    fn from(value: models::blockchain::BlockDigest) -> Self {
+        Self { number: value.block_number, hash: value.block_hash, digest: value.digest }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Clone)]
+pub struct BlockDigestsRequestResponse {
+    /// Digests ordered by block number. Blocks without a stored digest are omitted.
+    pub digests: Vec<BlockDigest>,
+}
+
+impl BlockDigestsRequestResponse {
+    pub fn new(digests: Vec<BlockDigest>) -> Self {
+        Self { digests }
+    }
+}
+
+/// Addresses to check for being tracked.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct TrackedAddressesRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    /// Max number of addresses supported is 10000
+    #[schema(value_type=Vec<String>)]
+    pub addresses: Vec<Bytes>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Clone)]
+pub struct TrackedAddressesRequestResponse {
+    /// The requested addresses tracked by Tycho, sorted.
+    #[schema(value_type=Vec<String>)]
+    pub addresses: Vec<Bytes>,
+}
+
+impl TrackedAddressesRequestResponse {
+    pub fn new(addresses: Vec<Bytes>) -> Self {
+        Self { addresses }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Clone)]
+pub struct ChainHeadResponse {
+    pub chain: Chain,
+    /// Number of the latest block stored for the chain.
+    pub block_number: u64,
+    /// Hash of the latest block stored for the chain.
+    #[schema(value_type=String)]
+    pub block_hash: Bytes,
+    /// Timestamp of the latest block stored for the chain.
+    pub block_ts: NaiveDateTime,
+    /// Block each extractor of the chain has processed up to, keyed by extractor name.
+    pub extractor_block_numbers: HashMap<String, u64>,
+    /// Seconds between the timestamp of the latest stored block and the time of the request.
+    /// Includes the chain's block time, so even a fully synced chain lags by a few seconds.
+    pub lag_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Hash, Clone)]
 pub struct TracedEntryPointRequestBody {
     #[serde(default)]
     pub chain: Chain,
@@ -1551,7 +2578,7 @@ pub struct TracedEntryPointRequestBody {
     pub pagination: PaginationParams,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, ToSchema, Eq, Hash)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, ToSchema, Eq, Hash, PartialOrd, Ord)]
 pub struct EntryPoint {
     #[schema(example = "0xEdf63cce4bA70cbE74064b7687882E71ebB0e988:getRate()")]
     /// Entry point id.
@@ -1565,7 +2592,7 @@ pub struct EntryPoint {
     pub signature: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, ToSchema, Eq, Hash)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, ToSchema, Eq, Hash, PartialOrd, Ord)]
 pub struct RPCTracerParams {
     /// The caller address of the transaction, if not provided tracing uses the default value
     /// for an address defined by the VM.
@@ -1584,7 +2611,7 @@ impl From<models::blockchain::RPCTracerParams> for RPCTracerParams {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
 #[serde(tag = "method", rename_all = "lowercase")]
 pub enum TracingParams {
     /// Uses RPC calls to retrieve the called addresses and retriggers
@@ -1624,8 +2651,10 @@ impl From<models::blockchain::EntryPointWithTracingParams> for EntryPointWithTra
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
 pub struct TracingResult {
     #[schema(value_type=HashSet<(String, String)>)]
+    #[serde(serialize_with = "sorted_set::serialize")]
     pub retriggers: HashSet<(StoreKey, StoreVal)>,
     #[schema(value_type=HashMap<String,HashSet<String>>)]
+    #[serde(serialize_with = "sorted_map_of_sets::serialize")]
     pub accessed_slots: HashMap<Address, HashSet<StoreKey>>,
 }
 
@@ -1715,11 +2744,379 @@ pub struct AddEntryPointRequestResponse {
         HashMap<ComponentId, Vec<(EntryPointWithTracingParams, TracingResult)>>,
 }
 
+/// Replaces the component allow/deny lists of a running extractor.
+///
+/// Empty lists disable the corresponding filter. The new filter only applies to blocks processed
+/// after the update, components skipped earlier are not backfilled.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct ComponentFilterRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    /// Name of the extractor to update
+    pub extractor: String,
+    /// Only index components with these ids
+    #[serde(default)]
+    pub allowed_ids: Vec<ComponentId>,
+    /// Never index components with these ids
+    #[serde(default)]
+    pub denied_ids: Vec<ComponentId>,
+    /// Only index components that contain at least one of these tokens
+    #[schema(value_type=Vec<String>)]
+    #[serde(default)]
+    pub allowed_tokens: Vec<Bytes>,
+    /// Never index components that contain any of these tokens
+    #[schema(value_type=Vec<String>)]
+    #[serde(default)]
+    pub denied_tokens: Vec<Bytes>,
+}
+
+/// A contract tracked by an extractor in blocks `from_block..until_block`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct TrackedContract {
+    #[schema(value_type=String)]
+    pub address: Bytes,
+    pub from_block: u64,
+    /// First block the contract is no longer tracked in, unset while it is tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until_block: Option<u64>,
+}
+
+/// A contract to start or stop tracking.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct TrackedContractUpdate {
+    #[schema(value_type=String)]
+    pub address: Bytes,
+    /// First block the update applies to
+    pub from_block: u64,
+}
+
+/// Adds and removes contracts tracked by a running extractor.
+///
+/// Updates only affect blocks processed afterwards, changes of earlier blocks are not backfilled.
+/// A request without updates returns the tracked contracts unchanged.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct TrackedContractsRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    /// Name of the extractor to update
+    pub extractor: String,
+    #[serde(default)]
+    pub add: Vec<TrackedContractUpdate>,
+    #[serde(default)]
+    pub remove: Vec<TrackedContractUpdate>,
+}
+
+/// Identifies a running extractor.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct ExtractorProfileRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    /// Name of the extractor
+    pub extractor: String,
+}
+
+/// Processing time statistics of a single stage over the profiling window. Times are in
+/// milliseconds.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Clone)]
+pub struct StageProfile {
+    /// One of `decode`, `validate`, `persist` or `emit`
+    pub stage: String,
+    /// Number of blocks in the window
+    pub samples: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Per-stage processing times of the most recently processed blocks.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Clone)]
+pub struct ExtractorProfile {
+    pub chain: Chain,
+    pub extractor: String,
+    /// Maximum number of blocks kept per stage
+    pub window: usize,
+    pub stages: Vec<StageProfile>,
+}
+
+/// Resources consumed by an API key in the current calendar month (UTC).
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Clone)]
+pub struct ApiKeyUsage {
+    /// Name of the key as configured, the key itself is never returned
+    pub name: String,
+    /// Month the usage was accumulated in, e.g. `2024-01`
+    pub month: String,
+    pub requests: u64,
+    /// Number of entities returned
+    pub rows_returned: u64,
+    pub bytes_returned: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_rows_quota: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_bytes_quota: Option<u64>,
+}
+
+/// An ad-hoc query against the views of the `analytics` schema.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct AnalyticsQueryRequestBody {
+    /// A single read-only `SELECT` (or `WITH ... SELECT`) statement, e.g.
+    /// `SELECT protocol_system, count(*) FROM protocol_component GROUP BY 1`
+    pub query: String,
+    /// Max number of returned rows, defaults to and is capped at 1000
+    #[serde(default)]
+    pub row_limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Clone)]
+pub struct AnalyticsQueryResponse {
+    /// The returned rows, each as an object keyed by column name
+    #[schema(value_type=Vec<Object>)]
+    pub rows: Vec<serde_json::Value>,
+    /// Whether the query returned more rows than the row limit
+    pub truncated: bool,
+}
+
+impl AnalyticsQueryResponse {
+    pub fn new(rows: Vec<serde_json::Value>, truncated: bool) -> Self {
+        Self { rows, truncated }
+    }
+}
+
+/// Lists destructive storage operations, most recent first.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct AuditLogRequestBody {
+    /// Only lists operations of this kind, e.g. `revert_state`, `prune_history`,
+    /// `quarantine_range` or `delete_contract`
+    #[serde(default)]
+    pub operation: Option<String>,
+    /// Only lists operations recorded at or after this time
+    #[serde(default)]
+    pub since: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub pagination: PaginationParams,
+}
+
+/// A revert, prune, repair or deletion recorded by the storage.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct AuditLogEntry {
+    pub operation: String,
+    /// Extractor that ran the operation, or the database user for maintenance commands
+    pub actor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain: Option<Chain>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub rows_affected: i64,
+    /// First and last affected block number, inclusive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_range: Option<(i64, i64)>,
+    pub timestamp: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct AuditLogRequestResponse {
+    pub entries: Vec<AuditLogEntry>,
+    pub pagination: PaginationResponse,
+}
+
+/// Compares the components of a protocol system indexed on two chains.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct DeploymentParityRequestBody {
+    pub protocol_system: String,
+    pub base_chain: Chain,
+    pub other_chain: Chain,
+}
+
+/// Attribute names present on only one of the chains. Numeric and address path segments are
+/// replaced by `*`, e.g. `ticks/*/net-liquidity`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct AttributeSchemaDiff {
+    pub missing_on_other: Vec<String>,
+    pub missing_on_base: Vec<String>,
+}
+
+impl From<models::protocol::AttributeSchemaDiff> for AttributeSchemaDiff {
+    fn from(value: models::protocol::AttributeSchemaDiff) -> Self {
+        Self {
+            missing_on_other: value
+                .missing_on_other
+                .into_iter()
+                .collect(),
+            missing_on_base: value
+                .missing_on_base
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct ComponentAttributeSchemaDiff {
+    pub component_id: String,
+    pub attributes: AttributeSchemaDiff,
+}
+
+/// Differences between the deployments of a protocol system on two chains.
+///
+/// Components are matched by id. Contracts are those used by any component of the system.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct DeploymentParityResponse {
+    pub protocol_system: String,
+    pub base_chain: Chain,
+    pub other_chain: Chain,
+    /// Whether both chains hold the same components and contracts with the same attributes
+    pub complete: bool,
+    pub n_base_components: usize,
+    pub n_other_components: usize,
+    pub components_missing_on_other: Vec<String>,
+    pub components_missing_on_base: Vec<String>,
+    #[schema(value_type=Vec<String>)]
+    pub contracts_missing_on_other: Vec<Bytes>,
+    #[schema(value_type=Vec<String>)]
+    pub contracts_missing_on_base: Vec<Bytes>,
+    /// Differences of the attributes used across the whole protocol system
+    pub attributes: AttributeSchemaDiff,
+    /// Components present on both chains whose attributes differ
+    pub component_attributes: Vec<ComponentAttributeSchemaDiff>,
+}
+
+impl From<models::protocol::DeploymentParity> for DeploymentParityResponse {
+    fn from(value: models::protocol::DeploymentParity) -> Self {
+        Self {
+            complete: value.is_complete(),
+            protocol_system: value.protocol_system,
+            base_chain: value.base_chain.into(),
+            other_chain: value.other_chain.into(),
+            n_base_components: value.n_base_components,
+            n_other_components: value.n_other_components,
+            components_missing_on_other: value
+                .components_missing_on_other
+                .into_iter()
+                .collect(),
+            components_missing_on_base: value
+                .components_missing_on_base
+                .into_iter()
+                .collect(),
+            contracts_missing_on_other: value
+                .contracts_missing_on_other
+                .into_iter()
+                .collect(),
+            contracts_missing_on_base: value
+                .contracts_missing_on_base
+                .into_iter()
+                .collect(),
+            attributes: value.attributes.into(),
+            component_attributes: value
+                .component_attributes
+                .into_iter()
+                .map(|(component_id, attributes)| ComponentAttributeSchemaDiff {
+                    component_id,
+                    attributes: attributes.into(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Identifies an extractor by its stored state.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, ToSchema, Eq, Clone)]
+pub struct ExtractorStatusRequestBody {
+    #[serde(default)]
+    pub chain: Chain,
+    /// Name of the extractor
+    pub extractor: String,
+}
+
+/// Substreams package an extractor ran.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct SubstreamsPackage {
+    pub name: String,
+    pub version: String,
+    pub module_name: String,
+    /// Digest of the output module and the modules it depends on
+    #[schema(value_type=String)]
+    pub module_hash: Bytes,
+    /// First block processed with this package, unknown if the extractor had no state yet
+    pub from_block: Option<u64>,
+}
+
+impl From<models::SubstreamsPackage> for SubstreamsPackage {
+    fn from(value: models::SubstreamsPackage) -> Self {
+        Self {
+            name: value.name,
+            version: value.version,
+            module_name: value.module_name,
+            module_hash: value.module_hash,
+            from_block: value.from_block,
+        }
+    }
+}
+
+/// Stored state of an extractor.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Eq, Hash, Clone)]
+pub struct ExtractorStatus {
+    pub chain: Chain,
+    pub extractor: String,
+    /// Hash of the last block committed by the extractor
+    #[schema(value_type=String)]
+    pub block_hash: Bytes,
+    /// Packages the extractor ran, oldest first
+    pub substreams_packages: Vec<SubstreamsPackage>,
+    /// Whether the module hash changed within the stored history, data before and after the
+    /// change may be inconsistent
+    pub module_hash_changed: bool,
+}
+
+impl From<models::ExtractionState> for ExtractorStatus {
+    fn from(value: models::ExtractionState) -> Self {
+        let module_hash_changed = value
+            .substreams_packages
+            .windows(2)
+            .any(|pair| !pair[0].same_module(&pair[1]));
+        Self {
+            chain: value.chain.into(),
+            extractor: value.name,
+            block_hash: value.block_hash,
+            substreams_packages: value
+                .substreams_packages
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            module_hash_changed,
+        }
+    }
+}
+
+/// State of several chains as of the same point in time.
+///
+/// Each chain is resolved to its latest stored block at or before `timestamp`, the version of
+/// every query is replaced by the block of its chain.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Clone)]
+pub struct MultiChainSnapshotRequestBody {
+    pub timestamp: NaiveDateTime,
+    #[serde(default)]
+    pub protocol_states: Vec<ProtocolStateRequestBody>,
+    #[serde(default)]
+    pub contract_states: Vec<StateRequestBody>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema, Clone)]
+pub struct MultiChainSnapshotResponse {
+    pub timestamp: NaiveDateTime,
+    /// The block each queried chain was resolved to
+    pub blocks: Vec<Block>,
+    /// Responses to the protocol state queries, in request order
+    pub protocol_states: Vec<ProtocolStateRequestResponse>,
+    /// Responses to the contract state queries, in request order
+    pub contract_states: Vec<StateRequestResponse>,
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
 
-    use maplit::hashmap;
+    use maplit::btreemap;
     use rstest::rstest;
 
     use super::*;
@@ -1730,16 +3127,26 @@ mod test {
             protocol_system: "protocol1".to_string(),
             component_ids: Some(vec!["component1".to_string(), "component2".to_string()]),
             tvl_gt: Some(1000.0),
+            min_tvl_usd: None,
             chain: Chain::Ethereum,
             pagination: PaginationParams::default(),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         };
 
         let body2 = ProtocolComponentsRequestBody {
             protocol_system: "protocol1".to_string(),
             component_ids: Some(vec!["component1".to_string(), "component2".to_string()]),
             tvl_gt: Some(1000.0 + 1e-7), // Within the tolerance ±1e-6
+            min_tvl_usd: None,
             chain: Chain::Ethereum,
             pagination: PaginationParams::default(),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         };
 
         // These should be considered equal due to the tolerance in tvl_gt
@@ -1752,22 +3159,48 @@ mod test {
             protocol_system: "protocol1".to_string(),
             component_ids: Some(vec!["component1".to_string(), "component2".to_string()]),
             tvl_gt: Some(1000.0),
+            min_tvl_usd: None,
             chain: Chain::Ethereum,
             pagination: PaginationParams::default(),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         };
 
         let body2 = ProtocolComponentsRequestBody {
             protocol_system: "protocol1".to_string(),
             component_ids: Some(vec!["component1".to_string(), "component2".to_string()]),
             tvl_gt: Some(1000.0 + 1e-5), // Outside the tolerance ±1e-6
+            min_tvl_usd: None,
             chain: Chain::Ethereum,
             pagination: PaginationParams::default(),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         };
 
         // These should not be equal due to the difference in tvl_gt
         assert_ne!(body1, body2);
     }
 
+    #[test]
+    fn test_parse_relative_version() {
+        let blocks_ago: VersionParam =
+            serde_json::from_str(r#"{"relative": {"blocks_ago": 100}}"#).unwrap();
+        let duration: VersionParam =
+            serde_json::from_str(r#"{"relative": {"duration": "24h"}}"#).unwrap();
+
+        assert_eq!(blocks_ago, VersionParam::relative(RelativeVersion::BlocksAgo(100)));
+        assert_eq!(duration, VersionParam::relative(RelativeVersion::Duration("24h".to_string())));
+        // Absolute versions serialize as before, servers without relative versions reject the
+        // field.
+        assert!(!serde_json::to_string(&VersionParam::new(None, None))
+            .unwrap()
+            .contains("relative"));
+    }
+
     #[test]
     fn test_parse_state_request() {
         let json_str = r#"
@@ -1810,9 +3243,19 @@ mod test {
                     chain: Some(Chain::Ethereum),
                     number: Some(block_number),
                 }),
+                finalized_only: false,
+                relative: None,
             },
             chain: Chain::Ethereum,
             pagination: PaginationParams::default(),
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: None,
+            annotate: false,
         };
 
         assert_eq!(result, expected);
@@ -1880,6 +3323,59 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_parse_state_request_with_filters() {
+        let json_str = r#"
+    {
+        "protocol_system": "uniswap_v2",
+        "code_hash": "0xa04b84acdf586a694085997f32c4aa11c2726a7f7e0b677a27d44d180c08e07f",
+        "min_balance": "0x0de0b6b3a7640000",
+        "max_balance": "0x056bc75e2d63100000",
+        "order_by_balance": "desc",
+        "has_code": true
+    }
+    "#;
+
+        let result: StateRequestBody = serde_json::from_str(json_str).unwrap();
+
+        assert_eq!(
+            result.code_hash,
+            Some(Bytes::from("0xa04b84acdf586a694085997f32c4aa11c2726a7f7e0b677a27d44d180c08e07f"))
+        );
+        assert_eq!(result.min_balance, Some(Bytes::from("0x0de0b6b3a7640000")));
+        assert_eq!(result.max_balance, Some(Bytes::from("0x056bc75e2d63100000")));
+        assert_eq!(result.order_by_balance, Some(SortOrder::Desc));
+        assert_eq!(result.has_code, Some(true));
+    }
+
+    #[test]
+    fn test_parse_state_request_fields() {
+        let json_str = r#"{"protocol_system": "uniswap_v2", "fields": ["slots"]}"#;
+
+        let result: StateRequestBody = serde_json::from_str(json_str).unwrap();
+
+        assert!(result.includes(AccountField::Slots));
+        assert!(!result.includes(AccountField::Code));
+        assert!(StateRequestBody::default().includes(AccountField::Code));
+    }
+
+    #[test]
+    fn test_parse_mixed_case_addresses() {
+        let lowercase = Bytes::from("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+
+        let tokens: TokensRequestBody = serde_json::from_str(
+            r#"{"token_addresses": ["0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"]}"#,
+        )
+        .unwrap();
+        let state: StateRequestBody = serde_json::from_str(
+            r#"{"contract_ids": ["0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED"]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(tokens.token_addresses, Some(vec![lowercase.clone()]));
+        assert_eq!(state.contract_ids, Some(vec![lowercase]));
+    }
+
     #[test]
     fn test_parse_state_request_no_contract_specified() {
         let json_str = r#"
@@ -1913,9 +3409,19 @@ mod test {
                     chain: Some(Chain::Ethereum),
                     number: Some(block_number),
                 }),
+                finalized_only: false,
+                relative: None,
             },
             chain: Chain::Ethereum,
             pagination: PaginationParams { page: 0, page_size: 20 },
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: None,
+            annotate: false,
         };
 
         assert_eq!(result, expected);
@@ -1984,6 +3490,8 @@ mod test {
                     chain: Some(Chain::Ethereum),
                     number: Some(block_number),
                 }),
+                finalized_only: false,
+                relative: None,
             },
             chain: Chain::Ethereum,
             include_balances: false,
@@ -2446,7 +3954,7 @@ mod test {
     #[test]
     fn test_block_account_changes_merge() {
         // Prepare account updates
-        let old_account_updates: HashMap<Bytes, AccountUpdate> = [(
+        let old_account_updates: BTreeMap<Bytes, AccountUpdate> = [(
             Bytes::from("0x0011"),
             AccountUpdate {
                 address: Bytes::from("0x00"),
@@ -2459,7 +3967,7 @@ mod test {
         )]
         .into_iter()
         .collect();
-        let new_account_updates: HashMap<Bytes, AccountUpdate> = [(
+        let new_account_updates: BTreeMap<Bytes, AccountUpdate> = [(
             Bytes::from("0x0011"),
             AccountUpdate {
                 address: Bytes::from("0x00"),
@@ -2491,7 +3999,7 @@ mod test {
         let res = block_account_changes_initial.merge(block_account_changes_new);
 
         // Create the expected result of the merge operation
-        let expected_account_updates: HashMap<Bytes, AccountUpdate> = [(
+        let expected_account_updates: BTreeMap<Bytes, AccountUpdate> = [(
             Bytes::from("0x0011"),
             AccountUpdate {
                 address: Bytes::from("0x00"),
@@ -2522,11 +4030,11 @@ mod test {
         let block_entity_changes_result1 = BlockChanges {
             extractor: String::from("extractor1"),
             revert: false,
-            state_updates: hashmap! { "state1".to_string() => ProtocolStateDelta::default() },
-            new_protocol_components: hashmap! { "component1".to_string() => ProtocolComponent::default() },
-            deleted_protocol_components: HashMap::new(),
-            component_balances: hashmap! {
-                "component1".to_string() => TokenBalances(hashmap! {
+            state_updates: btreemap! { "state1".to_string() => ProtocolStateDelta::default() },
+            new_protocol_components: btreemap! { "component1".to_string() => ProtocolComponent::default() },
+            deleted_protocol_components: BTreeMap::new(),
+            component_balances: btreemap! {
+                "component1".to_string() => TokenBalances(btreemap! {
                     Bytes::from("0x01") => ComponentBalance {
                             token: Bytes::from("0x01"),
                             balance: Bytes::from("0x01"),
@@ -2544,20 +4052,20 @@ mod test {
                 })
 
             },
-            component_tvl: hashmap! { "tvl1".to_string() => 1000.0 },
+            component_tvl: btreemap! { "tvl1".to_string() => 1000.0 },
             ..Default::default()
         };
         let block_entity_changes_result2 = BlockChanges {
             extractor: String::from("extractor2"),
             revert: true,
-            state_updates: hashmap! { "state2".to_string() => ProtocolStateDelta::default() },
-            new_protocol_components: hashmap! { "component2".to_string() => ProtocolComponent::default() },
-            deleted_protocol_components: hashmap! { "component3".to_string() => ProtocolComponent::default() },
-            component_balances: hashmap! {
+            state_updates: btreemap! { "state2".to_string() => ProtocolStateDelta::default() },
+            new_protocol_components: btreemap! { "component2".to_string() => ProtocolComponent::default() },
+            deleted_protocol_components: btreemap! { "component3".to_string() => ProtocolComponent::default() },
+            component_balances: btreemap! {
                 "component1".to_string() => TokenBalances::default(),
                 "component2".to_string() => TokenBalances::default()
             },
-            component_tvl: hashmap! { "tvl2".to_string() => 2000.0 },
+            component_tvl: btreemap! { "tvl2".to_string() => 2000.0 },
             ..Default::default()
         };
 
@@ -2566,19 +4074,19 @@ mod test {
         let expected_block_entity_changes_result = BlockChanges {
             extractor: String::from("extractor1"),
             revert: true,
-            state_updates: hashmap! {
+            state_updates: btreemap! {
                 "state1".to_string() => ProtocolStateDelta::default(),
                 "state2".to_string() => ProtocolStateDelta::default(),
             },
-            new_protocol_components: hashmap! {
+            new_protocol_components: btreemap! {
                 "component1".to_string() => ProtocolComponent::default(),
                 "component2".to_string() => ProtocolComponent::default(),
             },
-            deleted_protocol_components: hashmap! {
+            deleted_protocol_components: btreemap! {
                 "component3".to_string() => ProtocolComponent::default(),
             },
-            component_balances: hashmap! {
-                "component1".to_string() => TokenBalances(hashmap! {
+            component_balances: btreemap! {
+                "component1".to_string() => TokenBalances(btreemap! {
                     Bytes::from("0x01") => ComponentBalance {
                             token: Bytes::from("0x01"),
                             balance: Bytes::from("0x01"),
@@ -2596,7 +4104,7 @@ mod test {
                     }),
                 "component2".to_string() => TokenBalances::default(),
             },
-            component_tvl: hashmap! {
+            component_tvl: btreemap! {
                 "tvl1".to_string() => 1000.0,
                 "tvl2".to_string() => 2000.0
             },
@@ -2605,4 +4113,347 @@ mod test {
 
         assert_eq!(res, expected_block_entity_changes_result);
     }
+
+    #[test]
+    fn test_block_changes_merge_keeps_latest_block() {
+        let token = |addr: &str| {
+            (Bytes::from(addr), ResponseToken { address: Bytes::from(addr), ..Default::default() })
+        };
+        let first = BlockChanges {
+            block: Block { number: 1, ..Default::default() },
+            finalized_block_height: 0,
+            new_tokens: BTreeMap::from([token("0x01")]),
+            ..Default::default()
+        };
+        let second = BlockChanges {
+            block: Block { number: 2, ..Default::default() },
+            finalized_block_height: 1,
+            new_tokens: BTreeMap::from([token("0x02")]),
+            ..Default::default()
+        };
+
+        let res = first.merge(second);
+
+        assert_eq!(res.block.number, 2);
+        assert_eq!(res.finalized_block_height, 1);
+        assert_eq!(res.new_tokens, BTreeMap::from([token("0x01"), token("0x02")]));
+    }
+
+    #[rstest]
+    #[case::legacy_client(None, Some(SCHEMA_VERSION))]
+    #[case::picks_newest(Some(vec![1, 2, 3]), Some(2))]
+    #[case::older_client(Some(vec![1]), Some(1))]
+    #[case::unsupported(Some(vec![0, 3]), None)]
+    fn test_negotiate_schema_version(#[case] client: Option<Vec<u32>>, #[case] exp: Option<u32>) {
+        assert_eq!(negotiate_schema_version(client.as_deref()), exp);
+    }
+
+    #[test]
+    fn test_subscribe_command_without_schema_versions() {
+        let cmd: Command = serde_json::from_str(
+            r#"{"method":"subscribe","extractor_id":{"chain":"ethereum","name":"test"},"include_state":true}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Subscribe {
+                extractor_id: ExtractorIdentity::new(Chain::Ethereum, "test"),
+                include_state: true,
+                schema_versions: None,
+                granularity: MessageGranularity::Block,
+                filter: None,
+                heartbeats: false,
+                snapshots: false,
+                value_encoding: ValueEncoding::Hex,
+                attributes: None,
+                coalesce_window_ms: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_subscribe_command_with_filter() {
+        let cmd: Command = serde_json::from_str(
+            r#"{"method":"subscribe","extractor_id":{"chain":"ethereum","name":"test"},"include_state":true,"filter":{"tokens":["0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"]}}"#,
+        )
+        .unwrap();
+
+        let Command::Subscribe { filter, .. } = cmd else { panic!("expected subscribe command") };
+        assert_eq!(
+            filter,
+            Some(SubscriptionFilter {
+                component_ids: HashSet::new(),
+                tokens: HashSet::from([Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2")]),
+                contracts: HashSet::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_subscribe_command_coalescing() {
+        let cmd: Command = serde_json::from_str(
+            r#"{"method":"subscribe","extractor_id":{"chain":"ethereum","name":"test"},"include_state":true,"attributes":["liquidity"],"coalesce_window_ms":2000}"#,
+        )
+        .unwrap();
+
+        let Command::Subscribe { attributes, coalesce_window_ms, .. } = cmd else {
+            panic!("expected subscribe command")
+        };
+        assert_eq!(attributes, Some(HashSet::from(["liquidity".to_string()])));
+        assert_eq!(coalesce_window_ms, Some(2000));
+    }
+
+    #[test]
+    fn test_extractor_status_module_hash_changed() {
+        let package = |version: &str, hash: &str, from_block| models::SubstreamsPackage {
+            name: "ethereum_uniswap_v2".to_string(),
+            version: version.to_string(),
+            module_name: "map_pool_events".to_string(),
+            module_hash: Bytes::from(hash),
+            from_block,
+        };
+        let state = models::ExtractionState::new(
+            "uniswap_v2".to_string(),
+            models::Chain::Ethereum,
+            None,
+            &[],
+            Bytes::from("0x01"),
+        );
+
+        // a new version with the same module does not change the emitted data
+        let status = ExtractorStatus::from(
+            state
+                .clone()
+                .with_substreams_packages(vec![
+                    package("v0.1.0", "0xaa", None),
+                    package("v0.1.1", "0xaa", Some(10)),
+                ]),
+        );
+        assert!(!status.module_hash_changed);
+
+        let status = ExtractorStatus::from(state.with_substreams_packages(vec![
+            package("v0.1.0", "0xaa", None),
+            package("v0.2.0", "0xbb", Some(20)),
+        ]));
+        assert!(status.module_hash_changed);
+        assert_eq!(status.substreams_packages[1].from_block, Some(20));
+    }
+
+    #[test]
+    fn test_subscribe_command_heartbeats() {
+        let cmd = Command::Subscribe {
+            extractor_id: ExtractorIdentity::new(Chain::Ethereum, "test"),
+            include_state: true,
+            schema_versions: None,
+            granularity: MessageGranularity::Block,
+            filter: None,
+            heartbeats: true,
+            snapshots: false,
+            value_encoding: ValueEncoding::Hex,
+            attributes: None,
+            coalesce_window_ms: None,
+        };
+
+        let json = serde_json::to_string(&cmd).unwrap();
+
+        assert!(json.contains(r#""heartbeats":true"#));
+        assert_eq!(serde_json::from_str::<Command>(&json).unwrap(), cmd);
+    }
+
+    #[test]
+    fn test_component_snapshots_message_roundtrip() {
+        let msg = WebSocketMessage::ComponentSnapshots {
+            subscription_id: Uuid::nil(),
+            snapshots: vec![ResponseProtocolState {
+                component_id: "pool".to_string(),
+                attributes: HashMap::from([("reserve".to_string(), Bytes::from("0x01"))]),
+                balances: HashMap::from([(Bytes::from("0xaa"), Bytes::from("0x02"))]),
+                ..Default::default()
+            }],
+        };
+
+        let value = serde_json::to_value(&msg).unwrap();
+        let res: WebSocketMessage = serde_json::from_value(value).unwrap();
+
+        let WebSocketMessage::ComponentSnapshots { snapshots, .. } = res else {
+            panic!("expected component snapshots, got {res:?}")
+        };
+        assert_eq!(snapshots[0].component_id, "pool");
+        assert_eq!(snapshots[0].attributes["reserve"], Bytes::from("0x01"));
+    }
+
+    #[test]
+    fn test_gap_message_roundtrip() {
+        let gap = WebSocketMessage::Gap { subscription_id: Uuid::nil(), dropped_messages: 3 };
+        let ended = WebSocketMessage::Response(Response::SubscriptionEnded {
+            subscription_id: Uuid::nil(),
+        });
+
+        let gap = serde_json::from_value(serde_json::to_value(&gap).unwrap()).unwrap();
+        let ended = serde_json::from_value(serde_json::to_value(&ended).unwrap()).unwrap();
+
+        assert!(matches!(gap, WebSocketMessage::Gap { dropped_messages: 3, .. }));
+        assert!(matches!(ended, WebSocketMessage::Response(Response::SubscriptionEnded { .. })));
+    }
+
+    #[test]
+    fn test_subscription_value_encoding() {
+        let cmd: Command = serde_json::from_str(
+            r#"{"method":"subscribe","extractor_id":{"chain":"ethereum","name":"test"},"include_state":true,"value_encoding":"decimal_string"}"#,
+        )
+        .unwrap();
+        let msg = WebSocketMessage::ComponentSnapshots {
+            subscription_id: Uuid::nil(),
+            snapshots: vec![ResponseProtocolState {
+                component_id: "pool".to_string(),
+                balances: HashMap::from([(Bytes::from("0xaa"), Bytes::from("0x0100"))]),
+                ..Default::default()
+            }],
+        };
+
+        let Command::Subscribe { value_encoding, .. } = cmd else {
+            panic!("expected subscribe command")
+        };
+        let json = crate::serde_primitives::with_value_encoding(value_encoding, || {
+            serde_json::to_value(&msg).unwrap()
+        });
+
+        assert_eq!(value_encoding, ValueEncoding::DecimalString);
+        assert_eq!(json["snapshots"][0]["balances"]["0xaa"], "256");
+        assert_eq!(msg.subscription_id(), Some(Uuid::nil()));
+    }
+
+    #[test]
+    fn test_state_request_code_mode() {
+        let single: StateRequestBody =
+            serde_json::from_str(r#"{"contract_ids":["0xaa"]}"#).unwrap();
+        let multiple: StateRequestBody =
+            serde_json::from_str(r#"{"contract_ids":["0xaa","0xbb"]}"#).unwrap();
+        let listed: StateRequestBody =
+            serde_json::from_str(r#"{"contract_ids":["0xaa","0xbb"],"fields":["code"]}"#).unwrap();
+        let explicit: StateRequestBody =
+            serde_json::from_str(r#"{"contract_ids":["0xaa"],"code":"hash_only"}"#).unwrap();
+
+        assert_eq!(single.code_mode(), CodeMode::Full);
+        assert_eq!(multiple.code_mode(), CodeMode::HashOnly);
+        assert_eq!(listed.code_mode(), CodeMode::Full);
+        assert_eq!(explicit.code_mode(), CodeMode::HashOnly);
+    }
+
+    #[test]
+    fn test_block_changes_serialization_is_stable() {
+        let changes = |ids: Vec<u8>| {
+            let mut block = models::blockchain::BlockAggregatedChanges::default();
+            for i in ids {
+                let address = Bytes::from(vec![i]);
+                let component_id = format!("pc_{i}");
+                block.account_deltas.insert(
+                    address.clone(),
+                    models::contract::AccountDelta::new(
+                        models::Chain::Ethereum,
+                        address.clone(),
+                        (0..8u8)
+                            .map(|slot| (Bytes::from(vec![slot]), Some(Bytes::from(vec![i]))))
+                            .collect(),
+                        None,
+                        None,
+                        models::ChangeType::Update,
+                    ),
+                );
+                block.state_deltas.insert(
+                    component_id.clone(),
+                    models::protocol::ProtocolComponentStateDelta::new(
+                        &component_id,
+                        (0..8u8)
+                            .map(|attr| (format!("attr_{attr}"), Bytes::from(vec![i])))
+                            .collect(),
+                        (0..8u8)
+                            .map(|attr| format!("deleted_{attr}"))
+                            .collect(),
+                    ),
+                );
+                block
+                    .component_tvl
+                    .insert(component_id, i as f64);
+            }
+            block
+        };
+
+        let forward =
+            serde_json::to_string(&BlockChanges::from(changes((0..16).collect()))).unwrap();
+        let backward =
+            serde_json::to_string(&BlockChanges::from(changes((0..16).rev().collect()))).unwrap();
+
+        assert_eq!(forward, backward);
+        let first = forward.find(r#""pc_0":"#).unwrap();
+        let second = forward.find(r#""pc_1":"#).unwrap();
+        assert!(first < second);
+    }
+
+    #[test]
+    fn test_transaction_changes_from_block() {
+        let tx = |index: u64| models::blockchain::TxWithChanges {
+            tx: models::blockchain::Transaction::new(
+                Bytes::from(vec![index as u8]).lpad(32, 0),
+                Bytes::zero(32),
+                Bytes::zero(20),
+                None,
+                index,
+            ),
+            state_updates: HashMap::from([(
+                "pc_1".to_string(),
+                models::protocol::ProtocolComponentStateDelta {
+                    component_id: "pc_1".to_string(),
+                    updated_attributes: HashMap::from([(
+                        "attr".to_string(),
+                        Bytes::from(vec![index as u8]),
+                    )]),
+                    deleted_attributes: HashSet::new(),
+                },
+            )]),
+            ..Default::default()
+        };
+        let block = models::blockchain::BlockAggregatedChanges {
+            extractor: "test".to_string(),
+            component_tvl: HashMap::from([("pc_1".to_string(), 1.0)]),
+            txs_with_update: vec![tx(1), tx(2)],
+            ..Default::default()
+        };
+
+        let res = TransactionChangesMsg::from_block(block);
+
+        assert_eq!(res.len(), 2);
+        for (position, msg) in res.iter().enumerate() {
+            assert_eq!(msg.position, position);
+            assert_eq!(msg.tx_count, 2);
+            assert_eq!(msg.tx.index, position as u64 + 1);
+            assert_eq!(
+                msg.state_updates["pc_1"].updated_attributes["attr"],
+                Bytes::from(vec![position as u8 + 1])
+            );
+        }
+        assert!(res[0].component_tvl.is_empty());
+        assert_eq!(res[1].component_tvl, BTreeMap::from([("pc_1".to_string(), 1.0)]));
+    }
+
+    #[test]
+    fn test_parse_balance_history_request() {
+        let json_str = r#"
+        {
+            "component_id": "pc_1",
+            "token": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "from": "2024-01-01T00:00:00",
+            "to": "2024-01-08T00:00:00",
+            "granularity": "daily"
+        }
+        "#;
+
+        let res: BalanceHistoryRequestBody = serde_json::from_str(json_str).unwrap();
+
+        assert_eq!(res.chain, Chain::Ethereum);
+        assert_eq!(res.token, Bytes::from_str("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap());
+        assert_eq!(res.granularity, SampleInterval::Daily);
+        assert_eq!(res.to - res.from, chrono::Duration::days(7));
+    }
 }
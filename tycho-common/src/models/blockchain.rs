@@ -1,7 +1,8 @@
-use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
 
 use chrono::NaiveDateTime;
 use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use strum_macros::{Display, EnumString};
 use tracing::warn;
 
 use crate::{
@@ -12,7 +13,7 @@ use crate::{
             ComponentBalance, ProtocolChangesWithTx, ProtocolComponent, ProtocolComponentStateDelta,
         },
         token::Token,
-        Address, BlockHash, Chain, ComponentId, EntryPointId, MergeError, StoreKey,
+        Address, BlockHash, Chain, ComponentId, EntryPointId, MergeError, StoreKey, TxHash,
     },
     Bytes,
 };
@@ -24,6 +25,8 @@ pub struct Block {
     pub hash: Bytes,
     pub parent_hash: Bytes,
     pub ts: NaiveDateTime,
+    #[serde(default, skip_serializing_if = "BlockExtra::is_empty")]
+    pub extra: BlockExtra,
 }
 
 impl Block {
@@ -34,11 +37,238 @@ impl Block {
         parent_hash: Bytes,
         ts: NaiveDateTime,
     ) -> Self {
-        Block { hash, parent_hash, number, chain, ts }
+        Block { hash, parent_hash, number, chain, ts, extra: BlockExtra::default() }
+    }
+
+    pub fn with_extra(mut self, extra: BlockExtra) -> Self {
+        self.extra = extra;
+        self
+    }
+}
+
+/// Header fields beyond the ones every block carries, keyed by name.
+///
+/// Forks keep adding header fields, e.g. the blob gas fields of EIP-4844. They are stored as a
+/// single JSON object, so a new field only needs a key and accessor here, not a schema change.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(transparent)]
+pub struct BlockExtra(serde_json::Map<String, serde_json::Value>);
+
+impl BlockExtra {
+    pub const BASE_FEE_PER_GAS: &'static str = "base_fee_per_gas";
+    pub const BLOB_GAS_USED: &'static str = "blob_gas_used";
+    pub const EXCESS_BLOB_GAS: &'static str = "excess_blob_gas";
+    pub const PARENT_BEACON_BLOCK_ROOT: &'static str = "parent_beacon_block_root";
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the field `key`, or `None` if it is missing or not a `T`.
+    pub fn get<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.0
+            .get(key)
+            .and_then(|value| T::deserialize(value).ok())
+    }
+
+    /// Sets the field `key`, a `None` value removes it.
+    pub fn set<T: Serialize>(&mut self, key: &str, value: Option<T>) {
+        match value.and_then(|v| serde_json::to_value(v).ok()) {
+            Some(value) => {
+                self.0.insert(key.to_string(), value);
+            }
+            None => {
+                self.0.remove(key);
+            }
+        }
+    }
+
+    pub fn base_fee_per_gas(&self) -> Option<u64> {
+        self.get(Self::BASE_FEE_PER_GAS)
+    }
+
+    pub fn blob_gas_used(&self) -> Option<u64> {
+        self.get(Self::BLOB_GAS_USED)
+    }
+
+    pub fn excess_blob_gas(&self) -> Option<u64> {
+        self.get(Self::EXCESS_BLOB_GAS)
+    }
+
+    pub fn parent_beacon_block_root(&self) -> Option<Bytes> {
+        self.get(Self::PARENT_BEACON_BLOCK_ROOT)
+    }
+}
+
+impl From<serde_json::Value> for BlockExtra {
+    /// Anything but a JSON object yields no fields.
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Object(fields) => Self(fields),
+            _ => Self::default(),
+        }
+    }
+}
+
+impl From<BlockExtra> for serde_json::Value {
+    fn from(value: BlockExtra) -> Self {
+        serde_json::Value::Object(value.0)
+    }
+}
+
+/// A block message an extractor could not decode, kept so it can be replayed once the decoding
+/// issue is fixed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QuarantinedMessage {
+    pub block_number: u64,
+    /// Unset if the substreams clock didn't carry a valid block hash
+    pub block_hash: Option<BlockHash>,
+    /// Protobuf type of the payload
+    pub type_url: String,
+    /// The undecoded message
+    pub payload: Bytes,
+    /// Kind of the decode failure, e.g. `invalid_length`
+    pub kind: String,
+    pub error: String,
+}
+
+/// Deterministic digest of the changes written for a block.
+///
+/// Two deployments indexing the same chain store the same digest for a block if they wrote the
+/// same state. Comparing digests over a block range locates the first divergent block without
+/// comparing the state itself.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BlockDigest {
+    pub chain: Chain,
+    pub block_number: u64,
+    pub block_hash: BlockHash,
+    pub digest: Bytes,
+}
+
+impl BlockDigest {
+    /// Computes the digest of the changes written for `block`.
+    ///
+    /// Changes are reduced to the value each key holds at the end of the block, so the digest
+    /// only depends on the written state, not on the order in which it was collected. Deltas are
+    /// expected in transaction order.
+    pub fn compute(
+        block: &Block,
+        account_deltas: &[(TxHash, AccountDelta)],
+        state_deltas: &[(TxHash, ProtocolComponentStateDelta)],
+        component_balances: &[ComponentBalance],
+        account_balances: &[AccountBalance],
+    ) -> Self {
+        let mut records = BTreeMap::new();
+        for (_, delta) in account_deltas {
+            let address = delta.address.as_ref();
+            for (slot, value) in &delta.slots {
+                records.insert(
+                    digest_key("slot", &[address, slot.as_ref()]),
+                    value.as_ref().map(Bytes::to_vec),
+                );
+            }
+            if let Some(balance) = &delta.balance {
+                records.insert(digest_key("native_balance", &[address]), Some(balance.to_vec()));
+            }
+            if let Some(code) = &delta.code {
+                records
+                    .insert(digest_key("code", &[address]), Some(crate::keccak256(code).to_vec()));
+            }
+        }
+        for (_, delta) in state_deltas {
+            let component_id = delta.component_id.as_bytes();
+            for (name, value) in &delta.updated_attributes {
+                records.insert(
+                    digest_key("attribute", &[component_id, name.as_bytes()]),
+                    Some(value.to_vec()),
+                );
+            }
+            for name in &delta.deleted_attributes {
+                records.insert(digest_key("attribute", &[component_id, name.as_bytes()]), None);
+            }
+        }
+        for balance in component_balances {
+            records.insert(
+                digest_key(
+                    "component_balance",
+                    &[balance.component_id.as_bytes(), balance.token.as_ref()],
+                ),
+                Some(balance.balance.to_vec()),
+            );
+        }
+        for balance in account_balances {
+            records.insert(
+                digest_key("account_balance", &[balance.account.as_ref(), balance.token.as_ref()]),
+                Some(balance.balance.to_vec()),
+            );
+        }
+
+        let mut encoded = Vec::new();
+        for (key, value) in records {
+            encoded.extend_from_slice(&key);
+            match value {
+                // Deleted values are distinct from empty ones
+                None => encoded.push(0),
+                Some(value) => {
+                    encoded.push(1);
+                    encode_field(&mut encoded, &value);
+                }
+            }
+        }
+
+        Self {
+            chain: block.chain,
+            block_number: block.number,
+            block_hash: block.hash.clone(),
+            digest: Bytes::from(crate::keccak256(encoded).to_vec()),
+        }
     }
 }
 
-#[derive(Clone, Default, PartialEq, Debug, Eq, Hash)]
+/// Encodes a record key from its kind and fields, length prefixed so fields can't run into each
+/// other.
+fn digest_key(kind: &str, fields: &[&[u8]]) -> Vec<u8> {
+    let mut key = Vec::new();
+    encode_field(&mut key, kind.as_bytes());
+    for field in fields {
+        encode_field(&mut key, field);
+    }
+    key
+}
+
+fn encode_field(buf: &mut Vec<u8>, field: &[u8]) {
+    buf.extend_from_slice(&(field.len() as u32).to_be_bytes());
+    buf.extend_from_slice(field);
+}
+
+/// Finality of a stored block on its chain, ordered from weakest to strongest.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum FinalityStatus {
+    /// Stored, but not yet confirmed final.
+    Pending,
+    /// Part of the chain's safe head, reorgs are unlikely but possible. Only set by finality
+    /// sources that report a safe head.
+    Safe,
+    /// Can not be reorged anymore.
+    Finalized,
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Eq, Hash, Serialize, Deserialize)]
 pub struct Transaction {
     pub hash: Bytes,
     pub block_hash: Bytes,
@@ -86,6 +316,14 @@ pub struct BlockAggregatedChanges {
     pub account_balances: HashMap<Address, HashMap<Address, AccountBalance>>,
     pub component_tvl: HashMap<String, f64>,
     pub dci_update: DCIUpdate,
+    /// The changes of this block grouped by transaction, sorted by transaction index. Allows
+    /// consumers to follow intra-block ordering, empty for reverts.
+    #[serde(default)]
+    pub txs_with_update: Vec<TxWithChanges>,
+    /// Set on messages carrying only the block metadata of a block without any tracked changes.
+    /// They let consumers tell a live extractor apart from a stalled one.
+    #[serde(default)]
+    pub heartbeat: bool,
 }
 
 impl BlockAggregatedChanges {
@@ -121,6 +359,25 @@ impl BlockAggregatedChanges {
             account_balances,
             component_tvl,
             dci_update,
+            txs_with_update: Vec::new(),
+            heartbeat: false,
+        }
+    }
+
+    /// Creates a heartbeat message for a block without any tracked changes.
+    pub fn heartbeat(
+        extractor: &str,
+        chain: Chain,
+        block: Block,
+        finalized_block_height: u64,
+    ) -> Self {
+        Self {
+            extractor: extractor.to_string(),
+            chain,
+            block,
+            finalized_block_height,
+            heartbeat: true,
+            ..Default::default()
         }
     }
 }
@@ -148,10 +405,242 @@ impl BlockAggregatedChanges {
             account_balances: self.account_balances.clone(),
             component_tvl: self.component_tvl.clone(),
             dci_update: self.dci_update.clone(),
+            txs_with_update: self
+                .txs_with_update
+                .iter()
+                .map(|tx| TxWithChanges {
+                    account_deltas: HashMap::new(),
+                    state_updates: HashMap::new(),
+                    ..tx.clone()
+                })
+                .collect(),
+            heartbeat: self.heartbeat,
+        }
+    }
+
+    /// Drops updates and deletions of all component attributes except the given ones. State
+    /// deltas left without any attribute change are removed.
+    pub fn retain_attributes(&mut self, attributes: &HashSet<String>) {
+        let retain = |deltas: &mut HashMap<ComponentId, ProtocolComponentStateDelta>| {
+            deltas.retain(|_, delta| {
+                delta
+                    .updated_attributes
+                    .retain(|attr, _| attributes.contains(attr));
+                delta
+                    .deleted_attributes
+                    .retain(|attr| attributes.contains(attr));
+                !delta.updated_attributes.is_empty() || !delta.deleted_attributes.is_empty()
+            });
+        };
+        retain(&mut self.state_deltas);
+        for tx in self.txs_with_update.iter_mut() {
+            retain(&mut tx.state_updates);
         }
     }
 }
 
+/// Stateful filter narrowing the changes of a subscription down to the components and contracts
+/// a client is interested in.
+///
+/// Deltas reference components only by id, so components matched by token or contract are
+/// resolved from the component creations and balance changes passing through the filter. A
+/// filter must therefore be kept for the whole lifetime of a subscription.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeltasFilter {
+    component_ids: HashSet<ComponentId>,
+    tokens: HashSet<Address>,
+    contracts: HashSet<Address>,
+    /// Tracked components whose creation didn't pass through the filter, so their deltas alone
+    /// don't give their full state.
+    without_state: HashSet<ComponentId>,
+}
+
+impl DeltasFilter {
+    pub fn new(
+        component_ids: HashSet<ComponentId>,
+        tokens: HashSet<Address>,
+        contracts: HashSet<Address>,
+    ) -> Self {
+        let without_state = component_ids.clone();
+        Self { component_ids, tokens, contracts, without_state }
+    }
+
+    /// Takes the components the filter started tracking mid-stream, without having seen their
+    /// creation. These are the explicitly filtered components and existing components matched by
+    /// a balance change. Clients need a snapshot of their state to make use of their deltas.
+    pub fn take_components_without_state(&mut self) -> HashSet<ComponentId> {
+        std::mem::take(&mut self.without_state)
+    }
+
+    /// Start tracking the component if it holds a filtered token or uses a filtered contract.
+    fn observe_component(&mut self, component: &ProtocolComponent) {
+        let matches = self
+            .component_ids
+            .contains(&component.id) ||
+            component
+                .tokens
+                .iter()
+                .any(|t| self.tokens.contains(t)) ||
+            component
+                .contract_addresses
+                .iter()
+                .any(|c| self.contracts.contains(c));
+        if matches {
+            self.component_ids
+                .insert(component.id.clone());
+            self.contracts.extend(
+                component
+                    .contract_addresses
+                    .iter()
+                    .cloned(),
+            );
+        }
+    }
+
+    fn observe_balances<'a>(
+        &mut self,
+        balances: impl Iterator<Item = (&'a ComponentId, &'a HashMap<Address, ComponentBalance>)>,
+    ) {
+        for (component_id, balances) in balances {
+            if balances
+                .keys()
+                .any(|t| self.tokens.contains(t)) &&
+                self.component_ids
+                    .insert(component_id.clone())
+            {
+                self.without_state
+                    .insert(component_id.clone());
+            }
+        }
+    }
+
+    /// Removes all changes that don't concern the filtered components or contracts.
+    pub fn apply(&mut self, mut changes: BlockAggregatedChanges) -> BlockAggregatedChanges {
+        for component in changes
+            .new_protocol_components
+            .values()
+            .chain(
+                changes
+                    .deleted_protocol_components
+                    .values(),
+            )
+        {
+            self.observe_component(component);
+        }
+        self.observe_balances(changes.component_balances.iter());
+        for id in changes.new_protocol_components.keys() {
+            self.without_state.remove(id);
+        }
+
+        let components = &self.component_ids;
+        let contracts = &self.contracts;
+        changes
+            .state_deltas
+            .retain(|id, _| components.contains(id));
+        changes
+            .account_deltas
+            .retain(|addr, _| contracts.contains(addr));
+        changes
+            .new_protocol_components
+            .retain(|id, _| components.contains(id));
+        changes
+            .deleted_protocol_components
+            .retain(|id, _| components.contains(id));
+        changes
+            .component_balances
+            .retain(|id, _| components.contains(id));
+        changes
+            .account_balances
+            .retain(|addr, _| contracts.contains(addr));
+        changes
+            .component_tvl
+            .retain(|id, _| components.contains(id));
+
+        let used_tokens: HashSet<Address> = changes
+            .new_protocol_components
+            .values()
+            .flat_map(|c| c.tokens.iter().cloned())
+            .collect();
+        changes
+            .new_tokens
+            .retain(|addr, _| self.tokens.contains(addr) || used_tokens.contains(addr));
+
+        changes
+            .dci_update
+            .new_entrypoints
+            .retain(|id, _| components.contains(id));
+        retain_entrypoint_params(&mut changes.dci_update.new_entrypoint_params, components);
+        let kept_entrypoints: HashSet<&EntryPointId> = changes
+            .dci_update
+            .new_entrypoints
+            .values()
+            .flatten()
+            .map(|ep| &ep.external_id)
+            .chain(
+                changes
+                    .dci_update
+                    .new_entrypoint_params
+                    .keys(),
+            )
+            .collect();
+        let trace_results = std::mem::take(&mut changes.dci_update.trace_results)
+            .into_iter()
+            .filter(|(id, _)| kept_entrypoints.contains(id))
+            .collect();
+        changes.dci_update.trace_results = trace_results;
+
+        changes.txs_with_update = std::mem::take(&mut changes.txs_with_update)
+            .into_iter()
+            .filter_map(|mut tx| {
+                tx.protocol_components
+                    .retain(|id, _| components.contains(id));
+                tx.account_deltas
+                    .retain(|addr, _| contracts.contains(addr));
+                tx.state_updates
+                    .retain(|id, _| components.contains(id));
+                tx.balance_changes
+                    .retain(|id, _| components.contains(id));
+                tx.account_balance_changes
+                    .retain(|addr, _| contracts.contains(addr));
+                tx.entrypoints
+                    .retain(|id, _| components.contains(id));
+                retain_entrypoint_params(&mut tx.entrypoint_params, components);
+                let is_empty = tx.protocol_components.is_empty() &&
+                    tx.account_deltas.is_empty() &&
+                    tx.state_updates.is_empty() &&
+                    tx.balance_changes.is_empty() &&
+                    tx.account_balance_changes.is_empty() &&
+                    tx.entrypoints.is_empty() &&
+                    tx.entrypoint_params.is_empty();
+                (!is_empty).then_some(tx)
+            })
+            .collect();
+
+        changes
+    }
+}
+
+impl From<dto::SubscriptionFilter> for DeltasFilter {
+    fn from(value: dto::SubscriptionFilter) -> Self {
+        Self::new(value.component_ids, value.tokens, value.contracts)
+    }
+}
+
+/// Keeps only the tracing params that belong to one of the given components.
+fn retain_entrypoint_params(
+    params: &mut HashMap<EntryPointId, HashSet<(TracingParams, Option<ComponentId>)>>,
+    components: &HashSet<ComponentId>,
+) {
+    params.retain(|_, params| {
+        params.retain(|(_, component)| {
+            component
+                .as_ref()
+                .is_some_and(|id| components.contains(id))
+        });
+        !params.is_empty()
+    });
+}
+
 pub trait BlockScoped {
     fn block(&self) -> Block;
 }
@@ -170,6 +659,7 @@ impl From<dto::Block> for Block {
             hash: value.hash,
             parent_hash: value.parent_hash,
             ts: value.ts,
+            extra: BlockExtra::default(),
         }
     }
 }
@@ -182,7 +672,7 @@ pub struct DCIUpdate {
 }
 
 /// Changes grouped by their respective transaction.
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct TxWithChanges {
     pub tx: Transaction,
     pub protocol_components: HashMap<ComponentId, ProtocolComponent>,
@@ -854,4 +1344,217 @@ pub mod fixtures {
             &HashSet::from([store_key1.clone(), store_key2.clone()])
         );
     }
+
+    #[test]
+    fn test_deltas_filter() {
+        let weth = Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let usdc = Bytes::from("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+        let pool_contract = Bytes::from("0xaaaaaaaaa24eeeb8d57d431224f73832bc34f688");
+        let other_contract = Bytes::from("0xbbbbbbbbb24eeeb8d57d431224f73832bc34f688");
+        let weth_pool = ProtocolComponent {
+            id: "weth_pool".to_string(),
+            tokens: vec![weth.clone(), usdc.clone()],
+            contract_addresses: vec![pool_contract.clone()],
+            ..Default::default()
+        };
+        let usdc_pool = ProtocolComponent {
+            id: "usdc_pool".to_string(),
+            tokens: vec![usdc.clone()],
+            ..Default::default()
+        };
+        let delta = |id: &str| {
+            (id.to_string(), ProtocolComponentStateDelta::new(id, HashMap::new(), HashSet::new()))
+        };
+        let account = |addr: &Bytes| {
+            (addr.clone(), AccountDelta { address: addr.clone(), ..Default::default() })
+        };
+        let creation = BlockAggregatedChanges {
+            new_protocol_components: HashMap::from([
+                (weth_pool.id.clone(), weth_pool.clone()),
+                (usdc_pool.id.clone(), usdc_pool.clone()),
+            ]),
+            state_deltas: HashMap::from([delta("weth_pool"), delta("usdc_pool")]),
+            account_deltas: HashMap::from([account(&pool_contract), account(&other_contract)]),
+            ..Default::default()
+        };
+        let update = BlockAggregatedChanges {
+            state_deltas: HashMap::from([delta("weth_pool"), delta("usdc_pool")]),
+            account_deltas: HashMap::from([account(&pool_contract), account(&other_contract)]),
+            ..Default::default()
+        };
+        let mut filter = DeltasFilter::new(HashSet::new(), HashSet::from([weth]), HashSet::new());
+
+        let filtered_creation = filter.apply(creation);
+        // Once resolved, the component is also matched in blocks that don't contain its creation.
+        let filtered_update = filter.apply(update);
+
+        for res in [filtered_creation, filtered_update] {
+            assert_eq!(
+                res.state_deltas
+                    .keys()
+                    .collect::<Vec<_>>(),
+                vec!["weth_pool"]
+            );
+            assert_eq!(
+                res.account_deltas
+                    .keys()
+                    .collect::<Vec<_>>(),
+                vec![&pool_contract]
+            );
+        }
+    }
+
+    #[test]
+    fn test_deltas_filter_components_without_state() {
+        let weth = Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let balance = |id: &str| {
+            (
+                id.to_string(),
+                HashMap::from([(
+                    weth.clone(),
+                    ComponentBalance::new(
+                        weth.clone(),
+                        Bytes::from("0x01"),
+                        1.0,
+                        Bytes::zero(32),
+                        id,
+                    ),
+                )]),
+            )
+        };
+        let new_pool = ProtocolComponent {
+            id: "new_pool".to_string(),
+            tokens: vec![weth.clone()],
+            ..Default::default()
+        };
+        let changes = BlockAggregatedChanges {
+            new_protocol_components: HashMap::from([(new_pool.id.clone(), new_pool)]),
+            component_balances: HashMap::from([balance("new_pool"), balance("existing_pool")]),
+            ..Default::default()
+        };
+        let mut filter = DeltasFilter::new(
+            HashSet::from(["requested_pool".to_string()]),
+            HashSet::from([weth.clone()]),
+            HashSet::new(),
+        );
+
+        let initial = filter.take_components_without_state();
+        filter.apply(changes.clone());
+        let matched = filter.take_components_without_state();
+        filter.apply(changes);
+
+        assert_eq!(initial, HashSet::from(["requested_pool".to_string()]));
+        assert_eq!(matched, HashSet::from(["existing_pool".to_string()]));
+        assert!(filter
+            .take_components_without_state()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_retain_attributes() {
+        let delta = |id: &str, updated: &[&str], deleted: &[&str]| {
+            (
+                id.to_string(),
+                ProtocolComponentStateDelta::new(
+                    id,
+                    updated
+                        .iter()
+                        .map(|attr| (attr.to_string(), Bytes::from("0x01")))
+                        .collect(),
+                    deleted
+                        .iter()
+                        .map(|attr| attr.to_string())
+                        .collect(),
+                ),
+            )
+        };
+        let mut changes = BlockAggregatedChanges {
+            state_deltas: HashMap::from([
+                delta("pool_a", &["reserve0", "fee"], &["tick"]),
+                delta("pool_b", &["fee"], &[]),
+            ]),
+            txs_with_update: vec![TxWithChanges {
+                state_updates: HashMap::from([delta("pool_a", &["reserve0"], &[])]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        changes.retain_attributes(&HashSet::from(["reserve0".to_string(), "tick".to_string()]));
+
+        assert_eq!(
+            changes.state_deltas,
+            HashMap::from([delta("pool_a", &["reserve0"], &["tick"])])
+        );
+        assert_eq!(
+            changes.txs_with_update[0].state_updates,
+            HashMap::from([delta("pool_a", &["reserve0"], &[])])
+        );
+    }
+
+    #[test]
+    fn test_block_extra() {
+        let mut extra = BlockExtra::default();
+        extra.set(BlockExtra::BLOB_GAS_USED, Some(131072u64));
+        extra.set(BlockExtra::PARENT_BEACON_BLOCK_ROOT, Some(Bytes::from("0x0102")));
+        extra.set::<u64>(BlockExtra::EXCESS_BLOB_GAS, None);
+        let block = Block::default().with_extra(extra);
+
+        let decoded: Block = serde_json::from_str(&serde_json::to_string(&block).unwrap()).unwrap();
+        let legacy: Block = serde_json::from_str(
+            r#"{"number":1,"chain":"ethereum","hash":"0x01","parent_hash":"0x00","ts":"2020-01-01T00:00:00"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(decoded.extra.blob_gas_used(), Some(131072));
+        assert_eq!(decoded.extra.parent_beacon_block_root(), Some(Bytes::from("0x0102")));
+        assert_eq!(decoded.extra.excess_blob_gas(), None);
+        assert!(legacy.extra.is_empty());
+    }
+
+    #[test]
+    fn test_block_digest() {
+        let token = Bytes::from("0x01");
+        let balance = |tx: u8, value: u64| {
+            ComponentBalance::new(
+                token.clone(),
+                Bytes::from(value),
+                value as f64,
+                Bytes::from(vec![tx]),
+                "pool",
+            )
+        };
+        let state = |attribute: &str| {
+            (
+                Bytes::from("0x02"),
+                ProtocolComponentStateDelta::new(
+                    "pool",
+                    HashMap::from([(attribute.to_string(), Bytes::from("0x03"))]),
+                    HashSet::new(),
+                ),
+            )
+        };
+        let block = Block::default();
+
+        let digest = BlockDigest::compute(
+            &block,
+            &[],
+            &[state("a"), state("b")],
+            &[balance(1, 100), balance(2, 200)],
+            &[],
+        );
+        let reordered = BlockDigest::compute(
+            &block,
+            &[],
+            &[state("b"), state("a")],
+            &[balance(3, 50), balance(2, 200)],
+            &[],
+        );
+        let diverged =
+            BlockDigest::compute(&block, &[], &[state("a"), state("b")], &[balance(2, 300)], &[]);
+
+        assert_eq!(digest.digest.len(), 32);
+        assert_eq!(digest, reordered);
+        assert_ne!(digest.digest, diverged.digest);
+    }
 }
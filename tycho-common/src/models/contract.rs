@@ -8,8 +8,8 @@ use crate::{
     models::{
         blockchain::Transaction,
         protocol::{ComponentBalance, ProtocolComponent},
-        Address, Balance, Chain, ChangeType, Code, CodeHash, ComponentId, ContractId,
-        ContractStore, ContractStoreDeltas, MergeError, TxHash,
+        Address, Balance, BuildError, Chain, ChangeType, Code, CodeHash, ComponentId, ContractId,
+        ContractStore, ContractStoreDeltas, MergeError, StoreKey, StoreVal, TxHash,
     },
     Bytes,
 };
@@ -92,6 +92,129 @@ impl Account {
     }
 }
 
+/// Builds an [`Account`], deriving its code hash from its code.
+///
+/// Unset fields default to an empty account: no slots, balances or code, the address as title
+/// and zero hashes as modifying transactions.
+#[derive(Clone, Debug)]
+pub struct AccountBuilder {
+    chain: Chain,
+    address: Address,
+    title: Option<String>,
+    slots: ContractStore,
+    native_balance: Balance,
+    token_balances: HashMap<Address, AccountBalance>,
+    code: Code,
+    code_hash: Option<CodeHash>,
+    balance_modify_tx: TxHash,
+    code_modify_tx: TxHash,
+    creation_tx: Option<TxHash>,
+}
+
+impl AccountBuilder {
+    pub fn new(chain: Chain, address: Address) -> Self {
+        Self {
+            chain,
+            address,
+            title: None,
+            slots: ContractStore::new(),
+            native_balance: Balance::default(),
+            token_balances: HashMap::new(),
+            code: Code::default(),
+            code_hash: None,
+            balance_modify_tx: Bytes::zero(32),
+            code_modify_tx: Bytes::zero(32),
+            creation_tx: None,
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn slots(mut self, slots: ContractStore) -> Self {
+        self.slots = slots;
+        self
+    }
+
+    pub fn native_balance(mut self, balance: Balance) -> Self {
+        self.native_balance = balance;
+        self
+    }
+
+    /// Adds a token balance, replacing any previous balance of the same token.
+    pub fn token_balance(mut self, balance: AccountBalance) -> Self {
+        self.token_balances
+            .insert(balance.token.clone(), balance);
+        self
+    }
+
+    pub fn code(mut self, code: Code) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Sets the expected code hash. Building fails if it does not match the hash of the code.
+    pub fn code_hash(mut self, code_hash: CodeHash) -> Self {
+        self.code_hash = Some(code_hash);
+        self
+    }
+
+    pub fn balance_modify_tx(mut self, tx: TxHash) -> Self {
+        self.balance_modify_tx = tx;
+        self
+    }
+
+    pub fn code_modify_tx(mut self, tx: TxHash) -> Self {
+        self.code_modify_tx = tx;
+        self
+    }
+
+    pub fn creation_tx(mut self, tx: TxHash) -> Self {
+        self.creation_tx = Some(tx);
+        self
+    }
+
+    /// Uses `tx` as creation transaction and as last balance and code modification.
+    pub fn created_by(self, tx: &TxHash) -> Self {
+        self.creation_tx(tx.clone())
+            .balance_modify_tx(tx.clone())
+            .code_modify_tx(tx.clone())
+    }
+
+    pub fn build(self) -> Result<Account, BuildError> {
+        let code_hash = CodeHash::from(keccak256(&self.code));
+        if let Some(expected) = self.code_hash {
+            if expected != code_hash {
+                return Err(BuildError::CodeHashMismatch(self.address, expected, code_hash));
+            }
+        }
+        if let Some(foreign) = self
+            .token_balances
+            .values()
+            .find(|balance| balance.account != self.address)
+        {
+            return Err(BuildError::ForeignBalance(self.address, foreign.account.clone()));
+        }
+        Ok(Account {
+            title: self
+                .title
+                .unwrap_or_else(|| format!("{:#020x}", self.address)),
+            chain: self.chain,
+            address: self.address,
+            slots: self.slots,
+            native_balance: self.native_balance,
+            token_balances: self.token_balances,
+            code: self.code,
+            code_hash,
+            balance_modify_tx: self.balance_modify_tx,
+            code_modify_tx: self.code_modify_tx,
+            creation_tx: self.creation_tx,
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
 pub struct AccountDelta {
     pub chain: Chain,
@@ -466,6 +589,50 @@ impl From<&AccountChangesWithTx> for Vec<Account> {
     }
 }
 
+/// Merkle proof of an account and some of its storage slots against a block's state root, as
+/// returned by `eth_getProof` (EIP-1186).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AccountProof {
+    pub address: Address,
+    pub balance: Balance,
+    pub nonce: u64,
+    pub code_hash: CodeHash,
+    pub storage_hash: Bytes,
+    /// Trie nodes from the state root to the account
+    pub account_proof: Vec<Bytes>,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+/// Merkle proof of a storage slot against the account's storage root.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StorageProof {
+    pub key: StoreKey,
+    pub value: StoreVal,
+    /// Trie nodes from the storage root to the slot
+    pub proof: Vec<Bytes>,
+}
+
+/// Known meaning of a contract's storage slot, e.g. that slot `0x0` holds the owner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotAnnotation {
+    pub chain: Chain,
+    pub address: Address,
+    /// The slot, left padded to 32 bytes.
+    pub slot: StoreKey,
+    pub label: String,
+    pub description: Option<String>,
+}
+
+/// A contract an extractor persists the changes of, whether or not a component holds it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedContract {
+    pub address: Address,
+    /// First block whose changes of the contract are persisted
+    pub from_block: u64,
+    /// First block the contract is no longer tracked in, unset while it is tracked
+    pub until_block: Option<u64>,
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
@@ -564,23 +731,47 @@ mod test {
     }
 
     fn account() -> Account {
-        let code = vec![0, 0, 0, 0];
-        let code_hash = Bytes::from(keccak256(&code));
-        Account::new(
+        AccountBuilder::new(
             Chain::Ethereum,
             "0xe688b84b23f322a994A53dbF8E15FA82CDB71127"
                 .parse()
                 .unwrap(),
-            "0xe688b84b23f322a994a53dbf8e15fa82cdb71127".into(),
-            HashMap::new(),
-            Bytes::from(10000u64).lpad(32, 0),
-            HashMap::new(),
-            code.into(),
-            code_hash,
-            Bytes::zero(32),
-            Bytes::zero(32),
-            Some(Bytes::zero(32)),
         )
+        .native_balance(Bytes::from(10000u64).lpad(32, 0))
+        .code(Bytes::from(vec![0, 0, 0, 0]))
+        .created_by(&Bytes::zero(32))
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_account_builder_validates() {
+        let address = Bytes::from("0xe688b84b23f322a994a53dbf8e15fa82cdb71127");
+        let builder =
+            AccountBuilder::new(Chain::Ethereum, address.clone()).code(Bytes::from("0x00"));
+
+        let mismatch = builder
+            .clone()
+            .code_hash(Bytes::zero(32))
+            .build();
+        let foreign = builder
+            .token_balance(AccountBalance::new(
+                Bytes::from("0x01"),
+                Bytes::from("0x02"),
+                Bytes::from("0x03"),
+                Bytes::zero(32),
+            ))
+            .build();
+
+        assert_eq!(
+            mismatch,
+            Err(BuildError::CodeHashMismatch(
+                address.clone(),
+                Bytes::zero(32),
+                keccak256([0u8]).into()
+            ))
+        );
+        assert_eq!(foreign, Err(BuildError::ForeignBalance(address, Bytes::from("0x01"))));
     }
 
     #[test]
@@ -53,6 +53,39 @@ pub type AccountToContractStoreDeltas = HashMap<Address, ContractStoreDeltas>;
 /// Component id literal type to uniquely identify a component.
 pub type ComponentId = String;
 
+/// Brings a component id into the form it is stored in.
+///
+/// Many components are identified by their hex encoded address, which is stored in lowercase.
+/// Clients often send these checksummed, so `0x` prefixed hex ids are lowercased. Other ids are
+/// case sensitive and returned unchanged.
+pub fn normalize_component_id(id: &str) -> ComponentId {
+    match id.strip_prefix("0x") {
+        Some(digits)
+            if !digits.is_empty() &&
+                digits
+                    .bytes()
+                    .all(|b| b.is_ascii_hexdigit()) =>
+        {
+            id.to_ascii_lowercase()
+        }
+        _ => id.to_string(),
+    }
+}
+
+/// Ids a component id received as a filter is matched against: the id as given and, if it
+/// differs, its normalized form, see [`normalize_component_id`].
+///
+/// Stored ids keep the casing they were indexed with, so a component is found by its exact id as
+/// well as by the checksummed form of a lowercase hex id.
+pub fn component_id_candidates(id: &str) -> Vec<ComponentId> {
+    let normalized = normalize_component_id(id);
+    if normalized == id {
+        vec![normalized]
+    } else {
+        vec![id.to_string(), normalized]
+    }
+}
+
 /// Protocol system literal type to uniquely identify a protocol system.
 pub type ProtocolSystem = String;
 
@@ -99,6 +132,28 @@ fn native_eth(chain: Chain) -> Token {
     )
 }
 
+/// EIP-55 mixed case checksum encoding, falls back to plain hex for non 20 byte inputs.
+fn to_checksum_address(address: &[u8]) -> String {
+    let lower = hex::encode(address);
+    if address.len() != 20 {
+        return format!("0x{lower}");
+    }
+    let hash = crate::keccak256(lower.as_bytes());
+    let checksummed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{checksummed}")
+}
+
 fn wrapped_native_eth(chain: Chain, address: &str) -> Token {
     Token::new(&Bytes::from_str(address).unwrap(), "WETH", 18, 0, &[Some(2300)], chain, 100)
 }
@@ -115,6 +170,20 @@ impl Chain {
         }
     }
 
+    /// Formats an address the way it is usually displayed on this chain.
+    ///
+    /// EVM chains use EIP-55 checksummed addresses, Starknet addresses are zero padded to 32
+    /// bytes. Addresses that don't have the expected length are hex encoded as is.
+    pub fn format_address(&self, address: &[u8]) -> String {
+        match self {
+            Chain::Starknet if address.len() <= 32 => format!("0x{:0>64}", hex::encode(address)),
+            Chain::Starknet => format!("0x{}", hex::encode(address)),
+            Chain::Ethereum | Chain::ZkSync | Chain::Arbitrum | Chain::Base | Chain::Unichain => {
+                to_checksum_address(address)
+            }
+        }
+    }
+
     /// Returns the native token for the chain.
     pub fn native_token(&self) -> Token {
         match self {
@@ -153,6 +222,20 @@ impl Chain {
             }
         }
     }
+
+    /// Returns the USD stablecoin whose indexed price is used to convert native token values to
+    /// USD.
+    pub fn usd_reference_token(&self) -> Address {
+        let address = match self {
+            Chain::Ethereum => "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+            Chain::Starknet => "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8",
+            Chain::ZkSync => "0x1d17CBcF0D6D143135aE902365D2E5e2A16538D4",
+            Chain::Arbitrum => "0xaf88d065e77c8cC2239327C5EDb3A432268e5831",
+            Chain::Base => "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+            Chain::Unichain => "0x078D782b760474a361dDA0AF3839290b0EF57AD6",
+        };
+        Bytes::from(address)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
@@ -192,6 +275,9 @@ pub struct ExtractionState {
     pub attributes: serde_json::Value,
     pub cursor: Vec<u8>,
     pub block_hash: Bytes,
+    /// Substreams packages the extractor ran, oldest first. Saving a state without packages
+    /// keeps the stored ones.
+    pub substreams_packages: Vec<SubstreamsPackage>,
 }
 
 impl ExtractionState {
@@ -208,8 +294,40 @@ impl ExtractionState {
             attributes: attributes.unwrap_or_default(),
             cursor: cursor.to_vec(),
             block_hash,
+            substreams_packages: Vec::new(),
         }
     }
+
+    pub fn with_substreams_packages(mut self, packages: Vec<SubstreamsPackage>) -> Self {
+        self.substreams_packages = packages;
+        self
+    }
+
+    /// The package the extractor currently runs.
+    pub fn substreams_package(&self) -> Option<&SubstreamsPackage> {
+        self.substreams_packages.last()
+    }
+}
+
+/// Substreams package and output module an extractor streams from.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SubstreamsPackage {
+    /// Package name from the spkg metadata.
+    pub name: String,
+    /// Package version from the spkg metadata.
+    pub version: String,
+    pub module_name: String,
+    /// Digest of the output module and all modules it depends on, including their code.
+    pub module_hash: Bytes,
+    /// First block processed with this package, `None` if the extractor had no state yet.
+    pub from_block: Option<u64>,
+}
+
+impl SubstreamsPackage {
+    /// Whether both packages run the same module, only the module hash is compared.
+    pub fn same_module(&self, other: &Self) -> bool {
+        self.module_name == other.module_name && self.module_hash == other.module_hash
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Default, Deserialize, Serialize)]
@@ -274,7 +392,7 @@ impl ContractId {
 
 impl Display for ContractId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}: 0x{}", self.chain, hex::encode(&self.address))
+        write!(f, "{:?}: {}", self.chain, self.chain.format_address(&self.address))
     }
 }
 
@@ -300,6 +418,62 @@ impl From<&dto::PaginationParams> for PaginationParams {
     }
 }
 
+/// Position within a listing of protocol components pinned to a block.
+///
+/// Only components created at or before `block_number` are listed, ordered by id, and a page
+/// continues after the id of the last component of the previous page. Components inserted while
+/// paginating therefore neither shift nor duplicate the following pages.
+///
+/// Serialized as an opaque hex string, see [`ComponentCursor::from_str`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ComponentCursor {
+    pub block_number: u64,
+    /// Id of the last component of the previous page, `None` on the first page.
+    pub after: Option<ComponentId>,
+}
+
+impl ComponentCursor {
+    pub fn new(block_number: u64, after: Option<ComponentId>) -> Self {
+        Self { block_number, after }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+#[error("Invalid pagination cursor: {0}")]
+pub struct InvalidCursor(String);
+
+impl Display for ComponentCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let raw = format!(
+            "{}:{}",
+            self.block_number,
+            self.after
+                .as_deref()
+                .unwrap_or_default()
+        );
+        write!(f, "{}", hex::encode(raw))
+    }
+}
+
+impl FromStr for ComponentCursor {
+    type Err = InvalidCursor;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw = hex::decode(s)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| InvalidCursor(s.to_string()))?;
+        let (block_number, after) = raw
+            .split_once(':')
+            .ok_or_else(|| InvalidCursor(s.to_string()))?;
+        let block_number = block_number
+            .parse()
+            .map_err(|_| InvalidCursor(s.to_string()))?;
+        let after = (!after.is_empty()).then(|| after.to_string());
+        Ok(Self { block_number, after })
+    }
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum MergeError {
     #[error("Can't merge {0} from differring idendities: Expected {1}, got {2}")]
@@ -311,3 +485,82 @@ pub enum MergeError {
     #[error("Can't merge {0} with lower transaction index: {1} > {2}")]
     TransactionOrderError(String, u64, u64),
 }
+
+#[derive(Error, Debug, PartialEq)]
+pub enum BuildError {
+    #[error("{0} requires a {1}")]
+    MissingField(String, String),
+    #[error("Code hash of account {0} does not match its code: expected {1}, got {2}")]
+    CodeHashMismatch(Address, CodeHash, CodeHash),
+    #[error("Account {0} holds a token balance of account {1}")]
+    ForeignBalance(Address, Address),
+    #[error("Component {0} lists token {1} more than once")]
+    DuplicateToken(ComponentId, Address),
+    #[error("Component {0} does not match its canonical id {1}")]
+    ComponentIdMismatch(ComponentId, ComponentId),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_address() {
+        let address = Bytes::from("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+        assert_eq!(
+            Chain::Ethereum.format_address(&address),
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+        assert_eq!(
+            Chain::Starknet.format_address(&Bytes::from("0x049d36")),
+            "0x0000000000000000000000000000000000000000000000000000000000049d36"
+        );
+        assert_eq!(Chain::Ethereum.format_address(&Bytes::from("0x01")), "0x01");
+        assert_eq!(
+            ContractId::new(Chain::Ethereum, address).to_string(),
+            "Ethereum: 0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+    }
+
+    #[test]
+    fn test_normalize_component_id() {
+        assert_eq!(
+            normalize_component_id("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+        );
+        assert_eq!(normalize_component_id("0xABC-Pool"), "0xABC-Pool");
+        assert_eq!(normalize_component_id("ambient_Pool"), "ambient_Pool");
+        assert_eq!(normalize_component_id("0x"), "0x");
+    }
+
+    #[test]
+    fn test_component_id_candidates() {
+        assert_eq!(
+            component_id_candidates("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            vec![
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string(),
+                "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".to_string(),
+            ]
+        );
+        assert_eq!(component_id_candidates("ambient_Pool"), vec!["ambient_Pool".to_string()]);
+    }
+
+    #[test]
+    fn test_component_cursor_roundtrip() {
+        for cursor in [
+            ComponentCursor::new(21_000_000, None),
+            ComponentCursor::new(1, Some("0xabc:pool".to_string())),
+        ] {
+            assert_eq!(
+                cursor
+                    .to_string()
+                    .parse::<ComponentCursor>(),
+                Ok(cursor)
+            );
+        }
+        assert!("zz".parse::<ComponentCursor>().is_err());
+        assert!(hex::encode("no_block")
+            .parse::<ComponentCursor>()
+            .is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet};
 
 use chrono::NaiveDateTime;
 use num_bigint::BigUint;
@@ -6,13 +6,22 @@ use serde::{Deserialize, Serialize};
 use tracing::warn;
 
 use crate::{
+    keccak256,
     models::{
-        blockchain::Transaction, Address, AttrStoreKey, Balance, Chain, ChangeType, ComponentId,
-        MergeError, StoreVal, TxHash,
+        blockchain::{Block, Transaction},
+        contract::Account,
+        Address, AttrStoreKey, Balance, BuildError, Chain, ChangeType, ComponentId,
+        ImplementationType, MergeError, StoreVal, TxHash,
     },
     Bytes,
 };
 
+/// Prefix of component ids derived by [`ProtocolComponent::canonical_id`].
+pub const CANONICAL_ID_PREFIX: &str = "tc1:";
+
+/// Static attribute holding the salt a canonical component id was derived with.
+pub const CANONICAL_ID_SALT_ATTRIBUTE: &str = "canonical_id_salt";
+
 /// `ProtocolComponent` provides detailed descriptions of a component of a protocol,
 /// for example, swap pools that enables the exchange of two tokens.
 ///
@@ -24,6 +33,8 @@ use crate::{
 ///
 /// Every values of a `ProtocolComponent` must be static, they can't ever be changed after creation.
 /// The dynamic values associated to a component must be given using `ProtocolComponentState`.
+/// This includes its starting balances and thereby its TVL: they are sent as `ComponentBalance`
+/// changes of the creating transaction and stored like any later balance change.
 #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ProtocolComponent {
     pub id: ComponentId,
@@ -65,6 +76,178 @@ impl ProtocolComponent {
             created_at,
         }
     }
+
+    /// Derives a stable component id from the fields defining a component.
+    ///
+    /// The id hashes the chain, protocol system, tokens and contract addresses together with a
+    /// `salt`, which tells apart components with the same tokens and contracts, e.g. pools of a
+    /// singleton contract that differ by fee, or a pool redeployed to the same address. Tokens and
+    /// contracts are sorted first, so their order doesn't change the id.
+    pub fn canonical_id(
+        chain: Chain,
+        protocol_system: &str,
+        tokens: &[Address],
+        contract_addresses: &[Address],
+        salt: &[u8],
+    ) -> ComponentId {
+        let mut tokens = tokens.iter().collect::<Vec<_>>();
+        tokens.sort();
+        let mut contracts = contract_addresses
+            .iter()
+            .collect::<Vec<_>>();
+        contracts.sort();
+        let chain = chain.to_string();
+        let n_tokens = (tokens.len() as u32).to_be_bytes();
+        let fields = [chain.as_bytes(), protocol_system.as_bytes(), salt, &n_tokens]
+            .into_iter()
+            .chain(tokens.iter().map(|t| t.as_ref()))
+            .chain(contracts.iter().map(|c| c.as_ref()));
+        // Length prefixes and the token count keep adjacent fields from being ambiguous
+        let mut preimage = Vec::new();
+        for field in fields {
+            preimage.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            preimage.extend_from_slice(field);
+        }
+        format!("{CANONICAL_ID_PREFIX}0x{}", hex::encode(keccak256(preimage)))
+    }
+
+    /// Canonical key of the set of `tokens` a component holds: their addresses sorted and joined by
+    /// `-`, e.g. `0xa0b8...-0xc02a...`.
+    ///
+    /// Components holding the same tokens share a pair key however their tokens are ordered, so
+    /// (WETH, USDC) and (USDC, WETH) are the same pair.
+    pub fn pair_key(tokens: &[Address]) -> String {
+        let mut tokens = tokens.iter().collect::<Vec<_>>();
+        tokens.sort();
+        tokens.dedup();
+        tokens
+            .iter()
+            .map(|token| token.to_string())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Checks a component with a canonical id against the id derived from its fields. Components
+    /// with ids of any other form are not checked.
+    pub fn validate_id(&self) -> Result<(), BuildError> {
+        if !self.id.starts_with(CANONICAL_ID_PREFIX) {
+            return Ok(());
+        }
+        let salt = self
+            .static_attributes
+            .get(CANONICAL_ID_SALT_ATTRIBUTE)
+            .cloned()
+            .unwrap_or_default();
+        let expected = Self::canonical_id(
+            self.chain,
+            &self.protocol_system,
+            &self.tokens,
+            &self.contract_addresses,
+            &salt,
+        );
+        if self.id != expected {
+            return Err(BuildError::ComponentIdMismatch(self.id.clone(), expected));
+        }
+        Ok(())
+    }
+}
+
+/// Builds a [`ProtocolComponent`] created by a transaction.
+///
+/// Unset fields default to a component without tokens, contracts or static attributes, created
+/// at the unix epoch by a zero hash transaction.
+#[derive(Clone, Debug)]
+pub struct ProtocolComponentBuilder {
+    component: ProtocolComponent,
+    canonical_id_salt: Option<Bytes>,
+}
+
+impl ProtocolComponentBuilder {
+    pub fn new(id: &str, protocol_system: &str, protocol_type_name: &str, chain: Chain) -> Self {
+        Self {
+            component: ProtocolComponent {
+                id: id.to_string(),
+                protocol_system: protocol_system.to_string(),
+                protocol_type_name: protocol_type_name.to_string(),
+                chain,
+                change: ChangeType::Creation,
+                creation_tx: Bytes::zero(32),
+                ..Default::default()
+            },
+            canonical_id_salt: None,
+        }
+    }
+
+    /// Replaces the id with the [canonical id](ProtocolComponent::canonical_id) derived with
+    /// `salt` on build. The salt is kept as a static attribute, so the id can be validated later.
+    pub fn canonical_id(mut self, salt: Bytes) -> Self {
+        self.canonical_id_salt = Some(salt);
+        self
+    }
+
+    pub fn tokens(mut self, tokens: Vec<Address>) -> Self {
+        self.component.tokens = tokens;
+        self
+    }
+
+    pub fn contract_addresses(mut self, contract_addresses: Vec<Address>) -> Self {
+        self.component.contract_addresses = contract_addresses;
+        self
+    }
+
+    pub fn static_attribute(mut self, key: &str, value: StoreVal) -> Self {
+        self.component
+            .static_attributes
+            .insert(key.to_string(), value);
+        self
+    }
+
+    pub fn change(mut self, change: ChangeType) -> Self {
+        self.component.change = change;
+        self
+    }
+
+    pub fn creation_tx(mut self, tx: TxHash) -> Self {
+        self.component.creation_tx = tx;
+        self
+    }
+
+    pub fn created_at(mut self, created_at: NaiveDateTime) -> Self {
+        self.component.created_at = created_at;
+        self
+    }
+
+    pub fn build(self) -> Result<ProtocolComponent, BuildError> {
+        let mut component = self.component;
+        if let Some(salt) = self.canonical_id_salt {
+            component.id = ProtocolComponent::canonical_id(
+                component.chain,
+                &component.protocol_system,
+                &component.tokens,
+                &component.contract_addresses,
+                &salt,
+            );
+            component
+                .static_attributes
+                .insert(CANONICAL_ID_SALT_ATTRIBUTE.to_string(), salt);
+        }
+        if component.id.is_empty() {
+            return Err(BuildError::MissingField("ProtocolComponent".to_string(), "id".to_string()));
+        }
+        if component.protocol_system.is_empty() {
+            return Err(BuildError::MissingField(component.id, "protocol system".to_string()));
+        }
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = component
+            .tokens
+            .iter()
+            .find(|token| !seen.insert(*token))
+        {
+            return Err(BuildError::DuplicateToken(component.id.clone(), duplicate.clone()));
+        }
+        component.validate_id()?;
+        Ok(component)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -124,6 +307,15 @@ impl ProtocolComponentState {
     }
 }
 
+/// A protocol component together with the state of the contracts it holds.
+///
+/// Contracts that do not exist at the requested version are omitted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolComponentWithContracts {
+    pub component: ProtocolComponent,
+    pub contracts: Vec<Account>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ProtocolComponentStateDelta {
     pub component_id: ComponentId,
@@ -173,6 +365,15 @@ impl ProtocolComponentStateDelta {
     }
 }
 
+/// The protocol state changes between two versions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolStatesDelta {
+    pub deltas: Vec<ProtocolComponentStateDelta>,
+    /// The block the end version resolved to. For a timestamp this is the latest block at or
+    /// before it.
+    pub end_block: Block,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ComponentBalance {
     pub token: Address,
@@ -200,6 +401,108 @@ impl ComponentBalance {
     }
 }
 
+/// A raw event log emitted in a transaction, attributed to a protocol component.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ComponentEvent {
+    pub component_id: ComponentId,
+    pub tx_hash: TxHash,
+    /// Index of the log within its block.
+    pub log_index: u32,
+    /// Address of the contract that emitted the log.
+    pub address: Address,
+    pub topics: Vec<Bytes>,
+    pub data: Bytes,
+}
+
+impl ComponentEvent {
+    pub fn new(
+        component_id: &str,
+        tx_hash: TxHash,
+        log_index: u32,
+        address: Address,
+        topics: Vec<Bytes>,
+        data: Bytes,
+    ) -> Self {
+        Self { component_id: component_id.to_string(), tx_hash, log_index, address, topics, data }
+    }
+
+    /// The first topic, for non anonymous events the hash of the event signature.
+    pub fn signature(&self) -> Option<&Bytes> {
+        self.topics.first()
+    }
+}
+
+/// Current value of a component attribute, see the `analytics.component_latest_state` view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentLatestState {
+    pub component_id: ComponentId,
+    pub protocol_system: String,
+    pub attribute_name: AttrStoreKey,
+    pub attribute_value: StoreVal,
+    /// Start of the validity of the current value.
+    pub modified_ts: NaiveDateTime,
+}
+
+/// Current balance of a component in one of its tokens, together with the token's metadata. See
+/// the `analytics.component_latest_balance` view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentLatestBalance {
+    pub component_id: ComponentId,
+    pub protocol_system: String,
+    pub token: Address,
+    pub symbol: String,
+    pub decimals: u32,
+    pub balance: Balance,
+    pub balance_float: f64,
+    /// Start of the validity of the current balance.
+    pub modified_ts: NaiveDateTime,
+}
+
+/// Overview of a protocol system on one chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolSystemSummary {
+    pub name: String,
+    /// Number of components that were not deleted.
+    pub component_count: u64,
+    /// Implementation types of the system's protocol types, empty without components.
+    pub implementation_types: Vec<ImplementationType>,
+    /// Latest block in which a component was created or its state or balances changed, `None`
+    /// if nothing was indexed yet.
+    pub last_updated_block: Option<u64>,
+    pub last_updated_ts: Option<NaiveDateTime>,
+}
+
+/// Interval at which historical balances are sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleInterval {
+    #[default]
+    Hourly,
+    Daily,
+}
+
+impl SampleInterval {
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            SampleInterval::Hourly => chrono::Duration::hours(1),
+            SampleInterval::Daily => chrono::Duration::days(1),
+        }
+    }
+}
+
+/// A component's token balance over one sample interval.
+///
+/// `open` and `close` are the balances at the start and end of the interval; `high` and `low`
+/// the extremes reached in between. All values are the floating point balance representation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceSample {
+    /// Start of the interval.
+    pub ts: NaiveDateTime,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
 /// Token quality range filter
 ///
 /// The quality range is considered inclusive and used as a filter, will be applied as such.
@@ -317,6 +620,183 @@ impl ProtocolChangesWithTx {
     }
 }
 
+/// The components of a protocol system on one chain, together with the names of their current
+/// state attributes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProtocolDeployment {
+    pub chain: Chain,
+    pub components: Vec<ProtocolComponent>,
+    pub state_attributes: HashMap<ComponentId, HashSet<AttrStoreKey>>,
+}
+
+impl ProtocolDeployment {
+    fn components_by_id(&self) -> BTreeMap<&str, &ProtocolComponent> {
+        self.components
+            .iter()
+            .map(|c| (c.id.as_str(), c))
+            .collect()
+    }
+
+    fn component_schema(&self, component: &ProtocolComponent) -> BTreeSet<String> {
+        component
+            .static_attributes
+            .keys()
+            .chain(
+                self.state_attributes
+                    .get(&component.id)
+                    .into_iter()
+                    .flatten(),
+            )
+            .map(|name| attribute_schema_key(name))
+            .collect()
+    }
+}
+
+/// Maps an attribute name to the name shared by all attributes of its kind.
+///
+/// Path segments holding a number or an address, like the tick of `ticks/-60/net-liquidity`, are
+/// replaced by `*`, so the key doesn't depend on the values a component happens to hold.
+pub fn attribute_schema_key(name: &str) -> String {
+    name.split('/')
+        .map(|segment| {
+            if segment.parse::<i128>().is_ok() || segment.starts_with("0x") {
+                "*"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Attribute names, see [`attribute_schema_key`], present on only one of two chains.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttributeSchemaDiff {
+    pub missing_on_other: BTreeSet<String>,
+    pub missing_on_base: BTreeSet<String>,
+}
+
+impl AttributeSchemaDiff {
+    fn new(base: &BTreeSet<String>, other: &BTreeSet<String>) -> Self {
+        Self {
+            missing_on_other: base
+                .difference(other)
+                .cloned()
+                .collect(),
+            missing_on_base: other
+                .difference(base)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing_on_other.is_empty() && self.missing_on_base.is_empty()
+    }
+}
+
+/// Differences between the deployments of a protocol system on a base and an other chain.
+///
+/// Components are matched by id, which only lines up for protocols deployed at the same
+/// addresses on both chains. The attribute schemas are compared for the whole protocol system
+/// and for every matched component.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeploymentParity {
+    pub protocol_system: String,
+    pub base_chain: Chain,
+    pub other_chain: Chain,
+    pub n_base_components: usize,
+    pub n_other_components: usize,
+    pub components_missing_on_other: BTreeSet<ComponentId>,
+    pub components_missing_on_base: BTreeSet<ComponentId>,
+    pub contracts_missing_on_other: BTreeSet<Address>,
+    pub contracts_missing_on_base: BTreeSet<Address>,
+    pub attributes: AttributeSchemaDiff,
+    /// Matched components whose attributes differ, keyed by id.
+    pub component_attributes: BTreeMap<ComponentId, AttributeSchemaDiff>,
+}
+
+impl DeploymentParity {
+    pub fn compare(
+        protocol_system: &str,
+        base: &ProtocolDeployment,
+        other: &ProtocolDeployment,
+    ) -> Self {
+        let contracts = |deployment: &ProtocolDeployment| {
+            deployment
+                .components
+                .iter()
+                .flat_map(|c| c.contract_addresses.iter().cloned())
+                .collect::<BTreeSet<_>>()
+        };
+        let schema = |deployment: &ProtocolDeployment| {
+            deployment
+                .components
+                .iter()
+                .flat_map(|c| deployment.component_schema(c))
+                .collect::<BTreeSet<_>>()
+        };
+        let base_components = base.components_by_id();
+        let other_components = other.components_by_id();
+        let base_contracts = contracts(base);
+        let other_contracts = contracts(other);
+
+        let component_attributes = base_components
+            .iter()
+            .filter_map(|(id, base_component)| {
+                let other_component = other_components.get(id)?;
+                let diff = AttributeSchemaDiff::new(
+                    &base.component_schema(base_component),
+                    &other.component_schema(other_component),
+                );
+                (!diff.is_empty()).then(|| (id.to_string(), diff))
+            })
+            .collect();
+
+        Self {
+            protocol_system: protocol_system.to_string(),
+            base_chain: base.chain,
+            other_chain: other.chain,
+            n_base_components: base_components.len(),
+            n_other_components: other_components.len(),
+            components_missing_on_other: base_components
+                .keys()
+                .filter(|id| !other_components.contains_key(*id))
+                .map(|id| id.to_string())
+                .collect(),
+            components_missing_on_base: other_components
+                .keys()
+                .filter(|id| !base_components.contains_key(*id))
+                .map(|id| id.to_string())
+                .collect(),
+            contracts_missing_on_other: base_contracts
+                .difference(&other_contracts)
+                .cloned()
+                .collect(),
+            contracts_missing_on_base: other_contracts
+                .difference(&base_contracts)
+                .cloned()
+                .collect(),
+            attributes: AttributeSchemaDiff::new(&schema(base), &schema(other)),
+            component_attributes,
+        }
+    }
+
+    /// Whether both chains hold the same components and contracts with the same attributes.
+    pub fn is_complete(&self) -> bool {
+        self.components_missing_on_other
+            .is_empty() &&
+            self.components_missing_on_base
+                .is_empty() &&
+            self.contracts_missing_on_other
+                .is_empty() &&
+            self.contracts_missing_on_base
+                .is_empty() &&
+            self.attributes.is_empty() &&
+            self.component_attributes.is_empty()
+    }
+}
+
 pub struct GetAmountOutParams {
     pub amount_in: BigUint,
     pub token_in: Bytes,
@@ -335,6 +815,74 @@ mod test {
     const HASH_256_0: &str = "0x0000000000000000000000000000000000000000000000000000000000000000";
     const HASH_256_1: &str = "0x0000000000000000000000000000000000000000000000000000000000000001";
 
+    #[test]
+    fn test_pair_key() {
+        let weth = Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let usdc = Bytes::from("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+
+        assert_eq!(
+            ProtocolComponent::pair_key(&[weth.clone(), usdc.clone()]),
+            "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48-0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"
+        );
+        assert_eq!(
+            ProtocolComponent::pair_key(&[weth.clone(), usdc.clone()]),
+            ProtocolComponent::pair_key(&[usdc, weth.clone(), weth])
+        );
+    }
+
+    #[test]
+    fn test_canonical_component_id() {
+        let builder = ProtocolComponentBuilder::new("", "uniswap_v4", "swap", Chain::Ethereum)
+            .tokens(vec![Bytes::from("0x02"), Bytes::from("0x01")])
+            .contract_addresses(vec![Bytes::from("0xaa")])
+            .canonical_id(Bytes::from("0x0bb8"));
+
+        let component = builder.clone().build().unwrap();
+        let reordered = builder
+            .clone()
+            .tokens(vec![Bytes::from("0x01"), Bytes::from("0x02")])
+            .build()
+            .unwrap();
+        let other_fee = builder
+            .clone()
+            .canonical_id(Bytes::from("0x01f4"))
+            .build()
+            .unwrap();
+        let mut tampered = component.clone();
+        tampered.tokens.pop();
+
+        assert!(component
+            .id
+            .starts_with(CANONICAL_ID_PREFIX));
+        assert_eq!(component.id.len(), CANONICAL_ID_PREFIX.len() + 66);
+        assert_eq!(component.static_attributes[CANONICAL_ID_SALT_ATTRIBUTE], Bytes::from("0x0bb8"));
+        assert_eq!(reordered.id, component.id);
+        assert_ne!(other_fee.id, component.id);
+        assert!(matches!(tampered.validate_id(), Err(BuildError::ComponentIdMismatch(..))));
+        // Ids that aren't canonical are not validated
+        tampered.id = "0xpool".to_string();
+        assert_eq!(tampered.validate_id(), Ok(()));
+    }
+
+    #[test]
+    fn test_protocol_component_builder() {
+        let builder = ProtocolComponentBuilder::new("pool", "uniswap_v2", "swap", Chain::Ethereum)
+            .tokens(vec![Bytes::from("0x01"), Bytes::from("0x02")])
+            .static_attribute("fee", Bytes::from("0x1e"));
+
+        let component = builder.clone().build().unwrap();
+        let duplicate = builder
+            .tokens(vec![Bytes::from("0x01"), Bytes::from("0x01")])
+            .build();
+
+        assert_eq!(component.change, ChangeType::Creation);
+        assert_eq!(component.static_attributes["fee"], Bytes::from("0x1e"));
+        assert_eq!(
+            duplicate,
+            Err(BuildError::DuplicateToken("pool".to_string(), Bytes::from("0x01")))
+        );
+    }
+
     fn create_state(id: String) -> ProtocolComponentStateDelta {
         let attributes1: HashMap<String, Bytes> = vec![
             ("reserve1".to_owned(), Bytes::from(1000u64).lpad(32, 0)),
@@ -509,4 +1057,69 @@ mod test {
             ))
         );
     }
+
+    #[rstest]
+    #[case::plain("fee", "fee")]
+    #[case::tick("ticks/-60/net-liquidity", "ticks/*/net-liquidity")]
+    #[case::address("balance_owner/0xba12/amount", "balance_owner/*/amount")]
+    fn test_attribute_schema_key(#[case] name: &str, #[case] exp: &str) {
+        assert_eq!(attribute_schema_key(name), exp);
+    }
+
+    #[test]
+    fn test_deployment_parity() {
+        let vault = Bytes::from("0xba12");
+        let component = |id: &str, chain: Chain, contracts: Vec<Bytes>| {
+            ProtocolComponentBuilder::new(id, "balancer_v2", "pool", chain)
+                .contract_addresses(contracts)
+                .static_attribute("pool_type", Bytes::from("0x01"))
+                .build()
+                .unwrap()
+        };
+        let attributes = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<HashSet<_>>()
+        };
+        let base = ProtocolDeployment {
+            chain: Chain::Ethereum,
+            components: vec![
+                component("0x01", Chain::Ethereum, vec![vault.clone()]),
+                component("0x02", Chain::Ethereum, vec![vault.clone()]),
+            ],
+            state_attributes: HashMap::from([
+                ("0x01".to_string(), attributes(&["ticks/1/net-liquidity", "fee"])),
+                ("0x02".to_string(), attributes(&["fee"])),
+            ]),
+        };
+        let other = ProtocolDeployment {
+            chain: Chain::Base,
+            components: vec![component("0x01", Chain::Base, vec![vault.clone()])],
+            state_attributes: HashMap::from([(
+                "0x01".to_string(),
+                attributes(&["ticks/-5/net-liquidity"]),
+            )]),
+        };
+
+        let parity = DeploymentParity::compare("balancer_v2", &base, &other);
+
+        let missing_fee = AttributeSchemaDiff {
+            missing_on_other: BTreeSet::from(["fee".to_string()]),
+            missing_on_base: BTreeSet::new(),
+        };
+        assert!(!parity.is_complete());
+        assert_eq!(parity.components_missing_on_other, BTreeSet::from(["0x02".to_string()]));
+        assert!(parity
+            .components_missing_on_base
+            .is_empty());
+        assert!(parity
+            .contracts_missing_on_other
+            .is_empty());
+        assert_eq!(parity.attributes, missing_fee);
+        assert_eq!(
+            parity.component_attributes,
+            BTreeMap::from([("0x01".to_string(), missing_fee)])
+        );
+    }
 }
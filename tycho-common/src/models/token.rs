@@ -112,6 +112,19 @@ impl TryFrom<ResponseToken> for Token {
     }
 }
 
+/// Corrections to the metadata of a token, applied when tokens are served.
+///
+/// Unset fields keep the stored value. Overrides never change how amounts of the token are
+/// denominated: `display_decimals` is only a hint for presenting amounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenOverride {
+    pub chain: Chain,
+    pub address: Address,
+    pub symbol: Option<String>,
+    pub display_decimals: Option<u32>,
+    pub logo_uri: Option<String>,
+}
+
 /// Represents the quality of a token.
 ///
 /// * `Good`: Indicates that the token has successfully passed the analysis process.
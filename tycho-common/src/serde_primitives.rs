@@ -1,4 +1,9 @@
+use std::cell::Cell;
+
 use hex::FromHexError;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 fn decode_hex_with_prefix(val: &str) -> Result<Vec<u8>, FromHexError> {
     let mut stripped: String =
@@ -13,6 +18,55 @@ fn decode_hex_with_prefix(val: &str) -> Result<Vec<u8>, FromHexError> {
     hex::decode(&stripped)
 }
 
+/// Encoding of numeric byte values - balances, storage values and attributes - in serialized
+/// output.
+///
+/// Addresses, hashes and code are always hex encoded, only fields using the `encoded_*` helpers
+/// below are affected.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueEncoding {
+    /// Big endian hex string with 0x prefix.
+    #[default]
+    Hex,
+    /// The value interpreted as big endian unsigned integer, as a decimal string. Leading zero
+    /// bytes are not preserved.
+    DecimalString,
+}
+
+impl ValueEncoding {
+    pub fn is_hex(&self) -> bool {
+        *self == ValueEncoding::Hex
+    }
+}
+
+thread_local! {
+    static VALUE_ENCODING: Cell<ValueEncoding> = const { Cell::new(ValueEncoding::Hex) };
+}
+
+/// Runs `f` with values serialized using `encoding` on the current thread.
+///
+/// Serialization must happen synchronously within `f`, the previous encoding is restored
+/// afterwards.
+pub fn with_value_encoding<T>(encoding: ValueEncoding, f: impl FnOnce() -> T) -> T {
+    struct Reset(ValueEncoding);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            VALUE_ENCODING.with(|e| e.set(self.0));
+        }
+    }
+
+    let _reset = Reset(VALUE_ENCODING.with(|e| e.replace(encoding)));
+    f()
+}
+
+fn encode_value(val: &[u8]) -> String {
+    match VALUE_ENCODING.with(Cell::get) {
+        ValueEncoding::Hex => format!("0x{}", hex::encode(val)),
+        ValueEncoding::DecimalString => BigUint::from_bytes_be(val).to_string(),
+    }
+}
+
 /// serde functions for handling bytes as hex strings, such as [bytes::Bytes]
 pub mod hex_bytes {
     use serde::{Deserialize, Deserializer, Serializer};
@@ -80,30 +134,38 @@ pub mod hex_bytes_option {
     }
 }
 
-/// serde functions for handling HashMap with a bytes key
+/// serde functions for handling maps with a bytes key
+///
+/// Works with any map type, entries are serialized sorted by key.
 pub mod hex_hashmap_key {
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
     use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
 
     use super::decode_hex_with_prefix;
     use crate::Bytes;
 
-    pub fn serialize<S, V>(x: &HashMap<Bytes, V>, s: S) -> Result<S::Ok, S::Error>
+    pub fn serialize<'a, S, M, V>(x: &'a M, s: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
-        V: Serialize,
+        &'a M: IntoIterator<Item = (&'a Bytes, &'a V)>,
+        V: Serialize + 'a,
     {
-        let mut map = s.serialize_map(Some(x.len()))?;
-        for (k, v) in x.iter() {
-            map.serialize_entry(&format!("{k:#x}"), v)?;
+        let entries: BTreeMap<_, _> = x
+            .into_iter()
+            .map(|(k, v)| (format!("{k:#x}"), v))
+            .collect();
+        let mut map = s.serialize_map(Some(entries.len()))?;
+        for (k, v) in entries {
+            map.serialize_entry(&k, v)?;
         }
         map.end()
     }
 
-    pub fn deserialize<'de, V, D>(d: D) -> Result<HashMap<Bytes, V>, D::Error>
+    pub fn deserialize<'de, M, V, D>(d: D) -> Result<M, D::Error>
     where
         D: Deserializer<'de>,
+        M: FromIterator<(Bytes, V)>,
         V: Deserialize<'de>,
     {
         let interim = HashMap::<String, V>::deserialize(d)?;
@@ -114,13 +176,13 @@ pub mod hex_hashmap_key {
                 let k = decode_hex_with_prefix(&k).map_err(|e| de::Error::custom(e.to_string()))?;
                 Ok((Bytes::from(k), v))
             })
-            .collect::<Result<HashMap<_, _>, _>>()
+            .collect::<Result<M, _>>()
     }
 }
 
 /// serde functions for handling HashMap with bytes value
 pub mod hex_hashmap_value {
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
     use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
 
@@ -130,10 +192,11 @@ pub mod hex_hashmap_value {
     pub fn serialize<S, K>(x: &HashMap<K, Bytes>, s: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
-        K: Serialize,
+        K: Serialize + Ord,
     {
-        let mut map = s.serialize_map(Some(x.len()))?;
-        for (k, v) in x.iter() {
+        let entries: BTreeMap<_, _> = x.iter().collect();
+        let mut map = s.serialize_map(Some(entries.len()))?;
+        for (k, v) in entries {
             map.serialize_entry(k, &format!("{v:#x}"))?;
         }
         map.end()
@@ -158,7 +221,7 @@ pub mod hex_hashmap_value {
 
 /// serde functions for handling HashMap with a bytes key and value
 pub mod hex_hashmap_key_value {
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
     use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serializer};
 
@@ -169,8 +232,9 @@ pub mod hex_hashmap_key_value {
     where
         S: Serializer,
     {
-        let mut map = s.serialize_map(Some(x.len()))?;
-        for (k, v) in x.iter() {
+        let entries: BTreeMap<_, _> = x.iter().collect();
+        let mut map = s.serialize_map(Some(entries.len()))?;
+        for (k, v) in entries {
             map.serialize_entry(&format!("{k:#x}"), &format!("{v:#x}"))?;
         }
         map.end()
@@ -192,6 +256,144 @@ pub mod hex_hashmap_key_value {
     }
 }
 
+/// serde function serializing a HashSet as a sorted sequence, for use with `serialize_with`.
+pub mod sorted_set {
+    use std::collections::{BTreeSet, HashSet};
+
+    use serde::{Serialize, Serializer};
+
+    pub fn serialize<S, T>(x: &HashSet<T>, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize + Ord,
+    {
+        x.iter()
+            .collect::<BTreeSet<_>>()
+            .serialize(s)
+    }
+}
+
+/// serde function serializing a HashMap sorted by key, for use with `serialize_with`.
+pub mod sorted_map {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{Serialize, Serializer};
+
+    pub fn serialize<S, K, V>(x: &HashMap<K, V>, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        K: Serialize + Ord,
+        V: Serialize,
+    {
+        x.iter()
+            .collect::<BTreeMap<_, _>>()
+            .serialize(s)
+    }
+}
+
+/// Like [`sorted_map`] for maps of sets, the sets are sorted as well.
+pub mod sorted_map_of_sets {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+    use serde::{Serialize, Serializer};
+
+    pub fn serialize<S, K, V>(x: &HashMap<K, HashSet<V>>, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        K: Serialize + Ord,
+        V: Serialize + Ord,
+    {
+        x.iter()
+            .map(|(k, v)| (k, v.iter().collect::<BTreeSet<_>>()))
+            .collect::<BTreeMap<_, _>>()
+            .serialize(s)
+    }
+}
+
+/// serde functions for numeric byte values, serialized in the current [`ValueEncoding`].
+///
+/// Deserialization only accepts hex strings.
+pub mod encoded_bytes {
+    use serde::Serializer;
+
+    use super::encode_value;
+    pub use super::hex_bytes::deserialize;
+
+    pub fn serialize<S, T>(x: T, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]>,
+    {
+        s.serialize_str(&encode_value(x.as_ref()))
+    }
+}
+
+/// Like [`encoded_bytes`] for optional values.
+pub mod encoded_bytes_option {
+    use serde::Serializer;
+
+    use super::encode_value;
+    pub use super::hex_bytes_option::deserialize;
+
+    pub fn serialize<S, T>(x: &Option<T>, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]>,
+    {
+        match x {
+            Some(x) => s.serialize_str(&encode_value(x.as_ref())),
+            None => s.serialize_none(),
+        }
+    }
+}
+
+/// Like [`hex_hashmap_value`] with values serialized in the current [`ValueEncoding`].
+pub mod hex_hashmap_encoded_value {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{ser::SerializeMap, Serialize, Serializer};
+
+    use super::encode_value;
+    pub use super::hex_hashmap_value::deserialize;
+    use crate::Bytes;
+
+    pub fn serialize<S, K>(x: &HashMap<K, Bytes>, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        K: Serialize + Ord,
+    {
+        let entries: BTreeMap<_, _> = x.iter().collect();
+        let mut map = s.serialize_map(Some(entries.len()))?;
+        for (k, v) in entries {
+            map.serialize_entry(k, &encode_value(v))?;
+        }
+        map.end()
+    }
+}
+
+/// Like [`hex_hashmap_key_value`] with values serialized in the current [`ValueEncoding`].
+pub mod hex_hashmap_key_encoded_value {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{ser::SerializeMap, Serializer};
+
+    use super::encode_value;
+    pub use super::hex_hashmap_key_value::deserialize;
+    use crate::Bytes;
+
+    pub fn serialize<S>(x: &HashMap<Bytes, Bytes>, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let entries: BTreeMap<_, _> = x.iter().collect();
+        let mut map = s.serialize_map(Some(entries.len()))?;
+        for (k, v) in entries {
+            map.serialize_entry(&format!("{k:#x}"), &encode_value(v))?;
+        }
+        map.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde::{Deserialize, Serialize};
@@ -224,6 +426,32 @@ mod tests {
         assert_eq!(deserialized.bytes_option, Some(vec![0u8; 10]));
     }
 
+    #[derive(Debug, Serialize, Deserialize)]
+    struct EncodedStruct {
+        #[serde(with = "encoded_bytes")]
+        value: Vec<u8>,
+        #[serde(with = "hex_hashmap_key_encoded_value")]
+        slots: std::collections::HashMap<crate::Bytes, crate::Bytes>,
+    }
+
+    #[test]
+    fn encoded_bytes_respects_value_encoding() {
+        let test_struct = EncodedStruct {
+            value: vec![0, 1, 0],
+            slots: [(crate::Bytes::from("0x01"), crate::Bytes::from("0xff"))].into(),
+        };
+
+        let hex = serde_json::to_string(&test_struct).unwrap();
+        let decimal = with_value_encoding(ValueEncoding::DecimalString, || {
+            serde_json::to_string(&test_struct).unwrap()
+        });
+
+        assert_eq!(hex, r#"{"value":"0x000100","slots":{"0x01":"0xff"}}"#);
+        assert_eq!(decimal, r#"{"value":"256","slots":{"0x01":"255"}}"#);
+        // the encoding is scoped to the closure
+        assert_eq!(serde_json::to_string(&test_struct).unwrap(), hex);
+    }
+
     #[test]
     fn hex_bytes_option_none() {
         let test_struct = TestStruct { bytes: vec![0u8; 10], bytes_option: None };
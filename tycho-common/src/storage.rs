@@ -1,7 +1,9 @@
 //! Storage traits used by Tycho
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Display,
+    ops::RangeInclusive,
+    time::Duration,
 };
 
 use async_trait::async_trait;
@@ -12,17 +14,20 @@ use crate::{
     dto,
     models::{
         blockchain::{
-            Block, EntryPoint, EntryPointWithTracingParams, TracedEntryPoint, TracingParams,
-            TracingResult, Transaction,
+            Block, BlockDigest, EntryPoint, EntryPointWithTracingParams, FinalityStatus,
+            TracedEntryPoint, TracingParams, TracingResult, Transaction,
         },
-        contract::{Account, AccountBalance, AccountDelta},
+        contract::{Account, AccountBalance, AccountDelta, SlotAnnotation},
         protocol::{
-            ComponentBalance, ProtocolComponent, ProtocolComponentState,
-            ProtocolComponentStateDelta, QualityRange,
+            BalanceSample, ComponentBalance, ComponentEvent, ComponentLatestBalance,
+            ComponentLatestState, ProtocolComponent, ProtocolComponentState,
+            ProtocolComponentStateDelta, ProtocolComponentWithContracts, ProtocolDeployment,
+            ProtocolStatesDelta, ProtocolSystemSummary, QualityRange, SampleInterval,
         },
-        token::Token,
-        Address, BlockHash, Chain, ComponentId, ContractId, EntryPointId, ExtractionState,
-        PaginationParams, ProtocolSystem, ProtocolType, TxHash,
+        token::{Token, TokenOverride},
+        Address, AttrStoreKey, Balance, BlockHash, Chain, Code, CodeHash, ComponentCursor,
+        ComponentId, ContractId, EntryPointId, ExtractionState, PaginationParams, ProtocolSystem,
+        ProtocolType, StoreVal, TxHash,
     },
     Bytes,
 };
@@ -64,6 +69,8 @@ pub enum StorageError {
     DuplicateEntry(String, String),
     #[error("Could not find related {0} for {1} with id `{2}`!")]
     NoRelatedEntity(String, String, String),
+    #[error("The entity {0} with id `{1}` was deleted at {2}!")]
+    Deleted(String, String, NaiveDateTime),
     #[error("DecodeError: {0}")]
     DecodeError(String),
     #[error("Unexpected storage error: {0}")]
@@ -74,6 +81,69 @@ pub enum StorageError {
     WriteCacheGoneAway(),
     #[error("Invalid block range encountered")]
     InvalidBlockRange(),
+    #[error("Invalid version range: {0}")]
+    InvalidVersionRange(String),
+    #[error("Block {1} written by extractor {0} does not follow its stored block {2}")]
+    OutOfOrderBlock(String, u64, u64),
+    #[error("Query timed out: {0}")]
+    Timeout(String),
+    #[error("Query rejected by the database: {0}")]
+    InvalidQuery(String),
+    #[error("Write references missing entities: {0}")]
+    MissingReferences(MissingReferences),
+}
+
+/// Entities referenced by a batch of writes that are neither stored nor created by the batch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MissingReferences {
+    pub transactions: Vec<TxHash>,
+    pub accounts: Vec<Address>,
+    pub tokens: Vec<Address>,
+    pub components: Vec<ComponentId>,
+}
+
+impl MissingReferences {
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty() &&
+            self.accounts.is_empty() &&
+            self.tokens.is_empty() &&
+            self.components.is_empty()
+    }
+}
+
+impl Display for MissingReferences {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kinds = [
+            (
+                "transactions",
+                self.transactions
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                "accounts",
+                self.accounts
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            ),
+            (
+                "tokens",
+                self.tokens
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            ),
+            ("components", self.components.clone()),
+        ];
+        let parts = kinds
+            .into_iter()
+            .filter(|(_, ids)| !ids.is_empty())
+            .map(|(kind, ids)| format!("{kind} [{}]", ids.join(", ")))
+            .collect::<Vec<_>>();
+        write!(f, "{}", parts.join("; "))
+    }
 }
 
 /// Storage methods for chain specific objects.
@@ -112,6 +182,44 @@ pub trait ChainGateway {
     /// # Returns
     /// - An Ok result containing the block. Might fail if the block does not exist yet.
     async fn get_block(&self, id: &BlockIdentifier) -> Result<Block, StorageError>;
+
+    /// Retrieves the highest stored block of `chain` marked as finalized.
+    async fn get_finalized_block(&self, chain: &Chain) -> Result<Block, StorageError>;
+
+    /// Resolves a point in time to a block on each of `chains`.
+    ///
+    /// Returns the latest stored block at or before `ts` per chain. Chains without any stored
+    /// block at or before `ts` are omitted.
+    async fn get_blocks_at(
+        &self,
+        chains: &[Chain],
+        ts: NaiveDateTime,
+    ) -> Result<HashMap<Chain, Block>, StorageError>;
+
+    /// Raises the finality status of the stored blocks of `chain` up to and including block
+    /// number `up_to`.
+    ///
+    /// Blocks that already reached `status` or a stronger status keep theirs, so finality never
+    /// regresses.
+    async fn update_block_finality(
+        &self,
+        chain: &Chain,
+        status: FinalityStatus,
+        up_to: i64,
+    ) -> Result<(), StorageError>;
+
+    /// Stores the digests of blocks, replacing the digest a block already has.
+    async fn upsert_block_digests(&self, new: &[BlockDigest]) -> Result<(), StorageError>;
+
+    /// Retrieves the stored digests of the blocks of `chain` within `start_block..=end_block`,
+    /// ordered by block number. Blocks without a digest are omitted.
+    async fn get_block_digests(
+        &self,
+        chain: &Chain,
+        start_block: i64,
+        end_block: i64,
+    ) -> Result<Vec<BlockDigest>, StorageError>;
+
     /// Upserts a transaction to storage.
     ///
     /// Ignores any existing tx, if the new entry has different attributes
@@ -150,6 +258,100 @@ pub trait ChainGateway {
     /// # Returns
     /// - An Ok if the revert is successful, or a `StorageError` if not.
     async fn revert_state(&self, to: &BlockIdentifier) -> Result<(), StorageError>;
+
+    /// Sets the state of a protocol system aside to extract a block range again.
+    ///
+    /// Versions the protocol system wrote within `start_block..=end_block` are quarantined,
+    /// versions written after the range are parked. Versions valid right before the range become
+    /// current again. Other protocol systems are not affected.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the protocol system is indexed on.
+    /// - `protocol_system` The protocol system to repair.
+    /// - `start_block` The first block of the range.
+    /// - `end_block` The last block of the range, inclusive.
+    ///
+    /// # Returns
+    /// The id of the repair, used to restore and verify it once the range was replayed.
+    async fn quarantine_range(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        start_block: i64,
+        end_block: i64,
+    ) -> Result<i64, StorageError>;
+
+    /// Restores the versions parked by a repair on top of the replayed range.
+    ///
+    /// Fails if the repair was already restored.
+    async fn restore_quarantined_range(&self, repair_id: i64) -> Result<(), StorageError>;
+
+    /// Checks that blocks and state versions connect at both boundaries of a repaired range.
+    async fn verify_range_continuity(&self, repair_id: i64) -> Result<(), StorageError>;
+
+    /// Runs an ad-hoc analytics query in a read-only transaction.
+    ///
+    /// The query only sees the views of the `analytics` schema. It is cancelled once it runs
+    /// longer than `timeout` and returns at most `row_limit` rows, the remaining rows are dropped
+    /// and reported via [`AnalyticsRows::truncated`]. Errors raised by the query itself, like
+    /// syntax errors or denied reads, are returned as [`StorageError::InvalidQuery`].
+    async fn run_analytics_query(
+        &self,
+        query: &str,
+        row_limit: usize,
+        timeout: Duration,
+    ) -> Result<AnalyticsRows, StorageError>;
+
+    /// Appends an admin action to the audit log.
+    async fn record_admin_action(&self, action: &AdminAction) -> Result<(), StorageError>;
+
+    /// Lists destructive storage operations, like reverts, prunes, repairs and deletions, most
+    /// recent first.
+    ///
+    /// # Parameters
+    /// - `operation` Only lists operations of this kind, e.g. `revert_state`.
+    /// - `since` Only lists operations recorded at or after this time.
+    /// - `pagination_params` Optional pagination parameters to control the number of results.
+    async fn get_audit_log(
+        &self,
+        operation: Option<&str>,
+        since: Option<NaiveDateTime>,
+        pagination_params: Option<&PaginationParams>,
+    ) -> Result<WithTotal<Vec<AuditEntry>>, StorageError>;
+
+    /// Claims `key` of `api_key` for `action`.
+    ///
+    /// If the key is unused it is claimed and the caller must either complete or release it once
+    /// the action finished. Otherwise returns the recorded response or why the key can't be used.
+    /// Keys are forgotten after [`IDEMPOTENCY_KEY_RETENTION`], claims that were neither completed
+    /// nor released after [`IDEMPOTENCY_CLAIM_LEASE`].
+    async fn claim_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        action: &str,
+    ) -> Result<IdempotencyClaim, StorageError>;
+
+    /// Records the response of the action `key` was claimed for.
+    async fn complete_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), StorageError>;
+
+    /// Releases a claimed key without recording a response, so the action can be retried.
+    async fn release_idempotency_key(&self, api_key: &str, key: &str) -> Result<(), StorageError>;
+
+    /// Adds resources consumed by API keys to their usage of `month`, e.g. `2024-01`.
+    async fn add_api_key_usage(
+        &self,
+        month: &str,
+        usage: &[ApiKeyUsage],
+    ) -> Result<(), StorageError>;
+
+    /// Retrieves the usage of all API keys during `month`, e.g. `2024-01`.
+    async fn get_api_key_usage(&self, month: &str) -> Result<Vec<ApiKeyUsage>, StorageError>;
 }
 
 /// Store and retrieve state of Extractors.
@@ -184,6 +386,19 @@ pub trait ExtractionStateGateway {
     /// # Returns
     /// Ok, if state was stored successfully, Err if the state is not valid.
     async fn save_state(&self, state: &ExtractionState) -> Result<(), StorageError>;
+
+    /// Retrieves the number of the block each extractor of a chain has processed up to.
+    ///
+    /// # Parameters
+    /// - `chain` The chain of the extractors.
+    ///
+    /// # Returns
+    /// The block numbers keyed by extractor name, empty if no extractor state is stored for the
+    /// chain.
+    async fn get_extractor_block_numbers(
+        &self,
+        chain: &Chain,
+    ) -> Result<HashMap<String, u64>, StorageError>;
 }
 
 /// Point in time as either block or timestamp. If a block is chosen it
@@ -200,6 +415,11 @@ impl TryFrom<&dto::VersionParam> for BlockOrTimestamp {
     type Error = anyhow::Error;
 
     fn try_from(version: &dto::VersionParam) -> Result<Self, Self::Error> {
+        if version.relative.is_some() {
+            return Err(anyhow::format_err!(
+                "Relative versions must be resolved against the latest block"
+            ));
+        }
         match (&version.timestamp, &version.block) {
             (_, Some(block)) => {
                 // If a full block is provided, we prioritize hash over number and chain
@@ -222,6 +442,53 @@ impl TryFrom<&dto::VersionParam> for BlockOrTimestamp {
     }
 }
 
+impl BlockOrTimestamp {
+    /// Resolves a relative version against `head`, the latest block of the requested chain.
+    pub fn from_relative(
+        relative: &dto::RelativeVersion,
+        head: &Block,
+    ) -> Result<Self, anyhow::Error> {
+        match relative {
+            dto::RelativeVersion::BlocksAgo(n) => {
+                let number = head
+                    .number
+                    .checked_sub(*n)
+                    .ok_or_else(|| {
+                        anyhow::format_err!(
+                            "Can't go back {n} blocks from the latest block {}",
+                            head.number
+                        )
+                    })?;
+                Ok(BlockOrTimestamp::Block(BlockIdentifier::Number((head.chain, number as i64))))
+            }
+            dto::RelativeVersion::Duration(duration) => {
+                let duration = parse_relative_duration(duration)?;
+                Ok(BlockOrTimestamp::Timestamp(head.ts - duration))
+            }
+        }
+    }
+}
+
+/// Parses durations like `30m`, `24h` or `7d`.
+fn parse_relative_duration(value: &str) -> Result<chrono::Duration, anyhow::Error> {
+    let invalid =
+        || anyhow::format_err!("Invalid duration '{value}', expected e.g. '30m', '24h' or '7d'");
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(unit_start);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "s" => chrono::Duration::try_seconds(amount),
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        "w" => chrono::Duration::try_weeks(amount),
+        _ => None,
+    };
+    duration.ok_or_else(invalid)
+}
+
 /// References certain states within a single block.
 ///
 /// **Note:** Not all methods that take a version will support all version kinds,
@@ -265,6 +532,81 @@ pub struct WithTotal<T> {
     pub total: Option<i64>,
 }
 
+/// Rows returned by an analytics query, each as a JSON object keyed by column name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyticsRows {
+    pub rows: Vec<serde_json::Value>,
+    /// Whether the query returned more rows than the row limit.
+    pub truncated: bool,
+}
+
+/// An invocation of an admin endpoint, recorded in the audit log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminAction {
+    /// Name of the API key that invoked the action.
+    pub api_key: String,
+    pub role: String,
+    /// Method and path of the request, e.g. `POST /v1/admin/component_filter`.
+    pub action: String,
+    /// HTTP status the request was answered with.
+    pub status: u16,
+}
+
+/// A destructive storage operation, recorded in the audit log by the operation itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Kind of the operation, e.g. `revert_state` or `prune_history`.
+    pub operation: String,
+    /// Extractor that ran the operation, or the database user outside of extractors.
+    pub actor: String,
+    pub chain: Option<Chain>,
+    /// What the operation targeted, e.g. the protocol system of a repair.
+    pub target: Option<String>,
+    pub rows_affected: i64,
+    /// First and last block number affected, inclusive.
+    pub block_range: Option<(i64, i64)>,
+    pub ts: NaiveDateTime,
+}
+
+/// How long the response recorded for an idempotency key is kept.
+pub const IDEMPOTENCY_KEY_RETENTION: Duration = Duration::from_secs(24 * 3600);
+
+/// How long a claimed idempotency key without response blocks retries. Claims left behind by a
+/// server that stopped before completing or releasing them can be taken over afterwards.
+pub const IDEMPOTENCY_CLAIM_LEASE: Duration = Duration::from_secs(5 * 60);
+
+/// Response of an admin action, recorded under the idempotency key it was requested with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotentResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Outcome of claiming an idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyClaim {
+    /// The key was unused and is now held by the caller.
+    Claimed,
+    /// Another request with the key is still being processed.
+    InProgress,
+    /// The key was already used for a different action.
+    ActionMismatch,
+    /// The action already completed with this response.
+    Completed(IdempotentResponse),
+}
+
+/// Resources consumed by an API key, keyed by the name the key is configured with. The keys
+/// themselves are never stored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApiKeyUsage {
+    pub api_key: String,
+    pub requests: u64,
+    /// Number of entities returned.
+    pub rows_returned: u64,
+    pub bytes_returned: u64,
+}
+
 /// Store and retrieve protocol related structs.
 ///
 /// This trait defines how to retrieve protocol components, state as well as
@@ -291,6 +633,52 @@ pub trait ProtocolGateway {
         pagination_params: Option<&PaginationParams>,
     ) -> Result<WithTotal<Vec<ProtocolComponent>>, StorageError>;
 
+    /// Retrieve a page of ProtocolComponents as of a pinned block
+    ///
+    /// Keyset pagination over the components created at or before `cursor.block_number`, ordered
+    /// by id. Pages stay consistent while new components are inserted.
+    ///
+    /// # Parameters
+    /// - `chain` The chain of the components
+    /// - `system` Allows to optionally filter by system.
+    /// - `ids` Allows to optionally filter by id.
+    /// - `min_tvl` Allows to optionally filter by min tvl.
+    /// - `cursor` The pinned block and the id after which the page starts.
+    /// - `page_size` The maximum number of components to return.
+    ///
+    /// # Returns
+    /// The page, with the total number of components matching the filters at the pinned block.
+    async fn get_protocol_components_after(
+        &self,
+        chain: &Chain,
+        system: Option<String>,
+        ids: Option<&[&str]>,
+        min_tvl: Option<f64>,
+        cursor: &ComponentCursor,
+        page_size: i64,
+    ) -> Result<WithTotal<Vec<ProtocolComponent>>, StorageError>;
+
+    /// Retrieve ProtocolComponents together with their contracts
+    ///
+    /// Loads the components and the full state (code, balance and storage) of every contract they
+    /// hold in a single call. Both are read from the same snapshot, so the contract states are
+    /// consistent with the returned components.
+    ///
+    /// # Parameters
+    /// - `chain` The chain of the components
+    /// - `ids` The ids of the components to retrieve.
+    /// - `version` Version at which to retrieve the contract states. None retrieves the latest
+    ///   state.
+    ///
+    /// # Returns
+    /// The found components, unknown ids are skipped.
+    async fn get_components_with_contracts(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        version: Option<&Version>,
+    ) -> Result<Vec<ProtocolComponentWithContracts>, StorageError>;
+
     /// Retrieves owners of tokens
     ///
     /// Queries for owners (protocol components) of tokens that have a certain minimum
@@ -429,16 +817,20 @@ pub trait ProtocolGateway {
     /// # Parameters
     /// - `chain` The chain of the component
     /// - `start_version` The version at which to start looking for changes at.
-    /// - `end_version` The version at which to stop looking for changes.
+    /// - `end_version` The version at which to stop looking for changes. If `None`, changes up to
+    ///   the latest stored block are returned. That block is resolved from the same snapshot the
+    ///   changes are read from, so later blocks don't leak into the result.
     ///
     /// # Return
-    /// A list of ProtocolStateDeltas containing all state changes, Err if no changes were found.
+    /// The state changes together with the block the end version resolved to. Fails with
+    /// `InvalidVersionRange` if no end version is given and the start version lies after the
+    /// latest stored block.
     async fn get_protocol_states_delta(
         &self,
         chain: &Chain,
         start_version: Option<&BlockOrTimestamp>,
-        end_version: &BlockOrTimestamp,
-    ) -> Result<Vec<ProtocolComponentStateDelta>, StorageError>;
+        end_version: Option<&BlockOrTimestamp>,
+    ) -> Result<ProtocolStatesDelta, StorageError>;
 
     /// Retrieve protocol component balance changes
     ///
@@ -465,8 +857,240 @@ pub trait ProtocolGateway {
         version: Option<&Version>,
     ) -> Result<HashMap<String, HashMap<Bytes, ComponentBalance>>, StorageError>;
 
+    /// Retrieve the sampled balance history of a component's token.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the component belongs to.
+    /// - `component_id` The external id of the component.
+    /// - `token` The address of the token.
+    /// - `from` Start of the first sample interval.
+    /// - `to` Samples starting after this timestamp are not returned.
+    /// - `interval` Length of each sample interval.
+    ///
+    /// # Return
+    /// One sample per interval, ordered by time. Intervals that end before the first recorded
+    /// balance are omitted.
+    async fn get_balance_history(
+        &self,
+        chain: &Chain,
+        component_id: &str,
+        token: &Address,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        interval: SampleInterval,
+    ) -> Result<Vec<BalanceSample>, StorageError>;
+
+    /// Saves event logs attributed to protocol components.
+    ///
+    /// The transactions and components the events refer to must already be stored.
+    async fn add_component_events(&self, events: &[ComponentEvent]) -> Result<(), StorageError>;
+
+    /// Retrieve the event logs of components emitted within a block range.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the components belong to.
+    /// - `ids` The external ids of the components.
+    /// - `blocks` The inclusive range of block numbers to return events for.
+    ///
+    /// # Return
+    /// The events ordered by block and log index.
+    async fn get_component_events(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        blocks: RangeInclusive<i64>,
+    ) -> Result<Vec<ComponentEvent>, StorageError>;
+
+    /// Retrieve the components flagged as inactive.
+    ///
+    /// Components are flagged by a periodic analysis once their state and balances did not
+    /// change for a configured time.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the components belong to.
+    /// - `ids` The external ids of the components to check.
+    ///
+    /// # Return
+    /// The inactive components among `ids`, mapped to the timestamp of their last change.
+    /// Active or unknown components are omitted.
+    async fn get_inactive_components(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+    ) -> Result<HashMap<ComponentId, NaiveDateTime>, StorageError>;
+
+    /// Retrieve the extractor that created each component.
+    ///
+    /// Useful to attribute data when several extractors index overlapping protocol systems.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the components belong to.
+    /// - `ids` The external ids of the components to look up. If None, all components are
+    ///   considered.
+    /// - `extractor` If given, only components created by this extractor are returned.
+    ///
+    /// # Return
+    /// The matching components mapped to the name of their extractor. Components stored before
+    /// provenance was recorded are omitted.
+    async fn get_component_extractors(
+        &self,
+        chain: &Chain,
+        ids: Option<&[&str]>,
+        extractor: Option<&str>,
+    ) -> Result<HashMap<ComponentId, String>, StorageError>;
+
+    /// Retrieves the ids of the components holding exactly `tokens`, in any order.
+    ///
+    /// Components are matched by their pair key, see [`ProtocolComponent::pair_key`].
+    ///
+    /// # Parameters
+    /// - `chain` The chain the components belong to.
+    /// - `tokens` The tokens the components hold.
+    /// - `ids` The external ids of the components to consider. If None, all components are
+    ///   considered.
+    async fn get_component_ids_by_tokens(
+        &self,
+        chain: &Chain,
+        tokens: &[Address],
+        ids: Option<&[&str]>,
+    ) -> Result<Vec<ComponentId>, StorageError>;
+
+    /// Retrieve indexed attributes of a component within a key range.
+    ///
+    /// Protocol types may declare attribute name prefixes under `indexed_attributes` in their
+    /// attribute schema, e.g. `{"indexed_attributes": ["ticks/"]}`. Attributes named
+    /// `<prefix><integer>[/...]` are then indexed by that integer, which allows queries like
+    /// "all initialized ticks between X and Y".
+    ///
+    /// # Parameters
+    /// - `chain` The chain the component belongs to.
+    /// - `component_id` The external id of the component.
+    /// - `prefix` The declared attribute name prefix to query.
+    /// - `range` The inclusive range of keys to return.
+    /// - `at` The version at which to query the state. If None, the latest state is returned.
+    ///
+    /// # Return
+    /// The matching attributes and their values, grouped by key in ascending order.
+    async fn get_attribute_range(
+        &self,
+        chain: &Chain,
+        component_id: &str,
+        prefix: &str,
+        range: RangeInclusive<i64>,
+        at: Option<&Version>,
+    ) -> Result<BTreeMap<i64, HashMap<AttrStoreKey, StoreVal>>, StorageError>;
+
+    /// Renames a protocol state attribute across its full history.
+    ///
+    /// Meant for substreams modules that renamed an attribute, so that its history isn't split
+    /// between two names. All versions, including superseded and deleted ones, are renamed in a
+    /// single transaction and the rename is recorded for provenance. Extractors of the protocol
+    /// system should be stopped while renaming.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the components belong to.
+    /// - `protocol_system` The protocol system of the components.
+    /// - `component_ids` The external ids of the components to rename the attribute of. If None,
+    ///   all components of the protocol system are renamed.
+    /// - `old_name` The current name of the attribute.
+    /// - `new_name` The name to rename the attribute to, none of the components may have it yet.
+    ///
+    /// # Return
+    /// The number of renamed versions.
+    async fn rename_protocol_state_attribute(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        component_ids: Option<&[&str]>,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<i64, StorageError>;
+
+    /// Retrieves all components of a protocol system with the names of their current state
+    /// attributes.
+    ///
+    /// Meant for comparing the deployments of a protocol system on different chains, see
+    /// [`DeploymentParity`](crate::models::protocol::DeploymentParity). Attribute values are not
+    /// loaded.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the components belong to.
+    /// - `protocol_system` The protocol system of the components.
+    async fn get_protocol_deployment(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+    ) -> Result<ProtocolDeployment, StorageError>;
+
     async fn get_token_prices(&self, chain: &Chain) -> Result<HashMap<Bytes, f64>, StorageError>;
 
+    /// Returns when tokens, token overrides or protocol components of `chain` were last inserted or
+    /// modified, `None` if the chain has none.
+    async fn get_protocol_data_modified_at(
+        &self,
+        chain: &Chain,
+    ) -> Result<Option<NaiveDateTime>, StorageError>;
+
+    /// Retrieve token metadata overrides.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the tokens belong to.
+    /// - `addresses` Only return the overrides of these tokens. If None, all overrides of the chain
+    ///   are returned.
+    ///
+    /// # Return
+    /// The overrides sorted by token address.
+    async fn get_token_overrides(
+        &self,
+        chain: &Chain,
+        addresses: Option<&[&Address]>,
+    ) -> Result<Vec<TokenOverride>, StorageError>;
+
+    /// Creates token metadata overrides, replacing existing overrides of the same tokens.
+    ///
+    /// # Return
+    /// Ok if all overrides were stored, Err NotFound if one of the tokens is unknown.
+    async fn upsert_token_overrides(&self, overrides: &[TokenOverride])
+        -> Result<(), StorageError>;
+
+    /// Deletes the metadata overrides of tokens. Tokens without override are ignored.
+    async fn delete_token_overrides(
+        &self,
+        chain: &Chain,
+        addresses: &[&Address],
+    ) -> Result<(), StorageError>;
+
+    /// Stores snapshots of token total supplies.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the tokens belong to.
+    /// - `supplies` The total supply of each token. Unknown tokens are skipped.
+    /// - `ts` The time at which the supplies were observed.
+    async fn add_token_supplies(
+        &self,
+        chain: &Chain,
+        supplies: &HashMap<Address, Balance>,
+        ts: NaiveDateTime,
+    ) -> Result<(), StorageError>;
+
+    /// Retrieve the total supply of tokens.
+    ///
+    /// # Parameters
+    /// - `chain` The chain the tokens belong to.
+    /// - `tokens` The addresses of the tokens.
+    /// - `at` The version at which to look up the supplies. If None, the latest snapshots are
+    ///   returned.
+    ///
+    /// # Return
+    /// The most recent supply snapshot of each token taken at or before `at`. Tokens without
+    /// any snapshot are omitted.
+    async fn get_token_supplies(
+        &self,
+        chain: &Chain,
+        tokens: &[Address],
+        at: Option<&Version>,
+    ) -> Result<HashMap<Address, Balance>, StorageError>;
+
     async fn upsert_component_tvl(
         &self,
         chain: &Chain,
@@ -489,6 +1113,18 @@ pub trait ProtocolGateway {
         pagination_params: Option<&PaginationParams>,
     ) -> Result<WithTotal<Vec<String>>, StorageError>;
 
+    /// Retrieve an overview of every protocol system
+    ///
+    /// Aggregates the components of each known protocol system on `chain`. Systems without
+    /// components on the chain are included with a count of zero.
+    ///
+    /// # Return
+    /// The summaries ordered by system name.
+    async fn get_protocol_system_summaries(
+        &self,
+        chain: &Chain,
+    ) -> Result<Vec<ProtocolSystemSummary>, StorageError>;
+
     /// Retrieve the components total value locked (TVL).
     ///
     /// # Parameters
@@ -506,6 +1142,93 @@ pub trait ProtocolGateway {
         ids: Option<&[&str]>,
         pagination_params: Option<&PaginationParams>,
     ) -> Result<WithTotal<HashMap<String, f64>>, StorageError>;
+
+    /// Retrieves the current attributes of components.
+    ///
+    /// # Parameters
+    /// - `chain` The chain of the components.
+    /// - `system` Optionally restricts the result to a protocol system.
+    /// - `ids` Optionally restricts the result to these components.
+    ///
+    /// # Return
+    /// The attributes sorted by component id and attribute name.
+    async fn get_component_latest_states(
+        &self,
+        chain: &Chain,
+        system: Option<&str>,
+        ids: Option<&[&str]>,
+    ) -> Result<Vec<ComponentLatestState>, StorageError>;
+
+    /// Retrieves the current token balances of components along with the tokens' metadata.
+    ///
+    /// Takes the same filters as [`Self::get_component_latest_states`], the balances are sorted by
+    /// component id and token address.
+    async fn get_component_latest_balances(
+        &self,
+        chain: &Chain,
+        system: Option<&str>,
+        ids: Option<&[&str]>,
+    ) -> Result<Vec<ComponentLatestBalance>, StorageError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Additional filters for contract queries.
+///
+/// All filters are evaluated at the requested version and combined with AND.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContractFilter {
+    /// Only return accounts whose code has this hash.
+    pub code_hash: Option<CodeHash>,
+    /// Only return accounts holding at least this native balance.
+    pub min_balance: Option<Balance>,
+    /// Only return accounts holding at most this native balance.
+    pub max_balance: Option<Balance>,
+    /// Orders accounts by their native balance instead of by insertion. Accounts without a
+    /// balance come last.
+    pub balance_order: Option<SortOrder>,
+    /// Only return accounts with (`true`) or without (`false`) code. If unset, all returned
+    /// accounts are expected to have code.
+    pub has_code: Option<bool>,
+    /// Does not load the accounts' code. Returned accounts carry an empty code but the correct
+    /// code hash.
+    pub skip_code: bool,
+}
+
+impl ContractFilter {
+    pub fn with_code_hash(mut self, code_hash: CodeHash) -> Self {
+        self.code_hash = Some(code_hash);
+        self
+    }
+
+    pub fn with_min_balance(mut self, min_balance: Balance) -> Self {
+        self.min_balance = Some(min_balance);
+        self
+    }
+
+    pub fn with_max_balance(mut self, max_balance: Balance) -> Self {
+        self.max_balance = Some(max_balance);
+        self
+    }
+
+    pub fn with_balance_order(mut self, order: SortOrder) -> Self {
+        self.balance_order = Some(order);
+        self
+    }
+
+    pub fn with_has_code(mut self, has_code: bool) -> Self {
+        self.has_code = Some(has_code);
+        self
+    }
+
+    pub fn with_skip_code(mut self) -> Self {
+        self.skip_code = true;
+        self
+    }
 }
 
 /// Filters for entry points queries in the database.
@@ -620,6 +1343,11 @@ pub trait ContractStateGateway {
     /// - `version` Version at which to retrieve state for. None retrieves the latest state.
     /// - `include_slots`: Flag to determine whether to include slot changes. If set to `true`, it
     ///   includes storage slot.
+    ///
+    /// # Returns
+    /// The contract state at `version`. A contract deleted at or before `version` results in a
+    /// `StorageError::Deleted` carrying the deletion timestamp, while versions before the deletion
+    /// still return the contract's state at that time.
     async fn get_contract(
         &self,
         id: &ContractId,
@@ -641,6 +1369,7 @@ pub trait ContractStateGateway {
     ///   latest state.
     /// - `include_slots`: Flag to determine whether to include slot changes. If set to `true`, it
     ///   includes storage slot.
+    /// - `filter`: Optional additional filters on code and native balance, see [`ContractFilter`].
     /// - `pagination_params`: Optional pagination parameters to control the number of results.
     ///
     /// # Returns:
@@ -652,9 +1381,27 @@ pub trait ContractStateGateway {
         addresses: Option<&[Address]>,
         version: Option<&Version>,
         include_slots: bool,
+        filter: Option<&ContractFilter>,
         pagination_params: Option<&PaginationParams>,
     ) -> Result<WithTotal<Vec<Account>>, StorageError>;
 
+    /// Get contract code by its hash.
+    ///
+    /// Code with the same hash is identical, so it does not matter which account or version it is
+    /// taken from. Meant for clients that received only the code hash of a contract.
+    ///
+    /// # Parameters
+    /// - `chain`: The blockchain the code is deployed on.
+    /// - `code_hash`: The keccak256 hash of the code.
+    ///
+    /// # Returns
+    /// The code, or `StorageError::NotFound` if no account on `chain` ever had code with this hash.
+    async fn get_code_by_hash(
+        &self,
+        chain: &Chain,
+        code_hash: &CodeHash,
+    ) -> Result<Code, StorageError>;
+
     /// Inserts a new contract into the database.
     ///
     /// Inserts only the static values of the contract. To insert the contract slots, balance and
@@ -778,6 +1525,41 @@ pub trait ContractStateGateway {
         accounts: Option<&[Address]>,
         version: Option<&Version>,
     ) -> Result<HashMap<Address, HashMap<Address, AccountBalance>>, StorageError>;
+
+    /// Check which addresses are tracked
+    ///
+    /// # Parameters
+    /// - `chain` The chain of the accounts.
+    /// - `addresses` The addresses to check.
+    ///
+    /// # Return
+    /// The subset of `addresses` currently stored as accounts. Deleted accounts are not
+    /// considered tracked.
+    async fn get_tracked_addresses(
+        &self,
+        chain: &Chain,
+        addresses: &[Address],
+    ) -> Result<HashSet<Address>, StorageError>;
+
+    /// Retrieve the slot annotations of contracts
+    ///
+    /// # Parameters
+    /// - `chain` The chain of the contracts.
+    /// - `addresses` The contracts to retrieve the annotations of.
+    ///
+    /// # Return
+    /// The annotations of the given contracts, sorted by address and slot.
+    async fn get_slot_annotations(
+        &self,
+        chain: &Chain,
+        addresses: &[Address],
+    ) -> Result<Vec<SlotAnnotation>, StorageError>;
+
+    /// Inserts slot annotations, replacing the label and description of already annotated slots.
+    async fn upsert_slot_annotations(
+        &self,
+        annotations: &[SlotAnnotation],
+    ) -> Result<(), StorageError>;
 }
 
 pub trait Gateway:
@@ -791,3 +1573,53 @@ pub trait Gateway:
     + Sync
 {
 }
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn head() -> Block {
+        Block::new(
+            1000,
+            Chain::Ethereum,
+            Bytes::from("0x01"),
+            Bytes::from("0x00"),
+            "2024-06-02T00:00:00".parse().unwrap(),
+        )
+    }
+
+    #[rstest]
+    #[case::blocks_ago(
+        dto::RelativeVersion::BlocksAgo(100),
+        Some(BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 900))))
+    )]
+    #[case::day(
+        dto::RelativeVersion::Duration("24h".to_string()),
+        Some(BlockOrTimestamp::Timestamp("2024-06-01T00:00:00".parse().unwrap()))
+    )]
+    #[case::week(
+        dto::RelativeVersion::Duration("1w".to_string()),
+        Some(BlockOrTimestamp::Timestamp("2024-05-26T00:00:00".parse().unwrap()))
+    )]
+    #[case::before_genesis(dto::RelativeVersion::BlocksAgo(1001), None)]
+    #[case::unknown_unit(dto::RelativeVersion::Duration("24x".to_string()), None)]
+    #[case::missing_amount(dto::RelativeVersion::Duration("h".to_string()), None)]
+    #[case::missing_unit(dto::RelativeVersion::Duration("24".to_string()), None)]
+    fn test_from_relative(
+        #[case] relative: dto::RelativeVersion,
+        #[case] exp: Option<BlockOrTimestamp>,
+    ) {
+        let res = BlockOrTimestamp::from_relative(&relative, &head());
+
+        assert_eq!(res.ok(), exp);
+    }
+
+    #[test]
+    fn test_relative_version_param_needs_head() {
+        let version = dto::VersionParam::relative(dto::RelativeVersion::BlocksAgo(1));
+
+        assert!(BlockOrTimestamp::try_from(&version).is_err());
+    }
+}
@@ -6,9 +6,9 @@ use async_trait::async_trait;
 use crate::{
     models::{
         blockchain::{Block, BlockTag, EntryPointWithTracingParams, TracedEntryPoint},
-        contract::AccountDelta,
+        contract::{AccountDelta, AccountProof},
         token::{Token, TokenQuality, TransferCost, TransferTax},
-        Address, Balance, BlockHash, StoreKey,
+        Address, Balance, BlockHash, Chain, StoreKey,
     },
     Bytes,
 };
@@ -120,6 +120,54 @@ pub trait TokenPreProcessor: Send + Sync {
     ) -> Vec<Token>;
 }
 
+/// Trait for retrieving the total supply of tokens, used to snapshot supplies for derived
+/// metrics like the share of a token held by a component.
+#[async_trait]
+pub trait TokenSupplyFetcher: Send + Sync {
+    /// Retrieves the total supply of a token.
+    ///
+    /// # Parameters
+    /// * `token` - The address of the token.
+    /// * `block` - The block tag at which the supply should be read.
+    ///
+    /// # Returns
+    /// The total supply, big endian encoded. On failure, returns a string representing an error
+    /// message.
+    async fn get_total_supply(&self, token: Address, block: BlockTag) -> Result<Balance, String>;
+}
+
+/// Trait for fetching proofs of account and storage values from a node, letting clients verify
+/// served state against the chain.
+#[async_trait]
+pub trait StorageProofFetcher: Send + Sync {
+    /// Retrieves the proof of an account and the given storage slots.
+    ///
+    /// # Parameters
+    /// * `address` - The address of the account.
+    /// * `slots` - The storage slots to prove, absent slots are proven to be empty.
+    /// * `block` - The hash of the block whose state the proof is against.
+    ///
+    /// # Returns
+    /// The proof, on failure a string representing an error message.
+    async fn get_proof(
+        &self,
+        address: &Address,
+        slots: &[StoreKey],
+        block: &BlockHash,
+    ) -> Result<AccountProof, String>;
+}
+
+/// Trait for fetching block headers from a node, used to fill in blocks a stream did not emit.
+#[cfg_attr(feature = "test-utils", mockall::automock)]
+#[async_trait]
+pub trait BlockHeaderFetcher: Send + Sync {
+    /// Retrieves the header of the canonical block at `number`.
+    ///
+    /// # Returns
+    /// The block, on failure a string representing an error message.
+    async fn get_block_header(&self, chain: Chain, number: u64) -> Result<Block, String>;
+}
+
 /// Trait for tracing blockchain transaction execution.
 #[cfg_attr(feature = "test-utils", mockall::automock(type Error = String;))]
 #[async_trait]
@@ -16,7 +16,11 @@ use futures::future::try_join_all;
 use serde::{Deserialize, Serialize};
 use tracing::{trace, warn};
 use tycho_common::{
-    models::{blockchain::Block, contract::AccountDelta, Address, Chain, ChangeType},
+    models::{
+        blockchain::{Block, BlockExtra},
+        contract::AccountDelta,
+        Address, Chain, ChangeType,
+    },
     traits::{AccountExtractor, StorageSnapshotRequest},
     Bytes,
 };
@@ -173,6 +177,18 @@ impl EVMAccountExtractor {
             .await?
             .expect("Block not found");
 
+        let to_u64 = |value: Option<U256>| value.and_then(|v| u64::try_from(v).ok());
+        let mut extra = BlockExtra::default();
+        extra.set(BlockExtra::BASE_FEE_PER_GAS, to_u64(block.base_fee_per_gas));
+        extra.set(BlockExtra::BLOB_GAS_USED, to_u64(block.blob_gas_used));
+        extra.set(BlockExtra::EXCESS_BLOB_GAS, to_u64(block.excess_blob_gas));
+        extra.set(
+            BlockExtra::PARENT_BEACON_BLOCK_ROOT,
+            block
+                .parent_beacon_block_root
+                .map(|root| root.to_bytes()),
+        );
+
         Ok(Block {
             number: block.number.unwrap().as_u64(),
             hash: block.hash.unwrap().to_bytes(),
@@ -180,6 +196,7 @@ impl EVMAccountExtractor {
             chain: Chain::Ethereum,
             ts: NaiveDateTime::from_timestamp_opt(block.timestamp.as_u64() as i64, 0)
                 .expect("Failed to convert timestamp"),
+            extra,
         })
     }
 }
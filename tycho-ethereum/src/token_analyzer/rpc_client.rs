@@ -1,6 +1,18 @@
-use ethers::providers::{Http, Middleware, Provider};
+use async_trait::async_trait;
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{BlockId, H160, H256},
+};
+use tycho_common::{
+    models::{
+        blockchain::Block,
+        contract::{AccountProof, StorageProof},
+        Address, BlockHash, Chain, StoreKey,
+    },
+    traits::{BlockHeaderFetcher, StorageProofFetcher},
+};
 
-use crate::RPCError;
+use crate::{BytesCodec, RPCError};
 
 pub struct EthereumRpcClient {
     ethers_client: ethers::providers::Provider<Http>,
@@ -22,3 +34,71 @@ impl EthereumRpcClient {
             .map_err(RPCError::RequestError)
     }
 }
+
+#[async_trait]
+impl StorageProofFetcher for EthereumRpcClient {
+    async fn get_proof(
+        &self,
+        address: &Address,
+        slots: &[StoreKey],
+        block: &BlockHash,
+    ) -> Result<AccountProof, String> {
+        // The node expects full words, pad slots stored in their compact form.
+        let locations = slots
+            .iter()
+            .map(|slot| H256::from_bytes(&slot.lpad(32, 0)))
+            .collect();
+        let proof = self
+            .ethers_client
+            .get_proof(
+                H160::from_bytes(address),
+                locations,
+                Some(BlockId::Hash(H256::from_bytes(block))),
+            )
+            .await
+            .map_err(|err| format!("eth_getProof failed: {err}"))?;
+        Ok(AccountProof {
+            address: proof.address.to_bytes(),
+            balance: proof.balance.to_bytes(),
+            nonce: proof.nonce.as_u64(),
+            code_hash: proof.code_hash.to_bytes(),
+            storage_hash: proof.storage_hash.to_bytes(),
+            account_proof: proof
+                .account_proof
+                .into_iter()
+                .map(|node| node.to_vec().into())
+                .collect(),
+            storage_proof: proof
+                .storage_proof
+                .into_iter()
+                .map(|slot| StorageProof {
+                    key: slot.key.to_bytes(),
+                    value: slot.value.to_bytes(),
+                    proof: slot
+                        .proof
+                        .into_iter()
+                        .map(|node| node.to_vec().into())
+                        .collect(),
+                })
+                .collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl BlockHeaderFetcher for EthereumRpcClient {
+    async fn get_block_header(&self, chain: Chain, number: u64) -> Result<Block, String> {
+        let block = self
+            .ethers_client
+            .get_block(number)
+            .await
+            .map_err(|err| format!("eth_getBlockByNumber failed: {err}"))?
+            .ok_or_else(|| format!("Block {number} not found"))?;
+        let hash = block
+            .hash
+            .ok_or_else(|| format!("Block {number} is pending"))?;
+        let ts = chrono::DateTime::from_timestamp(block.timestamp.as_u64() as i64, 0)
+            .ok_or_else(|| format!("Block {number} has an invalid timestamp"))?;
+        Ok(Block::new(number, chain, hash.to_bytes(), block.parent_hash.to_bytes(), ts.naive_utc()))
+    }
+}
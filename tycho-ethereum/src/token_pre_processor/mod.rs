@@ -1,7 +1,13 @@
 use std::{str::FromStr, sync::Arc};
 
 use async_trait::async_trait;
-use ethers::{abi::Abi, contract::Contract, prelude::Provider, providers::Http, types::H160};
+use ethers::{
+    abi::Abi,
+    contract::Contract,
+    prelude::Provider,
+    providers::Http,
+    types::{BlockNumber, H160, U256},
+};
 use ethrpc::{http::HttpTransport, Web3, Web3Transport};
 use reqwest::Client;
 use serde_json::from_str;
@@ -10,9 +16,9 @@ use tycho_common::{
     models::{
         blockchain::BlockTag,
         token::{Token, TokenQuality},
-        Chain,
+        Address, Balance, Chain,
     },
-    traits::{TokenAnalyzer, TokenOwnerFinding, TokenPreProcessor},
+    traits::{TokenAnalyzer, TokenOwnerFinding, TokenPreProcessor, TokenSupplyFetcher},
     Bytes,
 };
 use unicode_segmentation::UnicodeSegmentation;
@@ -146,6 +152,34 @@ impl TokenPreProcessor for EthereumTokenPreProcessor {
     }
 }
 
+#[async_trait]
+impl TokenSupplyFetcher for EthereumTokenPreProcessor {
+    #[instrument(skip(self))]
+    async fn get_total_supply(&self, token: Address, block: BlockTag) -> Result<Balance, String> {
+        let contract = Contract::new(
+            H160::from_bytes(&token),
+            self.erc20_abi.clone(),
+            self.ethers_client.clone(),
+        );
+        let block = match block {
+            BlockTag::Finalized => BlockNumber::Finalized,
+            BlockTag::Safe => BlockNumber::Safe,
+            BlockTag::Latest => BlockNumber::Latest,
+            BlockTag::Earliest => BlockNumber::Earliest,
+            BlockTag::Pending => BlockNumber::Pending,
+            BlockTag::Number(n) => BlockNumber::Number(n.into()),
+        };
+        let supply: U256 = contract
+            .method("totalSupply", ())
+            .map_err(|e| e.to_string())?
+            .block(block)
+            .call()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(supply.to_bytes())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::HashMap, env};
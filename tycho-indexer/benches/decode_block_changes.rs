@@ -0,0 +1,92 @@
+//! Measures decoding of a `BlockChanges` message that touches many storage slots, similar to the
+//! blocks Ambient produces.
+//!
+//! Run with `cargo bench -p tycho-indexer --bench decode_block_changes`. Setting
+//! `RAYON_NUM_THREADS=1` gives the serial baseline.
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use tycho_common::models::Chain;
+use tycho_indexer::extractor::{models::BlockChanges, protobuf_deserialisation::TryFromMessage};
+use tycho_substreams::pb::tycho::evm::v1 as substreams;
+
+const N_TXS: usize = 50;
+const CONTRACTS_PER_TX: usize = 20;
+const SLOTS_PER_CONTRACT: usize = 500;
+
+fn word(seed: usize) -> Vec<u8> {
+    let mut word = vec![0u8; 32];
+    word[24..].copy_from_slice(&(seed as u64).to_be_bytes());
+    word
+}
+
+fn block_changes() -> substreams::BlockChanges {
+    let changes = (0..N_TXS)
+        .map(|tx_idx| substreams::TransactionChanges {
+            tx: Some(substreams::Transaction {
+                hash: word(tx_idx),
+                from: vec![0x01; 20],
+                to: vec![0x02; 20],
+                index: tx_idx as u64,
+            }),
+            contract_changes: (0..CONTRACTS_PER_TX)
+                .map(|contract_idx| substreams::ContractChange {
+                    address: word(tx_idx * CONTRACTS_PER_TX + contract_idx)[12..].to_vec(),
+                    change: substreams::ChangeType::Update.into(),
+                    slots: (0..SLOTS_PER_CONTRACT)
+                        .map(|slot_idx| substreams::ContractSlot {
+                            slot: word(slot_idx),
+                            value: word(tx_idx + slot_idx),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        })
+        .collect();
+
+    substreams::BlockChanges {
+        block: Some(substreams::Block {
+            hash: word(1),
+            parent_hash: word(0),
+            number: 1,
+            ts: 1_700_000_000,
+        }),
+        changes,
+        ..Default::default()
+    }
+}
+
+fn decode_block_changes(c: &mut Criterion) {
+    let msg = block_changes();
+    let protocol_types = HashMap::new();
+    let mut group = c.benchmark_group("decode_block_changes");
+    // Decoding a single block takes long enough that the default sample size is impractical.
+    group.sample_size(20);
+    group.throughput(Throughput::Elements((N_TXS * CONTRACTS_PER_TX * SLOTS_PER_CONTRACT) as u64));
+    group.bench_function("slots", |b| {
+        b.iter_batched(
+            || msg.clone(),
+            |msg| {
+                let decoded = BlockChanges::try_from_message((
+                    msg,
+                    "bench",
+                    Chain::Ethereum,
+                    "ambient",
+                    &protocol_types,
+                    0,
+                ))
+                .expect("decoding failed");
+                assert_eq!(decoded.txs_with_update.len(), N_TXS);
+                decoded
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode_block_changes);
+criterion_main!(benches);
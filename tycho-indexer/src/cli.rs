@@ -1,5 +1,15 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDateTime;
 use clap::{Args, Parser, Subcommand};
 use tycho_common::{models::Chain, Bytes};
+use tycho_storage::postgres::{db_growth::TableBudget, scheduler::TaskSchedule};
+
+use crate::{
+    config::{Profile, RuntimeConfig},
+    export::{ExportFormat, DEFAULT_PAGE_SIZE},
+    services::ws_queue::OverflowPolicy,
+};
 
 /// Tycho Indexer using Substreams
 ///
@@ -30,10 +40,33 @@ pub enum Command {
     Index(IndexArgs),
     /// Runs a single substream, intended for testing.
     Run(RunSpkgArgs),
+    /// Decodes and validates the output of a substream without a database, printing every block.
+    Simulate(SimulateArgs),
     /// Starts a job to analyze stored tokens for tax and gas cost.
     AnalyzeTokens(AnalyzeTokenArgs),
+    /// Starts a job to snapshot the total supply of stored tokens.
+    SnapshotTokenSupplies(TokenSupplyArgs),
+    /// Extracts a block range of a single extractor again, the extractor must be stopped.
+    #[command(alias = "backfill")]
+    Repair(RepairArgs),
     /// Starts Tycho RPC only. No extractors.
     Rpc,
+    /// Applies pending database migrations and exits.
+    Migrate,
+    /// Checks that the database is fully migrated and matches the expected schema.
+    Check(CheckArgs),
+    /// Deletes state history superseded before a date, extractors should be stopped.
+    Prune(PruneArgs),
+    /// Exports the components of a chain with their current state and balances to files.
+    Export(ExportArgs),
+    /// Deletes accounts no longer referenced by any component, token or traced entry point.
+    CollectAccounts(CollectAccountsArgs),
+    /// Renames a protocol state attribute across its history, extractors should be stopped.
+    RenameAttribute(RenameAttributeArgs),
+    /// Loads labels of contract storage slots from JSON mapping files.
+    LoadSlotAnnotations(LoadSlotAnnotationsArgs),
+    /// Compares the components of a protocol system indexed on two chains, fails on differences.
+    CompareDeployments(CompareDeploymentsArgs),
 }
 
 #[derive(Parser, Debug, Clone, PartialEq, Eq)]
@@ -48,6 +81,25 @@ pub struct GlobalArgs {
     )]
     pub database_url: String,
 
+    /// Seconds after which the database cancels any statement
+    #[clap(long, env)]
+    pub database_statement_timeout_secs: Option<u64>,
+
+    /// Seconds after which the database cancels statements of expensive reads, like deltas and
+    /// balance histories
+    #[clap(long, env)]
+    pub database_query_deadline_secs: Option<u64>,
+
+    /// Milliseconds after which reads of components, tokens and contracts are logged as slow,
+    /// together with their SQL
+    #[clap(long, env)]
+    pub database_slow_query_threshold_ms: Option<u64>,
+
+    /// Checks all transactions, accounts, tokens and components referenced by a write batch
+    /// before writing it and rejects the batch with a report of every missing one
+    #[clap(long, env)]
+    pub database_strict_writes: bool,
+
     /// Name of the s3 bucket used to retrieve spkgs
     #[clap(env = "TYCHO_S3_BUCKET", long, default_value = "repo.propellerheads-propellerheads")]
     //Default is for backward compatibility but needs to be removed later
@@ -72,6 +124,79 @@ pub struct GlobalArgs {
     /// The server version prefix
     #[clap(long, default_value = "v1")]
     pub server_version_prefix: String,
+
+    /// Date from which the v1 endpoints are deprecated, e.g. `2026-01-01T00:00:00`
+    ///
+    /// Responses of v1 endpoints then carry a `Deprecation` header.
+    #[clap(env = "TYCHO_V1_DEPRECATED_AT", long)]
+    pub v1_deprecated_at: Option<NaiveDateTime>,
+
+    /// Date after which the v1 endpoints may be removed, announced in a `Sunset` header
+    #[clap(env = "TYCHO_V1_SUNSET", long, requires = "v1_deprecated_at")]
+    pub v1_sunset: Option<NaiveDateTime>,
+
+    /// Messages queued per websocket client before the overflow policy applies
+    #[clap(long, default_value = "4096")]
+    pub ws_queue_capacity: usize,
+
+    /// What happens to websocket clients with a full queue, `disconnect` or `drop-oldest`
+    ///
+    /// With `drop-oldest` the oldest messages are dropped and clients receive a gap message
+    /// stating how many messages of a subscription they missed.
+    #[clap(long, default_value = "disconnect")]
+    pub ws_overflow_policy: OverflowPolicy,
+
+    /// Serve websocket subscriptions from the message bus instead of the extractors
+    ///
+    /// Slow clients then no longer stall indexing. Their subscriptions end once they fell so far
+    /// behind that they missed messages, clients have to resubscribe.
+    #[clap(long)]
+    pub ws_from_bus: bool,
+
+    /// Name of the s3 bucket holding offloaded contract data
+    ///
+    /// Must be set whenever the database contains offloaded data, otherwise reads of that data
+    /// fail.
+    #[clap(env = "TYCHO_COLD_STORAGE_BUCKET", long)]
+    pub cold_storage_bucket: Option<String>,
+
+    /// Endpoint of an S3 compatible object store to use instead of AWS S3 for cold storage
+    #[clap(env = "TYCHO_COLD_STORAGE_ENDPOINT", long)]
+    pub cold_storage_endpoint: Option<String>,
+
+    /// Message bus to additionally export all extractor messages to, e.g. `kafka://host:9092` or
+    /// `nats://host:4222`
+    ///
+    /// Requires the indexer to be built with the `kafka` or `nats` feature respectively.
+    #[clap(env = "TYCHO_SINK_URL", long)]
+    pub sink_url: Option<String>,
+
+    /// Topic exported messages are published to, `{chain}` and `{extractor}` are replaced per
+    /// extractor
+    #[clap(long, default_value = "tycho.{chain}.{extractor}")]
+    pub sink_topic: String,
+
+    /// Yaml file of API keys with their quotas
+    ///
+    /// If set, public RPC endpoints require one of the keys and meter their usage.
+    #[clap(env = "TYCHO_API_KEYS_CONFIG", long)]
+    pub api_keys_config: Option<String>,
+
+    /// Yaml file with the runtime configuration, see [`RuntimeConfig`]
+    #[clap(env = "TYCHO_CONFIG_FILE", long)]
+    pub config_file: Option<String>,
+
+    /// Profile of the runtime configuration to apply, `dev`, `staging` or `prod`
+    #[clap(env = "TYCHO_PROFILE", long, default_value = "dev")]
+    pub profile: Profile,
+
+    /// Prints the resolved runtime configuration and exits
+    #[clap(long)]
+    pub print_config: bool,
+
+    /// Runtime configuration resolved from the config file, the profile and the environment
+    #[clap(skip)]
+    pub config: RuntimeConfig,
 }
 
 #[derive(Args, Debug, Clone, PartialEq)]
@@ -99,6 +224,87 @@ pub struct IndexArgs {
     /// Any data before this date is not kept in storage.
     #[clap(long, env, default_value = "2024-01-01T00:00:00")]
     pub retention_horizon: String,
+
+    /// Minimum age in days of contract data moved to cold storage
+    ///
+    /// Enables offloading of contract code and historical storage versions older than this to
+    /// the cold storage bucket.
+    #[clap(long, env)]
+    pub cold_storage_min_age_days: Option<u32>,
+
+    /// Days without state or balance changes after which a component is flagged inactive
+    ///
+    /// Enables a periodic analysis that flags inactive components and clears the flag again once
+    /// they change.
+    #[clap(long, env)]
+    pub component_inactive_after_days: Option<u32>,
+
+    /// Minimum age in days of cleared storage slot versions before they are compacted
+    ///
+    /// Enables a periodic compaction deleting versions that cleared a slot once a new value was
+    /// set more than this many days before the last finalized block.
+    #[clap(long, env)]
+    pub storage_compaction_min_age_days: Option<u32>,
+
+    /// Size budget of a table as `<table>=<size>`, e.g. `contract_storage=2TB`
+    ///
+    /// Enables a periodic check of the budgeted tables' sizes that exports their size and growth
+    /// and alerts once a table exceeds its budget.
+    #[clap(long, env, value_delimiter = ',')]
+    pub db_budget: Vec<TableBudget>,
+
+    /// Skip redundant zero writes to storage slots while a table exceeds its budget
+    #[clap(long, env)]
+    pub db_budget_enforce: bool,
+
+    /// Days an account has to stay unreferenced before it is deleted
+    ///
+    /// Enables periodically collecting accounts no longer referenced by any component, token or
+    /// traced entry point, like the `collect-accounts` command does.
+    #[clap(long, env)]
+    pub orphaned_account_grace_period_days: Option<i64>,
+
+    /// Schedule of a maintenance task as `<task>=<schedule>`, e.g. `storage_compaction=0 3 * * *`
+    ///
+    /// Schedules are cron expressions in UTC, `@hourly`, `@daily`, `@weekly` or intervals like
+    /// `@every 30m`. Tasks are `cold_storage_offload`, `component_activity`,
+    /// `storage_compaction`, `db_growth` and `orphaned_accounts`, tasks without a schedule run
+    /// at their default interval. Separated by `;` in the environment variable.
+    #[clap(long, env = "MAINTENANCE_SCHEDULES", value_delimiter = ';')]
+    pub maintenance_schedule: Vec<TaskSchedule>,
+
+    /// Maximum random delay in seconds added before every run of a maintenance task
+    #[clap(long, env, default_value = "0")]
+    pub maintenance_jitter_secs: u64,
+
+    /// Run singleton maintenance tasks only on the replica holding the leader lock
+    ///
+    /// Required if several indexers run maintenance tasks against the same database.
+    #[clap(long, env)]
+    pub maintenance_leader_election: bool,
+
+    /// Announce every committed block with a Postgres notification
+    ///
+    /// Notifications are sent on the `tycho_changes_<chain>` channel and name the written blocks
+    /// and the number of changed entities.
+    #[clap(long, env)]
+    pub notify_changes: bool,
+
+    /// File to periodically snapshot the protocol cache to
+    ///
+    /// At startup the cache is warmed from this file instead of loading all tokens and components
+    /// from the db, unless the file is missing or outdated. Other caches are always loaded from the
+    /// db.
+    #[clap(long, env)]
+    pub protocol_cache_snapshot: Option<String>,
+
+    /// Seconds between writes of the protocol cache snapshot
+    #[clap(long, env, default_value = "300")]
+    pub protocol_cache_snapshot_interval_secs: u64,
+
+    /// Protocol cache snapshots written more than this many seconds ago are ignored at startup
+    #[clap(long, env, default_value = "3600")]
+    pub protocol_cache_snapshot_max_age_secs: u64,
 }
 
 #[derive(Args, Debug, Clone, PartialEq)]
@@ -148,24 +354,85 @@ pub struct RunSpkgArgs {
     /// - `rpc` - RPC is used to trace and retrieve detected accounts.
     #[clap(long)]
     pub dci_plugin: Option<String>,
+
+    /// Process blocks without writing anything to the database
+    ///
+    /// A summary of the changes that would have been written is logged for every block.
+    #[clap(long)]
+    pub dry_run: bool,
 }
 
 impl RunSpkgArgs {
     pub fn stop_block(&self) -> Option<i64> {
-        if let Some(s) = &self.stop_block {
-            if s.starts_with('+') {
-                let increment: i64 = s
-                    .strip_prefix('+')
-                    .expect("stripped stop block value")
-                    .parse()
-                    .expect("stop block value");
-                Some(self.start_block + increment)
-            } else {
-                Some(s.parse().expect("stop block value"))
-            }
+        parse_stop_block(self.start_block, self.stop_block.as_deref())
+    }
+}
+
+fn parse_stop_block(start_block: i64, stop_block: Option<&str>) -> Option<i64> {
+    if let Some(s) = stop_block {
+        if s.starts_with('+') {
+            let increment: i64 = s
+                .strip_prefix('+')
+                .expect("stripped stop block value")
+                .parse()
+                .expect("stop block value");
+            Some(start_block + increment)
         } else {
-            None
+            Some(s.parse().expect("stop block value"))
         }
+    } else {
+        None
+    }
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct SimulateArgs {
+    /// The blockchain the package indexes
+    #[clap(long, default_value = "ethereum")]
+    pub chain: String,
+
+    #[clap(flatten)]
+    pub substreams_args: SubstreamsArgs,
+
+    /// Substreams Package file
+    #[clap(long)]
+    pub spkg: String,
+
+    /// Substreams Module name
+    #[clap(long)]
+    pub module: String,
+
+    /// Name of the protocol system, used as the protocol system of the decoded components
+    #[clap(long, default_value = "test_protocol")]
+    pub protocol_system: String,
+
+    // The names of the protocol_types emitted by the module
+    #[clap(long, value_delimiter = ',')]
+    pub protocol_type_names: Vec<String>,
+
+    /// Post processor to apply to every block, by its name in the registry
+    #[clap(long)]
+    pub post_processor: Option<String>,
+
+    /// Substreams start block
+    #[clap(long)]
+    pub start_block: i64,
+
+    /// Substreams stop block
+    ///
+    /// Optional. If not provided, the simulation runs until the latest final block.
+    /// If prefixed with a `+` the value is interpreted as an increment to the start block.
+    #[clap(long)]
+    stop_block: Option<String>,
+
+    /// Print every block as a JSON message instead of a summary line
+    #[clap(long)]
+    pub json: bool,
+}
+
+impl SimulateArgs {
+    pub fn stop_block(&self) -> Option<i64> {
+        parse_stop_block(self.start_block, self.stop_block.as_deref())
     }
 }
 
@@ -189,6 +456,146 @@ pub struct AnalyzeTokenArgs {
     pub fetch_batch_size: usize,
 }
 
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct TokenSupplyArgs {
+    /// Ethereum node rpc url
+    #[clap(env, long)]
+    pub rpc_url: String,
+    /// Blockchain to snapshot token supplies for.
+    #[clap(long)]
+    pub chain: Chain,
+    /// How many supplies to fetch from the node concurrently.
+    #[clap(long, default_value = "10")]
+    pub concurrency: usize,
+    /// How many tokens to fetch from the db per page.
+    #[clap(long, default_value = "1000")]
+    pub fetch_batch_size: usize,
+    /// Skip tokens with a quality below this value.
+    #[clap(long, default_value = "51")]
+    pub min_quality: i32,
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct RepairArgs {
+    #[clap(flatten)]
+    pub substreams_args: SubstreamsArgs,
+
+    /// Extractors configuration file
+    #[clap(long, env, default_value = "./extractors.yaml")]
+    pub extractors_config: String,
+
+    /// Name of the extractor to repair, as in the extractors configuration
+    #[clap(long)]
+    pub extractor: String,
+
+    /// First block of the range to extract again
+    #[clap(long)]
+    pub start_block: i64,
+
+    /// Last block of the range to extract again, inclusive
+    #[clap(long)]
+    pub end_block: i64,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct PruneArgs {
+    /// Versions superseded before this date are deleted, e.g. `2024-01-01T00:00:00`
+    #[clap(long)]
+    pub before: NaiveDateTime,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct ExportArgs {
+    /// File format of the export, currently only `parquet`
+    #[clap(long, default_value = "parquet")]
+    pub format: ExportFormat,
+
+    /// Blockchain whose components are exported
+    #[clap(long, default_value = "ethereum")]
+    pub chain: Chain,
+
+    /// Only exports the components of this protocol system
+    #[clap(long)]
+    pub protocol_system: Option<String>,
+
+    /// Directory the files are written to, created if missing
+    #[clap(long)]
+    pub output: PathBuf,
+
+    /// Number of components read and written at once
+    #[clap(long, default_value_t = DEFAULT_PAGE_SIZE)]
+    pub page_size: i64,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct CheckArgs {
+    /// Additionally validates invariants across components, balances and versioned state, fails
+    /// if any is violated
+    #[clap(long)]
+    pub integrity: bool,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct CollectAccountsArgs {
+    /// Days an account has to stay unreferenced before it is deleted
+    #[clap(long, default_value = "7")]
+    pub grace_period_days: i64,
+
+    /// Number of accounts deleted per database transaction
+    #[clap(long, default_value = "1000")]
+    pub batch_size: i64,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct RenameAttributeArgs {
+    /// Blockchain the components are indexed on
+    #[clap(long, default_value = "ethereum")]
+    pub chain: Chain,
+
+    /// Protocol system whose components are renamed
+    #[clap(long)]
+    pub protocol_system: String,
+
+    /// Comma separated ids of the components to rename the attribute of, all components of the
+    /// protocol system if omitted
+    #[clap(long, value_delimiter = ',')]
+    pub components: Vec<String>,
+
+    /// Current name of the attribute
+    #[clap(long)]
+    pub from: String,
+
+    /// New name of the attribute
+    #[clap(long)]
+    pub to: String,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct LoadSlotAnnotationsArgs {
+    /// Blockchain the annotated contracts are deployed on
+    #[clap(long, default_value = "ethereum")]
+    pub chain: Chain,
+
+    /// Comma separated paths of the mapping files, later files override labels of earlier ones
+    #[clap(long, value_delimiter = ',', required = true)]
+    pub files: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct CompareDeploymentsArgs {
+    /// Protocol system to compare
+    #[clap(long)]
+    pub protocol_system: String,
+
+    /// Chain the protocol system is expected to be fully indexed on
+    #[clap(long, default_value = "ethereum")]
+    pub base_chain: Chain,
+
+    /// Chain to compare against the base chain
+    #[clap(long)]
+    pub other_chain: Chain,
+}
+
 #[cfg(test)]
 mod cli_tests {
     use super::*;
@@ -221,11 +628,29 @@ mod cli_tests {
             global_args: GlobalArgs {
                 endpoint_url: "http://example.com".to_string(),
                 database_url: "my_db".to_string(),
+                database_statement_timeout_secs: None,
+                database_query_deadline_secs: None,
+                database_slow_query_threshold_ms: None,
+                database_strict_writes: false,
                 rpc_url: "http://example.com".to_string(),
                 s3_bucket: Some("repo.propellerheads-propellerheads".to_string()),
                 server_ip: "0.0.0.0".to_string(),
                 server_port: 4242,
                 server_version_prefix: "v1".to_string(),
+                v1_deprecated_at: None,
+                v1_sunset: None,
+                ws_queue_capacity: 4096,
+                ws_overflow_policy: OverflowPolicy::Disconnect,
+                ws_from_bus: false,
+                cold_storage_bucket: None,
+                cold_storage_endpoint: None,
+                sink_url: None,
+                sink_topic: "tycho.{chain}.{extractor}".to_string(),
+                api_keys_config: None,
+                config_file: None,
+                profile: Profile::Dev,
+                print_config: false,
+                config: RuntimeConfig::default(),
             },
             command: Command::Run(RunSpkgArgs {
                 chain: "ethereum".to_string(),
@@ -240,12 +665,45 @@ mod cli_tests {
                 initialized_accounts: vec![],
                 initialization_block: 0,
                 dci_plugin: None,
+                dry_run: false,
             }),
         };
 
         assert_eq!(cli, expected_args);
     }
 
+    #[test]
+    fn test_arg_parsing_simulate_cmd() {
+        let cli = Cli::try_parse_from(vec![
+            "tycho-indexer",
+            "--rpc-url",
+            "http://example.com",
+            "simulate",
+            "--api_token",
+            "your_api_token",
+            "--spkg",
+            "package.spkg",
+            "--module",
+            "map_protocol_changes",
+            "--protocol-system",
+            "vm:balancer_v2",
+            "--start-block",
+            "17361664",
+            "--stop-block",
+            "+100",
+            "--json",
+        ])
+        .expect("parse errored");
+
+        let Command::Simulate(args) = cli.command() else {
+            panic!("expected simulate command");
+        };
+        assert_eq!(args.protocol_system, "vm:balancer_v2");
+        assert_eq!(args.stop_block(), Some(17361764));
+        assert!(args.json);
+        assert_eq!(args.post_processor, None);
+    }
+
     #[tokio::test]
     async fn test_arg_parsing_index_cmd() {
         let cli = Cli::try_parse_from(vec![
@@ -261,6 +719,8 @@ mod cli_tests {
             "/opt/extractors.yaml",
             "--api_token",
             "your_api_token",
+            "--maintenance-schedule",
+            "storage_compaction=0 3 * * *",
         ])
         .expect("parse errored");
 
@@ -268,11 +728,29 @@ mod cli_tests {
             global_args: GlobalArgs {
                 endpoint_url: "http://example.com".to_string(),
                 database_url: "my_db".to_string(),
+                database_statement_timeout_secs: None,
+                database_query_deadline_secs: None,
+                database_slow_query_threshold_ms: None,
+                database_strict_writes: false,
                 rpc_url: "http://example.com".to_string(),
                 s3_bucket: Some("repo.propellerheads-propellerheads".to_string()),
                 server_ip: "0.0.0.0".to_string(),
                 server_port: 4242,
                 server_version_prefix: "v1".to_string(),
+                v1_deprecated_at: None,
+                v1_sunset: None,
+                ws_queue_capacity: 4096,
+                ws_overflow_policy: OverflowPolicy::Disconnect,
+                ws_from_bus: false,
+                cold_storage_bucket: None,
+                cold_storage_endpoint: None,
+                sink_url: None,
+                sink_topic: "tycho.{chain}.{extractor}".to_string(),
+                api_keys_config: None,
+                config_file: None,
+                profile: Profile::Dev,
+                print_config: false,
+                config: RuntimeConfig::default(),
             },
             command: Command::Index(IndexArgs {
                 substreams_args: SubstreamsArgs {
@@ -281,12 +759,116 @@ mod cli_tests {
                 chains: vec!["ethereum".to_string()],
                 extractors_config: "/opt/extractors.yaml".to_string(),
                 retention_horizon: "2024-01-01T00:00:00".to_string(),
+                cold_storage_min_age_days: None,
+                component_inactive_after_days: None,
+                storage_compaction_min_age_days: None,
+                db_budget: Vec::new(),
+                db_budget_enforce: false,
+                orphaned_account_grace_period_days: None,
+                maintenance_schedule: vec!["storage_compaction=0 3 * * *"
+                    .parse()
+                    .unwrap()],
+                maintenance_jitter_secs: 0,
+                maintenance_leader_election: false,
+                notify_changes: false,
+                protocol_cache_snapshot: None,
+                protocol_cache_snapshot_interval_secs: 300,
+                protocol_cache_snapshot_max_age_secs: 3600,
             }),
         };
 
         assert_eq!(cli, expected_args);
     }
 
+    #[test]
+    fn test_arg_parsing_maintenance_cmds() {
+        let global = ["tycho-indexer", "--rpc-url", "http://example.com"];
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(global.iter().chain(args))
+                .expect("parse errored")
+                .command()
+        };
+
+        assert_eq!(
+            parse(&["prune", "--before", "2024-06-01T00:00:00"]),
+            Command::Prune(PruneArgs { before: "2024-06-01T00:00:00".parse().unwrap() })
+        );
+        assert!(matches!(
+            parse(&[
+                "backfill",
+                "--api_token",
+                "token",
+                "--extractor",
+                "uniswap_v2",
+                "--start-block",
+                "1",
+                "--end-block",
+                "2"
+            ]),
+            Command::Repair(RepairArgs { start_block: 1, end_block: 2, .. })
+        ));
+        assert_eq!(
+            parse(&["export", "--output", "/tmp/export", "--protocol-system", "uniswap_v2"]),
+            Command::Export(ExportArgs {
+                format: ExportFormat::Parquet,
+                chain: Chain::Ethereum,
+                protocol_system: Some("uniswap_v2".to_string()),
+                output: PathBuf::from("/tmp/export"),
+                page_size: DEFAULT_PAGE_SIZE,
+            })
+        );
+        assert_eq!(parse(&["check"]), Command::Check(CheckArgs { integrity: false }));
+        assert_eq!(parse(&["check", "--integrity"]), Command::Check(CheckArgs { integrity: true }));
+        assert_eq!(
+            parse(&["collect-accounts", "--grace-period-days", "3"]),
+            Command::CollectAccounts(CollectAccountsArgs {
+                grace_period_days: 3,
+                batch_size: 1000
+            })
+        );
+        assert_eq!(
+            parse(&[
+                "rename-attribute",
+                "--protocol-system",
+                "uniswap_v2",
+                "--components",
+                "0x01,0x02",
+                "--from",
+                "reserve0",
+                "--to",
+                "reserves_0"
+            ]),
+            Command::RenameAttribute(RenameAttributeArgs {
+                chain: Chain::Ethereum,
+                protocol_system: "uniswap_v2".to_string(),
+                components: vec!["0x01".to_string(), "0x02".to_string()],
+                from: "reserve0".to_string(),
+                to: "reserves_0".to_string(),
+            })
+        );
+        assert_eq!(
+            parse(&["load-slot-annotations", "--files", "vaults.json,pools.json"]),
+            Command::LoadSlotAnnotations(LoadSlotAnnotationsArgs {
+                chain: Chain::Ethereum,
+                files: vec!["vaults.json".to_string(), "pools.json".to_string()],
+            })
+        );
+        assert_eq!(
+            parse(&[
+                "compare-deployments",
+                "--protocol-system",
+                "uniswap_v3",
+                "--other-chain",
+                "base"
+            ]),
+            Command::CompareDeployments(CompareDeploymentsArgs {
+                protocol_system: "uniswap_v3".to_string(),
+                base_chain: Chain::Ethereum,
+                other_chain: Chain::Base,
+            })
+        );
+    }
+
     #[test]
     fn test_arg_parsing_missing_val() {
         let args = Cli::try_parse_from(vec![
@@ -0,0 +1,84 @@
+//! S3 backed object store for offloaded contract data.
+use async_trait::async_trait;
+use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_s3::{primitives::ByteStream, Client};
+use tracing::{info, instrument};
+use tycho_common::storage::StorageError;
+use tycho_storage::postgres::cold_storage::ColdStore;
+
+pub struct S3ColdStore {
+    client: Client,
+    bucket: String,
+}
+
+impl S3ColdStore {
+    /// Creates a store using credentials from the environment.
+    ///
+    /// `endpoint` allows using S3 compatible stores other than AWS, e.g. MinIO.
+    pub async fn from_env(bucket: &str, endpoint: Option<&str>) -> Self {
+        info!(bucket, endpoint, "Using S3 cold store");
+        let region_provider = RegionProviderChain::default_provider().or_else("eu-central-1");
+        let config = aws_config::from_env()
+            .region(region_provider)
+            .load()
+            .await;
+
+        let mut s3_config = aws_sdk_s3::config::Builder::from(&config);
+        if let Some(endpoint) = endpoint {
+            s3_config = s3_config
+                .endpoint_url(endpoint)
+                .force_path_style(true);
+        }
+
+        Self { client: Client::from_conf(s3_config.build()), bucket: bucket.to_string() }
+    }
+}
+
+#[async_trait]
+impl ColdStore for S3ColdStore {
+    #[instrument(skip(self, data), fields(size = data.len()))]
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(ByteStream::from(data))
+            .send()
+            .await
+            .map_err(|err| {
+                StorageError::Unexpected(format!(
+                    "Failed to upload cold storage object {key}: {err}"
+                ))
+            })?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let resp = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|err| {
+                let err = err.into_service_error();
+                if err.is_no_such_key() {
+                    StorageError::NotFound("ColdStorageObject".to_string(), key.to_string())
+                } else {
+                    StorageError::Unexpected(format!(
+                        "Failed to fetch cold storage object {key}: {err}"
+                    ))
+                }
+            })?;
+        let data = resp
+            .body
+            .collect()
+            .await
+            .map_err(|err| {
+                StorageError::Unexpected(format!("Failed to read cold storage object {key}: {err}"))
+            })?;
+        Ok(data.into_bytes().to_vec())
+    }
+}
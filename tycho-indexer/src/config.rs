@@ -0,0 +1,414 @@
+//! Typed runtime configuration of the indexer process.
+//!
+//! Process level settings, like worker threads, telemetry, database pool, caches and metrics, are
+//! collected in a [`RuntimeConfig`] loaded from an optional yaml file. Settings of the file's top
+//! level apply to every profile, the section of the selected [`Profile`] overrides them:
+//!
+//! ```yaml
+//! workers:
+//!   extraction_threads: 4
+//! profiles:
+//!   prod:
+//!     database:
+//!       pool_size: 64
+//!     telemetry:
+//!       otlp_exporter_endpoint: http://otel-collector:4317
+//! ```
+//!
+//! Values are resolved from the defaults, the file, the profile section and finally the legacy
+//! environment variables (`EXTRACTION_WORKER_THREADS`, `MAIN_WORKER_THREADS`, `ENABLE_CONSOLE` and
+//! `OTLP_EXPORTER_ENDPOINT`), later sources taking precedence. The result is validated as a whole,
+//! so all problems are reported at once.
+use std::{env, fmt, fs, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use tycho_storage::postgres::head_cache::{HeadCacheBudget, COMPONENT_STATE_CACHE_DEFAULT_MB};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    #[default]
+    Dev,
+    Staging,
+    Prod,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Profile::Dev => write!(f, "dev"),
+            Profile::Staging => write!(f, "staging"),
+            Profile::Prod => write!(f, "prod"),
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dev" => Ok(Profile::Dev),
+            "staging" => Ok(Profile::Staging),
+            "prod" => Ok(Profile::Prod),
+            _ => Err(format!("Unknown profile {s}, expected dev, staging or prod")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkersConfig {
+    /// Worker threads of the runtime running the extractors.
+    pub extraction_threads: usize,
+    /// Worker threads of the runtime running the services and the database writes.
+    pub main_threads: usize,
+}
+
+impl Default for WorkersConfig {
+    fn default() -> Self {
+        Self { extraction_threads: 2, main_threads: 3 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Replaces the log output with the tokio console subscriber.
+    pub enable_console: bool,
+    /// Exports traces to this OTLP collector instead of logging to stdout.
+    pub otlp_exporter_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Maximum connections of each database pool, defaults to four per CPU.
+    pub pool_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeadCacheConfig {
+    /// Memory budget of the cache of latest contract states, in MB per configured extractor.
+    pub max_mb_per_extractor: usize,
+    /// Memory budget of the cache of latest protocol component states, in MB per configured
+    /// extractor.
+    pub component_states_max_mb_per_extractor: usize,
+    /// Cached contract and component states older than this are read from the database again.
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for HeadCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_mb_per_extractor: 64,
+            component_states_max_mb_per_extractor: COMPONENT_STATE_CACHE_DEFAULT_MB,
+            max_age_secs: None,
+        }
+    }
+}
+
+impl HeadCacheConfig {
+    /// Budget of a cache shared by `n_extractors` extractors.
+    pub fn budget(&self, n_extractors: usize) -> HeadCacheBudget {
+        self.with_max_age(HeadCacheBudget::from_mb(self.max_mb_per_extractor * n_extractors.max(1)))
+    }
+
+    /// Budget of a component state cache shared by `n_extractors` extractors.
+    pub fn component_state_budget(&self, n_extractors: usize) -> HeadCacheBudget {
+        self.with_max_age(HeadCacheBudget::from_mb(
+            self.component_states_max_mb_per_extractor * n_extractors.max(1),
+        ))
+    }
+
+    fn with_max_age(&self, budget: HeadCacheBudget) -> HeadCacheBudget {
+        match self.max_age_secs {
+            Some(secs) => budget.with_max_age(Duration::from_secs(secs)),
+            None => budget,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServicesConfig {
+    /// Worker threads of the RPC server, defaults to one per CPU.
+    pub http_workers: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { host: "0.0.0.0".to_string(), port: 9898 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    pub workers: WorkersConfig,
+    pub telemetry: TelemetryConfig,
+    pub database: DatabaseConfig,
+    pub head_cache: HeadCacheConfig,
+    pub services: ServicesConfig,
+    pub metrics: MetricsConfig,
+}
+
+/// Every problem found while loading a configuration.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl RuntimeConfig {
+    /// Loads the configuration of `profile` from the file at `path`, if any, and the environment.
+    pub fn load(path: Option<&str>, profile: Profile) -> Result<Self, ConfigError> {
+        let contents = path
+            .map(|path| {
+                fs::read_to_string(path)
+                    .map_err(|e| ConfigError(vec![format!("Failed to read {path}: {e}")]))
+            })
+            .transpose()?;
+        Self::resolve(contents.as_deref(), profile, |name| env::var(name).ok())
+    }
+
+    fn resolve(
+        contents: Option<&str>,
+        profile: Profile,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut resolved =
+            serde_yaml::to_value(Self::default()).map_err(|e| ConfigError(vec![e.to_string()]))?;
+        if let Some(contents) = contents {
+            let mut file = match serde_yaml::from_str::<Value>(contents)
+                .map_err(|e| ConfigError(vec![format!("Malformed yaml: {e}")]))?
+            {
+                Value::Mapping(file) => file,
+                Value::Null => Mapping::new(),
+                _ => return Err(ConfigError(vec!["Expected a mapping at the top level".into()])),
+            };
+            let profiles = file.remove("profiles");
+            merge(&mut resolved, Value::Mapping(file));
+            if let Some(overrides) = select_profile(profiles, profile)? {
+                merge(&mut resolved, overrides);
+            }
+        }
+        let mut config: Self =
+            serde_yaml::from_value(resolved).map_err(|e| ConfigError(vec![e.to_string()]))?;
+
+        let mut problems = config.apply_env(env);
+        problems.extend(config.problems(profile));
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(problems))
+        }
+    }
+
+    /// Applies the legacy environment variables, returns the ones that failed to parse.
+    fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut threads = |name: &str, threads: &mut usize| {
+            if let Some(value) = env(name) {
+                match value.parse() {
+                    Ok(value) => *threads = value,
+                    Err(_) => problems.push(format!("{name} must be a number, got {value}")),
+                }
+            }
+        };
+        threads("EXTRACTION_WORKER_THREADS", &mut self.workers.extraction_threads);
+        threads("MAIN_WORKER_THREADS", &mut self.workers.main_threads);
+        if let Some(flag) = env("ENABLE_CONSOLE") {
+            self.telemetry.enable_console = flag == "true";
+        }
+        if let Some(endpoint) = env("OTLP_EXPORTER_ENDPOINT") {
+            self.telemetry.otlp_exporter_endpoint = Some(endpoint);
+        }
+        problems
+    }
+
+    fn problems(&self, profile: Profile) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.workers.extraction_threads == 0 {
+            problems.push("workers.extraction_threads must be at least 1".to_string());
+        }
+        if self.workers.main_threads == 0 {
+            problems.push("workers.main_threads must be at least 1".to_string());
+        }
+        if self.database.pool_size == Some(0) {
+            problems.push("database.pool_size must be at least 1".to_string());
+        }
+        if self.head_cache.max_mb_per_extractor == 0 {
+            problems.push("head_cache.max_mb_per_extractor must be at least 1".to_string());
+        }
+        if self
+            .head_cache
+            .component_states_max_mb_per_extractor ==
+            0
+        {
+            problems.push(
+                "head_cache.component_states_max_mb_per_extractor must be at least 1".to_string(),
+            );
+        }
+        if self.head_cache.max_age_secs == Some(0) {
+            problems.push("head_cache.max_age_secs must be at least 1".to_string());
+        }
+        if self.services.http_workers == Some(0) {
+            problems.push("services.http_workers must be at least 1".to_string());
+        }
+        if let Some(endpoint) = &self.telemetry.otlp_exporter_endpoint {
+            if !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+                problems.push(format!(
+                    "telemetry.otlp_exporter_endpoint must be an http(s) url, got {endpoint}"
+                ));
+            }
+        }
+        if self.metrics.port == 0 {
+            problems.push("metrics.port must not be 0".to_string());
+        }
+        if profile == Profile::Prod && self.telemetry.enable_console {
+            problems.push(
+                "telemetry.enable_console is not allowed in the prod profile, it disables the \
+                 log output and the trace export"
+                    .to_string(),
+            );
+        }
+        problems
+    }
+
+    /// The resolved configuration as yaml, as printed by `--print-config`.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("config serializes to yaml")
+    }
+}
+
+/// Picks the section of `profile` out of the file's profiles, rejecting unknown profile names.
+fn select_profile(profiles: Option<Value>, profile: Profile) -> Result<Option<Value>, ConfigError> {
+    let profiles = match profiles {
+        None | Some(Value::Null) => return Ok(None),
+        Some(Value::Mapping(profiles)) => profiles,
+        Some(_) => return Err(ConfigError(vec!["profiles must be a mapping".to_string()])),
+    };
+    let mut selected = None;
+    let mut problems = Vec::new();
+    for (name, section) in profiles {
+        match name.as_str().map(Profile::from_str) {
+            Some(Ok(name)) if name == profile => selected = Some(section),
+            Some(Ok(_)) => {}
+            Some(Err(e)) => problems.push(format!("profiles: {e}")),
+            None => problems.push(format!("profiles: expected a profile name, got {name:?}")),
+        }
+    }
+    if problems.is_empty() {
+        Ok(selected)
+    } else {
+        Err(ConfigError(problems))
+    }
+}
+
+/// Recursively merges `overrides` into `base`, mappings are merged key by key.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Mapping(base), Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+workers:
+  extraction_threads: 4
+database:
+  pool_size: 16
+profiles:
+  prod:
+    database:
+      pool_size: 64
+    telemetry:
+      otlp_exporter_endpoint: http://otel-collector:4317
+"#;
+
+    #[test]
+    fn test_resolve_profile_and_env_overrides() {
+        let env = |name: &str| (name == "MAIN_WORKER_THREADS").then(|| "8".to_string());
+
+        let dev = RuntimeConfig::resolve(Some(CONFIG), Profile::Dev, |_| None).unwrap();
+        let prod = RuntimeConfig::resolve(Some(CONFIG), Profile::Prod, env).unwrap();
+
+        assert_eq!(dev.workers, WorkersConfig { extraction_threads: 4, main_threads: 3 });
+        assert_eq!(dev.database.pool_size, Some(16));
+        assert_eq!(dev.telemetry.otlp_exporter_endpoint, None);
+        assert_eq!(prod.workers, WorkersConfig { extraction_threads: 4, main_threads: 8 });
+        assert_eq!(prod.database.pool_size, Some(64));
+        assert_eq!(
+            prod.telemetry.otlp_exporter_endpoint,
+            Some("http://otel-collector:4317".to_string())
+        );
+        assert_eq!(prod.metrics, MetricsConfig::default());
+        assert_eq!(dev.head_cache.budget(2), HeadCacheBudget::from_mb(128));
+        assert_eq!(dev.head_cache.component_state_budget(2), HeadCacheBudget::from_mb(32));
+    }
+
+    #[test]
+    fn test_resolve_reports_all_problems() {
+        let contents = r#"
+workers:
+  main_threads: 0
+telemetry:
+  enable_console: true
+  otlp_exporter_endpoint: otel-collector:4317
+"#;
+        let env = |name: &str| (name == "EXTRACTION_WORKER_THREADS").then(|| "two".to_string());
+
+        let res = RuntimeConfig::resolve(Some(contents), Profile::Prod, env);
+        let unknown_field =
+            RuntimeConfig::resolve(Some("workers:\n  threads: 2"), Profile::Dev, |_| None);
+        let unknown_profile =
+            RuntimeConfig::resolve(Some("profiles:\n  qa: {}"), Profile::Dev, |_| None);
+
+        assert_eq!(
+            res.unwrap_err().0,
+            vec![
+                "EXTRACTION_WORKER_THREADS must be a number, got two",
+                "workers.main_threads must be at least 1",
+                "telemetry.otlp_exporter_endpoint must be an http(s) url, got otel-collector:4317",
+                "telemetry.enable_console is not allowed in the prod profile, it disables the log \
+                 output and the trace export",
+            ]
+        );
+        assert!(unknown_field.unwrap_err().0[0].contains("unknown field `threads`"));
+        assert_eq!(
+            unknown_profile.unwrap_err().0,
+            vec!["profiles: Unknown profile qa, expected dev, staging or prod"]
+        );
+    }
+}
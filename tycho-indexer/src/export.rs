@@ -0,0 +1,422 @@
+//! Exports of indexed protocol data to files.
+//!
+//! An export is a snapshot of the protocol components of a chain as of its latest block, with
+//! their static attributes, state attributes and balances. Each of these is written to its own
+//! file in the output directory, e.g. `components.parquet`. Components are read in pages pinned to
+//! the snapshot block, so the export stays consistent while indexing continues.
+//!
+//! Attributes and balances are written in long format, one row per component and attribute or
+//! token, with values as raw bytes.
+use std::{
+    fmt,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use arrow_array::{
+    builder::{BinaryBuilder, ListBuilder, StringBuilder, TimestampMicrosecondBuilder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use thiserror::Error;
+use tracing::{debug, info};
+use tycho_common::{
+    models::{
+        protocol::{ProtocolComponent, ProtocolComponentState},
+        Chain, ComponentCursor,
+    },
+    storage::{BlockIdentifier, BlockOrTimestamp, Gateway, StorageError, Version, VersionKind},
+};
+
+/// Components read and written per page.
+pub const DEFAULT_PAGE_SIZE: i64 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Parquet,
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Parquet => write!(f, "parquet"),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!("Unknown export format {s}, expected parquet")),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Failed to read from storage: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Failed to write {0}: {1}")]
+    Write(String, String),
+}
+
+/// Block an export was taken at and the number of rows written per file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub block_number: u64,
+    pub components: usize,
+    pub static_attributes: usize,
+    pub states: usize,
+    pub balances: usize,
+}
+
+/// Exports the components of `chain`, optionally only those of `system`, to `dir`.
+///
+/// The directory is created if missing, existing files of a previous export are overwritten.
+pub async fn export_protocol_snapshot<G: Gateway + ?Sized>(
+    gw: &G,
+    chain: Chain,
+    system: Option<String>,
+    format: ExportFormat,
+    dir: &Path,
+    page_size: i64,
+) -> Result<ExportSummary, ExportError> {
+    let block = gw
+        .get_block(&BlockIdentifier::Latest(chain))
+        .await?;
+    let version = Version(
+        BlockOrTimestamp::Block(BlockIdentifier::Number((chain, block.number as i64))),
+        VersionKind::Last,
+    );
+    fs::create_dir_all(dir)
+        .map_err(|e| ExportError::Write(dir.display().to_string(), e.to_string()))?;
+    let mut writer = match format {
+        ExportFormat::Parquet => ParquetSnapshotWriter::create(dir)?,
+    };
+    info!(%chain, block_number = block.number, dir = %dir.display(), "Exporting components");
+
+    let mut cursor = ComponentCursor::new(block.number, None);
+    loop {
+        let components = gw
+            .get_protocol_components_after(&chain, system.clone(), None, None, &cursor, page_size)
+            .await?
+            .entity;
+        let Some(last) = components.last() else {
+            break;
+        };
+        cursor.after = Some(last.id.clone());
+        let ids = components
+            .iter()
+            .map(|component| component.id.as_str())
+            .collect::<Vec<_>>();
+        let states = gw
+            .get_protocol_states(&chain, Some(version.clone()), None, Some(&ids), true, None)
+            .await?
+            .entity;
+        writer.write_page(&components, &states)?;
+        debug!(n_components = writer.summary.components, "Exported page");
+        if (components.len() as i64) < page_size {
+            break;
+        }
+    }
+
+    let mut summary = writer.finish()?;
+    summary.block_number = block.number;
+    Ok(summary)
+}
+
+/// A parquet file together with the schema of its rows.
+struct ParquetTable {
+    path: PathBuf,
+    schema: SchemaRef,
+    writer: ArrowWriter<File>,
+}
+
+impl ParquetTable {
+    fn create(dir: &Path, name: &str, fields: Vec<Field>) -> Result<Self, ExportError> {
+        let path = dir.join(format!("{name}.parquet"));
+        let schema = Arc::new(Schema::new(fields));
+        let file = File::create(&path)
+            .map_err(|e| ExportError::Write(path.display().to_string(), e.to_string()))?;
+        let writer = ArrowWriter::try_new(file, schema.clone(), None)
+            .map_err(|e| ExportError::Write(path.display().to_string(), e.to_string()))?;
+        Ok(Self { path, schema, writer })
+    }
+
+    fn write(&mut self, columns: Vec<ArrayRef>) -> Result<(), ExportError> {
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)
+            .map_err(|e| ExportError::Write(self.path.display().to_string(), e.to_string()))?;
+        self.writer
+            .write(&batch)
+            .map_err(|e| ExportError::Write(self.path.display().to_string(), e.to_string()))
+    }
+
+    fn close(self) -> Result<(), ExportError> {
+        self.writer
+            .close()
+            .map_err(|e| ExportError::Write(self.path.display().to_string(), e.to_string()))?;
+        Ok(())
+    }
+}
+
+fn binary_list(name: &str) -> Field {
+    Field::new_list(name, Field::new("item", DataType::Binary, true), false)
+}
+
+/// Writes each page of components as one row group per file.
+struct ParquetSnapshotWriter {
+    components: ParquetTable,
+    static_attributes: ParquetTable,
+    states: ParquetTable,
+    balances: ParquetTable,
+    summary: ExportSummary,
+}
+
+impl ParquetSnapshotWriter {
+    fn create(dir: &Path) -> Result<Self, ExportError> {
+        let attribute_fields = || {
+            vec![
+                Field::new("component_id", DataType::Utf8, false),
+                Field::new("attribute", DataType::Utf8, false),
+                Field::new("value", DataType::Binary, false),
+            ]
+        };
+        Ok(Self {
+            components: ParquetTable::create(
+                dir,
+                "components",
+                vec![
+                    Field::new("id", DataType::Utf8, false),
+                    Field::new("protocol_system", DataType::Utf8, false),
+                    Field::new("protocol_type_name", DataType::Utf8, false),
+                    Field::new("chain", DataType::Utf8, false),
+                    binary_list("tokens"),
+                    binary_list("contract_addresses"),
+                    Field::new("creation_tx", DataType::Binary, false),
+                    Field::new(
+                        "created_at",
+                        DataType::Timestamp(TimeUnit::Microsecond, None),
+                        false,
+                    ),
+                ],
+            )?,
+            static_attributes: ParquetTable::create(dir, "static_attributes", attribute_fields())?,
+            states: ParquetTable::create(dir, "states", attribute_fields())?,
+            balances: ParquetTable::create(
+                dir,
+                "balances",
+                vec![
+                    Field::new("component_id", DataType::Utf8, false),
+                    Field::new("token", DataType::Binary, false),
+                    Field::new("balance", DataType::Binary, false),
+                ],
+            )?,
+            summary: ExportSummary::default(),
+        })
+    }
+
+    fn write_page(
+        &mut self,
+        components: &[ProtocolComponent],
+        states: &[ProtocolComponentState],
+    ) -> Result<(), ExportError> {
+        let mut ids = StringBuilder::new();
+        let mut systems = StringBuilder::new();
+        let mut types = StringBuilder::new();
+        let mut chains = StringBuilder::new();
+        let mut tokens = ListBuilder::new(BinaryBuilder::new());
+        let mut contracts = ListBuilder::new(BinaryBuilder::new());
+        let mut creation_txs = BinaryBuilder::new();
+        let mut created_at = TimestampMicrosecondBuilder::new();
+        let mut static_attributes = AttributeRows::default();
+        for component in components {
+            ids.append_value(&component.id);
+            systems.append_value(&component.protocol_system);
+            types.append_value(&component.protocol_type_name);
+            chains.append_value(component.chain.to_string());
+            for token in &component.tokens {
+                tokens.values().append_value(token);
+            }
+            tokens.append(true);
+            for contract in &component.contract_addresses {
+                contracts
+                    .values()
+                    .append_value(contract);
+            }
+            contracts.append(true);
+            creation_txs.append_value(&component.creation_tx);
+            created_at.append_value(
+                component
+                    .created_at
+                    .and_utc()
+                    .timestamp_micros(),
+            );
+            static_attributes.extend(&component.id, &component.static_attributes);
+        }
+        self.components.write(vec![
+            Arc::new(ids.finish()),
+            Arc::new(systems.finish()),
+            Arc::new(types.finish()),
+            Arc::new(chains.finish()),
+            Arc::new(tokens.finish()),
+            Arc::new(contracts.finish()),
+            Arc::new(creation_txs.finish()),
+            Arc::new(created_at.finish()),
+        ])?;
+        self.summary.components += components.len();
+        self.summary.static_attributes += static_attributes.len;
+        self.static_attributes
+            .write(static_attributes.finish())?;
+
+        let mut attributes = AttributeRows::default();
+        let mut balance_ids = StringBuilder::new();
+        let mut balance_tokens = BinaryBuilder::new();
+        let mut balances = BinaryBuilder::new();
+        let mut n_balances = 0;
+        for state in states {
+            attributes.extend(&state.component_id, &state.attributes);
+            let mut state_balances = state
+                .balances
+                .iter()
+                .collect::<Vec<_>>();
+            state_balances.sort_unstable();
+            for (token, balance) in state_balances {
+                balance_ids.append_value(&state.component_id);
+                balance_tokens.append_value(token);
+                balances.append_value(balance);
+                n_balances += 1;
+            }
+        }
+        self.summary.states += attributes.len;
+        self.states.write(attributes.finish())?;
+        self.summary.balances += n_balances;
+        self.balances.write(vec![
+            Arc::new(balance_ids.finish()),
+            Arc::new(balance_tokens.finish()),
+            Arc::new(balances.finish()),
+        ])
+    }
+
+    fn finish(self) -> Result<ExportSummary, ExportError> {
+        self.components.close()?;
+        self.static_attributes.close()?;
+        self.states.close()?;
+        self.balances.close()?;
+        Ok(self.summary)
+    }
+}
+
+/// Columns of an attribute file, attributes of a component are sorted by name.
+#[derive(Default)]
+struct AttributeRows {
+    component_ids: StringBuilder,
+    attributes: StringBuilder,
+    values: BinaryBuilder,
+    len: usize,
+}
+
+impl AttributeRows {
+    fn extend<'a>(
+        &mut self,
+        component_id: &str,
+        attributes: impl IntoIterator<Item = (&'a String, &'a tycho_common::Bytes)>,
+    ) {
+        let mut attributes = attributes
+            .into_iter()
+            .collect::<Vec<_>>();
+        attributes.sort_unstable();
+        for (attribute, value) in attributes {
+            self.component_ids
+                .append_value(component_id);
+            self.attributes.append_value(attribute);
+            self.values.append_value(value);
+            self.len += 1;
+        }
+    }
+
+    fn finish(mut self) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(self.component_ids.finish()),
+            Arc::new(self.attributes.finish()),
+            Arc::new(self.values.finish()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, slice};
+
+    use chrono::NaiveDateTime;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use tycho_common::{models::ChangeType, Bytes};
+
+    use super::*;
+
+    fn num_rows(dir: &Path, name: &str) -> i64 {
+        let file = File::open(dir.join(format!("{name}.parquet"))).unwrap();
+        SerializedFileReader::new(file)
+            .unwrap()
+            .metadata()
+            .file_metadata()
+            .num_rows()
+    }
+
+    #[test]
+    fn test_write_parquet_snapshot() {
+        let dir = std::env::temp_dir().join(format!("tycho-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let component = ProtocolComponent::new(
+            "pool_1",
+            "uniswap_v2",
+            "uniswap_v2_pool",
+            Chain::Ethereum,
+            vec![Bytes::from("0x01"), Bytes::from("0x02")],
+            vec![Bytes::from("0x03")],
+            HashMap::from([("fee".to_string(), Bytes::from("0x1e"))]),
+            ChangeType::Creation,
+            Bytes::from("0x04"),
+            NaiveDateTime::default(),
+        );
+        let state = ProtocolComponentState::new(
+            "pool_1",
+            HashMap::from([
+                ("reserve0".to_string(), Bytes::from("0x0a")),
+                ("reserve1".to_string(), Bytes::from("0x0b")),
+            ]),
+            HashMap::from([(Bytes::from("0x01"), Bytes::from("0x0a"))]),
+        );
+
+        let mut writer = ParquetSnapshotWriter::create(&dir).unwrap();
+        writer
+            .write_page(slice::from_ref(&component), slice::from_ref(&state))
+            .unwrap();
+        writer
+            .write_page(&[component], &[state])
+            .unwrap();
+        let summary = writer.finish().unwrap();
+
+        assert_eq!(
+            summary,
+            ExportSummary {
+                block_number: 0,
+                components: 2,
+                static_attributes: 2,
+                states: 4,
+                balances: 2
+            }
+        );
+        assert_eq!(num_rows(&dir, "components"), 2);
+        assert_eq!(num_rows(&dir, "static_attributes"), 2);
+        assert_eq!(num_rows(&dir, "states"), 4);
+        assert_eq!(num_rows(&dir, "balances"), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+//! Opt-in extraction of raw event logs emitted by protocol components.
+//!
+//! Substreams packages can attach the logs relevant to their components to the `BlockChanges`
+//! message, grouped by transaction, under field 100. `tycho_substreams::BlockChanges` does not know
+//! this field and skips it, so the logs are decoded from the same bytes with the overlay messages
+//! below. Packages that don't emit logs are unaffected.
+//!
+//! Logs are numerous, so they are only kept for the event signatures (and optionally the emitting
+//! contracts) an extractor is configured with.
+use std::collections::HashSet;
+
+use prost::Message;
+use serde::Deserialize;
+use tracing::debug;
+use tycho_common::{
+    models::{protocol::ComponentEvent, Address, TxHash},
+    Bytes,
+};
+
+use crate::extractor::{models::BlockChanges, ExtractionError};
+
+/// Overlay of `BlockChanges` that only decodes the component logs.
+#[derive(Clone, PartialEq, Message)]
+pub struct BlockComponentEvents {
+    #[prost(message, repeated, tag = "100")]
+    pub component_events: Vec<TransactionComponentEvents>,
+}
+
+/// Logs emitted within a single transaction.
+#[derive(Clone, PartialEq, Message)]
+pub struct TransactionComponentEvents {
+    #[prost(bytes = "vec", tag = "1")]
+    pub tx_hash: Vec<u8>,
+    #[prost(message, repeated, tag = "2")]
+    pub events: Vec<ComponentEventLog>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ComponentEventLog {
+    #[prost(string, tag = "1")]
+    pub component_id: String,
+    /// Index of the log within the block.
+    #[prost(uint32, tag = "2")]
+    pub log_index: u32,
+    #[prost(bytes = "vec", tag = "3")]
+    pub address: Vec<u8>,
+    #[prost(bytes = "vec", repeated, tag = "4")]
+    pub topics: Vec<Vec<u8>>,
+    #[prost(bytes = "vec", tag = "5")]
+    pub data: Vec<u8>,
+}
+
+/// Inclusion filters for component event logs.
+///
+/// Extraction is disabled unless at least one topic is configured. A log is stored if its first
+/// topic is configured and, if any addresses are configured, it was emitted by one of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ComponentEventsConfig {
+    #[serde(default)]
+    pub topics: HashSet<Bytes>,
+    #[serde(default)]
+    pub addresses: HashSet<Address>,
+}
+
+impl ComponentEventsConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.topics.is_empty()
+    }
+
+    pub fn accepts(&self, event: &ComponentEvent) -> bool {
+        event
+            .signature()
+            .is_some_and(|topic| self.topics.contains(topic)) &&
+            (self.addresses.is_empty() || self.addresses.contains(&event.address))
+    }
+
+    /// Decodes the accepted logs from a raw `BlockChanges` message.
+    ///
+    /// Logs of transactions without any other change in `changes` are dropped, as those
+    /// transactions are not stored.
+    pub fn decode(
+        &self,
+        data: &[u8],
+        changes: &BlockChanges,
+    ) -> Result<Vec<ComponentEvent>, ExtractionError> {
+        if !self.is_enabled() {
+            return Ok(Vec::new());
+        }
+
+        let stored_txs: HashSet<&TxHash> = changes
+            .txs_with_update
+            .iter()
+            .map(|tx| &tx.tx.hash)
+            .collect();
+        let msg = BlockComponentEvents::decode(data)?;
+
+        let mut events = Vec::new();
+        for tx in msg.component_events {
+            let tx_hash = TxHash::from(tx.tx_hash);
+            if !stored_txs.contains(&tx_hash) {
+                debug!(%tx_hash, n_events = tx.events.len(), "Dropping logs of unchanged tx");
+                continue;
+            }
+            events.extend(
+                tx.events
+                    .into_iter()
+                    .map(|log| {
+                        ComponentEvent::new(
+                            &log.component_id,
+                            tx_hash.clone(),
+                            log.log_index,
+                            log.address.into(),
+                            log.topics
+                                .into_iter()
+                                .map(Bytes::from)
+                                .collect(),
+                            log.data.into(),
+                        )
+                    })
+                    .filter(|event| self.accepts(event)),
+            );
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tycho_common::models::{
+        blockchain::{Block, Transaction, TxWithChanges},
+        Chain,
+    };
+
+    use super::*;
+
+    const SWAP: &str = "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67";
+    const SYNC: &str = "0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1";
+
+    fn log(component_id: &str, log_index: u32, address: &str, topic: &str) -> ComponentEventLog {
+        ComponentEventLog {
+            component_id: component_id.to_string(),
+            log_index,
+            address: Bytes::from(address).to_vec(),
+            topics: vec![Bytes::from(topic).to_vec(), vec![0u8; 32]],
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let config = ComponentEventsConfig {
+            topics: HashSet::from([Bytes::from(SWAP)]),
+            addresses: HashSet::from([Bytes::from("0x01")]),
+        };
+        let raw = BlockComponentEvents {
+            component_events: vec![
+                TransactionComponentEvents {
+                    tx_hash: vec![0xaa],
+                    events: vec![
+                        log("pool_a", 0, "0x01", SWAP),
+                        log("pool_a", 1, "0x01", SYNC),
+                        log("pool_b", 2, "0x02", SWAP),
+                    ],
+                },
+                TransactionComponentEvents {
+                    tx_hash: vec![0xbb],
+                    events: vec![log("pool_a", 3, "0x01", SWAP)],
+                },
+            ],
+        }
+        .encode_to_vec();
+        let changes = BlockChanges::new(
+            "test".to_string(),
+            Chain::Ethereum,
+            Block::default(),
+            0,
+            false,
+            vec![TxWithChanges {
+                tx: Transaction { hash: Bytes::from("0xaa"), ..Default::default() },
+                ..Default::default()
+            }],
+            Vec::new(),
+        );
+
+        let res = config.decode(&raw, &changes).unwrap();
+
+        assert_eq!(
+            res,
+            vec![ComponentEvent::new(
+                "pool_a",
+                Bytes::from("0xaa"),
+                0,
+                Bytes::from("0x01"),
+                vec![Bytes::from(SWAP), Bytes::from(vec![0u8; 32])],
+                Bytes::from(vec![1, 2, 3]),
+            )]
+        );
+        assert!(ComponentEventsConfig::default()
+            .decode(&raw, &changes)
+            .unwrap()
+            .is_empty());
+    }
+}
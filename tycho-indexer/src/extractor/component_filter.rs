@@ -0,0 +1,415 @@
+//! Extraction-time filtering of protocol components.
+//!
+//! Operators that only care about a subset of the components emitted by a substreams package can
+//! configure allow and deny lists per extractor. The filter is applied right after a message is
+//! decoded, so skipped components and everything scoped to them (state updates, balances, entry
+//! points and event logs) never reach the reorg buffer or the database.
+//!
+//! Account level changes (contract storage, code and native balances) are not filtered, since
+//! contracts may be shared across multiple components.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use tycho_common::{
+    dto,
+    models::{protocol::ProtocolComponent, Address, ComponentId},
+};
+
+use crate::extractor::models::BlockChanges;
+
+/// Allow and deny lists for protocol components.
+///
+/// A component is rejected if its id is denied or if any of its tokens is denied. If any allow list
+/// is set, a component is only accepted if its id is allowed or if at least one of its tokens is
+/// allowed. An empty filter accepts everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ComponentFilter {
+    #[serde(default)]
+    pub allowed_ids: HashSet<ComponentId>,
+    #[serde(default)]
+    pub denied_ids: HashSet<ComponentId>,
+    #[serde(default)]
+    pub allowed_tokens: HashSet<Address>,
+    #[serde(default)]
+    pub denied_tokens: HashSet<Address>,
+}
+
+/// Number of entities dropped by a single [`ComponentFilter::apply`] call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilteredCounts {
+    pub components: usize,
+    pub state_updates: usize,
+    pub balance_changes: usize,
+    pub entrypoints: usize,
+    pub events: usize,
+}
+
+impl FilteredCounts {
+    pub fn is_empty(&self) -> bool {
+        self.components == 0 &&
+            self.state_updates == 0 &&
+            self.balance_changes == 0 &&
+            self.entrypoints == 0 &&
+            self.events == 0
+    }
+}
+
+impl ComponentFilter {
+    /// Returns true if the filter has no effect.
+    pub fn is_empty(&self) -> bool {
+        self.allowed_ids.is_empty() &&
+            self.denied_ids.is_empty() &&
+            self.allowed_tokens.is_empty() &&
+            self.denied_tokens.is_empty()
+    }
+
+    pub fn accepts(&self, component: &ProtocolComponent) -> bool {
+        if self.denied_ids.contains(&component.id) ||
+            component
+                .tokens
+                .iter()
+                .any(|t| self.denied_tokens.contains(t))
+        {
+            return false;
+        }
+
+        if self.allowed_ids.is_empty() && self.allowed_tokens.is_empty() {
+            return true;
+        }
+
+        self.allowed_ids.contains(&component.id) ||
+            component
+                .tokens
+                .iter()
+                .any(|t| self.allowed_tokens.contains(t))
+    }
+
+    /// Ids of components that receive updates in `changes` but are not created within it.
+    ///
+    /// The caller is expected to decide on these, e.g. by resolving them through the protocol
+    /// cache, and pass the decisions to [`ComponentFilter::apply`].
+    pub fn referenced_component_ids(&self, changes: &BlockChanges) -> Vec<ComponentId> {
+        let created: HashSet<&ComponentId> = changes
+            .txs_with_update
+            .iter()
+            .flat_map(|tx| tx.protocol_components.keys())
+            .collect();
+
+        let referenced: HashSet<&ComponentId> = changes
+            .txs_with_update
+            .iter()
+            .flat_map(|tx| {
+                tx.state_updates
+                    .keys()
+                    .chain(tx.balance_changes.keys())
+                    .chain(tx.entrypoints.keys())
+                    .chain(
+                        tx.entrypoint_params
+                            .values()
+                            .flatten()
+                            .filter_map(|(_, component_id)| component_id.as_ref()),
+                    )
+            })
+            .chain(
+                changes
+                    .component_events
+                    .iter()
+                    .map(|event| &event.component_id),
+            )
+            .filter(|id| !created.contains(id))
+            .collect();
+
+        referenced
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Removes rejected components and all component scoped changes from `changes`.
+    ///
+    /// `decisions` must hold whether the components referenced by `changes` are accepted. Updates
+    /// for components that are neither created within `changes` nor accepted in `decisions` are
+    /// dropped, as they were skipped when they were created. The decisions about components created
+    /// within `changes` are added to `decisions`.
+    pub fn apply(
+        &self,
+        changes: &mut BlockChanges,
+        decisions: &mut HashMap<ComponentId, bool>,
+    ) -> FilteredCounts {
+        let mut counts = FilteredCounts::default();
+
+        let mut accepted: HashSet<ComponentId> = decisions
+            .iter()
+            .filter(|(_, accepted)| **accepted)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for tx in changes.txs_with_update.iter_mut() {
+            let n_before = tx.protocol_components.len();
+            tx.protocol_components.retain(|id, c| {
+                let accepts = self.accepts(c);
+                decisions.insert(id.clone(), accepts);
+                accepts
+            });
+            counts.components += n_before - tx.protocol_components.len();
+            accepted.extend(tx.protocol_components.keys().cloned());
+        }
+
+        for tx in changes.txs_with_update.iter_mut() {
+            let n_before = tx.state_updates.len();
+            tx.state_updates
+                .retain(|id, _| accepted.contains(id));
+            counts.state_updates += n_before - tx.state_updates.len();
+
+            let n_before = tx.balance_changes.len();
+            tx.balance_changes
+                .retain(|id, _| accepted.contains(id));
+            counts.balance_changes += n_before - tx.balance_changes.len();
+
+            let n_before = tx.entrypoints.len();
+            tx.entrypoints
+                .retain(|id, _| accepted.contains(id));
+            counts.entrypoints += n_before - tx.entrypoints.len();
+
+            tx.entrypoint_params
+                .retain(|_, params| {
+                    params.retain(|(_, component_id)| {
+                        component_id
+                            .as_ref()
+                            .is_none_or(|id| accepted.contains(id))
+                    });
+                    !params.is_empty()
+                });
+        }
+
+        let n_before = changes.component_events.len();
+        changes
+            .component_events
+            .retain(|event| accepted.contains(&event.component_id));
+        counts.events = n_before - changes.component_events.len();
+
+        counts
+    }
+}
+
+/// A filter together with its decisions about the components seen so far.
+///
+/// Rejected components are never stored, so without remembering the decision their ids would be
+/// looked up again on every block that still emits updates for them.
+#[derive(Debug, Default)]
+pub struct FilterState {
+    filter: Arc<ComponentFilter>,
+    decisions: HashMap<ComponentId, bool>,
+}
+
+impl FilterState {
+    pub fn new(filter: ComponentFilter) -> Self {
+        Self { filter: Arc::new(filter), decisions: HashMap::new() }
+    }
+
+    pub fn filter(&self) -> Arc<ComponentFilter> {
+        self.filter.clone()
+    }
+
+    /// Splits `ids` into the known decisions and the ids that still need to be decided on.
+    pub fn lookup(&self, ids: Vec<ComponentId>) -> (HashMap<ComponentId, bool>, Vec<ComponentId>) {
+        let mut known = HashMap::new();
+        let mut unknown = Vec::new();
+        for id in ids {
+            match self.decisions.get(&id) {
+                Some(accepted) => {
+                    known.insert(id, *accepted);
+                }
+                None => unknown.push(id),
+            }
+        }
+        (known, unknown)
+    }
+
+    /// Remembers decisions taken with `filter`. They are discarded if the filter was replaced
+    /// since.
+    pub fn record(&mut self, filter: &Arc<ComponentFilter>, decisions: HashMap<ComponentId, bool>) {
+        if Arc::ptr_eq(&self.filter, filter) {
+            self.decisions.extend(decisions);
+        }
+    }
+
+    /// Forgets all decisions, e.g. after a revert removed components they were taken for.
+    pub fn clear_decisions(&mut self) {
+        self.decisions.clear();
+    }
+}
+
+impl From<dto::ComponentFilterRequestBody> for ComponentFilter {
+    fn from(value: dto::ComponentFilterRequestBody) -> Self {
+        Self {
+            allowed_ids: value.allowed_ids.into_iter().collect(),
+            denied_ids: value.denied_ids.into_iter().collect(),
+            allowed_tokens: value
+                .allowed_tokens
+                .into_iter()
+                .collect(),
+            denied_tokens: value
+                .denied_tokens
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tycho_common::{
+        models::{
+            blockchain::{Block, TxWithChanges},
+            protocol::{ComponentEvent, ProtocolComponentStateDelta},
+            Chain,
+        },
+        Bytes,
+    };
+
+    use super::*;
+
+    fn component(id: &str, tokens: &[&str]) -> ProtocolComponent {
+        ProtocolComponent {
+            id: id.to_string(),
+            tokens: tokens
+                .iter()
+                .map(|t| Bytes::from(*t))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn state_update(id: &str) -> (ComponentId, ProtocolComponentStateDelta) {
+        (id.to_string(), ProtocolComponentStateDelta::new(id, HashMap::new(), HashSet::new()))
+    }
+
+    #[test]
+    fn test_accepts() {
+        let filter = ComponentFilter {
+            allowed_tokens: HashSet::from([Bytes::from("0x01")]),
+            denied_ids: HashSet::from(["pool_b".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(filter.accepts(&component("pool_a", &["0x01", "0x02"])));
+        assert!(!filter.accepts(&component("pool_b", &["0x01", "0x02"])));
+        assert!(!filter.accepts(&component("pool_c", &["0x02", "0x03"])));
+        assert!(ComponentFilter::default().accepts(&component("pool_c", &["0x02"])));
+    }
+
+    #[test]
+    fn test_apply() {
+        let filter = ComponentFilter {
+            denied_tokens: HashSet::from([Bytes::from("0x03")]),
+            ..Default::default()
+        };
+        let tx = TxWithChanges {
+            protocol_components: HashMap::from([
+                ("pool_a".to_string(), component("pool_a", &["0x01", "0x02"])),
+                ("pool_b".to_string(), component("pool_b", &["0x02", "0x03"])),
+            ]),
+            state_updates: HashMap::from([
+                state_update("pool_a"),
+                state_update("pool_b"),
+                state_update("pool_c"),
+                state_update("pool_d"),
+            ]),
+            ..Default::default()
+        };
+        let mut changes = BlockChanges::new(
+            "test".to_string(),
+            Chain::Ethereum,
+            Block::default(),
+            0,
+            false,
+            vec![tx],
+            Vec::new(),
+        );
+        changes.component_events = ["pool_a", "pool_b"]
+            .into_iter()
+            .map(|id| {
+                ComponentEvent::new(
+                    id,
+                    Bytes::default(),
+                    0,
+                    Bytes::default(),
+                    vec![],
+                    Bytes::default(),
+                )
+            })
+            .collect();
+        let mut decisions = HashMap::from([("pool_c".to_string(), true)]);
+
+        let mut referenced = filter.referenced_component_ids(&changes);
+        referenced.sort();
+        assert_eq!(referenced, vec!["pool_c".to_string(), "pool_d".to_string()]);
+
+        let counts = filter.apply(&mut changes, &mut decisions);
+
+        assert_eq!(
+            counts,
+            FilteredCounts { components: 1, state_updates: 2, events: 1, ..Default::default() }
+        );
+        let tx = &changes.txs_with_update[0];
+        assert_eq!(
+            tx.protocol_components
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["pool_a"]
+        );
+        let mut updated = tx
+            .state_updates
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        updated.sort();
+        assert_eq!(updated, vec!["pool_a".to_string(), "pool_c".to_string()]);
+        assert_eq!(changes.component_events.len(), 1);
+        assert_eq!(changes.component_events[0].component_id, "pool_a");
+        assert_eq!(
+            decisions,
+            HashMap::from([
+                ("pool_a".to_string(), true),
+                ("pool_b".to_string(), false),
+                ("pool_c".to_string(), true),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_filter_state() {
+        let mut state = FilterState::new(ComponentFilter {
+            denied_ids: HashSet::from(["pool_b".to_string()]),
+            ..Default::default()
+        });
+        let filter = state.filter();
+        state.record(
+            &filter,
+            HashMap::from([("pool_a".to_string(), true), ("pool_b".to_string(), false)]),
+        );
+
+        let (known, unknown) =
+            state.lookup(vec!["pool_a".to_string(), "pool_b".to_string(), "pool_c".to_string()]);
+
+        assert_eq!(
+            known,
+            HashMap::from([("pool_a".to_string(), true), ("pool_b".to_string(), false)])
+        );
+        assert_eq!(unknown, vec!["pool_c".to_string()]);
+
+        // Decisions of a replaced filter are stale.
+        let mut replaced = FilterState::new(ComponentFilter::default());
+        replaced.record(&filter, HashMap::from([("pool_b".to_string(), false)]));
+        assert_eq!(
+            replaced
+                .lookup(vec!["pool_b".to_string()])
+                .1,
+            vec!["pool_b".to_string()]
+        );
+    }
+}
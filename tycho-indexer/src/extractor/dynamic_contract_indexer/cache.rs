@@ -409,6 +409,7 @@ mod tests {
             parent_hash: BlockHash::from(parent_hash),
             ts: NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
             chain: Chain::Ethereum,
+            extra: Default::default(),
         }
     }
 
@@ -0,0 +1,143 @@
+//! [`ExtractorGateway`] decorator injecting storage faults, to test how extractors recover.
+//!
+//! Faults are configured with a [`FaultConfig`], each method counts as the operation of its own
+//! name, e.g. `advance`. Only available in tests and with the `fault-injection` feature, which
+//! also enables faults in the write cache, see `GatewayBuilder::set_fault_injector`.
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use tycho_common::{
+    models::{
+        blockchain::{Block, QuarantinedMessage},
+        contract::{Account, AccountBalance},
+        protocol::{ComponentBalance, ProtocolComponentState},
+        Address, ProtocolType, SubstreamsPackage,
+    },
+    storage::StorageError,
+    Bytes,
+};
+pub use tycho_storage::postgres::fault_injection::{FaultConfig, FaultInjector};
+
+use crate::extractor::{models::BlockChanges, protocol_extractor::ExtractorGateway};
+
+pub struct FaultInjectingGateway<G> {
+    inner: G,
+    injector: Arc<FaultInjector>,
+}
+
+impl<G> FaultInjectingGateway<G> {
+    pub fn new(inner: G, config: FaultConfig) -> Self {
+        Self { inner, injector: Arc::new(FaultInjector::new(config)) }
+    }
+
+    /// The injector deciding on the faults, to inspect the calls and faults seen so far.
+    pub fn injector(&self) -> Arc<FaultInjector> {
+        self.injector.clone()
+    }
+}
+
+#[async_trait]
+impl<G: ExtractorGateway> ExtractorGateway for FaultInjectingGateway<G> {
+    async fn get_cursor(&self) -> Result<(Vec<u8>, Bytes), StorageError> {
+        self.injector
+            .inject("get_cursor")
+            .await?;
+        self.inner.get_cursor().await
+    }
+
+    /// Never fails since the method is infallible, only delays are applied.
+    async fn ensure_protocol_types(&self, new_protocol_types: &[ProtocolType]) {
+        let _ = self
+            .injector
+            .inject("ensure_protocol_types")
+            .await;
+        self.inner
+            .ensure_protocol_types(new_protocol_types)
+            .await
+    }
+
+    async fn advance(
+        &self,
+        changes: &BlockChanges,
+        new_cursor: &str,
+        force_commit: bool,
+    ) -> Result<(), StorageError> {
+        self.injector.inject("advance").await?;
+        self.inner
+            .advance(changes, new_cursor, force_commit)
+            .await
+    }
+
+    async fn get_protocol_states<'a>(
+        &self,
+        component_ids: &[&'a str],
+    ) -> Result<Vec<ProtocolComponentState>, StorageError> {
+        self.injector
+            .inject("get_protocol_states")
+            .await?;
+        self.inner
+            .get_protocol_states(component_ids)
+            .await
+    }
+
+    async fn get_contracts(&self, addresses: &[Address]) -> Result<Vec<Account>, StorageError> {
+        self.injector
+            .inject("get_contracts")
+            .await?;
+        self.inner
+            .get_contracts(addresses)
+            .await
+    }
+
+    async fn get_components_balances<'a>(
+        &self,
+        component_ids: &[&'a str],
+    ) -> Result<HashMap<String, HashMap<Bytes, ComponentBalance>>, StorageError> {
+        self.injector
+            .inject("get_components_balances")
+            .await?;
+        self.inner
+            .get_components_balances(component_ids)
+            .await
+    }
+
+    async fn get_block(&self, block_number: Bytes) -> Result<Block, StorageError> {
+        self.injector
+            .inject("get_block")
+            .await?;
+        self.inner.get_block(block_number).await
+    }
+
+    async fn get_account_balances(
+        &self,
+        accounts: &[Address],
+    ) -> Result<HashMap<Address, HashMap<Address, AccountBalance>>, StorageError> {
+        self.injector
+            .inject("get_account_balances")
+            .await?;
+        self.inner
+            .get_account_balances(accounts)
+            .await
+    }
+
+    async fn record_substreams_package(
+        &self,
+        package: SubstreamsPackage,
+    ) -> Result<(), StorageError> {
+        self.injector
+            .inject("record_substreams_package")
+            .await?;
+        self.inner
+            .record_substreams_package(package)
+            .await
+    }
+
+    async fn quarantine_message(&self, message: &QuarantinedMessage) -> Result<(), StorageError> {
+        self.injector
+            .inject("quarantine_message")
+            .await?;
+        self.inner
+            .quarantine_message(message)
+            .await
+    }
+}
@@ -0,0 +1,166 @@
+//! Fills in blocks a substreams package did not emit.
+//!
+//! Packages may skip blocks without relevant changes. The next emitted block then doesn't extend
+//! the last processed one, which fails the parent hash check of the reorg buffer and leaves holes
+//! in the stored block history. The healer fetches the headers of the skipped blocks from a node,
+//! the extractor then stores them like any other block without changes.
+use std::sync::Arc;
+
+use futures03::future::try_join_all;
+use metrics::counter;
+use serde::Deserialize;
+use tracing::info;
+use tycho_common::{
+    models::{blockchain::Block, Chain},
+    traits::BlockHeaderFetcher,
+};
+
+use crate::extractor::ExtractionError;
+
+/// Healing of block gaps for an extractor whose package skips blocks.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct BlockGapHealingConfig {
+    /// Largest number of consecutive missing blocks that is filled in, larger gaps fail the
+    /// extractor.
+    #[serde(default = "default_max_gap")]
+    pub max_gap: u64,
+}
+
+fn default_max_gap() -> u64 {
+    256
+}
+
+impl Default for BlockGapHealingConfig {
+    fn default() -> Self {
+        Self { max_gap: default_max_gap() }
+    }
+}
+
+pub struct BlockGapHealer {
+    chain: Chain,
+    fetcher: Arc<dyn BlockHeaderFetcher>,
+    max_gap: u64,
+}
+
+impl BlockGapHealer {
+    pub fn new(chain: Chain, fetcher: Arc<dyn BlockHeaderFetcher>, max_gap: u64) -> Self {
+        Self { chain, fetcher, max_gap }
+    }
+
+    /// Returns the headers of the blocks between `last` and `next`, ordered by ascending number.
+    ///
+    /// Nothing is fetched if `next` extends `last` or doesn't come after it, the reorg buffer
+    /// deals with the latter. Fails if the gap exceeds the configured maximum or the fetched
+    /// headers don't link `last` to `next`, e.g. because the node is on another fork.
+    pub async fn heal(&self, last: &Block, next: &Block) -> Result<Vec<Block>, ExtractionError> {
+        if next.parent_hash == last.hash || next.number <= last.number + 1 {
+            return Ok(Vec::new());
+        }
+        let gap = next.number - last.number - 1;
+        if gap > self.max_gap {
+            return Err(ExtractionError::ReorgBufferError(format!(
+                "Gap of {gap} blocks between {} and {} exceeds the healing limit of {}",
+                last.number, next.number, self.max_gap
+            )));
+        }
+
+        let headers = try_join_all((last.number + 1..next.number).map(|number| {
+            self.fetcher
+                .get_block_header(self.chain, number)
+        }))
+        .await
+        .map_err(|err| {
+            ExtractionError::ServiceError(format!("Failed to fetch missing block header: {err}"))
+        })?;
+
+        let mut parent = last;
+        for header in headers.iter().chain([next]) {
+            if header.parent_hash != parent.hash {
+                return Err(ExtractionError::ReorgBufferError(format!(
+                    "Block {} with parent {} doesn't extend block {} with hash {}",
+                    header.number, header.parent_hash, parent.number, parent.hash
+                )));
+            }
+            parent = header;
+        }
+
+        info!(
+            chain = %self.chain,
+            from = last.number + 1,
+            to = next.number - 1,
+            "Healed block gap"
+        );
+        counter!("extractor_healed_blocks", "chain" => self.chain.to_string()).increment(gap);
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDateTime;
+    use tycho_common::{traits::MockBlockHeaderFetcher, Bytes};
+
+    use super::*;
+
+    fn block(number: u64) -> Block {
+        Block::new(
+            number,
+            Chain::Ethereum,
+            Bytes::from(number).lpad(32, 0),
+            Bytes::from(number - 1).lpad(32, 0),
+            NaiveDateTime::default(),
+        )
+    }
+
+    fn healer(max_gap: u64) -> BlockGapHealer {
+        let mut fetcher = MockBlockHeaderFetcher::new();
+        fetcher
+            .expect_get_block_header()
+            .returning(|_, number| Ok(block(number)));
+        BlockGapHealer::new(Chain::Ethereum, Arc::new(fetcher), max_gap)
+    }
+
+    #[tokio::test]
+    async fn test_heal_gap() {
+        let res = healer(10)
+            .heal(&block(1), &block(5))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.iter()
+                .map(|b| b.number)
+                .collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_heal_no_gap() {
+        let res = healer(10)
+            .heal(&block(1), &block(2))
+            .await
+            .unwrap();
+
+        assert!(res.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_heal_gap_too_large() {
+        let res = healer(2)
+            .heal(&block(1), &block(5))
+            .await;
+
+        assert!(matches!(res, Err(ExtractionError::ReorgBufferError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_heal_other_fork() {
+        let mut next = block(5);
+        next.parent_hash = Bytes::from("0xff").lpad(32, 0);
+
+        let res = healer(10).heal(&block(1), &next).await;
+
+        assert!(matches!(res, Err(ExtractionError::ReorgBufferError(_))));
+    }
+}
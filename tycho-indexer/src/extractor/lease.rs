@@ -0,0 +1,110 @@
+//! Exclusive ownership of an extractor identity.
+//!
+//! Running the same extractor (name and chain) twice against one database would interleave the
+//! writes of both processes. Before an extractor starts it therefore acquires a lease on its
+//! identity and keeps extending it from a separate task while running, so slow blocks can't delay
+//! the heartbeat. A second instance refuses to start until the lease was released or expired.
+//! Each commit of the extractor additionally verifies the lease is still held.
+use std::{sync::Arc, time::Duration};
+
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+use tracing::{info, warn};
+use tycho_common::{models::ExtractorIdentity, storage::StorageError};
+use tycho_storage::postgres::cache::CachedGateway;
+use uuid::Uuid;
+
+use crate::extractor::ExtractionError;
+
+/// Time after which a lease without heartbeat may be taken over by another instance.
+pub const DEFAULT_LEASE_TTL: Duration = Duration::from_secs(60);
+
+pub struct ExtractorLease {
+    gateway: CachedGateway,
+    id: ExtractorIdentity,
+    instance_id: String,
+    ttl: Duration,
+}
+
+impl ExtractorLease {
+    /// Acquires the lease on `id` or fails if another live instance holds it.
+    pub async fn acquire(
+        gateway: CachedGateway,
+        id: ExtractorIdentity,
+        ttl: Duration,
+    ) -> Result<Self, ExtractionError> {
+        let lease = Self { gateway, id, instance_id: instance_id(), ttl };
+        lease
+            .gateway
+            .acquire_extractor_lease(
+                &lease.id.name,
+                &lease.id.chain,
+                &lease.instance_id,
+                lease.lease_ttl(),
+            )
+            .await
+            .map_err(|err| match err {
+                StorageError::DuplicateEntry(..) => ExtractionError::Setup(format!(
+                    "Extractor {} is already running in another instance: {err}",
+                    lease.id
+                )),
+                err => ExtractionError::Storage(err),
+            })?;
+        Ok(lease)
+    }
+
+    /// Interval at which the lease should be renewed, leaves room for a few missed heartbeats.
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.ttl / 3
+    }
+
+    /// Extends the lease, fails if it has been taken over by another instance.
+    pub async fn renew(&self) -> Result<(), ExtractionError> {
+        self.gateway
+            .renew_extractor_lease(
+                &self.id.name,
+                &self.id.chain,
+                &self.instance_id,
+                self.lease_ttl(),
+            )
+            .await
+            .map_err(ExtractionError::Storage)
+    }
+
+    /// Renews the lease every heartbeat interval on a separate task.
+    ///
+    /// The task only ends once a renewal failed and returns the error it failed with.
+    pub fn spawn_heartbeat(self: Arc<Self>) -> JoinHandle<ExtractionError> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.heartbeat_interval());
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(err) = self.renew().await {
+                    return err;
+                }
+            }
+        })
+    }
+
+    /// Releases the lease so a replacement instance can start right away.
+    pub async fn release(&self) {
+        match self
+            .gateway
+            .release_extractor_lease(&self.id.name, &self.id.chain, &self.instance_id)
+            .await
+        {
+            Ok(()) => info!(extractor_id = %self.id, "Released extractor lease"),
+            Err(err) => warn!(extractor_id = %self.id, error = %err, "Failed to release lease"),
+        }
+    }
+
+    fn lease_ttl(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.ttl).expect("lease ttl out of range")
+    }
+}
+
+/// Identifies this process across hosts and restarts.
+fn instance_id() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_string());
+    format!("{host}-{}-{}", std::process::id(), Uuid::new_v4().simple())
+}
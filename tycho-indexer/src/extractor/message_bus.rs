@@ -0,0 +1,343 @@
+//! Broadcast bus carrying extractor messages to service consumers.
+//!
+//! Subscribing through the [`ExtractorHandle`](super::runner::ExtractorHandle) gives every
+//! subscriber a dedicated channel that the extractor awaits on each message, so a single slow
+//! consumer stalls indexing. The bus instead publishes into a bounded broadcast channel per
+//! extractor: publishing never blocks, and subscribers that fall more than the channel capacity
+//! behind skip the oldest messages. Skipped messages are logged and counted per extractor, and
+//! reported to subscribers: lossy subscriptions learn how many messages they missed, plain
+//! subscriptions end so the subscriber can resync.
+//!
+//! Consumers that must see every message, like the pending deltas buffer, should keep using the
+//! extractor handle.
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use metrics::{counter, gauge};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc::{self, error::SendError, Receiver},
+    watch,
+};
+use tracing::{debug, info_span, trace, warn, Instrument};
+use tycho_common::models::ExtractorIdentity;
+
+use crate::extractor::{
+    runner::{ControlMessage, MessageSender},
+    ExtractorMsg, TransactionSubscribers,
+};
+
+/// Default number of messages buffered per extractor.
+pub const DEFAULT_BUS_CAPACITY: usize = 128;
+
+#[derive(Clone)]
+struct ExtractorChannel {
+    tx: broadcast::Sender<ExtractorMsg>,
+    latest: Arc<watch::Sender<Option<ExtractorMsg>>>,
+    transaction_subscribers: TransactionSubscribers,
+}
+
+/// Registry of broadcast channels, keyed by extractor.
+pub struct MessageBus {
+    capacity: usize,
+    channels: RwLock<HashMap<ExtractorIdentity, ExtractorChannel>>,
+}
+
+impl Default for MessageBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUS_CAPACITY)
+    }
+}
+
+impl MessageBus {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, channels: RwLock::new(HashMap::new()) }
+    }
+
+    fn channel(&self, id: &ExtractorIdentity) -> ExtractorChannel {
+        if let Some(channel) = self
+            .channels
+            .read()
+            .expect("message bus lock poisoned")
+            .get(id)
+        {
+            return channel.clone();
+        }
+
+        self.channels
+            .write()
+            .expect("message bus lock poisoned")
+            .entry(id.clone())
+            .or_insert_with(|| {
+                let (tx, _) = broadcast::channel(self.capacity);
+                let (latest, _) = watch::channel(None);
+                ExtractorChannel {
+                    tx,
+                    latest: Arc::new(latest),
+                    transaction_subscribers: TransactionSubscribers::default(),
+                }
+            })
+            .clone()
+    }
+
+    /// Returns the publishing side of the extractor's channel, creating it if necessary.
+    pub fn publisher(&self, id: ExtractorIdentity) -> BusPublisher {
+        let channel = self.channel(&id);
+        BusPublisher { id, channel }
+    }
+
+    /// Returns a subscriber for the extractor's channel, creating it if necessary.
+    ///
+    /// Subscribing before the extractor started publishing is fine, the subscriber simply won't
+    /// receive anything until then.
+    pub fn subscriber(&self, id: ExtractorIdentity) -> BusSubscriber {
+        let channel = self.channel(&id);
+        BusSubscriber { id, channel }
+    }
+
+    /// Returns the transaction granularity subscribers of the extractor's channel, the extractor
+    /// needs these to know whether to keep the per transaction changes.
+    pub fn transaction_subscribers(&self, id: &ExtractorIdentity) -> TransactionSubscribers {
+        self.channel(id).transaction_subscribers
+    }
+}
+
+/// Publishes messages of a single extractor to the bus.
+#[derive(Clone)]
+pub struct BusPublisher {
+    id: ExtractorIdentity,
+    channel: ExtractorChannel,
+}
+
+impl BusPublisher {
+    /// Publishes a message without waiting on subscribers.
+    pub fn publish(&self, msg: ExtractorMsg) {
+        self.channel
+            .latest
+            .send_replace(Some(msg.clone()));
+        match self.channel.tx.send(msg) {
+            Ok(n_subscribers) => {
+                trace!(extractor_id = %self.id, n_subscribers, "Published message to bus.");
+            }
+            Err(_) => {
+                trace!(extractor_id = %self.id, "No active bus subscribers.");
+            }
+        }
+        gauge!(
+            "message_bus_backlog",
+            "chain" => self.id.chain.to_string(),
+            "extractor" => self.id.name.to_string()
+        )
+        .set(self.channel.tx.len() as f64);
+    }
+}
+
+/// Subscribes to messages of a single extractor on the bus.
+#[derive(Clone)]
+pub struct BusSubscriber {
+    id: ExtractorIdentity,
+    channel: ExtractorChannel,
+}
+
+impl BusSubscriber {
+    /// The most recently published message, if any.
+    pub fn latest(&self) -> Option<ExtractorMsg> {
+        self.channel.latest.borrow().clone()
+    }
+
+    /// Forwards bus messages into `tx` until either side is closed, together with the number of
+    /// messages skipped before each of them.
+    async fn forward(
+        id: ExtractorIdentity,
+        mut rx: broadcast::Receiver<ExtractorMsg>,
+        tx: mpsc::Sender<(u64, ExtractorMsg)>,
+    ) {
+        let mut skipped_before = 0;
+        loop {
+            match rx.recv().await {
+                Ok(msg) => {
+                    if tx
+                        .send((mem::take(&mut skipped_before), msg))
+                        .await
+                        .is_err()
+                    {
+                        debug!("Bus subscriber dropped.");
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Bus subscriber lagging, messages were dropped.");
+                    counter!(
+                        "message_bus_dropped_messages",
+                        "chain" => id.chain.to_string(),
+                        "extractor" => id.name.to_string()
+                    )
+                    .increment(skipped);
+                    skipped_before += skipped;
+                }
+                Err(RecvError::Closed) => {
+                    debug!("Bus channel closed.");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MessageSender for BusSubscriber {
+    /// Subscribes to every message, the subscription ends once the subscriber skipped any.
+    async fn subscribe(&self) -> Result<Receiver<ExtractorMsg>, SendError<ControlMessage>> {
+        let mut lossy_rx = self.subscribe_lossy().await?;
+        let (tx, rx) = mpsc::channel(1);
+        tokio::spawn(
+            async move {
+                while let Some((skipped, msg)) = lossy_rx.recv().await {
+                    if skipped > 0 {
+                        warn!(skipped, "Ending bus subscription that skipped messages.");
+                        return;
+                    }
+                    if tx.send(msg).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            .instrument(info_span!("bus_subscriber", extractor_id = %self.id)),
+        );
+        Ok(rx)
+    }
+
+    async fn subscribe_lossy(
+        &self,
+    ) -> Result<Receiver<(u64, ExtractorMsg)>, SendError<ControlMessage>> {
+        let (tx, rx) = mpsc::channel(16);
+        let bus_rx = self.channel.tx.subscribe();
+        tokio::spawn(
+            Self::forward(self.id.clone(), bus_rx, tx)
+                .instrument(info_span!("bus_subscriber", extractor_id = %self.id)),
+        );
+        Ok(rx)
+    }
+
+    fn transaction_subscribers(&self) -> Option<TransactionSubscribers> {
+        Some(
+            self.channel
+                .transaction_subscribers
+                .clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tycho_common::models::{blockchain::BlockAggregatedChanges, Chain};
+
+    use super::*;
+
+    fn msg(block_number: u64) -> ExtractorMsg {
+        let mut changes = BlockAggregatedChanges::default();
+        changes.block.number = block_number;
+        Arc::new(changes)
+    }
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let bus = MessageBus::new(4);
+        let id = ExtractorIdentity::new(Chain::Ethereum, "test");
+        let publisher = bus.publisher(id.clone());
+        let subscriber = bus.subscriber(id.clone());
+        let mut rx = subscriber.subscribe().await.unwrap();
+
+        publisher.publish(msg(1));
+        publisher.publish(msg(2));
+
+        assert_eq!(rx.recv().await.unwrap().block.number, 1);
+        assert_eq!(rx.recv().await.unwrap().block.number, 2);
+        assert_eq!(
+            subscriber
+                .latest()
+                .map(|m| m.block.number),
+            Some(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscribers_learn_about_skipped_messages() {
+        let bus = MessageBus::new(2);
+        let id = ExtractorIdentity::new(Chain::Ethereum, "test");
+        let publisher = bus.publisher(id.clone());
+        let subscriber = bus.subscriber(id);
+        let mut lossy_rx = subscriber
+            .subscribe_lossy()
+            .await
+            .unwrap();
+        let mut rx = subscriber.subscribe().await.unwrap();
+
+        // The forwarding tasks only run once the test yields, so both subscribers lag.
+        for block_number in 0..5 {
+            publisher.publish(msg(block_number));
+        }
+
+        let (skipped, first) = lossy_rx.recv().await.unwrap();
+        assert_eq!((skipped, first.block.number), (3, 3));
+        let (skipped, second) = lossy_rx.recv().await.unwrap();
+        assert_eq!((skipped, second.block.number), (0, 4));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_does_not_block_publisher() {
+        let bus = MessageBus::new(2);
+        let id = ExtractorIdentity::new(Chain::Ethereum, "test");
+        let publisher = bus.publisher(id.clone());
+        let mut bus_rx = bus
+            .subscriber(id)
+            .channel
+            .tx
+            .subscribe();
+
+        for block_number in 0..5 {
+            publisher.publish(msg(block_number));
+        }
+
+        assert!(matches!(bus_rx.recv().await, Err(RecvError::Lagged(3))));
+        assert_eq!(
+            bus_rx
+                .recv()
+                .await
+                .unwrap()
+                .block
+                .number,
+            3
+        );
+        assert_eq!(
+            bus_rx
+                .recv()
+                .await
+                .unwrap()
+                .block
+                .number,
+            4
+        );
+    }
+
+    #[test]
+    fn test_transaction_subscribers_are_shared_with_the_extractor() {
+        let bus = MessageBus::default();
+        let id = ExtractorIdentity::new(Chain::Ethereum, "test");
+        let extractor_subscribers = bus.transaction_subscribers(&id);
+        let subscriber_subscribers = bus
+            .subscriber(id)
+            .transaction_subscribers()
+            .unwrap();
+
+        let guard = subscriber_subscribers.register();
+        assert!(extractor_subscribers.any());
+        drop(guard);
+        assert!(!extractor_subscribers.any());
+    }
+}
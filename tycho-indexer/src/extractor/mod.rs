@@ -1,15 +1,22 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use async_trait::async_trait;
 use mockall::automock;
 use prost::DecodeError;
+use serde::Deserialize;
 use thiserror::Error;
 use tycho_common::{
     models::{
         blockchain::{Block, BlockAggregatedChanges, BlockScoped},
         contract::AccountBalance,
         protocol::ComponentBalance,
-        Address, BlockHash, ExtractorIdentity, MergeError,
+        Address, BlockHash, ExtractorIdentity, MergeError, SubstreamsPackage,
     },
     storage::StorageError,
     Bytes,
@@ -17,6 +24,7 @@ use tycho_common::{
 
 use crate::{
     extractor::{
+        component_filter::ComponentFilter,
         dynamic_contract_indexer::cache::DCICacheError,
         models::BlockChanges,
         reorg_buffer::{
@@ -28,23 +36,36 @@ use crate::{
 };
 
 pub mod chain_state;
+pub mod component_events;
+pub mod component_filter;
 mod dynamic_contract_indexer;
+#[cfg(any(test, feature = "fault-injection"))]
+pub mod fault_injection;
+pub mod gap_healer;
+pub mod lease;
+pub mod message_bus;
 pub mod models;
 pub mod post_processors;
+pub mod profiling;
 pub mod protobuf_deserialisation;
 pub mod protocol_cache;
 pub mod protocol_extractor;
 pub mod reorg_buffer;
+pub mod repair;
 pub mod runner;
+pub mod simulation;
 pub mod token_analysis_cron;
-mod u256_num;
+pub mod token_supply_cron;
+pub mod tracked_contracts;
+pub(crate) mod u256_num;
+pub mod utils;
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ExtractionError {
     #[error("Extractor setup failed: {0}")]
     Setup(String),
     #[error("Failed to decode: {0}")]
-    DecodeError(String),
+    DecodeError(#[from] MessageDecodeError),
     #[error("Protobuf error: {0}")]
     ProtobufError(#[from] DecodeError),
     #[error("Can't decode an empty message")]
@@ -69,6 +90,64 @@ pub enum ExtractionError {
     DCICacheError(#[from] DCICacheError),
 }
 
+/// Reasons a substreams message could not be decoded into the internal models.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum MessageDecodeError {
+    #[error("missing field: {0}")]
+    MissingField(String),
+    #[error("invalid length: {0}")]
+    InvalidLength(String),
+    #[error("invalid enum value: {0}")]
+    InvalidEnum(String),
+    #[error("value overflow: {0}")]
+    Overflow(String),
+    #[error("invalid value: {0}")]
+    InvalidValue(String),
+}
+
+/// How the extractor reacts to a block message that failed to decode.
+///
+/// Every failure stops the extractor unless its kind is explicitly configured to be skipped or
+/// quarantined, since the block's changes are missing from the indexed state afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodeFailureHandling {
+    /// Drop the block's changes and continue with the next block.
+    Skip,
+    /// Store the undecoded message for a later replay and continue with the next block.
+    Quarantine,
+    /// Stop the extractor.
+    #[default]
+    Stop,
+}
+
+impl DecodeFailureHandling {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Skip => "skip",
+            Self::Quarantine => "quarantine",
+            Self::Stop => "stop",
+        }
+    }
+}
+
+impl MessageDecodeError {
+    /// Short names of all variants, see [`Self::kind`].
+    pub const KINDS: [&'static str; 5] =
+        ["missing_field", "invalid_length", "invalid_enum", "overflow", "invalid_value"];
+
+    /// Short name of the variant, used in logs, metrics and the extractor config.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::MissingField(_) => "missing_field",
+            Self::InvalidLength(_) => "invalid_length",
+            Self::InvalidEnum(_) => "invalid_enum",
+            Self::Overflow(_) => "overflow",
+            Self::InvalidValue(_) => "invalid_value",
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum RPCError {
     #[error("RPC setup error: {0}")]
@@ -79,6 +158,35 @@ pub enum RPCError {
 
 pub type ExtractorMsg = Arc<BlockAggregatedChanges>;
 
+/// Counts the subscribers that receive the changes of an extractor per transaction.
+///
+/// Extractors only attach the per transaction breakdown to their messages while there is at least
+/// one such subscriber, since it duplicates all changes of a block.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionSubscribers(Arc<AtomicUsize>);
+
+impl TransactionSubscribers {
+    pub fn any(&self) -> bool {
+        self.0.load(Ordering::Relaxed) > 0
+    }
+
+    /// Registers a subscriber until the returned guard is dropped.
+    pub fn register(&self) -> TransactionSubscriberGuard {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        TransactionSubscriberGuard(self.0.clone())
+    }
+}
+
+/// Keeps a per transaction subscriber registered, see [`TransactionSubscribers::register`].
+#[derive(Debug)]
+pub struct TransactionSubscriberGuard(Arc<AtomicUsize>);
+
+impl Drop for TransactionSubscriberGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 #[automock]
 #[async_trait]
 pub trait Extractor: Send + Sync {
@@ -101,6 +209,16 @@ pub trait Extractor: Send + Sync {
     ) -> Result<Option<ExtractorMsg>, ExtractionError>;
 
     async fn handle_progress(&self, inp: ModulesProgress) -> Result<(), ExtractionError>;
+
+    /// Replaces the component allow/deny lists applied to incoming messages.
+    async fn set_component_filter(&self, filter: ComponentFilter);
+
+    /// Records the substreams package the extractor streams from, called before streaming
+    /// starts.
+    async fn record_substreams_package(
+        &self,
+        package: SubstreamsPackage,
+    ) -> Result<(), ExtractionError>;
 }
 
 #[automock]
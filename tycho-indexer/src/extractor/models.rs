@@ -8,7 +8,7 @@ use tycho_common::{
             Transaction, TxWithChanges,
         },
         contract::{AccountBalance, AccountChangesWithTx},
-        protocol::{ComponentBalance, ProtocolChangesWithTx, ProtocolComponent},
+        protocol::{ComponentBalance, ComponentEvent, ProtocolChangesWithTx, ProtocolComponent},
         token::Token,
         AccountToContractStore, Address, AttrStoreKey, Chain, ComponentId,
     },
@@ -159,6 +159,9 @@ pub struct BlockChanges {
     /// finalized.
     /// Populated by the `DynamicContractIndexer`
     pub trace_results: Vec<TracedEntryPoint>,
+    /// Event logs of the configured signatures, only populated if component event extraction is
+    /// enabled.
+    pub component_events: Vec<ComponentEvent>,
 }
 
 impl BlockChanges {
@@ -181,6 +184,7 @@ impl BlockChanges {
             txs_with_update,
             block_storage_changes,
             trace_results: Vec::new(),
+            component_events: Vec::new(),
         }
     }
 
@@ -193,12 +197,27 @@ impl BlockChanges {
     /// also aggregated into a result per entry point.
     ///
     /// Note - all non-protocol specific data in the BlockChanges object are lost during
-    /// aggregation. This means block_storage_changes is dropped.
+    /// aggregation. This means block_storage_changes is dropped, as are the changes per
+    /// transaction, see [`Self::aggregate_updates_with_txs`] to keep them.
     ///
     /// # Errors
     ///
     /// This returns an `ExtractionError` if there was a problem during merge.
     pub fn aggregate_updates(self) -> Result<BlockAggregatedChanges, ExtractionError> {
+        self.aggregate(false)
+    }
+
+    /// Aggregates updates like [`Self::aggregate_updates`], but additionally keeps the changes
+    /// grouped by transaction.
+    ///
+    /// This copies all changes of the block, only use it if a consumer needs the per transaction
+    /// breakdown.
+    pub fn aggregate_updates_with_txs(self) -> Result<BlockAggregatedChanges, ExtractionError> {
+        self.aggregate(true)
+    }
+
+    fn aggregate(self, keep_txs: bool) -> Result<BlockAggregatedChanges, ExtractionError> {
+        let txs_with_update = if keep_txs { self.txs_with_update.clone() } else { Vec::new() };
         let mut iter = self.txs_with_update.into_iter();
 
         // Use unwrap_or_else to provide a default state if iter.next() is None
@@ -243,6 +262,8 @@ impl BlockChanges {
                 new_entrypoint_params: aggregated_changes.entrypoint_params,
                 trace_results: aggregated_trace_results,
             },
+            txs_with_update,
+            heartbeat: false,
         })
     }
 
@@ -380,6 +401,7 @@ impl From<BlockContractChanges> for BlockChanges {
                 .collect(),
             block_storage_changes: Vec::new(),
             trace_results: Vec::new(),
+            component_events: Vec::new(),
         }
     }
 }
@@ -400,6 +422,7 @@ impl From<BlockEntityChanges> for BlockChanges {
                 .collect(),
             block_storage_changes: Vec::new(),
             trace_results: Vec::new(),
+            component_events: Vec::new(),
         }
     }
 }
@@ -443,6 +466,7 @@ pub mod fixtures {
                 txs_with_update,
                 block_storage_changes: Vec::new(),
                 trace_results: Vec::new(),
+                component_events: Vec::new(),
             }
         }
     }
@@ -0,0 +1,163 @@
+//! Lightweight per-stage timing of block processing.
+//!
+//! The extractor records how long each stage took for every processed block. Only a rolling window
+//! of the most recent blocks is kept, which is enough to tell where a slow protocol spends its time
+//! without attaching a profiler.
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tycho_common::{dto, models::ExtractorIdentity};
+
+/// Default number of blocks kept per stage.
+pub const DEFAULT_PROFILE_WINDOW: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Decoding the substreams message into block changes.
+    Decode,
+    /// Post processing, filtering, DCI and token checks.
+    Validate,
+    /// Reorg buffer insertion, database commits and cursor updates.
+    Persist,
+    /// Aggregating the block into the outgoing message.
+    Emit,
+}
+
+impl Stage {
+    const ALL: [Stage; 4] = [Stage::Decode, Stage::Validate, Stage::Persist, Stage::Emit];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Validate => "validate",
+            Stage::Persist => "persist",
+            Stage::Emit => "emit",
+        }
+    }
+}
+
+/// Keeps the durations of the last `window` blocks for each stage.
+#[derive(Debug)]
+pub struct StageProfiler {
+    id: ExtractorIdentity,
+    window: usize,
+    samples: Mutex<HashMap<Stage, VecDeque<Duration>>>,
+}
+
+impl StageProfiler {
+    pub fn new(id: ExtractorIdentity, window: usize) -> Self {
+        Self { id, window, samples: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn record(&self, stage: Stage, duration: Duration) {
+        let mut samples = self
+            .samples
+            .lock()
+            .expect("profiler lock poisoned");
+        let stage_samples = samples
+            .entry(stage)
+            .or_insert_with(|| VecDeque::with_capacity(self.window));
+        if stage_samples.len() == self.window {
+            stage_samples.pop_front();
+        }
+        stage_samples.push_back(duration);
+    }
+
+    /// Records the time elapsed since `start` and returns a new start instant for the next stage.
+    pub fn lap(&self, stage: Stage, start: Instant) -> Instant {
+        let now = Instant::now();
+        self.record(stage, now.duration_since(start));
+        now
+    }
+
+    pub fn snapshot(&self) -> dto::ExtractorProfile {
+        let samples = self
+            .samples
+            .lock()
+            .expect("profiler lock poisoned");
+        let stages = Stage::ALL
+            .iter()
+            .map(|stage| {
+                let mut durations: Vec<f64> = samples
+                    .get(stage)
+                    .map(|s| {
+                        s.iter()
+                            .map(|d| d.as_micros() as f64 / 1000.0)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                durations.sort_by(f64::total_cmp);
+                let mean_ms = if durations.is_empty() {
+                    0.0
+                } else {
+                    durations.iter().sum::<f64>() / durations.len() as f64
+                };
+                dto::StageProfile {
+                    stage: stage.as_str().to_string(),
+                    samples: durations.len(),
+                    mean_ms,
+                    p50_ms: percentile(&durations, 50),
+                    p90_ms: percentile(&durations, 90),
+                    p99_ms: percentile(&durations, 99),
+                    max_ms: durations
+                        .last()
+                        .copied()
+                        .unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        dto::ExtractorProfile {
+            chain: self.id.chain.into(),
+            extractor: self.id.name.clone(),
+            window: self.window,
+            stages,
+        }
+    }
+}
+
+/// Nearest rank percentile of sorted values.
+fn percentile(sorted: &[f64], p: usize) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use tycho_common::models::Chain;
+
+    use super::*;
+
+    #[test]
+    fn test_rolling_window() {
+        let profiler = StageProfiler::new(ExtractorIdentity::new(Chain::Ethereum, "test"), 10);
+
+        for ms in 1..=20 {
+            profiler.record(Stage::Persist, Duration::from_millis(ms));
+        }
+
+        let profile = profiler.snapshot();
+        let persist = profile
+            .stages
+            .iter()
+            .find(|s| s.stage == "persist")
+            .unwrap();
+        assert_eq!(persist.samples, 10);
+        assert_eq!(persist.mean_ms, 15.5);
+        assert_eq!(persist.p50_ms, 15.0);
+        assert_eq!(persist.p90_ms, 19.0);
+        assert_eq!(persist.max_ms, 20.0);
+        let decode = profile
+            .stages
+            .iter()
+            .find(|s| s.stage == "decode")
+            .unwrap();
+        assert_eq!(decode.samples, 0);
+    }
+}
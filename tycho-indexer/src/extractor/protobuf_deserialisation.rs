@@ -2,11 +2,13 @@
 use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 use chrono::NaiveDateTime;
+use rayon::prelude::*;
 use tracing::warn;
 use tycho_common::{
     models::{
         blockchain::{
-            Block, EntryPoint, RPCTracerParams, TracingParams, Transaction, TxWithChanges,
+            Block, BlockExtra, EntryPoint, RPCTracerParams, TracingParams, Transaction,
+            TxWithChanges,
         },
         contract::{AccountBalance, AccountChangesWithTx, AccountDelta},
         protocol::{
@@ -21,7 +23,7 @@ use tycho_substreams::pb::tycho::evm::v1 as substreams;
 use crate::extractor::{
     models::{BlockChanges, BlockContractChanges, BlockEntityChanges, TxWithStorageChanges},
     u256_num::bytes_to_f64,
-    ExtractionError,
+    ExtractionError, MessageDecodeError,
 };
 
 pub trait TryFromMessage {
@@ -53,6 +55,43 @@ impl TryFromMessage for AccountDelta {
     }
 }
 
+/// Transactions changing at least this many slots have their contract changes decoded on the rayon
+/// pool. Below it, the overhead of distributing the work outweighs the gain.
+const PARALLEL_DECODE_MIN_SLOTS: usize = 1024;
+
+/// Decodes the contract changes of a transaction into account deltas keyed by address.
+///
+/// Blocks touching protocols like Ambient contain tens of thousands of slots, so large
+/// transactions are decoded in parallel. The deltas are inserted in message order: if an address
+/// appears twice the later change wins, exactly as with serial decoding.
+fn decode_account_deltas(
+    changes: &[substreams::ContractChange],
+    chain: Chain,
+) -> Result<HashMap<Address, AccountDelta>, ExtractionError> {
+    let decode = |change: &substreams::ContractChange| {
+        AccountDelta::try_from_message((change.clone(), chain))
+    };
+    let n_slots: usize = changes
+        .iter()
+        .map(|change| change.slots.len())
+        .sum();
+    let deltas = if n_slots >= PARALLEL_DECODE_MIN_SLOTS {
+        changes
+            .par_iter()
+            .map(decode)
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        changes
+            .iter()
+            .map(decode)
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    Ok(deltas
+        .into_iter()
+        .map(|delta| (delta.address.clone(), delta))
+        .collect())
+}
+
 impl TryFromMessage for AccountBalance {
     type Args<'a> = (substreams::AccountBalanceChange, &'a Address, &'a Transaction);
 
@@ -80,11 +119,14 @@ impl TryFromMessage for Block {
             hash: msg.hash.into(),
             parent_hash: msg.parent_hash.into(),
             ts: NaiveDateTime::from_timestamp_opt(msg.ts as i64, 0).ok_or_else(|| {
-                ExtractionError::DecodeError(format!(
+                MessageDecodeError::Overflow(format!(
                     "Failed to convert timestamp {} to datetime!",
                     msg.ts
                 ))
+                .into()
             })?,
+            // The tycho.evm.v1 block message carries no header fields beyond these yet.
+            extra: BlockExtra::default(),
         })
     }
 }
@@ -112,6 +154,14 @@ impl TryFromMessage for ComponentBalance {
 
     fn try_from_message(args: Self::Args<'_>) -> Result<Self, ExtractionError> {
         let (msg, tx) = args;
+        if msg.balance.len() > 32 {
+            return Err(MessageDecodeError::InvalidLength(format!(
+                "Balance of token 0x{} has {} bytes, expected at most 32",
+                hex::encode(&msg.token),
+                msg.balance.len()
+            ))
+            .into());
+        }
         let balance_float = bytes_to_f64(&msg.balance).unwrap_or(f64::NAN);
         Ok(Self {
             token: msg.token.into(),
@@ -119,7 +169,7 @@ impl TryFromMessage for ComponentBalance {
             balance_float,
             modify_tx: tx.hash.clone(),
             component_id: String::from_utf8(msg.component_id)
-                .map_err(|error| ExtractionError::DecodeError(error.to_string()))?,
+                .map_err(|error| MessageDecodeError::InvalidValue(error.to_string()))?,
         })
     }
 }
@@ -136,12 +186,15 @@ impl TryFromMessage for ProtocolComponent {
 
     fn try_from_message(args: Self::Args<'_>) -> Result<Self, ExtractionError> {
         let (msg, chain, protocol_system, protocol_types, tx_hash, creation_ts) = args;
-        let tokens: Vec<Bytes> = msg
+        // Tokens are kept in their canonical order, by address, the order they are read from the
+        // db in.
+        let mut tokens: Vec<Bytes> = msg
             .tokens
             .clone()
             .into_iter()
             .map(Into::into)
             .collect();
+        tokens.sort();
 
         let contract_ids = msg
             .contracts
@@ -160,13 +213,14 @@ impl TryFromMessage for ProtocolComponent {
         let protocol_type = msg
             .protocol_type
             .clone()
-            .ok_or(ExtractionError::DecodeError("Missing protocol type".to_owned()))?;
+            .ok_or(MessageDecodeError::MissingField("Missing protocol type".to_owned()))?;
 
         if !protocol_types.contains_key(&protocol_type.name) {
-            return Err(ExtractionError::DecodeError(format!(
+            return Err(MessageDecodeError::InvalidEnum(format!(
                 "Unknown protocol type name: {}",
                 protocol_type.name
-            )));
+            ))
+            .into());
         }
 
         Ok(Self {
@@ -192,9 +246,10 @@ impl TryFromMessage for ChangeType {
             substreams::ChangeType::Creation => Ok(ChangeType::Creation),
             substreams::ChangeType::Update => Ok(ChangeType::Update),
             substreams::ChangeType::Deletion => Ok(ChangeType::Deletion),
-            substreams::ChangeType::Unspecified => Err(ExtractionError::DecodeError(format!(
+            substreams::ChangeType::Unspecified => Err(MessageDecodeError::InvalidEnum(format!(
                 "Unknown ChangeType enum member encountered: {args:?}"
-            ))),
+            ))
+            .into()),
         }
     }
 }
@@ -242,7 +297,7 @@ impl TryFromMessage for TracingParams {
     fn try_from_message(args: Self::Args<'_>) -> Result<Self, ExtractionError> {
         let msg = args;
         let trace_data = msg.trace_data.ok_or_else(|| {
-            ExtractionError::DecodeError("Missing trace data in EntryPointParams".to_owned())
+            MessageDecodeError::MissingField("Missing trace data in EntryPointParams".to_owned())
         })?;
 
         match trace_data {
@@ -347,7 +402,6 @@ impl TryFromMessage for TxWithChanges {
         ))?;
 
         let mut new_protocol_components: HashMap<ComponentId, ProtocolComponent> = HashMap::new();
-        let mut account_updates: HashMap<Address, AccountDelta> = HashMap::new();
         let mut state_updates: HashMap<ComponentId, ProtocolComponentStateDelta> = HashMap::new();
         let mut balance_changes: HashMap<ComponentId, HashMap<Address, ComponentBalance>> =
             HashMap::new();
@@ -373,10 +427,7 @@ impl TryFromMessage for TxWithChanges {
         }
 
         // Parse the account updates
-        for contract_change in msg.contract_changes.clone().into_iter() {
-            let update = AccountDelta::try_from_message((contract_change, block.chain))?;
-            account_updates.insert(update.address.clone(), update);
-        }
+        let account_updates = decode_account_deltas(&msg.contract_changes, block.chain)?;
 
         // Parse the state updates
         for state_msg in msg.entity_changes.into_iter() {
@@ -397,7 +448,7 @@ impl TryFromMessage for TxWithChanges {
         // Parse the component balance changes
         for balance_change in msg.balance_changes.into_iter() {
             let component_id = String::from_utf8(balance_change.component_id.clone())
-                .map_err(|error| ExtractionError::DecodeError(error.to_string()))?;
+                .map_err(|error| MessageDecodeError::InvalidValue(error.to_string()))?;
             let token_address = Bytes::from(balance_change.token.clone());
             let balance = ComponentBalance::try_from_message((balance_change, &tx))?;
 
@@ -481,7 +532,6 @@ impl TryFromMessage for BlockContractChanges {
             let mut tx_updates = Vec::new();
 
             for change in msg.changes.into_iter() {
-                let mut account_updates = HashMap::new();
                 let mut protocol_components = HashMap::new();
                 let mut balances_changes: HashMap<ComponentId, HashMap<Address, ComponentBalance>> =
                     HashMap::new();
@@ -492,14 +542,7 @@ impl TryFromMessage for BlockContractChanges {
 
                 if let Some(tx) = change.tx {
                     let tx = Transaction::try_from_message((tx, &block.hash.clone()))?;
-                    for contract_change in change
-                        .contract_changes
-                        .clone()
-                        .into_iter()
-                    {
-                        let update = AccountDelta::try_from_message((contract_change, chain))?;
-                        account_updates.insert(update.address.clone(), update);
-                    }
+                    let account_updates = decode_account_deltas(&change.contract_changes, chain)?;
                     for component_msg in change.component_changes.into_iter() {
                         let component = ProtocolComponent::try_from_message((
                             component_msg,
@@ -514,9 +557,10 @@ impl TryFromMessage for BlockContractChanges {
 
                     // parse the balance changes
                     for balance_change in change.balance_changes.into_iter() {
-                        let component_id =
-                            String::from_utf8(balance_change.component_id.clone())
-                                .map_err(|error| ExtractionError::DecodeError(error.to_string()))?;
+                        let component_id = String::from_utf8(balance_change.component_id.clone())
+                            .map_err(|error| {
+                            MessageDecodeError::InvalidValue(error.to_string())
+                        })?;
                         let token_address = balance_change.token.clone().into();
                         let balance = ComponentBalance::try_from_message((balance_change, &tx))?;
 
@@ -591,7 +635,7 @@ impl TryFromMessage for BlockEntityChanges {
                 .into_iter()
                 .map(|change| {
                     change.tx.as_ref().ok_or_else(|| {
-                        ExtractionError::DecodeError(
+                        MessageDecodeError::MissingField(
                             "TransactionEntityChanges misses a transaction".to_owned(),
                         )
                     })?;
@@ -657,12 +701,13 @@ impl TryFromMessage for BlockChanges {
         if let Some(block) = msg.block {
             let block = Block::try_from_message((block, chain))?;
 
+            // Transactions are decoded in parallel, the indexed collect keeps message order.
             let txs_with_update = msg
                 .changes
-                .into_iter()
+                .into_par_iter()
                 .map(|change| {
                     change.tx.as_ref().ok_or_else(|| {
-                        ExtractionError::DecodeError(
+                        MessageDecodeError::MissingField(
                             "TransactionEntityChanges misses a transaction".to_owned(),
                         )
                     })?;
@@ -819,6 +864,99 @@ mod test {
         assert_eq!(protocol_component.static_attributes, expected_attribute_map);
     }
 
+    #[test]
+    fn test_parse_encoded_protocol_component() {
+        use prost::Message;
+
+        let msg = fixtures::pb_protocol_component();
+        let encoded = msg.encode_to_vec();
+        let decoded =
+            substreams::ProtocolComponent::decode(encoded.as_slice()).expect("valid message");
+        assert_eq!(decoded, msg);
+
+        let protocol_types = HashMap::from([("WeightedPool".to_string(), ProtocolType::default())]);
+        let tx_hash = Bytes::from(vec![1; 32]);
+        let from_decoded = ProtocolComponent::try_from_message((
+            decoded,
+            Chain::Ethereum,
+            "ambient",
+            &protocol_types,
+            tx_hash.clone(),
+            Default::default(),
+        ))
+        .expect("valid component");
+        let from_msg = ProtocolComponent::try_from_message((
+            msg,
+            Chain::Ethereum,
+            "ambient",
+            &protocol_types,
+            tx_hash,
+            Default::default(),
+        ))
+        .expect("valid component");
+
+        assert_eq!(from_decoded, from_msg);
+        assert_eq!(
+            from_decoded.static_attributes["factory_address"],
+            Bytes::from(b"0x0fwe0g240g20".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_parse_protocol_component_orders_tokens() {
+        let weth = Bytes::from_str("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+        let usdc = Bytes::from_str("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap();
+        let mut msg = fixtures::pb_protocol_component();
+        msg.tokens = vec![weth.to_vec(), usdc.to_vec()];
+        let protocol_types = HashMap::from([("WeightedPool".to_string(), ProtocolType::default())]);
+
+        let component = ProtocolComponent::try_from_message((
+            msg,
+            Chain::Ethereum,
+            "ambient",
+            &protocol_types,
+            Bytes::default(),
+            Default::default(),
+        ))
+        .expect("valid component");
+
+        assert_eq!(component.tokens, vec![usdc, weth]);
+    }
+
+    #[rstest]
+    #[case::missing_type(
+        None,
+        MessageDecodeError::MissingField("Missing protocol type".to_string())
+    )]
+    #[case::unknown_type(
+        Some("UnknownPool"),
+        MessageDecodeError::InvalidEnum("Unknown protocol type name: UnknownPool".to_string())
+    )]
+    fn test_parse_protocol_component_invalid_type(
+        #[case] type_name: Option<&str>,
+        #[case] exp_err: MessageDecodeError,
+    ) {
+        let mut msg = fixtures::pb_protocol_component();
+        msg.protocol_type = type_name.map(|name| substreams::ProtocolType {
+            name: name.to_string(),
+            financial_type: 0,
+            attribute_schema: vec![],
+            implementation_type: 0,
+        });
+        let protocol_types = HashMap::from([("WeightedPool".to_string(), ProtocolType::default())]);
+
+        let res = ProtocolComponent::try_from_message((
+            msg,
+            Chain::Ethereum,
+            "ambient",
+            &protocol_types,
+            Bytes::default(),
+            Default::default(),
+        ));
+
+        assert_eq!(res, Err(ExtractionError::DecodeError(exp_err)));
+    }
+
     pub fn transaction() -> Transaction {
         create_transaction(
             "0000000000000000000000000000000000000000000000000000000011121314",
@@ -853,6 +991,52 @@ mod test {
         assert_eq!(from_message.component_id, expected_component_id);
     }
 
+    #[rstest]
+    #[case::serial(10)]
+    #[case::parallel(PARALLEL_DECODE_MIN_SLOTS)]
+    fn test_decode_account_deltas_keeps_last_change(#[case] n_slots: usize) {
+        let change = |value: u8| substreams::ContractChange {
+            address: vec![0xaa; 20],
+            change: substreams::ChangeType::Update.into(),
+            slots: (0..n_slots)
+                .map(|i| substreams::ContractSlot {
+                    slot: (i as u64).to_be_bytes().to_vec(),
+                    value: vec![value],
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let other = substreams::ContractChange { address: vec![0xbb; 20], ..change(3) };
+
+        let res = decode_account_deltas(&[change(1), other, change(2)], Chain::Ethereum).unwrap();
+
+        assert_eq!(res.len(), 2);
+        let delta = &res[&Bytes::from(vec![0xaa; 20])];
+        assert_eq!(delta.slots.len(), n_slots);
+        assert!(delta
+            .slots
+            .values()
+            .all(|v| v == &Some(Bytes::from(vec![2]))));
+    }
+
+    #[test]
+    fn test_parse_component_balance_invalid_length() {
+        let tx = transaction();
+        let msg = substreams::BalanceChange {
+            balance: vec![1u8; 33],
+            token: vec![0xaa; 20],
+            component_id: b"pool".to_vec(),
+        };
+
+        let res = ComponentBalance::try_from_message((msg, &tx));
+
+        assert!(matches!(
+            res,
+            Err(ExtractionError::DecodeError(MessageDecodeError::InvalidLength(_)))
+        ));
+    }
+
     #[test]
     fn test_parse_block_contract_changes() {
         let msg = fixtures::pb_block_contract_changes(0);
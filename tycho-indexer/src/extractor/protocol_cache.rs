@@ -1,14 +1,20 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
 use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
 use tycho_common::{
     models::{
         protocol::{ProtocolComponent, QualityRange},
         token::Token,
-        Address, Chain, ComponentId,
+        Address, Chain, ComponentId, PaginationParams,
     },
     storage::{ProtocolGateway, StorageError},
     Bytes,
@@ -57,6 +63,31 @@ pub struct ProtocolMemoryCache {
     gateway: Arc<dyn ProtocolGateway + Send + Sync>,
 }
 
+/// Where and how often a [`ProtocolMemoryCache`] is snapshotted.
+#[derive(Debug, Clone)]
+pub struct CacheSnapshotConfig {
+    pub path: PathBuf,
+    /// Pause between writes of the snapshot.
+    pub interval: std::time::Duration,
+    /// Snapshots written longer ago are ignored at startup.
+    pub max_age: chrono::Duration,
+}
+
+/// Tokens and components of a [`ProtocolMemoryCache`] as written to a snapshot file.
+///
+/// Only this cache is snapshotted, other caches like the head state or component balances are
+/// still loaded from the db at startup. Prices are left out, they go stale quickly and are cheap to
+/// load.
+#[derive(Serialize, Deserialize)]
+struct CacheSnapshot {
+    chain: Chain,
+    written_at: NaiveDateTime,
+    /// When tokens or components were last modified in the db before the snapshot was taken.
+    data_modified_at: Option<NaiveDateTime>,
+    tokens: Vec<Token>,
+    components: Vec<ProtocolComponent>,
+}
+
 #[derive(Default)]
 struct TokenPrices {
     prices: HashMap<Bytes, f64>,
@@ -115,6 +146,126 @@ impl ProtocolMemoryCache {
         Ok(())
     }
 
+    /// Populates the cache from the snapshot file at `path`, falling back to the db.
+    ///
+    /// The snapshot is only used if it was written for this chain less than `max_age` ago, no token
+    /// or component was modified in the db since and it still holds as many tokens and components
+    /// as the db. Otherwise, or if the file is missing or unreadable, the cache is populated with
+    /// [`Self::populate`].
+    #[instrument(skip(self))]
+    pub async fn populate_from_snapshot(
+        &self,
+        path: &Path,
+        max_age: chrono::Duration,
+    ) -> Result<(), StorageError> {
+        let snapshot = match read_snapshot(path.to_path_buf()).await {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                warn!(?err, "ProtocolCacheSnapshotUnreadable");
+                return self.populate().await;
+            }
+        };
+        let age = Local::now()
+            .naive_utc()
+            .signed_duration_since(snapshot.written_at);
+        if snapshot.chain != self.chain || age > max_age {
+            info!(chain = ?snapshot.chain, %age, "ProtocolCacheSnapshotStale");
+            return self.populate().await;
+        }
+        let data_modified_at = self
+            .gateway
+            .get_protocol_data_modified_at(&self.chain)
+            .await?;
+        if data_modified_at != snapshot.data_modified_at {
+            info!(
+                ?data_modified_at,
+                snapshot_data_modified_at = ?snapshot.data_modified_at,
+                "ProtocolCacheSnapshotOutdated"
+            );
+            return self.populate().await;
+        }
+        let (n_tokens, n_components) = self.count_stored().await?;
+        if n_tokens != snapshot.tokens.len() as i64 ||
+            n_components != snapshot.components.len() as i64
+        {
+            info!(n_tokens, n_components, "ProtocolCacheSnapshotOutdated");
+            return self.populate().await;
+        }
+
+        self.add_tokens(snapshot.tokens).await?;
+        self.add_components(snapshot.components)
+            .await?;
+        let n_prices = self.update_prices_cache().await?;
+        info!(?n_tokens, ?n_components, ?n_prices, "ProtocolCacheRestored");
+        Ok(())
+    }
+
+    /// Writes the cached tokens and components to `path`.
+    ///
+    /// The file is written next to `path` first and then moved into place, so readers never see a
+    /// partially written snapshot. The db's last modification is read before the cache, so changes
+    /// stored while the snapshot is taken make it outdated rather than go missing.
+    #[instrument(skip(self))]
+    pub async fn write_snapshot(&self, path: &Path) -> Result<(), StorageError> {
+        let data_modified_at = self
+            .gateway
+            .get_protocol_data_modified_at(&self.chain)
+            .await?;
+        let tokens = self
+            .tokens
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let components = self
+            .components
+            .read()
+            .await
+            .values()
+            .flat_map(|system| system.values().cloned())
+            .collect::<Vec<_>>();
+        let snapshot = CacheSnapshot {
+            chain: self.chain,
+            written_at: Local::now().naive_utc(),
+            data_modified_at,
+            tokens,
+            components,
+        };
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let data = serde_json::to_vec(&snapshot)
+                .map_err(|err| StorageError::Unexpected(format!("Snapshot encoding: {err}")))?;
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, data)
+                .and_then(|_| fs::rename(&tmp_path, &path))
+                .map_err(|err| {
+                    StorageError::Unexpected(format!("Writing snapshot {path:?}: {err}"))
+                })
+        })
+        .await
+        .map_err(|err| StorageError::Unexpected(format!("Snapshot task failed: {err}")))?
+    }
+
+    /// Returns the number of tokens and components stored in the db for this chain.
+    async fn count_stored(&self) -> Result<(i64, i64), StorageError> {
+        let pagination = PaginationParams::new(0, 1);
+        let n_tokens = self
+            .gateway
+            .get_tokens(self.chain, None, QualityRange::None(), None, Some(&pagination))
+            .await?
+            .total;
+        let n_components = self
+            .gateway
+            .get_protocol_components(&self.chain, None, None, None, Some(&pagination))
+            .await?
+            .total;
+        match (n_tokens, n_components) {
+            (Some(n_tokens), Some(n_components)) => Ok((n_tokens, n_components)),
+            _ => Err(StorageError::Unexpected("Gateway returned no totals".to_string())),
+        }
+    }
+
     #[instrument(skip_all)]
     async fn update_prices_cache(&self) -> Result<usize, StorageError> {
         let mut token_prices = self.token_prices.write().await;
@@ -129,6 +280,17 @@ impl ProtocolMemoryCache {
     }
 }
 
+async fn read_snapshot(path: PathBuf) -> Result<CacheSnapshot, StorageError> {
+    tokio::task::spawn_blocking(move || {
+        let data = fs::read(&path)
+            .map_err(|err| StorageError::Unexpected(format!("Reading snapshot {path:?}: {err}")))?;
+        serde_json::from_slice(&data)
+            .map_err(|err| StorageError::DecodeError(format!("Snapshot {path:?}: {err}")))
+    })
+    .await
+    .map_err(|err| StorageError::Unexpected(format!("Snapshot task failed: {err}")))?
+}
+
 #[async_trait]
 impl ProtocolDataCache for ProtocolMemoryCache {
     async fn get_token_prices<'a>(
@@ -465,4 +627,189 @@ mod tests {
         let cached_components = cache.components.read().await.clone();
         assert_eq!(cached_components, exp_components);
     }
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tycho_{name}_{}.json", std::process::id()))
+    }
+
+    fn modified_at(secs: i64) -> Option<NaiveDateTime> {
+        NaiveDateTime::from_timestamp_opt(secs, 0)
+    }
+
+    fn modified_gateway(data_modified_at: Option<NaiveDateTime>) -> MockGateway {
+        let mut gateway = MockGateway::new();
+        gateway
+            .expect_get_protocol_data_modified_at()
+            .return_once(move |_| Box::pin(async move { Ok(data_modified_at) }));
+        gateway
+    }
+
+    fn counting_gateway(
+        n_tokens: i64,
+        n_components: i64,
+        data_modified_at: Option<NaiveDateTime>,
+    ) -> MockGateway {
+        let mut gateway = modified_gateway(data_modified_at);
+        gateway
+            .expect_get_tokens()
+            .withf(|_, _, _, _, pagination| pagination.is_some())
+            .return_once(move |_, _, _, _, _| {
+                Box::pin(async move { Ok(WithTotal { entity: vec![], total: Some(n_tokens) }) })
+            });
+        gateway
+            .expect_get_protocol_components()
+            .withf(|_, _, _, _, pagination| pagination.is_some())
+            .return_once(move |_, _, _, _, _| {
+                Box::pin(async move { Ok(WithTotal { entity: vec![], total: Some(n_components) }) })
+            });
+        gateway
+            .expect_get_token_prices()
+            .return_once(|_| Box::pin(async { Ok(prices()) }));
+        gateway
+    }
+
+    #[tokio::test]
+    async fn test_populate_from_snapshot() {
+        let chain = Chain::Ethereum;
+        let path = snapshot_path("populate_from_snapshot");
+        let cache = ProtocolMemoryCache::new(
+            chain,
+            Duration::seconds(60),
+            Arc::new(modified_gateway(modified_at(100))),
+        );
+        cache
+            .add_tokens(tokens())
+            .await
+            .unwrap();
+        cache
+            .add_components(components())
+            .await
+            .unwrap();
+        cache
+            .write_snapshot(&path)
+            .await
+            .unwrap();
+        let restored = ProtocolMemoryCache::new(
+            chain,
+            Duration::seconds(60),
+            Arc::new(counting_gateway(2, 2, modified_at(100))),
+        );
+
+        restored
+            .populate_from_snapshot(&path, Duration::minutes(10))
+            .await
+            .unwrap();
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(*restored.tokens.read().await, *cache.tokens.read().await);
+        assert_eq!(*restored.components.read().await, *cache.components.read().await);
+        assert_eq!(
+            restored
+                .token_prices
+                .read()
+                .await
+                .prices,
+            prices()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_populate_from_outdated_snapshot() {
+        let chain = Chain::Ethereum;
+        let path = snapshot_path("populate_from_outdated_snapshot");
+        let cache = ProtocolMemoryCache::new(
+            chain,
+            Duration::seconds(60),
+            Arc::new(modified_gateway(modified_at(100))),
+        );
+        cache
+            .add_tokens(tokens())
+            .await
+            .unwrap();
+        cache
+            .write_snapshot(&path)
+            .await
+            .unwrap();
+        // The db holds a component the snapshot misses, so the cache is loaded from the db.
+        let mut gateway = counting_gateway(2, 1, modified_at(100));
+        gateway
+            .expect_get_tokens()
+            .withf(|_, _, _, _, pagination| pagination.is_none())
+            .return_once(|_, _, _, _, _| {
+                Box::pin(async { Ok(WithTotal { entity: tokens(), total: Some(2) }) })
+            });
+        gateway
+            .expect_get_protocol_components()
+            .withf(|_, _, _, _, pagination| pagination.is_none())
+            .return_once(|_, _, _, _, _| {
+                Box::pin(async { Ok(WithTotal { entity: components(), total: Some(2) }) })
+            });
+        let restored = ProtocolMemoryCache::new(chain, Duration::seconds(60), Arc::new(gateway));
+
+        restored
+            .populate_from_snapshot(&path, Duration::minutes(10))
+            .await
+            .unwrap();
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            restored
+                .components
+                .read()
+                .await
+                .get("sys1")
+                .map(HashMap::len),
+            Some(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_populate_from_snapshot_modified_since() {
+        let chain = Chain::Ethereum;
+        let path = snapshot_path("populate_from_snapshot_modified_since");
+        let cache = ProtocolMemoryCache::new(
+            chain,
+            Duration::seconds(60),
+            Arc::new(modified_gateway(modified_at(100))),
+        );
+        cache
+            .add_tokens(tokens())
+            .await
+            .unwrap();
+        cache
+            .add_components(components())
+            .await
+            .unwrap();
+        cache
+            .write_snapshot(&path)
+            .await
+            .unwrap();
+        // A component was replaced after the snapshot, the counts still match.
+        let mut gateway = counting_gateway(2, 2, modified_at(200));
+        gateway
+            .expect_get_tokens()
+            .withf(|_, _, _, _, pagination| pagination.is_none())
+            .return_once(|_, _, _, _, _| {
+                Box::pin(async { Ok(WithTotal { entity: tokens(), total: Some(2) }) })
+            });
+        gateway
+            .expect_get_protocol_components()
+            .withf(|_, _, _, _, pagination| pagination.is_none())
+            .return_once(|_, _, _, _, _| {
+                let mut components = components();
+                components[1].id = "component3".to_string();
+                Box::pin(async move { Ok(WithTotal { entity: components, total: Some(2) }) })
+            });
+        let restored = ProtocolMemoryCache::new(chain, Duration::seconds(60), Arc::new(gateway));
+
+        restored
+            .populate_from_snapshot(&path, Duration::minutes(10))
+            .await
+            .unwrap();
+
+        fs::remove_file(&path).unwrap();
+        let restored_components = restored.components.read().await;
+        assert!(restored_components["sys1"].contains_key("component3"));
+        assert!(!restored_components["sys1"].contains_key("component2"));
+    }
 }
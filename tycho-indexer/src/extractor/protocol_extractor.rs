@@ -3,6 +3,7 @@ use std::{
     slice,
     str::FromStr,
     sync::Arc,
+    time::Instant,
 };
 
 use async_trait::async_trait;
@@ -15,7 +16,8 @@ use tracing::{debug, error, info, instrument, trace, warn};
 use tycho_common::{
     models::{
         blockchain::{
-            Block, BlockAggregatedChanges, BlockTag, DCIUpdate, EntryPoint, TracingParams,
+            Block, BlockAggregatedChanges, BlockDigest, BlockTag, DCIUpdate, EntryPoint,
+            FinalityStatus, QuarantinedMessage, TracingParams, Transaction,
         },
         contract::{Account, AccountBalance, AccountDelta},
         protocol::{
@@ -24,7 +26,7 @@ use tycho_common::{
         },
         token::{Token, TokenOwnerStore},
         Address, Balance, BlockHash, Chain, ChangeType, ComponentId, EntryPointId, ExtractionState,
-        ExtractorIdentity, ProtocolType, TxHash,
+        ExtractorIdentity, ProtocolType, SubstreamsPackage, TxHash,
     },
     storage::{
         BlockIdentifier, ChainGateway, ContractStateGateway, EntryPointGateway,
@@ -40,15 +42,80 @@ use tycho_substreams::pb::tycho::evm::v1 as tycho_substreams;
 use crate::{
     extractor::{
         chain_state::ChainState,
+        component_events::ComponentEventsConfig,
+        component_filter::{ComponentFilter, FilterState},
+        gap_healer::BlockGapHealer,
         models::{BlockChanges, BlockContractChanges, BlockEntityChanges},
+        profiling::{Stage, StageProfiler},
         protobuf_deserialisation::TryFromMessage,
         protocol_cache::{ProtocolDataCache, ProtocolMemoryCache},
         reorg_buffer::ReorgBuffer,
-        BlockUpdateWithCursor, ExtractionError, Extractor, ExtractorExtension, ExtractorMsg,
+        tracked_contracts::TrackedContracts,
+        BlockUpdateWithCursor, DecodeFailureHandling, ExtractionError, Extractor,
+        ExtractorExtension, ExtractorMsg, MessageDecodeError, TransactionSubscribers,
+    },
+    pb::sf::substreams::{
+        rpc::v2::{BlockScopedData, BlockUndoSignal, ModulesProgress},
+        v1::Clock,
     },
-    pb::sf::substreams::rpc::v2::{BlockScopedData, BlockUndoSignal, ModulesProgress},
 };
 
+/// Decodes the output of a substreams module into [`BlockChanges`].
+#[allow(deprecated)]
+pub(crate) fn decode_block_changes(
+    data: &prost_types::Any,
+    extractor: &str,
+    chain: Chain,
+    protocol_system: &str,
+    protocol_types: &HashMap<String, ProtocolType>,
+    final_block_height: u64,
+) -> Result<BlockChanges, ExtractionError> {
+    // Backwards Compatibility:
+    // Check if message_type ends with BlockAccountChanges or BlockEntityChanges. If it does,
+    // then we need to decode as the corresponding message type, then convert it to BlockChanges
+    match data.type_url.as_str() {
+        url if url.ends_with("BlockChanges") => {
+            let raw_msg = tycho_substreams::BlockChanges::decode(data.value.as_slice())?;
+            trace!(?raw_msg, "Received BlockChanges message");
+            BlockChanges::try_from_message((
+                raw_msg,
+                extractor,
+                chain,
+                protocol_system,
+                protocol_types,
+                final_block_height,
+            ))
+        }
+        url if url.ends_with("BlockContractChanges") => {
+            let raw_msg = tycho_substreams::BlockContractChanges::decode(data.value.as_slice())?;
+            trace!(?raw_msg, "Received BlockContractChanges message");
+            BlockContractChanges::try_from_message((
+                raw_msg,
+                extractor,
+                chain,
+                protocol_system.to_string(),
+                protocol_types,
+                final_block_height,
+            ))
+            .map(Into::into)
+        }
+        url if url.ends_with("BlockEntityChanges") => {
+            let raw_msg = tycho_substreams::BlockEntityChanges::decode(data.value.as_slice())?;
+            trace!(?raw_msg, "Received BlockEntityChanges message");
+            BlockEntityChanges::try_from_message((
+                raw_msg,
+                extractor,
+                chain,
+                protocol_system,
+                protocol_types,
+                final_block_height,
+            ))
+            .map(Into::into)
+        }
+        url => Err(MessageDecodeError::InvalidEnum(format!("Unknown message type: {url}")).into()),
+    }
+}
+
 pub struct Inner {
     cursor: Vec<u8>,
     last_processed_block: Option<Block>,
@@ -72,6 +139,20 @@ pub struct ProtocolExtractor<G, T, E> {
     post_processor: Option<fn(BlockChanges) -> BlockChanges>,
     reorg_buffer: Mutex<ReorgBuffer<BlockUpdateWithCursor<BlockChanges>>>,
     dci_plugin: Option<Arc<Mutex<E>>>,
+    /// Allow/deny lists for components, can be replaced at runtime.
+    component_filter: Mutex<FilterState>,
+    /// Which component event logs to store, extraction is disabled by default.
+    component_events: ComponentEventsConfig,
+    /// Records per-stage processing times, if enabled.
+    profiler: Option<Arc<StageProfiler>>,
+    /// Contracts whose changes are persisted, all contracts if unset.
+    tracked_contracts: Option<Arc<TrackedContracts>>,
+    /// Fills in blocks the package did not emit, gaps fail the extractor if unset.
+    gap_healer: Option<BlockGapHealer>,
+    /// Subscribers receiving changes per transaction, messages only carry them if there are any.
+    transaction_subscribers: TransactionSubscribers,
+    /// Handling of decode failures by error kind, failures of unlisted kinds stop the extractor.
+    decode_failures: HashMap<String, DecodeFailureHandling>,
 }
 
 impl<G, T, E> ProtocolExtractor<G, T, E>
@@ -118,6 +199,13 @@ where
                     post_processor,
                     reorg_buffer: Mutex::new(ReorgBuffer::new()),
                     dci_plugin,
+                    component_filter: Mutex::new(FilterState::default()),
+                    component_events: ComponentEventsConfig::default(),
+                    profiler: None,
+                    tracked_contracts: None,
+                    gap_healer: None,
+                    transaction_subscribers: TransactionSubscribers::default(),
+                    decode_failures: HashMap::new(),
                 }
             }
             Ok((cursor, block_hash)) => {
@@ -154,6 +242,13 @@ where
                     post_processor,
                     reorg_buffer: Mutex::new(ReorgBuffer::new()),
                     dci_plugin,
+                    component_filter: Mutex::new(FilterState::default()),
+                    component_events: ComponentEventsConfig::default(),
+                    profiler: None,
+                    tracked_contracts: None,
+                    gap_healer: None,
+                    transaction_subscribers: TransactionSubscribers::default(),
+                    decode_failures: HashMap::new(),
                 }
             }
             Err(err) => return Err(ExtractionError::Setup(err.to_string())),
@@ -163,6 +258,125 @@ where
         Ok(res)
     }
 
+    /// Sets the initial component allow/deny lists.
+    pub fn with_component_filter(mut self, filter: ComponentFilter) -> Self {
+        self.component_filter = Mutex::new(FilterState::new(filter));
+        self
+    }
+
+    pub fn with_component_events(mut self, config: ComponentEventsConfig) -> Self {
+        self.component_events = config;
+        self
+    }
+
+    /// Sets how decode failures are handled, keyed by [`MessageDecodeError::kind`].
+    pub fn with_decode_failures(
+        mut self,
+        decode_failures: HashMap<String, DecodeFailureHandling>,
+    ) -> Self {
+        self.decode_failures = decode_failures;
+        self
+    }
+
+    pub fn with_profiler(mut self, profiler: Arc<StageProfiler>) -> Self {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    pub fn with_tracked_contracts(mut self, tracked_contracts: Arc<TrackedContracts>) -> Self {
+        self.tracked_contracts = Some(tracked_contracts);
+        self
+    }
+
+    pub fn with_gap_healer(mut self, gap_healer: BlockGapHealer) -> Self {
+        self.gap_healer = Some(gap_healer);
+        self
+    }
+
+    pub fn with_transaction_subscribers(mut self, subscribers: TransactionSubscribers) -> Self {
+        self.transaction_subscribers = subscribers;
+        self
+    }
+
+    /// Records the time spent in `stage` since `start`, returns the start of the next stage.
+    fn lap(&self, stage: Stage, start: Instant) -> Instant {
+        match &self.profiler {
+            Some(profiler) => profiler.lap(stage, start),
+            None => Instant::now(),
+        }
+    }
+
+    /// Drops components rejected by the component filter, together with all changes scoped to
+    /// them.
+    async fn apply_component_filter(&self, msg: &mut BlockChanges) -> Result<(), ExtractionError> {
+        let (filter, mut decisions, undecided) = {
+            let state = self.component_filter.lock().await;
+            let filter = state.filter();
+            if filter.is_empty() {
+                return Ok(());
+            }
+            let (decisions, undecided) = state.lookup(filter.referenced_component_ids(msg));
+            (filter, decisions, undecided)
+        };
+
+        if !undecided.is_empty() {
+            let known = self
+                .protocol_cache
+                .get_protocol_components(&self.protocol_system, &undecided)
+                .await?;
+            decisions.extend(undecided.into_iter().map(|id| {
+                let accepted = known
+                    .get(&id)
+                    .is_some_and(|c| filter.accepts(c));
+                (id, accepted)
+            }));
+        }
+
+        let counts = filter.apply(msg, &mut decisions);
+        self.component_filter
+            .lock()
+            .await
+            .record(&filter, decisions);
+        if counts.is_empty() {
+            return Ok(());
+        }
+
+        debug!(?counts, "Filtered components");
+        for (kind, count) in [
+            ("component", counts.components),
+            ("state_update", counts.state_updates),
+            ("balance_change", counts.balance_changes),
+            ("entrypoint", counts.entrypoints),
+            ("event", counts.events),
+        ] {
+            counter!(
+                "extractor_filtered_entities",
+                "chain" => self.chain.to_string(),
+                "extractor" => self.name.clone(),
+                "kind" => kind
+            )
+            .increment(count as u64);
+        }
+        Ok(())
+    }
+
+    /// Drops changes of contracts that are not tracked, if the extractor has a registry.
+    fn apply_tracked_contracts(&self, msg: &mut BlockChanges) {
+        let Some(tracked_contracts) = &self.tracked_contracts else {
+            return;
+        };
+        let dropped = tracked_contracts.apply(msg);
+        if dropped > 0 {
+            debug!(dropped, block_number = msg.block.number, "Dropped untracked contract changes");
+            counter!(
+                "extractor_untracked_contracts",
+                "chain" => self.chain.to_string(),
+                "extractor" => self.name.clone()
+            )
+            .increment(dropped as u64);
+        }
+    }
+
     async fn update_cursor(&self, cursor: String) {
         let mut state = self.inner.lock().await;
         state.cursor = cursor.into();
@@ -177,6 +391,56 @@ where
             .first_message_processed
     }
 
+    /// Builds the block of a heartbeat from the substreams clock.
+    ///
+    /// The clock doesn't carry the parent hash, it is only known if the previous block was
+    /// processed by this extractor. Returns `None` if the clock is missing or malformed.
+    async fn heartbeat_block(&self, clock: Option<&Clock>) -> Option<Block> {
+        let clock = clock?;
+        let hash = Bytes::from_str(&clock.id).ok()?;
+        let ts = clock.timestamp.as_ref()?;
+        let ts = chrono::DateTime::from_timestamp(ts.seconds, ts.nanos.try_into().ok()?)?;
+        let parent_hash = self
+            .inner
+            .lock()
+            .await
+            .last_processed_block
+            .as_ref()
+            .filter(|block| block.number + 1 == clock.number)
+            .map(|block| block.hash.clone())
+            .unwrap_or_default();
+        Some(Block::new(clock.number, self.chain, hash, parent_hash, ts.naive_utc()))
+    }
+
+    /// Returns empty changes for the blocks the package skipped between the last processed block
+    /// and `block`, ordered by ascending number. Nothing is returned without a gap healer.
+    async fn heal_block_gap(
+        &self,
+        block: &Block,
+        final_block_height: u64,
+    ) -> Result<Vec<BlockChanges>, ExtractionError> {
+        let (Some(healer), Some(last)) = (&self.gap_healer, self.get_last_processed_block().await)
+        else {
+            return Ok(Vec::new());
+        };
+        Ok(healer
+            .heal(&last, block)
+            .await?
+            .into_iter()
+            .map(|header| {
+                BlockChanges::new(
+                    self.name.clone(),
+                    self.chain,
+                    header,
+                    final_block_height,
+                    false,
+                    Vec::new(),
+                    Vec::new(),
+                )
+            })
+            .collect())
+    }
+
     async fn update_last_processed_block(&self, block: Block) {
         let mut state = self.inner.lock().await;
         state.last_processed_block = Some(block);
@@ -334,6 +598,80 @@ where
         Ok(())
     }
 
+    /// Returns the protocol state and balance keys of components whose deletion is reverted.
+    ///
+    /// A restored component was created either in a finalized block, then its keys are found in
+    /// the db, or in a block that is still buffered.
+    #[allow(clippy::type_complexity)]
+    async fn get_restored_component_keys(
+        &self,
+        reorg_buffer: &ReorgBuffer<BlockUpdateWithCursor<BlockChanges>>,
+        restored_components: &HashMap<ComponentId, ProtocolComponent>,
+    ) -> Result<(HashSet<(ComponentId, String)>, HashSet<(ComponentId, Bytes)>), ExtractionError>
+    {
+        if restored_components.is_empty() {
+            return Ok((HashSet::new(), HashSet::new()));
+        }
+        let component_ids = restored_components
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+
+        let mut state_keys: HashSet<_> = self
+            .gateway
+            .get_protocol_states(&component_ids)
+            .await?
+            .into_iter()
+            .flat_map(|state| {
+                let component_id = state.component_id;
+                state
+                    .attributes
+                    .into_keys()
+                    .map(move |key| (component_id.clone(), key))
+            })
+            .collect();
+        let mut balance_keys: HashSet<_> = self
+            .gateway
+            .get_components_balances(&component_ids)
+            .await?
+            .into_iter()
+            .flat_map(|(component_id, balances)| {
+                balances
+                    .into_keys()
+                    .map(move |token| (component_id.clone(), token))
+            })
+            .collect();
+
+        for block_msg in reorg_buffer.get_block_range(None, None)? {
+            for update in &block_msg.block_update().txs_with_update {
+                state_keys.extend(
+                    update
+                        .state_updates
+                        .iter()
+                        .filter(|(c_id, _)| restored_components.contains_key(*c_id))
+                        .flat_map(|(c_id, delta)| {
+                            delta
+                                .updated_attributes
+                                .keys()
+                                .map(move |key| (c_id.clone(), key.clone()))
+                        }),
+                );
+                balance_keys.extend(
+                    update
+                        .balance_changes
+                        .iter()
+                        .filter(|(c_id, _)| restored_components.contains_key(*c_id))
+                        .flat_map(|(c_id, balances)| {
+                            balances
+                                .keys()
+                                .map(move |token| (c_id.clone(), token.clone()))
+                        }),
+                );
+            }
+        }
+        Ok((state_keys, balance_keys))
+    }
+
     /// Returns component balances at the tip of the reorg buffer.
     ///
     /// Will return the requested balances at the tip of the reorg buffer. Might need
@@ -627,6 +965,7 @@ where
         &self,
         inp: BlockScopedData,
     ) -> Result<Option<ExtractorMsg>, ExtractionError> {
+        let stage_start = Instant::now();
         let data = inp
             .output
             .as_ref()
@@ -635,51 +974,14 @@ where
             .as_ref()
             .unwrap();
 
-        // Backwards Compatibility:
-        // Check if message_type ends with BlockAccountChanges or BlockEntityChanges. If it does,
-        // then we need to decode as the corresponding message type, then convert it to BlockChanges
-        let msg = match data.type_url.as_str() {
-            url if url.ends_with("BlockChanges") => {
-                let raw_msg = tycho_substreams::BlockChanges::decode(data.value.as_slice())?;
-                trace!(?raw_msg, "Received BlockChanges message");
-                BlockChanges::try_from_message((
-                    raw_msg,
-                    &self.name,
-                    self.chain,
-                    &self.protocol_system,
-                    &self.protocol_types,
-                    inp.final_block_height,
-                ))
-            }
-            url if url.ends_with("BlockContractChanges") => {
-                let raw_msg =
-                    tycho_substreams::BlockContractChanges::decode(data.value.as_slice())?;
-                trace!(?raw_msg, "Received BlockContractChanges message");
-                BlockContractChanges::try_from_message((
-                    raw_msg,
-                    &self.name,
-                    self.chain,
-                    self.protocol_system.clone(),
-                    &self.protocol_types,
-                    inp.final_block_height,
-                ))
-                .map(Into::into)
-            }
-            url if url.ends_with("BlockEntityChanges") => {
-                let raw_msg = tycho_substreams::BlockEntityChanges::decode(data.value.as_slice())?;
-                trace!(?raw_msg, "Received BlockEntityChanges message");
-                BlockEntityChanges::try_from_message((
-                    raw_msg,
-                    &self.name,
-                    self.chain,
-                    &self.protocol_system,
-                    &self.protocol_types,
-                    inp.final_block_height,
-                ))
-                .map(Into::into)
-            }
-            _ => return Err(ExtractionError::DecodeError("Unknown message type".into())),
-        };
+        let msg = decode_block_changes(
+            data,
+            &self.name,
+            self.chain,
+            &self.protocol_system,
+            &self.protocol_types,
+            inp.final_block_height,
+        );
 
         let msg = match msg {
             Ok(changes) => {
@@ -687,15 +989,91 @@ where
                 changes
             }
             Err(ExtractionError::Empty) => {
+                self.update_cursor(inp.cursor).await;
+                // Subscriptions opt into heartbeats, see `Command::Subscribe`.
+                let heartbeat = self
+                    .heartbeat_block(inp.clock.as_ref())
+                    .await
+                    .map(|block| {
+                        Arc::new(BlockAggregatedChanges::heartbeat(
+                            &self.name,
+                            self.chain,
+                            block,
+                            inp.final_block_height,
+                        ))
+                    });
+                return Ok(heartbeat);
+            }
+            Err(ExtractionError::DecodeError(err)) => {
+                let handling = self
+                    .decode_failures
+                    .get(err.kind())
+                    .copied()
+                    .unwrap_or_default();
+                counter!(
+                    "extractor_decode_errors",
+                    "chain" => self.chain.to_string(),
+                    "extractor" => self.name.clone(),
+                    "kind" => err.kind(),
+                    "handling" => handling.as_str()
+                )
+                .increment(1);
+                match handling {
+                    DecodeFailureHandling::Stop => return Err(err.into()),
+                    DecodeFailureHandling::Skip => {
+                        warn!(
+                            extractor = %self.name,
+                            block_number = inp.clock.as_ref().map(|c| c.number),
+                            kind = err.kind(),
+                            %err,
+                            "Skipping undecodable block message"
+                        );
+                    }
+                    DecodeFailureHandling::Quarantine => {
+                        let clock = inp.clock.as_ref().ok_or_else(|| {
+                            ExtractionError::Unknown(
+                                "Can't quarantine a block message without clock".to_string(),
+                            )
+                        })?;
+                        warn!(
+                            extractor = %self.name,
+                            block_number = clock.number,
+                            kind = err.kind(),
+                            %err,
+                            "Quarantining undecodable block message"
+                        );
+                        self.gateway
+                            .quarantine_message(&QuarantinedMessage {
+                                block_number: clock.number,
+                                block_hash: Bytes::from_str(&clock.id).ok(),
+                                type_url: data.type_url.clone(),
+                                payload: Bytes::from(data.value.clone()),
+                                kind: err.kind().to_string(),
+                                error: err.to_string(),
+                            })
+                            .await?;
+                    }
+                }
                 self.update_cursor(inp.cursor).await;
                 return Ok(None);
             }
             Err(e) => return Err(e),
         };
+        let stage_start = self.lap(Stage::Decode, stage_start);
 
         let mut msg =
             if let Some(post_process_f) = self.post_processor { post_process_f(msg) } else { msg };
 
+        if data.type_url.ends_with("BlockChanges") {
+            msg.component_events = self
+                .component_events
+                .decode(data.value.as_slice(), &msg)?;
+        }
+
+        self.apply_component_filter(&mut msg)
+            .await?;
+        self.apply_tracked_contracts(&mut msg);
+
         if let Some(last_processed_block) = self.get_last_processed_block().await {
             if msg.block.ts.timestamp() == last_processed_block.ts.timestamp() {
                 debug!("Block with identical timestamp detected. Prev block ts: {:?} - New block ts: {:?}", last_processed_block.ts, msg.block.ts);
@@ -732,13 +1110,26 @@ where
             .await?;
 
         trace!(?msg, "Processing message");
+        let stage_start = self.lap(Stage::Validate, stage_start);
 
         // Depending on how Substreams handle them, this condition could be problematic for single
         // block finality blockchains.
         let is_syncing = inp.final_block_height >= msg.block.number;
+        let healed = self
+            .heal_block_gap(&msg.block, inp.final_block_height)
+            .await?;
         {
             // keep reorg buffer guard within a limited scope
             let mut reorg_buffer = self.reorg_buffer.lock().await;
+            if !healed.is_empty() {
+                // resuming from the previous cursor streams the blocks after the gap again
+                let cursor = self.get_cursor().await;
+                for changes in healed {
+                    reorg_buffer
+                        .insert_block(BlockUpdateWithCursor::new(changes, cursor.clone()))
+                        .map_err(ExtractionError::Storage)?;
+                }
+            }
             reorg_buffer
                 .insert_block(BlockUpdateWithCursor::new(msg.clone(), inp.cursor.clone()))
                 .map_err(ExtractionError::Storage)?;
@@ -770,10 +1161,16 @@ where
         }
 
         self.update_cursor(inp.cursor).await;
+        let stage_start = self.lap(Stage::Persist, stage_start);
 
-        let mut changes = msg.aggregate_updates()?;
+        let mut changes = if self.transaction_subscribers.any() {
+            msg.aggregate_updates_with_txs()?
+        } else {
+            msg.aggregate_updates()?
+        };
         self.handle_tvl_changes(&mut changes)
             .await?;
+        self.lap(Stage::Emit, stage_start);
 
         if !is_syncing {
             debug!(
@@ -796,10 +1193,10 @@ where
     ) -> Result<Option<ExtractorMsg>, ExtractionError> {
         let block_ref = inp
             .last_valid_block
-            .ok_or_else(|| ExtractionError::DecodeError("Revert without block ref".into()))?;
+            .ok_or_else(|| MessageDecodeError::MissingField("Revert without block ref".into()))?;
 
         let block_hash = Bytes::from_str(&block_ref.id).map_err(|err| {
-            ExtractionError::DecodeError(format!(
+            MessageDecodeError::InvalidValue(format!(
                 "Failed to parse {} as block hash: {}",
                 block_ref.id, err
             ))
@@ -847,6 +1244,12 @@ where
             .purge(block_hash)
             .map_err(|e| ExtractionError::ReorgBufferError(e.to_string()))?;
 
+        // Components created in the purged blocks are gone, decide on them again if they recur.
+        self.component_filter
+            .lock()
+            .await
+            .clear_decisions();
+
         // Handle created and deleted components
         let (reverted_components_creations, reverted_components_deletions) =
             reverted_state.iter().fold(
@@ -891,10 +1294,34 @@ where
                 },
             );
         trace!(?reverted_components_creations, "Reverted components creations");
-        // TODO: For these reverted deletions we need to fetch the whole state (so get it from the
-        //  db and apply buffer update)
         trace!(?reverted_components_deletions, "Reverted components deletions");
 
+        // Components whose deletion is reverted are sent as new components, so clients need their
+        // whole state again, not only the keys that changed in the reverted blocks. Collect every
+        // attribute and balance they have in the db or in the blocks that remain buffered.
+        let (restored_state_keys, restored_balance_keys) = self
+            .get_restored_component_keys(&reorg_buffer, &reverted_components_deletions)
+            .await?;
+
+        // Accounts created in the reverted range didn't exist before it, so there is no previous
+        // state or balance to revert them to.
+        let reverted_account_creations: HashSet<&Address> = reverted_state
+            .iter()
+            .flat_map(|block_msg| {
+                block_msg
+                    .block_update()
+                    .txs_with_update
+                    .iter()
+                    .flat_map(|update| {
+                        update
+                            .account_deltas
+                            .iter()
+                            .filter(|(_, delta)| delta.change == ChangeType::Creation)
+                            .map(|(address, _)| address)
+                    })
+            })
+            .collect();
+
         // Handle reverted account state
         let reverted_account_state_keys: HashSet<_> = reverted_state
             .iter()
@@ -907,9 +1334,7 @@ where
                         update
                             .account_deltas
                             .iter()
-                            .filter(|(c_id, _)| {
-                                !reverted_components_creations.contains_key(&c_id.to_string())
-                            })
+                            .filter(|(address, _)| !reverted_account_creations.contains(address))
                             .flat_map(|(c_id, delta)| {
                                 delta
                                     .slots
@@ -1028,6 +1453,11 @@ where
                             })
                     })
             })
+            .chain(
+                restored_state_keys
+                    .iter()
+                    .map(|(c_id, key)| (c_id, key)),
+            )
             .collect();
 
         let reverted_protocol_state_keys_vec = reverted_protocol_state_keys
@@ -1134,6 +1564,11 @@ where
                             })
                     })
             })
+            .chain(
+                restored_balance_keys
+                    .iter()
+                    .map(|(id, token)| (id, token.clone())),
+            )
             .collect();
 
         let reverted_component_balances_keys_vec = reverted_component_balances_keys
@@ -1147,7 +1582,8 @@ where
             .get_component_balances(&reorg_buffer, &reverted_component_balances_keys_vec)
             .await?;
 
-        // Handle account balance changes
+        // Handle account balance changes. Accounts whose balances changed without any slot change
+        // need their balances reverted too, so these are not restricted to `account_deltas`.
         let reverted_account_balances_keys: HashSet<(Bytes, Bytes)> = reverted_state
             .iter()
             .flat_map(|block_msg| {
@@ -1159,7 +1595,7 @@ where
                         update
                             .account_balance_changes
                             .iter()
-                            .filter(|(account, _)| account_deltas.contains_key(*account))
+                            .filter(|(account, _)| !reverted_account_creations.contains(account))
                             .flat_map(|(account, balance_change)| {
                                 balance_change
                                     .keys()
@@ -1201,6 +1637,8 @@ where
             account_balances: combined_account_balances,
             component_tvl: HashMap::new(),
             dci_update: DCIUpdate::default(), // TODO: get reverted entrypoint info?
+            txs_with_update: Vec::new(),
+            heartbeat: false,
         };
 
         debug!("Successfully retrieved all previous states during revert!");
@@ -1217,12 +1655,31 @@ where
     async fn handle_progress(&self, _inp: ModulesProgress) -> Result<(), ExtractionError> {
         todo!()
     }
+
+    #[instrument(skip_all)]
+    async fn set_component_filter(&self, filter: ComponentFilter) {
+        info!(?filter, "Updating component filter");
+        *self.component_filter.lock().await = FilterState::new(filter);
+    }
+
+    #[instrument(skip_all)]
+    async fn record_substreams_package(
+        &self,
+        package: SubstreamsPackage,
+    ) -> Result<(), ExtractionError> {
+        self.gateway
+            .record_substreams_package(package)
+            .await?;
+        Ok(())
+    }
 }
 pub struct ExtractorPgGateway {
     name: String,
     chain: Chain,
     db_tx_batch_size: usize,
     state_gateway: CachedGateway,
+    /// Package history saved along with each cursor.
+    substreams_packages: Mutex<Vec<SubstreamsPackage>>,
 }
 
 #[automock]
@@ -1257,6 +1714,16 @@ pub trait ExtractorGateway: Send + Sync {
         &self,
         accounts: &[Address],
     ) -> Result<HashMap<Address, HashMap<Address, AccountBalance>>, StorageError>;
+
+    /// Appends `package` to the stored package history unless it is the current one. The
+    /// history is persisted together with the next cursor.
+    async fn record_substreams_package(
+        &self,
+        package: SubstreamsPackage,
+    ) -> Result<(), StorageError>;
+
+    /// Stores a block message that failed to decode, so it can be replayed later.
+    async fn quarantine_message(&self, message: &QuarantinedMessage) -> Result<(), StorageError>;
 }
 
 impl ExtractorPgGateway {
@@ -1266,7 +1733,13 @@ impl ExtractorPgGateway {
         db_tx_batch_size: usize,
         state_gateway: CachedGateway,
     ) -> Self {
-        Self { name: name.to_owned(), chain, db_tx_batch_size, state_gateway }
+        Self {
+            name: name.to_owned(),
+            chain,
+            db_tx_batch_size,
+            state_gateway,
+            substreams_packages: Mutex::new(Vec::new()),
+        }
     }
 
     #[instrument(skip_all)]
@@ -1281,6 +1754,12 @@ impl ExtractorPgGateway {
             None,
             new_cursor.as_bytes(),
             block_hash,
+        )
+        .with_substreams_packages(
+            self.substreams_packages
+                .lock()
+                .await
+                .clone(),
         );
         self.state_gateway
             .save_state(&state)
@@ -1288,6 +1767,28 @@ impl ExtractorPgGateway {
         Ok(())
     }
 
+    /// Contracts held by the stored components of `protocol_system`.
+    pub async fn get_component_contracts(
+        &self,
+        protocol_system: &str,
+    ) -> Result<HashSet<Address>, StorageError> {
+        let components = self
+            .state_gateway
+            .get_protocol_components(
+                &self.chain,
+                Some(protocol_system.to_string()),
+                None,
+                None,
+                None,
+            )
+            .await?;
+        Ok(components
+            .entity
+            .into_iter()
+            .flat_map(|component| component.contract_addresses)
+            .collect())
+    }
+
     async fn get_last_extraction_state(&self) -> Result<ExtractionState, StorageError> {
         let state = self
             .state_gateway
@@ -1304,6 +1805,65 @@ impl ExtractorGateway for ExtractorPgGateway {
             .get_block(&BlockIdentifier::Hash(block_hash))
             .await
     }
+
+    async fn quarantine_message(&self, message: &QuarantinedMessage) -> Result<(), StorageError> {
+        self.state_gateway
+            .upsert_quarantined_message(&self.name, &self.chain, message)
+            .await
+    }
+
+    async fn record_substreams_package(
+        &self,
+        mut package: SubstreamsPackage,
+    ) -> Result<(), StorageError> {
+        let mut packages = match self.get_last_extraction_state().await {
+            Ok(state) => {
+                let packages = state.substreams_packages;
+                match packages.last() {
+                    Some(current) if current.same_module(&package) => {
+                        if current.version == package.version {
+                            *self.substreams_packages.lock().await = packages;
+                            return Ok(());
+                        }
+                    }
+                    Some(current) => {
+                        warn!(
+                            extractor = self.name,
+                            previous_module_hash = %current.module_hash,
+                            previous_version = current.version,
+                            module_hash = %package.module_hash,
+                            version = package.version,
+                            "Substreams module hash changed mid-history, data before and after \
+                            the restart may be inconsistent"
+                        );
+                        counter!(
+                            "substreams_module_hash_changed",
+                            "extractor" => self.name.clone(),
+                            "chain" => self.chain.to_string()
+                        )
+                        .increment(1);
+                    }
+                    None => {}
+                }
+                let block = self.get_block(state.block_hash).await?;
+                package.from_block = Some(block.number + 1);
+                packages
+            }
+            Err(StorageError::NotFound(_, _)) => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        info!(
+            extractor = self.name,
+            package = package.name,
+            version = package.version,
+            module_hash = %package.module_hash,
+            from_block = ?package.from_block,
+            "Recording substreams package"
+        );
+        packages.push(package);
+        *self.substreams_packages.lock().await = packages;
+        Ok(())
+    }
     async fn get_cursor(&self) -> Result<(Vec<u8>, Bytes), StorageError> {
         let extraction_state = self.get_last_extraction_state().await;
         match extraction_state {
@@ -1342,12 +1902,8 @@ impl ExtractorGateway for ExtractorPgGateway {
                 .await?;
         }
 
-        // Insert block
-        self.state_gateway
-            .upsert_block(slice::from_ref(&changes.block))
-            .await?;
-
         // Collect transaction aggregated changes
+        let mut txs: Vec<Transaction> = Vec::with_capacity(changes.txs_with_update.len());
         let mut new_protocol_components: Vec<ProtocolComponent> = vec![];
         let mut state_updates: Vec<(TxHash, ProtocolComponentStateDelta)> = vec![];
         let mut account_changes: Vec<(Bytes, AccountDelta)> = vec![];
@@ -1362,11 +1918,7 @@ impl ExtractorGateway for ExtractorPgGateway {
 
         for tx_update in changes.txs_with_update.iter() {
             trace!(tx_hash = ?tx_update.tx.hash, "Processing tx");
-
-            // Insert transaction
-            self.state_gateway
-                .upsert_tx(slice::from_ref(&tx_update.tx))
-                .await?;
+            txs.push(tx_update.tx.clone());
 
             let hash: TxHash = tx_update.tx.hash.clone();
 
@@ -1476,26 +2028,17 @@ impl ExtractorGateway for ExtractorPgGateway {
                 .await?;
         }
 
-        // Insert changed accounts
-        if !account_changes.is_empty() {
-            self.state_gateway
-                .update_contracts(account_changes.as_slice())
-                .await?;
-        }
-
-        // Insert protocol state changes
-        if !state_updates.is_empty() {
-            self.state_gateway
-                .update_protocol_states(state_updates.as_slice())
-                .await?;
-        }
-
-        // Insert component balance changes
-        if !component_balance_changes.is_empty() {
-            self.state_gateway
-                .add_component_balances(component_balance_changes.as_slice())
-                .await?;
-        }
+        // Insert the block with its transactions, account, protocol state and component balance
+        // changes. They are committed together with the rest of this block's writes.
+        self.state_gateway
+            .apply_block_changes(
+                &changes.block,
+                &txs,
+                &account_changes,
+                &state_updates,
+                &component_balance_changes,
+            )
+            .await?;
 
         // Insert account balance changes
         if !account_balance_changes.is_empty() {
@@ -1504,6 +2047,18 @@ impl ExtractorGateway for ExtractorPgGateway {
                 .await?;
         }
 
+        // Digest of the changes written above, used to compare the history of deployments
+        let digest = BlockDigest::compute(
+            &changes.block,
+            &account_changes,
+            &state_updates,
+            &component_balance_changes,
+            &account_balance_changes,
+        );
+        self.state_gateway
+            .upsert_block_digests(slice::from_ref(&digest))
+            .await?;
+
         // Insert new entrypoints
         if !new_entrypoints.is_empty() {
             self.state_gateway
@@ -1525,13 +2080,34 @@ impl ExtractorGateway for ExtractorPgGateway {
                 .await?;
         }
 
+        // Insert component event logs
+        if !changes.component_events.is_empty() {
+            self.state_gateway
+                .add_component_events(changes.component_events.as_slice())
+                .await?;
+        }
+
         self.save_cursor(new_cursor, changes.block.hash.clone())
             .await?;
 
         let batch_size = if force_commit { 0 } else { self.db_tx_batch_size };
         self.state_gateway
             .commit_transaction(batch_size)
-            .await
+            .await?;
+
+        // Blocks are only advanced once substreams reported them final. Marking them after forced
+        // commits only keeps the update off the syncing path, the next forced commit covers all
+        // blocks committed in between.
+        if force_commit {
+            self.state_gateway
+                .update_block_finality(
+                    &self.chain,
+                    FinalityStatus::Finalized,
+                    changes.block.number as i64,
+                )
+                .await?;
+        }
+        Ok(())
     }
 
     async fn get_protocol_states<'a>(
@@ -1546,7 +2122,7 @@ impl ExtractorGateway for ExtractorPgGateway {
 
     async fn get_contracts(&self, addresses: &[Address]) -> Result<Vec<Account>, StorageError> {
         self.state_gateway
-            .get_contracts(&self.chain, Some(addresses), None, true, None)
+            .get_contracts(&self.chain, Some(addresses), None, true, None, None)
             .await
             .map(|contract_data| contract_data.entity)
     }
@@ -1578,7 +2154,10 @@ mod test {
 
     use super::*;
     use crate::{
-        extractor::MockExtractorExtension,
+        extractor::{
+            fault_injection::{FaultConfig, FaultInjectingGateway},
+            MockExtractorExtension,
+        },
         testing::{fixtures as pb_fixtures, MockGateway},
     };
 
@@ -1598,10 +2177,9 @@ mod test {
 
     const EXTRACTOR_NAME: &str = "TestExtractor";
     const TEST_PROTOCOL: &str = "TestProtocol";
-    async fn create_extractor(
-        gw: MockExtractorGateway,
-    ) -> ProtocolExtractor<MockExtractorGateway, MockTokenPreProcessor, MockExtractorExtension>
-    {
+    async fn create_extractor<G: ExtractorGateway>(
+        gw: G,
+    ) -> ProtocolExtractor<G, MockTokenPreProcessor, MockExtractorExtension> {
         let protocol_types = HashMap::from([("pt_1".to_string(), ProtocolType::default())]);
         let protocol_cache = ProtocolMemoryCache::new(
             Chain::Ethereum,
@@ -1697,7 +2275,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn test_handle_tick_scoped_data_old_native_msg() {
+    async fn test_handle_tick_scoped_data_storage_fault() {
         let mut gw = MockExtractorGateway::new();
         gw.expect_ensure_protocol_types()
             .times(1)
@@ -1705,16 +2283,71 @@ mod test {
         gw.expect_get_cursor()
             .times(1)
             .returning(|| Ok(("cursor".into(), Bytes::default())));
-        gw.expect_advance()
-            .times(1)
-            .returning(|_, _, _| Ok(()));
         gw.expect_get_block()
             .times(1)
             .returning(|_| Ok(Block::default()));
+        // The first advance is failed by the injector and never reaches the inner gateway.
+        gw.expect_advance()
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let gw = FaultInjectingGateway::new(
+            gw,
+            FaultConfig::default()
+                .fail(50)
+                .only(&["advance"]),
+        );
+        let injector = gw.injector();
 
         let extractor = create_extractor(gw).await;
+        let block_data = |number: u64| {
+            pb_fixtures::pb_block_scoped_data(
+                tycho_substreams::BlockChanges {
+                    block: Some(pb_fixtures::pb_blocks(number)),
+                    ..Default::default()
+                },
+                Some(format!("cursor@{number}").as_str()),
+                Some(number),
+            )
+        };
 
-        extractor
+        let failed = extractor
+            .handle_tick_scoped_data(block_data(1))
+            .await;
+        let cursor_after_fault = extractor.get_cursor().await;
+        let recovered = extractor
+            .handle_tick_scoped_data(block_data(2))
+            .await;
+
+        assert!(matches!(
+            failed,
+            Err(ExtractionError::Storage(StorageError::Unexpected(msg)))
+                if msg == "Injected fault in advance"
+        ));
+        assert_eq!(cursor_after_fault, "cursor");
+        assert!(recovered.is_ok());
+        assert_eq!(extractor.get_cursor().await, "cursor@2");
+        assert_eq!((injector.calls(), injector.faults()), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_handle_tick_scoped_data_old_native_msg() {
+        let mut gw = MockExtractorGateway::new();
+        gw.expect_ensure_protocol_types()
+            .times(1)
+            .returning(|_| ());
+        gw.expect_get_cursor()
+            .times(1)
+            .returning(|| Ok(("cursor".into(), Bytes::default())));
+        gw.expect_advance()
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        gw.expect_get_block()
+            .times(1)
+            .returning(|_| Ok(Block::default()));
+
+        let extractor = create_extractor(gw).await;
+
+        extractor
             .handle_tick_scoped_data(pb_fixtures::pb_block_scoped_data(
                 tycho_substreams::BlockEntityChanges {
                     block: Some(pb_fixtures::pb_blocks(1)),
@@ -1814,7 +2447,7 @@ mod test {
         assert_eq!(extractor.get_cursor().await, "cursor@2");
     }
     #[tokio::test]
-    async fn test_handle_tick_scoped_data_skip() {
+    async fn test_handle_tick_scoped_data_heartbeat() {
         let mut gw = MockExtractorGateway::new();
         gw.expect_ensure_protocol_types()
             .times(1)
@@ -1834,16 +2467,191 @@ mod test {
         let inp = pb_fixtures::pb_block_scoped_data((), None, None);
         let res = extractor
             .handle_tick_scoped_data(inp)
-            .await;
+            .await
+            .unwrap()
+            .expect("Expected a heartbeat");
 
-        match res {
-            Ok(Some(_)) => panic!("Expected Ok(None) but got Ok(Some(..))"),
-            Ok(None) => (), // This is the expected case
-            Err(_) => panic!("Expected Ok(None) but got Err(..)"),
-        }
+        let exp = BlockAggregatedChanges::heartbeat(
+            EXTRACTOR_NAME,
+            Chain::Ethereum,
+            Block::new(
+                420,
+                Chain::Ethereum,
+                Bytes::zero(32),
+                Bytes::default(),
+                "1970-01-01T00:16:40".parse().unwrap(),
+            ),
+            420,
+        );
+        assert_eq!(*res, exp);
         assert_eq!(extractor.get_cursor().await, "cursor@420");
     }
 
+    fn pb_invalid_balance_length() -> tycho_substreams::BlockChanges {
+        tycho_substreams::BlockChanges {
+            block: Some(pb_fixtures::pb_blocks(1)),
+            changes: vec![tycho_substreams::TransactionChanges {
+                tx: Some(pb_fixtures::pb_transactions(1, 1)),
+                balance_changes: vec![tycho_substreams::BalanceChange {
+                    token: vec![0xaa; 20],
+                    balance: vec![0x01; 33],
+                    component_id: "Balance1".as_bytes().to_vec(),
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_tick_scoped_data_stops_on_invalid_length() {
+        let mut gw = MockExtractorGateway::new();
+        gw.expect_ensure_protocol_types()
+            .times(1)
+            .returning(|_| ());
+        gw.expect_get_cursor()
+            .times(1)
+            .returning(|| Ok(("cursor".into(), Bytes::default())));
+        gw.expect_advance().times(0);
+        gw.expect_get_block()
+            .times(1)
+            .returning(|_| Ok(Block::default()));
+
+        let extractor = create_extractor(gw).await;
+
+        let res = extractor
+            .handle_tick_scoped_data(pb_fixtures::pb_block_scoped_data(
+                pb_invalid_balance_length(),
+                Some("cursor@1"),
+                Some(1),
+            ))
+            .await;
+
+        assert!(matches!(
+            res,
+            Err(ExtractionError::DecodeError(MessageDecodeError::InvalidLength(_)))
+        ));
+        assert_eq!(extractor.get_cursor().await, "cursor");
+    }
+
+    #[tokio::test]
+    async fn test_handle_tick_scoped_data_skips_configured_decode_failures() {
+        let mut gw = MockExtractorGateway::new();
+        gw.expect_ensure_protocol_types()
+            .times(1)
+            .returning(|_| ());
+        gw.expect_get_cursor()
+            .times(1)
+            .returning(|| Ok(("cursor".into(), Bytes::default())));
+        gw.expect_advance().times(0);
+        gw.expect_get_block()
+            .times(1)
+            .returning(|_| Ok(Block::default()));
+
+        let extractor = create_extractor(gw)
+            .await
+            .with_decode_failures(HashMap::from([(
+                "invalid_length".to_string(),
+                DecodeFailureHandling::Skip,
+            )]));
+
+        let res = extractor
+            .handle_tick_scoped_data(pb_fixtures::pb_block_scoped_data(
+                pb_invalid_balance_length(),
+                Some("cursor@1"),
+                Some(1),
+            ))
+            .await
+            .expect("skipped decode failures should not stop the extractor");
+
+        assert!(res.is_none());
+        assert_eq!(extractor.get_cursor().await, "cursor@1");
+    }
+
+    #[tokio::test]
+    async fn test_handle_tick_scoped_data_quarantines_configured_decode_failures() {
+        let msg = pb_invalid_balance_length();
+        let payload = Bytes::from(msg.encode_to_vec());
+        let block_hash = Bytes::from_str(crate::extractor::models::fixtures::HASH_256_0).unwrap();
+        let mut gw = MockExtractorGateway::new();
+        gw.expect_ensure_protocol_types()
+            .times(1)
+            .returning(|_| ());
+        gw.expect_get_cursor()
+            .times(1)
+            .returning(|| Ok(("cursor".into(), Bytes::default())));
+        gw.expect_advance().times(0);
+        gw.expect_get_block()
+            .times(1)
+            .returning(|_| Ok(Block::default()));
+        gw.expect_quarantine_message()
+            .withf(move |message| {
+                message.block_number == 420 &&
+                    message.block_hash.as_ref() == Some(&block_hash) &&
+                    message.type_url == "tycho.evm.v1.BlockChanges" &&
+                    message.payload == payload &&
+                    message.kind == "invalid_length"
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let extractor = create_extractor(gw)
+            .await
+            .with_decode_failures(HashMap::from([(
+                "invalid_length".to_string(),
+                DecodeFailureHandling::Quarantine,
+            )]));
+
+        let res = extractor
+            .handle_tick_scoped_data(pb_fixtures::pb_block_scoped_data(
+                msg,
+                Some("cursor@1"),
+                Some(1),
+            ))
+            .await
+            .expect("quarantined decode failures should not stop the extractor");
+
+        assert!(res.is_none());
+        assert_eq!(extractor.get_cursor().await, "cursor@1");
+    }
+
+    #[tokio::test]
+    async fn test_handle_tick_scoped_data_stops_on_missing_field() {
+        let mut gw = MockExtractorGateway::new();
+        gw.expect_ensure_protocol_types()
+            .times(1)
+            .returning(|_| ());
+        gw.expect_get_cursor()
+            .times(1)
+            .returning(|| Ok(("cursor".into(), Bytes::default())));
+        gw.expect_advance().times(0);
+        gw.expect_get_block()
+            .times(1)
+            .returning(|_| Ok(Block::default()));
+
+        let extractor = create_extractor(gw).await;
+
+        let msg = tycho_substreams::BlockChanges {
+            block: Some(pb_fixtures::pb_blocks(1)),
+            changes: vec![tycho_substreams::TransactionChanges { tx: None, ..Default::default() }],
+            ..Default::default()
+        };
+
+        let res = extractor
+            .handle_tick_scoped_data(pb_fixtures::pb_block_scoped_data(
+                msg,
+                Some("cursor@1"),
+                Some(1),
+            ))
+            .await;
+
+        assert!(matches!(
+            res,
+            Err(ExtractionError::DecodeError(MessageDecodeError::MissingField(_)))
+        ));
+        assert_eq!(extractor.get_cursor().await, "cursor");
+    }
+
     #[tokio::test]
     async fn test_handle_tick_scoped_data_same_ts() {
         // This test is to ensure that the extractor can handle multiple blocks with the same
@@ -2451,6 +3259,7 @@ mod test_serial_db {
                     .unwrap(),
                     parent_hash: Bytes::default(),
                     ts: db_fixtures::yesterday_half_past_midnight(),
+                    extra: Default::default(),
                 }])
                 .await
                 .expect("block insertion succeeded");
@@ -2906,8 +3715,8 @@ mod test_serial_db {
                         protocol_type_name: "pt_1".to_string(),
                         chain: Chain::Ethereum,
                         tokens: vec![
-                            Bytes::from_str("0xdac17f958d2ee523a2206206994597c13d831ec7").unwrap(),
                             Bytes::from_str(USDC_ADDRESS).unwrap(),
+                            Bytes::from_str("0xdac17f958d2ee523a2206206994597c13d831ec7").unwrap(),
                         ],
                         contract_addresses: vec![],
                         static_attributes: HashMap::new(),
@@ -2950,6 +3759,24 @@ mod test_serial_db {
                             component_id: "pc_1".to_string(),
                         }),
                     ])),
+                    // pc_2 is restored, so all its balances are sent: USDT from the db, USDC
+                    // from block 3 which is still buffered.
+                    ("pc_2".to_string(), HashMap::from([
+                        (Bytes::from_str("0xdac17f958d2ee523a2206206994597c13d831ec7").unwrap(), ComponentBalance {
+                            token: Bytes::from_str("0xdac17f958d2ee523a2206206994597c13d831ec7").unwrap(),
+                            balance: Bytes::from("0x00000001"),
+                            balance_float: 1.0,
+                            modify_tx: Bytes::from_str("0x0000000000000000000000000000000000000000000000000000000000000000").unwrap(),
+                            component_id: "pc_2".to_string(),
+                        }),
+                        (Bytes::from_str(USDC_ADDRESS).unwrap(), ComponentBalance {
+                            token: Bytes::from_str(USDC_ADDRESS).unwrap(),
+                            balance: Bytes::from("0x00000003"),
+                            balance_float: 3.0,
+                            modify_tx: Bytes::from_str("0x0000000000000000000000000000000000000000000000000000000000007532").unwrap(),
+                            component_id: "pc_2".to_string(),
+                        }),
+                    ])),
                 ]),
                 ..Default::default()
             };
@@ -3164,6 +3991,285 @@ mod test_serial_db {
             .await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_handle_vm_revert_balance_only_account_changes() {
+        run_against_db(|pool| async move {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("pool should get a connection");
+
+            let database_url =
+                std::env::var("DATABASE_URL").expect("Database URL must be set for testing");
+
+            for name in ["pt_1", "pt_2"] {
+                db_fixtures::insert_protocol_type(
+                    &mut conn,
+                    name,
+                    Some(FinancialType::Swap),
+                    None,
+                    Some(ImplementationType::Vm),
+                )
+                .await;
+            }
+
+            let (cached_gw, _gw_writer_thread) = GatewayBuilder::new(database_url.as_str())
+                .set_chains(&[Chain::Ethereum])
+                .set_protocol_systems(&["vm_protocol_system".to_string()])
+                .build()
+                .await
+                .unwrap();
+
+            let gw = ExtractorPgGateway::new("vm_name", Chain::Ethereum, 0, cached_gw.clone());
+            let protocol_types = HashMap::from([
+                ("pt_1".to_string(), ProtocolType::default()),
+                ("pt_2".to_string(), ProtocolType::default()),
+            ]);
+            let protocol_cache = ProtocolMemoryCache::new(
+                Chain::Ethereum,
+                chrono::Duration::seconds(900),
+                Arc::new(cached_gw),
+            );
+            let extractor = ProtocolExtractor::<
+                ExtractorPgGateway,
+                MockTokenPreProcessor,
+                MockExtractorExtension,
+            >::new(
+                gw,
+                "vm_name",
+                Chain::Ethereum,
+                ChainState::default(),
+                "vm_protocol_system".to_string(),
+                protocol_cache,
+                protocol_types,
+                get_mocked_token_pre_processor(),
+                None,
+                None,
+            )
+            .await
+            .expect("Failed to create extractor");
+
+            let account2 = Bytes::from_str("0000000000000000000000000000000000000002").unwrap();
+            let usdc = Bytes::from_str(USDC_ADDRESS).unwrap();
+            // The contract of pc_2 only changes its balance, neither its slots nor the component
+            // balances change.
+            let balance_only = tycho_substreams::BlockChanges {
+                block: Some(pb_fixtures::pb_blocks(6)),
+                changes: vec![tycho_substreams::TransactionChanges {
+                    tx: Some(pb_fixtures::pb_transactions(6, 1)),
+                    contract_changes: vec![tycho_substreams::ContractChange {
+                        address: account2.to_vec(),
+                        change: tycho_substreams::ChangeType::Update.into(),
+                        token_balances: vec![tycho_substreams::AccountBalanceChange {
+                            token: usdc.to_vec(),
+                            balance: 42_i32.to_be_bytes().to_vec(),
+                        }],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            let inputs = get_vm_inp_sequence().chain([pb_fixtures::pb_block_scoped_data(
+                balance_only,
+                Some("cursor@6"),
+                Some(3),
+            )]);
+            for inp in inputs {
+                extractor
+                    .handle_tick_scoped_data(inp)
+                    .await
+                    .unwrap();
+            }
+
+            let client_msg = extractor
+                .handle_revert(BlockUndoSignal {
+                    last_valid_block: Some(BlockRef {
+                        id: "0x0000000000000000000000000000000000000000000000000000000000000005"
+                            .to_string(),
+                        number: 5,
+                    }),
+                    last_valid_cursor: "cursor@5".into(),
+                })
+                .await
+                .unwrap()
+                .unwrap();
+
+            assert!(client_msg.revert);
+            assert!(client_msg.component_balances.is_empty());
+            assert_eq!(
+                client_msg.account_balances,
+                HashMap::from([(
+                    account2.clone(),
+                    HashMap::from([(
+                        usdc.clone(),
+                        AccountBalance {
+                            token: usdc.clone(),
+                            balance: Bytes::from("0x00000001"),
+                            modify_tx: Bytes::from(
+                                "0x000000000000000000000000000000000000000000000000000000000000c351"
+                            ),
+                            account: account2.clone(),
+                        }
+                    )])
+                )])
+            );
+        })
+        .await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_handle_vm_revert_skips_accounts_created_in_reverted_range() {
+        run_against_db(|pool| async move {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("pool should get a connection");
+
+            let database_url =
+                std::env::var("DATABASE_URL").expect("Database URL must be set for testing");
+
+            for name in ["pt_1", "pt_2"] {
+                db_fixtures::insert_protocol_type(
+                    &mut conn,
+                    name,
+                    Some(FinancialType::Swap),
+                    None,
+                    Some(ImplementationType::Vm),
+                )
+                .await;
+            }
+
+            let (cached_gw, _gw_writer_thread) = GatewayBuilder::new(database_url.as_str())
+                .set_chains(&[Chain::Ethereum])
+                .set_protocol_systems(&["vm_protocol_system".to_string()])
+                .build()
+                .await
+                .unwrap();
+
+            let gw = ExtractorPgGateway::new("vm_name", Chain::Ethereum, 0, cached_gw.clone());
+            let protocol_types = HashMap::from([
+                ("pt_1".to_string(), ProtocolType::default()),
+                ("pt_2".to_string(), ProtocolType::default()),
+            ]);
+            let protocol_cache = ProtocolMemoryCache::new(
+                Chain::Ethereum,
+                chrono::Duration::seconds(900),
+                Arc::new(cached_gw),
+            );
+            let extractor = ProtocolExtractor::<
+                ExtractorPgGateway,
+                MockTokenPreProcessor,
+                MockExtractorExtension,
+            >::new(
+                gw,
+                "vm_name",
+                Chain::Ethereum,
+                ChainState::default(),
+                "vm_protocol_system".to_string(),
+                protocol_cache,
+                protocol_types,
+                get_mocked_token_pre_processor(),
+                None,
+                None,
+            )
+            .await
+            .expect("Failed to create extractor");
+
+            let account2 = Bytes::from_str("0000000000000000000000000000000000000002").unwrap();
+            let account4 = Bytes::from_str("0000000000000000000000000000000000000004").unwrap();
+            let usdc = Bytes::from_str(USDC_ADDRESS).unwrap();
+            // pc_4 is created together with its contract account4. account2 existed before and
+            // is added to pc_4 as well.
+            let contract_change = |account: &Bytes, change: tycho_substreams::ChangeType| {
+                tycho_substreams::ContractChange {
+                    address: account.to_vec(),
+                    code: 123_i32.to_be_bytes().to_vec(),
+                    slots: vec![tycho_substreams::ContractSlot {
+                        slot: Bytes::from("0x01").into(),
+                        value: Bytes::from("0x2a").into(),
+                    }],
+                    change: change.into(),
+                    token_balances: vec![tycho_substreams::AccountBalanceChange {
+                        token: usdc.to_vec(),
+                        balance: 42_i32.to_be_bytes().to_vec(),
+                    }],
+                    ..Default::default()
+                }
+            };
+            let creation = tycho_substreams::BlockChanges {
+                block: Some(pb_fixtures::pb_blocks(6)),
+                changes: vec![tycho_substreams::TransactionChanges {
+                    tx: Some(pb_fixtures::pb_transactions(6, 1)),
+                    contract_changes: vec![
+                        contract_change(&account4, tycho_substreams::ChangeType::Creation),
+                        contract_change(&account2, tycho_substreams::ChangeType::Update),
+                    ],
+                    component_changes: vec![tycho_substreams::ProtocolComponent {
+                        id: "pc_4".to_owned(),
+                        tokens: vec![
+                            Bytes::from_str(WETH_ADDRESS)
+                                .unwrap()
+                                .to_vec(),
+                            usdc.to_vec(),
+                        ],
+                        contracts: vec![account4.to_vec(), account2.to_vec()],
+                        change: tycho_substreams::ChangeType::Creation.into(),
+                        protocol_type: Some(tycho_substreams::ProtocolType {
+                            name: "pt_1".to_string(),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            let inputs = get_vm_inp_sequence().chain([pb_fixtures::pb_block_scoped_data(
+                creation,
+                Some("cursor@6"),
+                Some(3),
+            )]);
+            for inp in inputs {
+                extractor
+                    .handle_tick_scoped_data(inp)
+                    .await
+                    .unwrap();
+            }
+
+            let client_msg = extractor
+                .handle_revert(BlockUndoSignal {
+                    last_valid_block: Some(BlockRef {
+                        id: "0x0000000000000000000000000000000000000000000000000000000000000005"
+                            .to_string(),
+                        number: 5,
+                    }),
+                    last_valid_cursor: "cursor@5".into(),
+                })
+                .await
+                .unwrap()
+                .unwrap();
+
+            assert!(client_msg.revert);
+            assert!(client_msg
+                .deleted_protocol_components
+                .contains_key("pc_4"));
+            assert!(!client_msg
+                .account_deltas
+                .contains_key(&account4));
+            assert!(!client_msg
+                .account_balances
+                .contains_key(&account4));
+            assert!(client_msg
+                .account_deltas
+                .contains_key(&account2));
+            assert!(client_msg
+                .account_balances
+                .contains_key(&account2));
+        })
+        .await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_timestamp_conflict_resolution_with_revert() {
         run_against_db(|pool| async move {
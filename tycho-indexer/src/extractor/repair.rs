@@ -0,0 +1,167 @@
+//! Re-extraction of a block range for a single extractor.
+//!
+//! If a substreams module bug corrupted the state written for some blocks, only these blocks of
+//! the affected extractor are extracted again. The state the extractor wrote from the first
+//! repaired block onwards is set aside, the range is replayed on top of the state valid before it
+//! and the state written after the range is put back. Other extractors are not affected.
+//!
+//! The replay reuses the identity of the extractor, so it must not run while the range is
+//! repaired. Its cursor is restored once the replay finished.
+use std::future::Future;
+
+use tracing::{info, warn};
+use tycho_common::{
+    models::{Chain, ExtractionState},
+    storage::{BlockIdentifier, ChainGateway, ExtractionStateGateway},
+};
+
+use crate::extractor::ExtractionError;
+
+/// Extracts the blocks `start_block..=end_block` of `extractor` again.
+///
+/// `replay` is expected to run the extractor from the cursor it finds in storage up to and
+/// including `end_block`. Fails if the replay did not reach `end_block` or if the repaired range
+/// doesn't connect to the surrounding history. In that case the state written after the range
+/// stays quarantined under the logged repair id.
+pub async fn repair_range<G, F, Fut>(
+    gw: &G,
+    chain: Chain,
+    extractor: &str,
+    start_block: i64,
+    end_block: i64,
+    replay: F,
+) -> Result<(), ExtractionError>
+where
+    G: ChainGateway + ExtractionStateGateway + Sync,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), ExtractionError>>,
+{
+    let live_state = gw.get_state(extractor, &chain).await?;
+    let parent = gw
+        .get_block(&BlockIdentifier::Number((chain, start_block - 1)))
+        .await?;
+    let last = gw
+        .get_block(&BlockIdentifier::Number((chain, end_block)))
+        .await?;
+
+    let repair_id = gw
+        .quarantine_range(&chain, extractor, start_block, end_block)
+        .await?;
+    info!(repair_id, extractor, start_block, end_block, "Quarantined range, replaying blocks");
+
+    // Without a cursor the replay starts at the configured start block, right after `parent`.
+    gw.save_state(&ExtractionState::new(
+        extractor.to_string(),
+        chain,
+        Some(live_state.attributes.clone()),
+        &[],
+        parent.hash,
+    ))
+    .await?;
+    let replayed = replay().await;
+    let replayed_state = gw.get_state(extractor, &chain).await;
+    gw.save_state(&live_state).await?;
+    if let Err(err) = replayed {
+        warn!(repair_id, error = %err, "Replay failed, the range stays quarantined");
+        return Err(err);
+    }
+    if replayed_state?.block_hash != last.hash {
+        return Err(ExtractionError::Unknown(format!(
+            "Replay of repair {repair_id} did not reach block {end_block}"
+        )));
+    }
+
+    gw.restore_quarantined_range(repair_id)
+        .await?;
+    gw.verify_range_continuity(repair_id)
+        .await?;
+    info!(repair_id, extractor, start_block, end_block, "Repaired range");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use tycho_common::Bytes;
+
+    use super::*;
+    use crate::testing::{block, MockGateway};
+
+    fn state(cursor: &str, block_hash: Bytes) -> ExtractionState {
+        ExtractionState::new(
+            "uniswap_v2".to_string(),
+            Chain::Ethereum,
+            None,
+            cursor.as_bytes(),
+            block_hash,
+        )
+    }
+
+    /// Expects the lookups and cursor updates every repair does, the live cursor is restored last.
+    fn setup_gw(replayed_to: u64) -> MockGateway {
+        let mut gw = MockGateway::new();
+        let mut states =
+            vec![state("live", block(20).hash), state("replayed", block(replayed_to).hash)]
+                .into_iter();
+        gw.expect_get_state()
+            .times(2)
+            .returning(move |_, _| Ok(states.next().unwrap()));
+        gw.expect_get_block()
+            .times(2)
+            .returning(|id| match id {
+                BlockIdentifier::Number((_, number)) => Ok(block(*number as u64)),
+                _ => panic!("blocks should be fetched by number"),
+            });
+        gw.expect_quarantine_range()
+            .withf(|chain, system, start, end| {
+                *chain == Chain::Ethereum && system == "uniswap_v2" && *start == 10 && *end == 12
+            })
+            .once()
+            .returning(|_, _, _, _| Ok(7));
+        let mut exp_saved =
+            vec![state("", block(9).hash), state("live", block(20).hash)].into_iter();
+        gw.expect_save_state()
+            .times(2)
+            .returning(move |saved| {
+                assert_eq!(saved, &exp_saved.next().unwrap());
+                Ok(())
+            });
+        gw
+    }
+
+    #[tokio::test]
+    async fn test_repair_range() {
+        let mut gw = setup_gw(12);
+        gw.expect_restore_quarantined_range()
+            .withf(|id| *id == 7)
+            .once()
+            .returning(|_| Ok(()));
+        gw.expect_verify_range_continuity()
+            .withf(|id| *id == 7)
+            .once()
+            .returning(|_| Ok(()));
+        let replayed = AtomicBool::new(false);
+
+        repair_range(&gw, Chain::Ethereum, "uniswap_v2", 10, 12, || async {
+            replayed.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+        .await
+        .expect("repair failed");
+
+        assert!(replayed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_repair_range_incomplete_replay() {
+        let mut gw = setup_gw(11);
+        gw.expect_restore_quarantined_range()
+            .never();
+
+        let res =
+            repair_range(&gw, Chain::Ethereum, "uniswap_v2", 10, 12, || async { Ok(()) }).await;
+
+        assert!(matches!(res, Err(ExtractionError::Unknown(_))));
+    }
+}
@@ -1,4 +1,9 @@
-use std::{collections::HashMap, env, path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    path::Path,
+    sync::Arc,
+};
 
 use anyhow::{format_err, Context, Result};
 use async_trait::async_trait;
@@ -18,26 +23,39 @@ use tokio::{
 use tokio_stream::StreamExt;
 use tracing::{debug, error, info, instrument, trace, warn, Instrument};
 use tycho_common::{
-    models::{Chain, ExtractorIdentity, FinancialType, ImplementationType, ProtocolType},
+    dto, keccak256,
+    models::{
+        blockchain::Block, Chain, ExtractorIdentity, FinancialType, ImplementationType,
+        ProtocolType, SubstreamsPackage,
+    },
     Bytes,
 };
 use tycho_ethereum::{
     account_extractor::contract::EVMBatchAccountExtractor,
-    entrypoint_tracer::tracer::EVMEntrypointService,
+    entrypoint_tracer::tracer::EVMEntrypointService, token_analyzer::rpc_client::EthereumRpcClient,
     token_pre_processor::EthereumTokenPreProcessor,
 };
-use tycho_storage::postgres::cache::CachedGateway;
+use tycho_storage::postgres::{cache::CachedGateway, storage_compaction::AttributeRetentionPolicy};
 
 use crate::{
     extractor::{
         chain_state::ChainState,
+        component_events::ComponentEventsConfig,
+        component_filter::ComponentFilter,
         dynamic_contract_indexer::dci::DynamicContractIndexer,
-        post_processors::POST_PROCESSOR_REGISTRY,
+        gap_healer::{BlockGapHealer, BlockGapHealingConfig},
+        lease::{ExtractorLease, DEFAULT_LEASE_TTL},
+        message_bus::{BusPublisher, MessageBus},
+        post_processors::{PostProcessorFn, POST_PROCESSOR_REGISTRY},
+        profiling::{StageProfiler, DEFAULT_PROFILE_WINDOW},
         protocol_cache::ProtocolMemoryCache,
         protocol_extractor::{ExtractorPgGateway, ProtocolExtractor},
-        ExtractionError, Extractor, ExtractorMsg,
+        simulation::{self, SimulationOutput, SimulationReport, Simulator},
+        tracked_contracts::{TrackedContractConfig, TrackedContracts},
+        DecodeFailureHandling, ExtractionError, Extractor, ExtractorMsg, MessageDecodeError,
+        TransactionSubscribers,
     },
-    pb::sf::substreams::v1::Package,
+    pb::sf::substreams::v1::{module, Package},
     substreams::{
         stream::{BlockResponse, SubstreamsStream},
         SubstreamsEndpoint,
@@ -46,6 +64,7 @@ use crate::{
 pub enum ControlMessage {
     Stop,
     Subscribe(Sender<ExtractorMsg>),
+    UpdateComponentFilter(ComponentFilter),
 }
 
 /// A trait for a message sender that can be used to subscribe to messages
@@ -54,23 +73,67 @@ pub enum ControlMessage {
 #[async_trait]
 pub trait MessageSender: Send + Sync {
     async fn subscribe(&self) -> Result<Receiver<ExtractorMsg>, SendError<ControlMessage>>;
+
+    /// Subscribes to messages that may be skipped, instead of awaited, if the subscriber falls
+    /// behind. Every message comes with the number of messages skipped right before it.
+    ///
+    /// Senders that never skip messages pair every message with zero.
+    async fn subscribe_lossy(
+        &self,
+    ) -> Result<Receiver<(u64, ExtractorMsg)>, SendError<ControlMessage>> {
+        let mut rx = self.subscribe().await?;
+        let (tx, lossy_rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            while let Some(msg) = rx.recv().await {
+                if tx.send((0, msg)).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(lossy_rx)
+    }
+
+    /// Subscribers receiving the changes of this sender per transaction, `None` if its messages
+    /// never carry the per transaction breakdown.
+    fn transaction_subscribers(&self) -> Option<TransactionSubscribers> {
+        None
+    }
 }
 
 #[derive(Clone)]
 pub struct ExtractorHandle {
     id: ExtractorIdentity,
     control_tx: Sender<ControlMessage>,
+    profiler: Arc<StageProfiler>,
+    tracked_contracts: Option<Arc<TrackedContracts>>,
+    transaction_subscribers: TransactionSubscribers,
 }
 
 impl ExtractorHandle {
-    fn new(id: ExtractorIdentity, control_tx: Sender<ControlMessage>) -> Self {
-        Self { id, control_tx }
+    fn new(
+        id: ExtractorIdentity,
+        control_tx: Sender<ControlMessage>,
+        profiler: Arc<StageProfiler>,
+        tracked_contracts: Option<Arc<TrackedContracts>>,
+        transaction_subscribers: TransactionSubscribers,
+    ) -> Self {
+        Self { id, control_tx, profiler, tracked_contracts, transaction_subscribers }
     }
 
     pub fn get_id(&self) -> ExtractorIdentity {
         self.id.clone()
     }
 
+    /// Processing time statistics of the most recent blocks.
+    pub fn profile(&self) -> dto::ExtractorProfile {
+        self.profiler.snapshot()
+    }
+
+    /// Registry of the contracts the extractor persists, `None` if it persists all contracts.
+    pub fn tracked_contracts(&self) -> Option<&TrackedContracts> {
+        self.tracked_contracts.as_deref()
+    }
+
     #[instrument(skip(self))]
     pub async fn stop(&self) -> Result<(), ExtractionError> {
         // TODO: send a oneshot along here and wait for it
@@ -79,6 +142,19 @@ impl ExtractorHandle {
             .await
             .map_err(|err| ExtractionError::Unknown(err.to_string()))
     }
+
+    /// Replaces the component allow/deny lists of the extractor. The new filter applies starting
+    /// with the next processed block.
+    #[instrument(skip(self))]
+    pub async fn update_component_filter(
+        &self,
+        filter: ComponentFilter,
+    ) -> Result<(), ExtractionError> {
+        self.control_tx
+            .send(ControlMessage::UpdateComponentFilter(filter))
+            .await
+            .map_err(|err| ExtractionError::Unknown(err.to_string()))
+    }
 }
 
 #[async_trait]
@@ -104,6 +180,20 @@ impl MessageSender for ExtractorHandle {
             Err(_) => panic!("Subscription timed out!"),
         }
     }
+
+    fn transaction_subscribers(&self) -> Option<TransactionSubscribers> {
+        Some(self.transaction_subscribers.clone())
+    }
+}
+
+/// Resolves with the error the lease heartbeat failed with, never if there is no lease.
+async fn lease_lost(heartbeat: &mut Option<JoinHandle<ExtractionError>>) -> ExtractionError {
+    match heartbeat {
+        Some(handle) => handle.await.unwrap_or_else(|err| {
+            ExtractionError::Unknown(format!("Lease heartbeat failed: {err}"))
+        }),
+        None => std::future::pending().await,
+    }
 }
 
 // Define the SubscriptionsMap type alias
@@ -118,6 +208,10 @@ pub struct ExtractorRunner {
     /// Handle of the tokio runtime on which the extraction tasks will be run.
     /// If 'None' the default runtime will be used.
     runtime_handle: Option<Handle>,
+    /// Optional bus that messages are additionally published to.
+    bus_publisher: Option<BusPublisher>,
+    /// Lease on the extractor identity, renewed while running and released on exit.
+    lease: Option<Arc<ExtractorLease>>,
 }
 
 impl ExtractorRunner {
@@ -135,9 +229,21 @@ impl ExtractorRunner {
             next_subscriber_id: 0,
             control_rx,
             runtime_handle,
+            bus_publisher: None,
+            lease: None,
         }
     }
 
+    pub fn with_bus_publisher(mut self, publisher: BusPublisher) -> Self {
+        self.bus_publisher = Some(publisher);
+        self
+    }
+
+    pub fn with_lease(mut self, lease: ExtractorLease) -> Self {
+        self.lease = Some(Arc::new(lease));
+        self
+    }
+
     pub fn run(mut self) -> JoinHandle<Result<(), ExtractionError>> {
         let runtime = self
             .runtime_handle
@@ -146,7 +252,11 @@ impl ExtractorRunner {
 
         runtime.spawn(async move {
             let id = self.extractor.get_id();
-            loop {
+            let mut heartbeat = self
+                .lease
+                .clone()
+                .map(ExtractorLease::spawn_heartbeat);
+            let res = loop {
                 // this is the main info span of an extractor
                 let loop_span = tracing::info_span!(
                     parent: None,  // don't attach this to the parent (builder) span to keep spans short
@@ -168,8 +278,16 @@ impl ExtractorRunner {
                                 ControlMessage::Subscribe(sender) => {
                                     self.subscribe(sender).await;
                                 },
+                                ControlMessage::UpdateComponentFilter(filter) => {
+                                    self.extractor.set_component_filter(filter).await;
+                                },
                             }
                         }
+                        err = lease_lost(&mut heartbeat) => {
+                            error!(error = %err, "Lost extractor lease!");
+                            tracing::Span::current().record("otel.status_code", "error");
+                            return Err(err);
+                        }
                         val = self.substreams.next() => {
                             match val {
                                 None => {
@@ -193,7 +311,8 @@ impl ExtractorRunner {
                                     match self.extractor.handle_tick_scoped_data(data.clone()).await {
                                         Ok(Some(msg)) => {
                                             trace!("Propagating new block data message.");
-                                            Self::propagate_msg(&self.subscriptions, msg).await
+                                            Self::propagate_msg(&self.subscriptions, msg.clone()).await;
+                                            self.publish_to_bus(msg);
                                         }
                                         Ok(None) => {
                                             trace!("No message to propagate.");
@@ -217,7 +336,8 @@ impl ExtractorRunner {
                                     match self.extractor.handle_revert(undo_signal.clone()).await {
                                         Ok(Some(msg)) => {
                                             trace!("Propagating block undo message.");
-                                            Self::propagate_msg(&self.subscriptions, msg).await
+                                            Self::propagate_msg(&self.subscriptions, msg.clone()).await;
+                                            self.publish_to_bus(msg);
                                         }
                                         Ok(None) => {
                                             trace!("No message to propagate.");
@@ -242,12 +362,22 @@ impl ExtractorRunner {
                     Ok(true) // Continue the loop
                 }
                 .instrument(loop_span)
-                .await?;
+                .await;
 
-                if !should_continue {
-                    break Ok(());
+                match should_continue {
+                    Ok(true) => {}
+                    Ok(false) => break Ok(()),
+                    Err(err) => break Err(err),
                 }
+            };
+
+            if let Some(heartbeat) = heartbeat {
+                heartbeat.abort();
             }
+            if let Some(lease) = &self.lease {
+                lease.release().await;
+            }
+            res
         })
     }
 
@@ -263,6 +393,14 @@ impl ExtractorRunner {
             .insert(subscriber_id, sender);
     }
 
+    /// Publishes a message to the bus once the direct subscribers received it, so bus consumers
+    /// never see a block before e.g. the pending deltas buffer was handed it.
+    fn publish_to_bus(&self, message: ExtractorMsg) {
+        if let Some(publisher) = &self.bus_publisher {
+            publisher.publish(message);
+        }
+    }
+
     // TODO: add message tracing_id to the log
     #[instrument(skip_all)]
     async fn propagate_msg(subscribers: &Arc<Mutex<SubscriptionsMap>>, message: ExtractorMsg) {
@@ -301,11 +439,42 @@ impl ExtractorRunner {
 pub struct ProtocolTypeConfig {
     name: String,
     financial_type: FinancialType,
+    /// Stored with the protocol type, e.g. to declare `indexed_attributes`.
+    #[serde(default)]
+    attribute_schema: Option<serde_json::Value>,
 }
 
 impl ProtocolTypeConfig {
     pub fn new(name: String, financial_type: FinancialType) -> Self {
-        Self { name, financial_type }
+        Self { name, financial_type, attribute_schema: None }
+    }
+}
+
+/// Downsampling of the stored attribute history of a protocol system, applied by the storage
+/// compaction job.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct AttributeRetentionConfig {
+    /// Keep one version per this many hours, the last one of each period
+    pub granularity_hours: u32,
+    /// Versions younger than this many days are kept as is
+    #[serde(default = "default_retention_min_age_days")]
+    pub min_age_days: u32,
+    /// Attributes to downsample, all attributes if empty
+    #[serde(default)]
+    pub attributes: Vec<String>,
+}
+
+fn default_retention_min_age_days() -> u32 {
+    7
+}
+
+impl From<&AttributeRetentionConfig> for AttributeRetentionPolicy {
+    fn from(value: &AttributeRetentionConfig) -> Self {
+        Self {
+            granularity: chrono::Duration::hours(value.granularity_hours.into()),
+            min_age: chrono::Duration::days(value.min_age_days.into()),
+            attributes: value.attributes.clone(),
+        }
     }
 }
 
@@ -328,6 +497,32 @@ pub struct ExtractorConfig {
     pub post_processor: Option<String>,
     #[serde(default)]
     pub dci_plugin: Option<DCIType>,
+    /// Allow/deny lists for the components emitted by the substreams package.
+    #[serde(default)]
+    pub component_filter: ComponentFilter,
+    /// Event logs to store for the emitted components, none by default.
+    #[serde(default)]
+    pub component_events: ComponentEventsConfig,
+    /// Substreams endpoints in order of preference. The stream fails over to the next one on
+    /// persistent errors and resumes from its cursor. Defaults to the global endpoint.
+    #[serde(default)]
+    pub substreams_endpoints: Vec<String>,
+    /// Contracts to persist changes of besides those held by components. If set, changes of any
+    /// other contract are dropped, if unset all contract changes are persisted.
+    #[serde(default)]
+    pub tracked_contracts: Option<Vec<TrackedContractConfig>>,
+    /// Downsampling of the attribute history of this protocol system, full history if unset.
+    #[serde(default)]
+    pub attribute_retention: Option<AttributeRetentionConfig>,
+    /// Fetches the headers of blocks the package skipped from the RPC node. Requires the global
+    /// RPC URL, if unset the package must emit every block.
+    #[serde(default)]
+    pub block_gap_healing: Option<BlockGapHealingConfig>,
+    /// How decode failures are handled by error kind, e.g. `overflow: skip`. Failures of unlisted
+    /// kinds stop the extractor. Skipped blocks lose their changes, quarantined blocks are stored
+    /// undecoded for a later replay.
+    #[serde(default)]
+    pub decode_failures: HashMap<String, DecodeFailureHandling>,
 }
 
 impl ExtractorConfig {
@@ -361,7 +556,83 @@ impl ExtractorConfig {
             initialized_accounts_block,
             post_processor,
             dci_plugin,
+            component_filter: ComponentFilter::default(),
+            component_events: ComponentEventsConfig::default(),
+            substreams_endpoints: Vec::new(),
+            tracked_contracts: None,
+            attribute_retention: None,
+            block_gap_healing: None,
+            decode_failures: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn chain(&self) -> Chain {
+        self.chain
+    }
+
+    /// Protocol types of the extractor by name.
+    pub(crate) fn protocol_types(&self) -> HashMap<String, ProtocolType> {
+        self.protocol_types
+            .iter()
+            .map(|pt| {
+                (
+                    pt.name.clone(),
+                    ProtocolType::new(
+                        pt.name.clone(),
+                        pt.financial_type.clone(),
+                        pt.attribute_schema.clone(),
+                        self.implementation_type.clone(),
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    /// Looks up the configured post processor in the registry.
+    pub(crate) fn post_processor_fn(&self) -> Result<Option<PostProcessorFn>, ExtractionError> {
+        self.post_processor
+            .as_ref()
+            .map(|name| {
+                POST_PROCESSOR_REGISTRY
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        ExtractionError::Setup(format!(
+                            "Post processor '{name}' not found in registry"
+                        ))
+                    })
+            })
+            .transpose()
+    }
+
+    /// Decode failure handling by error kind, rejects unknown kinds.
+    pub(crate) fn decode_failures(
+        &self,
+    ) -> Result<HashMap<String, DecodeFailureHandling>, ExtractionError> {
+        if let Some(kind) = self
+            .decode_failures
+            .keys()
+            .find(|kind| !MessageDecodeError::KINDS.contains(&kind.as_str()))
+        {
+            return Err(ExtractionError::Setup(format!(
+                "Unknown decode failure kind '{kind}', expected one of {:?}",
+                MessageDecodeError::KINDS
+            )));
         }
+        Ok(self.decode_failures.clone())
+    }
+
+    /// Restricts the extractor to the blocks `start_block..=end_block`, committing every block.
+    pub fn for_block_range(mut self, start_block: i64, end_block: i64) -> Self {
+        self.start_block = start_block;
+        // The substreams stop block is exclusive.
+        self.stop_block = Some(end_block + 1);
+        self.sync_batch_size = 1;
+        self
     }
 }
 
@@ -383,6 +654,12 @@ pub struct ExtractorBuilder {
     runtime_handle: Option<Handle>,
     /// Global RPC URL to use for DCI plugins
     rpc_url: Option<String>,
+    /// Bus the extractor publishes its messages to, if any.
+    message_bus: Option<Arc<MessageBus>>,
+    profiler: Arc<StageProfiler>,
+    tracked_contracts: Option<Arc<TrackedContracts>>,
+    lease: Option<ExtractorLease>,
+    transaction_subscribers: TransactionSubscribers,
 }
 
 pub type HandleResult = (JoinHandle<Result<(), ExtractionError>>, ExtractorHandle);
@@ -398,6 +675,14 @@ impl ExtractorBuilder {
             final_block_only: false,
             runtime_handle: None,
             rpc_url: None,
+            message_bus: None,
+            profiler: Arc::new(StageProfiler::new(
+                ExtractorIdentity::new(config.chain, &config.name),
+                DEFAULT_PROFILE_WINDOW,
+            )),
+            tracked_contracts: None,
+            lease: None,
+            transaction_subscribers: TransactionSubscribers::default(),
         }
     }
 
@@ -438,6 +723,12 @@ impl ExtractorBuilder {
         self
     }
 
+    /// Publish extractor messages to the given bus, in addition to direct subscribers.
+    pub fn message_bus(mut self, bus: Arc<MessageBus>) -> Self {
+        self.message_bus = Some(bus);
+        self
+    }
+
     #[cfg(test)]
     pub fn set_extractor(mut self, val: Arc<dyn Extractor>) -> Self {
         self.extractor = Some(val);
@@ -475,22 +766,24 @@ impl ExtractorBuilder {
         token_pre_processor: &EthereumTokenPreProcessor,
         protocol_cache: &ProtocolMemoryCache,
     ) -> Result<Self, ExtractionError> {
-        let protocol_types = self
-            .config
-            .protocol_types
-            .iter()
-            .map(|pt| {
-                (
-                    pt.name.clone(),
-                    ProtocolType::new(
-                        pt.name.clone(),
-                        pt.financial_type.clone(),
-                        None,
-                        self.config.implementation_type.clone(),
-                    ),
-                )
-            })
-            .collect();
+        // Claim the identity before touching any state so a second instance fails early.
+        self.lease = Some(
+            ExtractorLease::acquire(
+                cached_gw.clone(),
+                ExtractorIdentity::new(self.config.chain, &self.config.name),
+                DEFAULT_LEASE_TTL,
+            )
+            .await?,
+        );
+
+        if let Some(bus) = &self.message_bus {
+            self.transaction_subscribers = bus.transaction_subscribers(&ExtractorIdentity::new(
+                self.config.chain,
+                &self.config.name,
+            ));
+        }
+
+        let protocol_types = self.config.protocol_types();
 
         let gw = ExtractorPgGateway::new(
             &self.config.name,
@@ -499,21 +792,24 @@ impl ExtractorBuilder {
             cached_gw.clone(),
         );
 
-        let post_processor = self
-            .config
-            .post_processor
-            .as_ref()
-            .map(|name| {
-                POST_PROCESSOR_REGISTRY
-                    .get(name)
-                    .cloned()
-                    .ok_or_else(|| {
-                        ExtractionError::Setup(format!(
-                            "Post processor '{name}' not found in registry"
-                        ))
-                    })
-            })
-            .transpose()?;
+        if let Some(contracts) = &self.config.tracked_contracts {
+            let registry = TrackedContracts::new(contracts).with_store(
+                cached_gw.clone(),
+                &self.config.name,
+                self.config.chain,
+            );
+            registry.restore().await?;
+            for address in gw
+                .get_component_contracts(&self.config.name)
+                .await?
+            {
+                registry.add(address, 0);
+            }
+            self.tracked_contracts = Some(Arc::new(registry));
+        }
+
+        let post_processor = self.config.post_processor_fn()?;
+        let decode_failures = self.config.decode_failures()?;
 
         let dci_plugin = if let Some(ref dci_type) = self.config.dci_plugin {
             Some(match dci_type {
@@ -552,83 +848,245 @@ impl ExtractorBuilder {
             None
         };
 
-        self.extractor = Some(Arc::new(
-            ProtocolExtractor::<
-                ExtractorPgGateway,
-                EthereumTokenPreProcessor,
-                DynamicContractIndexer<
-                    EVMBatchAccountExtractor,
-                    EVMEntrypointService,
-                    CachedGateway,
-                >,
-            >::new(
-                gw,
-                &self.config.name,
+        let mut extractor = ProtocolExtractor::<
+            ExtractorPgGateway,
+            EthereumTokenPreProcessor,
+            DynamicContractIndexer<EVMBatchAccountExtractor, EVMEntrypointService, CachedGateway>,
+        >::new(
+            gw,
+            &self.config.name,
+            self.config.chain,
+            chain_state,
+            self.config.name.clone(),
+            protocol_cache.clone(),
+            protocol_types,
+            token_pre_processor.clone(),
+            post_processor,
+            dci_plugin,
+        )
+        .await?
+        .with_component_filter(self.config.component_filter.clone())
+        .with_component_events(self.config.component_events.clone())
+        .with_decode_failures(decode_failures)
+        .with_profiler(self.profiler.clone())
+        .with_transaction_subscribers(self.transaction_subscribers.clone());
+        if let Some(tracked_contracts) = &self.tracked_contracts {
+            extractor = extractor.with_tracked_contracts(tracked_contracts.clone());
+        }
+        if let Some(healing) = &self.config.block_gap_healing {
+            let rpc_url = self.rpc_url.as_ref().ok_or_else(|| {
+                ExtractionError::Setup(
+                    "RPC URL is required for block gap healing but not provided".to_string(),
+                )
+            })?;
+            extractor = extractor.with_gap_healer(BlockGapHealer::new(
                 self.config.chain,
-                chain_state,
-                self.config.name.clone(),
-                protocol_cache.clone(),
-                protocol_types,
-                token_pre_processor.clone(),
-                post_processor,
-                dci_plugin,
-            )
-            .await?,
-        ));
+                Arc::new(EthereumRpcClient::new_from_url(rpc_url)),
+                healing.max_gap,
+            ));
+        }
+        self.extractor = Some(Arc::new(extractor));
 
         Ok(self)
     }
 
-    #[instrument(name = "extractor_start", skip(self), fields(id))]
-    pub async fn run(self) -> Result<HandleResult, ExtractionError> {
-        let extractor = self
-            .extractor
-            .clone()
-            .expect("Extractor not set");
-        let extractor_id = extractor.get_id();
-
-        tracing::Span::current().record("id", format!("{extractor_id}"));
-
+    /// Reads the configured substreams package, downloading it first if missing.
+    async fn load_package(&self) -> Result<Package, ExtractionError> {
         self.ensure_spkg().await?;
 
         let content = std::fs::read(&self.config.spkg)
             .context(format_err!("read package from file '{}'", self.config.spkg))
             .map_err(|err| ExtractionError::SubstreamsError(err.to_string()))?;
-        let spkg = Package::decode(content.as_ref())
+        Package::decode(content.as_ref())
             .context("decode command")
-            .map_err(|err| ExtractionError::SubstreamsError(err.to_string()))?;
-        let endpoint = Arc::new(
-            SubstreamsEndpoint::new(&self.endpoint_url, Some(self.token))
-                .await
-                .map_err(|err| ExtractionError::SubstreamsError(err.to_string()))?,
-        );
+            .map_err(|err| ExtractionError::SubstreamsError(err.to_string()))
+    }
 
-        let cursor = extractor.get_cursor().await;
-        let stream = SubstreamsStream::new(
-            endpoint,
-            Some(cursor),
+    /// Opens the substreams stream of the configured module, starting from `cursor` if given.
+    /// `last_block` is the block stored with the cursor, used to resume should it be invalidated.
+    async fn open_stream(
+        &self,
+        spkg: &Package,
+        cursor: Option<String>,
+        last_block: Option<Block>,
+        stream_id: String,
+    ) -> Result<SubstreamsStream, ExtractionError> {
+        let urls = if self
+            .config
+            .substreams_endpoints
+            .is_empty()
+        {
+            std::slice::from_ref(&self.endpoint_url)
+        } else {
+            self.config
+                .substreams_endpoints
+                .as_slice()
+        };
+        let mut endpoints = Vec::with_capacity(urls.len());
+        for url in urls {
+            endpoints.push(Arc::new(
+                SubstreamsEndpoint::new(url, Some(self.token.clone()))
+                    .await
+                    .map_err(|err| ExtractionError::SubstreamsError(err.to_string()))?,
+            ));
+        }
+
+        Ok(SubstreamsStream::new(
+            endpoints,
+            cursor,
+            last_block,
             spkg.modules.clone(),
-            self.config.module_name,
+            self.config.module_name.clone(),
             self.config.start_block,
             self.config.stop_block.unwrap_or(0) as u64,
             self.final_block_only,
-            extractor_id.to_string(),
+            stream_id,
+        ))
+    }
+
+    /// Streams the configured module through a [`Simulator`] instead of an extractor and writes
+    /// the simulated blocks to `out`. Neither a database nor a node is used.
+    pub async fn simulate(
+        self,
+        output: SimulationOutput,
+        out: impl std::io::Write,
+    ) -> Result<SimulationReport, ExtractionError> {
+        let simulator = Simulator::new(
+            &self.config.name,
+            self.config.chain,
+            self.config.protocol_types(),
+            self.config.post_processor_fn()?,
+            output,
         );
+        let spkg = self.load_package().await?;
+        let stream = self
+            .open_stream(
+                &spkg,
+                None,
+                None,
+                format!("{}:{}:simulation", self.config.chain, self.config.name),
+            )
+            .await?;
+        simulation::simulate(stream, simulator, out).await
+    }
+
+    #[instrument(name = "extractor_start", skip(self), fields(id))]
+    pub async fn run(self) -> Result<HandleResult, ExtractionError> {
+        let extractor = self
+            .extractor
+            .clone()
+            .expect("Extractor not set");
+        let extractor_id = extractor.get_id();
+
+        tracing::Span::current().record("id", format!("{extractor_id}"));
+
+        let cursor = extractor.get_cursor().await;
+        let last_block = extractor
+            .get_last_processed_block()
+            .await;
+        let spkg = self.load_package().await?;
+        extractor
+            .record_substreams_package(substreams_package(&spkg, &self.config.module_name)?)
+            .await?;
+        let stream = self
+            .open_stream(&spkg, Some(cursor), last_block, extractor_id.to_string())
+            .await?;
 
         let (ctrl_tx, ctrl_rx) = mpsc::channel(128);
-        let runner = ExtractorRunner::new(
+        let mut runner = ExtractorRunner::new(
             extractor,
             stream,
             Arc::new(Mutex::new(HashMap::new())),
             ctrl_rx,
             self.runtime_handle,
         );
+        if let Some(bus) = &self.message_bus {
+            runner = runner.with_bus_publisher(bus.publisher(extractor_id.clone()));
+        }
+        if let Some(lease) = self.lease {
+            runner = runner.with_lease(lease);
+        }
 
         let handle = runner.run();
-        Ok((handle, ExtractorHandle::new(extractor_id, ctrl_tx)))
+        Ok((
+            handle,
+            ExtractorHandle::new(
+                extractor_id,
+                ctrl_tx,
+                self.profiler,
+                self.tracked_contracts,
+                self.transaction_subscribers,
+            ),
+        ))
     }
 }
 
+/// Identifies the package and output module an extractor streams from.
+///
+/// The module hash is a keccak256 digest over the output module and every module it reads from,
+/// each with its code. It changes whenever the data emitted by the module may change, while edits
+/// to unrelated modules or metadata of the package leave it untouched.
+fn substreams_package(
+    spkg: &Package,
+    module_name: &str,
+) -> Result<SubstreamsPackage, ExtractionError> {
+    let modules = spkg
+        .modules
+        .as_ref()
+        .ok_or_else(|| ExtractionError::SubstreamsError("Package contains no modules".into()))?;
+    let by_name = modules
+        .modules
+        .iter()
+        .map(|module| (module.name.as_str(), module))
+        .collect::<HashMap<_, _>>();
+
+    // modules the output module depends on, sorted by name to make the digest deterministic
+    let mut dependencies = BTreeMap::new();
+    let mut pending = vec![module_name];
+    while let Some(name) = pending.pop() {
+        if dependencies.contains_key(name) {
+            continue;
+        }
+        let module = by_name.get(name).ok_or_else(|| {
+            ExtractionError::SubstreamsError(format!("Module {name} not found in package"))
+        })?;
+        dependencies.insert(name, *module);
+        pending.extend(
+            module
+                .inputs
+                .iter()
+                .filter_map(|input| match &input.input {
+                    Some(module::input::Input::Map(map)) => Some(map.module_name.as_str()),
+                    Some(module::input::Input::Store(store)) => Some(store.module_name.as_str()),
+                    _ => None,
+                }),
+        );
+    }
+
+    let mut preimage = Vec::new();
+    for module in dependencies.values() {
+        preimage.extend(module.encode_to_vec());
+        if let Some(binary) = modules
+            .binaries
+            .get(module.binary_index as usize)
+        {
+            preimage.extend(&binary.content);
+        }
+    }
+    let meta = spkg.package_meta.first();
+    Ok(SubstreamsPackage {
+        name: meta
+            .map(|meta| meta.name.clone())
+            .unwrap_or_default(),
+        version: meta
+            .map(|meta| meta.version.clone())
+            .unwrap_or_default(),
+        module_name: module_name.to_string(),
+        module_hash: keccak256(preimage).into(),
+        from_block: None,
+    })
+}
+
 async fn download_file_from_s3(
     bucket: &str,
     key: &str,
@@ -668,7 +1126,10 @@ async fn download_file_from_s3(
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::extractor::MockExtractor;
+    use crate::{
+        extractor::MockExtractor,
+        pb::sf::substreams::v1::{Binary, Module, Modules, PackageMetadata},
+    };
 
     #[tokio::test]
     async fn test_extractor_runner_builder() {
@@ -677,6 +1138,9 @@ mod test {
         mock_extractor
             .expect_get_cursor()
             .returning(|| "cursor@0".to_string());
+        mock_extractor
+            .expect_get_last_processed_block()
+            .returning(|| None);
         mock_extractor
             .expect_get_id()
             .returning(ExtractorIdentity::default);
@@ -690,6 +1154,7 @@ mod test {
                 protocol_types: vec![ProtocolTypeConfig {
                     name: "test_module_pool".to_owned(),
                     financial_type: FinancialType::Swap,
+                    attribute_schema: None,
                 }],
                 spkg: "./test/spkg/substreams-ethereum-quickstart-v1.0.0.spkg".to_owned(),
                 module_name: "test_module".to_owned(),
@@ -715,4 +1180,56 @@ mod test {
             }
         }
     }
+
+    fn test_package(binaries: &[&[u8]]) -> Package {
+        let map_input = |name: &str| module::Input {
+            input: Some(module::input::Input::Map(module::input::Map {
+                module_name: name.to_string(),
+            })),
+        };
+        let modules = vec![
+            Module { name: "map_blocks".to_string(), binary_index: 0, ..Default::default() },
+            Module {
+                name: "map_pool_events".to_string(),
+                binary_index: 0,
+                inputs: vec![map_input("map_blocks")],
+                ..Default::default()
+            },
+            Module { name: "map_unrelated".to_string(), binary_index: 1, ..Default::default() },
+        ];
+        Package {
+            modules: Some(Modules {
+                modules,
+                binaries: binaries
+                    .iter()
+                    .map(|content| Binary {
+                        r#type: "wasm/rust-v1".to_string(),
+                        content: content.to_vec(),
+                    })
+                    .collect(),
+            }),
+            package_meta: vec![PackageMetadata {
+                name: "ethereum_uniswap_v2".to_string(),
+                version: "v0.1.0".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_substreams_package() {
+        let package = substreams_package(&test_package(&[b"a", b"b"]), "map_pool_events").unwrap();
+        // code of modules the output module does not read from is not part of the hash
+        let unrelated_changed =
+            substreams_package(&test_package(&[b"a", b"c"]), "map_pool_events").unwrap();
+        let dependency_changed =
+            substreams_package(&test_package(&[b"c", b"b"]), "map_pool_events").unwrap();
+
+        assert_eq!(package.name, "ethereum_uniswap_v2");
+        assert_eq!(package.version, "v0.1.0");
+        assert_eq!(package.module_hash, unrelated_changed.module_hash);
+        assert_ne!(package.module_hash, dependency_changed.module_hash);
+        assert!(substreams_package(&test_package(&[b"a", b"b"]), "map_missing").is_err());
+    }
 }
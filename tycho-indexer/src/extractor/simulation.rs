@@ -0,0 +1,451 @@
+//! Extractor simulation without a database.
+//!
+//! Lets protocol teams check the output of their substreams package against the decoder of the
+//! indexer before it is deployed. Every block is decoded, post processed and aggregated the same
+//! way the [`ProtocolExtractor`](super::protocol_extractor::ProtocolExtractor) does, then validated
+//! and written out instead of being persisted. Anything that needs storage or a node is left out:
+//! tokens are not fetched, the DCI does not run and component filters are not applied.
+//!
+//! Validation only knows about the components created during the simulation. Updates of other
+//! components are expected when starting after their creation, so they are only logged and not
+//! reported as issues.
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use tycho_common::{
+    dto,
+    models::{Chain, ComponentId, ProtocolType},
+    Bytes,
+};
+
+use crate::{
+    extractor::{
+        models::BlockChanges, post_processors::PostProcessorFn,
+        protocol_extractor::decode_block_changes, ExtractionError,
+    },
+    pb::sf::substreams::rpc::v2::{BlockScopedData, BlockUndoSignal},
+    substreams::stream::{BlockResponse, SubstreamsStream},
+};
+
+/// How simulated blocks are written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SimulationOutput {
+    /// One line per block counting its changes, followed by any validation issues.
+    #[default]
+    Summary,
+    /// One aggregated [`dto::BlockChanges`] message per line, issues are logged.
+    Json,
+}
+
+/// Totals of a simulation run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimulationReport {
+    pub blocks: u64,
+    pub empty_blocks: u64,
+    pub reverts: u64,
+    pub new_components: u64,
+    /// Blocks that failed to decode.
+    pub decode_errors: u64,
+    /// Validation issues across all blocks.
+    pub issues: u64,
+}
+
+pub struct Simulator {
+    name: String,
+    chain: Chain,
+    protocol_types: HashMap<String, ProtocolType>,
+    post_processor: Option<PostProcessorFn>,
+    output: SimulationOutput,
+    last_block: Option<u64>,
+    /// Tokens of the components created during the simulation and the block they were created at.
+    components: HashMap<ComponentId, (u64, HashSet<Bytes>)>,
+    report: SimulationReport,
+}
+
+impl Simulator {
+    pub fn new(
+        name: &str,
+        chain: Chain,
+        protocol_types: HashMap<String, ProtocolType>,
+        post_processor: Option<PostProcessorFn>,
+        output: SimulationOutput,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            chain,
+            protocol_types,
+            post_processor,
+            output,
+            last_block: None,
+            components: HashMap::new(),
+            report: SimulationReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &SimulationReport {
+        &self.report
+    }
+
+    /// Simulates a block, returns the lines to write out for it.
+    pub fn handle_block(&mut self, inp: &BlockScopedData) -> Result<Vec<String>, ExtractionError> {
+        let block_number = inp.clock.as_ref().map(|c| c.number);
+        let Some(data) = inp
+            .output
+            .as_ref()
+            .and_then(|output| output.map_output.as_ref())
+        else {
+            self.report.empty_blocks += 1;
+            return Ok(Vec::new());
+        };
+        let msg = match decode_block_changes(
+            data,
+            &self.name,
+            self.chain,
+            &self.name,
+            &self.protocol_types,
+            inp.final_block_height,
+        ) {
+            Ok(msg) => msg,
+            Err(ExtractionError::Empty) => {
+                self.report.empty_blocks += 1;
+                return Ok(Vec::new());
+            }
+            Err(ExtractionError::DecodeError(err)) => {
+                self.report.decode_errors += 1;
+                warn!(block_number, kind = err.kind(), %err, "Failed to decode block");
+                return Ok(match self.output {
+                    SimulationOutput::Summary => vec![format!(
+                        "block {}: failed to decode ({}): {err}",
+                        block_number.map_or("?".to_string(), |n| n.to_string()),
+                        err.kind()
+                    )],
+                    SimulationOutput::Json => Vec::new(),
+                });
+            }
+            Err(err) => return Err(err),
+        };
+        let msg =
+            if let Some(post_process_f) = self.post_processor { post_process_f(msg) } else { msg };
+        self.process(msg)
+    }
+
+    /// Forgets the blocks after the last valid block of the undo signal.
+    pub fn handle_revert(&mut self, inp: &BlockUndoSignal) {
+        let Some(block) = &inp.last_valid_block else {
+            return;
+        };
+        warn!(block_number = block.number, "Reverting simulated blocks");
+        self.report.reverts += 1;
+        self.last_block = Some(block.number);
+        self.components
+            .retain(|_, (created_at, _)| *created_at <= block.number);
+    }
+
+    fn process(&mut self, mut msg: BlockChanges) -> Result<Vec<String>, ExtractionError> {
+        let issues = self.validate(&msg);
+        self.report.blocks += 1;
+        self.report.issues += issues.len() as u64;
+        self.last_block = Some(msg.block.number);
+        // Aggregation requires ordered transactions, misordered ones were reported already.
+        msg.txs_with_update
+            .sort_by_key(|tx| tx.tx.index);
+
+        let n_txs = msg.txs_with_update.len();
+        let changes = msg.aggregate_updates()?;
+        self.report.new_components += changes.new_protocol_components.len() as u64;
+        Ok(match self.output {
+            SimulationOutput::Summary => {
+                let mut lines = vec![format!(
+                    "block {} ({:x}): {} txs, {} new components, {} state updates, \
+                     {} account updates, {} balance changes",
+                    changes.block.number,
+                    changes.block.hash,
+                    n_txs,
+                    changes.new_protocol_components.len(),
+                    changes.state_deltas.len(),
+                    changes.account_deltas.len(),
+                    changes
+                        .component_balances
+                        .values()
+                        .map(HashMap::len)
+                        .sum::<usize>(),
+                )];
+                lines.extend(
+                    issues
+                        .into_iter()
+                        .map(|issue| format!("  issue: {issue}")),
+                );
+                lines
+            }
+            SimulationOutput::Json => {
+                for issue in &issues {
+                    warn!(block_number = changes.block.number, %issue, "Validation issue");
+                }
+                let message = dto::BlockChanges::from(changes);
+                vec![serde_json::to_string(&message).map_err(|err| {
+                    ExtractionError::Unknown(format!("Failed to serialize block changes: {err}"))
+                })?]
+            }
+        })
+    }
+
+    /// Checks a block against the blocks simulated before it, records its new components.
+    fn validate(&mut self, msg: &BlockChanges) -> Vec<String> {
+        let mut issues = Vec::new();
+        if let Some(last_block) = self
+            .last_block
+            .filter(|last| msg.block.number <= *last)
+        {
+            issues.push(format!("block does not follow the previous block {last_block}"));
+        }
+
+        let mut last_index = None;
+        let mut unknown_components = HashSet::new();
+        for tx in &msg.txs_with_update {
+            if last_index.is_some_and(|index| tx.tx.index <= index) {
+                issues.push(format!("transaction {:x} is out of order", tx.tx.hash));
+            }
+            last_index = Some(tx.tx.index);
+
+            for component in tx.protocol_components.values() {
+                if self
+                    .components
+                    .insert(
+                        component.id.clone(),
+                        (
+                            msg.block.number,
+                            component
+                                .tokens
+                                .iter()
+                                .cloned()
+                                .collect(),
+                        ),
+                    )
+                    .is_some()
+                {
+                    issues.push(format!("component {} is created again", component.id));
+                }
+            }
+            for component_id in tx.state_updates.keys() {
+                if !self
+                    .components
+                    .contains_key(component_id)
+                {
+                    unknown_components.insert(component_id);
+                }
+            }
+            for (component_id, balances) in &tx.balance_changes {
+                let Some((_, tokens)) = self.components.get(component_id) else {
+                    unknown_components.insert(component_id);
+                    continue;
+                };
+                for token in balances.keys() {
+                    if !tokens.contains(token) {
+                        issues.push(format!(
+                            "balance of {token:x} changed on component {component_id} which \
+                             does not hold the token"
+                        ));
+                    }
+                }
+            }
+        }
+        if !unknown_components.is_empty() {
+            info!(
+                block_number = msg.block.number,
+                n_components = unknown_components.len(),
+                "Updated components created before the simulation"
+            );
+        }
+        issues
+    }
+}
+
+/// Feeds the blocks of `stream` through `simulator` and writes the resulting lines to `out` until
+/// the stream reaches its stop block.
+pub async fn simulate(
+    mut stream: SubstreamsStream,
+    mut simulator: Simulator,
+    mut out: impl Write,
+) -> Result<SimulationReport, ExtractionError> {
+    while let Some(response) = stream.next().await {
+        let lines =
+            match response.map_err(|err| ExtractionError::SubstreamsError(err.to_string()))? {
+                BlockResponse::New(data) => simulator.handle_block(&data)?,
+                BlockResponse::Undo(signal) => {
+                    simulator.handle_revert(&signal);
+                    Vec::new()
+                }
+            };
+        for line in lines {
+            writeln!(out, "{line}").map_err(|err| {
+                ExtractionError::Unknown(format!("Failed to write simulation output: {err}"))
+            })?;
+        }
+    }
+    Ok(simulator.report)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use tycho_common::models::{
+        blockchain::{Block, TxWithChanges},
+        protocol::{ComponentBalance, ProtocolComponent, ProtocolComponentStateDelta},
+        ChangeType,
+    };
+
+    use super::*;
+    use crate::extractor::models::fixtures::create_transaction;
+
+    const TOKEN: &str = "0x6b175474e89094c44da98b954eedeac495271d0f";
+    const OTHER_TOKEN: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+
+    fn block_changes(number: u64, txs: Vec<TxWithChanges>) -> BlockChanges {
+        BlockChanges::new(
+            "test".to_string(),
+            Chain::Ethereum,
+            Block::new(
+                number,
+                Chain::Ethereum,
+                Bytes::from(number).lpad(32, 0),
+                Bytes::from(number - 1).lpad(32, 0),
+                Default::default(),
+            ),
+            number,
+            false,
+            txs,
+            Vec::new(),
+        )
+    }
+
+    fn tx_with_changes(index: u64, block: u64) -> TxWithChanges {
+        TxWithChanges {
+            tx: create_transaction(
+                &Bytes::from(block * 100 + index)
+                    .lpad(32, 0)
+                    .to_string(),
+                &Bytes::from(block)
+                    .lpad(32, 0)
+                    .to_string(),
+                index,
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn balance(
+        component_id: &str,
+        token: &str,
+    ) -> HashMap<ComponentId, HashMap<Bytes, ComponentBalance>> {
+        let token = Bytes::from_str(token).unwrap();
+        HashMap::from([(
+            component_id.to_string(),
+            HashMap::from([(
+                token.clone(),
+                ComponentBalance {
+                    token,
+                    balance: Bytes::from(1_u64),
+                    balance_float: 1.0,
+                    modify_tx: Bytes::zero(32),
+                    component_id: component_id.to_string(),
+                },
+            )]),
+        )])
+    }
+
+    #[test]
+    fn test_simulator_validates_blocks() {
+        let mut simulator = Simulator::new(
+            "test",
+            Chain::Ethereum,
+            HashMap::new(),
+            None,
+            SimulationOutput::Summary,
+        );
+        let mut creation = tx_with_changes(1, 10);
+        creation.protocol_components = HashMap::from([(
+            "pool".to_string(),
+            ProtocolComponent {
+                id: "pool".to_string(),
+                tokens: vec![Bytes::from_str(TOKEN).unwrap()],
+                change: ChangeType::Creation,
+                ..Default::default()
+            },
+        )]);
+        creation.balance_changes = balance("pool", TOKEN);
+        let mut update = tx_with_changes(2, 10);
+        update.state_updates = HashMap::from([(
+            "old_pool".to_string(),
+            ProtocolComponentStateDelta::new("old_pool", HashMap::new(), HashSet::new()),
+        )]);
+        update.balance_changes = balance("pool", OTHER_TOKEN);
+
+        let first = simulator
+            .process(block_changes(10, vec![creation, update]))
+            .unwrap();
+        let repeated = simulator
+            .process(block_changes(10, vec![tx_with_changes(1, 10)]))
+            .unwrap();
+
+        assert_eq!(
+            first,
+            vec![
+                format!(
+                    "block 10 ({:x}): 2 txs, 1 new components, 1 state updates, 0 account \
+                     updates, 2 balance changes",
+                    Bytes::from(10_u64).lpad(32, 0)
+                ),
+                format!(
+                    "  issue: balance of {OTHER_TOKEN} changed on component pool which does not \
+                     hold the token"
+                ),
+            ]
+        );
+        assert_eq!(repeated[1], "  issue: block does not follow the previous block 10");
+        assert_eq!(
+            simulator.report(),
+            &SimulationReport { blocks: 2, new_components: 1, issues: 2, ..Default::default() }
+        );
+    }
+
+    #[test]
+    fn test_simulator_reverts_components() {
+        let mut simulator =
+            Simulator::new("test", Chain::Ethereum, HashMap::new(), None, SimulationOutput::Json);
+        let mut creation = tx_with_changes(1, 11);
+        creation.protocol_components = HashMap::from([(
+            "pool".to_string(),
+            ProtocolComponent { id: "pool".to_string(), ..Default::default() },
+        )]);
+
+        let lines = simulator
+            .process(block_changes(11, vec![creation.clone()]))
+            .unwrap();
+        simulator.handle_revert(&BlockUndoSignal {
+            last_valid_block: Some(crate::pb::sf::substreams::v1::BlockRef {
+                id: Bytes::from(10_u64)
+                    .lpad(32, 0)
+                    .to_string(),
+                number: 10,
+            }),
+            last_valid_cursor: String::new(),
+        });
+        simulator
+            .process(block_changes(11, vec![creation]))
+            .unwrap();
+
+        let message: dto::BlockChanges = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(message.block.number, 11);
+        assert!(message
+            .new_protocol_components
+            .contains_key("pool"));
+        // The component of the reverted block may be created again
+        assert_eq!(simulator.report().issues, 0);
+        assert_eq!(simulator.report().reverts, 1);
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use chrono::Utc;
+use futures03::{stream, StreamExt};
+use tracing::{info, warn};
+use tycho_common::{
+    models::{blockchain::BlockTag, protocol::QualityRange, PaginationParams},
+    storage::ProtocolGateway,
+    traits::TokenSupplyFetcher,
+};
+
+use crate::cli::TokenSupplyArgs;
+
+/// Snapshots the total supply of all stored tokens of sufficient quality.
+///
+/// Supplies are read at the latest block and stored with the current time, failed reads are
+/// logged and skipped.
+pub async fn snapshot_token_supplies(
+    args: TokenSupplyArgs,
+    gw: Arc<dyn ProtocolGateway + Send + Sync>,
+    fetcher: Arc<dyn TokenSupplyFetcher>,
+) -> anyhow::Result<()> {
+    let mut page = 0;
+    let page_size = args.fetch_batch_size as i64;
+    loop {
+        let start = Instant::now();
+        let pagination_params = PaginationParams::new(page, page_size);
+        let tokens = gw
+            .get_tokens(
+                args.chain,
+                None,
+                QualityRange::min_only(args.min_quality),
+                None,
+                Some(&pagination_params),
+            )
+            .await?
+            .entity;
+
+        let snapshot_ts = Utc::now().naive_utc();
+        let supplies = stream::iter(tokens.iter())
+            .map(|token| {
+                let fetcher = fetcher.clone();
+                async move {
+                    match fetcher
+                        .get_total_supply(token.address.clone(), BlockTag::Latest)
+                        .await
+                    {
+                        Ok(supply) => Some((token.address.clone(), supply)),
+                        Err(error) => {
+                            warn!(?token.address, %error, "Failed to fetch token supply");
+                            None
+                        }
+                    }
+                }
+            })
+            .buffer_unordered(args.concurrency)
+            .filter_map(|res| async move { res })
+            .collect::<HashMap<_, _>>()
+            .await;
+
+        if !supplies.is_empty() {
+            gw.add_token_supplies(&args.chain, &supplies, snapshot_ts)
+                .await?;
+        }
+        let duration = Instant::now().duration_since(start);
+        info!(
+            processed = tokens.len(),
+            stored = supplies.len(),
+            page = page,
+            duration = duration.as_secs(),
+            "Progress"
+        );
+
+        page += 1;
+        if tokens.len() < (page_size as usize) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+    use tycho_common::{
+        models::{token::Token, Address, Balance, Chain},
+        storage::WithTotal,
+        Bytes,
+    };
+
+    use super::*;
+    use crate::testing;
+
+    struct StaticSupplies(HashMap<Address, Balance>);
+
+    #[async_trait]
+    impl TokenSupplyFetcher for StaticSupplies {
+        async fn get_total_supply(
+            &self,
+            token: Address,
+            _block: BlockTag,
+        ) -> Result<Balance, String> {
+            self.0
+                .get(&token)
+                .cloned()
+                .ok_or_else(|| "totalSupply reverted".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_token_supplies() {
+        let weth = Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let broken = Bytes::from("0x228c6fcd7376177ff0cff304043f461189752750");
+        let args = TokenSupplyArgs {
+            rpc_url: String::new(),
+            chain: Chain::Ethereum,
+            concurrency: 2,
+            fetch_batch_size: 100,
+            min_quality: 51,
+        };
+        let mut gw = testing::MockGateway::new();
+        let tokens = vec![
+            Token::new(&weth, "WETH", 18, 0, &[], Chain::Ethereum, 100),
+            Token::new(&broken, "BLITZ", 9, 0, &[], Chain::Ethereum, 100),
+        ];
+        gw.expect_get_tokens()
+            .once()
+            .returning(move |_, _, _, _, _| {
+                let tokens = tokens.clone();
+                Box::pin(async move { Ok(WithTotal { entity: tokens, total: Some(2) }) })
+            });
+        let exp = HashMap::from([(weth.clone(), Bytes::from("0x64"))]);
+        gw.expect_add_token_supplies()
+            .once()
+            .returning(move |_, supplies, _| {
+                assert_eq!(supplies, &exp);
+                Box::pin(async { Ok(()) })
+            });
+        let fetcher = StaticSupplies(HashMap::from([(weth, Bytes::from("0x64"))]));
+
+        snapshot_token_supplies(args, Arc::new(gw), Arc::new(fetcher))
+            .await
+            .expect("snapshotting token supplies failed");
+    }
+}
@@ -0,0 +1,355 @@
+//! Registry of the contracts whose changes an extractor persists.
+//!
+//! Without a registry an extractor persists every contract change its substreams package emits.
+//! Once a registry is configured, changes of contracts that are not tracked at the block they
+//! occur in are dropped, together with their account balances. Contracts held by components are
+//! tracked from the block the component is created in; any other contract can be listed in the
+//! extractor config or added and removed at runtime through the admin RPC.
+//!
+//! Additions and removals take effect from a given block. Changes in blocks processed before an
+//! addition are not backfilled. Accounts indexed by the dynamic contract indexer are not subject
+//! to the registry.
+//!
+//! Contracts from the config and the admin RPC are stored, so runtime changes survive a restart
+//! and the accounts of tracked contracts are not collected as orphaned. Stored tracking windows
+//! take precedence over the config.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use serde::Deserialize;
+use tycho_common::{
+    dto,
+    models::{contract::TrackedContract, Address, Chain},
+    storage::StorageError,
+};
+use tycho_storage::postgres::cache::CachedGateway;
+
+use crate::extractor::models::BlockChanges;
+
+/// A contract listed in the extractor config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TrackedContractConfig {
+    pub address: Address,
+    /// First block whose changes of the contract are persisted
+    #[serde(default)]
+    pub from_block: u64,
+}
+
+/// Blocks `from_block..until_block` in which a contract is tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TrackingWindow {
+    from_block: u64,
+    until_block: Option<u64>,
+}
+
+impl TrackingWindow {
+    fn contains(&self, block: u64) -> bool {
+        self.from_block <= block
+            && self
+                .until_block
+                .is_none_or(|until| block < until)
+    }
+}
+
+/// Where the tracked contracts of an extractor are stored.
+struct TrackedContractStore {
+    gateway: CachedGateway,
+    extractor: String,
+    chain: Chain,
+}
+
+/// Contracts tracked by an extractor, shared between the extractor and its admin handle.
+#[derive(Default)]
+pub struct TrackedContracts {
+    windows: Mutex<HashMap<Address, TrackingWindow>>,
+    store: Option<TrackedContractStore>,
+}
+
+impl std::fmt::Debug for TrackedContracts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackedContracts")
+            .field("windows", &self.windows)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TrackedContracts {
+    pub fn new(contracts: &[TrackedContractConfig]) -> Self {
+        let registry = Self::default();
+        for contract in contracts {
+            registry.add(contract.address.clone(), contract.from_block);
+        }
+        registry
+    }
+
+    /// Stores the contracts of this registry for the extractor `extractor`.
+    pub fn with_store(mut self, gateway: CachedGateway, extractor: &str, chain: Chain) -> Self {
+        self.store =
+            Some(TrackedContractStore { gateway, extractor: extractor.to_string(), chain });
+        self
+    }
+
+    /// Replaces the tracking windows of stored contracts with the stored ones, then stores the
+    /// configured contracts that are not stored yet.
+    pub async fn restore(&self) -> Result<(), StorageError> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let stored = store
+            .gateway
+            .get_tracked_contracts(&store.extractor, &store.chain)
+            .await?;
+        let stored_addresses = stored
+            .iter()
+            .map(|contract| contract.address.clone())
+            .collect::<HashSet<_>>();
+        let unstored = {
+            let mut windows = self.windows.lock().unwrap();
+            for contract in stored {
+                windows.insert(
+                    contract.address,
+                    TrackingWindow {
+                        from_block: contract.from_block,
+                        until_block: contract.until_block,
+                    },
+                );
+            }
+            windows
+                .keys()
+                .filter(|address| !stored_addresses.contains(*address))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        self.persist(&unstored).await
+    }
+
+    /// Stores the current tracking windows of `addresses`.
+    async fn persist(&self, addresses: &[Address]) -> Result<(), StorageError> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let contracts = {
+            let windows = self.windows.lock().unwrap();
+            addresses
+                .iter()
+                .filter_map(|address| {
+                    windows
+                        .get(address)
+                        .map(|window| TrackedContract {
+                            address: address.clone(),
+                            from_block: window.from_block,
+                            until_block: window.until_block,
+                        })
+                })
+                .collect::<Vec<_>>()
+        };
+        store
+            .gateway
+            .upsert_tracked_contracts(&store.extractor, &store.chain, &contracts)
+            .await
+    }
+
+    /// Tracks `address` starting at `from_block`. Re-adding a contract that is still tracked keeps
+    /// the earlier start.
+    pub fn add(&self, address: Address, from_block: u64) {
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows
+            .entry(address)
+            .or_insert(TrackingWindow { from_block, until_block: None });
+        if window.until_block.is_some() {
+            *window = TrackingWindow { from_block, until_block: None };
+        } else {
+            window.from_block = window.from_block.min(from_block);
+        }
+    }
+
+    /// Stops tracking `address` starting at `from_block`.
+    pub fn remove(&self, address: &Address, from_block: u64) {
+        if let Some(window) = self
+            .windows
+            .lock()
+            .unwrap()
+            .get_mut(address)
+        {
+            window.until_block = Some(
+                window
+                    .until_block
+                    .map_or(from_block, |until| until.min(from_block)),
+            );
+        }
+    }
+
+    pub fn is_tracked(&self, address: &Address, block: u64) -> bool {
+        self.windows
+            .lock()
+            .unwrap()
+            .get(address)
+            .is_some_and(|window| window.contains(block))
+    }
+
+    /// Applies and stores the additions and removals of an admin request.
+    pub async fn update(
+        &self,
+        request: &dto::TrackedContractsRequestBody,
+    ) -> Result<(), StorageError> {
+        for contract in &request.add {
+            self.add(contract.address.clone(), contract.from_block);
+        }
+        for contract in &request.remove {
+            self.remove(&contract.address, contract.from_block);
+        }
+        let updated = request
+            .add
+            .iter()
+            .chain(&request.remove)
+            .map(|contract| contract.address.clone())
+            .collect::<Vec<_>>();
+        self.persist(&updated).await
+    }
+
+    /// All contracts ever tracked, sorted by address.
+    pub fn snapshot(&self) -> Vec<dto::TrackedContract> {
+        let mut contracts = self
+            .windows
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(address, window)| dto::TrackedContract {
+                address: address.clone(),
+                from_block: window.from_block,
+                until_block: window.until_block,
+            })
+            .collect::<Vec<_>>();
+        contracts.sort_by(|a, b| a.address.cmp(&b.address));
+        contracts
+    }
+
+    /// Tracks the contracts of components created in `changes`, then drops the contract changes
+    /// and account balances of untracked contracts. Returns the number of dropped accounts.
+    pub fn apply(&self, changes: &mut BlockChanges) -> usize {
+        let block = changes.block.number;
+        for tx in &changes.txs_with_update {
+            for component in tx.protocol_components.values() {
+                for address in &component.contract_addresses {
+                    self.add(address.clone(), block);
+                }
+            }
+        }
+
+        let mut dropped = 0;
+        for tx in changes.txs_with_update.iter_mut() {
+            let n_before = tx.account_deltas.len();
+            tx.account_deltas
+                .retain(|address, _| self.is_tracked(address, block));
+            dropped += n_before - tx.account_deltas.len();
+            tx.account_balance_changes
+                .retain(|address, _| self.is_tracked(address, block));
+        }
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use tycho_common::{
+        models::{
+            blockchain::{Block, TxWithChanges},
+            contract::AccountDelta,
+            protocol::ProtocolComponent,
+            Chain, ChangeType,
+        },
+        Bytes,
+    };
+
+    use super::*;
+
+    fn delta(address: &str) -> (Address, AccountDelta) {
+        let address = Bytes::from(address);
+        (
+            address.clone(),
+            AccountDelta::new(
+                Chain::Ethereum,
+                address,
+                HashMap::new(),
+                None,
+                None,
+                ChangeType::Update,
+            ),
+        )
+    }
+
+    #[test]
+    fn test_tracking_windows() {
+        let registry = TrackedContracts::new(&[TrackedContractConfig {
+            address: Bytes::from("0x01"),
+            from_block: 10,
+        }]);
+        registry.add(Bytes::from("0x01"), 20);
+        registry.remove(&Bytes::from("0x01"), 30);
+        registry.remove(&Bytes::from("0x02"), 30);
+
+        assert!(!registry.is_tracked(&Bytes::from("0x01"), 9));
+        assert!(registry.is_tracked(&Bytes::from("0x01"), 10));
+        assert!(registry.is_tracked(&Bytes::from("0x01"), 29));
+        assert!(!registry.is_tracked(&Bytes::from("0x01"), 30));
+        assert_eq!(
+            registry.snapshot(),
+            vec![dto::TrackedContract {
+                address: Bytes::from("0x01"),
+                from_block: 10,
+                until_block: Some(30)
+            }]
+        );
+
+        registry.add(Bytes::from("0x01"), 40);
+
+        assert!(!registry.is_tracked(&Bytes::from("0x01"), 35));
+        assert!(registry.is_tracked(&Bytes::from("0x01"), 40));
+    }
+
+    #[test]
+    fn test_apply() {
+        let registry = TrackedContracts::new(&[TrackedContractConfig {
+            address: Bytes::from("0x01"),
+            from_block: 0,
+        }]);
+        let tx = TxWithChanges {
+            protocol_components: HashMap::from([(
+                "pool".to_string(),
+                ProtocolComponent {
+                    id: "pool".to_string(),
+                    contract_addresses: vec![Bytes::from("0x02")],
+                    ..Default::default()
+                },
+            )]),
+            account_deltas: HashMap::from([delta("0x01"), delta("0x02"), delta("0x03")]),
+            ..Default::default()
+        };
+        let mut changes = BlockChanges::new(
+            "test".to_string(),
+            Chain::Ethereum,
+            Block { number: 5, ..Default::default() },
+            0,
+            false,
+            vec![tx],
+            Vec::new(),
+        );
+
+        let dropped = registry.apply(&mut changes);
+
+        assert_eq!(dropped, 1);
+        let mut kept = changes.txs_with_update[0]
+            .account_deltas
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        kept.sort();
+        assert_eq!(kept, vec![Bytes::from("0x01"), Bytes::from("0x02")]);
+        assert!(registry.is_tracked(&Bytes::from("0x02"), 5));
+        assert!(!registry.is_tracked(&Bytes::from("0x02"), 4));
+    }
+}
@@ -0,0 +1,130 @@
+//! Byte level helpers shared by the extractors.
+//!
+//! Starknet values are elements of the Stark prime field ("felts"), so they are 251 bit numbers
+//! that substreams and RPCs serialize as up to 32 big endian bytes or as hex strings with the
+//! leading zeros stripped. The helpers below validate these values and bring them into the 32 byte,
+//! zero padded form Tycho stores them in.
+use tycho_common::Bytes;
+
+use super::MessageDecodeError;
+
+/// The Stark prime `2^251 + 17 * 2^192 + 1` in big endian. Felts are strictly smaller.
+pub const STARK_PRIME: [u8; 32] = [
+    0x08, 0, 0, 0, 0, 0, 0, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0x01,
+];
+
+/// Starknet contract addresses are strictly smaller than `2^251 - 256`, big endian.
+pub const STARKNET_ADDRESS_BOUND: [u8; 32] = [
+    0x07, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
+];
+
+/// Parses up to 32 big endian bytes into a zero padded felt.
+///
+/// Fails if `data` is longer than 32 bytes or the value is not smaller than [`STARK_PRIME`].
+pub fn parse_felt(data: &[u8]) -> Result<[u8; 32], MessageDecodeError> {
+    if data.len() > 32 {
+        return Err(MessageDecodeError::InvalidLength(format!(
+            "Felt of {} bytes, expected at most 32: 0x{}",
+            data.len(),
+            hex::encode(data)
+        )));
+    }
+    let mut felt = [0u8; 32];
+    felt[32 - data.len()..].copy_from_slice(data);
+    // Both are big endian and of equal length, so they compare like the numbers they encode.
+    if felt >= STARK_PRIME {
+        return Err(MessageDecodeError::Overflow(format!(
+            "Felt 0x{} exceeds the Stark prime",
+            hex::encode(data)
+        )));
+    }
+    Ok(felt)
+}
+
+/// Parses a hex string, with or without `0x` prefix and leading zeros, into a zero padded felt.
+pub fn parse_felt_hex(value: &str) -> Result<[u8; 32], MessageDecodeError> {
+    let digits = value
+        .strip_prefix("0x")
+        .unwrap_or(value);
+    let digits = digits.trim_start_matches('0');
+    let padded = if digits.len() % 2 == 1 { format!("0{digits}") } else { digits.to_string() };
+    let data = hex::decode(&padded)
+        .map_err(|err| MessageDecodeError::InvalidValue(format!("Felt {value}: {err}")))?;
+    parse_felt(&data)
+}
+
+/// Validates a Starknet contract address and pads it to 32 bytes.
+///
+/// Addresses of the same contract may come with or without leading zeros, the padded form makes
+/// them compare equal.
+pub fn normalize_starknet_address(data: &[u8]) -> Result<Bytes, MessageDecodeError> {
+    let felt = parse_felt(data)?;
+    if felt >= STARKNET_ADDRESS_BOUND {
+        return Err(MessageDecodeError::InvalidValue(format!(
+            "Starknet address 0x{} is out of the address range",
+            hex::encode(data)
+        )));
+    }
+    Ok(Bytes::from(felt.to_vec()))
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    const STRK: &str = "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+
+    #[rstest]
+    #[case::zero("0x0", Some("0x0000000000000000000000000000000000000000000000000000000000000000"))]
+    #[case::odd_length(
+        "0x123",
+        Some("0x0000000000000000000000000000000000000000000000000000000000000123")
+    )]
+    #[case::no_prefix(
+        "00ff",
+        Some("0x00000000000000000000000000000000000000000000000000000000000000ff")
+    )]
+    #[case::prime_minus_one(
+        "0x800000000000011000000000000000000000000000000000000000000000000",
+        Some("0x0800000000000011000000000000000000000000000000000000000000000000")
+    )]
+    #[case::prime("0x800000000000011000000000000000000000000000000000000000000000001", None)]
+    #[case::too_long("0x1000000000000000000000000000000000000000000000000000000000000000000", None)]
+    #[case::not_hex("0xzz", None)]
+    fn test_parse_felt_hex(#[case] value: &str, #[case] exp: Option<&str>) {
+        let res = parse_felt_hex(value);
+
+        assert_eq!(
+            res.ok()
+                .map(|felt| format!("0x{}", hex::encode(felt)))
+                .as_deref(),
+            exp
+        );
+    }
+
+    #[test]
+    fn test_parse_felt_errors() {
+        assert!(matches!(parse_felt(&STARK_PRIME), Err(MessageDecodeError::Overflow(_))));
+        assert!(matches!(parse_felt(&[1u8; 33]), Err(MessageDecodeError::InvalidLength(_))));
+    }
+
+    #[rstest]
+    #[case::padded(STRK, Some(STRK))]
+    #[case::short(
+        "0x1234",
+        Some("0x0000000000000000000000000000000000000000000000000000000000001234")
+    )]
+    #[case::out_of_range(
+        "0x07ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff00",
+        None
+    )]
+    fn test_normalize_starknet_address(#[case] address: &str, #[case] exp: Option<&str>) {
+        let res = normalize_starknet_address(&Bytes::from(address));
+
+        assert_eq!(res.ok(), exp.map(Bytes::from));
+    }
+}
@@ -1,7 +1,12 @@
 pub mod cli;
+pub mod cold_store;
+pub mod config;
+pub mod export;
 pub mod extractor;
 pub mod pb;
 pub mod services;
+pub mod sink;
+pub mod slot_annotations;
 pub mod substreams;
 
 #[cfg(test)]
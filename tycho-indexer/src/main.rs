@@ -4,16 +4,18 @@ use std::{
     env,
     fs::File,
     io::Read,
+    path::PathBuf,
     process, slice,
     str::FromStr,
     sync::{mpsc, Arc},
+    time::Duration,
 };
 
 use actix_web::{dev::ServerHandle, web, App, HttpResponse, HttpServer, Responder};
 use chrono::{NaiveDateTime, Utc};
 use clap::Parser;
 use futures03::future::select_all;
-use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
 use serde::Deserialize;
 use tokio::{
     runtime::Handle,
@@ -24,12 +26,14 @@ use tokio::{
 use tracing::{debug, error, info, instrument, warn};
 use tracing_subscriber::EnvFilter;
 use tycho_common::{
+    dto,
     models::{
         blockchain::{Block, Transaction},
         contract::AccountDelta,
-        Address, Chain, ExtractionState, ImplementationType,
+        protocol::DeploymentParity,
+        Address, Chain, ExtractionState, ExtractorIdentity, ImplementationType,
     },
-    storage::{ChainGateway, ContractStateGateway, ExtractionStateGateway},
+    storage::{ChainGateway, ContractStateGateway, ExtractionStateGateway, ProtocolGateway},
     traits::{AccountExtractor, StorageSnapshotRequest},
     Bytes,
 };
@@ -38,20 +42,49 @@ use tycho_ethereum::{
     token_analyzer::rpc_client::EthereumRpcClient, token_pre_processor::EthereumTokenPreProcessor,
 };
 use tycho_indexer::{
-    cli::{AnalyzeTokenArgs, Cli, Command, GlobalArgs, IndexArgs, RunSpkgArgs},
+    cli::{
+        AnalyzeTokenArgs, CheckArgs, Cli, CollectAccountsArgs, Command, CompareDeploymentsArgs,
+        ExportArgs, GlobalArgs, IndexArgs, LoadSlotAnnotationsArgs, PruneArgs, RenameAttributeArgs,
+        RepairArgs, RunSpkgArgs, SimulateArgs, TokenSupplyArgs,
+    },
+    cold_store::S3ColdStore,
+    config::{MetricsConfig, RuntimeConfig, TelemetryConfig, WorkersConfig},
+    export,
     extractor::{
         chain_state::ChainState,
-        protocol_cache::ProtocolMemoryCache,
+        lease::{ExtractorLease, DEFAULT_LEASE_TTL},
+        message_bus::MessageBus,
+        protocol_cache::{CacheSnapshotConfig, ProtocolMemoryCache},
+        repair::repair_range,
         runner::{
             DCIType, ExtractorBuilder, ExtractorConfig, ExtractorHandle, HandleResult,
             ProtocolTypeConfig,
         },
+        simulation::SimulationOutput,
         token_analysis_cron::analyze_tokens,
+        token_supply_cron::snapshot_token_supplies,
         ExtractionError,
     },
-    services::ServicesBuilder,
+    services::{
+        route_metrics,
+        usage::ApiKeysConfig,
+        versioning::{ApiVersion, Deprecation, Deprecations},
+        ws_queue::DeliveryConfig,
+        ServicesBuilder,
+    },
+    sink::{self, DeltaExporter, SinkConfig},
+    slot_annotations::load_slot_annotations,
+};
+use tycho_storage::postgres::{
+    builder::GatewayBuilder,
+    cache::CachedGateway,
+    cold_storage::ColdStorageConfig,
+    component_activity::ComponentActivityConfig,
+    db_growth::DbGrowthConfig,
+    maintenance::{self, OrphanedAccountsConfig},
+    scheduler::SchedulerConfig,
+    storage_compaction::{AttributeRetentionPolicy, StorageCompactionConfig},
 };
-use tycho_storage::postgres::{builder::GatewayBuilder, cache::CachedGateway};
 
 mod ot;
 
@@ -83,32 +116,68 @@ type ExtractionTasks = Vec<JoinHandle<Result<(), ExtractionError>>>;
 type ServerTasks = Vec<JoinHandle<Result<(), ExtractionError>>>; //TODO: introduce an error type for it
 fn main() {
     let cli: Cli = Cli::parse();
-    let global_args = cli.args();
+    let mut global_args = cli.args();
+    global_args.config =
+        match RuntimeConfig::load(global_args.config_file.as_deref(), global_args.profile) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{e}");
+                process::exit(1);
+            }
+        };
+    if global_args.print_config {
+        print!("# profile: {}\n{}", global_args.profile, global_args.config.to_yaml());
+        return;
+    }
 
     match cli.command() {
         Command::Run(run_args) => run_spkg(global_args, run_args).unwrap(),
+        Command::Simulate(args) => run_simulate(global_args, args).unwrap(),
         Command::Index(indexer_args) => {
             run_indexer(global_args, indexer_args).unwrap();
         }
         Command::AnalyzeTokens(analyze_args) => {
             run_tycho_ethereum(global_args, analyze_args).unwrap();
         }
+        Command::SnapshotTokenSupplies(supply_args) => {
+            run_token_supply_snapshot(global_args, supply_args).unwrap();
+        }
+        Command::Repair(repair_args) => {
+            run_repair(global_args, repair_args).unwrap();
+        }
         Command::Rpc => run_rpc(global_args).unwrap(),
+        Command::Migrate => run_migrate(global_args).unwrap(),
+        Command::Check(args) => run_check(global_args, args).unwrap(),
+        Command::Prune(prune_args) => run_prune(global_args, prune_args).unwrap(),
+        Command::Export(args) => run_export(global_args, args).unwrap(),
+        Command::CollectAccounts(args) => run_collect_accounts(global_args, args).unwrap(),
+        Command::RenameAttribute(args) => run_rename_attribute(global_args, args).unwrap(),
+        Command::LoadSlotAnnotations(args) => run_load_slot_annotations(global_args, args).unwrap(),
+        Command::CompareDeployments(args) => run_compare_deployments(global_args, args).unwrap(),
     }
 }
 
-fn create_tracing_subscriber() {
+fn api_deprecations(global_args: &GlobalArgs) -> Deprecations {
+    global_args
+        .v1_deprecated_at
+        .map(|deprecated_at| {
+            (ApiVersion::V1, Deprecation { deprecated_at, sunset: global_args.v1_sunset })
+        })
+        .into_iter()
+        .collect()
+}
+
+fn create_tracing_subscriber(config: &TelemetryConfig) {
     // Set up the subscriber
-    let console_flag = std::env::var("ENABLE_CONSOLE").unwrap_or_else(|_| "false".to_string());
-    if console_flag == "true" {
+    if config.enable_console {
         console_subscriber::init();
     } else {
         // OTLP endpoint is set, construct OTLP pipeline
-        if let Ok(otlp_exporter_endpoint) = std::env::var("OTLP_EXPORTER_ENDPOINT") {
+        if let Some(otlp_exporter_endpoint) = config.otlp_exporter_endpoint.clone() {
             let config = ot::TracingConfig { otlp_exporter_endpoint };
             ot::init_tracing(config).unwrap();
         } else {
-            warn!("OTLP_EXPORTER_ENDPOINT not set defaulting to stdout subscriber!");
+            warn!("No OTLP exporter endpoint configured, defaulting to stdout subscriber!");
             let format = tracing_subscriber::fmt::format()
                 .with_level(true)
                 .with_target(false)
@@ -122,11 +191,23 @@ fn create_tracing_subscriber() {
 }
 
 /// Creates and runs the Prometheus metrics exporter using Actix Web.
-pub fn create_metrics_exporter() -> tokio::task::JoinHandle<()> {
-    let exporter_builder = PrometheusBuilder::new();
+pub fn create_metrics_exporter(config: &MetricsConfig) -> tokio::task::JoinHandle<()> {
+    let exporter_builder = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(route_metrics::LATENCY_METRIC.to_string()),
+            &route_metrics::LATENCY_BUCKETS,
+        )
+        .and_then(|builder| {
+            builder.set_buckets_for_metric(
+                Matcher::Full(route_metrics::RESPONSE_SIZE_METRIC.to_string()),
+                &route_metrics::RESPONSE_SIZE_BUCKETS,
+            )
+        })
+        .expect("Invalid histogram buckets");
     let handle = exporter_builder
         .install_recorder()
         .expect("Failed to install Prometheus recorder");
+    let address = (config.host.clone(), config.port);
 
     tokio::spawn(async move {
         if let Err(e) = HttpServer::new(move || {
@@ -138,7 +219,7 @@ pub fn create_metrics_exporter() -> tokio::task::JoinHandle<()> {
                 }),
             )
         })
-        .bind(("0.0.0.0", 9898))
+        .bind(address)
         .expect("Failed to bind metrics server")
         .run()
         .await
@@ -165,14 +246,7 @@ async fn metrics_handler(handle: PrometheusHandle) -> impl Responder {
 /// that server-related tasks do not interfere with the extraction workflow, and overall
 /// system performance is maintained.
 fn run_indexer(global_args: GlobalArgs, index_args: IndexArgs) -> Result<(), ExtractionError> {
-    let extraction_threads = std::env::var("EXTRACTION_WORKER_THREADS")
-        .unwrap_or_else(|_| "2".to_string())
-        .parse()
-        .expect("EXTRACTION_WORKER_THREADS must be a number");
-    let main_threads = std::env::var("MAIN_WORKER_THREADS")
-        .unwrap_or_else(|_| "3".to_string())
-        .parse()
-        .expect("MAIN_WORKER_THREADS must be a number");
+    let WorkersConfig { extraction_threads, main_threads } = global_args.config.workers;
     // We spawn a dedicated runtime for extraction
     let extraction_runtime = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(extraction_threads)
@@ -190,7 +264,7 @@ fn run_indexer(global_args: GlobalArgs, index_args: IndexArgs) -> Result<(), Ext
 
     let (extraction_tasks, other_tasks) = main_runtime
         .block_on(async {
-            create_tracing_subscriber();
+            create_tracing_subscriber(&global_args.config.telemetry);
             let _metrics_task = create_metrics_exporter();
 
             info!("Starting Tycho");
@@ -205,6 +279,69 @@ fn run_indexer(global_args: GlobalArgs, index_args: IndexArgs) -> Result<(), Ext
                 .parse()
                 .expect("Failed to parse retention horizon");
 
+            let cold_storage_offload = index_args
+                .cold_storage_min_age_days
+                .map(|days| ColdStorageConfig {
+                    min_age: chrono::Duration::days(days.into()),
+                    ..Default::default()
+                });
+
+            let component_activity = index_args
+                .component_inactive_after_days
+                .map(|days| ComponentActivityConfig {
+                    inactive_after: chrono::Duration::days(days.into()),
+                    ..Default::default()
+                });
+
+            let attribute_retention = extractors_config
+                .extractors
+                .iter()
+                .filter_map(|(system, config)| {
+                    config
+                        .attribute_retention
+                        .as_ref()
+                        .map(|retention| {
+                            (system.clone(), AttributeRetentionPolicy::from(retention))
+                        })
+                })
+                .collect::<HashMap<_, _>>();
+            let storage_compaction = (index_args
+                .storage_compaction_min_age_days
+                .is_some() ||
+                !attribute_retention.is_empty())
+            .then(|| {
+                let defaults = StorageCompactionConfig::default();
+                StorageCompactionConfig {
+                    compact_deleted_slots: index_args
+                        .storage_compaction_min_age_days
+                        .is_some(),
+                    min_age: index_args
+                        .storage_compaction_min_age_days
+                        .map_or(defaults.min_age, |days| chrono::Duration::days(days.into())),
+                    attribute_retention,
+                    ..defaults
+                }
+            });
+
+            let db_growth = (!index_args.db_budget.is_empty()).then(|| DbGrowthConfig {
+                budgets: index_args.db_budget.clone(),
+                enforce: index_args.db_budget_enforce,
+                ..Default::default()
+            });
+
+            let orphaned_accounts = index_args
+                .orphaned_account_grace_period_days
+                .map(|days| OrphanedAccountsConfig {
+                    grace_period: chrono::Duration::days(days),
+                    ..Default::default()
+                });
+
+            let scheduler = SchedulerConfig {
+                schedules: index_args.maintenance_schedule.clone(),
+                max_jitter: Duration::from_secs(index_args.maintenance_jitter_secs),
+                leader_election: index_args.maintenance_leader_election,
+            };
+
             let (extraction_tasks, other_tasks) = create_indexing_tasks(
                 &global_args,
                 &index_args
@@ -216,7 +353,27 @@ fn run_indexer(global_args: GlobalArgs, index_args: IndexArgs) -> Result<(), Ext
                     })
                     .collect::<Vec<_>>(),
                 retention_horizon,
+                cold_storage_offload,
+                component_activity,
+                storage_compaction,
+                db_growth,
+                orphaned_accounts,
+                scheduler,
+                index_args.notify_changes,
+                false,
                 extractors_config,
+                index_args
+                    .protocol_cache_snapshot
+                    .as_ref()
+                    .map(|path| CacheSnapshotConfig {
+                        path: PathBuf::from(path),
+                        interval: Duration::from_secs(
+                            index_args.protocol_cache_snapshot_interval_secs,
+                        ),
+                        max_age: chrono::Duration::seconds(
+                            index_args.protocol_cache_snapshot_max_age_secs as i64,
+                        ),
+                    }),
                 Some(extraction_runtime.handle()),
             )
             .await?;
@@ -256,7 +413,7 @@ fn run_indexer(global_args: GlobalArgs, index_args: IndexArgs) -> Result<(), Ext
 
 #[tokio::main]
 async fn run_spkg(global_args: GlobalArgs, run_args: RunSpkgArgs) -> Result<(), ExtractionError> {
-    create_tracing_subscriber();
+    create_tracing_subscriber(&global_args.config.telemetry);
     info!("Starting Tycho");
 
     let dci_plugin = run_args
@@ -297,8 +454,17 @@ async fn run_spkg(global_args: GlobalArgs, run_args: RunSpkgArgs) -> Result<(),
         &global_args,
         &[Chain::from_str(&run_args.chain).unwrap()],
         Utc::now().naive_utc(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        SchedulerConfig::default(),
+        false,
+        run_args.dry_run,
         config,
         None,
+        None,
     )
     .await?;
 
@@ -309,11 +475,61 @@ async fn run_spkg(global_args: GlobalArgs, run_args: RunSpkgArgs) -> Result<(),
     res.expect("Extractor- nor ServiceTasks should panic!")
 }
 
+#[tokio::main]
+async fn run_simulate(global_args: GlobalArgs, args: SimulateArgs) -> Result<(), anyhow::Error> {
+    // Stdout is reserved for the simulated blocks.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let stop_block = args.stop_block();
+    let config = ExtractorConfig::new(
+        args.protocol_system,
+        Chain::from_str(&args.chain)?,
+        ImplementationType::Vm,
+        1,
+        args.start_block,
+        stop_block,
+        args.protocol_type_names
+            .into_iter()
+            .map(|name| ProtocolTypeConfig::new(name, tycho_common::models::FinancialType::Swap))
+            .collect(),
+        args.spkg,
+        args.module,
+        Vec::new(),
+        0,
+        args.post_processor,
+        None,
+    );
+    let output = if args.json { SimulationOutput::Json } else { SimulationOutput::Summary };
+    let report =
+        ExtractorBuilder::new(&config, &global_args.endpoint_url, global_args.s3_bucket.as_deref())
+            .token(
+                &args
+                    .substreams_args
+                    .substreams_api_token,
+            )
+            .only_final_blocks()
+            .simulate(output, std::io::stdout().lock())
+            .await?;
+    info!(?report, "Simulation finished");
+    if report.decode_errors > 0 || report.issues > 0 {
+        anyhow::bail!(
+            "Simulation found {} undecodable blocks and {} validation issues",
+            report.decode_errors,
+            report.issues
+        );
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn run_rpc(global_args: GlobalArgs) -> Result<(), ExtractionError> {
-    create_tracing_subscriber();
+    create_tracing_subscriber(&global_args.config.telemetry);
 
-    let direct_gw = GatewayBuilder::new(&global_args.database_url)
+    let direct_gw = with_cold_store(GatewayBuilder::new(&global_args.database_url), &global_args)
+        .await
         .set_chains(&[Chain::Ethereum]) // TODO: handle multichain
         .build_direct_gw()
         .await?;
@@ -323,12 +539,20 @@ async fn run_rpc(global_args: GlobalArgs) -> Result<(), ExtractionError> {
     let api_key = env::var("AUTH_API_KEY").map_err(|_| {
         ExtractionError::Setup("AUTH_API_KEY environment variable is not set".to_string())
     })?;
+    let api_keys = global_args
+        .api_keys_config
+        .as_deref()
+        .map(ApiKeysConfig::from_yaml)
+        .transpose()?;
 
     let (server_handle, server_task) =
         ServicesBuilder::new(direct_gw.clone(), global_args.rpc_url.clone(), api_key)
             .prefix(&global_args.server_version_prefix)
+            .deprecations(api_deprecations(&global_args))
             .bind(&global_args.server_ip)
             .port(global_args.server_port)
+            .http_workers(global_args.config.services.http_workers)
+            .api_keys(api_keys)
             .run()?;
     info!(server_url, "Http and Ws server started");
     let shutdown_task = tokio::spawn(shutdown_handler(server_handle, vec![], None));
@@ -336,12 +560,58 @@ async fn run_rpc(global_args: GlobalArgs) -> Result<(), ExtractionError> {
     res.expect("ServiceTasks shouldn't panic!")
 }
 
+/// Configures the cold store on the builder if a cold storage bucket is set.
+async fn with_cold_store(builder: GatewayBuilder, global_args: &GlobalArgs) -> GatewayBuilder {
+    let builder = with_db_timeouts(builder, global_args);
+    match &global_args.cold_storage_bucket {
+        Some(bucket) => {
+            let store = S3ColdStore::from_env(
+                bucket,
+                global_args
+                    .cold_storage_endpoint
+                    .as_deref(),
+            )
+            .await;
+            builder.set_cold_store(Arc::new(store))
+        }
+        None => builder,
+    }
+}
+
+/// Configures the database timeouts, the slow query threshold and the pool size on the builder if
+/// set.
+fn with_db_timeouts(mut builder: GatewayBuilder, global_args: &GlobalArgs) -> GatewayBuilder {
+    if let Some(size) = global_args.config.database.pool_size {
+        builder = builder.set_pool_size(size);
+    }
+    if let Some(secs) = global_args.database_statement_timeout_secs {
+        builder = builder.set_statement_timeout(Duration::from_secs(secs));
+    }
+    if let Some(secs) = global_args.database_query_deadline_secs {
+        builder = builder.set_query_deadline(Duration::from_secs(secs));
+    }
+    if let Some(millis) = global_args.database_slow_query_threshold_ms {
+        builder = builder.set_slow_query_threshold(Duration::from_millis(millis));
+    }
+    builder
+}
+
 /// Creates extraction and server tasks.
+#[allow(clippy::too_many_arguments)]
 async fn create_indexing_tasks(
     global_args: &GlobalArgs,
     chains: &[Chain],
     retention_horizon: NaiveDateTime,
+    cold_storage_offload: Option<ColdStorageConfig>,
+    component_activity: Option<ComponentActivityConfig>,
+    storage_compaction: Option<StorageCompactionConfig>,
+    db_growth: Option<DbGrowthConfig>,
+    orphaned_accounts: Option<OrphanedAccountsConfig>,
+    scheduler: SchedulerConfig,
+    notify_changes: bool,
+    dry_run: bool,
     extractors_config: ExtractorConfigs,
+    protocol_cache_snapshot: Option<CacheSnapshotConfig>,
     extraction_runtime: Option<&Handle>,
 ) -> Result<(ExtractionTasks, ServerTasks), ExtractionError> {
     let rpc_client = EthereumRpcClient::new_from_url(&global_args.rpc_url.clone());
@@ -358,10 +628,51 @@ async fn create_indexing_tasks(
         .cloned()
         .collect();
 
-    let (cached_gw, gw_writer_handle) = GatewayBuilder::new(&global_args.database_url)
+    let mut gw_builder =
+        with_cold_store(GatewayBuilder::new(&global_args.database_url), global_args).await;
+    if let Some(config) = cold_storage_offload {
+        if global_args
+            .cold_storage_bucket
+            .is_none()
+        {
+            return Err(ExtractionError::Setup(
+                "Cold storage offloading requires a cold storage bucket".to_string(),
+            ));
+        }
+        gw_builder = gw_builder.set_cold_storage_offload(config);
+    }
+    if let Some(config) = component_activity {
+        gw_builder = gw_builder.set_component_activity(config);
+    }
+    if let Some(config) = storage_compaction {
+        gw_builder = gw_builder.set_storage_compaction(config);
+    }
+    if let Some(config) = db_growth {
+        gw_builder = gw_builder.set_db_growth(config);
+    }
+    if let Some(config) = orphaned_accounts {
+        gw_builder = gw_builder.set_orphaned_account_collection(config);
+    }
+    gw_builder = gw_builder.set_head_cache_budget(
+        global_args
+            .config
+            .head_cache
+            .budget(protocol_systems.len()),
+    );
+    gw_builder = gw_builder.set_component_state_cache_budget(
+        global_args
+            .config
+            .head_cache
+            .component_state_budget(protocol_systems.len()),
+    );
+    let (cached_gw, gw_writer_handle) = gw_builder
         .set_chains(chains)
         .set_protocol_systems(&protocol_systems)
         .set_retention_horizon(retention_horizon)
+        .set_notify_changes(notify_changes)
+        .set_strict_writes(global_args.database_strict_writes)
+        .set_scheduler(scheduler)
+        .set_dry_run(dry_run)
         .build()
         .await?;
     let token_processor = EthereumTokenPreProcessor::new_from_url(
@@ -371,9 +682,10 @@ async fn create_indexing_tasks(
             .expect("No chain provided"), //TODO: handle multichain?
     );
 
+    let message_bus = Arc::new(MessageBus::default());
     let (tasks, extractor_handles): (Vec<_>, Vec<_>) =
         // TODO: accept substreams configuration from cli.
-        build_all_extractors(&extractors_config, chain_state, chains, &global_args.endpoint_url,global_args.s3_bucket.as_deref(), &cached_gw, &token_processor, &global_args.rpc_url.clone(), extraction_runtime)
+        build_all_extractors(&extractors_config, chain_state, chains, &global_args.endpoint_url,global_args.s3_bucket.as_deref(), &cached_gw, &token_processor, &global_args.rpc_url.clone(), protocol_cache_snapshot, extraction_runtime, &message_bus)
             .await
             .map_err(|e| ExtractionError::Setup(format!("Failed to create extractors: {e}")))?
             .into_iter()
@@ -383,15 +695,49 @@ async fn create_indexing_tasks(
     let api_key = env::var("AUTH_API_KEY").map_err(|_| {
         ExtractionError::Setup("AUTH_API_KEY environment variable is not set".to_string())
     })?;
+    let api_keys = global_args
+        .api_keys_config
+        .as_deref()
+        .map(ApiKeysConfig::from_yaml)
+        .transpose()?;
     let (server_handle, server_task) =
         ServicesBuilder::new(cached_gw.clone(), global_args.rpc_url.clone(), api_key)
             .prefix(&global_args.server_version_prefix)
+            .deprecations(api_deprecations(&global_args))
             .bind(&global_args.server_ip)
             .port(global_args.server_port)
+            .http_workers(global_args.config.services.http_workers)
             .register_extractors(extractor_handles.clone())
+            .message_bus(
+                global_args
+                    .ws_from_bus
+                    .then_some(message_bus),
+            )
+            .ws_delivery(DeliveryConfig {
+                queue_capacity: global_args.ws_queue_capacity,
+                overflow_policy: global_args.ws_overflow_policy,
+            })
+            .api_keys(api_keys)
             .run()?;
     info!(server_url, "Http and Ws server started");
 
+    if let Some(sink_url) = &global_args.sink_url {
+        let sink = sink::connect(sink_url)
+            .await
+            .map_err(|e| ExtractionError::Setup(format!("Failed to connect sink: {e}")))?;
+        let config =
+            SinkConfig { topic_template: global_args.sink_topic.clone(), ..Default::default() };
+        let exporter = Arc::new(DeltaExporter::new(sink, config));
+        // Export tasks end with their extractor, shutdown is driven by the extractor tasks.
+        for handle in &extractor_handles {
+            exporter
+                .clone()
+                .run(handle.get_id(), handle)
+                .await
+                .map_err(|e| ExtractionError::Setup(e.to_string()))?;
+        }
+    }
+
     let shutdown_task =
         tokio::spawn(shutdown_handler(server_handle, extractor_handles, Some(gw_writer_handle)));
 
@@ -408,7 +754,9 @@ async fn build_all_extractors(
     cached_gw: &CachedGateway,
     token_pre_processor: &EthereumTokenPreProcessor,
     rpc_url: &str,
+    protocol_cache_snapshot: Option<CacheSnapshotConfig>,
     runtime: Option<&tokio::runtime::Handle>,
+    message_bus: &Arc<MessageBus>,
 ) -> Result<Vec<HandleResult>, ExtractionError> {
     let mut extractor_handles = Vec::new();
 
@@ -420,7 +768,15 @@ async fn build_all_extractors(
         chrono::Duration::seconds(900),
         Arc::new(cached_gw.clone()),
     );
-    protocol_cache.populate().await?;
+    match protocol_cache_snapshot {
+        Some(snapshot) => {
+            protocol_cache
+                .populate_from_snapshot(&snapshot.path, snapshot.max_age)
+                .await?;
+            spawn_protocol_cache_snapshots(protocol_cache.clone(), snapshot);
+        }
+        None => protocol_cache.populate().await?,
+    }
 
     for extractor_config in config.extractors.values() {
         initialize_accounts(
@@ -440,6 +796,7 @@ async fn build_all_extractors(
 
         let (task, handle) = ExtractorBuilder::new(extractor_config, endpoint_url, s3_bucket)
             .rpc_url(rpc_url)
+            .message_bus(message_bus.clone())
             .build(chain_state, cached_gw, token_pre_processor, &protocol_cache)
             .await?
             .set_runtime(runtime)
@@ -453,6 +810,27 @@ async fn build_all_extractors(
     Ok(extractor_handles)
 }
 
+/// Periodically writes the protocol cache to the snapshot file, so the next start can skip loading
+/// it from the db.
+fn spawn_protocol_cache_snapshots(
+    protocol_cache: ProtocolMemoryCache,
+    snapshot: CacheSnapshotConfig,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(snapshot.interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(err) = protocol_cache
+                .write_snapshot(&snapshot.path)
+                .await
+            {
+                warn!(?err, "Failed to write protocol cache snapshot");
+            }
+        }
+    });
+}
+
 async fn with_transaction<F, Fut, R>(gw: &CachedGateway, block: &Block, f: F) -> R
 where
     F: FnOnce() -> Fut,
@@ -600,7 +978,7 @@ async fn run_tycho_ethereum(
     global_args: GlobalArgs,
     analyzer_args: AnalyzeTokenArgs,
 ) -> Result<(), anyhow::Error> {
-    create_tracing_subscriber();
+    create_tracing_subscriber(&global_args.config.telemetry);
     let (cached_gw, gw_writer_thread) = GatewayBuilder::new(&global_args.database_url)
         .set_chains(&[analyzer_args.chain])
         .build()
@@ -618,6 +996,284 @@ async fn run_tycho_ethereum(
     Ok(())
 }
 
+#[tokio::main]
+async fn run_token_supply_snapshot(
+    global_args: GlobalArgs,
+    supply_args: TokenSupplyArgs,
+) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    let (cached_gw, gw_writer_thread) = GatewayBuilder::new(&global_args.database_url)
+        .set_chains(&[supply_args.chain])
+        .build()
+        .await?;
+    let fetcher = EthereumTokenPreProcessor::new_from_url(&supply_args.rpc_url, supply_args.chain);
+    let snapshot_thread =
+        snapshot_token_supplies(supply_args, Arc::new(cached_gw), Arc::new(fetcher));
+    select! {
+         res = snapshot_thread => {
+            res?;
+         },
+         res = gw_writer_thread => {
+            res?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_repair(global_args: GlobalArgs, repair_args: RepairArgs) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    let mut configs = ExtractorConfigs::from_yaml(&repair_args.extractors_config)
+        .map_err(|e| anyhow::format_err!("Failed to load extractors config: {e}"))?;
+    let config = configs
+        .extractors
+        .remove(&repair_args.extractor)
+        .ok_or_else(|| anyhow::format_err!("Unknown extractor {}", repair_args.extractor))?
+        .for_block_range(repair_args.start_block, repair_args.end_block);
+    let chain = config.chain();
+
+    let (cached_gw, gw_writer_thread) =
+        with_cold_store(GatewayBuilder::new(&global_args.database_url), &global_args)
+            .await
+            .set_chains(&[chain])
+            .set_protocol_systems(slice::from_ref(&repair_args.extractor))
+            // Versions closed within the replayed range are history already, keep all of them.
+            .set_retention_horizon(NaiveDateTime::MIN)
+            // The replay starts below the stored head of the extractor.
+            .set_repair_mode(true)
+            .build()
+            .await?;
+
+    // The repair rewrites the cursor of the extractor, make sure it is stopped.
+    ExtractorLease::acquire(
+        cached_gw.clone(),
+        ExtractorIdentity::new(chain, &repair_args.extractor),
+        DEFAULT_LEASE_TTL,
+    )
+    .await?
+    .release()
+    .await;
+
+    let block_number = EthereumRpcClient::new_from_url(&global_args.rpc_url)
+        .get_block_number()
+        .await
+        .expect("Error getting block number");
+    let chain_state = ChainState::new(chrono::Local::now().naive_utc(), block_number, 12);
+    let token_processor = EthereumTokenPreProcessor::new_from_url(&global_args.rpc_url, chain);
+    let protocol_cache = ProtocolMemoryCache::new(
+        chain,
+        chrono::Duration::seconds(900),
+        Arc::new(cached_gw.clone()),
+    );
+    protocol_cache.populate().await?;
+
+    let replay = || async {
+        let (task, _) = ExtractorBuilder::new(
+            &config,
+            &global_args.endpoint_url,
+            global_args.s3_bucket.as_deref(),
+        )
+        .rpc_url(&global_args.rpc_url)
+        .only_final_blocks()
+        .build(chain_state, &cached_gw, &token_processor, &protocol_cache)
+        .await?
+        .run()
+        .await?;
+        match task
+            .await
+            .expect("Extractor task shouldn't panic!")
+        {
+            // The stream ends with an error once the stop block is reached, whether all blocks
+            // were processed is checked through the cursor.
+            Ok(()) | Err(ExtractionError::SubstreamsError(_)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    };
+    let repair_thread = repair_range(
+        &cached_gw,
+        chain,
+        &repair_args.extractor,
+        repair_args.start_block,
+        repair_args.end_block,
+        replay,
+    );
+    select! {
+         res = repair_thread => {
+            res?;
+         },
+         res = gw_writer_thread => {
+            res?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_migrate(global_args: GlobalArgs) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    maintenance::migrate(&global_args.database_url).await?;
+    info!("Database migrated");
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_check(global_args: GlobalArgs, args: CheckArgs) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    maintenance::check(&global_args.database_url).await?;
+    if args.integrity {
+        let report = maintenance::check_integrity(&global_args.database_url).await?;
+        if !report.is_ok() {
+            let failed = report
+                .failed()
+                .map(|check| format!("{} ({} violations)", check.name, check.violations))
+                .collect::<Vec<_>>()
+                .join(", ");
+            anyhow::bail!("Integrity checks failed: {failed}");
+        }
+    }
+    info!("Database checks passed");
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_prune(global_args: GlobalArgs, prune_args: PruneArgs) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    let deleted = maintenance::prune_history(&global_args.database_url, prune_args.before).await?;
+    let n_deleted: usize = deleted.iter().map(|(_, n)| n).sum();
+    info!(n_deleted, "Database pruned");
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_export(global_args: GlobalArgs, args: ExportArgs) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    let gw = GatewayBuilder::new(&global_args.database_url)
+        .set_chains(&[args.chain])
+        .build_direct_gw()
+        .await?;
+    let summary = export::export_protocol_snapshot(
+        &gw,
+        args.chain,
+        args.protocol_system,
+        args.format,
+        &args.output,
+        args.page_size,
+    )
+    .await?;
+    info!(
+        block_number = summary.block_number,
+        components = summary.components,
+        static_attributes = summary.static_attributes,
+        states = summary.states,
+        balances = summary.balances,
+        output = %args.output.display(),
+        "Export finished"
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_collect_accounts(
+    global_args: GlobalArgs,
+    args: CollectAccountsArgs,
+) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    maintenance::collect_orphaned_accounts(
+        &global_args.database_url,
+        chrono::Duration::days(args.grace_period_days),
+        args.batch_size,
+    )
+    .await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_rename_attribute(
+    global_args: GlobalArgs,
+    args: RenameAttributeArgs,
+) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    let gw = GatewayBuilder::new(&global_args.database_url)
+        .set_chains(&[args.chain])
+        .build_direct_gw()
+        .await?;
+    let components = args
+        .components
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let renamed = gw
+        .rename_protocol_state_attribute(
+            &args.chain,
+            &args.protocol_system,
+            (!components.is_empty()).then_some(components.as_slice()),
+            &args.from,
+            &args.to,
+        )
+        .await?;
+    info!(renamed, from = %args.from, to = %args.to, "Attribute renamed");
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_load_slot_annotations(
+    global_args: GlobalArgs,
+    args: LoadSlotAnnotationsArgs,
+) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    // Parse all files before writing so a broken file doesn't leave a partial load behind.
+    let files = args
+        .files
+        .iter()
+        .map(|path| Ok((path, load_slot_annotations(args.chain, path)?)))
+        .collect::<Result<Vec<_>, ExtractionError>>()?;
+    let gw = GatewayBuilder::new(&global_args.database_url)
+        .set_chains(&[args.chain])
+        .build_direct_gw()
+        .await?;
+    // Files are written one by one, so labels of later files replace those of earlier ones.
+    for (path, annotations) in files {
+        gw.upsert_slot_annotations(&annotations)
+            .await?;
+        info!(%path, annotations = annotations.len(), "Slot annotations loaded");
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn run_compare_deployments(
+    global_args: GlobalArgs,
+    args: CompareDeploymentsArgs,
+) -> Result<(), anyhow::Error> {
+    create_tracing_subscriber(&global_args.config.telemetry);
+    if args.base_chain == args.other_chain {
+        anyhow::bail!("Base and other chain must differ");
+    }
+    let gw = GatewayBuilder::new(&global_args.database_url)
+        .set_chains(&[args.base_chain, args.other_chain])
+        .build_direct_gw()
+        .await?;
+    let base = gw
+        .get_protocol_deployment(&args.base_chain, &args.protocol_system)
+        .await?;
+    let other = gw
+        .get_protocol_deployment(&args.other_chain, &args.protocol_system)
+        .await?;
+    let parity = DeploymentParity::compare(&args.protocol_system, &base, &other);
+    let complete = parity.is_complete();
+    println!("{}", serde_json::to_string_pretty(&dto::DeploymentParityResponse::from(parity))?);
+    if !complete {
+        anyhow::bail!(
+            "Deployments of {} differ between {} and {}",
+            args.protocol_system,
+            args.base_chain,
+            args.other_chain
+        );
+    }
+    info!(protocol_system = %args.protocol_system, "Deployments match");
+    Ok(())
+}
+
 #[cfg(test)]
 mod test_serial_db {
     use tycho_storage::postgres::testing::run_against_db;
@@ -645,7 +1301,7 @@ mod test_serial_db {
             initialize_accounts(accounts, block_id, rpc_url.as_str(), chain, &cached_gw).await;
 
             let contracts = cached_gw
-                .get_contracts(&chain, None, None, true, None)
+                .get_contracts(&chain, None, None, true, None, None)
                 .await
                 .unwrap()
                 .entity;
@@ -678,7 +1334,7 @@ mod test_serial_db {
             initialize_accounts(accounts, block_id, rpc_url.as_str(), chain, &cached_gw).await;
 
             let contracts = cached_gw
-                .get_contracts(&chain, None, None, true, None)
+                .get_contracts(&chain, None, None, true, None, None)
                 .await
                 .unwrap()
                 .entity;
@@ -712,7 +1368,7 @@ mod test_serial_db {
             initialize_accounts(accounts, 20378315, rpc_url.as_str(), chain, &cached_gw).await;
 
             let contracts = cached_gw
-                .get_contracts(&chain, None, None, true, None)
+                .get_contracts(&chain, None, None, true, None, None)
                 .await
                 .unwrap()
                 .entity;
@@ -1,6 +1,21 @@
+//! Role based access control for the internal endpoints.
+//!
+//! Every API key in the api keys file may be given a role. Roles are ordered, a key may call all
+//! endpoints that require its role or a lower one:
+//! - `reader` inspects extractors and runs analytics queries,
+//! - `operator` changes what extractors index, e.g. component filters or tracked contracts,
+//! - `admin` may additionally read the usage of all keys.
+//!
+//! The key set via `AUTH_API_KEY` always has the admin role, keys without a role can't call any
+//! internal endpoint. Requests with an unknown key are rejected with `401`, requests with a known
+//! key but an insufficient role with `403`. Every request made with a known key is recorded in the
+//! audit log along with the status it was answered with.
 use std::{
+    collections::HashMap,
+    fmt,
     future::{ready, Future, Ready},
     pin::Pin,
+    sync::Arc,
 };
 
 use actix_web::{
@@ -8,14 +23,104 @@ use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     Error, HttpResponse,
 };
+use metrics::counter;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::error;
+use tycho_common::storage::{AdminAction, Gateway};
+
+use crate::services::usage::ApiKeysConfig;
+
+/// Name under which the `AUTH_API_KEY` key shows up in the audit log.
+const ROOT_KEY_NAME: &str = "root";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Reader,
+    Operator,
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Reader => write!(f, "reader"),
+            Role::Operator => write!(f, "operator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// Name and role of every key allowed on the internal endpoints.
+#[derive(Debug, Clone, Default)]
+pub struct AdminKeys {
+    keys: HashMap<String, (String, Role)>,
+}
+
+impl AdminKeys {
+    pub fn new(root_key: &str, config: Option<&ApiKeysConfig>) -> Self {
+        let mut keys = config
+            .map(|config| {
+                config
+                    .keys
+                    .iter()
+                    .filter_map(|(key, quota)| {
+                        quota
+                            .role
+                            .map(|role| (key.clone(), (quota.name.clone(), role)))
+                    })
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+        keys.insert(root_key.to_string(), (ROOT_KEY_NAME.to_string(), Role::Admin));
+        Self { keys }
+    }
+}
+
+/// Name of the key a request was authorized with, attached to requests passing access control.
+#[derive(Debug, Clone)]
+pub struct ApiKeyName(pub String);
+
+/// Hands admin actions to a background task that appends them to the audit log.
+#[derive(Clone)]
+pub struct AuditLog(mpsc::UnboundedSender<AdminAction>);
+
+impl AuditLog {
+    pub fn spawn<G>(gateway: G) -> Self
+    where
+        G: Gateway + Send + Sync + 'static,
+    {
+        let (tx, mut rx) = mpsc::unbounded_channel::<AdminAction>();
+        tokio::spawn(async move {
+            while let Some(action) = rx.recv().await {
+                if let Err(err) = gateway
+                    .record_admin_action(&action)
+                    .await
+                {
+                    error!(error = %err, ?action, "Failed to record admin action");
+                }
+            }
+        });
+        Self(tx)
+    }
+
+    fn record(&self, action: AdminAction) {
+        if self.0.send(action).is_err() {
+            error!("Audit log task stopped, admin action not recorded");
+        }
+    }
+}
 
 pub struct AccessControl {
-    required_key: String,
+    keys: Arc<AdminKeys>,
+    required_role: Role,
+    audit: Option<AuditLog>,
 }
 
 impl AccessControl {
-    pub fn new(key: &str) -> Self {
-        Self { required_key: key.to_string() }
+    pub fn new(keys: Arc<AdminKeys>, required_role: Role, audit: Option<AuditLog>) -> Self {
+        Self { keys, required_role, audit }
     }
 }
 
@@ -30,13 +135,20 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AccessControlMiddleware { service, required_key: self.required_key.clone() }))
+        ready(Ok(AccessControlMiddleware {
+            service,
+            keys: self.keys.clone(),
+            required_role: self.required_role,
+            audit: self.audit.clone(),
+        }))
     }
 }
 
 pub struct AccessControlMiddleware<S> {
     service: S,
-    required_key: String,
+    keys: Arc<AdminKeys>,
+    required_role: Role,
+    audit: Option<AuditLog>,
 }
 
 impl<S> Service<ServiceRequest> for AccessControlMiddleware<S>
@@ -50,22 +162,128 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let required_key = self.required_key.clone();
+        let Some((name, role)) = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|key| self.keys.keys.get(key))
+            .cloned()
+        else {
+            let response = HttpResponse::Unauthorized()
+                .body("Access denied")
+                .map_into_boxed_body();
+            return Box::pin(async move { Ok(req.into_response(response)) });
+        };
 
-        if let Some(header_value) = req.headers().get("Authorization") {
-            if header_value == required_key.as_str() {
-                let fut = self.service.call(req);
+        let action = AdminAction {
+            api_key: name,
+            role: role.to_string(),
+            action: format!("{} {}", req.method(), req.path()),
+            status: 0,
+        };
+        let audit = self.audit.clone();
 
-                // More problems occur if we try to fix this warning
-                #[allow(clippy::redundant_async_block)]
-                return Box::pin(async move { fut.await });
-            }
+        if role < self.required_role {
+            counter!(
+                "rpc_requests_rejected",
+                "api_key" => action.api_key.clone(),
+                "reason" => "InsufficientRole"
+            )
+            .increment(1);
+            let response = HttpResponse::Forbidden()
+                .body(format!("Requires the {} role", self.required_role))
+                .map_into_boxed_body();
+            let res = req.into_response(response);
+            record(audit, action, &res);
+            return Box::pin(async move { Ok(res) });
         }
 
-        let response = HttpResponse::Unauthorized()
-            .body("Access denied")
-            .map_into_boxed_body();
+        req.extensions_mut()
+            .insert(ApiKeyName(action.api_key.clone()));
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            record(audit, action, &res);
+            Ok(res)
+        })
+    }
+}
+
+fn record(audit: Option<AuditLog>, mut action: AdminAction, res: &ServiceResponse<BoxBody>) {
+    if let Some(audit) = audit {
+        action.status = res.status().as_u16();
+        audit.record(action);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{http::StatusCode, test, web, App};
+
+    use super::*;
+    use crate::services::usage::ApiKeyQuota;
+
+    fn keys() -> Arc<AdminKeys> {
+        let quota =
+            |name: &str, role| ApiKeyQuota { name: name.to_string(), role, ..Default::default() };
+        Arc::new(AdminKeys::new(
+            "root_key",
+            Some(&ApiKeysConfig {
+                keys: HashMap::from([
+                    ("reader_key".to_string(), quota("dashboard", Some(Role::Reader))),
+                    ("operator_key".to_string(), quota("ops", Some(Role::Operator))),
+                    ("public_key".to_string(), quota("partner", None)),
+                ]),
+            }),
+        ))
+    }
 
-        Box::pin(async move { Ok(req.into_response(response)) })
+    #[actix_rt::test]
+    async fn test_access_control_enforces_roles() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/v1/admin/component_filter")
+                    .wrap(AccessControl::new(keys(), Role::Operator, Some(AuditLog(tx))))
+                    .route(web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let mut statuses = Vec::new();
+        for key in ["public_key", "reader_key", "operator_key", "root_key", "unknown"] {
+            let res = test::call_service(
+                &app,
+                test::TestRequest::post()
+                    .uri("/v1/admin/component_filter")
+                    .insert_header(("Authorization", key))
+                    .to_request(),
+            )
+            .await;
+            statuses.push(res.status());
+        }
+
+        assert_eq!(
+            statuses,
+            vec![
+                StatusCode::UNAUTHORIZED,
+                StatusCode::FORBIDDEN,
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::UNAUTHORIZED,
+            ]
+        );
+        let mut recorded = Vec::new();
+        while let Ok(action) = rx.try_recv() {
+            recorded.push((action.api_key, action.role, action.status));
+        }
+        assert_eq!(
+            recorded,
+            vec![
+                ("dashboard".to_string(), "reader".to_string(), 403),
+                ("ops".to_string(), "operator".to_string(), 200),
+                ("root".to_string(), "admin".to_string(), 200),
+            ]
+        );
     }
 }
@@ -0,0 +1,179 @@
+//! This module contains Tycho admin endpoints used to inspect and reconfigure running extractors.
+use std::{collections::HashMap, sync::Arc};
+
+use actix_web::{web, HttpResponse};
+use metrics::counter;
+use tracing::{error, info};
+use tycho_common::{dto, models::ExtractorIdentity};
+
+use super::usage::UsageTracker;
+use crate::extractor::runner::ExtractorHandle;
+
+pub struct AdminHandler {
+    extractors: HashMap<ExtractorIdentity, ExtractorHandle>,
+    usage: Option<Arc<UsageTracker>>,
+}
+
+impl AdminHandler {
+    pub fn new(
+        extractors: HashMap<ExtractorIdentity, ExtractorHandle>,
+        usage: Option<Arc<UsageTracker>>,
+    ) -> Self {
+        Self { extractors, usage }
+    }
+}
+
+/// Update component filter
+///
+/// Replaces the component allow/deny lists of a running extractor.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/component_filter",
+    responses(
+    (status = 200, description = "OK", body = ComponentFilterRequestBody),
+    (status = 404, description = "Extractor not found"),
+    ),
+    request_body = ComponentFilterRequestBody,
+    security(
+    ("apiKey" = [])
+    ),
+)]
+pub async fn component_filter(
+    body: web::Json<dto::ComponentFilterRequestBody>,
+    handler: web::Data<AdminHandler>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "component_filter").increment(1);
+
+    let id = ExtractorIdentity::new(body.chain.into(), &body.extractor);
+    let Some(extractor) = handler.extractors.get(&id) else {
+        counter!("rpc_requests_failed", "endpoint" => "component_filter", "status" => "404")
+            .increment(1);
+        return HttpResponse::NotFound().body(format!("Extractor not found: {id}"));
+    };
+
+    match extractor
+        .update_component_filter(body.clone().into())
+        .await
+    {
+        Ok(()) => {
+            info!(extractor = %id, "Component filter updated");
+            HttpResponse::Ok().json(body.into_inner())
+        }
+        Err(err) => {
+            error!(error = %err, ?body, "Error while updating component filter.");
+            counter!("rpc_requests_failed", "endpoint" => "component_filter", "status" => "500")
+                .increment(1);
+            HttpResponse::InternalServerError().body(err.to_string())
+        }
+    }
+}
+
+/// Extractor profile
+///
+/// Returns per-stage processing times of the most recent blocks of a running extractor.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/extractor_profile",
+    responses(
+    (status = 200, description = "OK", body = ExtractorProfile),
+    (status = 404, description = "Extractor not found"),
+    ),
+    request_body = ExtractorProfileRequestBody,
+    security(
+    ("apiKey" = [])
+    ),
+)]
+pub async fn extractor_profile(
+    body: web::Json<dto::ExtractorProfileRequestBody>,
+    handler: web::Data<AdminHandler>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "extractor_profile").increment(1);
+
+    let id = ExtractorIdentity::new(body.chain.into(), &body.extractor);
+    match handler.extractors.get(&id) {
+        Some(extractor) => HttpResponse::Ok().json(extractor.profile()),
+        None => {
+            counter!("rpc_requests_failed", "endpoint" => "extractor_profile", "status" => "404")
+                .increment(1);
+            HttpResponse::NotFound().body(format!("Extractor not found: {id}"))
+        }
+    }
+}
+
+/// Update tracked contracts
+///
+/// Adds and removes contracts tracked by a running extractor and returns all its tracked
+/// contracts.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/tracked_contracts",
+    responses(
+    (status = 200, description = "OK", body = Vec<TrackedContract>),
+    (status = 404, description = "Extractor not found"),
+    (status = 409, description = "Extractor persists all contracts"),
+    ),
+    request_body = TrackedContractsRequestBody,
+    security(
+    ("apiKey" = [])
+    ),
+)]
+pub async fn tracked_contracts(
+    body: web::Json<dto::TrackedContractsRequestBody>,
+    handler: web::Data<AdminHandler>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "tracked_contracts").increment(1);
+
+    let id = ExtractorIdentity::new(body.chain.into(), &body.extractor);
+    let Some(extractor) = handler.extractors.get(&id) else {
+        counter!("rpc_requests_failed", "endpoint" => "tracked_contracts", "status" => "404")
+            .increment(1);
+        return HttpResponse::NotFound().body(format!("Extractor not found: {id}"));
+    };
+    let Some(registry) = extractor.tracked_contracts() else {
+        counter!("rpc_requests_failed", "endpoint" => "tracked_contracts", "status" => "409")
+            .increment(1);
+        return HttpResponse::Conflict()
+            .body(format!("Extractor {id} has no tracked contracts configured"));
+    };
+
+    if !body.add.is_empty() || !body.remove.is_empty() {
+        if let Err(err) = registry.update(&body).await {
+            error!(error = %err, ?body, "Error while storing tracked contracts.");
+            counter!("rpc_requests_failed", "endpoint" => "tracked_contracts", "status" => "500")
+                .increment(1);
+            return HttpResponse::InternalServerError().body(err.to_string());
+        }
+        info!(
+            extractor = %id,
+            added = body.add.len(),
+            removed = body.remove.len(),
+            "Tracked contracts updated"
+        );
+    }
+    HttpResponse::Ok().json(registry.snapshot())
+}
+
+/// API key usage
+///
+/// Returns the resources consumed by each configured API key in the current month. Empty if no
+/// API keys are configured.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/api_key_usage",
+    responses(
+    (status = 200, description = "OK", body = Vec<ApiKeyUsage>),
+    ),
+    security(
+    ("apiKey" = [])
+    ),
+)]
+pub async fn api_key_usage(handler: web::Data<AdminHandler>) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "api_key_usage").increment(1);
+
+    let usage = handler
+        .usage
+        .as_ref()
+        .map(|tracker| tracker.snapshot())
+        .unwrap_or_default();
+    HttpResponse::Ok().json(usage)
+}
@@ -87,6 +87,11 @@ where
         }
         Ok(response)
     }
+
+    /// Drops all cached responses, e.g. after the data they were built from changed.
+    pub fn invalidate_all(&self) {
+        self.cache.invalidate_all();
+    }
 }
 
 #[cfg(test)]
@@ -117,6 +117,9 @@ impl PendingDeltas {
                         "DeltaBufferPurge"
                     );
                     guard.purge(message.block.hash.clone())?;
+                } else if message.heartbeat {
+                    // Heartbeats carry no deltas and may lack the parent hash.
+                    trace!(block_number = message.block.number, "DeltaBufferSkipHeartbeat");
                 } else {
                     trace!(
                         block_number = message.block.number,
@@ -750,6 +753,30 @@ mod test {
         assert_eq!(res[0], &exp);
     }
 
+    #[test]
+    fn test_insert_skips_heartbeat() {
+        let buffer = PendingDeltas::new(["vm:extractor"]);
+        let mut heartbeat = vm_block_deltas();
+        heartbeat.heartbeat = true;
+
+        buffer
+            .insert(Arc::new(heartbeat))
+            .expect("insert failed");
+
+        let reorg_buffer = buffer
+            .buffers
+            .get("vm:extractor")
+            .expect("extractor buffer missing");
+        let binding = reorg_buffer.lock().unwrap();
+        assert_eq!(
+            binding
+                .get_block_range(None, None)
+                .expect("Failed to get block range")
+                .count(),
+            0
+        );
+    }
+
     #[test]
     fn test_merge_native_states() {
         let mut state = vec![native_state()]; // db state
@@ -0,0 +1,395 @@
+//! Idempotency keys for admin requests.
+//!
+//! Automation retrying an admin request, e.g. after a timeout, must not run the action twice.
+//! Requests may carry an `Idempotency-Key` header for this. The first request with a key runs
+//! the action and its response is recorded; any later request with the same key is answered with
+//! the recorded response and marked with the `Idempotent-Replayed` header.
+//!
+//! Keys are scoped to the API key a request was made with and bound to the method and path of
+//! the first request. A duplicate arriving while the first request is still running is rejected
+//! with `409`, reusing a key for a different endpoint with `422`. Server errors are not recorded,
+//! so a request that failed with a `5xx` can be retried with the same key. The same applies to
+//! requests dropped before the action finished, e.g. because the client disconnected, and to
+//! requests lost in a server restart once their claim expired. Requests without the header are
+//! passed through unchanged.
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+};
+
+use actix_web::{
+    body::{self, BoxBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorInternalServerError,
+    http::{header, StatusCode},
+    Error, HttpResponse,
+};
+use metrics::counter;
+use tracing::{error, warn};
+use tycho_common::storage::{Gateway, IdempotencyClaim, IdempotentResponse};
+
+use crate::services::access_control::ApiKeyName;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Replays recorded responses of requests carrying an idempotency key.
+pub struct Idempotency<G> {
+    gateway: Arc<G>,
+}
+
+impl<G> Idempotency<G> {
+    pub fn new(gateway: G) -> Self {
+        Self { gateway: Arc::new(gateway) }
+    }
+}
+
+impl<G> Clone for Idempotency<G> {
+    fn clone(&self) -> Self {
+        Self { gateway: self.gateway.clone() }
+    }
+}
+
+impl<S, G> Transform<S, ServiceRequest> for Idempotency<G>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    G: Gateway + Send + Sync + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = IdempotencyMiddleware<S, G>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotencyMiddleware {
+            service: Rc::new(service),
+            gateway: self.gateway.clone(),
+        }))
+    }
+}
+
+pub struct IdempotencyMiddleware<S, G> {
+    service: Rc<S>,
+    gateway: Arc<G>,
+}
+
+impl<S, G> Service<ServiceRequest> for IdempotencyMiddleware<S, G>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    G: Gateway + Send + Sync + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(key) = req
+            .headers()
+            .get(IDEMPOTENCY_KEY_HEADER)
+        else {
+            let fut = self.service.call(req);
+            // More problems occur if we try to fix this warning
+            #[allow(clippy::redundant_async_block)]
+            return Box::pin(async move { fut.await });
+        };
+        let Some(key) = key
+            .to_str()
+            .ok()
+            .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+            .map(str::to_string)
+        else {
+            let response = HttpResponse::BadRequest()
+                .body(format!(
+                    "{IDEMPOTENCY_KEY_HEADER} must be 1-{MAX_KEY_LENGTH} visible ASCII characters"
+                ))
+                .map_into_boxed_body();
+            return Box::pin(async move { Ok(req.into_response(response)) });
+        };
+        let api_key = req
+            .extensions()
+            .get::<ApiKeyName>()
+            .map(|name| name.0.clone())
+            .unwrap_or_default();
+        let action = format!("{} {}", req.method(), req.path());
+        let service = self.service.clone();
+        let gateway = self.gateway.clone();
+
+        Box::pin(async move {
+            let rejection = match gateway
+                .claim_idempotency_key(&api_key, &key, &action)
+                .await
+            {
+                Ok(IdempotencyClaim::Claimed) => None,
+                Ok(IdempotencyClaim::Completed(recorded)) => {
+                    counter!("rpc_idempotent_replays", "api_key" => api_key.clone()).increment(1);
+                    Some(replay(&recorded))
+                }
+                Ok(IdempotencyClaim::InProgress) => Some(
+                    HttpResponse::Conflict()
+                        .body("A request with this idempotency key is still in progress"),
+                ),
+                Ok(IdempotencyClaim::ActionMismatch) => Some(
+                    HttpResponse::UnprocessableEntity()
+                        .body("Idempotency key was already used for a different request"),
+                ),
+                Err(err) => {
+                    error!(error = %err, key, action, "Failed to claim idempotency key");
+                    Some(HttpResponse::ServiceUnavailable().body("Idempotency keys unavailable"))
+                }
+            };
+            if let Some(response) = rejection {
+                return Ok(req.into_response(response.map_into_boxed_body()));
+            }
+
+            let claim = ClaimGuard::new(gateway.clone(), &api_key, &key);
+            let res = match service.call(req).await {
+                Ok(res) if !res.status().is_server_error() => {
+                    claim.disarm();
+                    res
+                }
+                res => {
+                    claim.release().await;
+                    return res;
+                }
+            };
+            let (req, response) = res.into_parts();
+            let (response, body) = response.into_parts();
+            let body = body::to_bytes(body)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            let recorded = IdempotentResponse {
+                status: response.status().as_u16(),
+                content_type: response
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                body: body.to_vec(),
+            };
+            // The action ran, so the key stays claimed even if its response can't be recorded.
+            if let Err(err) = gateway
+                .complete_idempotency_key(&api_key, &key, &recorded)
+                .await
+            {
+                error!(error = %err, key, action, "Failed to record idempotent response");
+            }
+            Ok(ServiceResponse::new(
+                req,
+                response
+                    .set_body(body)
+                    .map_into_boxed_body(),
+            ))
+        })
+    }
+}
+
+async fn release<G: Gateway>(gateway: &G, api_key: &str, key: &str) {
+    if let Err(err) = gateway
+        .release_idempotency_key(api_key, key)
+        .await
+    {
+        warn!(error = %err, key, "Failed to release idempotency key");
+    }
+}
+
+/// Releases a claimed key if the request is dropped before its action finished.
+struct ClaimGuard<G: Gateway + Send + Sync + 'static> {
+    gateway: Arc<G>,
+    api_key: String,
+    key: String,
+    armed: bool,
+}
+
+impl<G: Gateway + Send + Sync + 'static> ClaimGuard<G> {
+    fn new(gateway: Arc<G>, api_key: &str, key: &str) -> Self {
+        Self { gateway, api_key: api_key.to_string(), key: key.to_string(), armed: true }
+    }
+
+    /// Keeps the key claimed, the action ran.
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+
+    async fn release(mut self) {
+        self.armed = false;
+        release(self.gateway.as_ref(), &self.api_key, &self.key).await;
+    }
+}
+
+impl<G: Gateway + Send + Sync + 'static> Drop for ClaimGuard<G> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            warn!(key = %self.key, "Dropped idempotency key claim left until its lease expires");
+            return;
+        };
+        let gateway = self.gateway.clone();
+        let api_key = std::mem::take(&mut self.api_key);
+        let key = std::mem::take(&mut self.key);
+        runtime.spawn(async move {
+            release(gateway.as_ref(), &api_key, &key).await;
+        });
+    }
+}
+
+fn replay(recorded: &IdempotentResponse) -> HttpResponse {
+    let mut response = HttpResponse::build(
+        StatusCode::from_u16(recorded.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+    );
+    if let Some(content_type) = &recorded.content_type {
+        response.insert_header((header::CONTENT_TYPE, content_type.as_str()));
+    }
+    response
+        .insert_header((REPLAYED_HEADER, "true"))
+        .body(recorded.body.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use actix_web::{test, web, App};
+    use mockall::predicate::eq;
+
+    use super::*;
+    use crate::testing::MockGateway;
+
+    #[actix_rt::test]
+    async fn test_idempotency_replays_completed_requests() {
+        let recorded = IdempotentResponse {
+            status: 200,
+            content_type: Some("application/json".to_string()),
+            body: br#"{"applied":1}"#.to_vec(),
+        };
+        let mut gw = MockGateway::new();
+        let mut seq = mockall::Sequence::new();
+        gw.expect_claim_idempotency_key()
+            .with(eq(""), eq("retry-1"), eq("POST /v1/admin/component_filter"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_, _, _| Ok(IdempotencyClaim::Claimed));
+        gw.expect_complete_idempotency_key()
+            .with(eq(""), eq("retry-1"), eq(recorded.clone()))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_, _, _| Ok(()));
+        let replayed = recorded.clone();
+        gw.expect_claim_idempotency_key()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(move |_, _, _| Ok(IdempotencyClaim::Completed(replayed)));
+        gw.expect_claim_idempotency_key()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_, _, _| Ok(IdempotencyClaim::InProgress));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/v1/admin/component_filter")
+                    .wrap(Idempotency::new(gw))
+                    .route(web::post().to(move || {
+                        handler_calls.fetch_add(1, Ordering::SeqCst);
+                        async { HttpResponse::Ok().json(serde_json::json!({"applied": 1})) }
+                    })),
+            ),
+        )
+        .await;
+        let request = || {
+            test::TestRequest::post()
+                .uri("/v1/admin/component_filter")
+                .insert_header((IDEMPOTENCY_KEY_HEADER, "retry-1"))
+                .to_request()
+        };
+
+        let first = test::call_service(&app, request()).await;
+        let first_replayed = first
+            .headers()
+            .contains_key(REPLAYED_HEADER);
+        let first_body = test::read_body(first).await;
+        let duplicate = test::call_service(&app, request()).await;
+        let duplicate_replayed = duplicate
+            .headers()
+            .contains_key(REPLAYED_HEADER);
+        let duplicate_body = test::read_body(duplicate).await;
+        let concurrent = test::call_service(&app, request()).await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(!first_replayed);
+        assert!(duplicate_replayed);
+        assert_eq!(first_body, recorded.body);
+        assert_eq!(duplicate_body, recorded.body);
+        assert_eq!(concurrent.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_rt::test]
+    async fn test_idempotency_releases_keys_of_failed_requests() {
+        let mut gw = MockGateway::new();
+        gw.expect_claim_idempotency_key()
+            .times(1)
+            .return_once(|_, _, _| Ok(IdempotencyClaim::Claimed));
+        gw.expect_release_idempotency_key()
+            .with(eq(""), eq("retry-2"))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        gw.expect_complete_idempotency_key()
+            .never();
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/v1/admin/component_filter")
+                    .wrap(Idempotency::new(gw))
+                    .route(web::post().to(HttpResponse::InternalServerError)),
+            ),
+        )
+        .await;
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/v1/admin/component_filter")
+                .insert_header((IDEMPOTENCY_KEY_HEADER, "retry-2"))
+                .to_request(),
+        )
+        .await;
+        let without_key = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/v1/admin/component_filter")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(without_key.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_releases_keys_of_dropped_requests() {
+        let (released_tx, released_rx) = tokio::sync::oneshot::channel();
+        let mut gw = MockGateway::new();
+        gw.expect_release_idempotency_key()
+            .with(eq("ops"), eq("retry-3"))
+            .times(1)
+            .return_once(move |_, _| {
+                released_tx.send(()).unwrap();
+                Ok(())
+            });
+        let gw = Arc::new(gw);
+
+        ClaimGuard::new(gw.clone(), "ops", "retry-4").disarm();
+        drop(ClaimGuard::new(gw, "ops", "retry-3"));
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), released_rx)
+            .await
+            .expect("claim was not released")
+            .unwrap();
+    }
+}
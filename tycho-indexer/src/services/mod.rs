@@ -12,18 +12,32 @@ use tokio::task::JoinHandle;
 use tracing::info;
 use tycho_common::{
     dto::{
-        AccountUpdate, BlockParam, Chain, ChangeType, ComponentTvlRequestBody,
-        ComponentTvlRequestResponse, ContractId, Health, PaginationParams, PaginationResponse,
-        ProtocolComponent, ProtocolComponentRequestResponse, ProtocolComponentsRequestBody,
-        ProtocolId, ProtocolStateDelta, ProtocolStateRequestBody, ProtocolStateRequestResponse,
-        ProtocolSystemsRequestBody, ProtocolSystemsRequestResponse, ResponseAccount,
-        ResponseProtocolState, ResponseToken, StateRequestBody, StateRequestResponse,
-        TokensRequestBody, TokensRequestResponse, TracedEntryPointRequestBody,
-        TracedEntryPointRequestResponse, VersionParam,
+        AccountField, AccountProof, AccountUpdate, AnalyticsQueryRequestBody,
+        AnalyticsQueryResponse, AttributeSchemaDiff, AuditLogEntry, AuditLogRequestBody,
+        AuditLogRequestResponse, BalanceHistoryRequestBody, BalanceHistoryRequestResponse,
+        BalanceSample, BlockDigest, BlockDigestsRequestBody, BlockDigestsRequestResponse,
+        BlockParam, Chain, ChainHeadResponse, ChangeType, CodeMode, ComponentAttributeSchemaDiff,
+        ComponentField, ComponentTvlRequestBody, ComponentTvlRequestResponse, ContractId,
+        ContractStorageProofRequestBody, ContractStorageProofResponse, DeploymentParityRequestBody,
+        DeploymentParityResponse, DisplayFormat, ExtractorStatus, ExtractorStatusRequestBody,
+        Health, ImplementationType, MultiChainSnapshotRequestBody, MultiChainSnapshotResponse,
+        PaginationParams, PaginationResponse, ProtocolComponent, ProtocolComponentRequestResponse,
+        ProtocolComponentsRequestBody, ProtocolId, ProtocolStateDelta, ProtocolStateRequestBody,
+        ProtocolStateRequestResponse, ProtocolSystemSummariesResponse, ProtocolSystemSummary,
+        ProtocolSystemsRequestBody, ProtocolSystemsRequestResponse, RelativeVersion,
+        ResponseAccount, ResponseProtocolState, ResponseToken, SampleInterval, SortOrder,
+        StateRequestBody, StateRequestResponse, StorageProof, SubstreamsPackage, TokenOverride,
+        TokenOverridesRequestBody, TokenOverridesRequestResponse, TokensRequestBody,
+        TokensRequestResponse, TracedEntryPointRequestBody, TracedEntryPointRequestResponse,
+        TrackedAddressesRequestBody, TrackedAddressesRequestResponse, VersionParam,
     },
+    models::ExtractorIdentity,
+    serde_primitives::ValueEncoding,
     storage::Gateway,
 };
-use tycho_ethereum::entrypoint_tracer::tracer::EVMEntrypointService;
+use tycho_ethereum::{
+    entrypoint_tracer::tracer::EVMEntrypointService, token_analyzer::rpc_client::EthereumRpcClient,
+};
 use utoipa::{
     openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
     Modify, OpenApi,
@@ -31,15 +45,29 @@ use utoipa::{
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
-    extractor::{runner::ExtractorHandle, ExtractionError},
-    services::deltas_buffer::PendingDeltas,
+    extractor::{
+        message_bus::MessageBus,
+        runner::{ExtractorHandle, MessageSender},
+        ExtractionError,
+    },
+    services::{
+        access_control::Role,
+        deltas_buffer::PendingDeltas,
+        versioning::{ApiVersion, Deprecations},
+    },
 };
 
-mod access_control;
+pub mod access_control;
+mod admin;
 mod cache;
 mod deltas_buffer;
+pub mod idempotency;
+pub mod route_metrics;
 mod rpc;
+pub mod usage;
+pub mod versioning;
 mod ws;
+pub mod ws_queue;
 
 /// Helper struct to build Tycho services such as HTTP and WS server.
 pub struct ServicesBuilder<G> {
@@ -49,14 +77,22 @@ pub struct ServicesBuilder<G> {
     rpc_url: String,
     api_key: String,
     extractor_handles: ws::MessageSenderMap,
+    admin_handles: HashMap<ExtractorIdentity, ExtractorHandle>,
+    message_bus: Option<Arc<MessageBus>>,
+    usage: Option<Arc<usage::UsageTracker>>,
+    admin_keys: access_control::AdminKeys,
+    deprecations: Deprecations,
+    ws_delivery: ws_queue::DeliveryConfig,
+    http_workers: Option<usize>,
     db_gateway: G,
 }
 
 impl<G> ServicesBuilder<G>
 where
-    G: Gateway + Send + Sync + 'static,
+    G: Gateway + Clone + Send + Sync + 'static,
 {
     pub fn new(db_gateway: G, rpc_url: String, api_key: String) -> Self {
+        let admin_keys = access_control::AdminKeys::new(&api_key, None);
         Self {
             prefix: "v1".to_owned(),
             port: 4242,
@@ -64,6 +100,13 @@ where
             rpc_url,
             api_key,
             extractor_handles: HashMap::new(),
+            admin_handles: HashMap::new(),
+            message_bus: None,
+            usage: None,
+            admin_keys,
+            deprecations: Deprecations::new(),
+            ws_delivery: ws_queue::DeliveryConfig::default(),
+            http_workers: None,
             db_gateway,
         }
     }
@@ -72,13 +115,52 @@ where
     pub fn register_extractors(mut self, handles: Vec<ExtractorHandle>) -> Self {
         for e in handles {
             let id = e.get_id();
+            self.admin_handles
+                .insert(id.clone(), e.clone());
             self.extractor_handles
                 .insert(id, Arc::new(e));
         }
         self
     }
 
-    /// Sets the URL prefix for the endpoints
+    /// Serves websocket subscriptions from the message bus instead of subscribing to the
+    /// extractors directly, if a bus is given. Slow websocket clients then skip messages instead of
+    /// stalling the extractors: their subscriptions end once they skipped any, so the client has to
+    /// resubscribe. The pending deltas buffer always subscribes directly.
+    pub fn message_bus(mut self, bus: Option<Arc<MessageBus>>) -> Self {
+        self.message_bus = bus;
+        self
+    }
+
+    /// Requires one of the configured API keys on all public RPC endpoints and meters their usage
+    /// against the keys' quotas. Without a config the endpoints stay open. Keys with a role may
+    /// also call the internal endpoints the role grants access to.
+    pub fn api_keys(mut self, config: Option<usage::ApiKeysConfig>) -> Self {
+        self.admin_keys = access_control::AdminKeys::new(&self.api_key, config.as_ref());
+        self.usage = config.map(|config| Arc::new(usage::UsageTracker::new(config)));
+        self
+    }
+
+    /// Bounds the messages queued for each websocket client and sets what happens to clients that
+    /// fall behind.
+    pub fn ws_delivery(mut self, config: ws_queue::DeliveryConfig) -> Self {
+        self.ws_delivery = config;
+        self
+    }
+
+    /// Sets the worker threads of the http server, by default one per CPU.
+    pub fn http_workers(mut self, workers: Option<usize>) -> Self {
+        self.http_workers = workers;
+        self
+    }
+
+    /// Announces deprecated API versions in the headers of their responses.
+    pub fn deprecations(mut self, deprecations: Deprecations) -> Self {
+        self.deprecations = deprecations;
+        self
+    }
+
+    /// Sets the URL prefix of the v1 endpoints, v2 endpoints are always served under `/v2`
     pub fn prefix(mut self, v: &str) -> Self {
         v.clone_into(&mut self.prefix);
         self
@@ -113,12 +195,30 @@ where
                 rpc::traced_entry_points,
                 rpc::protocol_state,
                 rpc::contract_state,
+                rpc::contract_storage_proof,
                 rpc::component_tvl,
+                rpc::balance_history,
+                rpc::block_digests,
+                rpc::tracked_addresses,
+                rpc::chain_head,
+                rpc::protocol_system_summaries,
+                rpc::multi_chain_snapshot,
+                rpc::contract_code,
+                rpc::analytics_query,
+                rpc::token_overrides,
+                rpc::audit_log,
+                rpc::deployment_parity,
+                rpc::extractor_status,
             ),
             components(
                 schemas(VersionParam),
                 schemas(BlockParam),
+                schemas(RelativeVersion),
                 schemas(ContractId),
+                schemas(ContractStorageProofRequestBody),
+                schemas(ContractStorageProofResponse),
+                schemas(AccountProof),
+                schemas(StorageProof),
                 schemas(StateRequestResponse),
                 schemas(StateRequestBody),
                 schemas(Chain),
@@ -145,6 +245,42 @@ where
                 schemas(ProtocolSystemsRequestResponse),
                 schemas(ComponentTvlRequestBody),
                 schemas(ComponentTvlRequestResponse),
+                schemas(BalanceHistoryRequestBody),
+                schemas(BalanceHistoryRequestResponse),
+                schemas(BalanceSample),
+                schemas(SampleInterval),
+                schemas(BlockDigestsRequestBody),
+                schemas(BlockDigestsRequestResponse),
+                schemas(BlockDigest),
+                schemas(TrackedAddressesRequestBody),
+                schemas(TrackedAddressesRequestResponse),
+                schemas(ChainHeadResponse),
+                schemas(ImplementationType),
+                schemas(ProtocolSystemSummary),
+                schemas(ProtocolSystemSummariesResponse),
+                schemas(MultiChainSnapshotRequestBody),
+                schemas(MultiChainSnapshotResponse),
+                schemas(AnalyticsQueryRequestBody),
+                schemas(AnalyticsQueryResponse),
+                schemas(TokenOverride),
+                schemas(TokenOverridesRequestBody),
+                schemas(TokenOverridesRequestResponse),
+                schemas(AuditLogRequestBody),
+                schemas(AuditLogEntry),
+                schemas(AuditLogRequestResponse),
+                schemas(DeploymentParityRequestBody),
+                schemas(AttributeSchemaDiff),
+                schemas(ComponentAttributeSchemaDiff),
+                schemas(DeploymentParityResponse),
+                schemas(ExtractorStatusRequestBody),
+                schemas(SubstreamsPackage),
+                schemas(ExtractorStatus),
+                schemas(DisplayFormat),
+                schemas(ValueEncoding),
+                schemas(AccountField),
+                schemas(CodeMode),
+                schemas(SortOrder),
+                schemas(ComponentField),
             ),
             modifiers(&SecurityAddon),
         )]
@@ -199,9 +335,22 @@ where
                 .await
                 .map_err(|err| ExtractionError::Unknown(err.to_string()))
         });
-        let ws_data = web::Data::new(ws::WsData::new(self.extractor_handles.clone()));
+        let ws_subscribers = match &self.message_bus {
+            Some(bus) => self
+                .extractor_handles
+                .keys()
+                .map(|id| {
+                    (
+                        id.clone(),
+                        Arc::new(bus.subscriber(id.clone()))
+                            as Arc<dyn MessageSender + Send + Sync>,
+                    )
+                })
+                .collect(),
+            None => self.extractor_handles.clone(),
+        };
         let (server_handle, server_task) =
-            self.start_server(Some(ws_data), openapi, Some(Arc::new(pending_deltas)))?;
+            self.start_server(Some(ws_subscribers), openapi, Some(Arc::new(pending_deltas)))?;
 
         let task = tokio::spawn(async move {
             try_join_all(vec![deltas_task, server_task])
@@ -216,17 +365,40 @@ where
     /// Helper to spawn the main server task, optionally enabling WebSocket services.
     fn start_server(
         self,
-        ws_data: Option<web::Data<ws::WsData>>,
+        ws_subscribers: Option<ws::MessageSenderMap>,
         openapi: utoipa::openapi::OpenApi,
         pending_deltas: Option<Arc<dyn PendingDeltasBuffer + Send + Sync>>,
     ) -> Result<(ServerHandle, JoinHandle<Result<(), ExtractionError>>), ExtractionError> {
         let tracer = EVMEntrypointService::try_from_url(&self.rpc_url)
             .map_err(|err| ExtractionError::Setup(format!("Failed to create tracer: {err}")))?;
 
-        let rpc_data =
-            web::Data::new(rpc::RpcHandler::new(self.db_gateway, pending_deltas, tracer));
+        let proof_fetcher = Arc::new(EthereumRpcClient::new_from_url(&self.rpc_url));
+        let rpc_data = web::Data::new(
+            rpc::RpcHandler::new(self.db_gateway, pending_deltas, tracer)
+                .with_proof_fetcher(proof_fetcher),
+        );
+        // Snapshots are served like protocol state requests, including unconfirmed changes.
+        let ws_data = ws_subscribers.map(|subscribers| {
+            web::Data::new(
+                ws::WsData::new(subscribers)
+                    .with_snapshots(rpc_data.clone().into_inner())
+                    .with_delivery(self.ws_delivery),
+            )
+        });
+        let audit = access_control::AuditLog::spawn(self.db_gateway.clone());
+        if let Some(usage) = &self.usage {
+            usage
+                .clone()
+                .spawn_flush(self.db_gateway.clone());
+        }
+        let idempotency = idempotency::Idempotency::new(self.db_gateway.clone());
+        let admin_keys = Arc::new(self.admin_keys);
+        let admin_data =
+            web::Data::new(admin::AdminHandler::new(self.admin_handles, self.usage.clone()));
+        let metering = usage::UsageMetering::new(self.usage);
+        let deprecations = Arc::new(self.deprecations);
 
-        let server = HttpServer::new(move || {
+        let mut server = HttpServer::new(move || {
             let cors = Cors::default()
                 .allowed_origin("https://open.gitbook.com")
                 .allowed_origin_fn(|origin, _req_head| {
@@ -240,71 +412,205 @@ where
                     http::header::AUTHORIZATION,
                     http::header::ACCEPT,
                     http::header::CONTENT_TYPE,
+                    http::header::HeaderName::from_static("api-version"),
                 ])
                 .max_age(3600); // Cache preflight requests for 1 hour
+            let access = |role| {
+                access_control::AccessControl::new(admin_keys.clone(), role, Some(audit.clone()))
+            };
+
+            // All versions share the same handlers, handlers with breaking changes take the
+            // `ApiVersion` of the request.
+            let routes = |prefix: &str, version: ApiVersion| {
+                let mut scope = web::scope(prefix)
+                    .service(
+                        web::resource("/contract_state")
+                            .wrap(metering.clone())
+                            .route(web::post().to(rpc::contract_state::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/contract_storage_proof")
+                            .wrap(metering.clone())
+                            .route(
+                                web::post()
+                                    .to(rpc::contract_storage_proof::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/protocol_state")
+                            .wrap(metering.clone())
+                            .route(web::post().to(rpc::protocol_state::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/tokens")
+                            .wrap(metering.clone())
+                            .route(web::post().to(rpc::tokens::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/protocol_components")
+                            .wrap(metering.clone())
+                            .route(
+                                web::post().to(rpc::protocol_components::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/traced_entry_points")
+                            .wrap(metering.clone())
+                            .route(
+                                web::post().to(rpc::traced_entry_points::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/add_entry_points")
+                            // TODO: add swagger service for internal endpoints
+                            .wrap(idempotency.clone())
+                            .wrap(access(Role::Operator))
+                            .route(
+                                web::post().to(rpc::add_entry_points::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/admin/component_filter")
+                            .wrap(idempotency.clone())
+                            .wrap(access(Role::Operator))
+                            .route(web::post().to(admin::component_filter)),
+                    )
+                    .service(
+                        web::resource("/admin/extractor_profile")
+                            .wrap(access(Role::Reader))
+                            .route(web::post().to(admin::extractor_profile)),
+                    )
+                    .service(
+                        web::resource("/admin/tracked_contracts")
+                            .wrap(idempotency.clone())
+                            .wrap(access(Role::Operator))
+                            .route(web::post().to(admin::tracked_contracts)),
+                    )
+                    .service(
+                        web::resource("/admin/api_key_usage")
+                            .wrap(access(Role::Admin))
+                            .route(web::get().to(admin::api_key_usage)),
+                    )
+                    .service(
+                        web::resource("/admin/analytics_query")
+                            .wrap(access(Role::Reader))
+                            .route(web::post().to(rpc::analytics_query::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/admin/token_overrides")
+                            .wrap(idempotency.clone())
+                            .wrap(access(Role::Operator))
+                            .route(web::post().to(rpc::token_overrides::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/admin/audit_log")
+                            .wrap(access(Role::Admin))
+                            .route(web::post().to(rpc::audit_log::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/admin/deployment_parity")
+                            .wrap(access(Role::Reader))
+                            .route(
+                                web::post().to(rpc::deployment_parity::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/admin/extractor_status")
+                            .wrap(access(Role::Reader))
+                            .route(
+                                web::post().to(rpc::extractor_status::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(web::resource("/health").route(web::get().to(rpc::health)))
+                    .service(
+                        web::resource("/protocol_systems")
+                            .wrap(metering.clone())
+                            .route(
+                                web::post().to(rpc::protocol_systems::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/component_tvl")
+                            .wrap(metering.clone())
+                            .route(web::post().to(rpc::component_tvl::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/balance_history")
+                            .wrap(metering.clone())
+                            .route(web::post().to(rpc::balance_history::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/block_digests")
+                            .wrap(metering.clone())
+                            .route(web::post().to(rpc::block_digests::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/tracked_addresses")
+                            .wrap(metering.clone())
+                            .route(
+                                web::post().to(rpc::tracked_addresses::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/{chain}/head")
+                            .wrap(metering.clone())
+                            .route(web::get().to(rpc::chain_head::<G, EVMEntrypointService>)),
+                    )
+                    .service(
+                        web::resource("/{chain}/protocol_systems")
+                            .wrap(metering.clone())
+                            .route(
+                                web::get()
+                                    .to(rpc::protocol_system_summaries::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/multi_chain_snapshot")
+                            .wrap(metering.clone())
+                            .route(
+                                web::post()
+                                    .to(rpc::multi_chain_snapshot::<G, EVMEntrypointService>),
+                            ),
+                    )
+                    .service(
+                        web::resource("/{chain}/contract_code/{code_hash}")
+                            .wrap(metering.clone())
+                            .route(web::get().to(rpc::contract_code::<G, EVMEntrypointService>)),
+                    );
+                if ws_data.is_some() {
+                    scope = scope
+                        .service(web::resource("/ws").route(web::get().to(ws::WsActor::ws_index)));
+                }
+                scope.wrap(versioning::Versioning::new(version, deprecations.clone()))
+            };
 
             let mut app = App::new()
                 .wrap(cors)
                 .app_data(rpc_data.clone())
-                .service(
-                    web::resource(format!("/{}/contract_state", self.prefix))
-                        .route(web::post().to(rpc::contract_state::<G, EVMEntrypointService>)),
-                )
-                .service(
-                    web::resource(format!("/{}/protocol_state", self.prefix))
-                        .route(web::post().to(rpc::protocol_state::<G, EVMEntrypointService>)),
-                )
-                .service(
-                    web::resource(format!("/{}/tokens", self.prefix))
-                        .route(web::post().to(rpc::tokens::<G, EVMEntrypointService>)),
-                )
-                .service(
-                    web::resource(format!("/{}/protocol_components", self.prefix))
-                        .route(web::post().to(rpc::protocol_components::<G, EVMEntrypointService>)),
-                )
-                .service(
-                    web::resource(format!("/{}/traced_entry_points", self.prefix))
-                        .route(web::post().to(rpc::traced_entry_points::<G, EVMEntrypointService>)),
-                )
-                .service(
-                    web::resource(format!("/{}/add_entry_points", self.prefix))
-                        // TODO: add swagger service for internal endpoints
-                        .wrap(access_control::AccessControl::new(&self.api_key))
-                        .route(web::post().to(rpc::add_entry_points::<G, EVMEntrypointService>)),
-                )
-                .service(
-                    web::resource(format!("/{}/health", self.prefix))
-                        .route(web::get().to(rpc::health)),
-                )
-                .service(
-                    web::resource(format!("/{}/protocol_systems", self.prefix))
-                        .route(web::post().to(rpc::protocol_systems::<G, EVMEntrypointService>)),
-                )
-                .service(
-                    web::resource(format!("/{}/component_tvl", self.prefix))
-                        .route(web::post().to(rpc::component_tvl::<G, EVMEntrypointService>)),
-                )
-                .wrap(RequestTracing::new())
-                .service(
-                    SwaggerUi::new("/docs/{_:.*}").url("/api-docs/openapi.json", openapi.clone()),
-                );
-
+                .app_data(admin_data.clone());
             if let Some(ws_data) = ws_data.clone() {
-                app = app.app_data(ws_data).service(
-                    web::resource(format!("/{}/ws", self.prefix))
-                        .route(web::get().to(ws::WsActor::ws_index)),
-                );
+                app = app.app_data(ws_data);
             }
 
-            app
+            app.service(routes(&format!("/{}", self.prefix), ApiVersion::V1))
+                .service(routes("/v2", ApiVersion::V2))
+                .wrap(RequestTracing::new())
+                .wrap(route_metrics::RouteMetrics)
+                .service(
+                    SwaggerUi::new("/docs/{_:.*}").url("/api-docs/openapi.json", openapi.clone()),
+                )
         })
         .keep_alive(std::time::Duration::from_secs(60)) // prevents early connection closures
         // Allows clients up to 30 seconds to reconnect before forcefully closing the connection.
         // This prevents us from closing a connection the client is expecting to be able to reuse.
-        .client_disconnect_timeout(std::time::Duration::from_secs(30))
-        .bind_auto_h2c((self.bind, self.port)) // allow HTTP2 requests over http connections
-        .map_err(|err| ExtractionError::ServiceError(err.to_string()))?
-        .run();
+        .client_disconnect_timeout(std::time::Duration::from_secs(30));
+        if let Some(workers) = self.http_workers {
+            server = server.workers(workers);
+        }
+        let server = server
+            .bind_auto_h2c((self.bind, self.port)) // allow HTTP2 requests over http connections
+            .map_err(|err| ExtractionError::ServiceError(err.to_string()))?
+            .run();
         let handle = server.handle();
         let task = tokio::spawn(async move {
             server
@@ -0,0 +1,131 @@
+//! Per route latency, response size and status metrics of the RPC server.
+//!
+//! Routes are labelled by their pattern, e.g. `/v1/contract_state`, so service levels can be
+//! tracked per endpoint. The histograms are exported with [`LATENCY_BUCKETS`] and
+//! [`RESPONSE_SIZE_BUCKETS`].
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    time::Instant,
+};
+
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use metrics::{counter, histogram};
+
+pub const LATENCY_METRIC: &str = "rpc_request_duration_seconds";
+pub const RESPONSE_SIZE_METRIC: &str = "rpc_response_size_bytes";
+
+/// Histogram buckets of [`LATENCY_METRIC`] in seconds.
+pub const LATENCY_BUCKETS: [f64; 12] =
+    [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Histogram buckets of [`RESPONSE_SIZE_METRIC`] in bytes.
+pub const RESPONSE_SIZE_BUCKETS: [f64; 8] = [1e3, 1e4, 1e5, 5e5, 1e6, 5e6, 1e7, 5e7];
+
+/// Records the latency, response size and status of every request.
+///
+/// Requests not matching any route are recorded under the `unmatched` route to keep the number of
+/// label values bounded.
+pub struct RouteMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RouteMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RouteMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RouteMetricsMiddleware { service }))
+    }
+}
+
+pub struct RouteMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RouteMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        let start = Instant::now();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await;
+            let status = match &res {
+                Ok(res) => res.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+            histogram!(LATENCY_METRIC, "route" => route.clone())
+                .record(start.elapsed().as_secs_f64());
+            if let Ok(res) = &res {
+                if let BodySize::Sized(size) = res.response().body().size() {
+                    histogram!(RESPONSE_SIZE_METRIC, "route" => route.clone()).record(size as f64);
+                }
+            }
+            counter!(
+                "rpc_responses",
+                "route" => route,
+                "status" => status.as_str().to_string()
+            )
+            .increment(1);
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_route_metrics_passes_responses_through() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RouteMetrics)
+                .route("/v1/tokens", web::post().to(|| async { HttpResponse::Ok().body("[]") })),
+        )
+        .await;
+
+        let ok = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/v1/tokens")
+                .to_request(),
+        )
+        .await;
+        let unmatched = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/v1/unknown")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(ok.status(), StatusCode::OK);
+        assert_eq!(test::read_body(ok).await, "[]");
+        assert_eq!(unmatched.status(), StatusCode::NOT_FOUND);
+    }
+}
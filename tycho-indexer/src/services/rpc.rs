@@ -2,40 +2,256 @@
 #![allow(deprecated)]
 use std::{
     collections::{HashMap, HashSet},
+    str::FromStr,
     sync::Arc,
 };
 
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{http::header::CACHE_CONTROL, web, HttpResponse, ResponseError};
 use anyhow::Error;
+use async_trait::async_trait;
 use chrono::{Duration, Utc};
 use diesel_async::pooled_connection::deadpool;
+use futures03::future::try_join_all;
 use metrics::counter;
 use reqwest::StatusCode;
+use serde::Serialize;
 use thiserror::Error;
 use tracing::{debug, error, info, instrument, trace, warn};
 use tycho_common::{
     dto::{self, PaginationResponse},
     models::{
         blockchain::{BlockAggregatedChanges, EntryPoint, TracedEntryPoint, TracingParams},
-        protocol::QualityRange,
-        Address, Chain, ComponentId, EntryPointId, PaginationParams,
+        protocol::{DeploymentParity, ProtocolComponent, QualityRange, SampleInterval},
+        Address, Chain, ComponentCursor, ComponentId, EntryPointId, ExtractorIdentity,
+        PaginationParams,
     },
+    serde_primitives::with_value_encoding,
     storage::{
-        BlockIdentifier, BlockOrTimestamp, EntryPointFilter, Gateway, StorageError, Version,
-        VersionKind,
+        BlockIdentifier, BlockOrTimestamp, ContractFilter, EntryPointFilter, Gateway, SortOrder,
+        StorageError, Version, VersionKind,
     },
-    traits::EntryPointTracer,
+    traits::{EntryPointTracer, StorageProofFetcher},
     Bytes,
 };
 
 use crate::{
-    extractor::reorg_buffer::{BlockNumberOrTimestamp, FinalityStatus},
+    extractor::{
+        reorg_buffer::{BlockNumberOrTimestamp, FinalityStatus},
+        u256_num::bytes_to_f64,
+    },
     services::{
         cache::RpcCache,
         deltas_buffer::{PendingDeltasBuffer, PendingDeltasError},
+        usage::with_rows_returned,
+        ws::SnapshotSource,
     },
 };
 
+/// Upper bound on the slots proven by a single storage proof request.
+const MAX_PROOF_SLOTS: usize = 1_000;
+
+/// Upper bound on the samples returned by a single balance history request.
+const MAX_BALANCE_HISTORY_SAMPLES: i64 = 2_000;
+
+/// Upper bound on the blocks covered by a single block digests request.
+const MAX_BLOCK_DIGESTS: u64 = 10_000;
+
+/// Upper bound on the addresses checked by a single tracked addresses request.
+const MAX_TRACKED_ADDRESSES: usize = 10_000;
+
+/// Upper bound on the state queries fanned out by a single multi chain snapshot request.
+const MAX_SNAPSHOT_QUERIES: usize = 20;
+
+/// Upper bound and default of the rows returned by a single analytics query.
+const MAX_ANALYTICS_ROWS: usize = 1_000;
+
+/// Analytics queries running longer than this are cancelled.
+const ANALYTICS_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Keywords of statements, clauses and commands that write or change the session.
+/// `SET_CONFIG` could switch back from the restricted role storage runs the query as.
+const FORBIDDEN_ANALYTICS_KEYWORDS: [&str; 19] = [
+    "INSERT",
+    "UPDATE",
+    "DELETE",
+    "MERGE",
+    "UPSERT",
+    "TRUNCATE",
+    "CREATE",
+    "ALTER",
+    "DROP",
+    "GRANT",
+    "REVOKE",
+    "COPY",
+    "CALL",
+    "DO",
+    "LOCK",
+    "INTO",
+    "SET",
+    "RESET",
+    "SET_CONFIG",
+];
+
+/// Functions analytics queries may call. Others could stall or change the pooled connection the
+/// query runs on, e.g. `pg_sleep`, `pg_advisory_lock` or `set_config`.
+const ALLOWED_ANALYTICS_FUNCTIONS: [&str; 60] = [
+    "abs",
+    "age",
+    "array_agg",
+    "array_length",
+    "avg",
+    "bool_and",
+    "bool_or",
+    "cast",
+    "ceil",
+    "coalesce",
+    "concat",
+    "count",
+    "date_part",
+    "date_trunc",
+    "decode",
+    "dense_rank",
+    "encode",
+    "exp",
+    "extract",
+    "first_value",
+    "floor",
+    "greatest",
+    "jsonb_agg",
+    "jsonb_build_object",
+    "json_agg",
+    "json_build_object",
+    "lag",
+    "last_value",
+    "lead",
+    "least",
+    "left",
+    "length",
+    "ln",
+    "log",
+    "lower",
+    "max",
+    "min",
+    "mod",
+    "nullif",
+    "ntile",
+    "octet_length",
+    "percentile_cont",
+    "percentile_disc",
+    "position",
+    "power",
+    "rank",
+    "replace",
+    "right",
+    "round",
+    "row_number",
+    "sqrt",
+    "stddev",
+    "string_agg",
+    "substr",
+    "substring",
+    "sum",
+    "to_char",
+    "trim",
+    "trunc",
+    "upper",
+];
+
+/// Keywords and type names that may precede an opening parenthesis without calling a function.
+const ANALYTICS_PARENTHESIZED_KEYWORDS: [&str; 40] = [
+    "all",
+    "and",
+    "any",
+    "array",
+    "as",
+    "between",
+    "by",
+    "case",
+    "char",
+    "decimal",
+    "distinct",
+    "else",
+    "except",
+    "exists",
+    "filter",
+    "from",
+    "group",
+    "having",
+    "ilike",
+    "in",
+    "intersect",
+    "is",
+    "join",
+    "lateral",
+    "like",
+    "not",
+    "numeric",
+    "on",
+    "or",
+    "over",
+    "row",
+    "select",
+    "some",
+    "then",
+    "union",
+    "using",
+    "values",
+    "varchar",
+    "when",
+    "where",
+];
+
+/// Checks that `query` is a single `SELECT` statement and strips a trailing semicolon.
+///
+/// The check is purely lexical and errs on the side of rejecting: forbidden keywords and calls of
+/// functions that are not allowlisted are rejected even inside string literals or quoted
+/// identifiers. It is not the only guard, storage runs the query read-only and with access to the
+/// analytics views only.
+fn validate_analytics_query(query: &str) -> Result<&str, RpcError> {
+    let query = query.trim();
+    let query = query
+        .strip_suffix(';')
+        .unwrap_or(query)
+        .trim_end();
+    if query.contains(';') {
+        return Err(RpcError::Parse("Only a single statement is allowed".to_string()));
+    }
+    if query.contains("--") || query.contains("/*") {
+        return Err(RpcError::Parse("Comments are not allowed".to_string()));
+    }
+
+    let mut keywords = query
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_uppercase);
+    if !matches!(keywords.next().as_deref(), Some("SELECT" | "WITH")) {
+        return Err(RpcError::Parse("Only SELECT statements are allowed".to_string()));
+    }
+    if let Some(keyword) =
+        keywords.find(|word| FORBIDDEN_ANALYTICS_KEYWORDS.contains(&word.as_str()))
+    {
+        return Err(RpcError::Parse(format!("Keyword {keyword} is not allowed")));
+    }
+    for (idx, _) in query.match_indices('(') {
+        let before = query[..idx].trim_end();
+        if before.ends_with('"') {
+            return Err(RpcError::Parse("Quoted function names are not allowed".to_string()));
+        }
+        let name = before
+            .rsplit(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !name.is_empty() &&
+            !ALLOWED_ANALYTICS_FUNCTIONS.contains(&name.as_str()) &&
+            !ANALYTICS_PARENTHESIZED_KEYWORDS.contains(&name.as_str())
+        {
+            return Err(RpcError::Parse(format!("Function {name} is not allowed")));
+        }
+    }
+    Ok(query)
+}
+
 #[derive(Error, Debug)]
 pub enum RpcError {
     #[error("Failed to parse JSON: {0}")]
@@ -50,6 +266,9 @@ pub enum RpcError {
     #[error("Failed to apply pending deltas: {0}")]
     DeltasError(#[from] PendingDeltasError),
 
+    #[error("Upstream node error: {0}")]
+    Upstream(String),
+
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
@@ -63,20 +282,30 @@ impl From<anyhow::Error> for RpcError {
 impl ResponseError for RpcError {
     fn error_response(&self) -> HttpResponse {
         match self {
+            RpcError::Storage(e @ StorageError::Deleted(..)) => {
+                HttpResponse::Gone().body(e.to_string())
+            }
+            RpcError::Storage(e @ StorageError::Timeout(_)) => {
+                HttpResponse::ServiceUnavailable().body(e.to_string())
+            }
             RpcError::Storage(e) => HttpResponse::NotFound().body(e.to_string()),
             RpcError::Parse(e) => HttpResponse::BadRequest().body(e.to_string()),
             RpcError::Connection(e) => HttpResponse::InternalServerError().body(e.to_string()),
             RpcError::DeltasError(e) => HttpResponse::InternalServerError().body(e.to_string()),
+            RpcError::Upstream(e) => HttpResponse::BadGateway().body(e.to_string()),
             RpcError::Unknown(e) => HttpResponse::InternalServerError().body(e.to_string()),
         }
     }
 
     fn status_code(&self) -> StatusCode {
         match self {
+            RpcError::Storage(StorageError::Deleted(..)) => StatusCode::GONE,
+            RpcError::Storage(StorageError::Timeout(_)) => StatusCode::SERVICE_UNAVAILABLE,
             RpcError::Storage(_) => StatusCode::NOT_FOUND,
             RpcError::Parse(_) => StatusCode::BAD_REQUEST,
             RpcError::Connection(_) => StatusCode::INTERNAL_SERVER_ERROR,
             RpcError::DeltasError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            RpcError::Upstream(_) => StatusCode::BAD_GATEWAY,
             RpcError::Unknown(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -96,6 +325,8 @@ pub struct RpcHandler<G, T> {
         RpcCache<dto::ProtocolComponentsRequestBody, dto::ProtocolComponentRequestResponse>,
     traced_entry_point_cache:
         RpcCache<dto::TracedEntryPointRequestBody, dto::TracedEntryPointRequestResponse>,
+    usd_price_cache: RpcCache<Chain, Option<f64>>,
+    proof_fetcher: Option<Arc<dyn StorageProofFetcher>>,
     #[allow(dead_code)]
     tracer: T,
 }
@@ -138,6 +369,8 @@ where
             dto::TracedEntryPointRequestResponse,
         >::new("traced_entry_points", 500, 7 * 60);
 
+        let usd_price_cache = RpcCache::<Chain, Option<f64>>::new("usd_price", 10, 60);
+
         Self {
             db_gateway,
             pending_deltas,
@@ -146,21 +379,31 @@ where
             protocol_state_cache,
             component_cache,
             traced_entry_point_cache,
+            usd_price_cache,
+            proof_fetcher: None,
             tracer,
         }
     }
 
+    /// Sets the node storage proofs are fetched from. If missing, proof requests fail.
+    pub fn with_proof_fetcher(mut self, fetcher: Arc<dyn StorageProofFetcher>) -> Self {
+        self.proof_fetcher = Some(fetcher);
+        self
+    }
+
     #[instrument(skip(self, request))]
     async fn get_contract_state(
         &self,
         request: &dto::StateRequestBody,
     ) -> Result<dto::StateRequestResponse, RpcError> {
         info!(?request, "Getting contract state.");
+        // Relative versions move with the chain head, the same request resolves differently later.
+        let should_cache = request.version.relative.is_none();
         self.contract_storage_cache
             .get(request.clone(), |r| async {
                 self.get_contract_state_inner(r)
                     .await
-                    .map(|res| (res, true))
+                    .map(|res| (res, should_cache))
             })
             .await
     }
@@ -169,10 +412,17 @@ where
         &self,
         request: dto::StateRequestBody,
     ) -> Result<dto::StateRequestResponse, RpcError> {
-        let at = BlockOrTimestamp::try_from(&request.version)?;
         let chain = request.chain.into();
+        let at = self
+            .resolve_version(&request.version, chain)
+            .await?;
         let (db_version, deltas_version) = self
-            .calculate_versions(&at, &request.protocol_system.clone(), chain)
+            .calculate_versions(
+                &at,
+                &request.protocol_system.clone(),
+                chain,
+                request.version.finalized_only,
+            )
             .await?;
 
         let pagination_params: PaginationParams = (&request.pagination).into();
@@ -196,6 +446,23 @@ where
             );
         }
 
+        let code_mode = request.code_mode();
+        let include_code = code_mode == dto::CodeMode::Full;
+        let include_slots = request.includes(dto::AccountField::Slots);
+        let filter = ContractFilter {
+            code_hash: request.code_hash.clone(),
+            min_balance: request.min_balance.clone(),
+            max_balance: request.max_balance.clone(),
+            balance_order: request
+                .order_by_balance
+                .map(|order| match order {
+                    dto::SortOrder::Asc => SortOrder::Ascending,
+                    dto::SortOrder::Desc => SortOrder::Descending,
+                }),
+            has_code: request.has_code,
+            skip_code: !include_code,
+        };
+
         // Get the contract states from the database
         let account_data = self
             .db_gateway
@@ -203,7 +470,8 @@ where
                 &chain,
                 paginated_addrs.as_deref(),
                 Some(&db_version),
-                true,
+                include_slots,
+                Some(&filter),
                 Some(&pagination_params),
             )
             .await
@@ -233,13 +501,152 @@ where
                                                              * addresses are not specified */
         };
 
-        Ok(dto::StateRequestResponse::new(
+        // Pending deltas may carry code and slots, skipped fields are pruned after applying them.
+        let mut response = dto::StateRequestResponse::new(
             accounts
                 .into_iter()
-                .map(dto::ResponseAccount::from)
+                .map(|mut account| {
+                    match code_mode {
+                        dto::CodeMode::Full => {}
+                        dto::CodeMode::HashOnly => account.code = Bytes::default(),
+                        dto::CodeMode::Omit => {
+                            account.code = Bytes::default();
+                            account.code_hash = Bytes::default();
+                        }
+                    }
+                    if !include_slots {
+                        account.slots.clear();
+                    }
+                    dto::ResponseAccount::from(account)
+                })
                 .collect(),
             PaginationResponse::new(pagination_params.page, pagination_params.page_size, total),
-        ))
+        );
+        if request.annotate {
+            let addresses = response
+                .accounts
+                .iter()
+                .map(|account| account.address.clone())
+                .collect::<Vec<_>>();
+            response.slot_annotations = self
+                .db_gateway
+                .get_slot_annotations(&chain, &addresses)
+                .await?
+                .into_iter()
+                .map(dto::SlotAnnotation::from)
+                .collect();
+        }
+        Ok(response)
+    }
+
+    /// Returns the stored storage of a contract at a block, with a proof of the values from the
+    /// upstream node if requested.
+    ///
+    /// Only blocks in the database are served, unconfirmed state can't be proven reliably.
+    #[instrument(skip(self, request))]
+    async fn get_contract_storage_proof(
+        &self,
+        request: &dto::ContractStorageProofRequestBody,
+    ) -> Result<dto::ContractStorageProofResponse, RpcError> {
+        info!(?request, "Getting contract storage proof.");
+        let chain = request.chain.into();
+        let block_id = match &request.version {
+            None => BlockIdentifier::Latest(chain),
+            Some(version) => match self
+                .resolve_version(version, chain)
+                .await?
+            {
+                BlockOrTimestamp::Block(block_id) => block_id,
+                BlockOrTimestamp::Timestamp(_) => {
+                    return Err(RpcError::Parse(
+                        "Storage proofs require a block version, not a timestamp".to_string(),
+                    ))
+                }
+            },
+        };
+        let block = self
+            .db_gateway
+            .get_block(&block_id)
+            .await?;
+        let version = Version(
+            BlockOrTimestamp::Block(BlockIdentifier::Hash(block.hash.clone())),
+            VersionKind::Last,
+        );
+
+        let mut contract = self
+            .db_gateway
+            .get_contracts(
+                &chain,
+                Some(std::slice::from_ref(&request.contract_id)),
+                Some(&version),
+                true,
+                None,
+                None,
+            )
+            .await?
+            .entity
+            .pop()
+            .ok_or_else(|| {
+                StorageError::NotFound("Account".to_string(), request.contract_id.to_string())
+            })?;
+        let keys = match &request.slots {
+            Some(slots) => slots.clone(),
+            None => contract.slots.keys().cloned().collect(),
+        };
+        let slots = keys
+            .iter()
+            .filter_map(|key| {
+                contract
+                    .slots
+                    .remove(key)
+                    .map(|value| (key.clone(), value))
+            })
+            .collect();
+
+        let proof = if request.include_proof {
+            if keys.len() > MAX_PROOF_SLOTS {
+                return Err(RpcError::Parse(format!(
+                    "Proofs cover at most {MAX_PROOF_SLOTS} slots, select the slots to prove"
+                )));
+            }
+            let fetcher = self
+                .proof_fetcher
+                .as_ref()
+                .ok_or_else(|| RpcError::Upstream("No node configured for proofs".to_string()))?;
+            let proof = fetcher
+                .get_proof(&contract.address, &keys, &block.hash)
+                .await
+                .map_err(RpcError::Upstream)?;
+            Some(proof.into())
+        } else {
+            None
+        };
+
+        Ok(dto::ContractStorageProofResponse {
+            address: contract.address,
+            block: block.into(),
+            slots,
+            proof,
+        })
+    }
+
+    /// Converts the requested version, relative versions are resolved against the latest stored
+    /// block of `chain`.
+    async fn resolve_version(
+        &self,
+        version: &dto::VersionParam,
+        chain: Chain,
+    ) -> Result<BlockOrTimestamp, RpcError> {
+        let Some(relative) = &version.relative else {
+            return Ok(BlockOrTimestamp::try_from(version)?);
+        };
+        let head = self
+            .db_gateway
+            .get_block(&BlockIdentifier::Latest(chain))
+            .await?;
+        let at = BlockOrTimestamp::from_relative(relative, &head)?;
+        debug!(?relative, ?at, "Resolved relative version");
+        Ok(at)
     }
 
     /// Calculates versions for state retrieval.
@@ -259,6 +666,7 @@ where
         request_version: &BlockOrTimestamp,
         protocol_system: &str,
         chain: Chain,
+        finalized_only: bool,
     ) -> Result<(Version, Option<BlockNumberOrTimestamp>), RpcError> {
         let ordered_version = match request_version {
             BlockOrTimestamp::Block(BlockIdentifier::Number((_, no))) => {
@@ -303,6 +711,11 @@ where
                     .number,
             ),
         };
+        if finalized_only {
+            return self
+                .calculate_finalized_version(request_version, ordered_version, chain)
+                .await;
+        }
         let request_version_finality =
             self.pending_deltas
                 .as_ref()
@@ -360,17 +773,50 @@ where
         }
     }
 
+    /// Calculates the db version of a request that must only see finalized blocks.
+    ///
+    /// Pending deltas are never applied. Timestamps past the latest finalized block resolve to
+    /// that block, while blocks past it are reported as not found.
+    async fn calculate_finalized_version(
+        &self,
+        request_version: &BlockOrTimestamp,
+        ordered_version: BlockNumberOrTimestamp,
+        chain: Chain,
+    ) -> Result<(Version, Option<BlockNumberOrTimestamp>), RpcError> {
+        let finalized = self
+            .db_gateway
+            .get_finalized_block(&chain)
+            .await?;
+        match ordered_version {
+            BlockNumberOrTimestamp::Number(number) if number > finalized.number => {
+                Err(RpcError::Storage(StorageError::NotFound(
+                    "Finalized version".to_string(),
+                    format!("{request_version:?}"),
+                )))
+            }
+            BlockNumberOrTimestamp::Timestamp(ts) if ts > finalized.ts => Ok((
+                Version(
+                    BlockOrTimestamp::Block(BlockIdentifier::Hash(finalized.hash)),
+                    VersionKind::Last,
+                ),
+                None,
+            )),
+            _ => Ok((Version(request_version.clone(), VersionKind::Last), None)),
+        }
+    }
+
     #[instrument(skip(self, request))]
     async fn get_protocol_state(
         &self,
         request: &dto::ProtocolStateRequestBody,
     ) -> Result<dto::ProtocolStateRequestResponse, RpcError> {
         debug!(?request, "Getting protocol state.");
+        let should_cache = request.version.relative.is_none();
         self.protocol_state_cache
             .get(request.clone(), |r| async {
                 self.get_protocol_state_inner(r)
                     .await
-                    .map(|res| (res, true))
+                    .map(|res| (res, should_cache))
             })
             .await
     }
@@ -379,10 +825,17 @@ where
         &self,
         request: dto::ProtocolStateRequestBody,
     ) -> Result<dto::ProtocolStateRequestResponse, RpcError> {
-        let at = BlockOrTimestamp::try_from(&request.version)?;
         let chain = request.chain.into();
+        let at = self
+            .resolve_version(&request.version, chain)
+            .await?;
         let (db_version, deltas_version) = self
-            .calculate_versions(&at, &request.protocol_system.clone(), chain)
+            .calculate_versions(
+                &at,
+                &request.protocol_system.clone(),
+                chain,
+                request.version.finalized_only,
+            )
             .await?;
 
         let pagination_params: PaginationParams = (&request.pagination).into();
@@ -414,7 +867,12 @@ where
                     protocol_system: request.protocol_system.clone(),
                     component_ids: None,
                     tvl_gt: None,
+                    min_tvl_usd: None,
                     pagination: request.pagination.clone(),
+                    fields: None,
+                    extractor: None,
+                    tokens: None,
+                    cursor: None,
                 };
                 let protocol_components = self
                     .get_protocol_components_inner(req)
@@ -444,7 +902,7 @@ where
             .db_gateway
             .get_protocol_states(
                 &chain,
-                Some(db_version),
+                Some(db_version.clone()),
                 Some(request.protocol_system.clone()),
                 Some(paginated_ids.as_slice()),
                 request.include_balances,
@@ -473,15 +931,58 @@ where
 
         trace!(db_state = ?states, "Updated states with buffer.");
 
+        let mut states = states
+            .into_iter()
+            .map(dto::ResponseProtocolState::from)
+            .collect::<Vec<_>>();
+        if request.include_balances {
+            self.add_balance_shares(&chain, &db_version, &mut states)
+                .await?;
+        }
+
         Ok(dto::ProtocolStateRequestResponse::new(
-            states
-                .into_iter()
-                .map(dto::ResponseProtocolState::from)
-                .collect(),
+            states,
             PaginationResponse::new(pagination_params.page, pagination_params.page_size, total),
         ))
     }
 
+    /// Sets the fraction of each token's total supply held by the components, using the latest
+    /// supply snapshots at `version`.
+    async fn add_balance_shares(
+        &self,
+        chain: &Chain,
+        version: &Version,
+        states: &mut [dto::ResponseProtocolState],
+    ) -> Result<(), RpcError> {
+        let tokens = states
+            .iter()
+            .flat_map(|state| state.balances.keys().cloned())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        if tokens.is_empty() {
+            return Ok(());
+        }
+        let supplies = self
+            .db_gateway
+            .get_token_supplies(chain, &tokens, Some(version))
+            .await?;
+        for state in states.iter_mut() {
+            state.balance_shares = state
+                .balances
+                .iter()
+                .filter_map(|(token, balance)| {
+                    let supply = bytes_to_f64(supplies.get(token)?)?;
+                    if supply <= 0.0 {
+                        return None;
+                    }
+                    Some((token.clone(), bytes_to_f64(balance)? / supply))
+                })
+                .collect();
+        }
+        Ok(())
+    }
+
     #[instrument(skip(self, request))]
     async fn get_protocol_systems(
         &self,
@@ -556,6 +1057,341 @@ where
         }
     }
 
+    #[instrument(skip(self, request))]
+    async fn get_balance_history(
+        &self,
+        request: &dto::BalanceHistoryRequestBody,
+    ) -> Result<dto::BalanceHistoryRequestResponse, RpcError> {
+        info!(?request, "Getting component balance history.");
+        let interval: SampleInterval = request.granularity.into();
+        if request.from > request.to {
+            return Err(RpcError::Parse("`from` must not be after `to`".to_string()));
+        }
+        let n_samples =
+            (request.to - request.from).num_seconds() / interval.duration().num_seconds() + 1;
+        if n_samples > MAX_BALANCE_HISTORY_SAMPLES {
+            return Err(RpcError::Parse(format!(
+                "Requested {n_samples} samples, at most {MAX_BALANCE_HISTORY_SAMPLES} are allowed"
+            )));
+        }
+
+        match self
+            .db_gateway
+            .get_balance_history(
+                &request.chain.into(),
+                &request.component_id,
+                &request.token,
+                request.from,
+                request.to,
+                interval,
+            )
+            .await
+        {
+            Ok(samples) => Ok(dto::BalanceHistoryRequestResponse::new(
+                samples
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            )),
+            Err(err) => {
+                error!(error = %err, "Error while getting balance history.");
+                Err(err.into())
+            }
+        }
+    }
+
+    #[instrument(skip(self, request))]
+    async fn get_block_digests(
+        &self,
+        request: &dto::BlockDigestsRequestBody,
+    ) -> Result<dto::BlockDigestsRequestResponse, RpcError> {
+        info!(?request, "Getting block digests.");
+        if request.start_block > request.end_block {
+            return Err(RpcError::Parse("`start_block` must not be after `end_block`".to_string()));
+        }
+        let n_blocks = request.end_block - request.start_block + 1;
+        if n_blocks > MAX_BLOCK_DIGESTS {
+            return Err(RpcError::Parse(format!(
+                "Requested {n_blocks} blocks, at most {MAX_BLOCK_DIGESTS} are allowed"
+            )));
+        }
+
+        match self
+            .db_gateway
+            .get_block_digests(
+                &request.chain.into(),
+                request.start_block as i64,
+                request.end_block as i64,
+            )
+            .await
+        {
+            Ok(digests) => Ok(dto::BlockDigestsRequestResponse::new(
+                digests
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            )),
+            Err(err) => {
+                error!(error = %err, "Error while getting block digests.");
+                Err(err.into())
+            }
+        }
+    }
+
+    async fn get_tracked_addresses(
+        &self,
+        request: &dto::TrackedAddressesRequestBody,
+    ) -> Result<dto::TrackedAddressesRequestResponse, RpcError> {
+        info!(n_addresses = request.addresses.len(), "Getting tracked addresses.");
+        if request.addresses.len() > MAX_TRACKED_ADDRESSES {
+            return Err(RpcError::Parse(format!(
+                "Requested {} addresses, at most {MAX_TRACKED_ADDRESSES} are allowed",
+                request.addresses.len()
+            )));
+        }
+
+        let mut tracked = self
+            .db_gateway
+            .get_tracked_addresses(&request.chain.into(), &request.addresses)
+            .await?
+            .into_iter()
+            .collect::<Vec<_>>();
+        tracked.sort_unstable();
+        Ok(dto::TrackedAddressesRequestResponse::new(tracked))
+    }
+
+    async fn get_contract_code(
+        &self,
+        chain: dto::Chain,
+        code_hash: &Bytes,
+    ) -> Result<Bytes, RpcError> {
+        Ok(self
+            .db_gateway
+            .get_code_by_hash(&chain.into(), code_hash)
+            .await?)
+    }
+
+    async fn get_chain_head(&self, chain: dto::Chain) -> Result<dto::ChainHeadResponse, RpcError> {
+        let model_chain = chain.into();
+        let block = self
+            .db_gateway
+            .get_block(&BlockIdentifier::Latest(model_chain))
+            .await?;
+        let extractor_block_numbers = self
+            .db_gateway
+            .get_extractor_block_numbers(&model_chain)
+            .await?;
+        // Clamped, clocks of the node and this host may be slightly apart.
+        let lag_seconds = (Utc::now().naive_utc() - block.ts)
+            .num_seconds()
+            .max(0) as u64;
+        Ok(dto::ChainHeadResponse {
+            chain,
+            block_number: block.number,
+            block_hash: block.hash,
+            block_ts: block.ts,
+            extractor_block_numbers,
+            lag_seconds,
+        })
+    }
+
+    async fn get_protocol_system_summaries(
+        &self,
+        chain: dto::Chain,
+    ) -> Result<dto::ProtocolSystemSummariesResponse, RpcError> {
+        let summaries = self
+            .db_gateway
+            .get_protocol_system_summaries(&chain.into())
+            .await?;
+        Ok(dto::ProtocolSystemSummariesResponse {
+            chain,
+            protocol_systems: summaries
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+
+    async fn get_multi_chain_snapshot(
+        &self,
+        request: &dto::MultiChainSnapshotRequestBody,
+    ) -> Result<dto::MultiChainSnapshotResponse, RpcError> {
+        let n_queries = request.protocol_states.len() + request.contract_states.len();
+        info!(timestamp = %request.timestamp, n_queries, "Getting multi chain snapshot.");
+        if n_queries > MAX_SNAPSHOT_QUERIES {
+            return Err(RpcError::Parse(format!(
+                "At most {MAX_SNAPSHOT_QUERIES} state queries are allowed per snapshot"
+            )));
+        }
+        if request
+            .protocol_states
+            .iter()
+            .map(|r| r.pagination.page_size)
+            .chain(
+                request
+                    .contract_states
+                    .iter()
+                    .map(|r| r.pagination.page_size),
+            )
+            .any(|page_size| page_size > 100)
+        {
+            return Err(RpcError::Parse("Page size must be less than or equal to 100.".to_string()));
+        }
+
+        let chains = request
+            .protocol_states
+            .iter()
+            .map(|r| r.chain)
+            .chain(
+                request
+                    .contract_states
+                    .iter()
+                    .map(|r| r.chain),
+            )
+            .map(Chain::from)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let blocks = self
+            .db_gateway
+            .get_blocks_at(&chains, request.timestamp)
+            .await?
+            .into_values()
+            .map(|block| (block.chain, dto::Block::from(block)))
+            .collect::<HashMap<_, _>>();
+        let version_at = |chain: dto::Chain| {
+            blocks
+                .get(&Chain::from(chain))
+                .map(|block| dto::VersionParam::new(None, Some(dto::BlockParam::from(block))))
+                .ok_or_else(|| {
+                    RpcError::Storage(StorageError::NotFound(
+                        "Block".to_string(),
+                        format!("{chain} at or before {}", request.timestamp),
+                    ))
+                })
+        };
+
+        let protocol_states = try_join_all(
+            request
+                .protocol_states
+                .iter()
+                .map(|r| async {
+                    let mut r = r.clone();
+                    r.version = version_at(r.chain)?;
+                    self.get_protocol_state(&r).await
+                }),
+        )
+        .await?;
+        let contract_states = try_join_all(
+            request
+                .contract_states
+                .iter()
+                .map(|r| async {
+                    let mut r = r.clone();
+                    r.version = version_at(r.chain)?;
+                    self.get_contract_state(&r).await
+                }),
+        )
+        .await?;
+
+        let mut blocks = blocks.into_values().collect::<Vec<_>>();
+        blocks.sort_by_key(|block| block.chain.to_string());
+        Ok(dto::MultiChainSnapshotResponse {
+            timestamp: request.timestamp,
+            blocks,
+            protocol_states,
+            contract_states,
+        })
+    }
+
+    async fn run_analytics_query(
+        &self,
+        request: &dto::AnalyticsQueryRequestBody,
+    ) -> Result<dto::AnalyticsQueryResponse, RpcError> {
+        let query = validate_analytics_query(&request.query)?;
+        let row_limit = request
+            .row_limit
+            .unwrap_or(MAX_ANALYTICS_ROWS)
+            .min(MAX_ANALYTICS_ROWS);
+        info!(query, row_limit, "Running analytics query.");
+
+        let res = self
+            .db_gateway
+            .run_analytics_query(query, row_limit, ANALYTICS_QUERY_TIMEOUT)
+            .await
+            .map_err(|err| match err {
+                // Syntax errors and denied reads are caused by the query itself.
+                StorageError::InvalidQuery(msg) => RpcError::Parse(msg),
+                err @ StorageError::Timeout(_) => err.into(),
+                err => RpcError::Unknown(err.to_string()),
+            })?;
+        Ok(dto::AnalyticsQueryResponse::new(res.rows, res.truncated))
+    }
+
+    async fn get_audit_log(
+        &self,
+        request: &dto::AuditLogRequestBody,
+    ) -> Result<dto::AuditLogRequestResponse, RpcError> {
+        info!(?request, "Getting audit log.");
+        let pagination_params: PaginationParams = (&request.pagination).into();
+        let res = self
+            .db_gateway
+            .get_audit_log(request.operation.as_deref(), request.since, Some(&pagination_params))
+            .await?;
+        let entries = res
+            .entity
+            .into_iter()
+            .map(|entry| dto::AuditLogEntry {
+                operation: entry.operation,
+                actor: entry.actor,
+                chain: entry.chain.map(dto::Chain::from),
+                target: entry.target,
+                rows_affected: entry.rows_affected,
+                block_range: entry.block_range,
+                timestamp: entry.ts,
+            })
+            .collect();
+        Ok(dto::AuditLogRequestResponse {
+            entries,
+            pagination: PaginationResponse::new(
+                request.pagination.page,
+                request.pagination.page_size,
+                res.total.unwrap_or_default(),
+            ),
+        })
+    }
+
+    async fn get_deployment_parity(
+        &self,
+        request: &dto::DeploymentParityRequestBody,
+    ) -> Result<dto::DeploymentParityResponse, RpcError> {
+        info!(?request, "Comparing protocol deployments.");
+        if request.base_chain == request.other_chain {
+            return Err(RpcError::Parse("`base_chain` and `other_chain` must differ".to_string()));
+        }
+        let base = self
+            .db_gateway
+            .get_protocol_deployment(&request.base_chain.into(), &request.protocol_system)
+            .await?;
+        let other = self
+            .db_gateway
+            .get_protocol_deployment(&request.other_chain.into(), &request.protocol_system)
+            .await?;
+        Ok(DeploymentParity::compare(&request.protocol_system, &base, &other).into())
+    }
+
+    async fn get_extractor_status(
+        &self,
+        request: &dto::ExtractorStatusRequestBody,
+    ) -> Result<dto::ExtractorStatus, RpcError> {
+        info!(?request, "Getting extractor status.");
+        let state = self
+            .db_gateway
+            .get_state(&request.extractor, &request.chain.into())
+            .await?;
+        Ok(state.into())
+    }
+
     #[instrument(skip(self, request))]
     async fn get_tokens(
         &self,
@@ -617,18 +1453,23 @@ where
             )
             .await
         {
-            Ok(token_data) => Ok(dto::TokensRequestResponse::new(
-                token_data
+            Ok(token_data) => {
+                let mut tokens = token_data
                     .entity
                     .into_iter()
                     .map(dto::ResponseToken::from)
-                    .collect(),
-                &PaginationResponse::new(
-                    request.pagination.page,
-                    request.pagination.page_size,
-                    token_data.total.unwrap_or_default(),
-                ),
-            )),
+                    .collect::<Vec<_>>();
+                self.apply_token_overrides(request.chain.into(), &mut tokens)
+                    .await?;
+                Ok(dto::TokensRequestResponse::new(
+                    tokens,
+                    &PaginationResponse::new(
+                        request.pagination.page,
+                        request.pagination.page_size,
+                        token_data.total.unwrap_or_default(),
+                    ),
+                ))
+            }
             Err(err) => {
                 error!(error = %err, "Error while getting tokens.");
                 Err(err.into())
@@ -636,21 +1477,123 @@ where
         }
     }
 
-    #[instrument(skip(self, request))]
-    async fn get_protocol_components(
+    /// Merges the stored metadata overrides over `tokens`.
+    async fn apply_token_overrides(
         &self,
-        request: &dto::ProtocolComponentsRequestBody,
-    ) -> Result<dto::ProtocolComponentRequestResponse, RpcError> {
-        info!(?request, "Getting protocol components.");
-        self.component_cache
-            .get(request.clone(), |r| async {
-                self.get_protocol_components_inner(r)
-                    .await
-                    .map(|res| {
-                        // If component ids were specified, check if we have all requested
-                        // components are in the response (should cache)
-                        if let Some(component_ids) = &request.component_ids {
-                            let all_found = component_ids.len() == res.pagination.total as usize;
+        chain: Chain,
+        tokens: &mut [dto::ResponseToken],
+    ) -> Result<(), RpcError> {
+        if tokens.is_empty() {
+            return Ok(());
+        }
+        let addresses = tokens
+            .iter()
+            .map(|token| &token.address)
+            .collect::<Vec<_>>();
+        let overrides = self
+            .db_gateway
+            .get_token_overrides(&chain, Some(&addresses))
+            .await?
+            .into_iter()
+            .map(|o| (o.address.clone(), dto::TokenOverride::from(o)))
+            .collect::<HashMap<_, _>>();
+        for token in tokens.iter_mut() {
+            if let Some(token_override) = overrides.get(&token.address) {
+                token.apply_override(token_override);
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_token_overrides(
+        &self,
+        request: &dto::TokenOverridesRequestBody,
+    ) -> Result<dto::TokenOverridesRequestResponse, RpcError> {
+        info!(
+            chain = %request.chain,
+            n_upserts = request.upsert.len(),
+            n_deletes = request.delete.len(),
+            "Updating token overrides."
+        );
+        for token_override in &request.upsert {
+            if token_override.symbol.is_none() &&
+                token_override
+                    .display_decimals
+                    .is_none() &&
+                token_override.logo_uri.is_none()
+            {
+                return Err(RpcError::Parse(format!(
+                    "Override of {} sets no field, delete it instead",
+                    token_override.address
+                )));
+            }
+            if token_override
+                .symbol
+                .as_deref()
+                .is_some_and(str::is_empty)
+            {
+                return Err(RpcError::Parse(format!(
+                    "Override of {} sets an empty symbol",
+                    token_override.address
+                )));
+            }
+        }
+
+        let chain = Chain::from(request.chain);
+        if !request.upsert.is_empty() {
+            let overrides = request
+                .upsert
+                .iter()
+                .cloned()
+                .map(|o| o.into_model(request.chain))
+                .collect::<Vec<_>>();
+            self.db_gateway
+                .upsert_token_overrides(&overrides)
+                .await?;
+        }
+        if !request.delete.is_empty() {
+            let addresses = request
+                .delete
+                .iter()
+                .collect::<Vec<_>>();
+            self.db_gateway
+                .delete_token_overrides(&chain, &addresses)
+                .await?;
+        }
+        if !request.upsert.is_empty() || !request.delete.is_empty() {
+            // Cached token responses carry the previous overrides
+            self.token_cache.invalidate_all();
+        }
+
+        let overrides = self
+            .db_gateway
+            .get_token_overrides(&chain, None)
+            .await?
+            .into_iter()
+            .map(dto::TokenOverride::from)
+            .collect();
+        Ok(dto::TokenOverridesRequestResponse { overrides })
+    }
+
+    #[instrument(skip(self, request))]
+    async fn get_protocol_components(
+        &self,
+        request: &dto::ProtocolComponentsRequestBody,
+    ) -> Result<dto::ProtocolComponentRequestResponse, RpcError> {
+        info!(?request, "Getting protocol components.");
+        self.component_cache
+            .get(request.clone(), |r| async {
+                self.get_protocol_components_inner(r)
+                    .await
+                    .map(|res| {
+                        // Pages after the first one are pinned by their cursor (should cache)
+                        if let Some(cursor) = &request.cursor {
+                            return (res, !cursor.is_empty());
+                        }
+                        // If component ids were specified, check if we have all requested
+                        // components are in the response (should cache)
+                        if let Some(component_ids) = &request.component_ids {
+                            let all_found = component_ids.len() == res.pagination.total as usize;
                             if all_found {
                                 return (res, true);
                             } else {
@@ -679,11 +1622,116 @@ where
 
         let ids_slice = ids_strs.as_deref();
 
+        let chain = request.chain.into();
+        // Components created by other extractors are excluded by narrowing the requested ids
+        let extractor_ids = match request.extractor.as_deref() {
+            Some(extractor) => Some(
+                self.db_gateway
+                    .get_component_extractors(&chain, ids_slice, Some(extractor))
+                    .await?
+                    .into_keys()
+                    .collect::<Vec<_>>(),
+            ),
+            None => None,
+        };
+        let extractor_id_strs: Option<Vec<&str>> = extractor_ids
+            .as_ref()
+            .map(|ids| ids.iter().map(String::as_str).collect());
+        let ids_slice = extractor_id_strs
+            .as_deref()
+            .or(ids_slice);
+        // Components holding other tokens are excluded the same way, matched by their pair key
+        let token_ids = match request.tokens.as_deref() {
+            Some(tokens) => Some(
+                self.db_gateway
+                    .get_component_ids_by_tokens(&chain, tokens, ids_slice)
+                    .await?,
+            ),
+            None => None,
+        };
+        let token_id_strs: Option<Vec<&str>> = token_ids
+            .as_ref()
+            .map(|ids| ids.iter().map(String::as_str).collect());
+        let ids_slice = token_id_strs.as_deref().or(ids_slice);
+
+        let usd_price = self.get_usd_price(chain).await?;
+        let min_tvl = match request.min_tvl_usd {
+            Some(min_tvl_usd) => {
+                let usd_price = usd_price.ok_or_else(|| {
+                    RpcError::Unknown(format!("No USD price available on {chain}"))
+                })?;
+                let min_tvl = min_tvl_usd * usd_price;
+                Some(
+                    request
+                        .tvl_gt
+                        .map_or(min_tvl, |tvl_gt| tvl_gt.max(min_tvl)),
+                )
+            }
+            None => request.tvl_gt,
+        };
+
+        let include_static_attributes = request.includes(dto::ComponentField::StaticAttributes);
+        let to_response = |c: ProtocolComponent| {
+            let mut pc = dto::ProtocolComponent::from(c);
+            pc.tokens.sort_unstable();
+            if !include_static_attributes {
+                pc.static_attributes.clear();
+            }
+            pc
+        };
+
+        if let Some(cursor) = request.cursor.as_deref() {
+            let cursor = self
+                .resolve_component_cursor(cursor, chain)
+                .await?;
+            let page_size = pagination_params.page_size;
+            // Fetching one more component than requested tells whether another page follows
+            let mut components = self
+                .db_gateway
+                .get_protocol_components_after(
+                    &chain,
+                    Some(system),
+                    ids_slice,
+                    min_tvl,
+                    &cursor,
+                    page_size + 1,
+                )
+                .await?;
+            let next_cursor =
+                (page_size > 0 && components.entity.len() as i64 > page_size).then(|| {
+                    components
+                        .entity
+                        .truncate(page_size as usize);
+                    let last_id = components
+                        .entity
+                        .last()
+                        .map(|c| c.id.clone());
+                    ComponentCursor::new(cursor.block_number, last_id).to_string()
+                });
+
+            let mut response_components = components
+                .entity
+                .into_iter()
+                .map(to_response)
+                .collect::<Vec<_>>();
+            self.add_component_details(&chain, usd_price, &mut response_components)
+                .await?;
+            return Ok(dto::ProtocolComponentRequestResponse {
+                protocol_components: response_components,
+                pagination: PaginationResponse::new(
+                    pagination_params.page,
+                    page_size,
+                    components.total.unwrap_or_default(),
+                ),
+                next_cursor,
+            });
+        }
+
         let buffered_components = self
             .pending_deltas
             .as_ref()
             .map_or(Ok(Vec::new()), |pending_delta| {
-                pending_delta.get_new_components(ids_slice, &system, request.tvl_gt)
+                pending_delta.get_new_components(ids_slice, &system, min_tvl)
             })?;
 
         debug!(n_components = buffered_components.len(), "RetrievedBufferedComponents");
@@ -705,11 +1753,7 @@ where
                             .min(total as usize),
                     )
                     .take(pagination_params.page_size as usize)
-                    .map(|c| {
-                        let mut pc = dto::ProtocolComponent::from(c);
-                        pc.tokens.sort_unstable();
-                        pc
-                    })
+                    .map(to_response)
                     .collect();
 
                 return Ok(dto::ProtocolComponentRequestResponse::new(
@@ -726,10 +1770,10 @@ where
         match self
             .db_gateway
             .get_protocol_components(
-                &request.chain.into(),
+                &chain,
                 Some(system),
                 ids_slice,
-                request.tvl_gt,
+                min_tvl,
                 Some(&pagination_params),
             )
             .await
@@ -762,14 +1806,12 @@ where
                     }
                 }
 
-                let response_components = components
+                let mut response_components = components
                     .into_iter()
-                    .map(|c| {
-                        let mut pc = dto::ProtocolComponent::from(c);
-                        pc.tokens.sort_unstable();
-                        pc
-                    })
+                    .map(to_response)
                     .collect::<Vec<dto::ProtocolComponent>>();
+                self.add_component_details(&chain, usd_price, &mut response_components)
+                    .await?;
                 Ok(dto::ProtocolComponentRequestResponse::new(
                     response_components,
                     PaginationResponse::new(
@@ -786,6 +1828,135 @@ where
         }
     }
 
+    /// Resolves a request cursor, an empty cursor pins a new listing to the latest stored block.
+    async fn resolve_component_cursor(
+        &self,
+        cursor: &str,
+        chain: Chain,
+    ) -> Result<ComponentCursor, RpcError> {
+        if !cursor.is_empty() {
+            return cursor
+                .parse::<ComponentCursor>()
+                .map_err(|e| RpcError::Parse(e.to_string()));
+        }
+        let head = self
+            .db_gateway
+            .get_block(&BlockIdentifier::Latest(chain))
+            .await?;
+        Ok(ComponentCursor::new(head.number, None))
+    }
+
+    /// Adds the inactivity flag, extractor and USD TVL to the components of a response.
+    async fn add_component_details(
+        &self,
+        chain: &Chain,
+        usd_price: Option<f64>,
+        components: &mut [dto::ProtocolComponent],
+    ) -> Result<(), RpcError> {
+        self.flag_inactive_components(chain, components)
+            .await?;
+        self.add_extractors(chain, components)
+            .await?;
+        if let Some(usd_price) = usd_price {
+            self.add_tvl_usd(chain, usd_price, components)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Sets `inactive_since` on the components flagged inactive in the database.
+    async fn flag_inactive_components(
+        &self,
+        chain: &Chain,
+        components: &mut [dto::ProtocolComponent],
+    ) -> Result<(), RpcError> {
+        if components.is_empty() {
+            return Ok(());
+        }
+        let ids = components
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<_>>();
+        let mut inactive = self
+            .db_gateway
+            .get_inactive_components(chain, &ids)
+            .await?;
+        for component in components.iter_mut() {
+            component.inactive_since = inactive.remove(&component.id);
+        }
+        Ok(())
+    }
+
+    /// Sets `extractor` to the extractor that created each stored component.
+    async fn add_extractors(
+        &self,
+        chain: &Chain,
+        components: &mut [dto::ProtocolComponent],
+    ) -> Result<(), RpcError> {
+        if components.is_empty() {
+            return Ok(());
+        }
+        let ids = components
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<_>>();
+        let mut extractors = self
+            .db_gateway
+            .get_component_extractors(chain, Some(&ids), None)
+            .await?;
+        for component in components.iter_mut() {
+            component.extractor = extractors.remove(&component.id);
+        }
+        Ok(())
+    }
+
+    /// Sets `tvl_usd` from the stored component TVLs, which are denoted in the native token.
+    async fn add_tvl_usd(
+        &self,
+        chain: &Chain,
+        usd_price: f64,
+        components: &mut [dto::ProtocolComponent],
+    ) -> Result<(), RpcError> {
+        if components.is_empty() {
+            return Ok(());
+        }
+        let ids = components
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<_>>();
+        let tvls = self
+            .db_gateway
+            .get_component_tvls(chain, None, Some(&ids), None)
+            .await?
+            .entity;
+        for component in components.iter_mut() {
+            component.tvl_usd = tvls
+                .get(&component.id)
+                .map(|tvl| tvl / usd_price);
+        }
+        Ok(())
+    }
+
+    /// Price of one USD in the chain's native token.
+    ///
+    /// Derived from the indexed price of the chain's USD reference token, `None` if that token is
+    /// not priced. Missing prices are not cached so they are picked up as soon as available.
+    async fn get_usd_price(&self, chain: Chain) -> Result<Option<f64>, RpcError> {
+        self.usd_price_cache
+            .get(chain, |chain| async move {
+                let prices = self
+                    .db_gateway
+                    .get_token_prices(&chain)
+                    .await?;
+                let price = prices
+                    .get(&chain.usd_reference_token())
+                    .copied()
+                    .filter(|price| *price > 0.0);
+                Ok::<_, RpcError>((price, price.is_some()))
+            })
+            .await
+    }
+
     #[instrument(skip(self, request))]
     async fn get_traced_entry_points(
         &self,
@@ -1008,6 +2179,84 @@ where
     }
 }
 
+#[async_trait]
+impl<G, T> SnapshotSource for RpcHandler<G, T>
+where
+    G: Gateway + Send + Sync,
+    T: EntryPointTracer + Send + Sync,
+{
+    async fn get_component_snapshots(
+        &self,
+        extractor_id: &ExtractorIdentity,
+        component_ids: Vec<ComponentId>,
+    ) -> Result<Vec<dto::ResponseProtocolState>, RpcError> {
+        // Bypasses the response cache, snapshots must reflect the latest block.
+        let page_size = component_ids.len() as i64;
+        let request = dto::ProtocolStateRequestBody {
+            protocol_ids: Some(component_ids),
+            protocol_system: extractor_id.name.clone(),
+            chain: extractor_id.chain.into(),
+            include_balances: true,
+            version: dto::VersionParam::default(),
+            pagination: dto::PaginationParams::new(0, page_size),
+        };
+        Ok(self
+            .get_protocol_state_inner(request)
+            .await?
+            .states)
+    }
+}
+
+/// Serializes a response with values in the requested encoding, rendering the `address` of every
+/// entry in `list_key` in the requested display format.
+fn json_with_display_params<R: Serialize>(
+    response: &R,
+    list_key: &str,
+    params: &dto::DisplayParams,
+) -> HttpResponse {
+    // The encoding is only in effect while serializing on this thread.
+    with_value_encoding(params.encoding, || {
+        json_with_display_format(response, list_key, params.display_format)
+    })
+}
+
+fn json_with_display_format<R: Serialize>(
+    response: &R,
+    list_key: &str,
+    format: dto::DisplayFormat,
+) -> HttpResponse {
+    if format == dto::DisplayFormat::Raw {
+        return HttpResponse::Ok().json(response);
+    }
+    let mut value = match serde_json::to_value(response) {
+        Ok(value) => value,
+        Err(err) => {
+            error!(error = %err, "Failed to serialize response.");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if let Some(entries) = value
+        .get_mut(list_key)
+        .and_then(serde_json::Value::as_array_mut)
+    {
+        for entry in entries {
+            let chain = entry
+                .get("chain")
+                .and_then(|c| serde_json::from_value::<dto::Chain>(c.clone()).ok());
+            let address = entry
+                .get("address")
+                .and_then(serde_json::Value::as_str)
+                .and_then(|a| Bytes::from_str(a).ok());
+            if let (Some(chain), Some(address)) = (chain, address) {
+                entry["address"] = Chain::from(chain)
+                    .format_address(&address)
+                    .into();
+            }
+        }
+    }
+    HttpResponse::Ok().json(value)
+}
+
 /// Retrieve contract states
 ///
 /// This endpoint retrieves the state of contracts within a specific execution environment. If no
@@ -1024,12 +2273,14 @@ where
         (status = 200, description = "OK", body = StateRequestResponse),
     ),
     request_body = StateRequestBody,
+    params(dto::DisplayParams),
     security(
          ("apiKey" = [])
     ),
 )]
 pub async fn contract_state<G: Gateway, T: EntryPointTracer>(
     body: web::Json<dto::StateRequestBody>,
+    display: web::Query<dto::DisplayParams>,
     handler: web::Data<RpcHandler<G, T>>,
 ) -> HttpResponse {
     // Note - filtering by protocol system is not supported on this endpoint. This is due to the
@@ -1054,7 +2305,10 @@ pub async fn contract_state<G: Gateway, T: EntryPointTracer>(
         .await;
 
     match response {
-        Ok(state) => HttpResponse::Ok().json(state),
+        Ok(state) => with_rows_returned(
+            json_with_display_params(&state, "accounts", &display),
+            state.accounts.len(),
+        ),
         Err(err) => {
             error!(error = %err, ?body, "Error while getting contract state.");
             let status = err.status_code().as_u16().to_string();
@@ -1065,6 +2319,49 @@ pub async fn contract_state<G: Gateway, T: EntryPointTracer>(
     }
 }
 
+/// Retrieve contract storage with proof
+///
+/// This endpoint returns the stored storage slots of a contract at a block, optionally together
+/// with the `eth_getProof` result of the upstream node for the same block. Clients can verify the
+/// returned values against the block's state root with the proof. Only blocks stored by Tycho are
+/// served, unconfirmed blocks are not.
+#[utoipa::path(
+    post,
+    path = "/v1/contract_storage_proof",
+    responses(
+        (status = 200, description = "OK", body = ContractStorageProofResponse),
+    ),
+    request_body = ContractStorageProofRequestBody,
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn contract_storage_proof<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::ContractStorageProofRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "contract_storage_proof").increment(1);
+
+    let response = handler
+        .into_inner()
+        .get_contract_storage_proof(&body)
+        .await;
+
+    match response {
+        Ok(storage) => {
+            let rows = storage.slots.len();
+            with_rows_returned(HttpResponse::Ok().json(storage), rows)
+        }
+        Err(err) => {
+            error!(error = %err, ?body, "Error while getting contract storage proof.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "contract_storage_proof", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
 /// Retrieve tokens
 ///
 /// This endpoint retrieves tokens for a specific execution environment, filtered by various
@@ -1076,12 +2373,14 @@ pub async fn contract_state<G: Gateway, T: EntryPointTracer>(
         (status = 200, description = "OK", body = TokensRequestResponse),
     ),
     request_body = TokensRequestBody,
+    params(dto::DisplayParams),
     security(
          ("apiKey" = [])
     ),
 )]
 pub async fn tokens<G: Gateway, T: EntryPointTracer>(
     body: web::Json<dto::TokensRequestBody>,
+    display: web::Query<dto::DisplayParams>,
     handler: web::Data<RpcHandler<G, T>>,
 ) -> HttpResponse {
     // Tracing and metrics
@@ -1101,7 +2400,10 @@ pub async fn tokens<G: Gateway, T: EntryPointTracer>(
         .await;
 
     match response {
-        Ok(state) => HttpResponse::Ok().json(state),
+        Ok(state) => with_rows_returned(
+            json_with_display_params(&state, "tokens", &display),
+            state.tokens.len(),
+        ),
         Err(err) => {
             error!(error = %err, ?body, "Error while getting tokens.");
             let status = err.status_code().as_u16().to_string();
@@ -1150,7 +2452,10 @@ pub async fn protocol_components<G: Gateway, T: EntryPointTracer>(
         .await;
 
     match response {
-        Ok(state) => HttpResponse::Ok().json(state),
+        Ok(state) => {
+            let rows = state.protocol_components.len();
+            with_rows_returned(HttpResponse::Ok().json(state), rows)
+        }
         Err(err) => {
             error!(error = %err, ?body, "Error while getting tokens.");
             let status = err.status_code().as_u16().to_string();
@@ -1170,12 +2475,14 @@ pub async fn protocol_components<G: Gateway, T: EntryPointTracer>(
         (status = 200, description = "OK", body = ProtocolStateRequestResponse),
     ),
     request_body = ProtocolStateRequestBody,
+    params(dto::DisplayParams),
     security(
          ("apiKey" = [])
     ),
 )]
 pub async fn protocol_state<G: Gateway, T: EntryPointTracer>(
     body: web::Json<dto::ProtocolStateRequestBody>,
+    display: web::Query<dto::DisplayParams>,
     handler: web::Data<RpcHandler<G, T>>,
 ) -> HttpResponse {
     // Tracing and metrics
@@ -1197,7 +2504,10 @@ pub async fn protocol_state<G: Gateway, T: EntryPointTracer>(
         .await;
 
     match response {
-        Ok(state) => HttpResponse::Ok().json(state),
+        Ok(state) => with_rows_returned(
+            json_with_display_params(&state, "states", &display),
+            state.states.len(),
+        ),
         Err(err) => {
             error!(error = %err, ?body, "Error while getting protocol states.");
             let status = err.status_code().as_u16().to_string();
@@ -1296,132 +2606,667 @@ pub async fn component_tvl<G: Gateway, T: EntryPointTracer>(
     }
 }
 
-/// Retrieve traced entry points
+/// Retrieve the balance history of a component's token
 ///
-/// This endpoint retrieves the traced entry points available in the indexer
+/// This endpoint samples a component's token balance at hourly or daily intervals and returns
+/// open, high, low and close values per interval.
 #[utoipa::path(
     post,
-    path = "/v1/traced_entry_points",
+    path = "/v1/balance_history",
     responses(
-    (status = 200, description = "OK", body = TracedEntryPointRequestResponse),
+        (status = 200, description = "OK", body = BalanceHistoryRequestResponse),
     ),
-    request_body = TracedEntryPointRequestBody,
+    request_body = BalanceHistoryRequestBody,
     security(
-    ("apiKey" = [])
+         ("apiKey" = [])
     ),
 )]
-pub async fn traced_entry_points<G: Gateway, T: EntryPointTracer>(
-    body: web::Json<dto::TracedEntryPointRequestBody>,
+pub async fn balance_history<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::BalanceHistoryRequestBody>,
     handler: web::Data<RpcHandler<G, T>>,
 ) -> HttpResponse {
-    // Tracing and metrics
-    tracing::Span::current().record("page", body.pagination.page);
-    tracing::Span::current().record("page.size", body.pagination.page_size);
-    counter!("rpc_requests", "endpoint" => "traced_entry_points").increment(1);
+    counter!("rpc_requests", "endpoint" => "balance_history").increment(1);
 
-    if body.pagination.page_size > 100 {
-        counter!("rpc_requests_failed", "endpoint" => "traced_entry_points", "status" => "400")
-            .increment(1);
-        return HttpResponse::BadRequest().body("Page size must be less than or equal to 100.");
+    let response = handler
+        .into_inner()
+        .get_balance_history(&body)
+        .await;
+
+    match response {
+        Ok(history) => HttpResponse::Ok().json(history),
+        Err(err) => {
+            error!(error = %err, ?body, "Error while getting balance history.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "balance_history", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
     }
+}
+
+/// Retrieve the change digests of a block range
+///
+/// This endpoint returns a digest over the state, balance and account changes stored for each
+/// block of the range. Comparing the digests of two deployments locates the blocks their indexed
+/// histories diverge at. Blocks that are not yet persisted have no digest.
+#[utoipa::path(
+    post,
+    path = "/v1/block_digests",
+    responses(
+        (status = 200, description = "OK", body = BlockDigestsRequestResponse),
+    ),
+    request_body = BlockDigestsRequestBody,
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn block_digests<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::BlockDigestsRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "block_digests").increment(1);
 
-    // Call the handler to get traced entry points
     let response = handler
         .into_inner()
-        .get_traced_entry_points(&body)
+        .get_block_digests(&body)
         .await;
 
     match response {
-        Ok(entry_points) => HttpResponse::Ok().json(entry_points),
+        Ok(digests) => HttpResponse::Ok().json(digests),
         Err(err) => {
-            error!(error = %err, ?body, "Error while getting traced entry points.");
+            error!(error = %err, ?body, "Error while getting block digests.");
             let status = err.status_code().as_u16().to_string();
-            counter!("rpc_requests_failed", "endpoint" => "traced_entry_points", "status" => status)
+            counter!("rpc_requests_failed", "endpoint" => "block_digests", "status" => status)
                 .increment(1);
             HttpResponse::from_error(err)
         }
     }
 }
 
-/// Add Entry Point
+/// Check which addresses are tracked
 ///
-/// Trace the given entry points and add the entry point, tracing params and results to DB.
+/// This endpoint returns the subset of the given addresses Tycho stores as accounts. Contracts
+/// created in blocks that are not yet persisted are not included.
 #[utoipa::path(
     post,
-    path = "/v1/add_entry_points",
+    path = "/v1/tracked_addresses",
     responses(
-    (status = 200, description = "OK", body = AddEntryPointRequestResponse),
+        (status = 200, description = "OK", body = TrackedAddressesRequestResponse),
     ),
-    request_body = AddEntryPointRequestBody,
+    request_body = TrackedAddressesRequestBody,
     security(
-    ("apiKey" = [])
+         ("apiKey" = [])
     ),
 )]
-pub async fn add_entry_points<G: Gateway, T: EntryPointTracer>(
-    body: web::Json<dto::AddEntryPointRequestBody>,
+pub async fn tracked_addresses<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::TrackedAddressesRequestBody>,
     handler: web::Data<RpcHandler<G, T>>,
 ) -> HttpResponse {
-    // Tracing and metrics
-    counter!("rpc_requests", "endpoint" => "add_entry_points").increment(1);
+    counter!("rpc_requests", "endpoint" => "tracked_addresses").increment(1);
 
-    // Call the handler to add entry points
     let response = handler
         .into_inner()
-        .add_entry_points(&body)
+        .get_tracked_addresses(&body)
         .await;
 
     match response {
-        Ok(add_entry_points_result) => HttpResponse::Ok().json(add_entry_points_result),
+        Ok(tracked) => HttpResponse::Ok().json(tracked),
         Err(err) => {
-            error!(error = %err, ?body, "Error while adding entry points.");
+            error!(error = %err, n_addresses = body.addresses.len(), "Error while getting tracked addresses.");
             let status = err.status_code().as_u16().to_string();
-            counter!("rpc_requests_failed", "endpoint" => "add_entry_points", "status" => status)
+            counter!("rpc_requests_failed", "endpoint" => "tracked_addresses", "status" => status)
                 .increment(1);
             HttpResponse::from_error(err)
         }
     }
 }
 
-/// Health check endpoint
+/// Retrieve the chain head
 ///
-/// This endpoint is used to check the health of the service.
+/// This endpoint returns the latest block stored for a chain, the block each of its extractors
+/// has processed up to and how far the stored data lags behind the wall clock. Load balancers and
+/// clients can use it to decide whether the data is fresh enough.
 #[utoipa::path(
     get,
-    path = "/v1/health",
+    path = "/v1/{chain}/head",
+    params(
+        ("chain" = Chain, Path, description = "The chain to get the head of"),
+    ),
     responses(
-        (status = 200, description = "OK", body=Health),
+        (status = 200, description = "OK", body = ChainHeadResponse),
     ),
     security(
          ("apiKey" = [])
-    )
+    ),
 )]
-pub async fn health() -> HttpResponse {
-    counter!("rpc_requests", "endpoint" => "health").increment(1);
-    HttpResponse::Ok().json(dto::Health::Ready)
-}
+pub async fn chain_head<G: Gateway, T: EntryPointTracer>(
+    chain: web::Path<dto::Chain>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "chain_head").increment(1);
+    let chain = chain.into_inner();
 
-#[cfg(test)]
-mod tests {
-    use std::{collections::HashMap, env, str::FromStr};
+    let response = handler
+        .into_inner()
+        .get_chain_head(chain)
+        .await;
 
-    use actix_web::test;
-    use chrono::NaiveDateTime;
-    use mockall::{mock, predicate::eq};
-    use tycho_common::{
-        keccak256,
-        models::{
-            blockchain::{
-                EntryPoint, EntryPointWithTracingParams, RPCTracerParams, TracingParams,
-                TracingResult,
-            },
-            contract::Account,
-            protocol::{ProtocolComponent, ProtocolComponentState},
-            token::Token,
-            ChangeType,
-        },
-        storage::WithTotal,
-        traits::MockEntryPointTracer,
-    };
+    match response {
+        Ok(head) => HttpResponse::Ok().json(head),
+        Err(err) => {
+            error!(error = %err, %chain, "Error while getting chain head.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "chain_head", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Retrieve an overview of the indexed protocol systems
+///
+/// This endpoint lists every protocol system known to Tycho with the number of its components on
+/// the chain, the implementation types of its protocol types and the latest block in which one of
+/// its components was created or changed.
+#[utoipa::path(
+    get,
+    path = "/v1/{chain}/protocol_systems",
+    params(
+        ("chain" = Chain, Path, description = "The chain to summarize the protocol systems of"),
+    ),
+    responses(
+        (status = 200, description = "OK", body = ProtocolSystemSummariesResponse),
+    ),
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn protocol_system_summaries<G: Gateway, T: EntryPointTracer>(
+    chain: web::Path<dto::Chain>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "protocol_system_summaries").increment(1);
+    let chain = chain.into_inner();
+
+    let response = handler
+        .into_inner()
+        .get_protocol_system_summaries(chain)
+        .await;
+
+    match response {
+        Ok(summaries) => {
+            let rows = summaries.protocol_systems.len();
+            with_rows_returned(HttpResponse::Ok().json(summaries), rows)
+        }
+        Err(err) => {
+            error!(error = %err, %chain, "Error while getting protocol system summaries.");
+            let status = err.status_code().as_u16().to_string();
+            counter!(
+                "rpc_requests_failed",
+                "endpoint" => "protocol_system_summaries",
+                "status" => status
+            )
+            .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Retrieve the state of several chains at the same point in time
+///
+/// This endpoint resolves the given timestamp to the latest stored block at or before it on every
+/// chain referenced by the queries, then runs each protocol and contract state query at the block
+/// of its chain. Versions given in the queries are ignored. Fails if a chain has no stored block
+/// at or before the timestamp.
+#[utoipa::path(
+    post,
+    path = "/v1/multi_chain_snapshot",
+    responses(
+        (status = 200, description = "OK", body = MultiChainSnapshotResponse),
+        (status = 400, description = "Too many queries or a page size above 100"),
+        (status = 404, description = "A chain has no block at or before the timestamp"),
+    ),
+    request_body = MultiChainSnapshotRequestBody,
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn multi_chain_snapshot<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::MultiChainSnapshotRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "multi_chain_snapshot").increment(1);
+
+    let response = handler
+        .into_inner()
+        .get_multi_chain_snapshot(&body)
+        .await;
+
+    match response {
+        Ok(snapshot) => {
+            let rows = snapshot
+                .protocol_states
+                .iter()
+                .map(|res| res.states.len())
+                .chain(
+                    snapshot
+                        .contract_states
+                        .iter()
+                        .map(|res| res.accounts.len()),
+                )
+                .sum();
+            with_rows_returned(HttpResponse::Ok().json(snapshot), rows)
+        }
+        Err(err) => {
+            error!(
+                error = %err,
+                timestamp = %body.timestamp,
+                "Error while getting multi chain snapshot."
+            );
+            let status = err.status_code().as_u16().to_string();
+            counter!(
+                "rpc_requests_failed",
+                "endpoint" => "multi_chain_snapshot",
+                "status" => status
+            )
+            .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Retrieve contract code by hash
+///
+/// Contract state responses may carry only the hash of a contract's code. This endpoint returns
+/// the code itself as raw bytes. The code of a hash never changes, so responses may be cached
+/// indefinitely. Only code of blocks that were already committed to the database is served.
+#[utoipa::path(
+    get,
+    path = "/v1/{chain}/contract_code/{code_hash}",
+    params(
+        ("chain" = Chain, Path, description = "The chain the code is deployed on"),
+        ("code_hash" = String, Path, description = "Hex encoded keccak256 hash of the code"),
+    ),
+    responses(
+        (status = 200, description = "OK", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 404, description = "No code with this hash"),
+    ),
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn contract_code<G: Gateway, T: EntryPointTracer>(
+    path: web::Path<(dto::Chain, String)>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "contract_code").increment(1);
+    let (chain, code_hash) = path.into_inner();
+
+    let response = match Bytes::from_str(&code_hash) {
+        Ok(hash) => {
+            handler
+                .into_inner()
+                .get_contract_code(chain, &hash)
+                .await
+        }
+        Err(err) => Err(RpcError::Parse(format!("Invalid code hash {code_hash}: {err}"))),
+    };
+
+    match response {
+        Ok(code) => with_rows_returned(
+            HttpResponse::Ok()
+                .content_type("application/octet-stream")
+                .insert_header((CACHE_CONTROL, "public, max-age=31536000, immutable"))
+                .body(code.to_vec()),
+            1,
+        ),
+        Err(err) => {
+            error!(error = %err, %chain, code_hash, "Error while getting contract code.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "contract_code", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Run an analytics query
+///
+/// This endpoint runs a read-only SQL query against the views of the `analytics` schema: `block`,
+/// `token`, `protocol_component`, `protocol_state`, `component_balance`, `contract_storage` and
+/// `account_balance`. Only a single `SELECT` statement is accepted, which may only call common
+/// aggregate, window, math, string and date functions. Queries are cancelled after 10 seconds and
+/// return at most 1000 rows. Fails with 500 if the analytics role was not provisioned.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/analytics_query",
+    responses(
+        (status = 200, description = "OK", body = AnalyticsQueryResponse),
+        (status = 400, description = "Query rejected"),
+        (status = 500, description = "Query could not be run"),
+        (status = 503, description = "Query timed out"),
+    ),
+    request_body = AnalyticsQueryRequestBody,
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn analytics_query<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::AnalyticsQueryRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "analytics_query").increment(1);
+
+    let response = handler
+        .into_inner()
+        .run_analytics_query(&body)
+        .await;
+
+    match response {
+        Ok(rows) => HttpResponse::Ok().json(rows),
+        Err(err) => {
+            warn!(error = %err, query = body.query, "Analytics query failed.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "analytics_query", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Retrieve the audit log
+///
+/// This endpoint lists destructive storage operations, like reverts, history prunes, range
+/// repairs and deletions, most recent first. Each entry records who ran the operation, when, the
+/// number of affected rows and the affected block range.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/audit_log",
+    responses(
+        (status = 200, description = "OK", body = AuditLogRequestResponse),
+    ),
+    request_body = AuditLogRequestBody,
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn audit_log<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::AuditLogRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "audit_log").increment(1);
+
+    let response = handler
+        .into_inner()
+        .get_audit_log(&body)
+        .await;
+
+    match response {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(err) => {
+            error!(error = %err, ?body, "Error while getting audit log.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "audit_log", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Compare the deployments of a protocol system on two chains
+///
+/// This endpoint lists the components and contracts of a protocol system indexed on only one of
+/// the chains, and the state and static attributes used on only one of them. Meant to detect
+/// incomplete indexing configurations of protocols deployed on several chains.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/deployment_parity",
+    responses(
+        (status = 200, description = "OK", body = DeploymentParityResponse),
+    ),
+    request_body = DeploymentParityRequestBody,
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn deployment_parity<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::DeploymentParityRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "deployment_parity").increment(1);
+
+    let response = handler
+        .into_inner()
+        .get_deployment_parity(&body)
+        .await;
+
+    match response {
+        Ok(parity) => HttpResponse::Ok().json(parity),
+        Err(err) => {
+            error!(error = %err, ?body, "Error while comparing protocol deployments.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "deployment_parity", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Extractor status
+///
+/// Returns the stored state of an extractor together with the history of the substreams packages
+/// it ran. Packages are identified by a hash of the output module and its dependencies, a hash
+/// change within the history means the stored data was produced by different module versions.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/extractor_status",
+    responses(
+        (status = 200, description = "OK", body = ExtractorStatus),
+        (status = 404, description = "Extractor not found"),
+    ),
+    request_body = ExtractorStatusRequestBody,
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn extractor_status<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::ExtractorStatusRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "extractor_status").increment(1);
+
+    let response = handler
+        .into_inner()
+        .get_extractor_status(&body)
+        .await;
+
+    match response {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(err) => {
+            error!(error = %err, ?body, "Error while getting extractor status.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "extractor_status", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Update token metadata overrides
+///
+/// Creates, replaces and deletes corrections of token metadata and returns all overrides of the
+/// chain. Overrides are merged over the stored symbol when tokens are served and add a display
+/// decimals and logo hint; the stored token rows are left untouched.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/token_overrides",
+    responses(
+        (status = 200, description = "OK", body = TokenOverridesRequestResponse),
+        (status = 400, description = "An override sets no field or an empty symbol"),
+        (status = 404, description = "Token not found"),
+    ),
+    request_body = TokenOverridesRequestBody,
+    security(
+         ("apiKey" = [])
+    ),
+)]
+pub async fn token_overrides<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::TokenOverridesRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "token_overrides").increment(1);
+
+    let response = handler
+        .into_inner()
+        .update_token_overrides(&body)
+        .await;
+
+    match response {
+        Ok(overrides) => HttpResponse::Ok().json(overrides),
+        Err(err) => {
+            error!(error = %err, ?body, "Error while updating token overrides.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "token_overrides", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Retrieve traced entry points
+///
+/// This endpoint retrieves the traced entry points available in the indexer
+#[utoipa::path(
+    post,
+    path = "/v1/traced_entry_points",
+    responses(
+    (status = 200, description = "OK", body = TracedEntryPointRequestResponse),
+    ),
+    request_body = TracedEntryPointRequestBody,
+    security(
+    ("apiKey" = [])
+    ),
+)]
+pub async fn traced_entry_points<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::TracedEntryPointRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    // Tracing and metrics
+    tracing::Span::current().record("page", body.pagination.page);
+    tracing::Span::current().record("page.size", body.pagination.page_size);
+    counter!("rpc_requests", "endpoint" => "traced_entry_points").increment(1);
+
+    if body.pagination.page_size > 100 {
+        counter!("rpc_requests_failed", "endpoint" => "traced_entry_points", "status" => "400")
+            .increment(1);
+        return HttpResponse::BadRequest().body("Page size must be less than or equal to 100.");
+    }
+
+    // Call the handler to get traced entry points
+    let response = handler
+        .into_inner()
+        .get_traced_entry_points(&body)
+        .await;
+
+    match response {
+        Ok(entry_points) => HttpResponse::Ok().json(entry_points),
+        Err(err) => {
+            error!(error = %err, ?body, "Error while getting traced entry points.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "traced_entry_points", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Add Entry Point
+///
+/// Trace the given entry points and add the entry point, tracing params and results to DB.
+#[utoipa::path(
+    post,
+    path = "/v1/add_entry_points",
+    responses(
+    (status = 200, description = "OK", body = AddEntryPointRequestResponse),
+    ),
+    request_body = AddEntryPointRequestBody,
+    security(
+    ("apiKey" = [])
+    ),
+)]
+pub async fn add_entry_points<G: Gateway, T: EntryPointTracer>(
+    body: web::Json<dto::AddEntryPointRequestBody>,
+    handler: web::Data<RpcHandler<G, T>>,
+) -> HttpResponse {
+    // Tracing and metrics
+    counter!("rpc_requests", "endpoint" => "add_entry_points").increment(1);
+
+    // Call the handler to add entry points
+    let response = handler
+        .into_inner()
+        .add_entry_points(&body)
+        .await;
+
+    match response {
+        Ok(add_entry_points_result) => HttpResponse::Ok().json(add_entry_points_result),
+        Err(err) => {
+            error!(error = %err, ?body, "Error while adding entry points.");
+            let status = err.status_code().as_u16().to_string();
+            counter!("rpc_requests_failed", "endpoint" => "add_entry_points", "status" => status)
+                .increment(1);
+            HttpResponse::from_error(err)
+        }
+    }
+}
+
+/// Health check endpoint
+///
+/// This endpoint is used to check the health of the service.
+#[utoipa::path(
+    get,
+    path = "/v1/health",
+    responses(
+        (status = 200, description = "OK", body=Health),
+    ),
+    security(
+         ("apiKey" = [])
+    )
+)]
+pub async fn health() -> HttpResponse {
+    counter!("rpc_requests", "endpoint" => "health").increment(1);
+    HttpResponse::Ok().json(dto::Health::Ready)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        env,
+        str::FromStr,
+    };
+
+    use actix_web::test;
+    use chrono::NaiveDateTime;
+    use mockall::{mock, predicate::eq};
+    use rstest::rstest;
+    use tycho_common::{
+        keccak256,
+        models::{
+            blockchain::{
+                Block, BlockDigest, EntryPoint, EntryPointWithTracingParams, RPCTracerParams,
+                TracingParams, TracingResult,
+            },
+            contract::{Account, SlotAnnotation},
+            protocol::{
+                ProtocolComponent, ProtocolComponentState, ProtocolDeployment,
+                ProtocolSystemSummary,
+            },
+            token::{Token, TokenOverride},
+            ChangeType, ExtractionState, ImplementationType, SubstreamsPackage,
+        },
+        storage::{AnalyticsRows, WithTotal},
+        traits::MockEntryPointTracer,
+    };
     use tycho_ethereum::entrypoint_tracer::tracer::EVMEntrypointService;
 
     use super::*;
@@ -1539,9 +3384,22 @@ mod tests {
         let expected = dto::StateRequestBody {
             contract_ids: Some(vec![contract0]),
             protocol_system: "uniswap_v2".to_string(),
-            version: dto::VersionParam { timestamp: Some(Utc::now().naive_utc()), block: None },
+            version: dto::VersionParam {
+                timestamp: Some(Utc::now().naive_utc()),
+                block: None,
+                finalized_only: false,
+                relative: None,
+            },
             chain: dto::Chain::Ethereum,
             pagination: dto::PaginationParams::default(),
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: None,
+            annotate: false,
         };
 
         let time_difference = expected
@@ -1591,7 +3449,7 @@ mod tests {
         let mut gw = MockGateway::new();
         let mock_response = Ok(WithTotal { entity: vec![expected.clone()], total: Some(10) });
         gw.expect_get_contracts()
-            .return_once(|_, _, _, _, _| Box::pin(async move { mock_response }));
+            .return_once(|_, _, _, _, _, _| Box::pin(async move { mock_response }));
 
         let mut mock_buffer = MockPendingDeltas::new();
         let buf_expected = Account::new(
@@ -1641,9 +3499,22 @@ mod tests {
                 Bytes::from_str("388C818CA8B9251b393131C08a736A67ccB19297").unwrap(),
             ]),
             protocol_system: "uniswap_v2".to_string(),
-            version: dto::VersionParam { timestamp: Some(Utc::now().naive_utc()), block: None },
+            version: dto::VersionParam {
+                timestamp: Some(Utc::now().naive_utc()),
+                block: None,
+                finalized_only: false,
+                relative: None,
+            },
             chain: dto::Chain::Ethereum,
             pagination: dto::PaginationParams::default(),
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: Some(dto::CodeMode::Full),
+            annotate: false,
         };
         let state = req_handler
             .get_contract_state_inner(request)
@@ -1656,6 +3527,334 @@ mod tests {
         assert_eq!(state.pagination.total, 2);
     }
 
+    struct StaticProof(tycho_common::models::contract::AccountProof);
+
+    #[async_trait]
+    impl StorageProofFetcher for StaticProof {
+        async fn get_proof(
+            &self,
+            address: &Address,
+            slots: &[Bytes],
+            block: &Bytes,
+        ) -> Result<tycho_common::models::contract::AccountProof, String> {
+            assert_eq!(block, &Bytes::from("0x0a"));
+            assert_eq!(slots, &[Bytes::from(1u32), Bytes::from(9u32)]);
+            Ok(tycho_common::models::contract::AccountProof {
+                address: address.clone(),
+                ..self.0.clone()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_contract_storage_proof() {
+        let address = Bytes::from_str(WETH).unwrap();
+        let account = Account::new(
+            Chain::Ethereum,
+            address.clone(),
+            "weth".to_owned(),
+            evm_contract_slots([(1, 3), (2, 1)]),
+            Bytes::from(101u8).lpad(32, 0),
+            HashMap::new(),
+            Bytes::from("C0C0C0"),
+            Bytes::zero(32),
+            Bytes::zero(32),
+            Bytes::zero(32),
+            None,
+        );
+        let mut gw = MockGateway::new();
+        gw.expect_get_block()
+            .with(eq(BlockIdentifier::Latest(Chain::Ethereum)))
+            .return_once(|_| {
+                Ok(Block::new(
+                    10,
+                    Chain::Ethereum,
+                    Bytes::from("0x0a"),
+                    Bytes::from("0x09"),
+                    NaiveDateTime::default(),
+                ))
+            });
+        gw.expect_get_contracts()
+            .withf(|_, _, version, include_slots, _, _| {
+                *include_slots &&
+                    version.as_ref().map(|v| &v.0) ==
+                        Some(&BlockOrTimestamp::Block(BlockIdentifier::Hash(Bytes::from(
+                            "0x0a",
+                        ))))
+            })
+            .return_once(move |_, _, _, _, _, _| {
+                Box::pin(async move { Ok(WithTotal { entity: vec![account], total: Some(1) }) })
+            });
+        let proof = tycho_common::models::contract::AccountProof {
+            storage_hash: Bytes::from("0x5e"),
+            ..Default::default()
+        };
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new())
+            .with_proof_fetcher(Arc::new(StaticProof(proof)));
+
+        let res = req_handler
+            .get_contract_storage_proof(&dto::ContractStorageProofRequestBody {
+                chain: dto::Chain::Ethereum,
+                contract_id: address.clone(),
+                slots: Some(vec![Bytes::from(1u32), Bytes::from(9u32)]),
+                version: None,
+                include_proof: true,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.block.hash, Bytes::from("0x0a"));
+        assert_eq!(
+            res.slots,
+            std::collections::BTreeMap::from([(Bytes::from(1u32), Bytes::from(3u32))])
+        );
+        let proof = res.proof.unwrap();
+        assert_eq!(proof.address, address);
+        assert_eq!(proof.storage_hash, Bytes::from("0x5e"));
+    }
+
+    #[tokio::test]
+    async fn test_get_contract_state_finalized_only() {
+        let finalized_hash = Bytes::from("0x0a");
+        let mut gw = MockGateway::new();
+        gw.expect_get_finalized_block()
+            .return_once({
+                let hash = finalized_hash.clone();
+                move |_| {
+                    Ok(Block::new(
+                        10,
+                        Chain::Ethereum,
+                        hash,
+                        Bytes::from("0x09"),
+                        NaiveDateTime::default(),
+                    ))
+                }
+            });
+        gw.expect_get_contracts()
+            .withf(move |_, _, version, _, _, _| {
+                matches!(
+                    version,
+                    Some(Version(BlockOrTimestamp::Block(BlockIdentifier::Hash(hash)), _))
+                        if hash == &finalized_hash
+                )
+            })
+            .return_once(|_, _, _, _, _, _| {
+                Box::pin(async move { Ok(WithTotal { entity: vec![], total: Some(0) }) })
+            });
+        // The buffer has no expectations, any use of pending deltas fails the test.
+        let req_handler = RpcHandler::new(
+            gw,
+            Some(Arc::new(MockPendingDeltas::new())),
+            MockEntryPointTracer::new(),
+        );
+
+        let request = dto::StateRequestBody {
+            contract_ids: None,
+            protocol_system: "uniswap_v2".to_string(),
+            version: dto::VersionParam {
+                timestamp: Some(Utc::now().naive_utc()),
+                block: None,
+                finalized_only: true,
+                relative: None,
+            },
+            chain: dto::Chain::Ethereum,
+            pagination: dto::PaginationParams::default(),
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: None,
+            annotate: false,
+        };
+        let state = req_handler
+            .get_contract_state_inner(request)
+            .await
+            .unwrap();
+
+        assert!(state.accounts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_contract_state_fields() {
+        let account = Account::new(
+            Chain::Ethereum,
+            "0x6b175474e89094c44da98b954eedeac495271d0f"
+                .parse()
+                .unwrap(),
+            "account0".to_owned(),
+            evm_contract_slots([(6, 30), (5, 25)]),
+            Bytes::from(101u8).lpad(32, 0),
+            HashMap::new(),
+            Bytes::from("C0C0C0"),
+            "0x106781541fd1c596ade97569d584baf47e3347d3ac67ce7757d633202061bdc4"
+                .parse()
+                .unwrap(),
+            Bytes::zero(32),
+            Bytes::zero(32),
+            None,
+        );
+        let mut gw = MockGateway::new();
+        let mock_response = Ok(WithTotal { entity: vec![account.clone()], total: Some(1) });
+        gw.expect_get_contracts()
+            .withf(|_, _, _, include_slots, filter, _| {
+                !*include_slots && filter.is_some_and(|f| f.skip_code)
+            })
+            .return_once(|_, _, _, _, _, _| Box::pin(async move { mock_response }));
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let request = dto::StateRequestBody {
+            contract_ids: Some(vec![account.address.clone()]),
+            protocol_system: "uniswap_v2".to_string(),
+            version: dto::VersionParam {
+                timestamp: Some(Utc::now().naive_utc()),
+                block: None,
+                finalized_only: false,
+                relative: None,
+            },
+            chain: dto::Chain::Ethereum,
+            pagination: dto::PaginationParams::default(),
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: Some(vec![]),
+            code: None,
+            annotate: false,
+        };
+        let state = req_handler
+            .get_contract_state_inner(request)
+            .await
+            .unwrap();
+
+        assert_eq!(state.accounts.len(), 1);
+        assert!(state.accounts[0].code.is_empty());
+        assert!(state.accounts[0].slots.is_empty());
+        assert_eq!(state.accounts[0].code_hash, account.code_hash);
+    }
+
+    #[tokio::test]
+    async fn test_get_contract_state_annotations() {
+        let address: Bytes = "0x6b175474e89094c44da98b954eedeac495271d0f"
+            .parse()
+            .unwrap();
+        let account = Account::new(
+            Chain::Ethereum,
+            address.clone(),
+            "account0".to_owned(),
+            evm_contract_slots([(0, 1)]),
+            Bytes::zero(32),
+            HashMap::new(),
+            Bytes::from("C0C0C0"),
+            Bytes::zero(32),
+            Bytes::zero(32),
+            Bytes::zero(32),
+            None,
+        );
+        let annotation = SlotAnnotation {
+            chain: Chain::Ethereum,
+            address: address.clone(),
+            slot: Bytes::from(0u64).lpad(32, 0),
+            label: "owner".to_string(),
+            description: None,
+        };
+        let mut gw = MockGateway::new();
+        gw.expect_get_contracts()
+            .return_once(move |_, _, _, _, _, _| {
+                Box::pin(async move { Ok(WithTotal { entity: vec![account], total: Some(1) }) })
+            });
+        let expected = annotation.clone();
+        gw.expect_get_slot_annotations()
+            .withf(move |_, addresses| addresses.contains(&address))
+            .return_once(move |_, _| Box::pin(async move { Ok(vec![annotation]) }));
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let mut request = dto::StateRequestBody::from_timestamp(
+            "uniswap_v2",
+            Utc::now().naive_utc(),
+            dto::Chain::Ethereum,
+        );
+        request.annotate = true;
+
+        let state = req_handler
+            .get_contract_state_inner(request)
+            .await
+            .unwrap();
+
+        assert_eq!(state.slot_annotations, vec![dto::SlotAnnotation::from(expected)]);
+    }
+
+    #[rstest]
+    #[case::single_contract_full(1, None, true, true)]
+    #[case::multiple_contracts_hash_only(2, None, false, true)]
+    #[case::explicit_full(2, Some(dto::CodeMode::Full), true, true)]
+    #[case::omit(1, Some(dto::CodeMode::Omit), false, false)]
+    #[tokio::test]
+    async fn test_get_contract_state_code_mode(
+        #[case] n_contracts: usize,
+        #[case] code: Option<dto::CodeMode>,
+        #[case] expect_code: bool,
+        #[case] expect_hash: bool,
+    ) {
+        let account = Account::new(
+            Chain::Ethereum,
+            "0x6b175474e89094c44da98b954eedeac495271d0f"
+                .parse()
+                .unwrap(),
+            "account0".to_owned(),
+            HashMap::new(),
+            Bytes::from(101u8).lpad(32, 0),
+            HashMap::new(),
+            Bytes::from("C0C0C0"),
+            "0x106781541fd1c596ade97569d584baf47e3347d3ac67ce7757d633202061bdc4"
+                .parse()
+                .unwrap(),
+            Bytes::zero(32),
+            Bytes::zero(32),
+            None,
+        );
+        let mut gw = MockGateway::new();
+        let mock_response = Ok(WithTotal { entity: vec![account.clone()], total: Some(1) });
+        gw.expect_get_contracts()
+            .withf(move |_, _, _, _, filter, _| filter.is_some_and(|f| f.skip_code != expect_code))
+            .return_once(|_, _, _, _, _, _| Box::pin(async move { mock_response }));
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let mut request = dto::StateRequestBody::new(
+            Some(vec![account.address.clone(); n_contracts]),
+            "uniswap_v2".to_string(),
+            dto::VersionParam::default(),
+            dto::Chain::Ethereum,
+            dto::PaginationParams::default(),
+        );
+        request.code = code;
+
+        let state = req_handler
+            .get_contract_state_inner(request)
+            .await
+            .unwrap();
+
+        assert_eq!(!state.accounts[0].code.is_empty(), expect_code);
+        assert_eq!(!state.accounts[0].code_hash.is_empty(), expect_hash);
+    }
+
+    #[tokio::test]
+    async fn test_get_contract_code() {
+        let code_hash = Bytes::from("0x1067");
+        let mut gw = MockGateway::new();
+        gw.expect_get_code_by_hash()
+            .withf(|chain, hash| chain == &Chain::Ethereum && hash == &Bytes::from("0x1067"))
+            .return_once(|_, _| Box::pin(async move { Ok(Bytes::from("C0C0C0")) }));
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let code = req_handler
+            .get_contract_code(dto::Chain::Ethereum, &code_hash)
+            .await
+            .unwrap();
+
+        assert_eq!(code, Bytes::from("C0C0C0"));
+    }
+
     /// Helper used to make tracing results comparisons deterministic.
     #[allow(clippy::type_complexity)]
     fn normalize_tracing_result(
@@ -2342,6 +4541,14 @@ mod tests {
             version: dto::VersionParam::default(),
             chain: dto::Chain::Ethereum,
             pagination: dto::PaginationParams::default(),
+            code_hash: None,
+            min_balance: None,
+            max_balance: None,
+            order_by_balance: None,
+            has_code: None,
+            fields: None,
+            code: None,
+            annotate: false,
         };
 
         // Serialize the request body to JSON
@@ -2362,6 +4569,8 @@ mod tests {
         // ensure the gateway is only accessed once - the second request should hit cache
         gw.expect_get_tokens()
             .return_once(|_, _, _, _, _| Box::pin(async move { mock_response }));
+        gw.expect_get_token_overrides()
+            .return_once(|_, _| Box::pin(async move { Ok(Vec::new()) }));
         let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
 
         // request for 2 tokens that are in the DB (WETH and USDC)
@@ -2383,22 +4592,228 @@ mod tests {
             .await
             .unwrap();
 
-        assert_eq!(tokens.tokens.len(), 2);
-        assert_eq!(tokens.tokens[0].symbol, "USDC");
-        assert_eq!(tokens.tokens[1].symbol, "WETH");
-        assert_eq!(tokens.pagination.total, 3);
-        assert_eq!(tokens.pagination.total_pages(), 2);
+        assert_eq!(tokens.tokens.len(), 2);
+        assert_eq!(tokens.tokens[0].symbol, "USDC");
+        assert_eq!(tokens.tokens[1].symbol, "WETH");
+        assert_eq!(tokens.pagination.total, 3);
+        assert_eq!(tokens.pagination.total_pages(), 2);
+
+        // Second request (should hit cache and not increase gateway access count)
+
+        let tokens = req_handler
+            .get_tokens(&request)
+            .await
+            .unwrap();
+
+        assert_eq!(tokens.tokens.len(), 2);
+        assert_eq!(tokens.tokens[0].symbol, "USDC");
+        assert_eq!(tokens.tokens[1].symbol, "WETH");
+    }
+
+    #[tokio::test]
+    async fn test_token_overrides() {
+        let weth = WETH.parse::<Bytes>().unwrap();
+        let token_override = TokenOverride {
+            chain: Chain::Ethereum,
+            address: weth.clone(),
+            symbol: Some("wETH".to_string()),
+            display_decimals: None,
+            logo_uri: Some("https://example.com/weth.png".to_string()),
+        };
+        let mut gw = MockGateway::new();
+        gw.expect_upsert_token_overrides()
+            .withf({
+                let token_override = token_override.clone();
+                move |overrides| overrides == [token_override.clone()]
+            })
+            .return_once(|_| Box::pin(async move { Ok(()) }));
+        gw.expect_get_token_overrides()
+            .returning({
+                let token_override = token_override.clone();
+                move |_, _| {
+                    let token_override = token_override.clone();
+                    Box::pin(async move { Ok(vec![token_override]) })
+                }
+            });
+        gw.expect_get_tokens()
+            .return_once(|_, _, _, _, _| {
+                Box::pin(async move {
+                    Ok(WithTotal {
+                        entity: vec![Token::new(
+                            &WETH.parse().unwrap(),
+                            "WETH",
+                            18,
+                            0,
+                            &[],
+                            Chain::Ethereum,
+                            100,
+                        )],
+                        total: Some(1),
+                    })
+                })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let empty = dto::TokenOverridesRequestBody {
+            upsert: vec![dto::TokenOverride { address: weth.clone(), ..Default::default() }],
+            ..Default::default()
+        };
+
+        let updated = req_handler
+            .update_token_overrides(&dto::TokenOverridesRequestBody {
+                upsert: vec![token_override.clone().into()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let tokens = req_handler
+            .get_tokens(&dto::TokensRequestBody {
+                token_addresses: Some(vec![weth]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let rejected = req_handler
+            .update_token_overrides(&empty)
+            .await;
+
+        assert_eq!(updated.overrides, vec![token_override.into()]);
+        assert_eq!(tokens.tokens[0].symbol, "wETH");
+        assert_eq!(tokens.tokens[0].decimals, 18);
+        assert_eq!(tokens.tokens[0].logo_uri.as_deref(), Some("https://example.com/weth.png"));
+        assert!(matches!(rejected, Err(RpcError::Parse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_balance_history_rejects_large_range() {
+        let req_handler = RpcHandler::new(MockGateway::new(), None, MockEntryPointTracer::new());
+        let from = "2020-01-01T00:00:00"
+            .parse::<chrono::NaiveDateTime>()
+            .unwrap();
+        let request = dto::BalanceHistoryRequestBody {
+            chain: dto::Chain::Ethereum,
+            component_id: "pc_1".to_string(),
+            token: USDC.parse().unwrap(),
+            from,
+            to: from + Duration::days(365),
+            granularity: dto::SampleInterval::Hourly,
+        };
+
+        let res = req_handler
+            .get_balance_history(&request)
+            .await;
+
+        assert!(matches!(res, Err(RpcError::Parse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_block_digests() {
+        let mut gw = MockGateway::new();
+        let digest = BlockDigest {
+            chain: Chain::Ethereum,
+            block_number: 2,
+            block_hash: Bytes::from("0x02"),
+            digest: Bytes::from("0xaa"),
+        };
+        let stored = digest.clone();
+        gw.expect_get_block_digests()
+            .withf(|chain, start, end| chain == &Chain::Ethereum && *start == 1 && *end == 3)
+            .return_once(move |_, _, _| Ok(vec![stored]));
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let request = |start_block, end_block| dto::BlockDigestsRequestBody {
+            chain: dto::Chain::Ethereum,
+            start_block,
+            end_block,
+        };
+
+        let res = req_handler
+            .get_block_digests(&request(1, 3))
+            .await
+            .unwrap();
+        let too_large = req_handler
+            .get_block_digests(&request(1, 20_000))
+            .await;
+
+        assert_eq!(res.digests, vec![dto::BlockDigest::from(digest)]);
+        assert!(matches!(too_large, Err(RpcError::Parse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_deployment_parity() {
+        let mut gw = MockGateway::new();
+        gw.expect_get_protocol_deployment()
+            .returning(|chain, system| {
+                let components = ["0x01", "0x02"]
+                    .into_iter()
+                    .take(if *chain == Chain::Ethereum { 2 } else { 1 })
+                    .map(|id| ProtocolComponent {
+                        id: id.to_string(),
+                        protocol_system: system.to_string(),
+                        chain: *chain,
+                        ..Default::default()
+                    })
+                    .collect();
+                let deployment =
+                    ProtocolDeployment { chain: *chain, components, ..Default::default() };
+                Box::pin(async move { Ok(deployment) })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let request = |other_chain| dto::DeploymentParityRequestBody {
+            protocol_system: "uniswap_v2".to_string(),
+            base_chain: dto::Chain::Ethereum,
+            other_chain,
+        };
+
+        let res = req_handler
+            .get_deployment_parity(&request(dto::Chain::Base))
+            .await
+            .unwrap();
+        let same_chain = req_handler
+            .get_deployment_parity(&request(dto::Chain::Ethereum))
+            .await;
+
+        assert!(!res.complete);
+        assert_eq!(res.n_base_components, 2);
+        assert_eq!(res.components_missing_on_other, vec!["0x02".to_string()]);
+        assert!(res
+            .components_missing_on_base
+            .is_empty());
+        assert!(matches!(same_chain, Err(RpcError::Parse(_))));
+    }
 
-        // Second request (should hit cache and not increase gateway access count)
+    #[tokio::test]
+    async fn test_get_extractor_status() {
+        let mut gw = MockGateway::new();
+        gw.expect_get_state()
+            .with(eq("uniswap_v2"), eq(Chain::Ethereum))
+            .return_once(|name, chain| {
+                let package = |version: &str, hash: &str, from_block| SubstreamsPackage {
+                    name: "ethereum_uniswap_v2".to_string(),
+                    version: version.to_string(),
+                    module_name: "map_pool_events".to_string(),
+                    module_hash: Bytes::from(hash),
+                    from_block,
+                };
+                Ok(ExtractionState::new(name.to_string(), *chain, None, &[], Bytes::from("0x01"))
+                    .with_substreams_packages(vec![
+                        package("v0.1.0", "0xaa", None),
+                        package("v0.2.0", "0xbb", Some(100)),
+                    ]))
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let request = dto::ExtractorStatusRequestBody {
+            chain: dto::Chain::Ethereum,
+            extractor: "uniswap_v2".to_string(),
+        };
 
-        let tokens = req_handler
-            .get_tokens(&request)
+        let res = req_handler
+            .get_extractor_status(&request)
             .await
             .unwrap();
 
-        assert_eq!(tokens.tokens.len(), 2);
-        assert_eq!(tokens.tokens[0].symbol, "USDC");
-        assert_eq!(tokens.tokens[1].symbol, "WETH");
+        assert!(res.module_hash_changed);
+        assert_eq!(res.substreams_packages.len(), 2);
+        assert_eq!(res.substreams_packages[1].version, "v0.2.0");
+        assert_eq!(res.substreams_packages[1].from_block, Some(100));
     }
 
     #[tokio::test]
@@ -2440,7 +4855,12 @@ mod tests {
             protocol_system: "uniswap_v2".to_string(),
             chain: dto::Chain::Ethereum,
             include_balances: true,
-            version: dto::VersionParam { timestamp: Some(Utc::now().naive_utc()), block: None },
+            version: dto::VersionParam {
+                timestamp: Some(Utc::now().naive_utc()),
+                block: None,
+                finalized_only: false,
+                relative: None,
+            },
             pagination: dto::PaginationParams::default(),
         };
         let res = req_handler
@@ -2454,6 +4874,54 @@ mod tests {
         assert_eq!(res.pagination.total, 2);
     }
 
+    #[tokio::test]
+    async fn test_get_protocol_state_balance_shares() {
+        let mut gw = MockGateway::new();
+        let weth = Bytes::from(WETH);
+        let usdc = Bytes::from(USDC);
+        let state = ProtocolComponentState::new(
+            "state1",
+            HashMap::new(),
+            HashMap::from([
+                (weth.clone(), Bytes::from("0x32")),
+                (usdc.clone(), Bytes::from("0x01")),
+            ]),
+        );
+        gw.expect_get_protocol_states()
+            .return_once(move |_, _, _, _, _, _| {
+                Box::pin(async move { Ok(WithTotal { entity: vec![state], total: Some(1) }) })
+            });
+        gw.expect_get_token_supplies()
+            .return_once({
+                let weth = weth.clone();
+                move |_, _, _| {
+                    Box::pin(async move { Ok(HashMap::from([(weth, Bytes::from("0xc8"))])) })
+                }
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let request = dto::ProtocolStateRequestBody {
+            protocol_ids: Some(vec!["state1".to_owned()]),
+            protocol_system: "uniswap_v2".to_string(),
+            chain: dto::Chain::Ethereum,
+            include_balances: true,
+            version: dto::VersionParam {
+                timestamp: Some(Utc::now().naive_utc()),
+                block: None,
+                finalized_only: false,
+                relative: None,
+            },
+            pagination: dto::PaginationParams::default(),
+        };
+        let res = req_handler
+            .get_protocol_state_inner(request)
+            .await
+            .unwrap();
+
+        // USDC has no supply snapshot, so no share is reported for it
+        assert_eq!(res.states[0].balance_shares, HashMap::from([(weth, 0.25)]));
+    }
+
     fn protocol_attributes<'a>(
         data: impl IntoIterator<Item = (&'a str, i32)>,
     ) -> HashMap<String, Bytes> {
@@ -2491,6 +4959,31 @@ mod tests {
         let mock_response = Ok(WithTotal { entity: vec![mock_res], total: Some(1) });
         gw.expect_get_protocol_components()
             .return_once(|_, _, _, _, _| Box::pin(async move { mock_response }));
+        let inactive_since = NaiveDateTime::default() + chrono::Duration::days(1);
+        gw.expect_get_inactive_components()
+            .return_once(move |_, ids| {
+                assert_eq!(ids, ["comp1", "comp_buff"]);
+                let inactive = HashMap::from([("comp1".to_string(), inactive_since)]);
+                Box::pin(async move { Ok(inactive) })
+            });
+        gw.expect_get_component_extractors()
+            .return_once(|_, ids, extractor| {
+                assert_eq!(ids, Some(["comp1", "comp_buff"].as_slice()));
+                assert_eq!(extractor, None);
+                let extractors = HashMap::from([("comp1".to_string(), "vm:ambient".to_string())]);
+                Box::pin(async move { Ok(extractors) })
+            });
+        gw.expect_get_token_prices()
+            .return_once(|_| {
+                let usdc = Chain::Ethereum.usd_reference_token();
+                Box::pin(async move { Ok(HashMap::from([(usdc, 0.0005)])) })
+            });
+        gw.expect_get_component_tvls()
+            .return_once(|_, _, ids, _| {
+                assert_eq!(ids, Some(["comp1", "comp_buff"].as_slice()));
+                let tvls = HashMap::from([("comp1".to_string(), 2.0)]);
+                Box::pin(async move { Ok(WithTotal { entity: tvls, total: Some(1) }) })
+            });
 
         let mut mock_buffer = MockPendingDeltas::new();
         let buf_expected = ProtocolComponent::new(
@@ -2521,8 +5014,13 @@ mod tests {
             protocol_system: "ambient".to_string(),
             component_ids: None,
             tvl_gt: None,
+            min_tvl_usd: None,
             chain: dto::Chain::Ethereum,
             pagination: dto::PaginationParams::new(0, 2),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         };
 
         let components = req_handler
@@ -2530,8 +5028,14 @@ mod tests {
             .await
             .unwrap();
 
+        let expected = dto::ProtocolComponent {
+            inactive_since: Some(inactive_since),
+            tvl_usd: Some(4000.0),
+            extractor: Some("vm:ambient".to_string()),
+            ..expected.into()
+        };
         assert_eq!(components.protocol_components.len(), 2);
-        assert_eq!(components.protocol_components[0], expected.into());
+        assert_eq!(components.protocol_components[0], expected);
         assert_eq!(components.protocol_components[1], buf_expected.into());
         assert_eq!(components.pagination.total, 2);
         assert_eq!(components.pagination.page, 0);
@@ -2569,6 +5073,12 @@ mod tests {
                     Box::pin(async move { mock_response_clone })
                 }
             });
+        gw.expect_get_inactive_components()
+            .returning(|_, _| Box::pin(async move { Ok(HashMap::new()) }));
+        gw.expect_get_component_extractors()
+            .returning(|_, _, _| Box::pin(async move { Ok(HashMap::new()) }));
+        gw.expect_get_token_prices()
+            .returning(|_| Box::pin(async move { Ok(HashMap::new()) }));
 
         let mut mock_buffer = MockPendingDeltas::new();
         let buf_expected1 = ProtocolComponent::new(
@@ -2615,8 +5125,13 @@ mod tests {
             protocol_system: "ambient".to_string(),
             component_ids: None,
             tvl_gt: None,
+            min_tvl_usd: None,
             chain: dto::Chain::Ethereum,
             pagination: dto::PaginationParams::new(0, 2),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         };
 
         let response1 = req_handler
@@ -2633,8 +5148,13 @@ mod tests {
             protocol_system: "ambient".to_string(),
             component_ids: None,
             tvl_gt: None,
+            min_tvl_usd: None,
             chain: dto::Chain::Ethereum,
             pagination: dto::PaginationParams::new(1, 2),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
         };
 
         let response2 = req_handler
@@ -2646,4 +5166,481 @@ mod tests {
         assert_eq!(response2.protocol_components[0], buf_expected2.into());
         assert_eq!(response2.pagination.total, 3);
     }
+
+    #[tokio::test]
+    async fn test_get_component_tvls_keeps_id_casing() {
+        // Ids are matched against stored ids in the storage layer, which needs them as requested.
+        let mut gw = MockGateway::new();
+        gw.expect_get_component_tvls()
+            .return_once(|_, _, ids, _| {
+                assert_eq!(
+                    ids,
+                    Some(["0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed", "ambient_Pool"].as_slice())
+                );
+                Box::pin(async move { Ok(WithTotal { entity: HashMap::new(), total: Some(0) }) })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let request = dto::ComponentTvlRequestBody::id_filtered(
+            vec![
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string(),
+                "ambient_Pool".to_string(),
+            ],
+            dto::Chain::Ethereum,
+        );
+
+        req_handler
+            .get_component_tvls(&request)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_protocol_components_min_tvl_usd() {
+        let mut gw = MockGateway::new();
+        gw.expect_get_token_prices()
+            .return_once(|_| {
+                let usdc = Chain::Ethereum.usd_reference_token();
+                Box::pin(async move { Ok(HashMap::from([(usdc, 0.0005)])) })
+            });
+        gw.expect_get_protocol_components()
+            .return_once(|_, _, _, min_tvl, _| {
+                // 1000 USD at 0.0005 ETH per USD, the stricter of both thresholds
+                assert_eq!(min_tvl, Some(0.5));
+                Box::pin(async move { Ok(WithTotal { entity: vec![], total: Some(0) }) })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let request = dto::ProtocolComponentsRequestBody {
+            protocol_system: "ambient".to_string(),
+            component_ids: None,
+            tvl_gt: Some(0.1),
+            min_tvl_usd: Some(1000.0),
+            chain: dto::Chain::Ethereum,
+            pagination: dto::PaginationParams::new(0, 10),
+            fields: None,
+            extractor: None,
+            tokens: None,
+            cursor: None,
+        };
+
+        let components = req_handler
+            .get_protocol_components_inner(request)
+            .await
+            .unwrap();
+
+        assert!(components
+            .protocol_components
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_protocol_components_by_extractor() {
+        let mut gw = MockGateway::new();
+        gw.expect_get_token_prices()
+            .return_once(|_| Box::pin(async move { Ok(HashMap::new()) }));
+        gw.expect_get_component_extractors()
+            .withf(|_, ids, extractor| ids.is_none() && *extractor == Some("vm:ambient"))
+            .return_once(|_, _, _| {
+                let extractors = HashMap::from([("comp1".to_string(), "vm:ambient".to_string())]);
+                Box::pin(async move { Ok(extractors) })
+            });
+        gw.expect_get_protocol_components()
+            .return_once(|_, _, ids, _, _| {
+                assert_eq!(ids, Some(["comp1"].as_slice()));
+                Box::pin(async move { Ok(WithTotal { entity: vec![], total: Some(0) }) })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let request = dto::ProtocolComponentsRequestBody {
+            protocol_system: "ambient".to_string(),
+            component_ids: None,
+            tvl_gt: None,
+            min_tvl_usd: None,
+            chain: dto::Chain::Ethereum,
+            pagination: dto::PaginationParams::new(0, 10),
+            fields: None,
+            extractor: Some("vm:ambient".to_string()),
+            tokens: None,
+            cursor: None,
+        };
+
+        let components = req_handler
+            .get_protocol_components_inner(request)
+            .await
+            .unwrap();
+
+        assert!(components
+            .protocol_components
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_protocol_components_by_tokens() {
+        let tokens = vec![Bytes::from("0x02"), Bytes::from("0x01")];
+        let mut gw = MockGateway::new();
+        gw.expect_get_token_prices()
+            .return_once(|_| Box::pin(async move { Ok(HashMap::new()) }));
+        let expected_tokens = tokens.clone();
+        gw.expect_get_component_ids_by_tokens()
+            .withf(move |_, tokens, ids| tokens == expected_tokens.as_slice() && ids.is_none())
+            .return_once(|_, _, _| Box::pin(async move { Ok(vec!["comp1".to_string()]) }));
+        gw.expect_get_protocol_components()
+            .return_once(|_, _, ids, _, _| {
+                assert_eq!(ids, Some(["comp1"].as_slice()));
+                Box::pin(async move { Ok(WithTotal { entity: vec![], total: Some(0) }) })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let request = dto::ProtocolComponentsRequestBody {
+            protocol_system: "ambient".to_string(),
+            pagination: dto::PaginationParams::new(0, 10),
+            tokens: Some(tokens),
+            ..Default::default()
+        };
+
+        let components = req_handler
+            .get_protocol_components_inner(request)
+            .await
+            .unwrap();
+
+        assert!(components
+            .protocol_components
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_protocol_components_by_cursor() {
+        let component = |id: &str| ProtocolComponent { id: id.to_string(), ..Default::default() };
+        let mut gw = MockGateway::new();
+        gw.expect_get_token_prices()
+            .returning(|_| Box::pin(async move { Ok(HashMap::new()) }));
+        gw.expect_get_inactive_components()
+            .returning(|_, _| Box::pin(async move { Ok(HashMap::new()) }));
+        gw.expect_get_component_extractors()
+            .returning(|_, _, _| Box::pin(async move { Ok(HashMap::new()) }));
+        gw.expect_get_block()
+            .with(eq(BlockIdentifier::Latest(Chain::Ethereum)))
+            .return_once(|_| Ok(Block { number: 10, ..Default::default() }));
+        gw.expect_get_protocol_components_after()
+            .withf(|_, _, _, _, cursor, page_size| {
+                *cursor == ComponentCursor::new(10, None) && *page_size == 3
+            })
+            .return_once(move |_, _, _, _, _, _| {
+                let components = vec![component("a"), component("b"), component("c")];
+                Box::pin(async move { Ok(WithTotal { entity: components, total: Some(5) }) })
+            });
+        gw.expect_get_protocol_components_after()
+            .withf(|_, _, _, _, cursor, _| {
+                *cursor == ComponentCursor::new(10, Some("b".to_string()))
+            })
+            .return_once(move |_, _, _, _, _, _| {
+                let components = vec![component("c"), component("d")];
+                Box::pin(async move { Ok(WithTotal { entity: components, total: Some(5) }) })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let request = |cursor: &str| dto::ProtocolComponentsRequestBody {
+            protocol_system: "ambient".to_string(),
+            pagination: dto::PaginationParams::new(0, 2),
+            cursor: Some(cursor.to_string()),
+            ..Default::default()
+        };
+
+        let first = req_handler
+            .get_protocol_components_inner(request(""))
+            .await
+            .unwrap();
+        let next_cursor = first.next_cursor.clone().unwrap();
+        let second = req_handler
+            .get_protocol_components_inner(request(&next_cursor))
+            .await
+            .unwrap();
+        let invalid = req_handler
+            .get_protocol_components_inner(request("zz"))
+            .await;
+
+        let ids = |res: &dto::ProtocolComponentRequestResponse| {
+            res.protocol_components
+                .iter()
+                .map(|c| c.id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&first), ["a", "b"]);
+        assert_eq!(first.pagination.total, 5);
+        assert_eq!(ids(&second), ["c", "d"]);
+        assert_eq!(second.next_cursor, None);
+        assert!(matches!(invalid, Err(RpcError::Parse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_tracked_addresses() {
+        let mut gw = MockGateway::new();
+        gw.expect_get_tracked_addresses()
+            .withf(|chain, addresses| *chain == Chain::Ethereum && addresses.len() == 3)
+            .return_once(|_, _| {
+                let tracked = HashSet::from([Bytes::from("0x03"), Bytes::from("0x01")]);
+                Box::pin(async move { Ok(tracked) })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let request = dto::TrackedAddressesRequestBody {
+            chain: dto::Chain::Ethereum,
+            addresses: vec![Bytes::from("0x01"), Bytes::from("0x02"), Bytes::from("0x03")],
+        };
+
+        let res = req_handler
+            .get_tracked_addresses(&request)
+            .await
+            .unwrap();
+
+        assert_eq!(res.addresses, vec![Bytes::from("0x01"), Bytes::from("0x03")]);
+    }
+
+    #[tokio::test]
+    async fn test_get_chain_head() {
+        let mut gw = MockGateway::new();
+        let ts = Utc::now().naive_utc() - Duration::seconds(30);
+        gw.expect_get_block()
+            .with(eq(BlockIdentifier::Latest(Chain::Ethereum)))
+            .return_once(move |_| {
+                Ok(Block::new(10, Chain::Ethereum, Bytes::from("0x0a"), Bytes::from("0x09"), ts))
+            });
+        gw.expect_get_extractor_block_numbers()
+            .with(eq(Chain::Ethereum))
+            .return_once(|_| Ok(HashMap::from([("uniswap_v2".to_string(), 8)])));
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let res = req_handler
+            .get_chain_head(dto::Chain::Ethereum)
+            .await
+            .unwrap();
+
+        assert_eq!(res.block_number, 10);
+        assert_eq!(res.block_hash, Bytes::from("0x0a"));
+        assert_eq!(res.extractor_block_numbers["uniswap_v2"], 8);
+        assert!((30..60).contains(&res.lag_seconds), "{}", res.lag_seconds);
+    }
+
+    #[tokio::test]
+    async fn test_get_protocol_system_summaries() {
+        let mut gw = MockGateway::new();
+        let ts = NaiveDateTime::from_str("2024-06-02T00:00:00").unwrap();
+        gw.expect_get_protocol_system_summaries()
+            .with(eq(Chain::Ethereum))
+            .return_once(move |_| {
+                Box::pin(async move {
+                    Ok(vec![ProtocolSystemSummary {
+                        name: "uniswap_v2".to_string(),
+                        component_count: 2,
+                        implementation_types: vec![ImplementationType::Custom],
+                        last_updated_block: Some(10),
+                        last_updated_ts: Some(ts),
+                    }])
+                })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let res = req_handler
+            .get_protocol_system_summaries(dto::Chain::Ethereum)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res,
+            dto::ProtocolSystemSummariesResponse {
+                chain: dto::Chain::Ethereum,
+                protocol_systems: vec![dto::ProtocolSystemSummary {
+                    name: "uniswap_v2".to_string(),
+                    component_count: 2,
+                    implementation_types: vec![dto::ImplementationType::Custom],
+                    last_updated_block: Some(10),
+                    last_updated_ts: Some(ts),
+                }],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_multi_chain_snapshot() {
+        let mut gw = MockGateway::new();
+        let ts = NaiveDateTime::from_str("2024-06-02T00:00:00").unwrap();
+        let block = Block::new(10, Chain::Ethereum, Bytes::from("0x0a"), Bytes::from("0x09"), ts);
+        gw.expect_get_blocks_at().returning({
+            let block = block.clone();
+            move |_, _| Ok(HashMap::from([(Chain::Ethereum, block.clone())]))
+        });
+        gw.expect_get_block()
+            .with(eq(BlockIdentifier::Hash(Bytes::from("0x0a"))))
+            .returning(move |_| Ok(block.clone()));
+        let state = ProtocolComponentState::new(
+            "state1",
+            protocol_attributes([("reserve1", 1000)]),
+            HashMap::new(),
+        );
+        gw.expect_get_protocol_states()
+            .withf(|chain, at, _, _, _, _| {
+                let at_block = BlockOrTimestamp::Block(BlockIdentifier::Hash(Bytes::from("0x0a")));
+                chain == &Chain::Ethereum &&
+                    at.as_ref()
+                        .is_some_and(|v| v.0 == at_block)
+            })
+            .return_once(move |_, _, _, _, _, _| {
+                Box::pin(async move { Ok(WithTotal { entity: vec![state], total: Some(1) }) })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let state_request = dto::ProtocolStateRequestBody {
+            protocol_ids: Some(vec!["state1".to_owned()]),
+            protocol_system: "uniswap_v2".to_string(),
+            chain: dto::Chain::Ethereum,
+            include_balances: false,
+            version: dto::VersionParam::default(),
+            pagination: dto::PaginationParams::default(),
+        };
+        let request = dto::MultiChainSnapshotRequestBody {
+            timestamp: ts + Duration::seconds(5),
+            protocol_states: vec![state_request.clone()],
+            contract_states: Vec::new(),
+        };
+        let missing_chain = dto::MultiChainSnapshotRequestBody {
+            protocol_states: vec![dto::ProtocolStateRequestBody {
+                chain: dto::Chain::Base,
+                ..state_request
+            }],
+            ..request.clone()
+        };
+
+        let res = req_handler
+            .get_multi_chain_snapshot(&request)
+            .await
+            .unwrap();
+        let missing = req_handler
+            .get_multi_chain_snapshot(&missing_chain)
+            .await;
+
+        assert_eq!(res.blocks.len(), 1);
+        assert_eq!(res.blocks[0].number, 10);
+        assert_eq!(res.protocol_states.len(), 1);
+        assert_eq!(res.protocol_states[0].states[0].component_id, "state1");
+        assert!(matches!(missing, Err(RpcError::Storage(StorageError::NotFound(_, _)))));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_relative_version() {
+        let mut gw = MockGateway::new();
+        let ts = NaiveDateTime::from_str("2024-06-02T00:00:00").unwrap();
+        gw.expect_get_block()
+            .with(eq(BlockIdentifier::Latest(Chain::Ethereum)))
+            .returning(move |_| {
+                Ok(Block::new(10, Chain::Ethereum, Bytes::from("0x0a"), Bytes::from("0x09"), ts))
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+
+        let blocks_ago = req_handler
+            .resolve_version(
+                &dto::VersionParam::relative(dto::RelativeVersion::BlocksAgo(4)),
+                Chain::Ethereum,
+            )
+            .await
+            .unwrap();
+        let too_far = req_handler
+            .resolve_version(
+                &dto::VersionParam::relative(dto::RelativeVersion::BlocksAgo(11)),
+                Chain::Ethereum,
+            )
+            .await;
+
+        assert_eq!(
+            blocks_ago,
+            BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 6)))
+        );
+        assert!(matches!(too_far, Err(RpcError::Parse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_tracked_addresses_too_many() {
+        let req_handler = RpcHandler::new(MockGateway::new(), None, MockEntryPointTracer::new());
+        let request = dto::TrackedAddressesRequestBody {
+            chain: dto::Chain::Ethereum,
+            addresses: vec![Bytes::from("0x01"); MAX_TRACKED_ADDRESSES + 1],
+        };
+
+        let res = req_handler
+            .get_tracked_addresses(&request)
+            .await;
+
+        assert!(matches!(res, Err(RpcError::Parse(_))));
+    }
+
+    #[rstest]
+    #[case::select("SELECT count(*) FROM block", Some("SELECT count(*) FROM block"))]
+    #[case::cte(
+        "with c AS (SELECT * FROM token) SELECT symbol FROM c;",
+        Some("with c AS (SELECT * FROM token) SELECT symbol FROM c")
+    )]
+    #[case::updated_at_column("SELECT updated_at FROM block", Some("SELECT updated_at FROM block"))]
+    #[case::multiple_statements("SELECT 1; DROP TABLE block", None)]
+    #[case::write("DELETE FROM block", None)]
+    #[case::writing_cte("WITH d AS (DELETE FROM block RETURNING *) SELECT * FROM d", None)]
+    #[case::select_into("SELECT * INTO blocks FROM block", None)]
+    #[case::row_lock("SELECT * FROM block FOR UPDATE", None)]
+    #[case::set_config("SELECT set_config('role', 'postgres', true)", None)]
+    #[case::comment("SELECT 1 -- ; DROP TABLE block", None)]
+    #[case::functions(
+        "SELECT chain, count(*), max(number) FROM block WHERE number IN (1, 2) GROUP BY chain",
+        Some(
+            "SELECT chain, count(*), max(number) FROM block WHERE number IN (1, 2) GROUP BY chain"
+        )
+    )]
+    #[case::cast(
+        "SELECT CAST(balance_float AS numeric(78, 0)) FROM component_balance",
+        Some("SELECT CAST(balance_float AS numeric(78, 0)) FROM component_balance")
+    )]
+    #[case::sleep("SELECT pg_sleep (10)", None)]
+    #[case::advisory_lock("SELECT pg_catalog.pg_advisory_lock(1)", None)]
+    #[case::quoted_function("SELECT \"pg_sleep\"(10)", None)]
+    fn test_validate_analytics_query(#[case] query: &str, #[case] exp: Option<&str>) {
+        let res = validate_analytics_query(query);
+
+        assert_eq!(res.ok(), exp);
+    }
+
+    #[tokio::test]
+    async fn test_run_analytics_query() {
+        let mut gw = MockGateway::new();
+        gw.expect_run_analytics_query()
+            .withf(|query, row_limit, _| {
+                query == "SELECT 1 AS one" && *row_limit == MAX_ANALYTICS_ROWS
+            })
+            .return_once(|_, _, _| {
+                Ok(AnalyticsRows { rows: vec![serde_json::json!({"one": 1})], truncated: false })
+            });
+        let req_handler = RpcHandler::new(gw, None, MockEntryPointTracer::new());
+        let request = dto::AnalyticsQueryRequestBody {
+            query: "SELECT 1 AS one;".to_string(),
+            row_limit: Some(MAX_ANALYTICS_ROWS + 1),
+        };
+
+        let res = req_handler
+            .run_analytics_query(&request)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res,
+            dto::AnalyticsQueryResponse::new(vec![serde_json::json!({"one": 1})], false)
+        );
+    }
+
+    #[rstest]
+    #[case::not_found(StorageError::NotFound("Account".into(), "0x01".into()), 404)]
+    #[case::deleted(
+        StorageError::Deleted("Account".into(), "0x01".into(), NaiveDateTime::default()),
+        410
+    )]
+    #[case::timeout(StorageError::Timeout("canceling statement".into()), 503)]
+    fn test_storage_error_status(#[case] err: StorageError, #[case] exp_status: u16) {
+        let err = RpcError::from(err);
+
+        assert_eq!(err.status_code().as_u16(), exp_status);
+        assert_eq!(err.error_response().status().as_u16(), exp_status);
+    }
 }
@@ -0,0 +1,562 @@
+//! Usage accounting and quotas per API key.
+//!
+//! Keys and their quotas are configured in a yaml file. Every metered request must carry one of
+//! the configured keys in its `Authorization` header and is rejected with
+//! - `401` if the key is missing or unknown,
+//! - `402` once the key exhausted its monthly rows or bytes quota,
+//! - `429` if the key exceeds its requests per minute.
+//!
+//! Usage is accumulated per calendar month (UTC) and periodically added to the usage stored in
+//! the database, so it survives restarts and quotas hold across all servers sharing the database.
+//! Usage metered since the last flush is lost if the server stops. Requests per minute are limited
+//! per server.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpResponse,
+};
+use chrono::Utc;
+use metrics::counter;
+use serde::Deserialize;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+use tycho_common::{
+    dto,
+    storage::{ApiKeyUsage, Gateway},
+};
+
+use crate::{extractor::ExtractionError, services::access_control::Role};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// How often usage is added to the database and quotas are refreshed with the usage of other
+/// servers.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ApiKeyQuota {
+    /// Name used in metrics, usage reports and storage instead of the key, must be unique
+    pub name: String,
+    #[serde(default)]
+    pub monthly_rows: Option<u64>,
+    #[serde(default)]
+    pub monthly_bytes: Option<u64>,
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    /// Grants access to the internal endpoints, see [`access_control`](super::access_control).
+    #[serde(default)]
+    pub role: Option<Role>,
+}
+
+/// Contents of the api keys file, quotas by key.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ApiKeysConfig {
+    pub keys: HashMap<String, ApiKeyQuota>,
+}
+
+impl ApiKeysConfig {
+    pub fn from_yaml(path: &str) -> Result<Self, ExtractionError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| ExtractionError::Setup(format!("Failed to read {path}: {e}")))?;
+        let config: Self = serde_yaml::from_str(&contents)
+            .map_err(|e| ExtractionError::Setup(format!("Failed to parse {path}: {e}")))?;
+        let mut names = HashSet::new();
+        if let Some(quota) = config
+            .keys
+            .values()
+            .find(|quota| !names.insert(&quota.name))
+        {
+            return Err(ExtractionError::Setup(format!(
+                "Failed to parse {path}: duplicate key name {}",
+                quota.name
+            )));
+        }
+        Ok(config)
+    }
+}
+
+/// Number of entities a response returns, attached to responses by the handlers.
+#[derive(Debug, Clone, Copy)]
+pub struct RowsReturned(pub u64);
+
+pub(crate) fn with_rows_returned(mut response: HttpResponse, rows: usize) -> HttpResponse {
+    response
+        .extensions_mut()
+        .insert(RowsReturned(rows as u64));
+    response
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rejection {
+    UnknownKey,
+    QuotaExhausted,
+    RateLimited,
+}
+
+impl Rejection {
+    fn response(self) -> HttpResponse {
+        match self {
+            Rejection::UnknownKey => HttpResponse::Unauthorized().body("Access denied"),
+            Rejection::QuotaExhausted => {
+                HttpResponse::PaymentRequired().body("Monthly quota exhausted")
+            }
+            Rejection::RateLimited => HttpResponse::TooManyRequests().body("Rate limit exceeded"),
+        }
+    }
+}
+
+/// Usage of a key in `month`, including the usage stored by other servers as of the last flush.
+#[derive(Debug)]
+struct KeyUsage {
+    month: String,
+    requests: u64,
+    rows_returned: u64,
+    bytes_returned: u64,
+    window_start: Instant,
+    window_requests: u32,
+}
+
+impl KeyUsage {
+    fn new(month: String, now: Instant) -> Self {
+        Self {
+            month,
+            requests: 0,
+            rows_returned: 0,
+            bytes_returned: 0,
+            window_start: now,
+            window_requests: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    keys: HashMap<String, KeyUsage>,
+    /// Usage not yet added to the database, by month and key name.
+    unflushed: BTreeMap<String, HashMap<String, ApiKeyUsage>>,
+}
+
+impl Usage {
+    fn add_unflushed(&mut self, month: &str, usage: &ApiKeyUsage) {
+        let unflushed = self
+            .unflushed
+            .entry(month.to_string())
+            .or_default()
+            .entry(usage.api_key.clone())
+            .or_insert_with(|| ApiKeyUsage {
+                api_key: usage.api_key.clone(),
+                ..Default::default()
+            });
+        unflushed.requests += usage.requests;
+        unflushed.rows_returned += usage.rows_returned;
+        unflushed.bytes_returned += usage.bytes_returned;
+    }
+}
+
+pub struct UsageTracker {
+    quotas: HashMap<String, ApiKeyQuota>,
+    usage: Mutex<Usage>,
+}
+
+impl UsageTracker {
+    pub fn new(config: ApiKeysConfig) -> Self {
+        Self { quotas: config.keys, usage: Mutex::new(Usage::default()) }
+    }
+
+    /// Admits a request of `key`, counting it towards the rate limit.
+    fn admit(&self, key: &str, month: &str, now: Instant) -> Result<(), Rejection> {
+        let quota = self
+            .quotas
+            .get(key)
+            .ok_or(Rejection::UnknownKey)?;
+        let mut state = self.usage.lock().unwrap();
+        let usage = state
+            .keys
+            .entry(key.to_string())
+            .or_insert_with(|| KeyUsage::new(month.to_string(), now));
+        if usage.month != month {
+            *usage = KeyUsage::new(month.to_string(), now);
+        }
+        if quota
+            .monthly_rows
+            .is_some_and(|max| usage.rows_returned >= max) ||
+            quota
+                .monthly_bytes
+                .is_some_and(|max| usage.bytes_returned >= max)
+        {
+            return Err(Rejection::QuotaExhausted);
+        }
+        if now.duration_since(usage.window_start) >= RATE_WINDOW {
+            usage.window_start = now;
+            usage.window_requests = 0;
+        }
+        if quota
+            .requests_per_minute
+            .is_some_and(|max| usage.window_requests >= max)
+        {
+            return Err(Rejection::RateLimited);
+        }
+        usage.window_requests += 1;
+        usage.requests += 1;
+        state.add_unflushed(
+            month,
+            &ApiKeyUsage { api_key: quota.name.clone(), requests: 1, ..Default::default() },
+        );
+        Ok(())
+    }
+
+    fn record(&self, key: &str, rows: u64, bytes: u64) {
+        let Some(quota) = self.quotas.get(key) else {
+            return;
+        };
+        let mut state = self.usage.lock().unwrap();
+        let Some(usage) = state.keys.get_mut(key) else {
+            return;
+        };
+        usage.rows_returned += rows;
+        usage.bytes_returned += bytes;
+        let month = usage.month.clone();
+        state.add_unflushed(
+            &month,
+            &ApiKeyUsage {
+                api_key: quota.name.clone(),
+                rows_returned: rows,
+                bytes_returned: bytes,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Adds the usage metered since the last flush to the database and refreshes the usage of the
+    /// current month with the stored one, which includes the usage of other servers. Usage that
+    /// fails to be added is kept for the next flush.
+    async fn flush<G: Gateway>(&self, gateway: &G, month: &str) {
+        let unflushed = std::mem::take(&mut self.usage.lock().unwrap().unflushed);
+        for (unflushed_month, usage) in unflushed {
+            let usage = usage.into_values().collect::<Vec<_>>();
+            if let Err(err) = gateway
+                .add_api_key_usage(&unflushed_month, &usage)
+                .await
+            {
+                warn!(error = %err, month = %unflushed_month, "Failed to store API key usage");
+                let mut state = self.usage.lock().unwrap();
+                for usage in &usage {
+                    state.add_unflushed(&unflushed_month, usage);
+                }
+            }
+        }
+
+        let stored_usage = match gateway.get_api_key_usage(month).await {
+            Ok(stored) => stored
+                .into_iter()
+                .map(|usage| (usage.api_key.clone(), usage))
+                .collect::<HashMap<_, _>>(),
+            Err(err) => {
+                warn!(error = %err, month, "Failed to load API key usage");
+                return;
+            }
+        };
+        let now = Instant::now();
+        let mut state = self.usage.lock().unwrap();
+        let Usage { keys, unflushed } = &mut *state;
+        let unflushed = unflushed.get(month);
+        for (key, quota) in &self.quotas {
+            let usage = keys
+                .entry(key.clone())
+                .or_insert_with(|| KeyUsage::new(month.to_string(), now));
+            if usage.month != month {
+                *usage = KeyUsage::new(month.to_string(), now);
+            }
+            let stored = stored_usage.get(&quota.name);
+            let pending = unflushed.and_then(|unflushed| unflushed.get(&quota.name));
+            let total =
+                |count: fn(&ApiKeyUsage) -> u64| stored.map_or(0, count) + pending.map_or(0, count);
+            usage.requests = total(|u| u.requests);
+            usage.rows_returned = total(|u| u.rows_returned);
+            usage.bytes_returned = total(|u| u.bytes_returned);
+        }
+    }
+
+    /// Spawns a task that flushes the usage every [`FLUSH_INTERVAL`], starting right away so
+    /// quotas account for the usage stored before the server started.
+    pub fn spawn_flush<G>(self: Arc<Self>, gateway: G)
+    where
+        G: Gateway + Send + Sync + 'static,
+    {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                self.flush(&gateway, &current_month())
+                    .await;
+            }
+        });
+    }
+
+    /// Usage of all keys in the current month, sorted by name.
+    pub fn snapshot(&self) -> Vec<dto::ApiKeyUsage> {
+        let month = current_month();
+        let state = self.usage.lock().unwrap();
+        let mut snapshot = self
+            .quotas
+            .iter()
+            .map(|(key, quota)| {
+                let mut entry = dto::ApiKeyUsage {
+                    name: quota.name.clone(),
+                    month: month.clone(),
+                    monthly_rows_quota: quota.monthly_rows,
+                    monthly_bytes_quota: quota.monthly_bytes,
+                    ..Default::default()
+                };
+                if let Some(usage) = state
+                    .keys
+                    .get(key)
+                    .filter(|u| u.month == month)
+                {
+                    entry.requests = usage.requests;
+                    entry.rows_returned = usage.rows_returned;
+                    entry.bytes_returned = usage.bytes_returned;
+                }
+                entry
+            })
+            .collect::<Vec<_>>();
+        snapshot.sort_by(|a, b| a.name.cmp(&b.name));
+        snapshot
+    }
+}
+
+fn current_month() -> String {
+    Utc::now().format("%Y-%m").to_string()
+}
+
+/// Meters requests against the quotas of a [`UsageTracker`]. Passes all requests through if no
+/// tracker is configured.
+#[derive(Clone, Default)]
+pub struct UsageMetering {
+    tracker: Option<Arc<UsageTracker>>,
+}
+
+impl UsageMetering {
+    pub fn new(tracker: Option<Arc<UsageTracker>>) -> Self {
+        Self { tracker }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for UsageMetering
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = UsageMeteringMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(UsageMeteringMiddleware { service, tracker: self.tracker.clone() }))
+    }
+}
+
+pub struct UsageMeteringMiddleware<S> {
+    service: S,
+    tracker: Option<Arc<UsageTracker>>,
+}
+
+impl<S> Service<ServiceRequest> for UsageMeteringMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(tracker) = self.tracker.clone() else {
+            let fut = self.service.call(req);
+            // More problems occur if we try to fix this warning
+            #[allow(clippy::redundant_async_block)]
+            return Box::pin(async move { fut.await });
+        };
+        let key = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        if let Err(rejection) = tracker.admit(&key, &current_month(), Instant::now()) {
+            let name = tracker
+                .quotas
+                .get(&key)
+                .map_or("unknown".to_string(), |q| q.name.clone());
+            counter!("rpc_requests_rejected", "api_key" => name, "reason" => format!("{rejection:?}"))
+                .increment(1);
+            let response = rejection
+                .response()
+                .map_into_boxed_body();
+            return Box::pin(async move { Ok(req.into_response(response)) });
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            let rows = res
+                .response()
+                .extensions()
+                .get::<RowsReturned>()
+                .map_or(0, |rows| rows.0);
+            let bytes = match res.response().body().size() {
+                BodySize::Sized(size) => size,
+                _ => 0,
+            };
+            tracker.record(&key, rows, bytes);
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{http::StatusCode, test, web, App};
+    use mockall::predicate::eq;
+    use tycho_common::storage::StorageError;
+
+    use super::*;
+    use crate::testing::MockGateway;
+
+    fn tracker() -> UsageTracker {
+        UsageTracker::new(ApiKeysConfig {
+            keys: HashMap::from([(
+                "key_a".to_string(),
+                ApiKeyQuota {
+                    name: "partner_a".to_string(),
+                    monthly_rows: Some(10),
+                    monthly_bytes: None,
+                    requests_per_minute: Some(2),
+                    role: None,
+                },
+            )]),
+        })
+    }
+
+    #[test]
+    fn test_admit_enforces_quotas() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        assert_eq!(tracker.admit("other", "2024-01", now), Err(Rejection::UnknownKey));
+        assert_eq!(tracker.admit("key_a", "2024-01", now), Ok(()));
+        assert_eq!(tracker.admit("key_a", "2024-01", now), Ok(()));
+        assert_eq!(tracker.admit("key_a", "2024-01", now), Err(Rejection::RateLimited));
+        let next_window = now + RATE_WINDOW;
+        assert_eq!(tracker.admit("key_a", "2024-01", next_window), Ok(()));
+        tracker.record("key_a", 10, 100);
+        assert_eq!(tracker.admit("key_a", "2024-01", next_window), Err(Rejection::QuotaExhausted));
+        // Usage starts over each month
+        assert_eq!(tracker.admit("key_a", "2024-02", next_window), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_flush_stores_usage_and_loads_totals() {
+        let tracker = tracker();
+        let now = Instant::now();
+        tracker
+            .admit("key_a", "2024-01", now)
+            .unwrap();
+        tracker.record("key_a", 3, 7);
+        let unflushed = ApiKeyUsage {
+            api_key: "partner_a".to_string(),
+            requests: 1,
+            rows_returned: 3,
+            bytes_returned: 7,
+        };
+        let mut gw = MockGateway::new();
+        let mut seq = mockall::Sequence::new();
+        gw.expect_add_api_key_usage()
+            .with(eq("2024-01"), eq(vec![unflushed.clone()]))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_, _| Err(StorageError::Unexpected("connection lost".to_string())));
+        gw.expect_get_api_key_usage()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| Err(StorageError::Unexpected("connection lost".to_string())));
+        gw.expect_add_api_key_usage()
+            .with(eq("2024-01"), eq(vec![unflushed]))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_, _| Ok(()));
+        // Includes the usage of other servers
+        gw.expect_get_api_key_usage()
+            .with(eq("2024-01"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| {
+                Ok(vec![ApiKeyUsage {
+                    api_key: "partner_a".to_string(),
+                    requests: 5,
+                    rows_returned: 10,
+                    bytes_returned: 70,
+                }])
+            });
+
+        tracker.flush(&gw, "2024-01").await;
+        tracker.flush(&gw, "2024-01").await;
+
+        let state = tracker.usage.lock().unwrap();
+        let usage = &state.keys["key_a"];
+        assert_eq!((usage.requests, usage.rows_returned, usage.bytes_returned), (5, 10, 70));
+        assert!(state.unflushed.is_empty());
+        drop(state);
+        assert_eq!(tracker.admit("key_a", "2024-01", now), Err(Rejection::QuotaExhausted));
+    }
+
+    #[actix_rt::test]
+    async fn test_usage_metering_records_rows_and_bytes() {
+        let tracker = Arc::new(tracker());
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/v1/tokens")
+                    .wrap(UsageMetering::new(Some(tracker.clone())))
+                    .route(web::post().to(|| async {
+                        with_rows_returned(HttpResponse::Ok().body("[1,2,3]"), 3)
+                    })),
+            ),
+        )
+        .await;
+
+        let unauthorized = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/v1/tokens")
+                .to_request(),
+        )
+        .await;
+        let ok = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/v1/tokens")
+                .insert_header(("Authorization", "key_a"))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(ok.status(), StatusCode::OK);
+        let usage = tracker.snapshot();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].name, "partner_a");
+        assert_eq!((usage[0].requests, usage[0].rows_returned, usage[0].bytes_returned), (1, 3, 7));
+    }
+}
@@ -0,0 +1,269 @@
+//! Versioned API namespaces.
+//!
+//! Every route is served under each supported version, e.g. `/v1/tokens` and `/v2/tokens`, by the
+//! same handler. Handlers that change in a breaking way take an [`ApiVersion`] argument and branch
+//! on it, all other routes stay shared as they are.
+//!
+//! A request is served with the version of its path, unless it picks another one with the
+//! `Api-Version` header, e.g. for clients that can't change their URLs. Responses name the version
+//! they were served with in the same header. Responses of a deprecated version additionally carry
+//! a `Deprecation` header (RFC 9745) and, once its removal is scheduled, a `Sunset` header
+//! (RFC 8594). Requests are counted per version and route to follow the migration of clients.
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+};
+
+use actix_web::{
+    body::BoxBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    Error, FromRequest, HttpRequest, HttpResponse,
+};
+use chrono::NaiveDateTime;
+use metrics::counter;
+
+pub const API_VERSION_HEADER: &str = "Api-Version";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+        }
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = String;
+
+    /// Parses a version with or without its `v` prefix, e.g. `v2` or `2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_prefix(['v', 'V']).unwrap_or(s) {
+            "1" => Ok(ApiVersion::V1),
+            "2" => Ok(ApiVersion::V2),
+            _ => Err(format!("Unsupported API version {s}, supported versions are v1 and v2")),
+        }
+    }
+}
+
+/// Version a request is served with, requests outside a versioned scope are served as v1.
+impl FromRequest for ApiVersion {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(req
+            .extensions()
+            .get::<ApiVersion>()
+            .copied()
+            .unwrap_or(ApiVersion::V1)))
+    }
+}
+
+/// Announced deprecation of an API version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecation {
+    /// Date from which the version is deprecated.
+    pub deprecated_at: NaiveDateTime,
+    /// Date after which the version may stop being served.
+    pub sunset: Option<NaiveDateTime>,
+}
+
+impl Deprecation {
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        let deprecation = format!("@{}", self.deprecated_at.and_utc().timestamp());
+        if let Ok(value) = HeaderValue::try_from(deprecation) {
+            headers.insert(HeaderName::from_static("deprecation"), value);
+        }
+        let sunset = self.sunset.map(|sunset| {
+            sunset
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string()
+        });
+        if let Some(Ok(value)) = sunset.map(HeaderValue::try_from) {
+            headers.insert(HeaderName::from_static("sunset"), value);
+        }
+    }
+}
+
+pub type Deprecations = HashMap<ApiVersion, Deprecation>;
+
+/// Resolves the version of requests to a versioned scope and marks their responses.
+pub struct Versioning {
+    version: ApiVersion,
+    deprecations: Arc<Deprecations>,
+}
+
+impl Versioning {
+    /// `version` is the version of the wrapped scope's path.
+    pub fn new(version: ApiVersion, deprecations: Arc<Deprecations>) -> Self {
+        Self { version, deprecations }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for Versioning
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = VersioningMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(VersioningMiddleware {
+            service,
+            version: self.version,
+            deprecations: self.deprecations.clone(),
+        }))
+    }
+}
+
+pub struct VersioningMiddleware<S> {
+    service: S,
+    version: ApiVersion,
+    deprecations: Arc<Deprecations>,
+}
+
+impl<S> Service<ServiceRequest> for VersioningMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let requested = req
+            .headers()
+            .get(API_VERSION_HEADER)
+            .map(|value| {
+                value
+                    .to_str()
+                    .map_err(|_| format!("Invalid {API_VERSION_HEADER} header"))
+                    .and_then(ApiVersion::from_str)
+            })
+            .transpose();
+        let version = match requested {
+            Ok(version) => version.unwrap_or(self.version),
+            Err(msg) => {
+                let response = HttpResponse::BadRequest()
+                    .body(msg)
+                    .map_into_boxed_body();
+                return Box::pin(async move { Ok(req.into_response(response)) });
+            }
+        };
+
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        counter!("rpc_api_version_requests", "version" => version.as_str(), "route" => route)
+            .increment(1);
+        req.extensions_mut().insert(version);
+        let deprecation = self.deprecations.get(&version).copied();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+            let headers = res.headers_mut();
+            headers.insert(
+                HeaderName::from_static("api-version"),
+                HeaderValue::from_static(version.as_str()),
+            );
+            if let Some(deprecation) = deprecation {
+                deprecation.insert_headers(headers);
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{http::StatusCode, test, web, App};
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    async fn served_version(version: ApiVersion) -> HttpResponse {
+        HttpResponse::Ok().body(version.as_str())
+    }
+
+    #[actix_rt::test]
+    async fn test_versioning_negotiates_and_marks_deprecated_versions() {
+        let deprecations = Arc::new(Deprecations::from([(
+            ApiVersion::V1,
+            Deprecation {
+                deprecated_at: NaiveDate::from_ymd_opt(2026, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap(),
+                sunset: Some(
+                    NaiveDate::from_ymd_opt(2026, 6, 30)
+                        .unwrap()
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap(),
+                ),
+            },
+        )]));
+        let scope = |prefix, version| {
+            web::scope(prefix)
+                .service(web::resource("/tokens").route(web::post().to(served_version)))
+                .wrap(Versioning::new(version, deprecations.clone()))
+        };
+        let app = test::init_service(
+            App::new()
+                .service(scope("/v1", ApiVersion::V1))
+                .service(scope("/v2", ApiVersion::V2)),
+        )
+        .await;
+        let request = |uri, version: Option<&str>| {
+            let mut req = test::TestRequest::post().uri(uri);
+            if let Some(version) = version {
+                req = req.insert_header((API_VERSION_HEADER, version));
+            }
+            req.to_request()
+        };
+
+        let v1 = test::call_service(&app, request("/v1/tokens", None)).await;
+        let v1_headers = v1.headers().clone();
+        let v1_body = test::read_body(v1).await;
+        let v2 = test::call_service(&app, request("/v2/tokens", None)).await;
+        let v2_headers = v2.headers().clone();
+        let negotiated = test::call_service(&app, request("/v1/tokens", Some("2"))).await;
+        let negotiated_headers = negotiated.headers().clone();
+        let negotiated_body = test::read_body(negotiated).await;
+        let unsupported = test::call_service(&app, request("/v1/tokens", Some("v3"))).await;
+
+        assert_eq!(v1_body, "v1");
+        assert_eq!(v1_headers.get("api-version").unwrap(), "v1");
+        assert_eq!(v1_headers.get("deprecation").unwrap(), "@1767225600");
+        assert_eq!(v1_headers.get("sunset").unwrap(), "Tue, 30 Jun 2026 00:00:00 GMT");
+        assert_eq!(v2_headers.get("api-version").unwrap(), "v2");
+        assert!(!v2_headers.contains_key("deprecation"));
+        assert_eq!(negotiated_body, "v2");
+        assert!(!negotiated_headers.contains_key("sunset"));
+        assert_eq!(unsupported.status(), StatusCode::BAD_REQUEST);
+    }
+}
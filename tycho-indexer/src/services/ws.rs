@@ -1,27 +1,39 @@
 //! This module contains Tycho Websocket implementation
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Debug,
     sync::Arc,
     time::{Duration, Instant},
 };
 
-use actix::{
-    Actor, ActorContext, ActorFutureExt, AsyncContext, SpawnHandle, StreamHandler, WrapFuture,
-};
+use actix::{Actor, ActorContext, ActorFutureExt, AsyncContext, StreamHandler, WrapFuture};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use async_trait::async_trait;
+use futures03::{stream, Stream, StreamExt};
 use metrics::{counter, gauge};
 use serde::Serialize;
 use thiserror::Error;
+use tokio::task::JoinHandle;
 use tracing::{debug, error, info, instrument, trace, warn};
 use tycho_common::{
-    dto::{BlockChanges, Command, Response, WebSocketMessage},
-    models::ExtractorIdentity,
+    dto::{
+        negotiate_schema_version, BlockChanges, Command, MessageGranularity, Response,
+        ResponseProtocolState, SubscriptionFilter, TransactionChangesMsg, WebSocketMessage,
+        MIN_SCHEMA_VERSION, SCHEMA_VERSION,
+    },
+    models::{blockchain::DeltasFilter, ComponentId, ExtractorIdentity},
+    serde_primitives::{with_value_encoding, ValueEncoding},
 };
 use uuid::Uuid;
 
-use crate::extractor::runner::MessageSender;
+use crate::{
+    extractor::runner::MessageSender,
+    services::{
+        rpc::RpcError,
+        ws_queue::{ClientQueue, Delivery, DeliveryConfig},
+    },
+};
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
@@ -41,6 +53,9 @@ pub enum WebsocketError {
 
     #[error("Failed to subscribe to extractor: {0}")]
     SubscribeError(ExtractorIdentity),
+
+    #[error("Unsupported schema versions: {0:?}")]
+    UnsupportedSchemaVersion(Vec<u32>),
 }
 
 impl Serialize for WebsocketError {
@@ -60,22 +75,57 @@ impl Serialize for WebsocketError {
             }
             WebsocketError::SubscribeError(extractor_id) => serializer
                 .serialize_str(&format!("Failed to subscribe to extractor: {extractor_id:?}")),
+            WebsocketError::UnsupportedSchemaVersion(versions) => {
+                serializer.serialize_str(&format!(
+                    "Unsupported schema versions: {versions:?}, supported: {MIN_SCHEMA_VERSION}..={SCHEMA_VERSION}"
+                ))
+            }
         }
     }
 }
 
 pub type MessageSenderMap = HashMap<ExtractorIdentity, Arc<dyn MessageSender + Send + Sync>>;
 
+/// Provides the latest state of components to subscriptions requesting snapshots.
+#[async_trait]
+pub trait SnapshotSource {
+    /// Returns the latest state, including balances, of the given components. Unconfirmed
+    /// changes of the extractor are included.
+    async fn get_component_snapshots(
+        &self,
+        extractor_id: &ExtractorIdentity,
+        component_ids: Vec<ComponentId>,
+    ) -> Result<Vec<ResponseProtocolState>, RpcError>;
+}
+
 /// Shared application data between all connections
 /// The subscribers map is read-only after initialization, so no mutex is needed
 pub struct WsData {
     /// There is one extractor subscriber per extractor identity
     pub subscribers: Arc<MessageSenderMap>,
+    /// If missing, subscriptions requesting snapshots receive deltas only.
+    pub snapshots: Option<Arc<dyn SnapshotSource + Send + Sync>>,
+    /// Bounds the messages queued for each connection.
+    pub delivery: DeliveryConfig,
 }
 
 impl WsData {
     pub fn new(extractors: MessageSenderMap) -> Self {
-        Self { subscribers: Arc::new(extractors) }
+        Self {
+            subscribers: Arc::new(extractors),
+            snapshots: None,
+            delivery: DeliveryConfig::default(),
+        }
+    }
+
+    pub fn with_snapshots(mut self, source: Arc<dyn SnapshotSource + Send + Sync>) -> Self {
+        self.snapshots = Some(source);
+        self
+    }
+
+    pub fn with_delivery(mut self, delivery: DeliveryConfig) -> Self {
+        self.delivery = delivery;
+        self
     }
 }
 
@@ -90,17 +140,27 @@ pub struct WsActor {
     /// connection.
     heartbeat: Instant,
     app_state: web::Data<WsData>,
-    subscriptions: HashMap<Uuid, SpawnHandle>,
+    /// Tasks queueing the messages of each subscription.
+    subscriptions: HashMap<Uuid, JoinHandle<()>>,
+    /// Messages waiting to be sent to the client, bounded so slow clients can't hold on to an
+    /// unlimited amount of memory.
+    queue: Arc<ClientQueue>,
+    /// Encoding of subscriptions that requested a non-default one.
+    value_encodings: HashMap<Uuid, ValueEncoding>,
     user_identity: Option<String>,
 }
 
 impl WsActor {
     fn new(app_state: web::Data<WsData>, user_identity: Option<String>) -> Self {
+        let id = Uuid::new_v4();
+        let queue = Arc::new(ClientQueue::new(id, app_state.delivery));
         Self {
-            id: Uuid::new_v4(),
+            id,
             heartbeat: Instant::now(),
             app_state,
             subscriptions: HashMap::new(),
+            queue,
+            value_encodings: HashMap::new(),
             user_identity,
         }
     }
@@ -204,12 +264,21 @@ impl WsActor {
     /// 2. We spawn an async future that handles the extractor subscription
     /// 3. The future's completion handler updates actor state and sends the response to the client
     /// 4. If the future fails, an error response is sent instead
+    #[allow(clippy::too_many_arguments)]
     #[instrument(skip(self, ctx), fields(WsActor.id = %self.id, subscription_id))]
     fn subscribe(
         &mut self,
         ctx: &mut ws::WebsocketContext<Self>,
         extractor_id: &ExtractorIdentity,
         include_state: bool,
+        schema_version: u32,
+        granularity: MessageGranularity,
+        filter: Option<SubscriptionFilter>,
+        heartbeats: bool,
+        snapshots: bool,
+        value_encoding: ValueEncoding,
+        attributes: Option<HashSet<String>>,
+        coalesce_window: Option<Duration>,
     ) {
         let extractor_id = extractor_id.clone();
         // Step 1: Direct HashMap access (no mutex needed since map is read-only after
@@ -254,25 +323,113 @@ impl WsActor {
         let user_identity = self.user_identity.clone();
         let extractor_id_for_future = extractor_id.clone();
         let extractor_id_for_error = extractor_id.clone();
+        let is_filtered = filter.is_some();
+        let snapshot_source = if snapshots { self.app_state.snapshots.clone() } else { None };
+        // Extractors only keep the per transaction changes while someone subscribes to them.
+        let transaction_subscriber = (granularity == MessageGranularity::Transaction)
+            .then(|| message_sender.transaction_subscribers())
+            .flatten()
+            .map(|subscribers| subscribers.register());
 
         // Step 3: Create async future for subscription setup
         // This future will run independently without blocking the actor's message processing
         // Use async operation instead of block_on to prevent runtime deadlocks
         let fut = async move {
-            match message_sender.subscribe().await {
+            match message_sender.subscribe_lossy().await {
                 Ok(mut rx) => {
                     let elapsed = start_time.elapsed();
                     debug!(actor_id = %actor_id, elapsed_ms = elapsed.as_millis(), "subscribe completed successfully");
 
+                    let mut filter = filter.map(DeltasFilter::from);
+                    let snapshot_extractor_id = extractor_id_for_future.clone();
                     let stream = async_stream::stream! {
-                        while let Some(item) = rx.recv().await {
-                            let result = if include_state {
-                                (*item).clone().into()
+                        let _transaction_subscriber = transaction_subscriber;
+                        while let Some((skipped, item)) = rx.recv().await {
+                            // The bus skips messages of subscriptions that fall behind.
+                            if skipped > 0 {
+                                yield WebSocketMessage::Gap {
+                                    subscription_id,
+                                    dropped_messages: skipped,
+                                };
+                            }
+                            if item.heartbeat && !heartbeats {
+                                continue;
+                            }
+                            let mut block = if include_state {
+                                (*item).clone()
                             } else {
-                                item.drop_state().into()
+                                item.drop_state()
                             };
-                            yield Ok((subscription_id, result));
+                            if let Some(attributes) = attributes.as_ref() {
+                                block.retain_attributes(attributes);
+                            }
+                            // Filtered subscriptions still receive every block, possibly empty,
+                            // so clients can keep track of the chain head.
+                            let block = match filter.as_mut() {
+                                Some(filter) => filter.apply(block),
+                                None => block,
+                            };
+                            // Snapshots go out before the block carrying the components' first
+                            // deltas. Deltas hold absolute values, so it doesn't matter whether
+                            // the snapshot already includes them.
+                            let without_state = filter
+                                .as_mut()
+                                .map(DeltasFilter::take_components_without_state)
+                                .unwrap_or_default();
+                            if let Some(source) = snapshot_source
+                                .as_ref()
+                                .filter(|_| !without_state.is_empty())
+                            {
+                                match source
+                                    .get_component_snapshots(
+                                        &snapshot_extractor_id,
+                                        without_state.into_iter().collect(),
+                                    )
+                                    .await
+                                {
+                                    Ok(snapshots) => {
+                                        yield WebSocketMessage::ComponentSnapshots {
+                                            subscription_id,
+                                            snapshots,
+                                        };
+                                    }
+                                    Err(err) => {
+                                        error!(%err, "Failed to load component snapshots");
+                                        counter!(
+                                            "websocket_snapshot_errors",
+                                            "extractor" => snapshot_extractor_id.name.clone()
+                                        )
+                                        .increment(1);
+                                    }
+                                }
+                            }
+                            // Reverts have no transaction breakdown and are always sent whole.
+                            if granularity == MessageGranularity::Transaction &&
+                                !block.revert &&
+                                !block.txs_with_update.is_empty()
+                            {
+                                for changes in TransactionChangesMsg::from_block(block) {
+                                    let changes = changes.into_schema_version(schema_version);
+                                    yield WebSocketMessage::TransactionChanges {
+                                        subscription_id,
+                                        changes,
+                                    };
+                                }
+                            } else {
+                                let deltas = BlockChanges::from(block)
+                                    .into_schema_version(schema_version);
+                                yield WebSocketMessage::BlockChanges { subscription_id, deltas };
+                            }
+                        }
+                    };
+
+                    // Transaction messages are meant to be consumed one by one, only blocks are
+                    // merged.
+                    let stream = match coalesce_window {
+                        Some(window) if granularity == MessageGranularity::Block => {
+                            coalesce(stream, window).boxed_local()
                         }
+                        _ => stream.boxed_local(),
                     };
 
                     Some((subscription_id, stream, extractor_id_for_future.clone()))
@@ -295,8 +452,15 @@ impl WsActor {
             // If failed: send error response to client
             match result {
                 Some((subscription_id, stream, extractor_id)) => {
-                    let handle = ctx.add_stream(stream);
+                    // Queueing runs outside the actor, which only progresses as fast as the
+                    // client reads.
+                    let handle = actix::spawn(forward(stream, actor.queue.clone()));
                     actor.subscriptions.insert(subscription_id, handle);
+                    if !value_encoding.is_hex() {
+                        actor
+                            .value_encodings
+                            .insert(subscription_id, value_encoding);
+                    }
                     debug!("Added subscription to hashmap");
                     gauge!("websocket_extractor_subscriptions_active", "subscription_id" => subscription_id.to_string()).increment(1);
                     counter!(
@@ -305,12 +469,20 @@ impl WsActor {
                         "chain"=> extractor_id.chain.to_string(),
                         "extractor" => extractor_id.name.to_string(),
                         "user_identity" => user_identity.unwrap_or("unknown".to_string()),
+                        "schema_version" => schema_version.to_string(),
+                        "granularity" => format!("{granularity:?}"),
+                        "filtered" => is_filtered.to_string(),
+                        "value_encoding" => format!("{value_encoding:?}"),
+                        "coalesce_window_ms" => coalesce_window
+                            .map(|window| window.as_millis().to_string())
+                            .unwrap_or_default(),
                     )
                     .increment(1);
 
                     let message = Response::NewSubscription {
                         extractor_id: extractor_id.into(),
                         subscription_id,
+                        schema_version: Some(schema_version),
                     };
                     ctx.text(serde_json::to_string(&message).unwrap());
                 }
@@ -331,9 +503,13 @@ impl WsActor {
             .remove(&subscription_id)
         {
             debug!("Subscription ID found");
-            // Cancel the future of the subscription stream
-            ctx.cancel_future(handle);
-            debug!("Cancelled subscription future");
+            self.value_encodings
+                .remove(&subscription_id);
+            // Stop queueing and discard what is still queued, nothing may follow the end message
+            handle.abort();
+            self.queue
+                .remove_subscription(subscription_id);
+            debug!("Cancelled subscription task");
             gauge!("websocket_extractor_subscriptions_active", "subscription_id" => subscription_id.to_string()).decrement(1);
 
             let message = Response::SubscriptionEnded { subscription_id };
@@ -358,40 +534,132 @@ impl Actor for WsActor {
 
         // Start the heartbeat
         self.heartbeat(ctx);
+
+        // Deliver queued messages as the client reads them
+        let deliveries = stream::unfold(self.queue.clone(), |queue| async move {
+            queue
+                .next()
+                .await
+                .map(|delivery| (delivery, queue))
+        });
+        ctx.add_stream(deliveries);
     }
 
     #[instrument(skip_all, fields(WsActor.id = %self.id), name = "WsActor::stopped")]
-    fn stopped(&mut self, ctx: &mut Self::Context) {
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
         info!("Websocket connection closed");
 
         gauge!("websocket_connections_active", "id" => self.id.to_string()).decrement(1);
 
         // Close all remaining subscriptions
+        self.value_encodings.clear();
         for (subscription_id, handle) in self.subscriptions.drain() {
             debug!(subscription_id = ?subscription_id, "Closing subscription.");
-            ctx.cancel_future(handle);
+            handle.abort();
             gauge!("websocket_extractor_subscriptions_active", "subscription_id" => subscription_id.to_string()).decrement(1);
         }
+        self.queue.close();
     }
 }
 
-/// Handle incoming messages from the extractor and forward them to the WS connection
-impl StreamHandler<Result<(Uuid, BlockChanges), ws::ProtocolError>> for WsActor {
+/// Forward queued messages to the WS connection
+impl StreamHandler<Delivery> for WsActor {
     #[instrument(skip_all, fields(WsActor.id = %self.id))]
-    fn handle(
-        &mut self,
-        msg: Result<(Uuid, BlockChanges), ws::ProtocolError>,
-        ctx: &mut Self::Context,
-    ) {
-        trace!("Message received from extractor");
-        match msg {
-            Ok((subscription_id, deltas)) => {
+    fn handle(&mut self, delivery: Delivery, ctx: &mut Self::Context) {
+        match delivery {
+            Delivery::Message(msg) => {
                 trace!("Forwarding message to client");
-                let msg = WebSocketMessage::BlockChanges { deltas, subscription_id };
-                ctx.text(serde_json::to_string(&msg).unwrap());
+                let encoding = msg
+                    .subscription_id()
+                    .and_then(|id| self.value_encodings.get(&id).copied())
+                    .unwrap_or_default();
+                ctx.text(with_value_encoding(encoding, || serde_json::to_string(&msg).unwrap()));
             }
-            Err(e) => {
-                error!(error = %e, "Failed to receive message from extractor");
+            Delivery::Overflow => {
+                warn!("Websocket client queue full, disconnecting!");
+                counter!("websocket_connections_dropped", "reason" => "slow_client").increment(1);
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Policy,
+                    description: Some("Client too slow".into()),
+                }));
+                ctx.stop();
+            }
+        }
+    }
+}
+
+/// Queues the messages of a subscription until it ends or the client is disconnected.
+async fn forward(messages: impl Stream<Item = WebSocketMessage>, queue: Arc<ClientQueue>) {
+    let mut messages = std::pin::pin!(messages);
+    while let Some(msg) = messages.next().await {
+        if queue.push(msg).is_err() {
+            return;
+        }
+    }
+    // The connection ends with the extractor of any of its subscriptions.
+    queue.close();
+}
+
+/// Buffers the block messages of a subscription and sends them merged once per window.
+///
+/// The window starts with the first message buffered after a flush, so an idle subscription
+/// sends the next block only after a full window. Snapshots are passed through right away, the
+/// block carrying the components' first deltas follows with the next flush. Reverts flush the
+/// buffer and are sent unmerged.
+fn coalesce(
+    messages: impl Stream<Item = WebSocketMessage>,
+    window: Duration,
+) -> impl Stream<Item = WebSocketMessage> {
+    #[allow(clippy::large_enum_variant)]
+    enum Event {
+        Message(Option<WebSocketMessage>),
+        Flush,
+    }
+
+    async_stream::stream! {
+        let mut messages = std::pin::pin!(messages);
+        let mut pending: Option<(Uuid, BlockChanges)> = None;
+        let mut deadline = tokio::time::Instant::now();
+        loop {
+            let event = tokio::select! {
+                msg = messages.next() => Event::Message(msg),
+                _ = tokio::time::sleep_until(deadline), if pending.is_some() => Event::Flush,
+            };
+            match event {
+                Event::Message(Some(WebSocketMessage::BlockChanges { subscription_id, deltas }))
+                    if !deltas.revert =>
+                {
+                    pending = Some(match pending.take() {
+                        Some((id, buffered)) => (id, buffered.merge(deltas)),
+                        None => {
+                            deadline = tokio::time::Instant::now() + window;
+                            (subscription_id, deltas)
+                        }
+                    });
+                }
+                Event::Message(Some(msg)) => {
+                    if matches!(
+                        &msg,
+                        WebSocketMessage::BlockChanges { .. } | WebSocketMessage::Gap { .. }
+                    ) {
+                        if let Some((subscription_id, deltas)) = pending.take() {
+                            yield WebSocketMessage::BlockChanges { subscription_id, deltas };
+                        }
+                    }
+                    yield msg;
+                }
+                Event::Message(None) => {
+                    if let Some((subscription_id, deltas)) = pending.take() {
+                        yield WebSocketMessage::BlockChanges { subscription_id, deltas };
+                    }
+                    break;
+                }
+                Event::Flush => {
+                    if let Some((subscription_id, deltas)) = pending.take() {
+                        counter!("websocket_coalesced_messages").increment(1);
+                        yield WebSocketMessage::BlockChanges { subscription_id, deltas };
+                    }
+                }
             }
         }
     }
@@ -421,9 +689,42 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsActor {
                         debug!(actor_id = %self.id, "Parsed command successfully");
                         // Handle the message based on its variant
                         match message {
-                            Command::Subscribe { extractor_id, include_state } => {
-                                debug!(actor_id = %self.id, %extractor_id, "Message handler: Processing subscribe request");
-                                self.subscribe(ctx, &extractor_id.clone().into(), include_state);
+                            Command::Subscribe {
+                                extractor_id,
+                                include_state,
+                                schema_versions,
+                                granularity,
+                                filter,
+                                heartbeats,
+                                snapshots,
+                                value_encoding,
+                                attributes,
+                                coalesce_window_ms,
+                            } => {
+                                debug!(actor_id = %self.id, %extractor_id, ?schema_versions, "Message handler: Processing subscribe request");
+                                let Some(schema_version) =
+                                    negotiate_schema_version(schema_versions.as_deref())
+                                else {
+                                    let error = WebsocketError::UnsupportedSchemaVersion(
+                                        schema_versions.unwrap_or_default(),
+                                    );
+                                    error!(%error, "Schema negotiation failed");
+                                    ctx.text(serde_json::to_string(&error).unwrap());
+                                    return;
+                                };
+                                self.subscribe(
+                                    ctx,
+                                    &extractor_id.clone().into(),
+                                    include_state,
+                                    schema_version,
+                                    granularity,
+                                    filter,
+                                    heartbeats,
+                                    snapshots,
+                                    value_encoding,
+                                    attributes,
+                                    coalesce_window_ms.map(Duration::from_millis),
+                                );
                                 debug!(actor_id = %self.id, %extractor_id, "Message handler: Subscribe method completed");
                             }
                             Command::Unsubscribe { subscription_id } => {
@@ -745,8 +1046,18 @@ mod tests {
         debug!("Connected to test server");
 
         // Create and send a subscribe message from the client
-        let action =
-            Command::Subscribe { extractor_id: extractor_id.clone().into(), include_state: true };
+        let action = Command::Subscribe {
+            extractor_id: extractor_id.clone().into(),
+            include_state: true,
+            schema_versions: None,
+            granularity: MessageGranularity::Block,
+            filter: None,
+            heartbeats: false,
+            snapshots: false,
+            value_encoding: ValueEncoding::Hex,
+            attributes: None,
+            coalesce_window_ms: None,
+        };
         connection
             .send(Message::Text(serde_json::to_string(&action).unwrap()))
             .await
@@ -760,6 +1071,7 @@ mod tests {
         let first_subscription_id = if let Response::NewSubscription {
             extractor_id: _extractor_id,
             subscription_id: first_subscription_id,
+            ..
         } = response
         {
             debug!(first_subscription_id = ?first_subscription_id, "Received first subscription ID");
@@ -775,8 +1087,18 @@ mod tests {
         debug!("Received DummyMessage from server");
 
         // Create and send a second subscribe message from the client
-        let action =
-            Command::Subscribe { extractor_id: extractor_id2.clone().into(), include_state: true };
+        let action = Command::Subscribe {
+            extractor_id: extractor_id2.clone().into(),
+            include_state: true,
+            schema_versions: None,
+            granularity: MessageGranularity::Block,
+            filter: None,
+            heartbeats: false,
+            snapshots: false,
+            value_encoding: ValueEncoding::Hex,
+            attributes: None,
+            coalesce_window_ms: None,
+        };
         connection
             .send(Message::Text(serde_json::to_string(&action).unwrap()))
             .await
@@ -790,6 +1112,7 @@ mod tests {
         if let Response::NewSubscription {
             extractor_id: _extractor_id2,
             subscription_id: second_subscription_id,
+            ..
         } = response
         {
             debug!(second_subscription_id = ?second_subscription_id, "Received second subscription ID");
@@ -843,16 +1166,107 @@ mod tests {
         Ok(())
     }
 
+    #[actix_rt::test]
+    async fn test_subscribe_unsupported_schema_version() {
+        let extractor_id = ExtractorIdentity::new(Chain::Ethereum, "dummy");
+        let message_sender = Arc::new(MyMessageSender::new(extractor_id.clone()));
+        let mut subscribers_map = HashMap::new();
+        subscribers_map
+            .insert(extractor_id.clone(), message_sender as Arc<dyn MessageSender + Send + Sync>);
+        let app_state = web::Data::new(WsData::new(subscribers_map));
+        let server = start(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .service(web::resource("/ws/").route(web::get().to(WsActor::ws_index)))
+        });
+        let url = server
+            .url("/ws/")
+            .to_string()
+            .replacen("http://", "ws://", 1);
+        let (mut connection, _response) = tokio_tungstenite::connect_async(url)
+            .await
+            .expect("Failed to connect");
+
+        let action = Command::Subscribe {
+            extractor_id: extractor_id.into(),
+            include_state: true,
+            schema_versions: Some(vec![SCHEMA_VERSION + 1]),
+            granularity: MessageGranularity::Block,
+            filter: None,
+            heartbeats: false,
+            snapshots: false,
+            value_encoding: ValueEncoding::Hex,
+            attributes: None,
+            coalesce_window_ms: None,
+        };
+        connection
+            .send(Message::Text(serde_json::to_string(&action).unwrap()))
+            .await
+            .expect("Failed to send subscribe message");
+
+        let response = wait_for_response(&mut connection, |msg| {
+            matches!(msg, Message::Text(text) if text.contains("Unsupported schema versions"))
+        })
+        .await;
+
+        assert!(response.is_ok(), "Expected a schema version error: {response:?}");
+    }
+
     #[test]
     fn test_msg() {
         // Create and send a subscribe message from the client
         let extractor_id =
             ExtractorIdentity { chain: Chain::Ethereum, name: "vm:ambient".to_owned() };
-        let action = Command::Subscribe { extractor_id: extractor_id.into(), include_state: true };
+        let action = Command::Subscribe {
+            extractor_id: extractor_id.into(),
+            include_state: true,
+            schema_versions: Some(vec![SCHEMA_VERSION]),
+            granularity: MessageGranularity::Block,
+            filter: None,
+            heartbeats: false,
+            snapshots: false,
+            value_encoding: ValueEncoding::Hex,
+            attributes: None,
+            coalesce_window_ms: None,
+        };
         let res = serde_json::to_string(&action).unwrap();
         println!("{res}");
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce() {
+        let subscription_id = Uuid::new_v4();
+        let block = |number: u64, revert: bool| WebSocketMessage::BlockChanges {
+            subscription_id,
+            deltas: BlockChanges {
+                block: tycho_common::dto::Block { number, ..Default::default() },
+                revert,
+                ..Default::default()
+            },
+        };
+        let (tx, rx) = mpsc::channel(10);
+        let messages = coalesce(
+            tokio_stream::wrappers::ReceiverStream::new(rx),
+            Duration::from_secs(2),
+        )
+        .map(|msg| match msg {
+            WebSocketMessage::BlockChanges { deltas, .. } => (deltas.block.number, deltas.revert),
+            other => panic!("unexpected message {other:?}"),
+        });
+        let mut messages = std::pin::pin!(messages);
+
+        tx.send(block(1, false)).await.unwrap();
+        tx.send(block(2, false)).await.unwrap();
+        let merged = messages.next().await;
+        tx.send(block(3, false)).await.unwrap();
+        tx.send(block(4, true)).await.unwrap();
+        drop(tx);
+        let rest = messages.collect::<Vec<_>>().await;
+
+        assert_eq!(merged, Some((2, false)));
+        assert_eq!(rest, vec![(3, false), (4, true)]);
+    }
+
     /// Message sender that simulates slow operations to trigger the deadlock
     pub struct SlowMessageSender {
         extractor_id: ExtractorIdentity,
@@ -949,8 +1363,18 @@ mod tests {
             connections.push(connection);
         }
 
-        let subscribe_msg =
-            Command::Subscribe { extractor_id: extractor_id.clone().into(), include_state: true };
+        let subscribe_msg = Command::Subscribe {
+            extractor_id: extractor_id.clone().into(),
+            include_state: true,
+            schema_versions: None,
+            granularity: MessageGranularity::Block,
+            filter: None,
+            heartbeats: false,
+            snapshots: false,
+            value_encoding: ValueEncoding::Hex,
+            attributes: None,
+            coalesce_window_ms: None,
+        };
         let msg_text = serde_json::to_string(&subscribe_msg).unwrap();
 
         // Send subscription requests from all clients simultaneously
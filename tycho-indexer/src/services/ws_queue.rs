@@ -0,0 +1,264 @@
+//! Bounded send queues of websocket clients.
+//!
+//! Subscriptions of a client push their messages into the client's queue from their own tasks,
+//! independently of how fast the client reads. Once the queue is full, the configured
+//! [`OverflowPolicy`] applies: the client is either disconnected, or its oldest messages are
+//! dropped and the affected subscriptions are told how many messages they missed. Either way the
+//! memory held for a slow client stays bounded.
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use metrics::{counter, gauge};
+use tokio::sync::Notify;
+use tycho_common::dto::WebSocketMessage;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Closes the connection, clients are expected to reconnect and resync.
+    #[default]
+    Disconnect,
+    /// Drops the oldest queued messages and notifies the affected subscriptions of the gap.
+    DropOldest,
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverflowPolicy::Disconnect => write!(f, "disconnect"),
+            OverflowPolicy::DropOldest => write!(f, "drop-oldest"),
+        }
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            _ => Err(format!("Unknown overflow policy {s}, expected disconnect or drop-oldest")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryConfig {
+    /// Messages queued per client before the overflow policy applies.
+    pub queue_capacity: usize,
+    pub overflow_policy: OverflowPolicy,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self { queue_capacity: 4096, overflow_policy: OverflowPolicy::default() }
+    }
+}
+
+/// Next item to hand to a client.
+#[derive(Debug)]
+pub enum Delivery {
+    Message(WebSocketMessage),
+    /// The queue overflowed under [`OverflowPolicy::Disconnect`], the client has to be closed.
+    Overflow,
+}
+
+/// Returned when a message can't be queued because the client is disconnected for being slow.
+#[derive(Debug, PartialEq, Eq)]
+pub struct QueueOverflow;
+
+#[derive(Default)]
+struct QueueState {
+    messages: VecDeque<WebSocketMessage>,
+    /// Messages dropped per subscription since the last gap notification.
+    dropped: HashMap<Uuid, u64>,
+    overflowed: bool,
+    closed: bool,
+}
+
+pub struct ClientQueue {
+    client_id: String,
+    config: DeliveryConfig,
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+impl ClientQueue {
+    pub fn new(client_id: Uuid, config: DeliveryConfig) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            config,
+            state: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn push(&self, msg: WebSocketMessage) -> Result<(), QueueOverflow> {
+        let mut state = self.state.lock().unwrap();
+        if state.overflowed {
+            return Err(QueueOverflow);
+        }
+        if state.messages.len() >= self.config.queue_capacity {
+            match self.config.overflow_policy {
+                OverflowPolicy::Disconnect => {
+                    state.overflowed = true;
+                    drop(state);
+                    self.notify.notify_one();
+                    return Err(QueueOverflow);
+                }
+                OverflowPolicy::DropOldest => {
+                    let dropped_subscription = state
+                        .messages
+                        .pop_front()
+                        .and_then(|dropped| dropped.subscription_id());
+                    if let Some(subscription_id) = dropped_subscription {
+                        *state
+                            .dropped
+                            .entry(subscription_id)
+                            .or_default() += 1;
+                    }
+                    counter!("websocket_messages_dropped", "id" => self.client_id.clone())
+                        .increment(1);
+                }
+            }
+        }
+        state.messages.push_back(msg);
+        self.record_depth(&state);
+        drop(state);
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Waits for the next item, returns `None` once the queue is closed and drained.
+    ///
+    /// Gaps are announced before any remaining message, the dropped messages were the oldest.
+    pub async fn next(&self) -> Option<Delivery> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.overflowed {
+                    return Some(Delivery::Overflow);
+                }
+                if let Some(&subscription_id) = state.dropped.keys().next() {
+                    let dropped_messages = state
+                        .dropped
+                        .remove(&subscription_id)
+                        .unwrap_or_default();
+                    return Some(Delivery::Message(WebSocketMessage::Gap {
+                        subscription_id,
+                        dropped_messages,
+                    }));
+                }
+                if let Some(msg) = state.messages.pop_front() {
+                    self.record_depth(&state);
+                    return Some(Delivery::Message(msg));
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Discards queued messages and pending gaps of an ended subscription.
+    pub fn remove_subscription(&self, subscription_id: Uuid) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .messages
+            .retain(|msg| msg.subscription_id() != Some(subscription_id));
+        state.dropped.remove(&subscription_id);
+        self.record_depth(&state);
+    }
+
+    /// Ends delivery once the queued messages are drained.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    fn record_depth(&self, state: &QueueState) {
+        gauge!("websocket_client_queue_depth", "id" => self.client_id.clone())
+            .set(state.messages.len() as f64);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn snapshots(subscription_id: Uuid) -> WebSocketMessage {
+        WebSocketMessage::ComponentSnapshots { subscription_id, snapshots: vec![] }
+    }
+
+    async fn drain(queue: &ClientQueue) -> Vec<String> {
+        queue.close();
+        let mut delivered = Vec::new();
+        while let Some(delivery) = queue.next().await {
+            delivered.push(match delivery {
+                Delivery::Message(WebSocketMessage::Gap { dropped_messages, .. }) => {
+                    format!("gap {dropped_messages}")
+                }
+                Delivery::Message(_) => "snapshots".to_string(),
+                Delivery::Overflow => return vec!["overflow".to_string()],
+            });
+        }
+        delivered
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_announces_gaps() {
+        let subscription_id = Uuid::new_v4();
+        let queue = ClientQueue::new(
+            Uuid::new_v4(),
+            DeliveryConfig { queue_capacity: 2, overflow_policy: OverflowPolicy::DropOldest },
+        );
+
+        for _ in 0..5 {
+            queue
+                .push(snapshots(subscription_id))
+                .unwrap();
+        }
+
+        assert_eq!(drain(&queue).await, vec!["gap 3", "snapshots", "snapshots"]);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_on_overflow() {
+        let subscription_id = Uuid::new_v4();
+        let queue = ClientQueue::new(
+            Uuid::new_v4(),
+            DeliveryConfig { queue_capacity: 1, overflow_policy: OverflowPolicy::Disconnect },
+        );
+
+        queue
+            .push(snapshots(subscription_id))
+            .unwrap();
+        let overflow = queue.push(snapshots(subscription_id));
+
+        assert_eq!(overflow, Err(QueueOverflow));
+        assert_eq!(queue.push(snapshots(subscription_id)), Err(QueueOverflow));
+        assert_eq!(drain(&queue).await, vec!["overflow"]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_subscription_discards_its_messages() {
+        let (kept, removed) = (Uuid::new_v4(), Uuid::new_v4());
+        let queue = ClientQueue::new(
+            Uuid::new_v4(),
+            DeliveryConfig { queue_capacity: 2, overflow_policy: OverflowPolicy::DropOldest },
+        );
+
+        queue.push(snapshots(removed)).unwrap();
+        queue.push(snapshots(removed)).unwrap();
+        queue.push(snapshots(kept)).unwrap();
+        queue.remove_subscription(removed);
+
+        assert_eq!(drain(&queue).await, vec!["snapshots"]);
+    }
+}
@@ -0,0 +1,401 @@
+//! Exports extractor messages to an external message bus such as Kafka or NATS.
+//!
+//! For consumers that would rather read changes from their own message bus than from the
+//! websocket. The exporter subscribes to every extractor like a websocket client would, converts
+//! each message to the same [`dto::BlockChanges`] and publishes it, wrapped in a [`SinkEnvelope`],
+//! to a topic per chain and extractor.
+//!
+//! Publishing is retried with exponential backoff. Since a retry may deliver a message twice,
+//! each envelope carries a `dedup_id` that is stable across retries, the backends additionally
+//! pass it to the broker where it supports deduplication. A block is exported again after it was
+//! reverted, so the id also counts the reverts exported before the message. The count restarts
+//! with the indexer, ids of messages replayed after a restart only match until the first revert.
+//! Messages that still fail after all retries are dropped and counted, the gap shows in the
+//! envelope `sequence`.
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tokio_retry::{strategy::ExponentialBackoff, Retry};
+use tracing::{debug, error, info, warn};
+use tycho_common::{
+    dto,
+    models::{BlockHash, ExtractorIdentity},
+};
+
+use crate::extractor::{runner::MessageSender, ExtractorMsg};
+
+/// Default topic, `{chain}` and `{extractor}` are replaced per extractor.
+pub const DEFAULT_TOPIC_TEMPLATE: &str = "tycho.{chain}.{extractor}";
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("Unsupported sink url: {0}")]
+    Unsupported(String),
+    #[error("Failed to connect to sink: {0}")]
+    Connection(String),
+    #[error("Failed to publish to {0}: {1}")]
+    Publish(String, String),
+}
+
+/// A message as it is handed to a sink backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SinkRecord {
+    pub topic: String,
+    /// Extractor name, backends with partitions use it to keep an extractor's messages ordered.
+    pub key: String,
+    pub dedup_id: String,
+    pub payload: Vec<u8>,
+}
+
+#[async_trait]
+pub trait DeltaSink: Send + Sync {
+    async fn publish(&self, record: &SinkRecord) -> Result<(), SinkError>;
+}
+
+/// Payload of every exported message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SinkEnvelope {
+    /// Position in the exported stream of the extractor, restarts at 0 with the indexer.
+    pub sequence: u64,
+    /// Identifies the message across retries.
+    pub dedup_id: String,
+    pub changes: dto::BlockChanges,
+}
+
+impl SinkEnvelope {
+    fn new(sequence: u64, msg: &ExtractorMsg, position: &StreamPosition) -> Self {
+        let mut dedup_id = format!(
+            "{}:{}:{:x}:{}:{}",
+            msg.chain,
+            msg.extractor,
+            msg.block.hash,
+            if msg.revert { "revert" } else { "apply" },
+            position.reverts
+        );
+        if let (true, Some(head)) = (msg.revert, &position.head) {
+            dedup_id.push_str(&format!(":{head:x}"));
+        }
+        Self { sequence, dedup_id, changes: msg.as_ref().clone().into() }
+    }
+}
+
+/// Tracks what an exported stream went through so far, messages about the same block recur after
+/// reverts.
+#[derive(Debug, Default)]
+struct StreamPosition {
+    /// Block of the last exported message.
+    head: Option<BlockHash>,
+    /// Reverts exported so far.
+    reverts: u64,
+}
+
+impl StreamPosition {
+    fn advance(&mut self, msg: &ExtractorMsg) {
+        self.head = Some(msg.block.hash.clone());
+        if msg.revert {
+            self.reverts += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SinkConfig {
+    pub topic_template: String,
+    /// Attempts after the first failed publish before a message is dropped.
+    pub max_retries: usize,
+}
+
+impl Default for SinkConfig {
+    fn default() -> Self {
+        Self { topic_template: DEFAULT_TOPIC_TEMPLATE.to_string(), max_retries: 5 }
+    }
+}
+
+impl SinkConfig {
+    fn topic(&self, id: &ExtractorIdentity) -> String {
+        self.topic_template
+            .replace("{chain}", &id.chain.to_string())
+            .replace("{extractor}", &id.name)
+    }
+}
+
+/// Connects to the sink at `url`, e.g. `kafka://broker1:9092,broker2:9092` or
+/// `nats://localhost:4222`. Each scheme requires the feature of the same name.
+pub async fn connect(url: &str) -> Result<Arc<dyn DeltaSink>, SinkError> {
+    match url.split_once("://") {
+        #[cfg(feature = "kafka")]
+        Some(("kafka", brokers)) => Ok(Arc::new(kafka::KafkaSink::new(brokers)?)),
+        #[cfg(feature = "nats")]
+        Some(("nats", _)) => Ok(Arc::new(nats::NatsSink::connect(url).await?)),
+        _ => Err(SinkError::Unsupported(url.to_string())),
+    }
+}
+
+pub struct DeltaExporter {
+    sink: Arc<dyn DeltaSink>,
+    config: SinkConfig,
+}
+
+impl DeltaExporter {
+    pub fn new(sink: Arc<dyn DeltaSink>, config: SinkConfig) -> Self {
+        Self { sink, config }
+    }
+
+    /// Exports all messages of the extractor `id` until its subscription closes.
+    pub async fn run(
+        self: Arc<Self>,
+        id: ExtractorIdentity,
+        sender: &dyn MessageSender,
+    ) -> Result<JoinHandle<()>, SinkError> {
+        let mut rx = sender
+            .subscribe()
+            .await
+            .map_err(|err| SinkError::Connection(format!("Failed to subscribe to {id}: {err}")))?;
+        let topic = self.config.topic(&id);
+        info!(extractor_id = %id, topic, "Exporting extractor messages");
+        Ok(tokio::spawn(async move {
+            let mut sequence = 0;
+            let mut position = StreamPosition::default();
+            while let Some(msg) = rx.recv().await {
+                self.export(&id, &topic, sequence, &msg, &position)
+                    .await;
+                position.advance(&msg);
+                sequence += 1;
+            }
+            debug!(extractor_id = %id, "Extractor subscription closed, export stopped");
+        }))
+    }
+
+    async fn export(
+        &self,
+        id: &ExtractorIdentity,
+        topic: &str,
+        sequence: u64,
+        msg: &ExtractorMsg,
+        position: &StreamPosition,
+    ) {
+        let envelope = SinkEnvelope::new(sequence, msg, position);
+        let record = SinkRecord {
+            topic: topic.to_string(),
+            key: id.name.clone(),
+            dedup_id: envelope.dedup_id.clone(),
+            payload: serde_json::to_vec(&envelope).expect("Failed to serialize sink envelope"),
+        };
+        let strategy = ExponentialBackoff::from_millis(2)
+            .factor(50)
+            .max_delay(Duration::from_secs(10))
+            .take(self.config.max_retries);
+        let res = Retry::spawn(strategy, || async {
+            self.sink
+                .publish(&record)
+                .await
+                .inspect_err(|err| warn!(error = %err, sequence, "Publishing to sink failed"))
+        })
+        .await;
+        let status = match res {
+            Ok(()) => "published",
+            Err(err) => {
+                error!(extractor_id = %id, error = %err, sequence, "Dropped message after retries");
+                "dropped"
+            }
+        };
+        counter!(
+            "sink_messages",
+            "chain" => id.chain.to_string(),
+            "extractor" => id.name.clone(),
+            "status" => status
+        )
+        .increment(1);
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use rdkafka::{
+        message::{Header, OwnedHeaders},
+        producer::{FutureProducer, FutureRecord},
+        ClientConfig,
+    };
+
+    use super::{DeltaSink, SinkError, SinkRecord};
+
+    pub struct KafkaSink {
+        producer: FutureProducer,
+    }
+
+    impl KafkaSink {
+        pub fn new(brokers: &str) -> Result<Self, SinkError> {
+            let producer = ClientConfig::new()
+                .set("bootstrap.servers", brokers)
+                .set("enable.idempotence", "true")
+                .create()
+                .map_err(|err| SinkError::Connection(err.to_string()))?;
+            Ok(Self { producer })
+        }
+    }
+
+    #[async_trait]
+    impl DeltaSink for KafkaSink {
+        async fn publish(&self, record: &SinkRecord) -> Result<(), SinkError> {
+            let headers = OwnedHeaders::new()
+                .insert(Header { key: "dedup-id", value: Some(record.dedup_id.as_str()) });
+            let kafka_record = FutureRecord::to(&record.topic)
+                .key(&record.key)
+                .payload(&record.payload)
+                .headers(headers);
+            self.producer
+                .send(kafka_record, Duration::from_secs(5))
+                .await
+                .map(|_| ())
+                .map_err(|(err, _)| SinkError::Publish(record.topic.clone(), err.to_string()))
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use async_nats::{jetstream, HeaderMap};
+    use async_trait::async_trait;
+
+    use super::{DeltaSink, SinkError, SinkRecord};
+
+    /// Publishes to JetStream, which drops redeliveries within the stream's duplicate window.
+    pub struct NatsSink {
+        jetstream: jetstream::Context,
+    }
+
+    impl NatsSink {
+        pub async fn connect(url: &str) -> Result<Self, SinkError> {
+            let client = async_nats::connect(url)
+                .await
+                .map_err(|err| SinkError::Connection(err.to_string()))?;
+            Ok(Self { jetstream: jetstream::new(client) })
+        }
+    }
+
+    #[async_trait]
+    impl DeltaSink for NatsSink {
+        async fn publish(&self, record: &SinkRecord) -> Result<(), SinkError> {
+            let publish_error = |err: String| SinkError::Publish(record.topic.clone(), err);
+            let mut headers = HeaderMap::new();
+            headers.insert("Nats-Msg-Id", record.dedup_id.as_str());
+            self.jetstream
+                .publish_with_headers(record.topic.clone(), headers, record.payload.clone().into())
+                .await
+                .map_err(|err| publish_error(err.to_string()))?
+                .await
+                .map_err(|err| publish_error(err.to_string()))?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use tokio::sync::mpsc::{self, error::SendError, Receiver};
+    use tycho_common::models::{blockchain::BlockAggregatedChanges, Chain};
+
+    use super::*;
+    use crate::extractor::runner::ControlMessage;
+
+    /// Hands out a prepared receiver to the first subscriber.
+    struct ReplaySender(Mutex<Option<Receiver<ExtractorMsg>>>);
+
+    #[async_trait]
+    impl MessageSender for ReplaySender {
+        async fn subscribe(&self) -> Result<Receiver<ExtractorMsg>, SendError<ControlMessage>> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap()
+                .take()
+                .expect("subscribed twice"))
+        }
+    }
+
+    /// Fails the first `failures` publishes, records the rest.
+    #[derive(Default)]
+    struct FlakySink {
+        failures: Mutex<usize>,
+        published: Mutex<Vec<SinkRecord>>,
+    }
+
+    #[async_trait]
+    impl DeltaSink for FlakySink {
+        async fn publish(&self, record: &SinkRecord) -> Result<(), SinkError> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(SinkError::Publish(record.topic.clone(), "unavailable".to_string()));
+            }
+            self.published
+                .lock()
+                .unwrap()
+                .push(record.clone());
+            Ok(())
+        }
+    }
+
+    fn msg(block_number: u64, revert: bool) -> ExtractorMsg {
+        let mut changes = BlockAggregatedChanges {
+            extractor: "uniswap_v2".to_string(),
+            chain: Chain::Ethereum,
+            revert,
+            ..Default::default()
+        };
+        changes.block.number = block_number;
+        changes.block.hash = block_number.to_be_bytes().into();
+        Arc::new(changes)
+    }
+
+    #[tokio::test]
+    async fn test_export_retries_and_sequences_messages() {
+        let sink = Arc::new(FlakySink { failures: Mutex::new(2), ..Default::default() });
+        let exporter = Arc::new(DeltaExporter::new(sink.clone(), SinkConfig::default()));
+        let (tx, rx) = mpsc::channel(8);
+        let sender = ReplaySender(Mutex::new(Some(rx)));
+        tx.send(msg(1, false)).await.unwrap();
+        tx.send(msg(2, false)).await.unwrap();
+        tx.send(msg(1, true)).await.unwrap();
+        tx.send(msg(2, false)).await.unwrap();
+        tx.send(msg(1, true)).await.unwrap();
+        drop(tx);
+
+        exporter
+            .run(ExtractorIdentity::new(Chain::Ethereum, "uniswap_v2"), &sender)
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+
+        let published = sink.published.lock().unwrap();
+        let envelopes = published
+            .iter()
+            .map(|record| serde_json::from_slice::<SinkEnvelope>(&record.payload).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(published[0].topic, "tycho.ethereum.uniswap_v2");
+        assert_eq!(
+            envelopes
+                .iter()
+                .map(|e| (e.sequence, e.dedup_id.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, "ethereum:uniswap_v2:0x0000000000000001:apply:0"),
+                (1, "ethereum:uniswap_v2:0x0000000000000002:apply:0"),
+                (2, "ethereum:uniswap_v2:0x0000000000000001:revert:0:0x0000000000000002"),
+                (3, "ethereum:uniswap_v2:0x0000000000000002:apply:1"),
+                (4, "ethereum:uniswap_v2:0x0000000000000001:revert:1:0x0000000000000002"),
+            ]
+        );
+        assert_eq!(published[0].dedup_id, envelopes[0].dedup_id);
+    }
+}
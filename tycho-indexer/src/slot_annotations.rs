@@ -0,0 +1,116 @@
+//! Slot annotations loaded from JSON mapping files.
+//!
+//! A mapping file maps contract addresses to their annotated slots. Each slot maps either to its
+//! label or to an object with a label and a description:
+//!
+//! ```json
+//! {
+//!   "0xba12222222228d8ba445958a75a0704d566bf2c8": {
+//!     "0x0": "owner",
+//!     "0x3": { "label": "fee", "description": "Swap fee with 18 decimals" }
+//!   }
+//! }
+//! ```
+//!
+//! Slots may omit leading zeros, they are left padded to 32 bytes to match the stored slots.
+use std::{collections::HashMap, fs, str::FromStr};
+
+use serde::Deserialize;
+use tycho_common::{
+    models::{contract::SlotAnnotation, Address, Chain},
+    Bytes,
+};
+
+use crate::extractor::ExtractionError;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SlotLabel {
+    Label(String),
+    Detailed { label: String, description: Option<String> },
+}
+
+/// Reads the annotations of a mapping file, all annotated contracts are assumed to be on `chain`.
+pub fn load_slot_annotations(
+    chain: Chain,
+    path: &str,
+) -> Result<Vec<SlotAnnotation>, ExtractionError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ExtractionError::Setup(format!("Failed to read {path}: {e}")))?;
+    parse_slot_annotations(chain, &contents)
+        .map_err(|e| ExtractionError::Setup(format!("Failed to parse {path}: {e}")))
+}
+
+fn parse_slot_annotations(chain: Chain, contents: &str) -> Result<Vec<SlotAnnotation>, String> {
+    let mapping: HashMap<String, HashMap<String, SlotLabel>> =
+        serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    let mut annotations = Vec::new();
+    for (address, slots) in mapping {
+        let address =
+            Address::from_str(&address).map_err(|e| format!("invalid address {address}: {e}"))?;
+        for (slot, label) in slots {
+            let (label, description) = match label {
+                SlotLabel::Label(label) => (label, None),
+                SlotLabel::Detailed { label, description } => (label, description),
+            };
+            annotations.push(SlotAnnotation {
+                chain,
+                address: address.clone(),
+                slot: parse_slot(&slot)?,
+                label,
+                description,
+            });
+        }
+    }
+    annotations.sort_by(|a, b| (&a.address, &a.slot).cmp(&(&b.address, &b.slot)));
+    Ok(annotations)
+}
+
+fn parse_slot(slot: &str) -> Result<Bytes, String> {
+    let digits = slot.strip_prefix("0x").unwrap_or(slot);
+    let padded = if digits.len() % 2 == 1 { format!("0{digits}") } else { digits.to_string() };
+    let bytes = hex::decode(&padded).map_err(|e| format!("invalid slot {slot}: {e}"))?;
+    if bytes.len() > 32 {
+        return Err(format!("invalid slot {slot}: longer than 32 bytes"));
+    }
+    Ok(Bytes::from(bytes).lpad(32, 0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_slot_annotations() {
+        let contents = r#"{
+            "0xba12222222228d8ba445958a75a0704d566bf2c8": {
+                "0x3": { "label": "fee", "description": "Swap fee with 18 decimals" },
+                "0x0": "owner"
+            }
+        }"#;
+
+        let annotations = parse_slot_annotations(Chain::Ethereum, contents).unwrap();
+
+        let address = Address::from_str("0xba12222222228d8ba445958a75a0704d566bf2c8").unwrap();
+        assert_eq!(
+            annotations,
+            vec![
+                SlotAnnotation {
+                    chain: Chain::Ethereum,
+                    address: address.clone(),
+                    slot: Bytes::zero(32),
+                    label: "owner".to_string(),
+                    description: None,
+                },
+                SlotAnnotation {
+                    chain: Chain::Ethereum,
+                    address,
+                    slot: Bytes::from(vec![3u8]).lpad(32, 0),
+                    label: "fee".to_string(),
+                    description: Some("Swap fee with 18 decimals".to_string()),
+                },
+            ]
+        );
+        assert!(parse_slot_annotations(Chain::Ethereum, r#"{"0x01": {"0xzz": "owner"}}"#).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use std::{
     pin::Pin,
+    str::FromStr,
     sync::Arc,
     task::{Context, Poll},
     time::{Duration, SystemTime, UNIX_EPOCH},
@@ -14,11 +15,12 @@ use prost::Message as ProstMessage;
 use tokio::time::sleep;
 use tokio_retry::strategy::ExponentialBackoff;
 use tracing::{error, info, trace, warn};
+use tycho_common::{models::blockchain::Block, Bytes};
 
 use crate::{
     pb::sf::substreams::{
         rpc::v2::{response::Message, BlockScopedData, BlockUndoSignal, Request, Response},
-        v1::Modules,
+        v1::{Clock, Modules},
     },
     substreams::SubstreamsEndpoint,
 };
@@ -34,10 +36,15 @@ pub struct SubstreamsStream {
 }
 
 impl SubstreamsStream {
+    /// Streams from the first of `endpoints`, failing over to the next one on persistent errors.
+    ///
+    /// `last_block` is the last block stored by the consumer of `cursor`. Should the provider
+    /// invalidate the cursor, the stream restarts from this block with a fresh cursor.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        endpoint: Arc<SubstreamsEndpoint>,
+        endpoints: Vec<Arc<SubstreamsEndpoint>>,
         cursor: Option<String>,
+        last_block: Option<Block>,
         modules: Option<Modules>,
         output_module_name: String,
         start_block: i64,
@@ -47,8 +54,9 @@ impl SubstreamsStream {
     ) -> Self {
         SubstreamsStream {
             stream: Box::pin(stream_blocks(
-                endpoint,
+                Providers::new(endpoints),
                 cursor,
+                last_block.map(|block| (block.number, block.hash)),
                 modules,
                 output_module_name,
                 start_block,
@@ -79,11 +87,111 @@ async fn wait_for_next_retry(
     }
 }
 
+/// Consecutive failed connection attempts after which the stream fails over to the next
+/// endpoint, if there is one.
+const FAILOVER_AFTER_FAILURES: u32 = 5;
+
+/// Ordered substreams endpoints of a stream and the blocks served by the active one.
+///
+/// Every range of blocks served by an endpoint is logged and counted per provider once the stream
+/// moves on, so it can be audited which provider served which blocks.
+struct Providers {
+    endpoints: Vec<Arc<SubstreamsEndpoint>>,
+    active: usize,
+    /// First and last block served by the active endpoint.
+    served: Option<(u64, u64)>,
+    /// Failovers since the last served block.
+    failovers: usize,
+}
+
+impl Providers {
+    fn new(endpoints: Vec<Arc<SubstreamsEndpoint>>) -> Self {
+        assert!(!endpoints.is_empty(), "at least one substreams endpoint is required");
+        Self { endpoints, active: 0, served: None, failovers: 0 }
+    }
+
+    fn active(&self) -> Arc<SubstreamsEndpoint> {
+        self.endpoints[self.active].clone()
+    }
+
+    fn record_block(&mut self, number: u64, extractor_id: &str) {
+        let first = self
+            .served
+            .map_or(number, |(first, _)| first);
+        self.served = Some((first, number));
+        self.failovers = 0;
+        counter!(
+            "substreams_blocks_served",
+            "extractor" => extractor_id.to_string(),
+            "provider" => self.endpoints[self.active].uri.clone()
+        )
+        .increment(1);
+    }
+
+    /// Whether another endpoint can be tried before every endpoint failed in a row.
+    fn can_fail_over(&self) -> bool {
+        self.failovers + 1 < self.endpoints.len()
+    }
+
+    /// Switches to the next endpoint, returning the provider and block range served by the
+    /// previous one.
+    fn fail_over(&mut self, extractor_id: &str) -> Option<(String, u64, u64)> {
+        let served = self.finish_range(extractor_id);
+        let previous = self.active();
+        self.active = (self.active + 1) % self.endpoints.len();
+        self.failovers += 1;
+        counter!(
+            "substreams_failover",
+            "extractor" => extractor_id.to_string(),
+            "provider" => previous.uri.clone()
+        )
+        .increment(1);
+        warn!(from = %previous, to = %self.active(), "Failing over to next substreams endpoint");
+        served
+    }
+
+    /// Logs the range served by the active endpoint, if any.
+    fn finish_range(&mut self, extractor_id: &str) -> Option<(String, u64, u64)> {
+        let (first_block, last_block) = self.served.take()?;
+        let provider = self.endpoints[self.active].uri.clone();
+        info!(extractor_id, %provider, first_block, last_block, "SubstreamsProviderRange");
+        Some((provider, first_block, last_block))
+    }
+}
+
+/// Whether the provider rejected the start cursor, e.g. because the modules changed since it was
+/// issued. Retrying with the same cursor fails forever.
+fn is_cursor_invalidated(status: &tonic::Status) -> bool {
+    matches!(status.code(), tonic::Code::InvalidArgument | tonic::Code::FailedPrecondition) &&
+        status
+            .message()
+            .to_lowercase()
+            .contains("cursor")
+}
+
+/// Checks that the first block served after a cursor reset is the block the stream restarted
+/// from. Blocks following it are then children of the last stored block.
+fn verify_restart(anchor: &(u64, Bytes), clock: Option<&Clock>) -> Result<(), Error> {
+    let (number, hash) = anchor;
+    let clock = clock.ok_or_else(|| anyhow!("Block without clock after cursor reset"))?;
+    let served_hash = Bytes::from_str(&clock.id)
+        .map_err(|err| anyhow!("Invalid block hash {} after cursor reset: {err}", clock.id))?;
+    if clock.number != *number || &served_hash != hash {
+        return Err(anyhow!(
+            "Chain discontinuity after cursor reset: expected block {number} ({hash}), got block \
+             {} ({served_hash})",
+            clock.number
+        ));
+    }
+    Ok(())
+}
+
 // Create the Stream implementation that streams blocks with auto-reconnection.
 #[allow(clippy::too_many_arguments)]
 fn stream_blocks(
-    endpoint: Arc<SubstreamsEndpoint>,
+    mut providers: Providers,
     cursor: Option<String>,
+    last_block: Option<(u64, Bytes)>,
     modules: Option<Modules>,
     output_module_name: String,
     start_block_num: i64,
@@ -93,7 +201,12 @@ fn stream_blocks(
 ) -> impl Stream<Item = Result<BlockResponse, Error>> {
     let mut latest_cursor = cursor.unwrap_or_default();
     let mut latest_block = start_block_num as u64;
+    // Number and hash of the last block served, the restart point should the cursor be invalidated
+    let mut latest_served = last_block;
+    // Block the stream restarted from after a cursor reset, until it's served again
+    let mut restart_anchor: Option<(u64, Bytes)> = None;
     let mut retry_count = 0;
+    let mut failures = 0;
     let mut backoff = DEFAULT_BACKOFF.clone();
 
     try_stream! {
@@ -102,8 +215,10 @@ fn stream_blocks(
                 warn!("Blockstreams disconnected, connecting again");
             }
 
-            let result = endpoint.clone().substreams(Request {
-                start_block_num,
+            let result = providers.active().substreams(Request {
+                start_block_num: restart_anchor
+                    .as_ref()
+                    .map_or(start_block_num, |(number, _)| *number as i64),
                 start_cursor: latest_cursor.clone(),
                 stop_block_num,
                 final_blocks_only,
@@ -123,6 +238,14 @@ fn stream_blocks(
                     for await response in stream {
                         match process_substreams_response(response).await {
                             BlockProcessedResult::BlockScopedData(block_scoped_data) => {
+                                if let Some(anchor) = restart_anchor.take() {
+                                    verify_restart(&anchor, block_scoped_data.clock.as_ref())?;
+                                    // The restart block was already processed, skip it
+                                    warn!(%extractor_id, block_number = anchor.0, "Cursor reset verified, stream continues");
+                                    latest_cursor = block_scoped_data.cursor.clone();
+                                    continue;
+                                }
+
                                 if let Some(block) = block_scoped_data.clock.clone() {
                                     if let Some(block_ts) = block.timestamp {
                                         let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards!?").as_millis();
@@ -130,12 +253,17 @@ fn stream_blocks(
                                         gauge!("substreams_lag_millis", "extractor" => extractor_id.clone()).set(lag as f64);
                                     }
                                     latest_block = block.number;
+                                    latest_served = Bytes::from_str(&block.id)
+                                        .ok()
+                                        .map(|hash| (block.number, hash));
+                                    providers.record_block(block.number, &extractor_id);
                                 };
 
                                 gauge!("block_message_size_bytes", "extractor" => extractor_id.clone()).set(block_scoped_data.encoded_len() as f64);
 
                                 // Reset backoff because we got a good value from the stream
                                 backoff = DEFAULT_BACKOFF.clone();
+                                failures = 0;
 
                                 let cursor = block_scoped_data.cursor.clone();
                                 yield BlockResponse::New(block_scoped_data);
@@ -145,8 +273,14 @@ fn stream_blocks(
                             BlockProcessedResult::BlockUndoSignal(block_undo_signal) => {
                                 // Reset backoff because we got a good value from the stream
                                 backoff = DEFAULT_BACKOFF.clone();
+                                failures = 0;
 
                                 let to_block = block_undo_signal.last_valid_block.clone().unwrap_or_default().number;
+                                if let Some(valid) = &block_undo_signal.last_valid_block {
+                                    latest_served = Bytes::from_str(&valid.id)
+                                        .ok()
+                                        .map(|hash| (valid.number, hash));
+                                }
                                 counter!(
                                     "chain_reorg",
                                     "extractor" => extractor_id.clone(),
@@ -162,16 +296,45 @@ fn stream_blocks(
                             },
                             BlockProcessedResult::Skip() => {},
                             BlockProcessedResult::TonicError(status) => {
-                                // Unauthenticated errors are not retried, we forward the error back to the
-                                // stream consumer which handles it
+                                // A rejected cursor would be rejected again on every retry. Restart
+                                // from the last served block with a fresh cursor instead.
+                                if is_cursor_invalidated(&status) && !latest_cursor.is_empty() {
+                                    counter!("substreams_cursor_reset", "extractor" => extractor_id.clone()).increment(1);
+                                    error!(
+                                        %extractor_id,
+                                        restart_block = latest_served.as_ref().map(|(number, _)| *number),
+                                        "Substreams cursor invalidated by the provider, RESETTING CURSOR: {:#}",
+                                        status
+                                    );
+                                    latest_cursor.clear();
+                                    restart_anchor = latest_served.clone();
+                                    continue 'retry_loop;
+                                }
+
+                                // Unauthenticated errors are not retried on the same endpoint. Unless
+                                // another endpoint is left, we forward the error back to the stream
+                                // consumer which handles it
                                 if status.code() == tonic::Code::Unauthenticated {
                                     counter!("substreams_failure", "extractor" => extractor_id.clone(), "cause" => "unauthenticated").increment(1);
-                                    return Err(anyhow::Error::new(status.clone()))?;
+                                    if !providers.can_fail_over() {
+                                        return Err(anyhow::Error::new(status.clone()))?;
+                                    }
+                                    providers.fail_over(&extractor_id);
+                                    failures = 0;
+                                    continue 'retry_loop;
                                 }
 
                                 error!("Received tonic error {:#}", status);
                                 counter!("substreams_failure", "extractor" => extractor_id.clone(), "cause" => status.code().to_string()).increment(1);
 
+                                failures += 1;
+                                if failures >= FAILOVER_AFTER_FAILURES && providers.endpoints.len() > 1 {
+                                    providers.fail_over(&extractor_id);
+                                    failures = 0;
+                                    backoff = DEFAULT_BACKOFF.clone();
+                                    continue 'retry_loop;
+                                }
+
                                 // If we reach this point, we must wait a bit before retrying
                                 wait_for_next_retry(&mut backoff, &mut retry_count, &extractor_id).await?;
                                 continue 'retry_loop;
@@ -180,12 +343,21 @@ fn stream_blocks(
                     }
 
                     info!("Stream completed, reached end block");
+                    providers.finish_range(&extractor_id);
                     return;
                 },
                 Err(e) => {
                     counter!("substreams_failure", "module" => output_module_name.clone(), "cause" => "connection_error").increment(1);
                     error!("Unable to connect to endpoint: {:#}", e);
 
+                    failures += 1;
+                    if failures >= FAILOVER_AFTER_FAILURES && providers.endpoints.len() > 1 {
+                        providers.fail_over(&extractor_id);
+                        failures = 0;
+                        backoff = DEFAULT_BACKOFF.clone();
+                        continue 'retry_loop;
+                    }
+
                     // If we reach this point, we must wait a bit before retrying
                     wait_for_next_retry(&mut backoff, &mut retry_count, &extractor_id).await?;
                 }
@@ -280,3 +452,59 @@ impl Stream for SubstreamsStream {
         self.stream.poll_next_unpin(cx)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_providers_fail_over() {
+        let mut endpoints = Vec::new();
+        for url in ["http://first:443", "http://second:443"] {
+            endpoints.push(Arc::new(
+                SubstreamsEndpoint::new(url, None)
+                    .await
+                    .unwrap(),
+            ));
+        }
+        let first_uri = endpoints[0].uri.clone();
+        let mut providers = Providers::new(endpoints);
+
+        providers.record_block(10, "test");
+        providers.record_block(11, "test");
+        let first = providers.fail_over("test");
+        let can_fail_over_again = providers.can_fail_over();
+        let second = providers.fail_over("test");
+        providers.record_block(12, "test");
+
+        assert_eq!(first, Some((first_uri.clone(), 10, 11)));
+        assert!(!can_fail_over_again);
+        assert_eq!(second, None);
+        assert_eq!(providers.active().uri, first_uri);
+        assert!(providers.can_fail_over());
+    }
+
+    #[test]
+    fn test_is_cursor_invalidated() {
+        let invalid =
+            tonic::Status::invalid_argument("invalid start cursor \"abc\": module changed");
+        let other = tonic::Status::invalid_argument("unknown output module");
+        let unavailable = tonic::Status::unavailable("cursor backend unavailable");
+
+        assert!(is_cursor_invalidated(&invalid));
+        assert!(!is_cursor_invalidated(&other));
+        assert!(!is_cursor_invalidated(&unavailable));
+    }
+
+    #[test]
+    fn test_verify_restart() {
+        let hash = "0x0000000000000000000000000000000000000000000000000000000000000001";
+        let anchor = (10, Bytes::from_str(hash).unwrap());
+        let clock = |number, id: &str| Clock { id: id.to_string(), number, timestamp: None };
+
+        assert!(verify_restart(&anchor, Some(&clock(10, hash))).is_ok());
+        assert!(verify_restart(&anchor, Some(&clock(10, &hash.replace('1', "2")))).is_err());
+        assert!(verify_restart(&anchor, Some(&clock(11, hash))).is_err());
+        assert!(verify_restart(&anchor, None).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::RangeInclusive,
     time::Duration,
 };
 
@@ -9,22 +10,26 @@ use mockall::mock;
 use tycho_common::{
     models::{
         blockchain::{
-            Block, EntryPoint, EntryPointWithTracingParams, TracedEntryPoint, TracingParams,
-            TracingResult, Transaction,
+            Block, BlockDigest, EntryPoint, EntryPointWithTracingParams, FinalityStatus,
+            TracedEntryPoint, TracingParams, TracingResult, Transaction,
         },
-        contract::{Account, AccountBalance, AccountDelta},
+        contract::{Account, AccountBalance, AccountDelta, SlotAnnotation},
         protocol::{
-            ComponentBalance, ProtocolComponent, ProtocolComponentState,
-            ProtocolComponentStateDelta, QualityRange,
+            BalanceSample, ComponentBalance, ComponentEvent, ComponentLatestBalance,
+            ComponentLatestState, ProtocolComponent, ProtocolComponentState,
+            ProtocolComponentStateDelta, ProtocolComponentWithContracts, ProtocolDeployment,
+            ProtocolStatesDelta, ProtocolSystemSummary, QualityRange, SampleInterval,
         },
-        token::Token,
-        Address, Chain, ComponentId, ContractId, EntryPointId, ExtractionState, PaginationParams,
-        ProtocolType, TxHash,
+        token::{Token, TokenOverride},
+        Address, AttrStoreKey, Balance, Chain, Code, CodeHash, ComponentCursor, ComponentId,
+        ContractId, EntryPointId, ExtractionState, PaginationParams, ProtocolType, StoreVal,
+        TxHash,
     },
     storage::{
-        BlockIdentifier, BlockOrTimestamp, ChainGateway, ContractStateGateway, EntryPointFilter,
-        EntryPointGateway, ExtractionStateGateway, Gateway, ProtocolGateway, StorageError, Version,
-        WithTotal,
+        AdminAction, AnalyticsRows, ApiKeyUsage, AuditEntry, BlockIdentifier, BlockOrTimestamp,
+        ChainGateway, ContractFilter, ContractStateGateway, EntryPointFilter, EntryPointGateway,
+        ExtractionStateGateway, Gateway, IdempotencyClaim, IdempotentResponse, ProtocolGateway,
+        StorageError, Version, WithTotal,
     },
     Bytes,
 };
@@ -35,15 +40,83 @@ mock! {
     impl ExtractionStateGateway for Gateway {
         async fn get_state(&self, name: &str, chain: &Chain) -> Result<ExtractionState, StorageError>;
         async fn save_state(&self, state: &ExtractionState) -> Result<(), StorageError>;
+        async fn get_extractor_block_numbers(
+            &self,
+            chain: &Chain,
+        ) -> Result<HashMap<String, u64>, StorageError>;
     }
 
     #[async_trait]
     impl ChainGateway for Gateway {
         async fn upsert_block(&self, new: &[Block]) -> Result<(), StorageError>;
         async fn get_block(&self, id: &BlockIdentifier) -> Result<Block, StorageError>;
+        async fn get_finalized_block(&self, chain: &Chain) -> Result<Block, StorageError>;
+        async fn get_blocks_at(
+            &self,
+            chains: &[Chain],
+            ts: NaiveDateTime,
+        ) -> Result<HashMap<Chain, Block>, StorageError>;
+        async fn update_block_finality(
+            &self,
+            chain: &Chain,
+            status: FinalityStatus,
+            up_to: i64,
+        ) -> Result<(), StorageError>;
+        async fn upsert_block_digests(&self, new: &[BlockDigest]) -> Result<(), StorageError>;
+        async fn get_block_digests(
+            &self,
+            chain: &Chain,
+            start_block: i64,
+            end_block: i64,
+        ) -> Result<Vec<BlockDigest>, StorageError>;
         async fn upsert_tx(&self, new: &[Transaction]) -> Result<(), StorageError>;
         async fn get_tx(&self, hash: &TxHash) -> Result<Transaction, StorageError>;
         async fn revert_state(&self, to: &BlockIdentifier) -> Result<(), StorageError>;
+        async fn quarantine_range(
+            &self,
+            chain: &Chain,
+            protocol_system: &str,
+            start_block: i64,
+            end_block: i64,
+        ) -> Result<i64, StorageError>;
+        async fn restore_quarantined_range(&self, repair_id: i64) -> Result<(), StorageError>;
+        async fn verify_range_continuity(&self, repair_id: i64) -> Result<(), StorageError>;
+        async fn run_analytics_query(
+            &self,
+            query: &str,
+            row_limit: usize,
+            timeout: Duration,
+        ) -> Result<AnalyticsRows, StorageError>;
+        async fn record_admin_action(&self, action: &AdminAction) -> Result<(), StorageError>;
+        async fn get_audit_log(
+            &self,
+            operation: Option<&str>,
+            since: Option<NaiveDateTime>,
+            pagination_params: Option<&PaginationParams>,
+        ) -> Result<WithTotal<Vec<AuditEntry>>, StorageError>;
+        async fn claim_idempotency_key(
+            &self,
+            api_key: &str,
+            key: &str,
+            action: &str,
+        ) -> Result<IdempotencyClaim, StorageError>;
+        async fn complete_idempotency_key(
+            &self,
+            api_key: &str,
+            key: &str,
+            response: &IdempotentResponse,
+        ) -> Result<(), StorageError>;
+        async fn release_idempotency_key(
+            &self,
+            api_key: &str,
+            key: &str,
+        ) -> Result<(), StorageError>;
+        async fn add_api_key_usage(
+            &self,
+            month: &str,
+            usage: &[ApiKeyUsage],
+        ) -> Result<(), StorageError>;
+        async fn get_api_key_usage(&self, month: &str) -> Result<Vec<ApiKeyUsage>, StorageError>;
     }
 
     impl EntryPointGateway for Gateway {
@@ -181,13 +254,14 @@ mock! {
             Self: 'async_trait;
 
         #[allow(clippy::type_complexity)]
-        fn get_contracts<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+        fn get_contracts<'life0, 'life1, 'life2, 'life3, 'life4, 'life5, 'async_trait>(
             &'life0 self,
             chain: &'life1 Chain,
             addresses: Option<&'life2 [Address]>,
             version: Option<&'life3 Version>,
             include_slots: bool,
-            pagination_params: Option<&'life4 PaginationParams>,
+            filter: Option<&'life4 ContractFilter>,
+            pagination_params: Option<&'life5 PaginationParams>,
         ) -> ::core::pin::Pin<
             Box<
                 dyn ::core::future::Future<
@@ -201,6 +275,24 @@ mock! {
             'life2: 'async_trait,
             'life3: 'async_trait,
             'life4: 'async_trait,
+            'life5: 'async_trait,
+            Self: 'async_trait;
+
+        fn get_code_by_hash<'life0, 'life1, 'life2, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            code_hash: &'life2 CodeHash,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Code, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
             Self: 'async_trait;
 
         fn insert_contract<'life0, 'life1, 'async_trait>(
@@ -305,6 +397,58 @@ mock! {
             'life3: 'async_trait,
             Self: 'async_trait;
 
+        #[allow(clippy::type_complexity)]
+        fn get_tracked_addresses<'life0, 'life1, 'life2, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            addresses: &'life2 [Address],
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<Output = Result<HashSet<Address>, StorageError>>
+                    + ::core::marker::Send
+                    + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_slot_annotations<'life0, 'life1, 'life2, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            addresses: &'life2 [Address],
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<Output = Result<Vec<SlotAnnotation>, StorageError>>
+                    + ::core::marker::Send
+                    + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn upsert_slot_annotations<'life0, 'life1, 'async_trait>(
+            &'life0 self,
+            annotations: &'life1 [SlotAnnotation],
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<Output = Result<(), StorageError>>
+                    + ::core::marker::Send
+                    + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            Self: 'async_trait;
+
     }
 
     impl ProtocolGateway for Gateway {
@@ -333,6 +477,53 @@ mock! {
             'life4: 'async_trait,
             Self: 'async_trait;
 
+        #[allow(clippy::type_complexity)]
+        fn get_protocol_components_after<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            system: Option<String>,
+            ids: Option<&'life2 [&'life3 str]>,
+            min_tvl: Option<f64>,
+            cursor: &'life4 ComponentCursor,
+            page_size: i64,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<WithTotal<Vec<ProtocolComponent>>,
+                        StorageError,
+                    >,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            'life4: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_components_with_contracts<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            ids: &'life2 [&'life3 str],
+            version: Option<&'life4 Version>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Vec<ProtocolComponentWithContracts>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            'life4: 'async_trait,
+            Self: 'async_trait;
+
         #[allow(clippy::type_complexity)]
         fn get_token_owners<'life0, 'life1, 'life2, 'async_trait>(
             &'life0 self,
@@ -513,14 +704,11 @@ mock! {
             &'life0 self,
             chain: &'life1 Chain,
             start_version: Option<&'life2 BlockOrTimestamp>,
-            end_version: &'life3 BlockOrTimestamp,
+            end_version: Option<&'life3 BlockOrTimestamp>,
         ) -> ::core::pin::Pin<
             Box<
                 dyn ::core::future::Future<
-                    Output = Result<
-                        Vec<ProtocolComponentStateDelta>,
-                        StorageError,
-                    >,
+                    Output = Result<ProtocolStatesDelta, StorageError>,
                 > + ::core::marker::Send + 'async_trait,
             >,
         >
@@ -574,6 +762,200 @@ mock! {
             'life4: 'async_trait,
             Self: 'async_trait;
 
+        #[allow(clippy::type_complexity)]
+        fn get_balance_history<'life0, 'life1, 'life2, 'life3, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            component_id: &'life2 str,
+            token: &'life3 Address,
+            from: NaiveDateTime,
+            to: NaiveDateTime,
+            interval: SampleInterval,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Vec<BalanceSample>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            Self: 'async_trait;
+
+        fn add_component_events<'life0, 'life1, 'async_trait>(
+            &'life0 self,
+            events: &'life1 [ComponentEvent],
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<(), StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_component_events<'life0, 'life1, 'life2, 'life3, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            ids: &'life2 [&'life3 str],
+            blocks: RangeInclusive<i64>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Vec<ComponentEvent>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_inactive_components<'life0, 'life1, 'life2, 'life3, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            ids: &'life2 [&'life3 str],
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<HashMap<ComponentId, NaiveDateTime>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_component_extractors<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            ids: Option<&'life2 [&'life3 str]>,
+            extractor: Option<&'life4 str>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<HashMap<ComponentId, String>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            'life4: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_component_ids_by_tokens<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            tokens: &'life2 [Address],
+            ids: Option<&'life3 [&'life4 str]>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<Output = Result<Vec<ComponentId>, StorageError>>
+                    + ::core::marker::Send
+                    + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            'life4: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_attribute_range<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            component_id: &'life2 str,
+            prefix: &'life3 str,
+            range: RangeInclusive<i64>,
+            at: Option<&'life4 Version>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<BTreeMap<i64, HashMap<AttrStoreKey, StoreVal>>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            'life4: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn rename_protocol_state_attribute<
+            'life0,
+            'life1,
+            'life2,
+            'life3,
+            'life4,
+            'life5,
+            'life6,
+            'async_trait,
+        >(
+            &'life0 self,
+            chain: &'life1 Chain,
+            protocol_system: &'life2 str,
+            component_ids: Option<&'life3 [&'life4 str]>,
+            old_name: &'life5 str,
+            new_name: &'life6 str,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<i64, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            'life4: 'async_trait,
+            'life5: 'async_trait,
+            'life6: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_protocol_deployment<'life0, 'life1, 'life2, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            protocol_system: &'life2 str,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<ProtocolDeployment, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            Self: 'async_trait;
+
         #[allow(clippy::type_complexity)]
         fn get_token_prices<'life0, 'life1, 'async_trait>(
             &'life0 self,
@@ -590,6 +972,112 @@ mock! {
             'life1: 'async_trait,
             Self: 'async_trait;
 
+        #[allow(clippy::type_complexity)]
+        fn get_protocol_data_modified_at<'life0, 'life1, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Option<NaiveDateTime>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_token_overrides<'life0, 'life1, 'life2, 'life3, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            addresses: Option<&'life2 [&'life3 Address]>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Vec<TokenOverride>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            Self: 'async_trait;
+
+        fn upsert_token_overrides<'life0, 'life1, 'async_trait>(
+            &'life0 self,
+            overrides: &'life1 [TokenOverride],
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<(), StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            Self: 'async_trait;
+
+        fn delete_token_overrides<'life0, 'life1, 'life2, 'life3, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            addresses: &'life2 [&'life3 Address],
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<(), StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            Self: 'async_trait;
+
+        fn add_token_supplies<'life0, 'life1, 'life2, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            supplies: &'life2 HashMap<Address, Balance>,
+            ts: NaiveDateTime,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<(), StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            Self: 'async_trait;
+
+        #[allow(clippy::type_complexity)]
+        fn get_token_supplies<'life0, 'life1, 'life2, 'life3, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            tokens: &'life2 [Address],
+            at: Option<&'life3 Version>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<HashMap<Address, Balance>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            Self: 'async_trait;
+
         fn upsert_component_tvl<'life0, 'life1, 'life2, 'async_trait>(
             &'life0 self,
             chain: &'life1 Chain,
@@ -625,6 +1113,22 @@ mock! {
             'life2: 'async_trait,
             Self: 'async_trait;
 
+        #[allow(clippy::type_complexity)]
+        fn get_protocol_system_summaries<'life0, 'life1, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Vec<ProtocolSystemSummary>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            Self: 'async_trait;
+
         #[allow(clippy::type_complexity)]
         fn get_component_tvls<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
             &'life0 self,
@@ -646,6 +1150,46 @@ mock! {
             'life3: 'async_trait,
             'life4: 'async_trait,
             Self: 'async_trait;
+
+        fn get_component_latest_states<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            system: Option<&'life2 str>,
+            ids: Option<&'life3 [&'life4 str]>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Vec<ComponentLatestState>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            'life4: 'async_trait,
+            Self: 'async_trait;
+
+        fn get_component_latest_balances<'life0, 'life1, 'life2, 'life3, 'life4, 'async_trait>(
+            &'life0 self,
+            chain: &'life1 Chain,
+            system: Option<&'life2 str>,
+            ids: Option<&'life3 [&'life4 str]>,
+        ) -> ::core::pin::Pin<
+            Box<
+                dyn ::core::future::Future<
+                    Output = Result<Vec<ComponentLatestBalance>, StorageError>,
+                > + ::core::marker::Send + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+            'life1: 'async_trait,
+            'life2: 'async_trait,
+            'life3: 'async_trait,
+            'life4: 'async_trait,
+            Self: 'async_trait;
     }
 
     impl Gateway for Gateway {}
@@ -0,0 +1,396 @@
+//! Measures write throughput and versioned read latency of the Postgres gateway.
+//!
+//! The write benchmarks upsert batches of contract slots, protocol state attributes and component
+//! balances, each batch in a new block so previous versions get closed like during indexing. The
+//! read benchmarks query contracts and protocol states at the latest and at a historical version
+//! of a seeded dataset.
+//!
+//! Requires a database with all migrations applied, see the README. Everything runs inside test
+//! transactions that are rolled back, so the database is left untouched. To run:
+//! `DATABASE_URL=postgres://... cargo bench -p tycho-storage --features bench`
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use chrono::NaiveDateTime;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use tokio::runtime::Runtime;
+use tycho_common::{
+    models::{
+        blockchain::{Block, Transaction},
+        contract::AccountDelta,
+        protocol::{ComponentBalance, ProtocolComponentStateDelta},
+        Address, Chain, ChangeType, TxHash,
+    },
+    storage::{BlockIdentifier, BlockOrTimestamp, Version, VersionKind},
+    Bytes,
+};
+use tycho_storage::postgres::{bench::BenchGateway, db_fixtures};
+
+const CHAIN: Chain = Chain::Ethereum;
+const N_CONTRACTS: usize = 100;
+const N_COMPONENTS: usize = 1_000;
+const N_TOKENS: usize = 10;
+/// Number of entities written per batch, e.g. slots summed over all contracts.
+const BATCH_SIZES: [usize; 3] = [100, 1_000, 10_000];
+/// Blocks of the read dataset, every block updates the same entities.
+const SEED_BLOCKS: u64 = 50;
+const SEED_BATCH_SIZE: usize = 1_000;
+
+/// 32 bytes with `prefix` as the first and `seed` in the last 8 bytes, so values of different
+/// kinds never collide.
+fn word(prefix: u8, seed: u64) -> Bytes {
+    let mut word = [0u8; 32];
+    word[0] = prefix;
+    word[24..].copy_from_slice(&seed.to_be_bytes());
+    Bytes::from(word.to_vec())
+}
+
+#[derive(Clone, Copy)]
+enum Entity {
+    Slots,
+    States,
+    Balances,
+}
+
+impl Entity {
+    fn name(&self) -> &'static str {
+        match self {
+            Entity::Slots => "slots",
+            Entity::States => "protocol_states",
+            Entity::Balances => "component_balances",
+        }
+    }
+}
+
+struct Fixture {
+    gw: BenchGateway,
+    conn: AsyncPgConnection,
+    contracts: Vec<Address>,
+    components: Vec<String>,
+    tokens: Vec<Address>,
+    block_number: u64,
+    base_ts: NaiveDateTime,
+}
+
+impl Fixture {
+    /// Opens a test transaction and inserts the chain, tokens, contracts and components all
+    /// benchmarks write to.
+    async fn setup() -> Self {
+        let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .expect("Failed to connect to the database");
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+
+        let chain_id = db_fixtures::insert_chain(&mut conn, &CHAIN.to_string()).await;
+        db_fixtures::insert_token(
+            &mut conn,
+            chain_id,
+            "0000000000000000000000000000000000000000",
+            "ETH",
+            18,
+            Some(100),
+        )
+        .await;
+        let blocks = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let genesis_tx =
+            db_fixtures::insert_txns(&mut conn, &[(blocks[1], 0, &word(0xee, 0).to_string())])
+                .await[0];
+
+        let mut tokens = Vec::with_capacity(N_TOKENS);
+        let mut token_ids = Vec::with_capacity(N_TOKENS);
+        for idx in 0..N_TOKENS {
+            let address = Bytes::from(word(0xaa, idx as u64)[12..].to_vec());
+            let (_, token_id) = db_fixtures::insert_token(
+                &mut conn,
+                chain_id,
+                &hex::encode(&address),
+                &format!("TKN{idx}"),
+                18,
+                Some(100),
+            )
+            .await;
+            tokens.push(address);
+            token_ids.push(token_id);
+        }
+
+        let mut contracts = Vec::with_capacity(N_CONTRACTS);
+        for idx in 0..N_CONTRACTS {
+            let address = Bytes::from(word(0xcc, idx as u64)[12..].to_vec());
+            db_fixtures::insert_account(
+                &mut conn,
+                &hex::encode(&address),
+                &format!("contract_{idx}"),
+                chain_id,
+                Some(genesis_tx),
+            )
+            .await;
+            contracts.push(address);
+        }
+
+        let system_id =
+            db_fixtures::insert_protocol_system(&mut conn, "bench_protocol".to_string()).await;
+        let type_id =
+            db_fixtures::insert_protocol_type(&mut conn, "bench_pool", None, None, None).await;
+        let mut components = Vec::with_capacity(N_COMPONENTS);
+        for idx in 0..N_COMPONENTS {
+            let id = format!("component_{idx}");
+            db_fixtures::insert_protocol_component(
+                &mut conn,
+                &id,
+                chain_id,
+                system_id,
+                type_id,
+                genesis_tx,
+                Some(token_ids.clone()),
+                None,
+            )
+            .await;
+            components.push(id);
+        }
+
+        let gw = BenchGateway::from_connection(&mut conn).await;
+        let mut fixture = Fixture {
+            gw,
+            conn,
+            contracts,
+            components,
+            tokens,
+            block_number: 2,
+            base_ts: db_fixtures::yesterday_one_am(),
+        };
+
+        // Contracts are only returned by reads once they have code and a balance.
+        let tx = fixture.next_tx().await;
+        let deltas = fixture
+            .contracts
+            .iter()
+            .map(|address| {
+                AccountDelta::new(
+                    CHAIN,
+                    address.clone(),
+                    HashMap::new(),
+                    Some(word(0x03, 0)),
+                    Some(Bytes::from("0x6000")),
+                    ChangeType::Creation,
+                )
+            })
+            .collect::<Vec<_>>();
+        let new = deltas
+            .iter()
+            .map(|delta| (tx.clone(), delta))
+            .collect::<Vec<_>>();
+        fixture
+            .gw
+            .update_contracts(&CHAIN, &new, &mut fixture.conn)
+            .await
+            .unwrap();
+        fixture
+    }
+
+    /// Inserts the next block with a single transaction and returns the transaction's hash.
+    async fn next_tx(&mut self) -> TxHash {
+        self.block_number += 1;
+        let block = Block::new(
+            self.block_number,
+            CHAIN,
+            word(0xbb, self.block_number),
+            word(0xbb, self.block_number - 1),
+            self.base_ts + chrono::Duration::seconds(self.block_number as i64),
+        );
+        let tx = Transaction::new(
+            word(0xee, self.block_number),
+            block.hash.clone(),
+            Bytes::from(word(0xff, 0)[12..].to_vec()),
+            None,
+            0,
+        );
+        self.gw
+            .upsert_block(&[block], &mut self.conn)
+            .await
+            .unwrap();
+        self.gw
+            .upsert_tx(std::slice::from_ref(&tx), &mut self.conn)
+            .await
+            .unwrap();
+        tx.hash
+    }
+
+    /// Slot updates spread evenly over all contracts.
+    fn account_deltas(&self, batch_size: usize) -> Vec<AccountDelta> {
+        let per_contract = batch_size.div_ceil(N_CONTRACTS);
+        self.contracts
+            .iter()
+            .map(|address| {
+                let slots = (0..per_contract)
+                    .map(|slot| (word(0x01, slot as u64), Some(word(0x02, self.block_number))))
+                    .collect();
+                AccountDelta::new(CHAIN, address.clone(), slots, None, None, ChangeType::Update)
+            })
+            .collect()
+    }
+
+    /// Attribute updates spread evenly over all components.
+    fn state_deltas(&self, batch_size: usize) -> Vec<ProtocolComponentStateDelta> {
+        let per_component = batch_size.div_ceil(N_COMPONENTS);
+        self.components
+            .iter()
+            .map(|id| {
+                let attributes = (0..per_component)
+                    .map(|attr| (format!("attr_{attr}"), word(0x04, self.block_number)))
+                    .collect::<HashMap<_, _>>();
+                ProtocolComponentStateDelta::new(id, attributes, HashSet::new())
+            })
+            .collect()
+    }
+
+    /// One balance per component and token pair, up to `batch_size` of them.
+    fn balances(&self, batch_size: usize, tx: &TxHash) -> Vec<ComponentBalance> {
+        self.components
+            .iter()
+            .flat_map(|id| {
+                self.tokens.iter().map(move |token| {
+                    ComponentBalance::new(
+                        token.clone(),
+                        word(0x05, self.block_number),
+                        self.block_number as f64,
+                        tx.clone(),
+                        id,
+                    )
+                })
+            })
+            .take(batch_size)
+            .collect()
+    }
+
+    /// Writes a batch of `entity` in the next block.
+    ///
+    /// Returns the time spent writing, without creating the block and building the batch.
+    async fn write(&mut self, entity: Entity, batch_size: usize) -> Duration {
+        let tx = self.next_tx().await;
+        let start;
+        match entity {
+            Entity::Slots => {
+                let deltas = self.account_deltas(batch_size);
+                let new = deltas
+                    .iter()
+                    .map(|delta| (tx.clone(), delta))
+                    .collect::<Vec<_>>();
+                start = Instant::now();
+                self.gw
+                    .update_contracts(&CHAIN, &new, &mut self.conn)
+                    .await
+            }
+            Entity::States => {
+                let deltas = self.state_deltas(batch_size);
+                let new = deltas
+                    .iter()
+                    .map(|delta| (tx.clone(), delta))
+                    .collect::<Vec<_>>();
+                start = Instant::now();
+                self.gw
+                    .update_protocol_states(&CHAIN, &new, &mut self.conn)
+                    .await
+            }
+            Entity::Balances => {
+                let balances = self.balances(batch_size, &tx);
+                start = Instant::now();
+                self.gw
+                    .add_component_balances(&balances, &CHAIN, &mut self.conn)
+                    .await
+            }
+        }
+        .expect("Write failed");
+        start.elapsed()
+    }
+
+    fn version(&self, block_number: u64) -> Version {
+        Version(
+            BlockOrTimestamp::Block(BlockIdentifier::Number((CHAIN, block_number as i64))),
+            VersionKind::Last,
+        )
+    }
+
+    async fn read_contracts(&mut self, version: &Version) -> usize {
+        self.gw
+            .get_contracts(&CHAIN, None, Some(version), true, &mut self.conn)
+            .await
+            .expect("Read failed")
+            .entity
+            .len()
+    }
+
+    async fn read_protocol_states(&mut self, version: &Version) -> usize {
+        self.gw
+            .get_protocol_states(&CHAIN, Some(version.clone()), None, true, &mut self.conn)
+            .await
+            .expect("Read failed")
+            .entity
+            .len()
+    }
+}
+
+fn upsert_throughput(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("upsert");
+    // A single write of the largest batches takes seconds.
+    group.sample_size(10);
+    for entity in [Entity::Slots, Entity::States, Entity::Balances] {
+        let mut fixture = rt.block_on(Fixture::setup());
+        for batch_size in BATCH_SIZES {
+            group.throughput(Throughput::Elements(batch_size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(entity.name(), batch_size),
+                &batch_size,
+                |b, &batch_size| {
+                    b.iter_custom(|iters| {
+                        rt.block_on(async {
+                            let mut elapsed = Duration::ZERO;
+                            for _ in 0..iters {
+                                elapsed += fixture.write(entity, batch_size).await;
+                            }
+                            elapsed
+                        })
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn versioned_read_latency(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut fixture = rt.block_on(async {
+        let mut fixture = Fixture::setup().await;
+        for _ in 0..SEED_BLOCKS {
+            for entity in [Entity::Slots, Entity::States, Entity::Balances] {
+                fixture
+                    .write(entity, SEED_BATCH_SIZE)
+                    .await;
+            }
+        }
+        fixture
+    });
+    let latest = fixture.version(fixture.block_number);
+    let historical = fixture.version(fixture.block_number - SEED_BLOCKS / 2);
+
+    let mut group = c.benchmark_group("versioned_read");
+    for (label, version) in [("latest", &latest), ("historical", &historical)] {
+        group.bench_function(BenchmarkId::new("contracts", label), |b| {
+            b.iter(|| rt.block_on(fixture.read_contracts(version)))
+        });
+        group.bench_function(BenchmarkId::new("protocol_states", label), |b| {
+            b.iter(|| rt.block_on(fixture.read_protocol_states(version)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, upsert_throughput, versioned_read_latency);
+criterion_main!(benches);
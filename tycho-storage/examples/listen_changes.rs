@@ -0,0 +1,66 @@
+//! Consumes the change notifications of a chain, see `tycho_storage::postgres::notify`.
+//!
+//! The indexer must run with `--notify-changes`. To run:
+//! `DATABASE_URL=postgres://... cargo run --example listen_changes -- ethereum`
+//!
+//! Notifications are only delivered while the connection is open. A real consumer reads the
+//! current state from the database after connecting and then applies what it gets notified of.
+use std::str::FromStr;
+
+use futures03::{stream, StreamExt};
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, NoTls};
+use tycho_common::models::Chain;
+use tycho_storage::postgres::notify::{channel, ChangeNotification};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let database_url = std::env::var("DATABASE_URL")?;
+    let chain = Chain::from_str(
+        &std::env::args()
+            .nth(1)
+            .unwrap_or_else(|| "ethereum".to_string()),
+    )?;
+
+    let (client, mut connection) = tokio_postgres::connect(&database_url, NoTls).await?;
+    // The connection yields notifications as messages and has to be polled for any query to
+    // make progress, so it is driven by a separate task.
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(notification)) => {
+                    if tx.send(notification).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!("Connection failed: {err}");
+                    break;
+                }
+            }
+        }
+    });
+
+    client
+        .batch_execute(&format!("LISTEN {}", channel(&chain)))
+        .await?;
+    println!("Listening for changes on {chain}");
+
+    while let Some(notification) = rx.recv().await {
+        let change: ChangeNotification = serde_json::from_str(notification.payload())?;
+        println!(
+            "{} wrote blocks {}..={}: {:?}",
+            change
+                .extractor
+                .as_deref()
+                .unwrap_or("unknown"),
+            change.start_block,
+            change.end_block,
+            change.entities
+        );
+    }
+    Ok(())
+}
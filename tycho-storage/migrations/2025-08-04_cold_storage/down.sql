@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS contract_storage_cold;
+ALTER TABLE contract_code DROP COLUMN IF EXISTS cold_ref;
@@ -0,0 +1,21 @@
+-- Object key of a code blob moved to cold storage. Once set, the code column is emptied and reads
+-- fetch the code from the object store instead.
+ALTER TABLE contract_code ADD COLUMN IF NOT EXISTS cold_ref text;
+
+-- Historical contract storage versions moved to cold storage. Each row points to an object holding
+-- all versions of a single account that were valid within [valid_from, valid_to).
+CREATE TABLE IF NOT EXISTS contract_storage_cold(
+    "id" bigserial PRIMARY KEY,
+    -- the contract the offloaded versions belong to.
+    "account_id" bigint REFERENCES account(id) ON DELETE CASCADE NOT NULL,
+    -- earliest valid_from of the offloaded versions.
+    "valid_from" timestamptz NOT NULL,
+    -- latest valid_to of the offloaded versions.
+    "valid_to" timestamptz NOT NULL,
+    -- key of the object holding the versions.
+    "object_key" text NOT NULL,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_contract_storage_cold_account_id ON contract_storage_cold(account_id, valid_from, valid_to);
@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS update_modtime_extractor_instance ON extractor_instance;
+DROP TABLE IF EXISTS extractor_instance;
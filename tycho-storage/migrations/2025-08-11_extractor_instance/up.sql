@@ -0,0 +1,29 @@
+-- Registry of running extractor instances. Each extractor identity (name, chain) can be leased by
+-- a single instance at a time; the holder regularly extends its lease. Another instance may only
+-- take over the identity once the lease expired.
+CREATE TABLE IF NOT EXISTS extractor_instance(
+    "id" bigserial PRIMARY KEY,
+    -- name of the extractor
+    "name" varchar(255) NOT NULL,
+    -- Extractor identities are scoped to a specific chain.
+    "chain_id" bigint REFERENCES "chain"(id) NOT NULL,
+    -- unique id of the process currently holding the lease.
+    "instance_id" varchar(255) NOT NULL,
+    -- Timestamp the current holder acquired the lease.
+    "acquired_ts" timestamptz NOT NULL,
+    -- Timestamp of the last heartbeat of the current holder.
+    "heartbeat_ts" timestamptz NOT NULL,
+    -- Timestamp after which the lease can be taken over by another instance.
+    "lease_expires_ts" timestamptz NOT NULL,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Timestamp this entry was last modified.
+    "modified_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- only allow a single instance per extractor identity.
+    UNIQUE (chain_id, "name")
+);
+
+CREATE TRIGGER update_modtime_extractor_instance
+    BEFORE UPDATE ON "extractor_instance"
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
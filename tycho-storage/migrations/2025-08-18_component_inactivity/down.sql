@@ -0,0 +1,2 @@
+ALTER TABLE protocol_component
+    DROP COLUMN IF EXISTS "inactive_since";
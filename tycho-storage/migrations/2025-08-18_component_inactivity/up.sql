@@ -0,0 +1,4 @@
+-- Timestamp of the last state or balance change of a component that has been flagged inactive.
+-- NULL while the component is active.
+ALTER TABLE protocol_component
+    ADD COLUMN IF NOT EXISTS "inactive_since" timestamptz;
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS protocol_state_index;
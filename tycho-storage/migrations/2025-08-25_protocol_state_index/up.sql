@@ -0,0 +1,17 @@
+-- Numeric index over protocol state attributes whose names embed an integer key, e.g. the tick in
+-- `ticks/-887220/net-liquidity`. Which attributes are indexed is declared by the protocol type's
+-- attribute schema. Rows are only ever added: range queries join the versioned state, so deleted
+-- or reverted attributes simply drop out of the results.
+CREATE TABLE IF NOT EXISTS protocol_state_index(
+    -- the component the attribute belongs to.
+    "protocol_component_id" bigint REFERENCES protocol_component(id) ON DELETE CASCADE NOT NULL,
+    -- full name of the indexed attribute.
+    "attribute_name" varchar NOT NULL,
+    -- declared prefix the attribute name matched.
+    "index_prefix" varchar NOT NULL,
+    -- integer key parsed from the attribute name.
+    "index_key" bigint NOT NULL,
+    PRIMARY KEY (protocol_component_id, attribute_name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_protocol_state_index_key ON protocol_state_index(protocol_component_id, index_prefix, index_key);
@@ -0,0 +1,13 @@
+-- Snapshots of each token's total supply, taken periodically by an enrichment job. Used to derive
+-- the share of a token's supply held by a component at a given version.
+CREATE TABLE IF NOT EXISTS token_supply(
+    -- the token the snapshot belongs to.
+    "token_id" bigint REFERENCES token(id) ON DELETE CASCADE NOT NULL,
+    -- total supply of the token, big endian encoded.
+    "total_supply" bytea NOT NULL,
+    -- time at which the supply was observed.
+    "ts" timestamptz NOT NULL,
+    -- timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (token_id, ts)
+);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS quarantined_row;
+DROP TABLE IF EXISTS block_range_repair;
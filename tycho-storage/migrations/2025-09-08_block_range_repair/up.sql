@@ -0,0 +1,34 @@
+-- Repairs of a block range of a single protocol system. Each repair replays the range from
+-- substreams after setting the state versions written in and after the range aside.
+CREATE TABLE IF NOT EXISTS block_range_repair(
+    "id" bigserial PRIMARY KEY,
+    -- chain the repaired protocol system is indexed on.
+    "chain_id" bigint REFERENCES "chain"(id) NOT NULL,
+    -- name of the repaired protocol system.
+    "protocol_system" varchar(255) NOT NULL,
+    -- first block of the repaired range.
+    "start_block" bigint NOT NULL,
+    -- last block of the repaired range, inclusive.
+    "end_block" bigint NOT NULL,
+    -- Timestamp the versions following the range were restored, NULL while the replay is pending.
+    "restored_ts" timestamptz,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Versioned rows removed by a repair, kept as json so any versioned table can be stored.
+CREATE TABLE IF NOT EXISTS quarantined_row(
+    "id" bigserial PRIMARY KEY,
+    "repair_id" bigint REFERENCES block_range_repair(id) ON DELETE CASCADE NOT NULL,
+    -- table the row was removed from.
+    "table_name" varchar(255) NOT NULL,
+    -- Rows written after the repaired range are parked and restored once the replay finished.
+    -- The remaining rows were written within the range and are kept for inspection only.
+    "parked" bool NOT NULL,
+    -- the removed row.
+    "row_data" jsonb NOT NULL,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_quarantined_row_repair_id ON quarantined_row(repair_id, table_name);
@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS idx_protocol_component_chain_extractor;
+
+ALTER TABLE protocol_state
+    DROP COLUMN IF EXISTS "extractor";
+
+ALTER TABLE protocol_component
+    DROP COLUMN IF EXISTS "extractor";
@@ -0,0 +1,12 @@
+-- Name of the extractor that inserted a row. Writers set `tycho.extractor` for the duration of
+-- their transaction, rows inserted outside an extractor's transaction stay NULL.
+ALTER TABLE protocol_component
+    ADD COLUMN IF NOT EXISTS "extractor" varchar(255)
+        DEFAULT NULLIF(current_setting('tycho.extractor', TRUE), '');
+
+ALTER TABLE protocol_state
+    ADD COLUMN IF NOT EXISTS "extractor" varchar(255)
+        DEFAULT NULLIF(current_setting('tycho.extractor', TRUE), '');
+
+CREATE INDEX IF NOT EXISTS idx_protocol_component_chain_extractor
+    ON protocol_component(chain_id, extractor);
@@ -0,0 +1,54 @@
+-- Read-only views for ad-hoc analytics queries. Queries run as `tycho_analytics`, which may only
+-- read these views, so the underlying tables are reachable through the views only. Creating the
+-- role requires CREATEROLE, it is provisioned by `sql_scripts/provision_analytics_role.sql`.
+CREATE SCHEMA IF NOT EXISTS analytics;
+
+CREATE OR REPLACE VIEW analytics.block AS
+SELECT c.name AS chain, b.number, b.hash, b.parent_hash, b.ts, b.main
+FROM block b
+JOIN chain c ON c.id = b.chain_id;
+
+CREATE OR REPLACE VIEW analytics.token AS
+SELECT c.name AS chain, a.address, t.symbol, t.decimals, t.quality
+FROM token t
+JOIN account a ON a.id = t.account_id
+JOIN chain c ON c.id = a.chain_id;
+
+CREATE OR REPLACE VIEW analytics.protocol_component AS
+SELECT c.name AS chain, pc.external_id AS component_id, ps.name AS protocol_system,
+    pt.name AS protocol_type, pc.attributes, pc.created_at, pc.deleted_at, pc.inactive_since,
+    pc.extractor
+FROM protocol_component pc
+JOIN chain c ON c.id = pc.chain_id
+JOIN protocol_system ps ON ps.id = pc.protocol_system_id
+JOIN protocol_type pt ON pt.id = pc.protocol_type_id;
+
+CREATE OR REPLACE VIEW analytics.protocol_state AS
+SELECT c.name AS chain, pc.external_id AS component_id, s.attribute_name, s.attribute_value,
+    s.valid_from, s.valid_to
+FROM protocol_state s
+JOIN protocol_component pc ON pc.id = s.protocol_component_id
+JOIN chain c ON c.id = pc.chain_id;
+
+CREATE OR REPLACE VIEW analytics.component_balance AS
+SELECT c.name AS chain, pc.external_id AS component_id, a.address AS token, cb.new_balance AS balance,
+    cb.balance_float, cb.valid_from, cb.valid_to
+FROM component_balance cb
+JOIN protocol_component pc ON pc.id = cb.protocol_component_id
+JOIN chain c ON c.id = pc.chain_id
+JOIN token t ON t.id = cb.token_id
+JOIN account a ON a.id = t.account_id;
+
+CREATE OR REPLACE VIEW analytics.contract_storage AS
+SELECT c.name AS chain, a.address, cs.slot, cs.value, cs.valid_from, cs.valid_to
+FROM contract_storage cs
+JOIN account a ON a.id = cs.account_id
+JOIN chain c ON c.id = a.chain_id;
+
+CREATE OR REPLACE VIEW analytics.account_balance AS
+SELECT c.name AS chain, a.address, ta.address AS token, ab.balance, ab.valid_from, ab.valid_to
+FROM account_balance ab
+JOIN account a ON a.id = ab.account_id
+JOIN chain c ON c.id = a.chain_id
+JOIN token t ON t.id = ab.token_id
+JOIN account ta ON ta.id = t.account_id;
@@ -0,0 +1,13 @@
+DROP VIEW IF EXISTS analytics.block;
+
+CREATE VIEW analytics.block AS
+SELECT c.name AS chain, b.number, b.hash, b.parent_hash, b.ts, b.main
+FROM block b
+JOIN chain c ON c.id = b.chain_id;
+
+DROP INDEX IF EXISTS idx_block_chain_finality_number;
+
+ALTER TABLE block
+    DROP COLUMN IF EXISTS finality_status;
+
+DROP TYPE IF EXISTS finality_status;
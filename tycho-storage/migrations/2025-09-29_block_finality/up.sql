@@ -0,0 +1,16 @@
+CREATE TYPE finality_status AS ENUM ('pending', 'safe', 'finalized');
+
+-- Extractors only commit blocks substreams reported final and mark them finalized after each
+-- commit, the blocks stored so far are final as well.
+ALTER TABLE block
+    ADD COLUMN IF NOT EXISTS finality_status finality_status NOT NULL DEFAULT 'finalized';
+ALTER TABLE block
+    ALTER COLUMN finality_status SET DEFAULT 'pending';
+
+CREATE INDEX IF NOT EXISTS idx_block_chain_finality_number
+    ON block(chain_id, finality_status, number);
+
+CREATE OR REPLACE VIEW analytics.block AS
+SELECT c.name AS chain, b.number, b.hash, b.parent_hash, b.ts, b.main, b.finality_status
+FROM block b
+JOIN chain c ON c.id = b.chain_id;
@@ -0,0 +1,22 @@
+-- Raw event logs attributed to protocol components. Only extractors configured with event topics
+-- write to this table, see `component_events` in the extractor configuration.
+CREATE TABLE IF NOT EXISTS component_event(
+    "id" bigserial PRIMARY KEY,
+    -- the component the log was attributed to.
+    "protocol_component_id" bigint REFERENCES protocol_component(id) ON DELETE CASCADE NOT NULL,
+    -- the transaction that emitted the log, reverted logs are deleted with it.
+    "transaction_id" bigint REFERENCES "transaction"(id) ON DELETE CASCADE NOT NULL,
+    -- index of the log within its block.
+    "log_index" bigint NOT NULL,
+    -- address of the contract that emitted the log.
+    "address" bytea NOT NULL,
+    -- the log topics, the first one is the event signature for non anonymous events.
+    "topics" bytea[] NOT NULL,
+    -- the abi encoded non indexed event parameters.
+    "data" bytea NOT NULL,
+    -- timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (protocol_component_id, transaction_id, log_index)
+);
+
+CREATE INDEX IF NOT EXISTS idx_component_event_transaction_id ON component_event(transaction_id);
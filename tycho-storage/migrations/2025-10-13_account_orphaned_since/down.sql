@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_account_orphaned_since;
+
+ALTER TABLE account
+    DROP COLUMN IF EXISTS "orphaned_since";
@@ -0,0 +1,8 @@
+-- Time an account was first found unreferenced by any component, token or traced entry point.
+-- NULL while the account is referenced. Accounts orphaned for longer than a grace period are
+-- deleted together with their versioned data.
+ALTER TABLE account
+    ADD COLUMN IF NOT EXISTS "orphaned_since" timestamptz;
+
+CREATE INDEX IF NOT EXISTS idx_account_orphaned_since ON account (orphaned_since)
+WHERE orphaned_since IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Header fields beyond the common ones, e.g. blob gas fields, as a JSON object keyed by field
+-- name. NULL if the block carries none.
+ALTER TABLE block
+    ADD COLUMN IF NOT EXISTS "extra" jsonb;
@@ -0,0 +1 @@
+DROP INDEX IF EXISTS idx_contract_code_hash;
@@ -0,0 +1,2 @@
+-- Serves code lookups by hash, e.g. for clients that received only the hash of a contract's code.
+CREATE INDEX IF NOT EXISTS idx_contract_code_hash ON contract_code (hash);
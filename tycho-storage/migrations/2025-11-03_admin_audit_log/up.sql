@@ -0,0 +1,16 @@
+-- Invocations of admin endpoints, one row per request including rejected ones.
+CREATE TABLE IF NOT EXISTS admin_audit_log(
+    "id" bigserial PRIMARY KEY,
+    -- name of the API key the request was made with, never the key itself.
+    "api_key" varchar(255) NOT NULL,
+    -- role of the API key at the time of the request.
+    "role" varchar(32) NOT NULL,
+    -- method and path of the request.
+    "action" varchar(255) NOT NULL,
+    -- HTTP status the request was answered with.
+    "status" integer NOT NULL,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_admin_audit_log_inserted_ts ON admin_audit_log(inserted_ts);
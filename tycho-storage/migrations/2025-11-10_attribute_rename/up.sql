@@ -0,0 +1,16 @@
+-- Renames of protocol state attributes. Renamed versions keep no trace of their former name, this
+-- table records which name they were written under.
+CREATE TABLE IF NOT EXISTS protocol_state_attribute_rename(
+    "id" bigserial PRIMARY KEY,
+    "chain_id" bigint REFERENCES "chain"(id) NOT NULL,
+    -- protocol system whose components were renamed.
+    "protocol_system" varchar(255) NOT NULL,
+    -- external ids of the renamed components, NULL if all components of the system were renamed.
+    "component_ids" text[],
+    "old_name" varchar NOT NULL,
+    "new_name" varchar NOT NULL,
+    -- number of protocol_state versions renamed, including deleted and superseded ones.
+    "renamed_versions" bigint NOT NULL,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
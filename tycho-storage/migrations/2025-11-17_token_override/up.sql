@@ -0,0 +1,23 @@
+-- Corrections to token metadata that is wrong on-chain. Overrides are merged over the token rows
+-- when tokens are served, the token rows themselves keep what was read from chain.
+CREATE TABLE IF NOT EXISTS token_override(
+    "id" bigserial PRIMARY KEY,
+    -- the corrected token, at most one override per token.
+    "token_id" bigint REFERENCES "token"(id) ON DELETE CASCADE NOT NULL UNIQUE,
+    -- symbol served instead of the stored one, NULL keeps the stored symbol.
+    "symbol" varchar(255),
+    -- decimals front ends should use to display amounts. Amounts are still denominated in the
+    -- token's on-chain decimals.
+    "display_decimals" integer,
+    -- URI of an image representing the token.
+    "logo_uri" text,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Timestamp this entry was last modified.
+    "modified_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER update_modtime_token_override
+    BEFORE UPDATE ON "token_override"
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
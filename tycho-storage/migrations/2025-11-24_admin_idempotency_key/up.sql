@@ -0,0 +1,30 @@
+-- Results of admin requests made with an idempotency key. A retried request with the same key is
+-- answered with the recorded result instead of running the action again.
+CREATE TABLE IF NOT EXISTS admin_idempotency_key(
+    "id" bigserial PRIMARY KEY,
+    -- name of the API key the request was made with, keys are scoped per API key.
+    "api_key" varchar(255) NOT NULL,
+    -- value of the Idempotency-Key header.
+    "key" varchar(255) NOT NULL,
+    -- method and path of the request, a key can't be reused for a different action.
+    "action" varchar(255) NOT NULL,
+    -- HTTP status the request was answered with, NULL while the request is in progress.
+    "status" integer,
+    -- content type of the recorded response.
+    "content_type" varchar(255),
+    -- body of the recorded response.
+    "body" bytea,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Timestamp this entry was last modified.
+    "modified_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE ("api_key", "key")
+);
+
+CREATE INDEX IF NOT EXISTS idx_admin_idempotency_key_inserted_ts
+    ON admin_idempotency_key(inserted_ts);
+
+CREATE TRIGGER update_modtime_admin_idempotency_key
+    BEFORE UPDATE ON "admin_idempotency_key"
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
@@ -0,0 +1,2 @@
+DROP VIEW IF EXISTS analytics.component_latest_state;
+DROP VIEW IF EXISTS analytics.component_latest_balance;
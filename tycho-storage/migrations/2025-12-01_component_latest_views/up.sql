@@ -0,0 +1,23 @@
+-- Current attributes and balances of components with chain, protocol system and tokens resolved,
+-- the joins most analytics queries start from. The latest version of a row has its `valid_to` set
+-- to the far future, the filter on it only touches the default partition.
+CREATE OR REPLACE VIEW analytics.component_latest_state AS
+SELECT c.name AS chain, pc.external_id AS component_id, ps.name AS protocol_system,
+    s.attribute_name, s.attribute_value, s.valid_from AS modified_ts
+FROM protocol_state s
+JOIN protocol_component pc ON pc.id = s.protocol_component_id
+JOIN protocol_system ps ON ps.id = pc.protocol_system_id
+JOIN chain c ON c.id = pc.chain_id
+WHERE s.valid_to IS NULL OR s.valid_to >= '262142-12-31T00:00:00Z';
+
+CREATE OR REPLACE VIEW analytics.component_latest_balance AS
+SELECT c.name AS chain, pc.external_id AS component_id, ps.name AS protocol_system,
+    a.address AS token, t.symbol, t.decimals, cb.new_balance AS balance, cb.balance_float,
+    cb.valid_from AS modified_ts
+FROM component_balance cb
+JOIN protocol_component pc ON pc.id = cb.protocol_component_id
+JOIN protocol_system ps ON ps.id = pc.protocol_system_id
+JOIN chain c ON c.id = pc.chain_id
+JOIN token t ON t.id = cb.token_id
+JOIN account a ON a.id = t.account_id
+WHERE cb.valid_to IS NULL OR cb.valid_to >= '262142-12-31T00:00:00Z';
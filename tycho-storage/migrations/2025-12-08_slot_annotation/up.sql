@@ -0,0 +1,25 @@
+-- Known meaning of storage slots of VM contracts, e.g. that slot 0x0 holds the owner. Used to
+-- annotate contract state for debugging. Contracts are referenced by address so annotations can
+-- be loaded before a contract is indexed.
+CREATE TABLE IF NOT EXISTS slot_annotation(
+    "id" bigserial PRIMARY KEY,
+    "chain_id" bigint REFERENCES "chain"(id) ON DELETE CASCADE NOT NULL,
+    -- address of the annotated contract.
+    "address" bytea NOT NULL,
+    -- the annotated slot, left padded to 32 bytes like the stored slots.
+    "slot" bytea NOT NULL,
+    -- short name of what the slot holds, e.g. `owner`.
+    "label" varchar(255) NOT NULL,
+    -- longer explanation of the slot's contents.
+    "description" text,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Timestamp this entry was last modified.
+    "modified_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE ("chain_id", "address", "slot")
+);
+
+CREATE TRIGGER update_modtime_slot_annotation
+    BEFORE UPDATE ON "slot_annotation"
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
@@ -0,0 +1,19 @@
+DROP INDEX IF EXISTS idx_component_balance_token_id_balance_numeric;
+
+DROP INDEX IF EXISTS idx_account_balance_token_id_balance_numeric;
+
+DROP TRIGGER IF EXISTS set_balance_numeric_component_balance ON component_balance;
+
+DROP TRIGGER IF EXISTS set_balance_numeric_account_balance ON account_balance;
+
+ALTER TABLE component_balance
+    DROP COLUMN IF EXISTS balance_numeric;
+
+ALTER TABLE account_balance
+    DROP COLUMN IF EXISTS balance_numeric;
+
+DROP FUNCTION IF EXISTS set_balance_numeric_component_balance();
+
+DROP FUNCTION IF EXISTS set_balance_numeric_account_balance();
+
+DROP FUNCTION IF EXISTS bytea_to_numeric(bytea);
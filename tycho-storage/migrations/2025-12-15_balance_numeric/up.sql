@@ -0,0 +1,77 @@
+-- Balances are stored as big endian bytes of varying length, which can't be compared or ordered
+-- numerically. Both balance tables get a numeric copy of their balance, kept in sync by triggers
+-- so writers don't need to provide it.
+
+-- Decodes a big endian unsigned integer. Returns NULL for values wider than 256 bits, which don't
+-- fit numeric(78, 0).
+CREATE OR REPLACE FUNCTION bytea_to_numeric(value bytea)
+    RETURNS numeric
+    AS $$
+DECLARE
+    result numeric := 0;
+BEGIN
+    value := ltrim(value, '\x00'::bytea);
+    IF length(value) > 32 THEN
+        RETURN NULL;
+    END IF;
+    FOR i IN 0..length(value) - 1 LOOP
+        result := result * 256 + get_byte(value, i);
+    END LOOP;
+    RETURN result;
+END;
+$$
+LANGUAGE plpgsql
+IMMUTABLE STRICT PARALLEL SAFE;
+
+CREATE OR REPLACE FUNCTION set_balance_numeric_account_balance()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    NEW.balance_numeric := bytea_to_numeric(NEW.balance);
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION set_balance_numeric_component_balance()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    NEW.balance_numeric := bytea_to_numeric(NEW.new_balance);
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+ALTER TABLE account_balance
+    ADD COLUMN IF NOT EXISTS balance_numeric numeric(78, 0);
+
+-- Added to the partitioned parent, so all partitions get the column.
+ALTER TABLE component_balance
+    ADD COLUMN IF NOT EXISTS balance_numeric numeric(78, 0);
+
+CREATE TRIGGER set_balance_numeric_account_balance
+    BEFORE INSERT OR UPDATE OF balance ON account_balance
+    FOR EACH ROW
+    EXECUTE PROCEDURE set_balance_numeric_account_balance();
+
+CREATE TRIGGER set_balance_numeric_component_balance
+    BEFORE INSERT OR UPDATE OF new_balance ON component_balance
+    FOR EACH ROW
+    EXECUTE PROCEDURE set_balance_numeric_component_balance();
+
+UPDATE
+    account_balance
+SET
+    balance_numeric = bytea_to_numeric(balance);
+
+UPDATE
+    component_balance
+SET
+    balance_numeric = bytea_to_numeric(new_balance);
+
+CREATE INDEX IF NOT EXISTS idx_account_balance_token_id_balance_numeric ON
+    account_balance(token_id, balance_numeric);
+
+CREATE INDEX IF NOT EXISTS idx_component_balance_token_id_balance_numeric ON
+    component_balance(token_id, balance_numeric);
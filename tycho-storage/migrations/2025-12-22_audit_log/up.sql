@@ -0,0 +1,27 @@
+-- Destructive storage operations like reverts, prunes, repairs and deletions, one row per
+-- operation. Entries are append only and never pruned by the indexer.
+CREATE TABLE IF NOT EXISTS audit_log(
+    "id" bigserial PRIMARY KEY,
+    -- kind of the operation, e.g. `revert_state` or `prune_history`.
+    "operation" varchar(64) NOT NULL,
+    -- extractor that ran the operation, or the database user outside of extractor transactions.
+    "actor" varchar(255) NOT NULL
+        DEFAULT COALESCE(NULLIF(current_setting('tycho.extractor', TRUE), ''), session_user),
+    -- name of the chain the operation was scoped to, if any. Not a reference so entries outlive
+    -- their chain.
+    "chain" varchar(255),
+    -- what the operation targeted, e.g. the protocol system or the deleted components.
+    "target" text,
+    -- number of rows deleted or modified by the operation.
+    "rows_affected" bigint NOT NULL,
+    -- first and last block number the operation affected, if it affected a block range.
+    "start_block" bigint,
+    "end_block" bigint,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_operation_inserted_ts
+    ON audit_log(operation, inserted_ts);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_inserted_ts ON audit_log(inserted_ts);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_protocol_component_pair_key;
+
+ALTER TABLE protocol_component
+    DROP COLUMN IF EXISTS pair_key;
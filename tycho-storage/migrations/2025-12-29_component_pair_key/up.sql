@@ -0,0 +1,23 @@
+-- Canonical key of the tokens a component holds: their addresses sorted and joined by `-`. Set by
+-- the indexer on insert, components holding the same tokens share a key however they list them.
+ALTER TABLE protocol_component
+    ADD COLUMN IF NOT EXISTS pair_key text;
+
+UPDATE protocol_component pc
+SET pair_key = keys.pair_key
+FROM (
+    SELECT h.protocol_component_id,
+        string_agg('0x' || encode(a.address, 'hex'), '-' ORDER BY a.address) AS pair_key
+    FROM protocol_component_holds_token h
+    JOIN token t ON t.id = h.token_id
+    JOIN account a ON a.id = t.account_id
+    GROUP BY h.protocol_component_id) keys
+WHERE keys.protocol_component_id = pc.id;
+
+-- Components without tokens get the empty key, as the indexer stores for them.
+UPDATE protocol_component
+SET pair_key = ''
+WHERE pair_key IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_protocol_component_pair_key
+    ON protocol_component(chain_id, pair_key);
@@ -0,0 +1,16 @@
+-- Digest of the changes an extractor wrote for a block, used to compare the indexed history of
+-- two deployments. Digests are removed with their block on reverts.
+CREATE TABLE IF NOT EXISTS block_digest(
+    "block_id" bigint PRIMARY KEY REFERENCES block(id) ON DELETE CASCADE,
+    -- keccak256 over the sorted state, balance and account changes of the block.
+    "digest" bytea NOT NULL,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Timestamp this entry was last modified.
+    "modified_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER update_modtime_block_digest
+    BEFORE UPDATE ON "block_digest"
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
@@ -0,0 +1,2 @@
+ALTER TABLE extraction_state
+    DROP COLUMN IF EXISTS "substreams_packages";
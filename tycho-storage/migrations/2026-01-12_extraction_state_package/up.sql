@@ -0,0 +1,4 @@
+-- Substreams packages an extractor ran, oldest first: name, version, output module, module hash
+-- and the first block processed with it. Used to tell which package produced which data.
+ALTER TABLE extraction_state
+    ADD COLUMN IF NOT EXISTS "substreams_packages" jsonb;
@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS update_modtime_tracked_contract ON tracked_contract;
+DROP TABLE IF EXISTS tracked_contract;
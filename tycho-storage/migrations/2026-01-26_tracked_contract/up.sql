@@ -0,0 +1,28 @@
+-- Contracts an extractor persists the changes of, listed in its config or added through the admin
+-- RPC. Contracts held by components are not listed here. Stored so the list survives restarts and
+-- tracked contracts are not collected as orphaned accounts.
+CREATE TABLE IF NOT EXISTS tracked_contract(
+    "id" bigserial PRIMARY KEY,
+    -- name of the extractor tracking the contract.
+    "extractor" varchar(255) NOT NULL,
+    "chain_id" bigint REFERENCES "chain"(id) NOT NULL,
+    -- address of the contract, it doesn't need to be indexed yet.
+    "address" bytea NOT NULL,
+    -- first block whose changes of the contract are persisted.
+    "from_block" bigint NOT NULL,
+    -- first block the contract is no longer tracked in, NULL while it is tracked.
+    "until_block" bigint,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Timestamp this entry was last modified.
+    "modified_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (chain_id, extractor, address)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tracked_contract_chain_id_address
+    ON tracked_contract(chain_id, address);
+
+CREATE TRIGGER update_modtime_tracked_contract
+    BEFORE UPDATE ON "tracked_contract"
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
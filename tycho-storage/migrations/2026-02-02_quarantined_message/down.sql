@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS update_modtime_quarantined_message ON quarantined_message;
+DROP TABLE IF EXISTS quarantined_message;
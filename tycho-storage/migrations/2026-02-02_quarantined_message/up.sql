@@ -0,0 +1,30 @@
+-- Block messages an extractor could not decode and was configured to quarantine. The raw message
+-- is kept so it can be replayed once the decoding issue is fixed, while the extractor moves on.
+CREATE TABLE IF NOT EXISTS quarantined_message(
+    "id" bigserial PRIMARY KEY,
+    -- name of the extractor that received the message.
+    "extractor" varchar(255) NOT NULL,
+    "chain_id" bigint REFERENCES "chain"(id) NOT NULL,
+    -- number of the block the message belongs to.
+    "block_number" bigint NOT NULL,
+    -- hash of the block, NULL if the substreams clock didn't carry a valid one.
+    "block_hash" bytea,
+    -- protobuf type of the payload.
+    "type_url" text NOT NULL,
+    -- the undecoded message.
+    "payload" bytea NOT NULL,
+    -- kind of the decode failure, e.g. `invalid_length`.
+    "kind" varchar(255) NOT NULL,
+    -- the decode error.
+    "error" text NOT NULL,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Timestamp this entry was last modified.
+    "modified_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (chain_id, extractor, block_number, type_url)
+);
+
+CREATE TRIGGER update_modtime_quarantined_message
+    BEFORE UPDATE ON "quarantined_message"
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
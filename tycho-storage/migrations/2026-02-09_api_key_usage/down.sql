@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS update_modtime_api_key_usage ON api_key_usage;
+DROP TABLE IF EXISTS api_key_usage;
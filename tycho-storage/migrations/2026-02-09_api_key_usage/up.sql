@@ -0,0 +1,23 @@
+-- Resources consumed by API keys per calendar month (UTC). Servers add the usage they metered
+-- periodically, so quotas hold across restarts and are shared by all servers of a deployment.
+CREATE TABLE IF NOT EXISTS api_key_usage(
+    "id" bigserial PRIMARY KEY,
+    -- name of the API key as configured, keys themselves are not stored.
+    "api_key" varchar(255) NOT NULL,
+    -- month the usage was accumulated in, e.g. `2024-01`.
+    "month" varchar(7) NOT NULL,
+    "requests" bigint NOT NULL DEFAULT 0,
+    -- number of entities returned.
+    "rows_returned" bigint NOT NULL DEFAULT 0,
+    "bytes_returned" bigint NOT NULL DEFAULT 0,
+    -- Timestamp this entry was inserted into this table.
+    "inserted_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Timestamp this entry was last modified.
+    "modified_ts" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE ("month", "api_key")
+);
+
+CREATE TRIGGER update_modtime_api_key_usage
+    BEFORE UPDATE ON "api_key_usage"
+    FOR EACH ROW
+    EXECUTE PROCEDURE update_modified_column();
@@ -0,0 +1,25 @@
+-- Provision the role analytics queries run as
+--
+-- Optional, analytics queries are rejected until this ran. It needs a user with CREATEROLE, which
+-- the service user usually lacks, so it is not part of the migrations. Run it after the
+-- migrations, passing the user tycho connects as:
+--
+--   psql "$ADMIN_DATABASE_URL" -v service_user=tycho -f provision_analytics_role.sql
+--
+-- Views added by later migrations are covered by the default privileges below, as long as the
+-- migrations run as the service user. Running the script again is harmless.
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT FROM pg_roles WHERE rolname = 'tycho_analytics') THEN
+        CREATE ROLE tycho_analytics NOLOGIN;
+    END IF;
+END
+$$;
+
+-- Allows the service user to switch to the role with `SET ROLE`.
+GRANT tycho_analytics TO :"service_user";
+
+GRANT USAGE ON SCHEMA analytics TO tycho_analytics;
+GRANT SELECT ON ALL TABLES IN SCHEMA analytics TO tycho_analytics;
+ALTER DEFAULT PRIVILEGES FOR ROLE :"service_user" IN SCHEMA analytics
+    GRANT SELECT ON TABLES TO tycho_analytics;
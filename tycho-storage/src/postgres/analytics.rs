@@ -0,0 +1,418 @@
+//! Ad-hoc analytics queries over the views of the `analytics` schema.
+//!
+//! Queries run as the `tycho_analytics` role, which may only read the views created by the
+//! `analytics_views` migration. The views resolve chain, component and token ids, so results don't
+//! depend on internal ids. Callers are expected to run the query in a read-only transaction, see
+//! [`CachedGateway`](super::cache::CachedGateway).
+//!
+//! Creating the role requires `CREATEROLE`, so the migrations leave it to an optional ops step, see
+//! `sql_scripts/provision_analytics_role.sql`. Queries are rejected with
+//! [`StorageError::Unsupported`] until the service user is a member of the role.
+//!
+//! A role switched to with `SET ROLE` can be switched back by the query itself, e.g. via
+//! `set_config('role', ...)`, and session level advisory locks taken by the query outlive its
+//! transaction. Queries from untrusted sources must be validated before they get here, as the RPC
+//! does, and callers release leftover locks with [`PostgresGateway::release_advisory_locks`]
+//! before returning the connection to the pool.
+//!
+//! The `component_latest_*` views join the current component state and balances with their
+//! protocol system and tokens. Besides being queryable ad-hoc, they back typed gateway accessors
+//! which run as the service user.
+use std::time::Duration;
+
+use diesel::{
+    sql_types::{Array, Bool, Nullable, Text},
+    QueryableByName,
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tracing::instrument;
+use tycho_common::{
+    models::{
+        component_id_candidates,
+        protocol::{ComponentLatestBalance, ComponentLatestState},
+        Chain,
+    },
+    storage::{AnalyticsRows, StorageError},
+};
+
+use super::{orm, PostgresError, PostgresGateway};
+
+/// Filters shared by the queries over the `component_latest_*` views.
+const COMPONENT_FILTER: &str = "chain = $1
+    AND ($2::text IS NULL OR protocol_system = $2)
+    AND ($3::text[] IS NULL OR component_id = ANY($3))";
+
+/// Role analytics queries run as.
+const ANALYTICS_ROLE: &str = "tycho_analytics";
+
+#[derive(QueryableByName, Debug)]
+struct RoleCheck {
+    #[diesel(sql_type = Bool)]
+    is_member: bool,
+}
+
+#[derive(QueryableByName, Debug)]
+struct JsonRow {
+    #[diesel(sql_type = Text)]
+    row: String,
+}
+
+impl PostgresGateway {
+    /// Runs `query` against the analytics views.
+    ///
+    /// The role, search path and statement timeout are set transaction locally, so the caller
+    /// must have started a transaction on `conn`.
+    #[instrument(skip(self, conn))]
+    pub async fn run_analytics_query(
+        &self,
+        query: &str,
+        row_limit: usize,
+        timeout: Duration,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<AnalyticsRows, StorageError> {
+        // The oid variant of `pg_has_role` does not fail for a missing role, the name variant does.
+        let RoleCheck { is_member } = diesel::sql_query(
+            "SELECT EXISTS (
+                SELECT FROM pg_roles WHERE rolname = $1 AND pg_has_role(current_user, oid, 'MEMBER')
+            ) AS is_member",
+        )
+        .bind::<Text, _>(ANALYTICS_ROLE)
+        .get_result(conn)
+        .await
+        .map_err(PostgresError::from)?;
+        if !is_member {
+            return Err(StorageError::Unsupported(format!(
+                "Analytics queries require the service user to be a member of the \
+                {ANALYTICS_ROLE} role"
+            )));
+        }
+
+        // `SET` does not accept bind parameters.
+        for setting in [
+            format!("SET LOCAL statement_timeout = {}", timeout.as_millis()),
+            format!("SET LOCAL ROLE {ANALYTICS_ROLE}"),
+            "SET LOCAL search_path = analytics".to_string(),
+        ] {
+            diesel::sql_query(setting)
+                .execute(conn)
+                .await
+                .map_err(PostgresError::from)?;
+        }
+
+        // Wrapping the query as a subquery rejects anything that isn't a single `SELECT`.
+        let mut rows = diesel::sql_query(format!(
+            "SELECT row_to_json(q)::text AS row FROM ({query}) AS q LIMIT {}",
+            row_limit + 1
+        ))
+        .load::<JsonRow>(conn)
+        .await
+        .map_err(|err| match PostgresError::from(err) {
+            PostgresError(StorageError::Unexpected(msg)) => StorageError::InvalidQuery(msg),
+            PostgresError(err) => err,
+        })?
+        .into_iter()
+        .map(|JsonRow { row }| {
+            serde_json::from_str(&row).map_err(|err| {
+                StorageError::DecodeError(format!("Failed to decode analytics row: {err}"))
+            })
+        })
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+        let truncated = rows.len() > row_limit;
+        rows.truncate(row_limit);
+        Ok(AnalyticsRows { rows, truncated })
+    }
+
+    /// Releases the session level advisory locks held by `conn`, e.g. ones taken by an analytics
+    /// query. Must be called outside of the query's transaction.
+    pub(crate) async fn release_advisory_locks(
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        diesel::sql_query("SELECT pg_advisory_unlock_all()")
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+
+    #[instrument(skip(self, conn))]
+    pub async fn get_component_latest_states(
+        &self,
+        chain: &Chain,
+        system: Option<&str>,
+        ids: Option<&[&str]>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<ComponentLatestState>, StorageError> {
+        let ids = ids.map(|ids| {
+            ids.iter()
+                .flat_map(|id| component_id_candidates(id))
+                .collect::<Vec<_>>()
+        });
+        let rows = diesel::sql_query(format!(
+            "SELECT component_id, protocol_system, attribute_name, attribute_value, modified_ts
+            FROM analytics.component_latest_state
+            WHERE {COMPONENT_FILTER}
+            ORDER BY component_id, attribute_name"
+        ))
+        .bind::<Text, _>(chain.to_string())
+        .bind::<Nullable<Text>, _>(system)
+        .bind::<Nullable<Array<Text>>, _>(ids)
+        .load::<orm::ComponentLatestStateRow>(conn)
+        .await
+        .map_err(PostgresError::from)?;
+        Ok(rows
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip(self, conn))]
+    pub async fn get_component_latest_balances(
+        &self,
+        chain: &Chain,
+        system: Option<&str>,
+        ids: Option<&[&str]>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<ComponentLatestBalance>, StorageError> {
+        let ids = ids.map(|ids| {
+            ids.iter()
+                .flat_map(|id| component_id_candidates(id))
+                .collect::<Vec<_>>()
+        });
+        let rows = diesel::sql_query(format!(
+            "SELECT component_id, protocol_system, token, symbol, decimals, balance, balance_float,
+                modified_ts
+            FROM analytics.component_latest_balance
+            WHERE {COMPONENT_FILTER}
+            ORDER BY component_id, token"
+        ))
+        .bind::<Text, _>(chain.to_string())
+        .bind::<Nullable<Text>, _>(system)
+        .bind::<Nullable<Array<Text>>, _>(ids)
+        .load::<orm::ComponentLatestBalanceRow>(conn)
+        .await
+        .map_err(PostgresError::from)?;
+        Ok(rows
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel_async::AsyncConnection;
+    use serde_json::json;
+    use tycho_common::Bytes;
+
+    use super::*;
+    use crate::postgres::db_fixtures;
+
+    async fn setup_db() -> AsyncPgConnection {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        conn
+    }
+
+    #[tokio::test]
+    async fn test_run_analytics_query() {
+        let mut conn = setup_db().await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+
+        let res = gw
+            .run_analytics_query(
+                "SELECT chain FROM block WHERE false UNION ALL SELECT 'a' UNION ALL SELECT 'b'",
+                1,
+                Duration::from_secs(5),
+                &mut conn,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(res, AnalyticsRows { rows: vec![json!({"chain": "a"})], truncated: true });
+    }
+
+    #[tokio::test]
+    async fn test_run_analytics_query_only_reads_views() {
+        let mut conn = setup_db().await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+
+        let res = gw
+            .run_analytics_query(
+                "SELECT * FROM public.chain",
+                10,
+                Duration::from_secs(5),
+                &mut conn,
+            )
+            .await;
+
+        assert!(matches!(res, Err(StorageError::InvalidQuery(_))), "{res:?}");
+    }
+
+    #[tokio::test]
+    async fn test_run_analytics_query_timeout() {
+        let mut conn = setup_db().await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+
+        let res = gw
+            .run_analytics_query("SELECT pg_sleep(1)", 10, Duration::from_millis(10), &mut conn)
+            .await;
+
+        assert!(matches!(res, Err(StorageError::Timeout(_))), "{res:?}");
+    }
+
+    #[derive(QueryableByName, Debug)]
+    struct LockCount {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        count: i64,
+    }
+
+    async fn advisory_lock_count(conn: &mut AsyncPgConnection) -> i64 {
+        diesel::sql_query(
+            "SELECT count(*) AS count FROM pg_locks
+            WHERE locktype = 'advisory' AND pid = pg_backend_pid()",
+        )
+        .get_result::<LockCount>(conn)
+        .await
+        .unwrap()
+        .count
+    }
+
+    #[tokio::test]
+    async fn test_release_advisory_locks() {
+        let mut conn = setup_db().await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        gw.run_analytics_query(
+            "SELECT pg_advisory_lock(1, 2)",
+            10,
+            Duration::from_secs(5),
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        assert_eq!(advisory_lock_count(&mut conn).await, 1);
+
+        PostgresGateway::release_advisory_locks(&mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(advisory_lock_count(&mut conn).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_component_latest_views() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[
+                (blk[0], 1, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945"),
+                (blk[1], 1, "0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7"),
+            ],
+        )
+        .await;
+        let system_id = db_fixtures::insert_protocol_system(&mut conn, "ambient".to_string()).await;
+        let type_id = db_fixtures::insert_protocol_type(&mut conn, "pool", None, None, None).await;
+        let (_, token_id) = db_fixtures::insert_token(
+            &mut conn,
+            chain_id,
+            "6b175474e89094c44da98b954eedeac495271d0f",
+            "DAI",
+            18,
+            None,
+        )
+        .await;
+        let component_id = db_fixtures::insert_protocol_component(
+            &mut conn,
+            "0xaabb",
+            chain_id,
+            system_id,
+            type_id,
+            txn[0],
+            Some(vec![token_id]),
+            None,
+        )
+        .await;
+        db_fixtures::insert_protocol_state(
+            &mut conn,
+            component_id,
+            txn[0],
+            "reserve".to_string(),
+            Bytes::from("0x01"),
+            None,
+            Some(txn[1]),
+        )
+        .await;
+        db_fixtures::insert_protocol_state(
+            &mut conn,
+            component_id,
+            txn[1],
+            "reserve".to_string(),
+            Bytes::from("0x02"),
+            Some(Bytes::from("0x01")),
+            None,
+        )
+        .await;
+        db_fixtures::insert_component_balance(
+            &mut conn,
+            Bytes::from("0x10"),
+            Bytes::from("0x00"),
+            16.0,
+            token_id,
+            txn[1],
+            component_id,
+            None,
+        )
+        .await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+
+        let states = gw
+            .get_component_latest_states(
+                &Chain::Ethereum,
+                Some("ambient"),
+                Some(&["0xAABB"]),
+                &mut conn,
+            )
+            .await
+            .unwrap();
+        let balances = gw
+            .get_component_latest_balances(&Chain::Ethereum, None, None, &mut conn)
+            .await
+            .unwrap();
+        let other_system = gw
+            .get_component_latest_states(&Chain::Ethereum, Some("uniswap_v2"), None, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            states,
+            vec![ComponentLatestState {
+                component_id: "0xaabb".to_string(),
+                protocol_system: "ambient".to_string(),
+                attribute_name: "reserve".to_string(),
+                attribute_value: Bytes::from("0x02"),
+                modified_ts: db_fixtures::yesterday_half_past_midnight(),
+            }]
+        );
+        assert_eq!(
+            balances,
+            vec![ComponentLatestBalance {
+                component_id: "0xaabb".to_string(),
+                protocol_system: "ambient".to_string(),
+                token: Bytes::from("0x6b175474e89094c44da98b954eedeac495271d0f"),
+                symbol: "DAI".to_string(),
+                decimals: 18,
+                balance: Bytes::from("0x10"),
+                balance_float: 16.0,
+                modified_ts: db_fixtures::yesterday_half_past_midnight(),
+            }]
+        );
+        assert!(other_system.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+//! Resources consumed by API keys per month.
+//!
+//! Servers add the usage they metered since their last flush, so the stored totals cover all
+//! servers sharing the database and survive restarts.
+use diesel::{upsert::excluded, ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tracing::instrument;
+use tycho_common::storage::{ApiKeyUsage, StorageError};
+
+use super::{schema, PostgresError, PostgresGateway};
+
+impl PostgresGateway {
+    /// Adds `usage` to the usage stored for `month`.
+    #[instrument(skip(self, usage, conn))]
+    pub async fn add_api_key_usage(
+        &self,
+        month: &str,
+        usage: &[ApiKeyUsage],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::api_key_usage::dsl;
+
+        if usage.is_empty() {
+            return Ok(());
+        }
+        let values = usage
+            .iter()
+            .map(|u| {
+                (
+                    dsl::api_key.eq(&u.api_key),
+                    dsl::month.eq(month),
+                    dsl::requests.eq(u.requests as i64),
+                    dsl::rows_returned.eq(u.rows_returned as i64),
+                    dsl::bytes_returned.eq(u.bytes_returned as i64),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(dsl::api_key_usage)
+            .values(&values)
+            .on_conflict((dsl::month, dsl::api_key))
+            .do_update()
+            .set((
+                dsl::requests.eq(dsl::requests + excluded(dsl::requests)),
+                dsl::rows_returned.eq(dsl::rows_returned + excluded(dsl::rows_returned)),
+                dsl::bytes_returned.eq(dsl::bytes_returned + excluded(dsl::bytes_returned)),
+            ))
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+
+    /// Returns the usage stored for `month`, ordered by API key name.
+    #[instrument(skip(self, conn))]
+    pub async fn get_api_key_usage(
+        &self,
+        month: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<ApiKeyUsage>, StorageError> {
+        use schema::api_key_usage::dsl;
+
+        let rows = dsl::api_key_usage
+            .filter(dsl::month.eq(month))
+            .order_by(dsl::api_key)
+            .select((dsl::api_key, dsl::requests, dsl::rows_returned, dsl::bytes_returned))
+            .get_results::<(String, i64, i64, i64)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(rows
+            .into_iter()
+            .map(|(api_key, requests, rows_returned, bytes_returned)| ApiKeyUsage {
+                api_key,
+                requests: requests as u64,
+                rows_returned: rows_returned as u64,
+                bytes_returned: bytes_returned as u64,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+
+    fn usage(api_key: &str, requests: u64, rows_returned: u64, bytes_returned: u64) -> ApiKeyUsage {
+        ApiKeyUsage { api_key: api_key.to_string(), requests, rows_returned, bytes_returned }
+    }
+
+    #[tokio::test]
+    async fn test_api_key_usage_accumulates_per_month() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+
+        gw.add_api_key_usage("2024-01", &[usage("dashboard", 1, 10, 100)], &mut conn)
+            .await
+            .unwrap();
+        gw.add_api_key_usage(
+            "2024-01",
+            &[usage("dashboard", 2, 5, 50), usage("analytics", 1, 1, 1)],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        gw.add_api_key_usage("2024-02", &[usage("dashboard", 1, 1, 1)], &mut conn)
+            .await
+            .unwrap();
+
+        let january = gw
+            .get_api_key_usage("2024-01", &mut conn)
+            .await
+            .unwrap();
+        let march = gw
+            .get_api_key_usage("2024-03", &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(january, vec![usage("analytics", 1, 1, 1), usage("dashboard", 3, 15, 150)]);
+        assert!(march.is_empty());
+    }
+}
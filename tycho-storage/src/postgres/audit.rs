@@ -0,0 +1,276 @@
+//! Audit logs of admin actions and of destructive storage operations.
+//!
+//! Destructive operations, like reverts, prunes, repairs and deletions, record themselves within
+//! their own transaction, so an entry exists exactly if the operation committed. Entries are
+//! append only and never pruned by the indexer.
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tracing::instrument;
+use tycho_common::{
+    models::{Chain, PaginationParams},
+    storage::{AdminAction, AuditEntry, StorageError, WithTotal},
+};
+
+use super::{schema, PostgresError, PostgresGateway};
+
+/// A destructive operation about to be appended to the audit log, see [`record_operation`].
+#[derive(Debug)]
+pub(crate) struct AuditRecord<'a> {
+    operation: &'a str,
+    chain: Option<&'a Chain>,
+    target: Option<String>,
+    rows_affected: i64,
+    block_range: Option<(i64, i64)>,
+}
+
+impl<'a> AuditRecord<'a> {
+    pub(crate) fn new(operation: &'a str, rows_affected: i64) -> Self {
+        Self { operation, chain: None, target: None, rows_affected, block_range: None }
+    }
+
+    pub(crate) fn chain(mut self, chain: &'a Chain) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    pub(crate) fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// First and last block number the operation affected, inclusive.
+    pub(crate) fn block_range(mut self, start: i64, end: i64) -> Self {
+        self.block_range = Some((start, end));
+        self
+    }
+}
+
+/// Appends a destructive operation to the audit log.
+///
+/// The actor defaults to the extractor of the surrounding transaction, see the `tycho.extractor`
+/// setting, so callers should record within the transaction of the operation.
+pub(crate) async fn record_operation(
+    record: AuditRecord<'_>,
+    conn: &mut AsyncPgConnection,
+) -> Result<(), StorageError> {
+    use schema::audit_log::dsl;
+    diesel::insert_into(dsl::audit_log)
+        .values((
+            dsl::operation.eq(record.operation),
+            dsl::chain.eq(record.chain.map(Chain::to_string)),
+            dsl::target.eq(record.target),
+            dsl::rows_affected.eq(record.rows_affected),
+            dsl::start_block.eq(record
+                .block_range
+                .map(|(start, _)| start)),
+            dsl::end_block.eq(record.block_range.map(|(_, end)| end)),
+        ))
+        .execute(conn)
+        .await
+        .map_err(PostgresError::from)?;
+    Ok(())
+}
+
+impl PostgresGateway {
+    #[instrument(skip(self, conn))]
+    pub async fn record_admin_action(
+        &self,
+        action: &AdminAction,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        diesel::insert_into(schema::admin_audit_log::table)
+            .values((
+                schema::admin_audit_log::api_key.eq(&action.api_key),
+                schema::admin_audit_log::role.eq(&action.role),
+                schema::admin_audit_log::action.eq(&action.action),
+                schema::admin_audit_log::status.eq(i32::from(action.status)),
+            ))
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+
+    #[instrument(skip(self, conn))]
+    pub async fn get_audit_log(
+        &self,
+        operation: Option<&str>,
+        since: Option<NaiveDateTime>,
+        pagination_params: Option<&PaginationParams>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<WithTotal<Vec<AuditEntry>>, StorageError> {
+        use schema::audit_log::dsl;
+        let mut query = dsl::audit_log.into_boxed();
+        let mut count_query = dsl::audit_log.into_boxed();
+        if let Some(operation) = operation {
+            query = query.filter(dsl::operation.eq(operation));
+            count_query = count_query.filter(dsl::operation.eq(operation));
+        }
+        if let Some(since) = since {
+            query = query.filter(dsl::inserted_ts.ge(since));
+            count_query = count_query.filter(dsl::inserted_ts.ge(since));
+        }
+        if let Some(pagination) = pagination_params {
+            query = query
+                .limit(pagination.page_size)
+                .offset(pagination.offset());
+        }
+
+        let total = count_query
+            .count()
+            .get_result::<i64>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        #[allow(clippy::type_complexity)]
+        let rows: Vec<(
+            String,
+            String,
+            Option<String>,
+            Option<String>,
+            i64,
+            Option<i64>,
+            Option<i64>,
+            NaiveDateTime,
+        )> = query
+            .select((
+                dsl::operation,
+                dsl::actor,
+                dsl::chain,
+                dsl::target,
+                dsl::rows_affected,
+                dsl::start_block,
+                dsl::end_block,
+                dsl::inserted_ts,
+            ))
+            .order_by((dsl::inserted_ts.desc(), dsl::id.desc()))
+            .load(conn)
+            .await
+            .map_err(PostgresError::from)?;
+
+        let entries = rows
+            .into_iter()
+            .map(|(operation, actor, chain, target, rows_affected, start, end, ts)| {
+                let chain = chain
+                    .map(|name| {
+                        Chain::from_str(&name).map_err(|_| {
+                            StorageError::DecodeError(format!("Unknown chain {name} in audit log"))
+                        })
+                    })
+                    .transpose()?;
+                Ok(AuditEntry {
+                    operation,
+                    actor,
+                    chain,
+                    target,
+                    rows_affected,
+                    block_range: start.zip(end),
+                    ts,
+                })
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        Ok(WithTotal { entity: entries, total: Some(total) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_admin_action() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        let action = AdminAction {
+            api_key: "ops".to_string(),
+            role: "operator".to_string(),
+            action: "POST /v1/admin/component_filter".to_string(),
+            status: 200,
+        };
+
+        gw.record_admin_action(&action, &mut conn)
+            .await
+            .unwrap();
+
+        let recorded = schema::admin_audit_log::table
+            .select((
+                schema::admin_audit_log::api_key,
+                schema::admin_audit_log::role,
+                schema::admin_audit_log::action,
+                schema::admin_audit_log::status,
+            ))
+            .get_results::<(String, String, String, i32)>(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            recorded,
+            vec![(
+                "ops".to_string(),
+                "operator".to_string(),
+                "POST /v1/admin/component_filter".to_string(),
+                200
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_audit_log() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        diesel::sql_query("SELECT set_config('tycho.extractor', 'vm:ambient', true)")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        record_operation(
+            AuditRecord::new("revert_state", 3)
+                .chain(&Chain::Ethereum)
+                .block_range(2, 4),
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        record_operation(AuditRecord::new("delete_token_overrides", 1).target("0x01"), &mut conn)
+            .await
+            .unwrap();
+
+        let all = gw
+            .get_audit_log(None, None, Some(&PaginationParams::new(0, 1)), &mut conn)
+            .await
+            .unwrap();
+        let reverts = gw
+            .get_audit_log(Some("revert_state"), None, None, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(all.total, Some(2));
+        assert_eq!(all.entity.len(), 1);
+        assert_eq!(reverts.total, Some(1));
+        let entry = &reverts.entity[0];
+        assert_eq!(
+            (
+                entry.actor.as_str(),
+                entry.chain,
+                entry.target.clone(),
+                entry.rows_affected,
+                entry.block_range
+            ),
+            ("vm:ambient", Some(Chain::Ethereum), None, 3, Some((2, 4)))
+        );
+    }
+}
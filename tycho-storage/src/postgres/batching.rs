@@ -0,0 +1,265 @@
+//! Splits batch writes into statements Postgres accepts.
+//!
+//! A statement can carry at most [`MAX_BIND_PARAMS`] bind parameters, the protocol encodes their
+//! count as an `i16`. Blocks touching many slots, states or balances easily exceed this when
+//! written with a single multi row `INSERT`, so callers iterate over [`batches`] instead. Next to
+//! the parameter count each batch is capped at [`MAX_BATCH_BYTES`] of bound payload, which keeps
+//! rows with large values (e.g. contract code) from building statements of hundreds of megabytes.
+use super::orm;
+
+/// Maximum number of bind parameters a single Postgres statement may carry.
+pub(crate) const MAX_BIND_PARAMS: usize = u16::MAX as usize;
+
+/// Maximum number of payload bytes bound by a single batch statement.
+pub(crate) const MAX_BATCH_BYTES: usize = 32 * 1024 * 1024;
+
+/// Bytes bound by any fixed size value: integers, floats and timestamps.
+const FIXED_VALUE_SIZE: usize = 8;
+
+/// A row written as part of a multi row statement.
+pub(crate) trait BatchRow {
+    /// Number of bind parameters the row adds to the statement.
+    const BIND_PARAMS: usize;
+
+    /// Approximate number of bytes the row binds.
+    fn payload_size(&self) -> usize;
+}
+
+/// Splits `rows` into consecutive batches within the statement limits.
+///
+/// Every batch contains at least one row, a single row exceeding the payload budget is written
+/// on its own.
+pub(crate) fn batches<T: BatchRow>(rows: &[T]) -> Batches<'_, T> {
+    Batches::new(rows, MAX_BIND_PARAMS, MAX_BATCH_BYTES)
+}
+
+/// Splits `items` into chunks binding at most [`MAX_BIND_PARAMS`] parameters each, for
+/// statements whose rows bind `params_per_item` fixed size values.
+pub(crate) fn param_chunks<T>(items: &[T], params_per_item: usize) -> std::slice::Chunks<'_, T> {
+    items.chunks((MAX_BIND_PARAMS / params_per_item.max(1)).max(1))
+}
+
+pub(crate) struct Batches<'a, T> {
+    rows: &'a [T],
+    max_params: usize,
+    max_bytes: usize,
+}
+
+impl<'a, T: BatchRow> Batches<'a, T> {
+    fn new(rows: &'a [T], max_params: usize, max_bytes: usize) -> Self {
+        Self { rows, max_params, max_bytes }
+    }
+}
+
+impl<'a, T: BatchRow> Iterator for Batches<'a, T> {
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rows.is_empty() {
+            return None;
+        }
+        let max_rows = (self.max_params / T::BIND_PARAMS).max(1);
+        let mut bytes = 0;
+        let mut n = 0;
+        for row in self.rows.iter().take(max_rows) {
+            bytes += row.payload_size();
+            if n > 0 && bytes > self.max_bytes {
+                break;
+            }
+            n += 1;
+        }
+        let (batch, rest) = self.rows.split_at(n);
+        self.rows = rest;
+        Some(batch)
+    }
+}
+
+impl BatchRow for orm::NewSlot {
+    const BIND_PARAMS: usize = 8;
+
+    fn payload_size(&self) -> usize {
+        self.slot.len() +
+            self.value
+                .as_ref()
+                .map_or(0, |v| v.len()) +
+            self.previous_value
+                .as_ref()
+                .map_or(0, |v| v.len()) +
+            5 * FIXED_VALUE_SIZE
+    }
+}
+
+impl BatchRow for orm::NewSlotLatest {
+    const BIND_PARAMS: usize = 8;
+
+    fn payload_size(&self) -> usize {
+        self.slot.len() +
+            self.value
+                .as_ref()
+                .map_or(0, |v| v.len()) +
+            self.previous_value
+                .as_ref()
+                .map_or(0, |v| v.len()) +
+            5 * FIXED_VALUE_SIZE
+    }
+}
+
+impl BatchRow for orm::NewProtocolState {
+    const BIND_PARAMS: usize = 7;
+
+    fn payload_size(&self) -> usize {
+        self.attribute_name.len() +
+            self.attribute_value.len() +
+            self.previous_value
+                .as_ref()
+                .map_or(0, |v| v.len()) +
+            4 * FIXED_VALUE_SIZE
+    }
+}
+
+impl BatchRow for orm::NewProtocolStateLatest {
+    const BIND_PARAMS: usize = 7;
+
+    fn payload_size(&self) -> usize {
+        self.attribute_name.len() +
+            self.attribute_value.len() +
+            self.previous_value
+                .as_ref()
+                .map_or(0, |v| v.len()) +
+            4 * FIXED_VALUE_SIZE
+    }
+}
+
+impl BatchRow for orm::NewComponentBalance {
+    const BIND_PARAMS: usize = 8;
+
+    fn payload_size(&self) -> usize {
+        self.new_balance.len() + self.previous_value.len() + 6 * FIXED_VALUE_SIZE
+    }
+}
+
+impl BatchRow for orm::NewComponentBalanceLatest {
+    const BIND_PARAMS: usize = 8;
+
+    fn payload_size(&self) -> usize {
+        self.new_balance.len() + self.previous_value.len() + 6 * FIXED_VALUE_SIZE
+    }
+}
+
+impl BatchRow for orm::NewAccountBalance {
+    const BIND_PARAMS: usize = 6;
+
+    fn payload_size(&self) -> usize {
+        self.balance.len() + 5 * FIXED_VALUE_SIZE
+    }
+}
+
+impl BatchRow for orm::NewContractCode<'_> {
+    const BIND_PARAMS: usize = 6;
+
+    fn payload_size(&self) -> usize {
+        self.code.len() + self.hash.len() + 4 * FIXED_VALUE_SIZE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tycho_common::Bytes;
+
+    use super::*;
+    use crate::postgres::MAX_TS;
+
+    fn slot_rows(n: usize, value_size: usize) -> Vec<orm::NewSlotLatest> {
+        (0..n)
+            .map(|i| orm::NewSlotLatest {
+                slot: Bytes::from((i as u64).to_be_bytes().to_vec()).lpad(32, 0),
+                value: Some(Bytes::from(vec![1u8; value_size])),
+                previous_value: None,
+                account_id: 1,
+                modify_tx: 1,
+                ordinal: i as i64,
+                valid_from: MAX_TS,
+                valid_to: MAX_TS,
+            })
+            .collect()
+    }
+
+    fn assert_within_limits<T: BatchRow>(rows: &[T], max_params: usize, max_bytes: usize) {
+        let mut n_rows = 0;
+        for batch in Batches::new(rows, max_params, max_bytes) {
+            assert!(!batch.is_empty());
+            assert!(batch.len() * T::BIND_PARAMS <= max_params);
+            if batch.len() > 1 {
+                let bytes: usize = batch.iter().map(T::payload_size).sum();
+                assert!(bytes <= max_bytes);
+            }
+            n_rows += batch.len();
+        }
+        assert_eq!(n_rows, rows.len());
+    }
+
+    #[test]
+    fn test_batches_100k_slots_by_bind_params() {
+        let rows = slot_rows(100_000, 32);
+
+        let res = batches(&rows).collect::<Vec<_>>();
+
+        let max_rows = MAX_BIND_PARAMS / orm::NewSlotLatest::BIND_PARAMS;
+        assert_eq!(res.len(), 100_000usize.div_ceil(max_rows));
+        assert!(res[..res.len() - 1]
+            .iter()
+            .all(|b| b.len() == max_rows));
+        assert_within_limits(&rows, MAX_BIND_PARAMS, MAX_BATCH_BYTES);
+        // batches are consecutive and preserve the row order
+        let ordinals = res
+            .iter()
+            .flat_map(|b| b.iter().map(|r| r.ordinal))
+            .collect::<Vec<_>>();
+        assert_eq!(ordinals, (0..100_000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_batches_100k_slots_by_payload() {
+        let rows = slot_rows(100_000, 1_024);
+        let max_bytes = 1024 * 1024;
+
+        let res = Batches::new(&rows, MAX_BIND_PARAMS, max_bytes).collect::<Vec<_>>();
+
+        let row_size = rows[0].payload_size();
+        assert_eq!(res[0].len(), max_bytes / row_size);
+        assert_within_limits(&rows, MAX_BIND_PARAMS, max_bytes);
+    }
+
+    #[test]
+    fn test_batches_oversized_row_alone() {
+        let rows = slot_rows(3, 2_048);
+
+        let res = Batches::new(&rows, MAX_BIND_PARAMS, 1_024).collect::<Vec<_>>();
+
+        assert_eq!(
+            res.iter()
+                .map(|b| b.len())
+                .collect::<Vec<_>>(),
+            vec![1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn test_batches_empty() {
+        let rows = slot_rows(0, 32);
+
+        assert_eq!(batches(&rows).count(), 0);
+    }
+
+    #[test]
+    fn test_param_chunks() {
+        let items = vec![0u8; 100_000];
+
+        let res = param_chunks(&items, 2).collect::<Vec<_>>();
+
+        assert_eq!(res.len(), 4);
+        assert!(res
+            .iter()
+            .all(|c| c.len() * 2 <= MAX_BIND_PARAMS));
+    }
+}
@@ -0,0 +1,105 @@
+//! Gateway access for the storage benchmarks.
+//!
+//! The benchmarks time single gateway calls on a connection inside a test transaction, which the
+//! public gateway types don't allow. [`BenchGateway`] exposes only the calls they measure.
+//!
+//! Only available with the `bench` feature.
+use diesel_async::AsyncPgConnection;
+use tycho_common::{
+    models::{
+        blockchain::{Block, Transaction},
+        contract::{Account, AccountDelta},
+        protocol::{ComponentBalance, ProtocolComponentState, ProtocolComponentStateDelta},
+        Address, Chain, TxHash,
+    },
+    storage::{StorageError, Version, WithTotal},
+};
+
+use super::PostgresGateway;
+
+/// Thin wrapper around the internal Postgres gateway.
+#[derive(Clone)]
+pub struct BenchGateway(PostgresGateway);
+
+impl BenchGateway {
+    pub async fn from_connection(conn: &mut AsyncPgConnection) -> Self {
+        Self(PostgresGateway::from_connection(conn).await)
+    }
+
+    pub async fn upsert_block(
+        &self,
+        blocks: &[Block],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        self.0.upsert_block(blocks, conn).await
+    }
+
+    pub async fn upsert_tx(
+        &self,
+        new: &[Transaction],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        self.0.upsert_tx(new, conn).await
+    }
+
+    pub async fn update_contracts(
+        &self,
+        chain: &Chain,
+        new: &[(TxHash, &AccountDelta)],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        self.0
+            .update_contracts(chain, new, conn)
+            .await
+    }
+
+    pub async fn update_protocol_states(
+        &self,
+        chain: &Chain,
+        new: &[(TxHash, &ProtocolComponentStateDelta)],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        self.0
+            .update_protocol_states(chain, new, conn)
+            .await
+    }
+
+    pub async fn add_component_balances(
+        &self,
+        component_balances: &[ComponentBalance],
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        self.0
+            .add_component_balances(component_balances, chain, conn)
+            .await
+    }
+
+    /// Contracts of `chain` at `version`, see the `ContractStateGateway`.
+    pub async fn get_contracts(
+        &self,
+        chain: &Chain,
+        ids: Option<&[Address]>,
+        version: Option<&Version>,
+        include_slots: bool,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<WithTotal<Vec<Account>>, StorageError> {
+        self.0
+            .get_contracts(chain, ids, version, include_slots, None, None, conn)
+            .await
+    }
+
+    /// Protocol states of `chain` at `version`, see the `ProtocolGateway`.
+    pub async fn get_protocol_states(
+        &self,
+        chain: &Chain,
+        at: Option<Version>,
+        ids: Option<&[&str]>,
+        retrieve_balances: bool,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<WithTotal<Vec<ProtocolComponentState>>, StorageError> {
+        self.0
+            .get_protocol_states(chain, at, None, ids, retrieve_balances, None, conn)
+            .await
+    }
+}
@@ -1,10 +1,24 @@
+use std::{sync::Arc, time::Duration};
+
 use chrono::NaiveDateTime;
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection};
 use tokio::{sync::mpsc, task::JoinHandle};
 use tycho_common::{models::Chain, storage::StorageError};
 
 use crate::{
     postgres,
-    postgres::{cache::CachedGateway, direct::DirectGateway, PostgresGateway},
+    postgres::{
+        cache::{CachedGateway, DryRunWriteExecutor},
+        cold_storage::{ColdStorageConfig, ColdStorageOffloader, ColdStore},
+        component_activity::{ComponentActivityConfig, ComponentActivityMonitor},
+        db_growth::{DbGrowthConfig, DbGrowthMonitor},
+        direct::DirectGateway,
+        head_cache::HeadCacheBudget,
+        maintenance::{OrphanedAccountCollector, OrphanedAccountsConfig},
+        scheduler::{LeaderElection, Schedule, Scheduler, SchedulerConfig},
+        storage_compaction::{StorageCompactionConfig, StorageCompactor},
+        PostgresGateway,
+    },
 };
 
 #[derive(Default)]
@@ -13,6 +27,25 @@ pub struct GatewayBuilder {
     protocol_systems: Vec<String>,
     retention_horizon: NaiveDateTime,
     chains: Vec<Chain>,
+    cold_store: Option<Arc<dyn ColdStore>>,
+    cold_storage_offload: Option<ColdStorageConfig>,
+    component_activity: Option<ComponentActivityConfig>,
+    storage_compaction: Option<StorageCompactionConfig>,
+    db_growth: Option<DbGrowthConfig>,
+    orphaned_accounts: Option<OrphanedAccountsConfig>,
+    scheduler: SchedulerConfig,
+    head_cache_budget: Option<HeadCacheBudget>,
+    component_state_cache_budget: Option<HeadCacheBudget>,
+    statement_timeout: Option<Duration>,
+    pool_size: Option<usize>,
+    query_deadline: Option<Duration>,
+    slow_query_threshold: Option<Duration>,
+    notify_changes: bool,
+    strict_writes: bool,
+    repair_mode: bool,
+    dry_run: bool,
+    #[cfg(any(test, feature = "fault-injection"))]
+    fault_injector: Option<Arc<postgres::fault_injection::FaultInjector>>,
 }
 
 impl GatewayBuilder {
@@ -35,18 +68,190 @@ impl GatewayBuilder {
         self
     }
 
+    /// Sets the object store holding offloaded contract data, reads fetch offloaded data from it.
+    ///
+    /// Must be set on every gateway reading from a database with offloaded data.
+    pub fn set_cold_store(mut self, store: Arc<dyn ColdStore>) -> Self {
+        self.cold_store = Some(store);
+        self
+    }
+
+    /// Enables periodically moving aged contract data to the cold store. Only takes effect with
+    /// [`GatewayBuilder::build`] and if a cold store is set.
+    pub fn set_cold_storage_offload(mut self, config: ColdStorageConfig) -> Self {
+        self.cold_storage_offload = Some(config);
+        self
+    }
+
+    /// Enables periodically flagging components without recent state or balance changes as
+    /// inactive. Only takes effect with [`GatewayBuilder::build`].
+    pub fn set_component_activity(mut self, config: ComponentActivityConfig) -> Self {
+        self.component_activity = Some(config);
+        self
+    }
+
+    /// Enables periodically deleting contract storage rows of cleared slots once a new value
+    /// superseded them before the finality horizon. Only takes effect with
+    /// [`GatewayBuilder::build`].
+    pub fn set_storage_compaction(mut self, config: StorageCompactionConfig) -> Self {
+        self.storage_compaction = Some(config);
+        self
+    }
+
+    /// Enables periodically comparing table sizes against budgets, see [`postgres::db_growth`].
+    /// Only takes effect with [`GatewayBuilder::build`].
+    pub fn set_db_growth(mut self, config: DbGrowthConfig) -> Self {
+        self.db_growth = Some(config);
+        self
+    }
+
+    /// Enables periodically deleting accounts no longer referenced by any component, token or
+    /// traced entry point, see [`postgres::maintenance::collect_orphaned_accounts`]. Only takes
+    /// effect with [`GatewayBuilder::build`].
+    pub fn set_orphaned_account_collection(mut self, config: OrphanedAccountsConfig) -> Self {
+        self.orphaned_accounts = Some(config);
+        self
+    }
+
+    /// Configures the scheduler running the periodic maintenance tasks, see
+    /// [`postgres::scheduler`]. Tasks are named `cold_storage_offload`, `component_activity`,
+    /// `storage_compaction`, `db_growth` and `orphaned_accounts`, all but `db_growth` are
+    /// singletons. Only takes effect with [`GatewayBuilder::build`].
+    pub fn set_scheduler(mut self, config: SchedulerConfig) -> Self {
+        self.scheduler = config;
+        self
+    }
+
+    /// Bounds the memory of the cache of latest contract states, see
+    /// [`postgres::head_cache`]. Defaults to [`HeadCacheBudget::default`].
+    pub fn set_head_cache_budget(mut self, budget: HeadCacheBudget) -> Self {
+        self.head_cache_budget = Some(budget);
+        self
+    }
+
+    /// Bounds the memory of the cache of latest protocol component states, kept separately
+    /// from the contract state budget.
+    pub fn set_component_state_cache_budget(mut self, budget: HeadCacheBudget) -> Self {
+        self.component_state_cache_budget = Some(budget);
+        self
+    }
+
+    /// Cancels any statement running longer than `timeout`, applied to every pooled connection.
+    pub fn set_statement_timeout(mut self, timeout: Duration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits the connections of the pool to `size`, by default four per CPU.
+    pub fn set_pool_size(mut self, size: usize) -> Self {
+        self.pool_size = Some(size);
+        self
+    }
+
+    /// Cancels statements of expensive reads, like deltas and version ranges, running longer
+    /// than `deadline`. Usually shorter than the statement timeout.
+    pub fn set_query_deadline(mut self, deadline: Duration) -> Self {
+        self.query_deadline = Some(deadline);
+        self
+    }
+
+    /// Logs reads of components, tokens and contracts taking longer than `threshold` together with
+    /// their SQL.
+    pub fn set_slow_query_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// Announces every committed write with a Postgres notification, see
+    /// [`postgres::notify`]. Only takes effect with [`GatewayBuilder::build`].
+    pub fn set_notify_changes(mut self, notify_changes: bool) -> Self {
+        self.notify_changes = notify_changes;
+        self
+    }
+
+    /// Checks that every transaction, account, token and component a write references is either
+    /// stored or written by the same batch before writing it. Batches failing the check are
+    /// rejected with [`StorageError::MissingReferences`] listing all missing references. Only
+    /// takes effect with [`GatewayBuilder::build`].
+    pub fn set_strict_writes(mut self, strict_writes: bool) -> Self {
+        self.strict_writes = strict_writes;
+        self
+    }
+
+    /// Accepts writes of blocks that don't follow the block stored with the extraction state of
+    /// the writing extractor, for repairs deliberately rewriting its history. Otherwise such
+    /// writes are rejected with [`StorageError::OutOfOrderBlock`]. Only takes effect with
+    /// [`GatewayBuilder::build`].
+    pub fn set_repair_mode(mut self, repair_mode: bool) -> Self {
+        self.repair_mode = repair_mode;
+        self
+    }
+
+    /// Builds a gateway that reads from the database but never writes to it, see
+    /// [`DryRunWriteExecutor`]. Only takes effect with [`GatewayBuilder::build`].
+    ///
+    /// Chains and protocol systems are not inserted either, so they must already exist.
+    pub fn set_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Injects the faults of `injector` into every write of the write cache, to test how its
+    /// clients recover. Only takes effect with [`GatewayBuilder::build`].
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn set_fault_injector(
+        mut self,
+        injector: Arc<postgres::fault_injection::FaultInjector>,
+    ) -> Self {
+        self.fault_injector = Some(injector);
+        self
+    }
+
+    async fn postgres_gateway(
+        &self,
+        pool: &Pool<AsyncPgConnection>,
+    ) -> Result<PostgresGateway, StorageError> {
+        let mut gw = PostgresGateway::new(pool.clone(), self.retention_horizon).await?;
+        if let Some(store) = &self.cold_store {
+            gw = gw.with_cold_store(store.clone());
+        }
+        if let Some(deadline) = self.query_deadline {
+            gw = gw.with_query_deadline(deadline);
+        }
+        if let Some(threshold) = self.slow_query_threshold {
+            gw = gw.with_slow_query_threshold(threshold);
+        }
+        Ok(gw)
+    }
+
     pub async fn build(self) -> Result<(CachedGateway, JoinHandle<()>), StorageError> {
-        let pool = postgres::connect(&self.database_url).await?;
-        postgres::ensure_chains(&self.chains, pool.clone()).await;
-        postgres::ensure_protocol_systems(&self.protocol_systems, pool.clone()).await;
+        let pool =
+            postgres::connect(&self.database_url, self.statement_timeout, self.pool_size).await?;
+        if !self.dry_run {
+            postgres::ensure_chains(&self.chains, pool.clone()).await;
+            postgres::ensure_protocol_systems(&self.protocol_systems, pool.clone()).await;
+        }
 
-        let inner_gw = PostgresGateway::new(pool.clone(), self.retention_horizon).await?;
+        let inner_gw = self.postgres_gateway(&pool).await?;
         let (tx, rx) = mpsc::channel(10);
         let chain = self
             .chains
             .first()
             .expect("No chains provided"); //TODO: handle multichain?
-        let write_executor = postgres::cache::DBCacheWriteExecutor::new(
+
+        if self.dry_run {
+            let handle = DryRunWriteExecutor::new(chain.to_string(), rx).run();
+            let mut cached_gw = CachedGateway::new(tx, pool.clone(), inner_gw).with_dry_run();
+            if let Some(budget) = self.head_cache_budget {
+                cached_gw = cached_gw.with_head_cache_budget(budget);
+            }
+            if let Some(budget) = self.component_state_cache_budget {
+                cached_gw = cached_gw.with_component_state_cache_budget(budget);
+            }
+            return Ok((cached_gw, handle));
+        }
+
+        let mut write_executor = postgres::cache::DBCacheWriteExecutor::new(
             chain.to_string(),
             *chain,
             pool.clone(),
@@ -54,28 +259,102 @@ impl GatewayBuilder {
             rx,
         )
         .await;
+        if self.notify_changes {
+            write_executor = write_executor.with_change_notifications();
+        }
+        if self.strict_writes {
+            write_executor = write_executor.with_strict_writes();
+        }
+        if self.repair_mode {
+            write_executor = write_executor.with_repair_mode();
+        }
+        #[cfg(any(test, feature = "fault-injection"))]
+        if let Some(injector) = self.fault_injector.clone() {
+            write_executor = write_executor.with_fault_injector(injector);
+        }
         let handle = write_executor.run();
 
-        let cached_gw = CachedGateway::new(tx, pool.clone(), inner_gw.clone());
+        let mut scheduler = Scheduler::new(
+            self.scheduler.clone(),
+            LeaderElection::new(&self.database_url, &format!("maintenance:{chain}")),
+        );
+        if let (Some(_), Some(config)) = (&self.cold_store, self.cold_storage_offload) {
+            scheduler.register(
+                "cold_storage_offload",
+                Schedule::Every(config.interval),
+                true,
+                ColdStorageOffloader::new(pool.clone(), inner_gw.clone(), config),
+            );
+        }
+        if let Some(config) = self.component_activity {
+            scheduler.register(
+                "component_activity",
+                Schedule::Every(config.interval),
+                true,
+                ComponentActivityMonitor::new(pool.clone(), inner_gw.clone(), *chain, config),
+            );
+        }
+        if let Some(config) = self.storage_compaction {
+            scheduler.register(
+                "storage_compaction",
+                Schedule::Every(config.interval),
+                true,
+                StorageCompactor::new(pool.clone(), inner_gw.clone(), *chain, config),
+            );
+        }
+        if let Some(config) = self.db_growth {
+            // Every replica skips zero writes on its own, so every replica checks the budgets.
+            scheduler.register(
+                "db_growth",
+                Schedule::Every(config.interval),
+                false,
+                DbGrowthMonitor::new(pool.clone(), config, inner_gw.skip_zero_slot_writes.clone()),
+            );
+        }
+        if let Some(config) = self.orphaned_accounts {
+            scheduler.register(
+                "orphaned_accounts",
+                Schedule::Every(config.interval),
+                true,
+                OrphanedAccountCollector::new(&self.database_url, config),
+            );
+        }
+        scheduler.run();
+
+        let mut cached_gw = CachedGateway::new(tx, pool.clone(), inner_gw.clone());
+        if let Some(budget) = self.head_cache_budget {
+            cached_gw = cached_gw.with_head_cache_budget(budget);
+        }
+        if let Some(budget) = self.component_state_cache_budget {
+            cached_gw = cached_gw.with_component_state_cache_budget(budget);
+        }
         Ok((cached_gw, handle))
     }
 
     pub async fn build_gw(self) -> Result<CachedGateway, StorageError> {
-        let pool = postgres::connect(&self.database_url).await?;
+        let pool =
+            postgres::connect(&self.database_url, self.statement_timeout, self.pool_size).await?;
 
-        let inner_gw = PostgresGateway::new(pool.clone(), self.retention_horizon).await?;
+        let inner_gw = self.postgres_gateway(&pool).await?;
         let (tx, _) = mpsc::channel(10);
 
-        let cached_gw = CachedGateway::new(tx, pool.clone(), inner_gw.clone());
+        let mut cached_gw = CachedGateway::new(tx, pool.clone(), inner_gw.clone());
+        if let Some(budget) = self.head_cache_budget {
+            cached_gw = cached_gw.with_head_cache_budget(budget);
+        }
+        if let Some(budget) = self.component_state_cache_budget {
+            cached_gw = cached_gw.with_component_state_cache_budget(budget);
+        }
         Ok(cached_gw)
     }
 
     pub async fn build_direct_gw(self) -> Result<DirectGateway, StorageError> {
-        let pool = postgres::connect(&self.database_url).await?;
+        let pool =
+            postgres::connect(&self.database_url, self.statement_timeout, self.pool_size).await?;
         postgres::ensure_chains(&self.chains, pool.clone()).await;
         postgres::ensure_protocol_systems(&self.protocol_systems, pool.clone()).await;
 
-        let inner_gw = PostgresGateway::new(pool.clone(), self.retention_horizon).await?;
+        let inner_gw = self.postgres_gateway(&pool).await?;
 
         let chain = self
             .chains
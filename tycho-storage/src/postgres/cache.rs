@@ -1,46 +1,66 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     num::NonZeroUsize,
+    ops::RangeInclusive,
+    slice,
     sync::Arc,
 };
 
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
 use diesel_async::{
-    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
-    AsyncPgConnection,
+    pooled_connection::deadpool::{Object, Pool},
+    scoped_futures::ScopedFutureExt,
+    AsyncConnection, AsyncPgConnection, RunQueryDsl,
 };
+use itertools::Itertools;
 use lru::LruCache;
 use tokio::{
     sync::{mpsc, oneshot, Mutex},
     task::JoinHandle,
 };
-use tracing::{debug, info, info_span, instrument, trace, warn, Instrument};
+use tracing::{debug, error, info, info_span, instrument, trace, warn, Instrument};
 use tycho_common::{
     models::{
         self,
         blockchain::{
-            Block, EntryPoint, EntryPointWithTracingParams, TracedEntryPoint, TracingParams,
-            TracingResult, Transaction,
+            Block, BlockDigest, EntryPoint, EntryPointWithTracingParams, FinalityStatus,
+            QuarantinedMessage, TracedEntryPoint, TracingParams, TracingResult, Transaction,
         },
-        contract::{Account, AccountBalance, AccountDelta},
+        contract::{Account, AccountBalance, AccountDelta, SlotAnnotation, TrackedContract},
         protocol::{
-            ComponentBalance, ProtocolComponent, ProtocolComponentState,
-            ProtocolComponentStateDelta, QualityRange,
+            BalanceSample, ComponentBalance, ComponentEvent, ComponentLatestBalance,
+            ComponentLatestState, ProtocolComponent, ProtocolComponentState,
+            ProtocolComponentStateDelta, ProtocolComponentWithContracts, ProtocolDeployment,
+            ProtocolStatesDelta, ProtocolSystemSummary, QualityRange, SampleInterval,
         },
-        token::Token,
-        Address, Chain, ComponentId, ContractId, EntryPointId, ExtractionState, PaginationParams,
-        ProtocolType, TxHash,
+        token::{Token, TokenOverride},
+        Address, AttrStoreKey, Balance, Chain, Code, CodeHash, ComponentCursor, ComponentId,
+        ContractId, EntryPointId, ExtractionState, PaginationParams, ProtocolType, StoreVal,
+        TxHash,
     },
     storage::{
-        BlockIdentifier, BlockOrTimestamp, ChainGateway, ContractStateGateway, EntryPointFilter,
-        EntryPointGateway, ExtractionStateGateway, Gateway, ProtocolGateway, StorageError, Version,
-        WithTotal,
+        AdminAction, AnalyticsRows, ApiKeyUsage, AuditEntry, BlockIdentifier, BlockOrTimestamp,
+        ChainGateway, ContractFilter, ContractStateGateway, EntryPointFilter, EntryPointGateway,
+        ExtractionStateGateway, Gateway, IdempotencyClaim, IdempotentResponse, ProtocolGateway,
+        StorageError, Version, VersionKind, WithTotal,
     },
     Bytes,
 };
 
-use super::{PostgresError, PostgresGateway};
+use super::{
+    head_cache::{
+        ComponentStateCache, HeadCacheBudget, HeadStateCache, COMPONENT_STATE_CACHE_DEFAULT_MB,
+    },
+    notify::{self, ChangeNotification},
+    PostgresError, PostgresGateway,
+};
+
+/// Maximum number of contracts kept in the head state cache.
+const HEAD_CACHE_CAPACITY: usize = 10_000;
+
+/// Maximum number of protocol components kept in the component state cache.
+const COMPONENT_STATE_CACHE_CAPACITY: usize = 50_000;
 
 /// Represents different types of database write operations.
 #[derive(PartialEq, Clone, Debug)]
@@ -49,6 +69,8 @@ pub(crate) enum WriteOp {
     UpsertBlock(Vec<models::blockchain::Block>),
     // Simply merge
     UpsertTx(Vec<models::blockchain::Transaction>),
+    // Simply merge
+    UpsertBlockDigests(Vec<models::blockchain::BlockDigest>),
     // Simply keep last
     SaveExtractionState(ExtractionState),
     // Support saving a batch
@@ -69,6 +91,8 @@ pub(crate) enum WriteOp {
     // Simply merge
     UpsertProtocolState(Vec<(TxHash, models::protocol::ProtocolComponentStateDelta)>),
     // Simply merge
+    InsertComponentEvents(Vec<models::protocol::ComponentEvent>),
+    // Simply merge
     InsertEntryPoints(HashMap<models::ComponentId, HashSet<models::blockchain::EntryPoint>>),
     // Simply merge
     InsertEntryPointTracingParams(
@@ -83,6 +107,7 @@ impl WriteOp {
         match self {
             WriteOp::UpsertBlock(_) => "UpsertBlock",
             WriteOp::UpsertTx(_) => "UpsertTx",
+            WriteOp::UpsertBlockDigests(_) => "UpsertBlockDigests",
             WriteOp::SaveExtractionState(_) => "SaveExtractionState",
             WriteOp::InsertContract(_) => "InsertContract",
             WriteOp::UpdateContracts(_) => "UpdateContracts",
@@ -92,12 +117,35 @@ impl WriteOp {
             WriteOp::UpdateTokens(_) => "UpdateTokens",
             WriteOp::InsertComponentBalances(_) => "InsertComponentBalances",
             WriteOp::UpsertProtocolState(_) => "UpsertProtocolState",
+            WriteOp::InsertComponentEvents(_) => "InsertComponentEvents",
             WriteOp::InsertEntryPoints(_) => "InsertEntryPoints",
             WriteOp::InsertEntryPointTracingParams(_) => "InsertEntryPointTracingParams",
             WriteOp::UpsertTracedEntryPoints(_) => "UpsertTracedEntryPoints",
         }
     }
 
+    /// Number of entities written by this operation.
+    fn n_items(&self) -> usize {
+        match self {
+            WriteOp::UpsertBlock(v) => v.len(),
+            WriteOp::UpsertTx(v) => v.len(),
+            WriteOp::UpsertBlockDigests(v) => v.len(),
+            WriteOp::SaveExtractionState(_) => 1,
+            WriteOp::InsertContract(v) => v.len(),
+            WriteOp::UpdateContracts(v) => v.len(),
+            WriteOp::InsertAccountBalances(v) => v.len(),
+            WriteOp::InsertProtocolComponents(v) => v.len(),
+            WriteOp::InsertTokens(v) => v.len(),
+            WriteOp::UpdateTokens(v) => v.len(),
+            WriteOp::InsertComponentBalances(v) => v.len(),
+            WriteOp::UpsertProtocolState(v) => v.len(),
+            WriteOp::InsertComponentEvents(v) => v.len(),
+            WriteOp::InsertEntryPoints(v) => v.values().map(HashSet::len).sum(),
+            WriteOp::InsertEntryPointTracingParams(v) => v.values().map(HashSet::len).sum(),
+            WriteOp::UpsertTracedEntryPoints(v) => v.len(),
+        }
+    }
+
     fn order_key(&self) -> usize {
         match self {
             WriteOp::UpsertBlock(_) => 0,
@@ -110,10 +158,12 @@ impl WriteOp {
             WriteOp::InsertProtocolComponents(_) => 7,
             WriteOp::InsertComponentBalances(_) => 8,
             WriteOp::UpsertProtocolState(_) => 9,
-            WriteOp::InsertEntryPoints(_) => 10,
-            WriteOp::InsertEntryPointTracingParams(_) => 11,
-            WriteOp::UpsertTracedEntryPoints(_) => 12,
-            WriteOp::SaveExtractionState(_) => 13,
+            WriteOp::InsertComponentEvents(_) => 10,
+            WriteOp::InsertEntryPoints(_) => 11,
+            WriteOp::InsertEntryPointTracingParams(_) => 12,
+            WriteOp::UpsertTracedEntryPoints(_) => 13,
+            WriteOp::UpsertBlockDigests(_) => 14,
+            WriteOp::SaveExtractionState(_) => 15,
         }
     }
 }
@@ -149,6 +199,8 @@ pub struct DBTransaction {
     tx: oneshot::Sender<Result<(), StorageError>>,
     /// Purely used to add an attribute to the span when the transaction is commited
     owner: Option<String>,
+    /// Instance that must hold the lease on the owning extractor for the transaction to commit.
+    lease_holder: Option<String>,
 }
 
 impl DBTransaction {
@@ -171,6 +223,11 @@ impl DBTransaction {
                     l.extend(r.iter().cloned());
                     return Ok(());
                 }
+                (WriteOp::UpsertBlockDigests(l), WriteOp::UpsertBlockDigests(r)) => {
+                    self.size += r.len();
+                    l.extend(r.iter().cloned());
+                    return Ok(());
+                }
                 (WriteOp::SaveExtractionState(l), WriteOp::SaveExtractionState(r)) => {
                     l.clone_from(r);
                     return Ok(());
@@ -215,6 +272,11 @@ impl DBTransaction {
                     l.extend(r.iter().cloned());
                     return Ok(());
                 }
+                (WriteOp::InsertComponentEvents(l), WriteOp::InsertComponentEvents(r)) => {
+                    self.size += r.len();
+                    l.extend(r.iter().cloned());
+                    return Ok(());
+                }
                 (WriteOp::InsertEntryPoints(l), WriteOp::InsertEntryPoints(r)) => {
                     for (component_id, entry_points) in r.iter() {
                         let entry = l
@@ -253,6 +315,22 @@ impl DBTransaction {
         self.operations.push(op);
         Ok(())
     }
+
+    fn change_notification(&self) -> ChangeNotification {
+        let mut entities = BTreeMap::new();
+        for op in self.operations.iter() {
+            *entities
+                .entry(op.variant_name().to_string())
+                .or_default() += op.n_items();
+        }
+        ChangeNotification {
+            start_block: self.block_range.start.number,
+            end_block: self.block_range.end.number,
+            end_block_hash: self.block_range.end.hash.clone(),
+            extractor: self.owner.clone(),
+            entities,
+        }
+    }
 }
 
 /// Represents different types of messages that can be sent to the DBCacheWriteExecutor.
@@ -309,6 +387,16 @@ pub(crate) struct DBCacheWriteExecutor {
     state_gateway: PostgresGateway,
     persisted_block: Option<models::blockchain::Block>,
     msg_receiver: mpsc::Receiver<DBCacheMessage>,
+    /// Whether to announce every committed transaction, see [`notify`].
+    notify_changes: bool,
+    /// Whether to check the references of a transaction before writing it, see [`references`].
+    ///
+    /// [`references`]: super::references
+    strict_writes: bool,
+    /// Whether extractors may write blocks not following their stored head, e.g. during repairs.
+    repair_mode: bool,
+    #[cfg(any(test, feature = "fault-injection"))]
+    fault_injector: Option<Arc<super::fault_injection::FaultInjector>>,
 }
 
 impl DBCacheWriteExecutor {
@@ -331,7 +419,47 @@ impl DBCacheWriteExecutor {
 
         debug!("Persisted block: {:?}", persisted_block);
 
-        Self { name, chain, pool, state_gateway, persisted_block, msg_receiver }
+        Self {
+            name,
+            chain,
+            pool,
+            state_gateway,
+            persisted_block,
+            msg_receiver,
+            notify_changes: false,
+            strict_writes: false,
+            repair_mode: false,
+            #[cfg(any(test, feature = "fault-injection"))]
+            fault_injector: None,
+        }
+    }
+
+    /// Sends a [`ChangeNotification`] with every committed transaction.
+    pub(crate) fn with_change_notifications(mut self) -> Self {
+        self.notify_changes = true;
+        self
+    }
+
+    /// Rejects transactions referencing entities that are neither stored nor written by them.
+    pub(crate) fn with_strict_writes(mut self) -> Self {
+        self.strict_writes = true;
+        self
+    }
+
+    /// Accepts transactions whose blocks don't follow the stored head of their owner.
+    pub(crate) fn with_repair_mode(mut self) -> Self {
+        self.repair_mode = true;
+        self
+    }
+
+    /// Passes every attempt to write a transaction through `injector`, as the `write` operation.
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub(crate) fn with_fault_injector(
+        mut self,
+        injector: Arc<super::fault_injection::FaultInjector>,
+    ) -> Self {
+        self.fault_injector = Some(injector);
+        self
     }
 
     /// Spawns a task to process incoming database messages (write requests or flush commands).
@@ -362,6 +490,26 @@ impl DBCacheWriteExecutor {
             .await
             .expect("pool should be connected");
 
+        let notification = self
+            .notify_changes
+            .then(|| new_db_tx.change_notification());
+
+        // Other extractors of the chain may write the same blocks concurrently.
+        let writes_blocks = new_db_tx
+            .operations
+            .iter()
+            .any(|op| matches!(op, WriteOp::UpsertBlock(_)));
+        if writes_blocks {
+            if let Err(err) = self
+                .state_gateway
+                .lock_block_writer(&self.chain, &mut conn)
+                .await
+            {
+                let _ = new_db_tx.tx.send(Err(err));
+                return;
+            }
+        }
+
         let mut retry_count = 0;
         let max_retries = 3;
         let mut res =
@@ -373,6 +521,44 @@ impl DBCacheWriteExecutor {
                 .repeatable_read()
                 .run(|conn| {
                     async {
+                        #[cfg(any(test, feature = "fault-injection"))]
+                        if let Some(injector) = &self.fault_injector {
+                            injector.inject("write").await?;
+                        }
+                        if let Some(extractor_id) = new_db_tx.owner.as_deref() {
+                            if !self.repair_mode {
+                                self.state_gateway
+                                    .check_block_order(
+                                        extractor_id,
+                                        &self.chain,
+                                        &new_db_tx.block_range.start,
+                                        conn,
+                                    )
+                                    .await?;
+                            }
+                            if let Some(instance_id) = new_db_tx.lease_holder.as_deref() {
+                                self.state_gateway
+                                    .check_extractor_lease(
+                                        extractor_id,
+                                        &self.chain,
+                                        instance_id,
+                                        conn,
+                                    )
+                                    .await?;
+                            }
+                            // Inserted components and states default their `extractor` column
+                            // to this transaction local setting.
+                            diesel::sql_query("SELECT set_config('tycho.extractor', $1, true)")
+                                .bind::<diesel::sql_types::Text, _>(extractor_id)
+                                .execute(conn)
+                                .await
+                                .map_err(PostgresError::from)?;
+                        }
+                        if self.strict_writes {
+                            self.state_gateway
+                                .check_references(&self.chain, &new_db_tx.operations, conn)
+                                .await?;
+                        }
                         for op in new_db_tx.operations.iter() {
                             match self.execute_write_op(op, conn).await {
                                 Err(PostgresError(StorageError::DuplicateEntry(entity, id))) => {
@@ -390,6 +576,9 @@ impl DBCacheWriteExecutor {
                                 _ => {}
                             }
                         }
+                        if let Some(notification) = notification.as_ref() {
+                            notify::notify(&self.chain, notification, conn).await?;
+                        }
                         Result::<(), PostgresError>::Ok(())
                     }
                     .scope_boxed()
@@ -398,14 +587,13 @@ impl DBCacheWriteExecutor {
 
             match res {
                 Ok(_) => break,
-                Err(PostgresError(StorageError::Unexpected(ref e)))
-                    if e.contains("deadlock detected") =>
-                {
+                Err(PostgresError(StorageError::Unexpected(ref e))) if is_transient(e) => {
                     retry_count += 1;
                     if retry_count < max_retries {
                         let delay = std::time::Duration::from_secs(retry_count);
                         warn!(
-                            "Deadlock detected, retrying in {:?} (attempt {}/{})",
+                            error = e,
+                            "Transaction conflict, retrying in {:?} (attempt {}/{})",
                             delay,
                             retry_count + 1,
                             max_retries
@@ -418,8 +606,23 @@ impl DBCacheWriteExecutor {
             }
         }
 
+        if writes_blocks {
+            if let Err(err) = self
+                .state_gateway
+                .unlock_block_writer(&self.chain, &mut conn)
+                .await
+            {
+                // The lock is held until the session ends, so the connection must not be reused.
+                error!(%err, "Failed to release the block writer lock, dropping connection");
+                drop(Object::<AsyncPgConnection>::take(conn));
+            }
+        }
+
         if res.is_ok() {
             debug!("DBTransactionCommitted");
+        } else {
+            // Ids resolved within the rolled back transaction may not exist.
+            self.state_gateway.clear_id_cache();
         }
 
         match self.persisted_block.as_ref() {
@@ -442,33 +645,41 @@ impl DBCacheWriteExecutor {
     ///
     /// This function handles different types of write operations such as
     /// upserts, updates, and reverts, ensuring data consistency in the database.
-    #[instrument(skip_all, fields(op=operation.variant_name()))]
     async fn execute_write_op(
         &mut self,
         operation: &WriteOp,
         conn: &mut AsyncPgConnection,
+    ) -> Result<(), PostgresError> {
+        self.state_gateway
+            .execute_write_op(&self.chain, operation, conn)
+            .await
+    }
+}
+
+impl PostgresGateway {
+    /// Executes a single write operation on the given connection.
+    #[instrument(skip_all, fields(op=operation.variant_name()))]
+    async fn execute_write_op(
+        &self,
+        chain: &Chain,
+        operation: &WriteOp,
+        conn: &mut AsyncPgConnection,
     ) -> Result<(), PostgresError> {
         trace!(op=?operation, name="ExecuteWriteOp");
         match operation {
-            WriteOp::UpsertBlock(block) => {
-                self.state_gateway
-                    .upsert_block(block, conn)
-                    .await?
-            }
+            WriteOp::UpsertBlock(block) => self.upsert_block(block, conn).await?,
             WriteOp::UpsertTx(transaction) => {
-                self.state_gateway
-                    .upsert_tx(transaction, conn)
+                self.upsert_tx(transaction, conn)
                     .await?
             }
-            WriteOp::SaveExtractionState(state) => {
-                self.state_gateway
-                    .save_state(state, conn)
+            WriteOp::UpsertBlockDigests(digests) => {
+                self.upsert_block_digests(digests, conn)
                     .await?
             }
+            WriteOp::SaveExtractionState(state) => self.save_state(state, conn).await?,
             WriteOp::InsertContract(contracts) => {
                 for contract in contracts.iter() {
-                    self.state_gateway
-                        .insert_contract(contract, conn)
+                    self.insert_contract(contract, conn)
                         .await?
                 }
             }
@@ -478,33 +689,27 @@ impl DBCacheWriteExecutor {
                     .map(|(tx, update)| (tx.clone(), update))
                     .collect();
                 let changes_slice = collected_changes.as_slice();
-                self.state_gateway
-                    .update_contracts(&self.chain, changes_slice, conn)
+                self.update_contracts(chain, changes_slice, conn)
                     .await?
             }
             WriteOp::InsertAccountBalances(balances) => {
-                self.state_gateway
-                    .add_account_balances(balances.as_slice(), &self.chain, conn)
+                self.add_account_balances(balances.as_slice(), chain, conn)
                     .await?
             }
             WriteOp::InsertProtocolComponents(components) => {
-                self.state_gateway
-                    .add_protocol_components(components.as_slice(), conn)
+                self.add_protocol_components(components.as_slice(), conn)
                     .await?
             }
             WriteOp::InsertTokens(tokens) => {
-                self.state_gateway
-                    .add_tokens(tokens.as_slice(), conn)
+                self.add_tokens(tokens.as_slice(), conn)
                     .await?
             }
             WriteOp::UpdateTokens(tokens) => {
-                self.state_gateway
-                    .update_tokens(tokens.as_slice(), conn)
+                self.update_tokens(tokens.as_slice(), conn)
                     .await?
             }
             WriteOp::InsertComponentBalances(balances) => {
-                self.state_gateway
-                    .add_component_balances(balances.as_slice(), &self.chain, conn)
+                self.add_component_balances(balances.as_slice(), chain, conn)
                     .await?
             }
             WriteOp::UpsertProtocolState(deltas) => {
@@ -516,32 +721,136 @@ impl DBCacheWriteExecutor {
                     .map(|(tx, update)| (tx.clone(), update))
                     .collect();
                 let changes_slice = collected_changes.as_slice();
-                self.state_gateway
-                    .update_protocol_states(&self.chain, changes_slice, conn)
+                self.update_protocol_states(chain, changes_slice, conn)
+                    .await?
+            }
+            WriteOp::InsertComponentEvents(events) => {
+                self.add_component_events(events.as_slice(), chain, conn)
                     .await?
             }
             WriteOp::UpsertTracedEntryPoints(traced_entry_points) => {
-                self.state_gateway
-                    .upsert_traced_entry_points(traced_entry_points.as_slice(), conn)
+                self.upsert_traced_entry_points(traced_entry_points.as_slice(), conn)
                     .await?
             }
             WriteOp::InsertEntryPoints(new_entry_points) => {
-                self.state_gateway
-                    .insert_entry_points(new_entry_points, &self.chain, conn)
+                self.insert_entry_points(new_entry_points, chain, conn)
                     .await?
             }
             WriteOp::InsertEntryPointTracingParams(new_entry_point_tracing_params) => {
-                self.state_gateway
-                    .insert_entry_point_tracing_params(
-                        new_entry_point_tracing_params,
-                        &self.chain,
-                        conn,
-                    )
+                self.insert_entry_point_tracing_params(new_entry_point_tracing_params, chain, conn)
                     .await?
             }
         };
         Ok(())
     }
+
+    /// Writes all changes of a single block using the given connection.
+    ///
+    /// Writes are issued in the same order the write cache uses for its batches: the block first,
+    /// then its transactions, contract deltas, protocol state deltas and finally component
+    /// balances. Wrap the call in a db transaction if the block should be applied atomically.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn apply_block_changes(
+        &self,
+        chain: &Chain,
+        block: &models::blockchain::Block,
+        txs: &[models::blockchain::Transaction],
+        contract_deltas: &[(TxHash, models::contract::AccountDelta)],
+        protocol_deltas: &[(TxHash, models::protocol::ProtocolComponentStateDelta)],
+        balances: &[models::protocol::ComponentBalance],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let ops = block_change_ops(block, txs, contract_deltas, protocol_deltas, balances);
+        for op in ops.iter() {
+            self.execute_write_op(chain, op, conn)
+                .await
+                .map_err(|PostgresError(e)| e)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write operations for the changes of a single block, sorted in the order they are written.
+fn block_change_ops(
+    block: &models::blockchain::Block,
+    txs: &[models::blockchain::Transaction],
+    contract_deltas: &[(TxHash, models::contract::AccountDelta)],
+    protocol_deltas: &[(TxHash, models::protocol::ProtocolComponentStateDelta)],
+    balances: &[models::protocol::ComponentBalance],
+) -> Vec<WriteOp> {
+    let mut ops = vec![WriteOp::UpsertBlock(vec![block.clone()])];
+    if !txs.is_empty() {
+        ops.push(WriteOp::UpsertTx(txs.to_vec()));
+    }
+    if !contract_deltas.is_empty() {
+        ops.push(WriteOp::UpdateContracts(contract_deltas.to_vec()));
+    }
+    if !protocol_deltas.is_empty() {
+        ops.push(WriteOp::UpsertProtocolState(protocol_deltas.to_vec()));
+    }
+    if !balances.is_empty() {
+        ops.push(WriteOp::InsertComponentBalances(balances.to_vec()));
+    }
+    ops.sort_by_key(WriteOp::order_key);
+    ops
+}
+
+/// Whether a write failed only due to a concurrent transaction and succeeds if attempted again.
+fn is_transient(err: &str) -> bool {
+    // Diesel does not expose the SQLSTATE of these errors, so the message is checked.
+    err.contains("deadlock detected") || err.contains("could not serialize access")
+}
+
+/// Stand-in for [`DBCacheWriteExecutor`] that never touches the database.
+///
+/// Every transaction is acknowledged as committed, but only a summary of the changes it would have
+/// written is logged. Used to debug extractors without mutating the database.
+pub(crate) struct DryRunWriteExecutor {
+    name: String,
+    msg_receiver: mpsc::Receiver<DBCacheMessage>,
+    totals: BTreeMap<&'static str, usize>,
+    n_transactions: usize,
+}
+
+impl DryRunWriteExecutor {
+    pub(crate) fn new(name: String, msg_receiver: mpsc::Receiver<DBCacheMessage>) -> Self {
+        Self { name, msg_receiver, totals: BTreeMap::new(), n_transactions: 0 }
+    }
+
+    pub fn run(mut self) -> JoinHandle<()> {
+        info!(name = self.name, "DryRunWriteExecutor started, no changes will be persisted!");
+        tokio::spawn(async move {
+            while let Some(message) = self.msg_receiver.recv().await {
+                match message {
+                    DBCacheMessage::Write(db_tx) => self.write(db_tx),
+                }
+            }
+        })
+    }
+
+    fn write(&mut self, db_tx: DBTransaction) {
+        let mut changes: BTreeMap<&'static str, usize> = BTreeMap::new();
+        for op in db_tx.operations.iter() {
+            *changes
+                .entry(op.variant_name())
+                .or_default() += op.n_items();
+        }
+        for (op, n) in changes.iter() {
+            *self.totals.entry(op).or_default() += n;
+        }
+        self.n_transactions += 1;
+
+        info!(
+            block_range = %db_tx.block_range,
+            n_blocks = db_tx.block_range.end.number - db_tx.block_range.start.number + 1,
+            extractor_id = db_tx.owner.as_deref().unwrap_or_default(),
+            ?changes,
+            "DryRun: skipped db transaction"
+        );
+        debug!(n_transactions = self.n_transactions, totals = ?self.totals, "DryRun: totals");
+
+        let _ = db_tx.tx.send(Ok(()));
+    }
 }
 
 #[derive(Hash, Eq, PartialEq, Debug)]
@@ -571,6 +880,13 @@ pub struct CachedGateway {
     pool: Pool<AsyncPgConnection>,
     state_gateway: PostgresGateway,
     lru_cache: Arc<Mutex<DeltasCache>>,
+    head_cache: Arc<HeadStateCache>,
+    component_state_cache: Arc<ComponentStateCache>,
+    /// Instances holding the lease on an extractor, by extractor name. Transactions owned by these
+    /// extractors only commit while the lease is still held.
+    leases: Arc<Mutex<HashMap<String, String>>>,
+    /// If set, writes bypassing the write executor are skipped.
+    dry_run: bool,
 }
 
 impl Clone for CachedGateway {
@@ -582,6 +898,10 @@ impl Clone for CachedGateway {
             pool: self.pool.clone(),
             state_gateway: self.state_gateway.clone(),
             lru_cache: self.lru_cache.clone(),
+            head_cache: self.head_cache.clone(),
+            component_state_cache: self.component_state_cache.clone(),
+            leases: self.leases.clone(),
+            dry_run: self.dry_run,
         }
     }
 }
@@ -594,6 +914,15 @@ impl CachedGateway {
         if let Some(tx) = open_tx.as_mut() {
             tx.0.block_range.end = block.clone();
         } else {
+            let lease_holder = match owner {
+                Some(owner) => self
+                    .leases
+                    .lock()
+                    .await
+                    .get(owner)
+                    .cloned(),
+                None => None,
+            };
             let (tx, rx) = oneshot::channel();
             *open_tx = Some((
                 DBTransaction {
@@ -602,6 +931,7 @@ impl CachedGateway {
                     operations: vec![],
                     tx,
                     owner: owner.map(String::from),
+                    lease_holder,
                 },
                 rx,
             ));
@@ -621,6 +951,25 @@ impl CachedGateway {
         }
     }
 
+    /// Adds all changes of a single block to the open transaction.
+    ///
+    /// The changes are committed together with the rest of the transaction, so if any write of
+    /// the batch fails none of them are persisted. See [`PostgresGateway::apply_block_changes`]
+    /// for the order they are written in.
+    pub async fn apply_block_changes(
+        &self,
+        block: &models::blockchain::Block,
+        txs: &[models::blockchain::Transaction],
+        contract_deltas: &[(TxHash, models::contract::AccountDelta)],
+        protocol_deltas: &[(TxHash, models::protocol::ProtocolComponentStateDelta)],
+        balances: &[models::protocol::ComponentBalance],
+    ) -> Result<(), StorageError> {
+        for op in block_change_ops(block, txs, contract_deltas, protocol_deltas, balances) {
+            self.add_op(op).await?;
+        }
+        Ok(())
+    }
+
     pub async fn commit_transaction(&self, min_ops_batch_size: usize) -> Result<(), StorageError> {
         let mut open_tx = self.open_tx.lock().await;
         match open_tx.take() {
@@ -634,6 +983,14 @@ impl CachedGateway {
                         db_txn
                             .operations
                             .sort_by_key(|e| e.order_key());
+                        let head_cache_write = self
+                            .head_cache
+                            .prepare_write(&db_txn.operations)
+                            .await;
+                        let component_state_write = self
+                            .component_state_cache
+                            .prepare_write(&db_txn.operations)
+                            .await;
                         debug!(
                             size = db_txn.size,
                             ops = ?db_txn
@@ -649,6 +1006,14 @@ impl CachedGateway {
                             .expect("Send message to receiver ok");
                         rx.await
                             .map_err(|_| StorageError::WriteCacheGoneAway())??;
+                        if !self.dry_run {
+                            self.head_cache
+                                .apply_committed(&head_cache_write)
+                                .await;
+                            self.component_state_cache
+                                .apply_committed(&component_state_write)
+                                .await;
+                        }
 
                         Ok::<(), StorageError>(())
                     }
@@ -675,7 +1040,49 @@ impl CachedGateway {
             pool,
             state_gateway,
             lru_cache: Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(5).unwrap()))),
+            head_cache: Arc::new(HeadStateCache::new(
+                NonZeroUsize::new(HEAD_CACHE_CAPACITY).unwrap(),
+                HeadCacheBudget::default(),
+            )),
+            component_state_cache: Arc::new(ComponentStateCache::new(
+                NonZeroUsize::new(COMPONENT_STATE_CACHE_CAPACITY).unwrap(),
+                HeadCacheBudget::from_mb(COMPONENT_STATE_CACHE_DEFAULT_MB),
+            )),
+            leases: Arc::new(Mutex::new(HashMap::new())),
+            dry_run: false,
+        }
+    }
+
+    /// Bounds the memory taken by the head state cache, replacing the default budget.
+    pub fn with_head_cache_budget(mut self, budget: HeadCacheBudget) -> Self {
+        self.head_cache =
+            Arc::new(HeadStateCache::new(NonZeroUsize::new(HEAD_CACHE_CAPACITY).unwrap(), budget));
+        self
+    }
+
+    /// Bounds the memory taken by the cache of latest component states, replacing the default
+    /// budget of [`COMPONENT_STATE_CACHE_DEFAULT_MB`].
+    pub fn with_component_state_cache_budget(mut self, budget: HeadCacheBudget) -> Self {
+        self.component_state_cache = Arc::new(ComponentStateCache::new(
+            NonZeroUsize::new(COMPONENT_STATE_CACHE_CAPACITY).unwrap(),
+            budget,
+        ));
+        self
+    }
+
+    /// Skips all writes that don't go through the write executor. To be combined with a
+    /// [`DryRunWriteExecutor`].
+    pub(crate) fn with_dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    /// Returns true and logs the skipped write if in dry run mode.
+    fn skip_write(&self, op: &str) -> bool {
+        if self.dry_run {
+            info!(op, "DryRun: skipped direct write");
         }
+        self.dry_run
     }
 
     pub async fn get_delta(
@@ -719,8 +1126,9 @@ impl CachedGateway {
             .await?;
         let protocol_delta = self
             .state_gateway
-            .get_protocol_states_delta(chain, start_version, end_version, &mut db)
-            .await?;
+            .get_protocol_states_delta(chain, start_version, Some(end_version), &mut db)
+            .await?
+            .deltas;
         let balance_deltas = self
             .state_gateway
             .get_balance_deltas(chain, start_version, end_version, &mut db)
@@ -732,169 +1140,694 @@ impl CachedGateway {
 
         Ok((accounts_delta, protocol_delta, balance_deltas))
     }
-}
 
-#[async_trait]
-impl ExtractionStateGateway for CachedGateway {
-    #[instrument(skip_all)]
-    async fn get_state(&self, name: &str, chain: &Chain) -> Result<ExtractionState, StorageError> {
+    /// Acquires the lease on an extractor identity, see
+    /// [`PostgresGateway::acquire_extractor_lease`].
+    ///
+    /// Leases bypass the write cache so conflicts are detected before any data is written. Once
+    /// acquired, transactions of the extractor only commit while `instance_id` holds the lease.
+    pub async fn acquire_extractor_lease(
+        &self,
+        name: &str,
+        chain: &Chain,
+        instance_id: &str,
+        ttl: chrono::Duration,
+    ) -> Result<(), StorageError> {
+        if self.skip_write("acquire_extractor_lease") {
+            return Ok(());
+        }
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_state(name, chain, &mut conn)
-            .await
-    }
-    #[instrument(skip_all)]
-    async fn save_state(&self, new: &ExtractionState) -> Result<(), StorageError> {
-        self.add_op(WriteOp::SaveExtractionState(new.clone()))
-            .await?;
-        Ok(())
-    }
-}
-
-#[async_trait]
-impl ChainGateway for CachedGateway {
-    #[instrument(skip_all)]
-    async fn upsert_block(&self, new: &[Block]) -> Result<(), StorageError> {
-        self.add_op(WriteOp::UpsertBlock(new.to_vec()))
+            .acquire_extractor_lease(name, chain, instance_id, ttl, &mut conn)
             .await?;
+        self.leases
+            .lock()
+            .await
+            .insert(name.to_string(), instance_id.to_string());
         Ok(())
     }
 
-    #[instrument(skip_all)]
-    async fn get_block(&self, id: &BlockIdentifier) -> Result<Block, StorageError> {
+    pub async fn renew_extractor_lease(
+        &self,
+        name: &str,
+        chain: &Chain,
+        instance_id: &str,
+        ttl: chrono::Duration,
+    ) -> Result<(), StorageError> {
+        if self.dry_run {
+            return Ok(());
+        }
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_block(id, &mut conn)
+            .renew_extractor_lease(name, chain, instance_id, ttl, &mut conn)
             .await
     }
 
-    async fn upsert_tx(&self, new: &[Transaction]) -> Result<(), StorageError> {
-        self.add_op(WriteOp::UpsertTx(new.to_vec()))
-            .await?;
-        Ok(())
-    }
-
-    #[instrument(skip_all)]
-    async fn get_tx(&self, hash: &TxHash) -> Result<Transaction, StorageError> {
+    pub async fn release_extractor_lease(
+        &self,
+        name: &str,
+        chain: &Chain,
+        instance_id: &str,
+    ) -> Result<(), StorageError> {
+        if self.skip_write("release_extractor_lease") {
+            return Ok(());
+        }
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
+        self.leases.lock().await.remove(name);
         self.state_gateway
-            .get_tx(hash, &mut conn)
+            .release_extractor_lease(name, chain, instance_id, &mut conn)
             .await
     }
 
-    #[instrument(skip_all)]
-    async fn revert_state(&self, to: &BlockIdentifier) -> Result<(), StorageError> {
+    /// Contracts tracked by the extractor `name`, see [`PostgresGateway::get_tracked_contracts`].
+    pub async fn get_tracked_contracts(
+        &self,
+        name: &str,
+        chain: &Chain,
+    ) -> Result<Vec<TrackedContract>, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .revert_state(to, &mut conn)
+            .get_tracked_contracts(name, chain, &mut conn)
             .await
     }
-}
 
-#[async_trait]
-impl ContractStateGateway for CachedGateway {
-    #[instrument(skip_all)]
-    async fn get_contract(
+    /// Stores contracts tracked by the extractor `name`. Like leases, these bypass the write
+    /// cache, tracking changes are made outside of block processing.
+    pub async fn upsert_tracked_contracts(
         &self,
-        id: &ContractId,
-        version: Option<&Version>,
-        include_slots: bool,
-    ) -> Result<Account, StorageError> {
+        name: &str,
+        chain: &Chain,
+        contracts: &[TrackedContract],
+    ) -> Result<(), StorageError> {
+        if self.skip_write("upsert_tracked_contracts") {
+            return Ok(());
+        }
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_contract(id, version, include_slots, &mut conn)
+            .upsert_tracked_contracts(name, chain, contracts, &mut conn)
             .await
     }
 
-    #[instrument(skip_all)]
-    async fn get_contracts(
+    /// Messages quarantined by the extractor `name`, see
+    /// [`PostgresGateway::get_quarantined_messages`].
+    pub async fn get_quarantined_messages(
         &self,
+        name: &str,
         chain: &Chain,
-        addresses: Option<&[Address]>,
-        version: Option<&Version>,
-        include_slots: bool,
-        pagination_params: Option<&PaginationParams>,
-    ) -> Result<WithTotal<Vec<Account>>, StorageError> {
+    ) -> Result<Vec<QuarantinedMessage>, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_contracts(chain, addresses, version, include_slots, pagination_params, &mut conn)
+            .get_quarantined_messages(name, chain, &mut conn)
             .await
     }
 
-    #[instrument(skip_all)]
-    async fn insert_contract(&self, new: &Account) -> Result<(), StorageError> {
-        self.add_op(WriteOp::InsertContract(vec![new.clone()]))
-            .await?;
-        Ok(())
-    }
-
-    #[instrument(skip_all)]
-    async fn update_contracts(&self, new: &[(TxHash, AccountDelta)]) -> Result<(), StorageError> {
-        self.add_op(WriteOp::UpdateContracts(new.to_vec()))
-            .await?;
-        Ok(())
-    }
-
-    #[instrument(skip_all)]
-    async fn delete_contract(&self, id: &ContractId, at_tx: &TxHash) -> Result<(), StorageError> {
+    /// Stores a message the extractor `name` could not decode. Written directly rather than
+    /// through the write cache, so the message is kept even if the block is never committed.
+    pub async fn upsert_quarantined_message(
+        &self,
+        name: &str,
+        chain: &Chain,
+        message: &QuarantinedMessage,
+    ) -> Result<(), StorageError> {
+        if self.skip_write("upsert_quarantined_message") {
+            return Ok(());
+        }
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .delete_contract(id, at_tx, &mut conn)
+            .upsert_quarantined_message(name, chain, message, &mut conn)
             .await
     }
+}
 
+#[async_trait]
+impl ExtractionStateGateway for CachedGateway {
     #[instrument(skip_all)]
-    async fn get_accounts_delta(
-        &self,
-        chain: &Chain,
-        start_version: Option<&BlockOrTimestamp>,
-        end_version: &BlockOrTimestamp,
-    ) -> Result<Vec<AccountDelta>, StorageError> {
+    async fn get_state(&self, name: &str, chain: &Chain) -> Result<ExtractionState, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_accounts_delta(chain, start_version, end_version, &mut conn)
+            .get_state(name, chain, &mut conn)
             .await
     }
-
     #[instrument(skip_all)]
-    async fn add_account_balances(
-        &self,
-        account_balances: &[AccountBalance],
-    ) -> Result<(), StorageError> {
-        self.add_op(WriteOp::InsertAccountBalances(account_balances.to_vec()))
+    async fn save_state(&self, new: &ExtractionState) -> Result<(), StorageError> {
+        self.add_op(WriteOp::SaveExtractionState(new.clone()))
             .await?;
         Ok(())
     }
-
     #[instrument(skip_all)]
-    async fn get_account_balances(
+    async fn get_extractor_block_numbers(
         &self,
         chain: &Chain,
-        addresses: Option<&[Address]>,
+    ) -> Result<HashMap<String, u64>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_extractor_block_numbers(chain, &mut conn)
+            .await
+    }
+}
+
+#[async_trait]
+impl ChainGateway for CachedGateway {
+    #[instrument(skip_all)]
+    async fn upsert_block(&self, new: &[Block]) -> Result<(), StorageError> {
+        self.add_op(WriteOp::UpsertBlock(new.to_vec()))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_block(&self, id: &BlockIdentifier) -> Result<Block, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_block(id, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_finalized_block(&self, chain: &Chain) -> Result<Block, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_finalized_block(chain, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_blocks_at(
+        &self,
+        chains: &[Chain],
+        ts: NaiveDateTime,
+    ) -> Result<HashMap<Chain, Block>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_blocks_at(chains, ts, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_block_finality(
+        &self,
+        chain: &Chain,
+        status: FinalityStatus,
+        up_to: i64,
+    ) -> Result<(), StorageError> {
+        if self.skip_write("update_block_finality") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .update_block_finality(chain, status, up_to, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn upsert_block_digests(&self, new: &[BlockDigest]) -> Result<(), StorageError> {
+        self.add_op(WriteOp::UpsertBlockDigests(new.to_vec()))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_block_digests(
+        &self,
+        chain: &Chain,
+        start_block: i64,
+        end_block: i64,
+    ) -> Result<Vec<BlockDigest>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_block_digests(chain, start_block, end_block, &mut conn)
+            .await
+    }
+
+    async fn upsert_tx(&self, new: &[Transaction]) -> Result<(), StorageError> {
+        self.add_op(WriteOp::UpsertTx(new.to_vec()))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_tx(&self, hash: &TxHash) -> Result<Transaction, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_tx(hash, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn revert_state(&self, to: &BlockIdentifier) -> Result<(), StorageError> {
+        if self.skip_write("revert_state") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let res = self
+            .state_gateway
+            .revert_state(to, &mut conn)
+            .await;
+        self.head_cache.clear().await;
+        self.component_state_cache.clear().await;
+        res
+    }
+
+    #[instrument(skip_all)]
+    async fn quarantine_range(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        start_block: i64,
+        end_block: i64,
+    ) -> Result<i64, StorageError> {
+        if self.skip_write("quarantine_range") {
+            return Err(StorageError::Unsupported("Repairs in dry run mode".to_string()));
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let res = conn
+            .transaction(|conn| {
+                async {
+                    let repair_id = self
+                        .state_gateway
+                        .quarantine_range(chain, protocol_system, start_block, end_block, conn)
+                        .await?;
+                    Result::<i64, PostgresError>::Ok(repair_id)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(StorageError::from);
+        // The quarantined versions may include the latest state of contracts and components.
+        self.head_cache.clear().await;
+        self.component_state_cache.clear().await;
+        res
+    }
+
+    #[instrument(skip_all)]
+    async fn restore_quarantined_range(&self, repair_id: i64) -> Result<(), StorageError> {
+        if self.skip_write("restore_quarantined_range") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let res = conn
+            .transaction(|conn| {
+                async {
+                    self.state_gateway
+                        .restore_quarantined_range(repair_id, conn)
+                        .await?;
+                    Result::<(), PostgresError>::Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(StorageError::from);
+        self.head_cache.clear().await;
+        self.component_state_cache.clear().await;
+        res
+    }
+
+    #[instrument(skip_all)]
+    async fn verify_range_continuity(&self, repair_id: i64) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .verify_range_continuity(repair_id, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn run_analytics_query(
+        &self,
+        query: &str,
+        row_limit: usize,
+        timeout: std::time::Duration,
+    ) -> Result<AnalyticsRows, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let res = conn
+            .build_transaction()
+            .read_only()
+            .run(|conn| {
+                async move {
+                    self.state_gateway
+                        .run_analytics_query(query, row_limit, timeout, conn)
+                        .await
+                        .map_err(PostgresError::from)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(|PostgresError(err)| err);
+        // Session level locks survive the transaction and would stay with the pooled connection.
+        PostgresGateway::release_advisory_locks(&mut conn).await?;
+        res
+    }
+
+    #[instrument(skip_all)]
+    async fn record_admin_action(&self, action: &AdminAction) -> Result<(), StorageError> {
+        if self.skip_write("record_admin_action") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .record_admin_action(action, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_audit_log(
+        &self,
+        operation: Option<&str>,
+        since: Option<NaiveDateTime>,
+        pagination_params: Option<&PaginationParams>,
+    ) -> Result<WithTotal<Vec<AuditEntry>>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_audit_log(operation, since, pagination_params, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn claim_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        action: &str,
+    ) -> Result<IdempotencyClaim, StorageError> {
+        if self.skip_write("claim_idempotency_key") {
+            return Ok(IdempotencyClaim::Claimed);
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                let claim = self
+                    .state_gateway
+                    .claim_idempotency_key(api_key, key, action, conn)
+                    .await?;
+                Result::<IdempotencyClaim, PostgresError>::Ok(claim)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
+
+    #[instrument(skip_all)]
+    async fn complete_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), StorageError> {
+        if self.skip_write("complete_idempotency_key") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .complete_idempotency_key(api_key, key, response, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn release_idempotency_key(&self, api_key: &str, key: &str) -> Result<(), StorageError> {
+        if self.skip_write("release_idempotency_key") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .release_idempotency_key(api_key, key, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn add_api_key_usage(
+        &self,
+        month: &str,
+        usage: &[ApiKeyUsage],
+    ) -> Result<(), StorageError> {
+        if self.skip_write("add_api_key_usage") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .add_api_key_usage(month, usage, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_api_key_usage(&self, month: &str) -> Result<Vec<ApiKeyUsage>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_api_key_usage(month, &mut conn)
+            .await
+    }
+}
+
+#[async_trait]
+impl ContractStateGateway for CachedGateway {
+    #[instrument(skip_all)]
+    async fn get_contract(
+        &self,
+        id: &ContractId,
+        version: Option<&Version>,
+        include_slots: bool,
+    ) -> Result<Account, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_contract(id, version, include_slots, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_contracts(
+        &self,
+        chain: &Chain,
+        addresses: Option<&[Address]>,
+        version: Option<&Version>,
+        include_slots: bool,
+        filter: Option<&ContractFilter>,
+        pagination_params: Option<&PaginationParams>,
+    ) -> Result<WithTotal<Vec<Account>>, StorageError> {
+        // Only full, unfiltered and unpaginated reads of specific contracts at the latest version
+        // can be served from the head state cache.
+        let head_addresses = addresses.filter(|addresses| {
+            filter.is_none() &&
+                version.is_none_or(|v| {
+                    matches!(
+                        v,
+                        Version(
+                            BlockOrTimestamp::Block(BlockIdentifier::Latest(_)),
+                            VersionKind::Last
+                        )
+                    )
+                }) &&
+                pagination_params
+                    .is_none_or(|p| p.offset() == 0 && p.page_size >= addresses.len() as i64)
+        });
+
+        if let Some(head_addresses) = head_addresses {
+            if let Some(mut accounts) = self
+                .head_cache
+                .get_all(*chain, head_addresses)
+                .await
+            {
+                trace!(n_accounts = accounts.len(), "HeadCacheHit");
+                if !include_slots {
+                    accounts
+                        .iter_mut()
+                        .for_each(|account| account.slots.clear());
+                }
+                let total = accounts.len() as i64;
+                return Ok(WithTotal { entity: accounts, total: Some(total) });
+            }
+        }
+
+        let generation = self.head_cache.generation();
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let res = self
+            .state_gateway
+            .get_contracts(
+                chain,
+                addresses,
+                version,
+                include_slots,
+                filter,
+                pagination_params,
+                &mut conn,
+            )
+            .await?;
+
+        if head_addresses.is_some() && include_slots {
+            self.head_cache
+                .fill(generation, &res.entity)
+                .await;
+        }
+        Ok(res)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_code_by_hash(
+        &self,
+        chain: &Chain,
+        code_hash: &CodeHash,
+    ) -> Result<Code, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_code_by_hash(chain, code_hash, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn insert_contract(&self, new: &Account) -> Result<(), StorageError> {
+        self.add_op(WriteOp::InsertContract(vec![new.clone()]))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn update_contracts(&self, new: &[(TxHash, AccountDelta)]) -> Result<(), StorageError> {
+        self.add_op(WriteOp::UpdateContracts(new.to_vec()))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_contract(&self, id: &ContractId, at_tx: &TxHash) -> Result<(), StorageError> {
+        if self.skip_write("delete_contract") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .delete_contract(id, at_tx, &mut conn)
+            .await?;
+        self.head_cache
+            .evict(id.chain, &id.address)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_accounts_delta(
+        &self,
+        chain: &Chain,
+        start_version: Option<&BlockOrTimestamp>,
+        end_version: &BlockOrTimestamp,
+    ) -> Result<Vec<AccountDelta>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .run_with_deadline(&mut conn, |conn| {
+                self.state_gateway
+                    .get_accounts_delta(chain, start_version, end_version, conn)
+                    .scope_boxed()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn add_account_balances(
+        &self,
+        account_balances: &[AccountBalance],
+    ) -> Result<(), StorageError> {
+        self.add_op(WriteOp::InsertAccountBalances(account_balances.to_vec()))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_account_balances(
+        &self,
+        chain: &Chain,
+        addresses: Option<&[Address]>,
         version: Option<&Version>,
     ) -> Result<HashMap<Address, HashMap<Address, AccountBalance>>, StorageError> {
         let mut conn =
@@ -905,6 +1838,61 @@ impl ContractStateGateway for CachedGateway {
             .get_account_balances(chain, addresses, version, false, &mut conn)
             .await
     }
+
+    #[instrument(skip_all)]
+    async fn get_tracked_addresses(
+        &self,
+        chain: &Chain,
+        addresses: &[Address],
+    ) -> Result<HashSet<Address>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_tracked_addresses(chain, addresses, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_slot_annotations(
+        &self,
+        chain: &Chain,
+        addresses: &[Address],
+    ) -> Result<Vec<SlotAnnotation>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_slot_annotations(chain, addresses, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn upsert_slot_annotations(
+        &self,
+        annotations: &[SlotAnnotation],
+    ) -> Result<(), StorageError> {
+        if self.skip_write("upsert_slot_annotations") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                self.state_gateway
+                    .upsert_slot_annotations(annotations, conn)
+                    .await?;
+                Result::<(), PostgresError>::Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
 }
 
 #[async_trait]
@@ -913,18 +1901,59 @@ impl ProtocolGateway for CachedGateway {
     async fn get_protocol_components(
         &self,
         chain: &Chain,
-        system: Option<String>,
-        ids: Option<&[&str]>,
-        min_tvl: Option<f64>,
-        pagination_params: Option<&PaginationParams>,
-    ) -> Result<WithTotal<Vec<ProtocolComponent>>, StorageError> {
+        system: Option<String>,
+        ids: Option<&[&str]>,
+        min_tvl: Option<f64>,
+        pagination_params: Option<&PaginationParams>,
+    ) -> Result<WithTotal<Vec<ProtocolComponent>>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_protocol_components(chain, system, ids, min_tvl, pagination_params, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_protocol_components_after(
+        &self,
+        chain: &Chain,
+        system: Option<String>,
+        ids: Option<&[&str]>,
+        min_tvl: Option<f64>,
+        cursor: &ComponentCursor,
+        page_size: i64,
+    ) -> Result<WithTotal<Vec<ProtocolComponent>>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_protocol_components_after(
+                chain, system, ids, min_tvl, cursor, page_size, &mut conn,
+            )
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_components_with_contracts(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        version: Option<&Version>,
+    ) -> Result<Vec<ProtocolComponentWithContracts>, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
-        self.state_gateway
-            .get_protocol_components(chain, system, ids, min_tvl, pagination_params, &mut conn)
-            .await
+        // Components and contracts must be read from the same snapshot.
+        PostgresGateway::run_in_snapshot(&mut conn, |conn| {
+            self.state_gateway
+                .get_components_with_contracts(chain, ids, version, conn)
+                .scope_boxed()
+        })
+        .await
     }
 
     #[instrument(skip_all)]
@@ -956,13 +1985,22 @@ impl ProtocolGateway for CachedGateway {
         to_delete: &[ProtocolComponent],
         block_ts: NaiveDateTime,
     ) -> Result<(), StorageError> {
+        if self.skip_write("delete_protocol_components") {
+            return Ok(());
+        }
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
             .delete_protocol_components(to_delete, block_ts, &mut conn)
-            .await
+            .await?;
+        for component in to_delete {
+            self.component_state_cache
+                .evict(component.chain, slice::from_ref(&component.id))
+                .await;
+        }
+        Ok(())
     }
 
     #[instrument(skip_all)]
@@ -970,6 +2008,9 @@ impl ProtocolGateway for CachedGateway {
         &self,
         new_protocol_types: &[ProtocolType],
     ) -> Result<(), StorageError> {
+        if self.skip_write("add_protocol_types") {
+            return Ok(());
+        }
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
@@ -989,11 +2030,54 @@ impl ProtocolGateway for CachedGateway {
         retrieve_balances: bool,
         pagination_params: Option<&PaginationParams>,
     ) -> Result<WithTotal<Vec<ProtocolComponentState>>, StorageError> {
+        // Only unpaginated reads of specific components at the latest version can be served from
+        // the component state cache.
+        let head_ids = ids
+            .filter(|ids| {
+                system.is_none() &&
+                    at.as_ref().is_none_or(|v| {
+                        matches!(
+                            v,
+                            Version(
+                                BlockOrTimestamp::Block(BlockIdentifier::Latest(_)),
+                                VersionKind::Last
+                            )
+                        )
+                    }) &&
+                    pagination_params
+                        .is_none_or(|p| p.offset() == 0 && p.page_size >= ids.len() as i64)
+            })
+            .map(|ids| {
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .unique()
+                    .collect::<Vec<_>>()
+            });
+
+        if let Some(head_ids) = &head_ids {
+            if let Some(mut states) = self
+                .component_state_cache
+                .get_all(*chain, head_ids)
+                .await
+            {
+                trace!(n_states = states.len(), "ComponentStateCacheHit");
+                if !retrieve_balances {
+                    states
+                        .iter_mut()
+                        .for_each(|state| state.balances.clear());
+                }
+                let total = states.len() as i64;
+                return Ok(WithTotal { entity: states, total: Some(total) });
+            }
+        }
+
+        let generation = self.component_state_cache.generation();
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
-        self.state_gateway
+        let res = self
+            .state_gateway
             .get_protocol_states(
                 chain,
                 at,
@@ -1003,7 +2087,14 @@ impl ProtocolGateway for CachedGateway {
                 pagination_params,
                 &mut conn,
             )
-            .await
+            .await?;
+
+        if head_ids.is_some() && retrieve_balances {
+            self.component_state_cache
+                .fill(generation, *chain, &res.entity)
+                .await;
+        }
+        Ok(res)
     }
 
     #[instrument(skip_all)]
@@ -1044,6 +2135,29 @@ impl ProtocolGateway for CachedGateway {
         Ok(())
     }
 
+    #[instrument(skip_all)]
+    async fn add_component_events(&self, events: &[ComponentEvent]) -> Result<(), StorageError> {
+        self.add_op(WriteOp::InsertComponentEvents(events.to_vec()))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn get_component_events(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        blocks: RangeInclusive<i64>,
+    ) -> Result<Vec<ComponentEvent>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_component_events(chain, ids, blocks, &mut conn)
+            .await
+    }
+
     #[instrument(skip_all)]
     async fn add_tokens(&self, tokens: &[Token]) -> Result<(), StorageError> {
         self.add_op(WriteOp::InsertTokens(tokens.to_vec()))
@@ -1062,6 +2176,9 @@ impl ProtocolGateway for CachedGateway {
     /// for these use cases that creates a single transactions and emits them immediately.
     #[instrument(skip_all)]
     async fn update_tokens(&self, tokens: &[Token]) -> Result<(), StorageError> {
+        if self.skip_write("update_tokens") {
+            return Ok(());
+        }
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
@@ -1085,15 +2202,23 @@ impl ProtocolGateway for CachedGateway {
         &self,
         chain: &Chain,
         start_version: Option<&BlockOrTimestamp>,
-        end_version: &BlockOrTimestamp,
-    ) -> Result<Vec<ProtocolComponentStateDelta>, StorageError> {
+        end_version: Option<&BlockOrTimestamp>,
+    ) -> Result<ProtocolStatesDelta, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
-        self.state_gateway
-            .get_protocol_states_delta(chain, start_version, end_version, &mut conn)
-            .await
+        // The end block must be resolved from the same snapshot the changes are read from.
+        PostgresGateway::run_in_snapshot(&mut conn, |conn| {
+            self.state_gateway
+                .run_with_deadline(conn, |conn| {
+                    self.state_gateway
+                        .get_protocol_states_delta(chain, start_version, end_version, conn)
+                        .scope_boxed()
+                })
+                .scope_boxed()
+        })
+        .await
     }
 
     #[instrument(skip_all)]
@@ -1108,7 +2233,11 @@ impl ProtocolGateway for CachedGateway {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_balance_deltas(chain, start_version, target_version, &mut conn)
+            .run_with_deadline(&mut conn, |conn| {
+                self.state_gateway
+                    .get_balance_deltas(chain, start_version, target_version, conn)
+                    .scope_boxed()
+            })
             .await
     }
 
@@ -1129,57 +2258,356 @@ impl ProtocolGateway for CachedGateway {
     }
 
     #[instrument(skip_all)]
-    async fn get_token_prices(&self, chain: &Chain) -> Result<HashMap<Bytes, f64>, StorageError> {
+    async fn get_balance_history(
+        &self,
+        chain: &Chain,
+        component_id: &str,
+        token: &Address,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        interval: SampleInterval,
+    ) -> Result<Vec<BalanceSample>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .run_with_deadline(&mut conn, |conn| {
+                self.state_gateway
+                    .get_balance_history(chain, component_id, token, from, to, interval, conn)
+                    .scope_boxed()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_inactive_components(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+    ) -> Result<HashMap<ComponentId, NaiveDateTime>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_inactive_components(chain, ids, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_component_extractors(
+        &self,
+        chain: &Chain,
+        ids: Option<&[&str]>,
+        extractor: Option<&str>,
+    ) -> Result<HashMap<ComponentId, String>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_component_extractors(chain, ids, extractor, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_component_ids_by_tokens(
+        &self,
+        chain: &Chain,
+        tokens: &[Address],
+        ids: Option<&[&str]>,
+    ) -> Result<Vec<ComponentId>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_component_ids_by_tokens(chain, tokens, ids, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_attribute_range(
+        &self,
+        chain: &Chain,
+        component_id: &str,
+        prefix: &str,
+        range: RangeInclusive<i64>,
+        at: Option<&Version>,
+    ) -> Result<BTreeMap<i64, HashMap<AttrStoreKey, StoreVal>>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_attribute_range(chain, component_id, prefix, range, at, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_protocol_deployment(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+    ) -> Result<ProtocolDeployment, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_protocol_deployment(chain, protocol_system, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn rename_protocol_state_attribute(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        component_ids: Option<&[&str]>,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<i64, StorageError> {
+        if self.skip_write("rename_protocol_state_attribute") {
+            return Err(StorageError::Unsupported("Attribute renames in dry run mode".to_string()));
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let res = conn
+            .transaction(|conn| {
+                async {
+                    let renamed = self
+                        .state_gateway
+                        .rename_protocol_state_attribute(
+                            chain,
+                            protocol_system,
+                            component_ids,
+                            old_name,
+                            new_name,
+                            conn,
+                        )
+                        .await?;
+                    Result::<i64, PostgresError>::Ok(renamed)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(StorageError::from);
+        self.component_state_cache.clear().await;
+        res
+    }
+
+    #[instrument(skip_all)]
+    async fn get_token_prices(&self, chain: &Chain) -> Result<HashMap<Bytes, f64>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_token_prices(chain, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_protocol_data_modified_at(
+        &self,
+        chain: &Chain,
+    ) -> Result<Option<NaiveDateTime>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_protocol_data_modified_at(chain, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_token_overrides(
+        &self,
+        chain: &Chain,
+        addresses: Option<&[&Address]>,
+    ) -> Result<Vec<TokenOverride>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_token_overrides(chain, addresses, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn upsert_token_overrides(
+        &self,
+        overrides: &[TokenOverride],
+    ) -> Result<(), StorageError> {
+        if self.skip_write("upsert_token_overrides") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                self.state_gateway
+                    .upsert_token_overrides(overrides, conn)
+                    .await?;
+                Result::<(), PostgresError>::Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_token_overrides(
+        &self,
+        chain: &Chain,
+        addresses: &[&Address],
+    ) -> Result<(), StorageError> {
+        if self.skip_write("delete_token_overrides") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .delete_token_overrides(chain, addresses, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn add_token_supplies(
+        &self,
+        chain: &Chain,
+        supplies: &HashMap<Address, Balance>,
+        ts: NaiveDateTime,
+    ) -> Result<(), StorageError> {
+        if self.skip_write("add_token_supplies") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .add_token_supplies(chain, supplies, ts, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_token_supplies(
+        &self,
+        chain: &Chain,
+        tokens: &[Address],
+        at: Option<&Version>,
+    ) -> Result<HashMap<Address, Balance>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_token_supplies(chain, tokens, at, &mut conn)
+            .await
+    }
+
+    /// TODO: add to transaction instead
+    #[instrument(skip_all)]
+    async fn upsert_component_tvl(
+        &self,
+        chain: &Chain,
+        tvl_values: &HashMap<String, f64>,
+    ) -> Result<(), StorageError> {
+        if self.skip_write("upsert_component_tvl") {
+            return Ok(());
+        }
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .upsert_component_tvl(chain, tvl_values, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_protocol_systems(
+        &self,
+        chain: &Chain,
+        pagination_params: Option<&PaginationParams>,
+    ) -> Result<WithTotal<Vec<String>>, StorageError> {
+        self.state_gateway
+            .get_protocol_systems(chain, pagination_params)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_protocol_system_summaries(
+        &self,
+        chain: &Chain,
+    ) -> Result<Vec<ProtocolSystemSummary>, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_token_prices(chain, &mut conn)
+            .get_protocol_system_summaries(chain, &mut conn)
             .await
     }
 
-    /// TODO: add to transaction instead
     #[instrument(skip_all)]
-    async fn upsert_component_tvl(
+    async fn get_component_tvls(
         &self,
         chain: &Chain,
-        tvl_values: &HashMap<String, f64>,
-    ) -> Result<(), StorageError> {
+        system: Option<String>,
+        ids: Option<&[&str]>,
+        pagination_params: Option<&PaginationParams>,
+    ) -> Result<WithTotal<HashMap<String, f64>>, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .upsert_component_tvl(chain, tvl_values, &mut conn)
+            .get_component_tvls(chain, system, ids, pagination_params, &mut conn)
             .await
     }
 
     #[instrument(skip_all)]
-    async fn get_protocol_systems(
+    async fn get_component_latest_states(
         &self,
         chain: &Chain,
-        pagination_params: Option<&PaginationParams>,
-    ) -> Result<WithTotal<Vec<String>>, StorageError> {
+        system: Option<&str>,
+        ids: Option<&[&str]>,
+    ) -> Result<Vec<ComponentLatestState>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
         self.state_gateway
-            .get_protocol_systems(chain, pagination_params)
+            .get_component_latest_states(chain, system, ids, &mut conn)
             .await
     }
 
     #[instrument(skip_all)]
-    async fn get_component_tvls(
+    async fn get_component_latest_balances(
         &self,
         chain: &Chain,
-        system: Option<String>,
+        system: Option<&str>,
         ids: Option<&[&str]>,
-        pagination_params: Option<&PaginationParams>,
-    ) -> Result<WithTotal<HashMap<String, f64>>, StorageError> {
+    ) -> Result<Vec<ComponentLatestBalance>, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_component_tvls(chain, system, ids, pagination_params, &mut conn)
+            .get_component_latest_balances(chain, system, ids, &mut conn)
             .await
     }
 }
@@ -1264,6 +2692,40 @@ impl EntryPointGateway for CachedGateway {
 
 impl Gateway for CachedGateway {}
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dry_run_write_executor() {
+        let (tx, rx) = mpsc::channel(10);
+        let handle = DryRunWriteExecutor::new("ethereum".to_owned(), rx).run();
+
+        let block = models::blockchain::Block::default();
+        let (os_tx, os_rx) = oneshot::channel();
+        let mut db_tx = DBTransaction {
+            block_range: BlockRange::new(&block, &block),
+            size: 0,
+            operations: vec![],
+            tx: os_tx,
+            owner: None,
+            lease_holder: None,
+        };
+        db_tx
+            .add_operation(WriteOp::UpsertBlock(vec![block.clone()]))
+            .unwrap();
+        tx.send(DBCacheMessage::Write(db_tx))
+            .await
+            .unwrap();
+
+        os_rx
+            .await
+            .expect("executor responded")
+            .expect("dry run write succeeds");
+        handle.abort();
+    }
+}
+
 #[cfg(test)]
 mod test_serial_db {
     use std::{collections::HashSet, slice, str::FromStr, time::Duration};
@@ -1271,7 +2733,13 @@ mod test_serial_db {
     use tycho_common::models::ChangeType;
 
     use super::*;
-    use crate::postgres::{db_fixtures, db_fixtures::yesterday_one_am, testing::run_against_db};
+    use crate::postgres::{
+        db_fixtures,
+        db_fixtures::yesterday_one_am,
+        fault_injection::{FaultConfig, FaultInjector},
+        schema,
+        testing::run_against_db,
+    };
 
     #[tokio::test]
     async fn test_write_and_flush() {
@@ -1329,6 +2797,296 @@ mod test_serial_db {
         .await;
     }
 
+    #[tokio::test]
+    async fn test_write_recovers_from_faults() {
+        run_against_db(|connection_pool| async move {
+            let mut connection = connection_pool
+                .get()
+                .await
+                .expect("Failed to get a connection from the pool");
+            db_fixtures::insert_chain(&mut connection, "ethereum").await;
+            let gateway: PostgresGateway = PostgresGateway::from_connection(&mut connection).await;
+            // Conflicts the first attempt, then fails every other attempt from the third one.
+            let injector = Arc::new(FaultInjector::new(
+                FaultConfig::default()
+                    .conflict(25)
+                    .fail(50),
+            ));
+            let (tx, rx) = mpsc::channel(10);
+            let handle = DBCacheWriteExecutor::new(
+                "ethereum".to_owned(),
+                Chain::Ethereum,
+                connection_pool.clone(),
+                gateway.clone(),
+                rx,
+            )
+            .await
+            .with_fault_injector(injector.clone())
+            .run();
+
+            let mut results = Vec::new();
+            for block in [get_sample_block(1), get_sample_block(2), get_sample_block(2)] {
+                let os_rx =
+                    send_write_message(&tx, block.clone(), vec![WriteOp::UpsertBlock(vec![block])])
+                        .await;
+                results.push(
+                    os_rx
+                        .await
+                        .expect("Response from channel ok"),
+                );
+            }
+            handle.abort();
+
+            let latest = gateway
+                .get_block(&BlockIdentifier::Latest(Chain::Ethereum), &mut connection)
+                .await
+                .expect("Failed to fetch latest block");
+            assert!(results[0].is_ok());
+            assert_eq!(
+                results[1],
+                Err(StorageError::Unexpected("Injected fault in write".to_string()))
+            );
+            assert!(results[2].is_ok());
+            assert_eq!(injector.calls(), 4);
+            assert_eq!(latest, get_sample_block(2));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_writers_of_one_chain() {
+        run_against_db(|connection_pool| async move {
+            let mut connection = connection_pool
+                .get()
+                .await
+                .expect("Failed to get a connection from the pool");
+            db_fixtures::insert_chain(&mut connection, "ethereum").await;
+            let gateway: PostgresGateway = PostgresGateway::from_connection(&mut connection).await;
+            let mut senders = Vec::new();
+            let mut handles = Vec::new();
+            for name in ["extractor_a", "extractor_b"] {
+                let (tx, rx) = mpsc::channel(10);
+                let executor = DBCacheWriteExecutor::new(
+                    name.to_owned(),
+                    Chain::Ethereum,
+                    connection_pool.clone(),
+                    gateway.clone(),
+                    rx,
+                )
+                .await;
+                handles.push(executor.run());
+                senders.push(tx);
+            }
+
+            let blocks = vec![get_sample_block(1), get_sample_block(2)];
+            let mut responses = Vec::new();
+            for tx in &senders {
+                responses.push(
+                    send_write_message(
+                        tx,
+                        blocks[1].clone(),
+                        vec![WriteOp::UpsertBlock(blocks.clone())],
+                    )
+                    .await,
+                );
+            }
+            let results = futures03::future::join_all(responses).await;
+            for handle in handles {
+                handle.abort();
+            }
+
+            let latest = gateway
+                .get_block(&BlockIdentifier::Latest(Chain::Ethereum), &mut connection)
+                .await
+                .expect("Failed to fetch latest block");
+            for res in results {
+                res.expect("Response from channel ok")
+                    .expect("Both writers succeed");
+            }
+            assert_eq!(latest, get_sample_block(2));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_apply_block_changes() {
+        run_against_db(|connection_pool| async move {
+            let mut connection = connection_pool
+                .get()
+                .await
+                .expect("Failed to get a connection from the pool");
+            db_fixtures::insert_chain(&mut connection, "ethereum").await;
+            let gateway: PostgresGateway = PostgresGateway::from_connection(&mut connection).await;
+            let block = get_sample_block(1);
+            let tx = get_sample_transaction(1);
+
+            gateway
+                .apply_block_changes(
+                    &Chain::Ethereum,
+                    &block,
+                    slice::from_ref(&tx),
+                    &[],
+                    &[],
+                    &[],
+                    &mut connection,
+                )
+                .await
+                .expect("block changes applied");
+
+            let fetched_block = gateway
+                .get_block(&BlockIdentifier::Number((Chain::Ethereum, 1)), &mut connection)
+                .await
+                .expect("block inserted");
+            let fetched_tx = gateway
+                .get_tx(&tx.hash, &mut connection)
+                .await
+                .expect("tx inserted");
+            assert_eq!(fetched_block, block);
+            assert_eq!(fetched_tx, tx);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_block_changes_are_rolled_back() {
+        run_against_db(|connection_pool| async move {
+            let mut connection = connection_pool
+                .get()
+                .await
+                .expect("Failed to get a connection from the pool");
+            let chain_id = db_fixtures::insert_chain(&mut connection, "ethereum").await;
+            let usdc_address = Bytes::from("0xdAC17F958D2ee523a2206206994597C13D831ec7");
+            db_fixtures::insert_token(
+                &mut connection,
+                chain_id,
+                "dAC17F958D2ee523a2206206994597C13D831ec7",
+                "USDT",
+                6,
+                Some(100),
+            )
+            .await;
+            db_fixtures::insert_protocol_system(&mut connection, "ambient".to_owned()).await;
+            db_fixtures::insert_protocol_type(&mut connection, "ambient_pool", None, None, None)
+                .await;
+            let gateway: PostgresGateway = PostgresGateway::from_connection(&mut connection).await;
+            let (tx, rx) = mpsc::channel(10);
+            let handle = DBCacheWriteExecutor::new(
+                "ethereum".to_owned(),
+                Chain::Ethereum,
+                connection_pool.clone(),
+                gateway.clone(),
+                rx,
+            )
+            .await
+            .run();
+            let cached_gw = CachedGateway::new(tx, connection_pool.clone(), gateway.clone());
+
+            let block = get_sample_block(1);
+            let tx_1 = get_sample_transaction(1);
+            let component_id = "ambient_USDT".to_owned();
+            let component = models::protocol::ProtocolComponent {
+                id: component_id.clone(),
+                protocol_system: "ambient".to_string(),
+                protocol_type_name: "ambient_pool".to_string(),
+                chain: Chain::Ethereum,
+                tokens: vec![usdc_address.clone()],
+                contract_addresses: vec![],
+                change: ChangeType::Creation,
+                creation_tx: tx_1.hash.clone(),
+                static_attributes: Default::default(),
+                created_at: Default::default(),
+            };
+            let balance = models::protocol::ComponentBalance {
+                token: usdc_address,
+                balance_float: 1.0,
+                balance: Bytes::from(&[1u8]),
+                modify_tx: tx_1.hash.clone(),
+                component_id: component_id.clone(),
+            };
+            let attributes: HashMap<String, Bytes> =
+                [("reserve1".to_owned(), Bytes::from(1000u64).lpad(32, 0))]
+                    .into_iter()
+                    .collect();
+            // The state of the unknown component fails the write after the block, the component
+            // and its balance were written.
+            let protocol_deltas = [component_id.as_str(), "unknown"].map(|id| {
+                (
+                    tx_1.hash.clone(),
+                    models::protocol::ProtocolComponentStateDelta::new(
+                        id,
+                        attributes.clone(),
+                        HashSet::new(),
+                    ),
+                )
+            });
+
+            cached_gw
+                .start_transaction(&block, None)
+                .await;
+            cached_gw
+                .add_protocol_components(slice::from_ref(&component))
+                .await
+                .expect("component added");
+            cached_gw
+                .apply_block_changes(
+                    &block,
+                    slice::from_ref(&tx_1),
+                    &[],
+                    &protocol_deltas,
+                    slice::from_ref(&balance),
+                )
+                .await
+                .expect("block changes added");
+            let res = cached_gw.commit_transaction(0).await;
+            handle.abort();
+
+            assert_eq!(
+                res,
+                Err(StorageError::NotFound("Component id".to_string(), "unknown".to_string()))
+            );
+            assert!(gateway
+                .get_block(&BlockIdentifier::Number((Chain::Ethereum, 1)), &mut connection)
+                .await
+                .is_err());
+            let components = gateway
+                .get_protocol_components(
+                    &Chain::Ethereum,
+                    None,
+                    Some(&[component_id.as_str()]),
+                    None,
+                    None,
+                    &mut connection,
+                )
+                .await
+                .expect("components retrieved");
+            assert!(components.entity.is_empty());
+            let states = gateway
+                .get_protocol_states(
+                    &Chain::Ethereum,
+                    None,
+                    None,
+                    Some(&[component_id.as_str()]),
+                    false,
+                    None,
+                    &mut connection,
+                )
+                .await
+                .expect("states retrieved");
+            assert!(states.entity.is_empty());
+            let balances = gateway
+                .get_component_balances(
+                    &Chain::Ethereum,
+                    Some(&[component_id.as_str()]),
+                    None,
+                    &mut connection,
+                )
+                .await
+                .expect("balances retrieved");
+            assert!(balances.is_empty());
+        })
+        .await;
+    }
+
     #[tokio::test]
     async fn test_writes_and_new_blocks() {
         run_against_db(|connection_pool| async move {
@@ -1611,6 +3369,161 @@ mod test_serial_db {
         .await;
     }
 
+    #[tokio::test]
+    async fn test_dry_run_skips_direct_writes() {
+        run_against_db(|connection_pool| async move {
+            use diesel::QueryDsl;
+
+            let mut connection = connection_pool
+                .get()
+                .await
+                .expect("Failed to get a connection from the pool");
+            let chain_id = db_fixtures::insert_chain(&mut connection, "ethereum").await;
+            db_fixtures::insert_token(
+                &mut connection,
+                chain_id,
+                "0000000000000000000000000000000000000000",
+                "ETH",
+                18,
+                Some(100),
+            )
+            .await;
+            let gateway: PostgresGateway = PostgresGateway::from_connection(&mut connection).await;
+            let (tx, rx) = mpsc::channel(10);
+            let handle = DryRunWriteExecutor::new("ethereum".to_owned(), rx).run();
+            let cached_gw = CachedGateway::new(tx, connection_pool.clone(), gateway).with_dry_run();
+
+            cached_gw
+                .add_token_supplies(
+                    &Chain::Ethereum,
+                    &HashMap::from([(Bytes::zero(20), Bytes::from(100u64).lpad(32, 0))]),
+                    yesterday_one_am(),
+                )
+                .await
+                .expect("token supplies skipped");
+            cached_gw
+                .record_admin_action(&AdminAction {
+                    api_key: "ops".to_string(),
+                    role: "admin".to_string(),
+                    action: "POST /v1/admin/component_filter".to_string(),
+                    status: 200,
+                })
+                .await
+                .expect("admin action skipped");
+            let claim = cached_gw
+                .claim_idempotency_key("ops", "key", "revert")
+                .await
+                .expect("claim skipped");
+            cached_gw
+                .complete_idempotency_key(
+                    "ops",
+                    "key",
+                    &IdempotentResponse { status: 200, content_type: None, body: vec![] },
+                )
+                .await
+                .expect("completion skipped");
+            cached_gw
+                .release_idempotency_key("ops", "key")
+                .await
+                .expect("release skipped");
+            handle.abort();
+
+            assert_eq!(claim, IdempotencyClaim::Claimed);
+            for count in [
+                schema::token_supply::table
+                    .count()
+                    .get_result::<i64>(&mut connection)
+                    .await,
+                schema::admin_audit_log::table
+                    .count()
+                    .get_result::<i64>(&mut connection)
+                    .await,
+                schema::admin_idempotency_key::table
+                    .count()
+                    .get_result::<i64>(&mut connection)
+                    .await,
+            ] {
+                assert_eq!(count.expect("rows counted"), 0);
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_commit_requires_extractor_lease() {
+        run_against_db(|connection_pool| async move {
+            let mut connection = connection_pool
+                .get()
+                .await
+                .expect("Failed to get a connection from the pool");
+            db_fixtures::insert_chain(&mut connection, "ethereum").await;
+            let gateway: PostgresGateway = PostgresGateway::from_connection(&mut connection).await;
+            let (tx, rx) = mpsc::channel(10);
+            let handle = DBCacheWriteExecutor::new(
+                "ethereum".to_owned(),
+                Chain::Ethereum,
+                connection_pool.clone(),
+                gateway.clone(),
+                rx,
+            )
+            .await
+            .run();
+            let cached_gw = CachedGateway::new(tx, connection_pool.clone(), gateway.clone());
+            let ttl = chrono::Duration::seconds(60);
+            cached_gw
+                .acquire_extractor_lease("vm:test", &Chain::Ethereum, "instance-a", ttl)
+                .await
+                .expect("lease acquired");
+
+            let block_1 = get_sample_block(1);
+            cached_gw
+                .start_transaction(&block_1, Some("vm:test"))
+                .await;
+            cached_gw
+                .upsert_block(slice::from_ref(&block_1))
+                .await
+                .expect("block added");
+            cached_gw
+                .commit_transaction(0)
+                .await
+                .expect("commit with lease succeeds");
+
+            // Another instance takes over the lease
+            gateway
+                .release_extractor_lease("vm:test", &Chain::Ethereum, "instance-a", &mut connection)
+                .await
+                .expect("lease released");
+            gateway
+                .acquire_extractor_lease(
+                    "vm:test",
+                    &Chain::Ethereum,
+                    "instance-b",
+                    ttl,
+                    &mut connection,
+                )
+                .await
+                .expect("lease taken over");
+
+            let block_2 = get_sample_block(2);
+            cached_gw
+                .start_transaction(&block_2, Some("vm:test"))
+                .await;
+            cached_gw
+                .upsert_block(slice::from_ref(&block_2))
+                .await
+                .expect("block added");
+            let res = cached_gw.commit_transaction(0).await;
+            handle.abort();
+
+            assert!(res.is_err());
+            assert!(gateway
+                .get_block(&BlockIdentifier::Number((Chain::Ethereum, 2)), &mut connection)
+                .await
+                .is_err());
+        })
+        .await;
+    }
+
     fn get_sample_block(version: usize) -> models::blockchain::Block {
         let ts1 = yesterday_one_am();
         let ts2 = ts1 + Duration::from_secs(3600);
@@ -1694,6 +3607,7 @@ mod test_serial_db {
             operations,
             tx: os_tx,
             owner: None,
+            lease_holder: None,
         };
 
         tx.send(DBCacheMessage::Write(db_transaction))
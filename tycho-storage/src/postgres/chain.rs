@@ -1,16 +1,26 @@
 use std::collections::HashMap;
 
-use diesel::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Integer, Timestamptz},
+};
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use itertools::Itertools;
 use tracing::{instrument, warn};
 use tycho_common::{
-    models::{blockchain::*, BlockHash, TxHash},
+    models::{blockchain::*, BlockHash, Chain, TxHash},
     storage::{BlockIdentifier, StorageError},
     Bytes,
 };
 
-use super::{orm, schema, storage_error_from_diesel, PostgresError, PostgresGateway, MAX_TS};
+use super::{
+    audit::{record_operation, AuditRecord},
+    orm, schema, storage_error_from_diesel, PostgresError, PostgresGateway, MAX_TS,
+};
+
+/// First key of the advisory locks serializing block writes, the chain id is the second one.
+const BLOCK_WRITER_LOCK_NAMESPACE: i32 = 0x626c6b;
 
 impl PostgresGateway {
     #[instrument(skip_all)]
@@ -24,6 +34,7 @@ impl PostgresGateway {
             warn!("Upsert blocks called with empty blocks!");
             return Ok(());
         }
+        self.id_cache.start_block();
         let block_chain_id = self.get_chain_id(&blocks[0].chain)?;
         let new_blocks = blocks
             .iter()
@@ -34,10 +45,12 @@ impl PostgresGateway {
                 main: true,
                 number: new.number as i64,
                 ts: new.ts,
+                extra: (!new.extra.is_empty()).then(|| new.extra.clone().into()),
             })
             .collect_vec();
 
-        // assumes that block with the same hash will not appear with different values
+        // Blocks are identified by their hash. Extractors of the same chain write the same blocks,
+        // the first write wins and later ones are no-ops.
         diesel::insert_into(block)
             .values(&new_blocks)
             .on_conflict_do_nothing()
@@ -51,6 +64,67 @@ impl PostgresGateway {
                     None,
                 )
             })?;
+
+        // A stored block with the same hash at another height means the writers disagree about
+        // the chain, which must not be silently ignored.
+        let hashes = new_blocks
+            .iter()
+            .map(|b| b.hash.clone())
+            .collect_vec();
+        let stored = block
+            .filter(chain_id.eq(block_chain_id))
+            .filter(hash.eq_any(&hashes))
+            .select((hash, number))
+            .load::<(BlockHash, i64)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        for (stored_hash, stored_number) in stored {
+            if let Some(conflict) = new_blocks
+                .iter()
+                .find(|b| b.hash == stored_hash && b.number != stored_number)
+            {
+                return Err(StorageError::Unexpected(format!(
+                    "Block {stored_hash} is stored at height {stored_number}, not {}",
+                    conflict.number
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Serializes writes of blocks to `chain` by holding a session level advisory lock until
+    /// [`PostgresGateway::unlock_block_writer`] is called.
+    ///
+    /// Must be taken before the write transaction starts: repeatable read transactions would
+    /// otherwise not see blocks committed while waiting for the lock and fail to insert them.
+    pub(crate) async fn lock_block_writer(
+        &self,
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        diesel::sql_query("SELECT pg_advisory_lock($1, $2::integer)")
+            .bind::<Integer, _>(BLOCK_WRITER_LOCK_NAMESPACE)
+            .bind::<BigInt, _>(chain_id)
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+
+    /// Releases the lock taken by [`PostgresGateway::lock_block_writer`].
+    pub(crate) async fn unlock_block_writer(
+        &self,
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        diesel::sql_query("SELECT pg_advisory_unlock($1, $2::integer)")
+            .bind::<Integer, _>(BLOCK_WRITER_LOCK_NAMESPACE)
+            .bind::<BigInt, _>(chain_id)
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
         Ok(())
     }
 
@@ -79,9 +153,197 @@ impl PostgresGateway {
             std::mem::take(&mut orm_block.hash),
             std::mem::take(&mut orm_block.parent_hash),
             orm_block.ts,
+        )
+        .with_extra(
+            orm_block
+                .extra
+                .take()
+                .map(BlockExtra::from)
+                .unwrap_or_default(),
+        ))
+    }
+
+    #[instrument(skip_all)]
+    pub async fn get_finalized_block(
+        &self,
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Block, StorageError> {
+        let mut orm_block = orm::Block::most_recent_finalized(*chain, conn)
+            .await
+            .map_err(|err| {
+                storage_error_from_diesel(err, "FinalizedBlock", &chain.to_string(), None)
+            })?;
+        Ok(Block::new(
+            orm_block.number as u64,
+            *chain,
+            std::mem::take(&mut orm_block.hash),
+            std::mem::take(&mut orm_block.parent_hash),
+            orm_block.ts,
+        )
+        .with_extra(
+            orm_block
+                .extra
+                .take()
+                .map(BlockExtra::from)
+                .unwrap_or_default(),
         ))
     }
 
+    /// Returns the latest main block at or before `ts` of each chain, chains without such a block
+    /// are omitted.
+    #[instrument(skip(self, conn))]
+    pub async fn get_blocks_at(
+        &self,
+        chains: &[Chain],
+        ts: NaiveDateTime,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<Chain, Block>, StorageError> {
+        let mut blocks = HashMap::new();
+        for chain in chains {
+            let chain_id = self.get_chain_id(chain)?;
+            let orm_block = schema::block::table
+                .filter(schema::block::chain_id.eq(chain_id))
+                .filter(schema::block::main.eq(true))
+                .filter(schema::block::ts.le(ts))
+                .order((schema::block::ts.desc(), schema::block::number.desc()))
+                .select(orm::Block::as_select())
+                .first::<orm::Block>(conn)
+                .await
+                .optional()
+                .map_err(PostgresError::from)?;
+            if let Some(mut orm_block) = orm_block {
+                let block = Block::new(
+                    orm_block.number as u64,
+                    *chain,
+                    std::mem::take(&mut orm_block.hash),
+                    std::mem::take(&mut orm_block.parent_hash),
+                    orm_block.ts,
+                )
+                .with_extra(
+                    orm_block
+                        .extra
+                        .take()
+                        .map(BlockExtra::from)
+                        .unwrap_or_default(),
+                );
+                blocks.insert(*chain, block);
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Raises the finality status of the blocks of `chain` up to and including block number
+    /// `up_to` to `status`. Blocks that already reached `status` or a stronger one are untouched.
+    #[instrument(skip(self, conn))]
+    pub async fn update_block_finality(
+        &self,
+        chain: &Chain,
+        status: FinalityStatus,
+        up_to: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use super::schema::block::dsl::*;
+        let block_chain_id = self.get_chain_id(chain)?;
+        let status = orm::FinalityStatus::from(status);
+        diesel::update(block)
+            .filter(chain_id.eq(block_chain_id))
+            .filter(number.le(up_to))
+            .filter(finality_status.lt(status))
+            .set(finality_status.eq(status))
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+
+    /// Stores block digests, replacing the digest of blocks that already have one.
+    #[instrument(skip_all)]
+    pub async fn upsert_block_digests(
+        &self,
+        digests: &[BlockDigest],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        if digests.is_empty() {
+            return Ok(());
+        }
+        let chain_id = self.get_chain_id(&digests[0].chain)?;
+        let hashes = digests
+            .iter()
+            .map(|d| d.block_hash.clone())
+            .collect_vec();
+        let block_ids = schema::block::table
+            .filter(schema::block::chain_id.eq(chain_id))
+            .filter(schema::block::hash.eq_any(&hashes))
+            .select((schema::block::hash, schema::block::id))
+            .get_results::<(BlockHash, i64)>(conn)
+            .await
+            .map_err(PostgresError::from)?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let new_digests = digests
+            .iter()
+            .map(|d| {
+                let block_id = *block_ids
+                    .get(&d.block_hash)
+                    .ok_or_else(|| {
+                        StorageError::NoRelatedEntity(
+                            "Block".to_string(),
+                            "BlockDigest".to_string(),
+                            d.block_hash.to_string(),
+                        )
+                    })?;
+                Ok(orm::NewBlockDigest { block_id, digest: d.digest.clone() })
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+
+        diesel::insert_into(schema::block_digest::table)
+            .values(&new_digests)
+            .on_conflict(schema::block_digest::block_id)
+            .do_update()
+            .set(
+                schema::block_digest::digest
+                    .eq(diesel::upsert::excluded(schema::block_digest::digest)),
+            )
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+
+    /// Returns the digests of the main blocks of `chain` within `start_block..=end_block`,
+    /// ordered by block number.
+    #[instrument(skip(self, conn))]
+    pub async fn get_block_digests(
+        &self,
+        chain: &Chain,
+        start_block: i64,
+        end_block: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<BlockDigest>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let digests = schema::block_digest::table
+            .inner_join(schema::block::table)
+            .filter(schema::block::chain_id.eq(chain_id))
+            .filter(schema::block::main.eq(true))
+            .filter(schema::block::number.between(start_block, end_block))
+            .order_by(schema::block::number)
+            .select((schema::block::number, schema::block::hash, schema::block_digest::digest))
+            .load::<(i64, BlockHash, Bytes)>(conn)
+            .await
+            .map_err(PostgresError::from)?
+            .into_iter()
+            .map(|(block_number, block_hash, digest)| BlockDigest {
+                chain: *chain,
+                block_number: block_number as u64,
+                block_hash,
+                digest,
+            })
+            .collect();
+        Ok(digests)
+    }
+
     #[instrument(skip_all)]
     pub async fn upsert_tx(
         &self,
@@ -189,6 +451,8 @@ impl PostgresGateway {
         // from a big number of tables. Reverting state, signifies deleting
         // history. We will not keep any branches in the db only the main branch
         // will be kept.
+        // Reverted transactions lose their ids, they get new ones once inserted again.
+        self.clear_id_cache();
         let block = orm::Block::by_id(to, conn)
             .await
             .map_err(PostgresError::from)?;
@@ -198,65 +462,73 @@ impl PostgresGateway {
         // deleting the correct blocks, which then triggers cascading deletes on
         // child entries. All blocks after the `to` block are deleted - the `to`
         // block and its connected data persists.
-        diesel::delete(
+        let deleted: Vec<i64> = diesel::delete(
             schema::block::table
                 .filter(schema::block::number.gt(block.number))
                 .filter(schema::block::chain_id.eq(block.chain_id)),
         )
-        .execute(conn)
-        .await
-        .map_err(PostgresError::from)?;
-
-        // Any versioned table's rows, which have `valid_to` set to "> block.ts"
-        // need, to be updated to be valid again (thus, valid_to = NULL).
-        diesel::update(
-            schema::contract_storage::table.filter(schema::contract_storage::valid_to.gt(block.ts)),
-        )
-        .set(schema::contract_storage::valid_to.eq(MAX_TS))
-        .execute(conn)
+        .returning(schema::block::number)
+        .get_results(conn)
         .await
         .map_err(PostgresError::from)?;
 
-        diesel::update(
-            schema::account_balance::table.filter(schema::account_balance::valid_to.gt(block.ts)),
-        )
-        .set(schema::account_balance::valid_to.eq(MAX_TS))
-        .execute(conn)
-        .await
-        .map_err(PostgresError::from)?;
-
-        diesel::update(
-            schema::contract_code::table.filter(schema::contract_code::valid_to.gt(block.ts)),
-        )
-        .set(schema::contract_code::valid_to.eq(MAX_TS))
-        .execute(conn)
-        .await
-        .map_err(PostgresError::from)?;
-
-        diesel::update(
-            schema::protocol_state::table.filter(schema::protocol_state::valid_to.gt(block.ts)),
-        )
-        .set(schema::protocol_state::valid_to.eq(MAX_TS))
-        .execute(conn)
-        .await
-        .map_err(PostgresError::from)?;
-
-        // Any versioned table's rows, which have `deleted_at` set to "> block.ts"
-        // need, to be updated to be valid again (thus, deleted_at = NULL).
-        diesel::update(schema::account::table.filter(schema::account::deleted_at.gt(block.ts)))
-            .set(schema::account::deleted_at.eq(MAX_TS))
+        // Versions closed after the `to` block become current again, and so do entities deleted
+        // after it. All versioned tables are updated by a single set-based statement. It is scoped
+        // to the reverted chain, and rows that are already current are skipped so that the latest
+        // state is not rewritten on every revert.
+        //
+        // Current accounts and components have no `deleted_at`, so restoring them clears it. The
+        // deletion transaction was removed together with its block and their token and contract
+        // links are left untouched on deletion, so nothing else needs restoring. Entities created
+        // after the `to` block are gone already: they cascade from their creation transaction.
+        let query = r#"
+            WITH chain_accounts AS (
+                SELECT id FROM account WHERE chain_id = $1
+            ), chain_components AS (
+                SELECT id FROM protocol_component WHERE chain_id = $1
+            ), contract_storage_reverted AS (
+                UPDATE contract_storage SET valid_to = $3
+                WHERE valid_to > $2 AND valid_to < $3
+                    AND account_id IN (SELECT id FROM chain_accounts)
+            ), account_balance_reverted AS (
+                UPDATE account_balance SET valid_to = $3
+                WHERE valid_to > $2 AND valid_to < $3
+                    AND account_id IN (SELECT id FROM chain_accounts)
+            ), contract_code_reverted AS (
+                UPDATE contract_code SET valid_to = $3
+                WHERE valid_to > $2 AND valid_to < $3
+                    AND account_id IN (SELECT id FROM chain_accounts)
+            ), protocol_state_reverted AS (
+                UPDATE protocol_state SET valid_to = $3
+                WHERE valid_to > $2 AND valid_to < $3
+                    AND protocol_component_id IN (SELECT id FROM chain_components)
+            ), component_balance_reverted AS (
+                UPDATE component_balance SET valid_to = $3
+                WHERE valid_to > $2 AND valid_to < $3
+                    AND protocol_component_id IN (SELECT id FROM chain_components)
+            ), account_reverted AS (
+                UPDATE account SET deleted_at = NULL
+                WHERE chain_id = $1 AND deleted_at > $2
+            )
+            UPDATE protocol_component SET deleted_at = NULL
+            WHERE chain_id = $1 AND deleted_at > $2
+            "#;
+        diesel::sql_query(query)
+            .bind::<BigInt, _>(block.chain_id)
+            .bind::<Timestamptz, _>(block.ts)
+            .bind::<Timestamptz, _>(MAX_TS)
             .execute(conn)
             .await
             .map_err(PostgresError::from)?;
 
-        diesel::update(
-            schema::protocol_component::table
-                .filter(schema::protocol_component::deleted_at.gt(block.ts)),
-        )
-        .set(schema::protocol_component::deleted_at.eq(MAX_TS))
-        .execute(conn)
-        .await
-        .map_err(PostgresError::from)?;
+        let chain = self.get_chain(&block.chain_id)?;
+        let mut record = AuditRecord::new("revert_state", deleted.len() as i64)
+            .chain(&chain)
+            .target(format!("revert to block {}", block.number));
+        if let (Some(first), Some(last)) = (deleted.iter().min(), deleted.iter().max()) {
+            record = record.block_range(*first, *last);
+        }
+        record_operation(record, conn).await?;
 
         Ok(())
     }
@@ -266,6 +538,7 @@ impl PostgresGateway {
 mod test {
     use std::{slice, str::FromStr, time::Duration};
 
+    use chrono::NaiveDateTime;
     use diesel_async::AsyncConnection;
     use tycho_common::models::Chain;
 
@@ -350,6 +623,98 @@ mod test {
         assert_eq!(block, exp);
     }
 
+    #[tokio::test]
+    async fn test_get_blocks_at() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let between = gw
+            .get_blocks_at(
+                &[Chain::Ethereum],
+                yesterday_midnight() + chrono::Duration::minutes(10),
+                &mut conn,
+            )
+            .await
+            .unwrap();
+        let before = gw
+            .get_blocks_at(
+                &[Chain::Ethereum],
+                yesterday_midnight() - chrono::Duration::minutes(10),
+                &mut conn,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(between[&Chain::Ethereum].number, 1);
+        assert!(before.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_block_finality() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let exp = Block::new(
+            1,
+            Chain::Ethereum,
+            Bytes::from("0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6"),
+            Bytes::from("0xd4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"),
+            yesterday_midnight(),
+        );
+
+        gw.update_block_finality(&Chain::Ethereum, FinalityStatus::Finalized, 1, &mut conn)
+            .await
+            .unwrap();
+        // Must not downgrade block 1.
+        gw.update_block_finality(&Chain::Ethereum, FinalityStatus::Safe, 2, &mut conn)
+            .await
+            .unwrap();
+        let finalized = gw
+            .get_finalized_block(&Chain::Ethereum, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(finalized, exp);
+    }
+
+    #[tokio::test]
+    async fn test_upsert_block_digests() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let digest = |number: u64, hash: &str, digest: &str| BlockDigest {
+            chain: Chain::Ethereum,
+            block_number: number,
+            block_hash: Bytes::from(hash),
+            digest: Bytes::from(digest),
+        };
+        let block_1 = "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6";
+        let block_2 = "0xb495a1d7e6663152ae92708da4843337b958146015a2802f4193a410044698c9";
+
+        gw.upsert_block_digests(
+            &[digest(1, block_1, "0x01"), digest(2, block_2, "0x02")],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        // Digests are replaced once a block is written again.
+        gw.upsert_block_digests(&[digest(2, block_2, "0x03")], &mut conn)
+            .await
+            .unwrap();
+        let all = gw
+            .get_block_digests(&Chain::Ethereum, 0, 10, &mut conn)
+            .await
+            .unwrap();
+        let second = gw
+            .get_block_digests(&Chain::Ethereum, 2, 2, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(all, vec![digest(1, block_1, "0x01"), digest(2, block_2, "0x03")]);
+        assert_eq!(second, vec![digest(2, block_2, "0x03")]);
+    }
+
     #[tokio::test]
     async fn test_add_block() {
         let mut conn = setup_db().await;
@@ -392,6 +757,64 @@ mod test {
         assert_eq!(retrieved_block, block);
     }
 
+    #[tokio::test]
+    async fn test_upsert_block_conflicting_height() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let stored = gw
+            .get_block(&BlockIdentifier::Number((Chain::Ethereum, 1)), &mut conn)
+            .await
+            .unwrap();
+        let mut conflicting = stored.clone();
+        conflicting.number = 5;
+
+        let again = gw
+            .upsert_block(slice::from_ref(&stored), &mut conn)
+            .await;
+        let res = gw
+            .upsert_block(slice::from_ref(&conflicting), &mut conn)
+            .await;
+
+        assert!(again.is_ok());
+        assert_eq!(
+            res,
+            Err(StorageError::Unexpected(format!(
+                "Block {} is stored at height 1, not 5",
+                stored.hash
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upsert_block_with_extra() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let mut extra = BlockExtra::default();
+        extra.set(BlockExtra::BLOB_GAS_USED, Some(393216u64));
+        extra.set(BlockExtra::EXCESS_BLOB_GAS, Some(0u64));
+        let block = Block::new(
+            3,
+            Chain::Ethereum,
+            Bytes::from("0x03"),
+            Bytes::from("0xb495a1d7e6663152ae92708da4843337b958146015a2802f4193a410044698c9"),
+            yesterday_half_past_midnight(),
+        )
+        .with_extra(extra);
+
+        gw.upsert_block(slice::from_ref(&block), &mut conn)
+            .await
+            .unwrap();
+        let retrieved_block = gw
+            .get_block(&BlockIdentifier::Hash(block.hash.clone()), &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(retrieved_block, block);
+        assert_eq!(retrieved_block.extra.blob_gas_used(), Some(393216));
+    }
+
     fn transaction(hash: &str) -> Transaction {
         Transaction {
             hash: Bytes::from(hash),
@@ -625,4 +1048,205 @@ mod test {
             .unwrap();
         assert_eq!(c1.len(), 0);
     }
+
+    /// Reverting to block 1 drops a component created in block 2 together with its token links,
+    /// and restores a component deleted in block 2 with its token links intact.
+    #[tokio::test]
+    async fn test_revert_protocol_components() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[
+                (
+                    blk[0],
+                    1i64,
+                    "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945",
+                ),
+                (
+                    blk[1],
+                    1i64,
+                    "0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7",
+                ),
+            ],
+        )
+        .await;
+        let (_, weth) = db_fixtures::insert_token(
+            &mut conn,
+            chain_id,
+            "C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+            "WETH",
+            18,
+            None,
+        )
+        .await;
+        let system_id = db_fixtures::insert_protocol_system(&mut conn, "ambient".to_owned()).await;
+        let type_id = db_fixtures::insert_protocol_type(&mut conn, "pool", None, None, None).await;
+        let deleted = db_fixtures::insert_protocol_component(
+            &mut conn,
+            "deleted_pool",
+            chain_id,
+            system_id,
+            type_id,
+            txn[0],
+            Some(vec![weth]),
+            None,
+        )
+        .await;
+        db_fixtures::insert_protocol_component(
+            &mut conn,
+            "created_pool",
+            chain_id,
+            system_id,
+            type_id,
+            txn[1],
+            Some(vec![weth]),
+            None,
+        )
+        .await;
+        diesel::update(schema::protocol_component::table.find(deleted))
+            .set((
+                schema::protocol_component::deleted_at.eq(yesterday_half_past_midnight()),
+                schema::protocol_component::deletion_tx.eq(txn[1]),
+            ))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        gw.revert_state(&BlockIdentifier::Number((Chain::Ethereum, 1)), &mut conn)
+            .await
+            .unwrap();
+
+        let components = schema::protocol_component::table
+            .select((
+                schema::protocol_component::id,
+                schema::protocol_component::deleted_at,
+                schema::protocol_component::deletion_tx,
+            ))
+            .get_results::<(i64, Option<NaiveDateTime>, Option<i64>)>(&mut conn)
+            .await
+            .unwrap();
+        let linked_components = schema::protocol_component_holds_token::table
+            .select(schema::protocol_component_holds_token::protocol_component_id)
+            .get_results::<i64>(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(components, vec![(deleted, None, None)]);
+        assert_eq!(linked_components, vec![deleted]);
+    }
+
+    /// Reverts 64 blocks of a contract with a long storage history next to a large, untouched
+    /// current state and checks the revert stays fast.
+    #[tokio::test]
+    async fn test_revert_64_blocks_performance() {
+        const REVERTED_SLOTS: i64 = 1_000;
+        const CURRENT_SLOTS: i64 = 50_000;
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let start = yesterday_midnight();
+
+        // 65 blocks one minute apart, each with a single transaction.
+        diesel::sql_query(
+            r#"
+            INSERT INTO block (hash, parent_hash, main, number, ts, chain_id)
+            SELECT int8send(n), int8send(n - 1), true, n, $2 + n * interval '1 minute', $1
+            FROM generate_series(0, 64) n
+            "#,
+        )
+        .bind::<BigInt, _>(chain_id)
+        .bind::<Timestamptz, _>(start)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        diesel::sql_query(
+            r#"
+            INSERT INTO "transaction" (hash, "from", "to", index, block_id)
+            SELECT int8send(number), '\x00', '\x00', 0, id FROM block WHERE chain_id = $1
+            "#,
+        )
+        .bind::<BigInt, _>(chain_id)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        let first_tx = schema::transaction::table
+            .inner_join(schema::block::table)
+            .filter(schema::block::number.eq(0))
+            .select(schema::transaction::id)
+            .first::<i64>(&mut conn)
+            .await
+            .unwrap();
+        let reverted = db_fixtures::insert_account(
+            &mut conn,
+            "6B175474E89094C44Da98b954EedeAC495271d0F",
+            "reverted",
+            chain_id,
+            Some(first_tx),
+        )
+        .await;
+        let current = db_fixtures::insert_account(
+            &mut conn,
+            "73BcE791c239c8010Cd3C857d96580037CCdd0EE",
+            "current",
+            chain_id,
+            Some(first_tx),
+        )
+        .await;
+
+        // Every slot of the reverted account changes in every block.
+        diesel::sql_query(
+            r#"
+            INSERT INTO contract_storage (slot, value, account_id, modify_tx, ordinal, valid_from, valid_to)
+            SELECT int8send(s), int8send(b.number), $1, t.id, 0, b.ts,
+                   COALESCE(lead(b.ts) OVER (PARTITION BY s ORDER BY b.number), $4)
+            FROM generate_series(1, $3) s
+            CROSS JOIN block b
+            JOIN "transaction" t ON t.block_id = b.id
+            WHERE b.chain_id = $2
+            "#,
+        )
+        .bind::<BigInt, _>(reverted)
+        .bind::<BigInt, _>(chain_id)
+        .bind::<BigInt, _>(REVERTED_SLOTS)
+        .bind::<Timestamptz, _>(MAX_TS)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        diesel::sql_query(
+            r#"
+            INSERT INTO contract_storage (slot, value, account_id, modify_tx, ordinal, valid_from, valid_to)
+            SELECT int8send(s), int8send(s), $1, $2, 0, $3, $4
+            FROM generate_series(1, $5) s
+            "#,
+        )
+        .bind::<BigInt, _>(current)
+        .bind::<BigInt, _>(first_tx)
+        .bind::<Timestamptz, _>(start)
+        .bind::<Timestamptz, _>(MAX_TS)
+        .bind::<BigInt, _>(CURRENT_SLOTS)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let started = std::time::Instant::now();
+        gw.revert_state(&BlockIdentifier::Number((Chain::Ethereum, 0)), &mut conn)
+            .await
+            .unwrap();
+        let elapsed = started.elapsed();
+
+        assert!(elapsed < Duration::from_secs(10), "reverting 64 blocks took {elapsed:?}");
+        let values = schema::contract_storage::table
+            .filter(schema::contract_storage::account_id.eq(reverted))
+            .filter(schema::contract_storage::valid_to.eq(MAX_TS))
+            .select(schema::contract_storage::value)
+            .get_results::<Option<Bytes>>(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(values.len() as i64, REVERTED_SLOTS);
+        assert!(values
+            .iter()
+            .all(|value| value == &Some(Bytes::zero(8))));
+    }
 }
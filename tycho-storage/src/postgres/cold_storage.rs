@@ -0,0 +1,509 @@
+//! Tiering of aged contract data to an object store.
+//!
+//! On archive nodes contract code and historical storage versions make up most of the database.
+//! This module moves them to an S3 compatible object store and leaves small stubs in Postgres:
+//!
+//! - Offloaded code rows keep their hash, but their `code` column is emptied and `cold_ref` holds
+//!   the key of the object containing the code. Objects are keyed by code hash, so identical code
+//!   is only stored once.
+//! - Offloaded storage versions are removed from `contract_storage`. A `contract_storage_cold` row
+//!   records the account and the validity range covered by the object holding them.
+//!
+//! The gateway reads offloaded data back transparently. Only data older than the configured minimum
+//! age is moved, which must be well beyond any reorg depth: deltas for reverts are computed from
+//! the data kept in Postgres only.
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument, Level};
+use tycho_common::{
+    models::{Address, Code, CodeHash},
+    storage::StorageError,
+    Bytes,
+};
+
+use super::{scheduler::PeriodicTask, schema, PostgresError, PostgresGateway};
+
+/// Number of code blobs kept in memory after being fetched from the object store.
+const CODE_CACHE_CAPACITY: usize = 10_000;
+
+/// Minimal interface to an S3 compatible object store.
+#[async_trait]
+pub trait ColdStore: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ColdStorageConfig {
+    /// Data younger than this is never offloaded.
+    pub min_age: chrono::Duration,
+    /// Maximum number of code rows, respectively accounts, processed per batch.
+    pub batch_size: i64,
+    /// Maximum number of storage versions written into a single object.
+    pub max_segment_size: i64,
+    /// Pause between offload runs.
+    pub interval: std::time::Duration,
+}
+
+impl Default for ColdStorageConfig {
+    fn default() -> Self {
+        Self {
+            min_age: chrono::Duration::days(30),
+            batch_size: 100,
+            max_segment_size: 50_000,
+            interval: std::time::Duration::from_secs(3600),
+        }
+    }
+}
+
+/// A historical version of a storage slot as stored in the object store.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ColdSlotVersion {
+    slot: Bytes,
+    value: Option<Bytes>,
+    previous_value: Option<Bytes>,
+    modify_tx: i64,
+    ordinal: i64,
+    valid_from: NaiveDateTime,
+    valid_to: NaiveDateTime,
+}
+
+/// Read-through access to offloaded data.
+pub(crate) struct ColdStorage {
+    store: Arc<dyn ColdStore>,
+    code_cache: Mutex<LruCache<String, Code>>,
+}
+
+impl ColdStorage {
+    pub(crate) fn new(store: Arc<dyn ColdStore>) -> Self {
+        Self {
+            store,
+            code_cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(CODE_CACHE_CAPACITY).expect("capacity is non zero"),
+            )),
+        }
+    }
+
+    async fn get_code(&self, key: &str) -> Result<Code, StorageError> {
+        if let Some(code) = self
+            .code_cache
+            .lock()
+            .expect("code cache lock poisoned")
+            .get(key)
+        {
+            return Ok(code.clone());
+        }
+        let code = Code::from(self.store.get(key).await?);
+        self.code_cache
+            .lock()
+            .expect("code cache lock poisoned")
+            .put(key.to_string(), code.clone());
+        Ok(code)
+    }
+
+    async fn get_slot_versions(&self, key: &str) -> Result<Vec<ColdSlotVersion>, StorageError> {
+        let data = self.store.get(key).await?;
+        serde_json::from_slice(&data).map_err(|err| {
+            StorageError::DecodeError(format!("Invalid cold storage object {key}: {err}"))
+        })
+    }
+}
+
+fn code_key(code_hash: &CodeHash) -> String {
+    format!("code/{}", hex::encode(code_hash))
+}
+
+fn slots_key(account_id: i64, valid_from: NaiveDateTime, valid_to: NaiveDateTime) -> String {
+    format!(
+        "slots/{account_id}/{}-{}",
+        valid_from.and_utc().timestamp_micros(),
+        valid_to.and_utc().timestamp_micros()
+    )
+}
+
+/// Picks the versions valid at `version_ts`, keeping the latest one per slot.
+fn slots_valid_at(
+    versions: Vec<ColdSlotVersion>,
+    version_ts: NaiveDateTime,
+) -> HashMap<Bytes, ColdSlotVersion> {
+    let mut res: HashMap<Bytes, ColdSlotVersion> = HashMap::new();
+    for version in versions
+        .into_iter()
+        .filter(|v| v.valid_from <= version_ts && v.valid_to > version_ts)
+    {
+        match res.entry(version.slot.clone()) {
+            Entry::Occupied(mut e) => {
+                let current = e.get();
+                if (version.valid_from, version.ordinal) > (current.valid_from, current.ordinal) {
+                    e.insert(version);
+                }
+            }
+            Entry::Vacant(e) => {
+                e.insert(version);
+            }
+        }
+    }
+    res
+}
+
+impl PostgresGateway {
+    /// Returns the code, fetching it from cold storage if it was offloaded.
+    pub(crate) async fn resolve_code(
+        &self,
+        code: Code,
+        cold_ref: Option<&str>,
+    ) -> Result<Code, StorageError> {
+        match (cold_ref, &self.cold_storage) {
+            (None, _) => Ok(code),
+            (Some(key), Some(cold_storage)) => cold_storage.get_code(key).await,
+            (Some(key), None) => Err(StorageError::Unexpected(format!(
+                "Code {key} was offloaded but no cold store is configured"
+            ))),
+        }
+    }
+
+    /// Retrieves offloaded slot values valid at `version_ts`.
+    ///
+    /// Returns the same `(account_id, slot, value)` tuples as the query on `contract_storage`, so
+    /// both can be merged. Nothing is read if no cold store is configured.
+    #[instrument(level = Level::DEBUG, skip(self, contracts, conn))]
+    pub(crate) async fn get_cold_contract_slots(
+        &self,
+        chain_id: i64,
+        contracts: Option<&[Address]>,
+        version_ts: NaiveDateTime,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<(i64, Bytes, Option<Bytes>)>, StorageError> {
+        let Some(cold_storage) = &self.cold_storage else {
+            return Ok(Vec::new());
+        };
+
+        let segments = {
+            use schema::{account, contract_storage_cold::dsl::*};
+            let mut q = contract_storage_cold
+                .inner_join(account::table)
+                .filter(account::chain_id.eq(chain_id))
+                .filter(valid_from.le(version_ts))
+                .filter(valid_to.gt(version_ts))
+                .select((account_id, object_key))
+                .into_boxed();
+            if let Some(addresses) = contracts {
+                q = q.filter(account::address.eq_any(addresses));
+            }
+            q.get_results::<(i64, String)>(conn)
+                .await
+                .map_err(PostgresError::from)?
+        };
+
+        let mut res = Vec::new();
+        for (account_id, key) in segments {
+            let versions = cold_storage
+                .get_slot_versions(&key)
+                .await?;
+            res.extend(
+                slots_valid_at(versions, version_ts)
+                    .into_values()
+                    .map(|v| (account_id, v.slot, v.value)),
+            );
+        }
+        Ok(res)
+    }
+
+    /// Moves a batch of code blobs first valid before `cutoff` to cold storage.
+    ///
+    /// Returns the number of offloaded code rows.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub(crate) async fn offload_code(
+        &self,
+        cutoff: NaiveDateTime,
+        batch_size: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<usize, StorageError> {
+        let Some(cold_storage) = &self.cold_storage else {
+            return Err(StorageError::Unsupported("No cold store configured".to_string()));
+        };
+
+        let rows = {
+            use schema::contract_code::dsl::*;
+            contract_code
+                .filter(cold_ref.is_null())
+                .filter(valid_from.lt(cutoff))
+                .filter(code.ne(Code::default()))
+                .order_by(id)
+                .limit(batch_size)
+                .select((id, hash, code))
+                .get_results::<(i64, CodeHash, Code)>(conn)
+                .await
+                .map_err(PostgresError::from)?
+        };
+
+        let mut ids_by_key: HashMap<String, Vec<i64>> = HashMap::new();
+        for (row_id, code_hash, code) in rows {
+            let key = code_key(&code_hash);
+            if let Entry::Vacant(e) = ids_by_key.entry(key.clone()) {
+                cold_storage
+                    .store
+                    .put(&key, code.to_vec())
+                    .await?;
+                e.insert(Vec::new());
+            }
+            ids_by_key
+                .get_mut(&key)
+                .expect("entry was just inserted")
+                .push(row_id);
+        }
+
+        let mut n_offloaded = 0;
+        for (key, ids) in ids_by_key {
+            use schema::contract_code::dsl::*;
+            n_offloaded += diesel::update(contract_code.filter(id.eq_any(&ids)))
+                .set((code.eq(Code::default()), cold_ref.eq(Some(&key))))
+                .execute(conn)
+                .await
+                .map_err(PostgresError::from)?;
+        }
+        Ok(n_offloaded)
+    }
+
+    /// Moves historical storage versions of a batch of accounts to cold storage.
+    ///
+    /// Only versions that stopped being valid before `cutoff` are moved, at most
+    /// `max_segment_size` (plus ties) per account and call. Returns the number of offloaded
+    /// versions.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub(crate) async fn offload_slots(
+        &self,
+        cutoff: NaiveDateTime,
+        batch_size: i64,
+        max_segment_size: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<usize, StorageError> {
+        let Some(cold_storage) = &self.cold_storage else {
+            return Err(StorageError::Unsupported("No cold store configured".to_string()));
+        };
+
+        let account_ids = {
+            use schema::contract_storage::dsl::*;
+            contract_storage
+                .filter(valid_to.lt(cutoff))
+                .select(account_id)
+                .distinct()
+                .limit(batch_size)
+                .get_results::<i64>(conn)
+                .await
+                .map_err(PostgresError::from)?
+        };
+
+        let mut n_offloaded = 0;
+        for acc_id in account_ids {
+            use schema::contract_storage::dsl::*;
+
+            // Segments end at the valid_to of the n-th oldest version, so all versions sharing
+            // this valid_to end up in the same segment.
+            let segment_end = contract_storage
+                .filter(account_id.eq(acc_id))
+                .filter(valid_to.lt(cutoff))
+                .order_by(valid_to)
+                .offset(max_segment_size - 1)
+                .select(valid_to)
+                .first::<NaiveDateTime>(conn)
+                .await
+                .optional()
+                .map_err(PostgresError::from)?
+                .unwrap_or(cutoff);
+
+            let versions = contract_storage
+                .filter(account_id.eq(acc_id))
+                .filter(valid_to.lt(cutoff))
+                .filter(valid_to.le(segment_end))
+                .select((slot, value, previous_value, modify_tx, ordinal, valid_from, valid_to))
+                .get_results::<(
+                    Bytes,
+                    Option<Bytes>,
+                    Option<Bytes>,
+                    i64,
+                    i64,
+                    NaiveDateTime,
+                    NaiveDateTime,
+                )>(conn)
+                .await
+                .map_err(PostgresError::from)?
+                .into_iter()
+                .map(|(s, v, pv, tx, o, from, to)| ColdSlotVersion {
+                    slot: s,
+                    value: v,
+                    previous_value: pv,
+                    modify_tx: tx,
+                    ordinal: o,
+                    valid_from: from,
+                    valid_to: to,
+                })
+                .collect::<Vec<_>>();
+
+            let (Some(segment_from), Some(segment_to)) = (
+                versions
+                    .iter()
+                    .map(|v| v.valid_from)
+                    .min(),
+                versions
+                    .iter()
+                    .map(|v| v.valid_to)
+                    .max(),
+            ) else {
+                continue;
+            };
+            let key = slots_key(acc_id, segment_from, segment_to);
+            let data = serde_json::to_vec(&versions).map_err(|err| {
+                StorageError::Unexpected(format!("Failed to encode storage versions: {err}"))
+            })?;
+            cold_storage
+                .store
+                .put(&key, data)
+                .await?;
+
+            n_offloaded += conn
+                .transaction(|conn| {
+                    async move {
+                        diesel::insert_into(schema::contract_storage_cold::table)
+                            .values((
+                                schema::contract_storage_cold::account_id.eq(acc_id),
+                                schema::contract_storage_cold::valid_from.eq(segment_from),
+                                schema::contract_storage_cold::valid_to.eq(segment_to),
+                                schema::contract_storage_cold::object_key.eq(&key),
+                            ))
+                            .execute(conn)
+                            .await?;
+                        diesel::delete(
+                            contract_storage
+                                .filter(account_id.eq(acc_id))
+                                .filter(valid_to.lt(cutoff))
+                                .filter(valid_to.le(segment_end)),
+                        )
+                        .execute(conn)
+                        .await
+                    }
+                    .scope_boxed()
+                })
+                .await
+                .map_err(PostgresError::from)?;
+        }
+        Ok(n_offloaded)
+    }
+}
+
+/// Periodically moves aged contract data to cold storage.
+pub(crate) struct ColdStorageOffloader {
+    pool: Pool<AsyncPgConnection>,
+    gateway: PostgresGateway,
+    config: ColdStorageConfig,
+}
+
+impl ColdStorageOffloader {
+    pub(crate) fn new(
+        pool: Pool<AsyncPgConnection>,
+        gateway: PostgresGateway,
+        config: ColdStorageConfig,
+    ) -> Self {
+        Self { pool, gateway, config }
+    }
+
+    /// Offloads batches until no aged data is left. Returns the number of offloaded code rows
+    /// and storage versions.
+    async fn offload(&self) -> Result<(usize, usize), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let cutoff = Utc::now().naive_utc() - self.config.min_age;
+
+        let mut n_code = 0;
+        loop {
+            let n = self
+                .gateway
+                .offload_code(cutoff, self.config.batch_size, &mut conn)
+                .await?;
+            n_code += n;
+            if n == 0 {
+                break;
+            }
+        }
+
+        let mut n_slots = 0;
+        loop {
+            let n = self
+                .gateway
+                .offload_slots(
+                    cutoff,
+                    self.config.batch_size,
+                    self.config.max_segment_size,
+                    &mut conn,
+                )
+                .await?;
+            n_slots += n;
+            if n == 0 {
+                break;
+            }
+        }
+        Ok((n_code, n_slots))
+    }
+}
+
+#[async_trait]
+impl PeriodicTask for ColdStorageOffloader {
+    async fn run_once(&mut self) -> Result<(), StorageError> {
+        let (n_code, n_slots) = self.offload().await?;
+        debug!(n_code, n_slots, "Offloaded aged contract data");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn version(slot: &str, ordinal: i64, from: i64, to: i64) -> ColdSlotVersion {
+        let ts = NaiveDateTime::default();
+        ColdSlotVersion {
+            slot: Bytes::from(slot),
+            value: Some(Bytes::from(vec![ordinal as u8])),
+            previous_value: None,
+            modify_tx: 1,
+            ordinal,
+            valid_from: ts + Duration::seconds(from),
+            valid_to: ts + Duration::seconds(to),
+        }
+    }
+
+    #[test]
+    fn test_slots_valid_at() {
+        let versions = vec![
+            version("0x01", 1, 0, 10),
+            version("0x01", 2, 10, 20),
+            version("0x02", 3, 5, 10),
+            version("0x02", 4, 5, 10),
+            version("0x03", 5, 20, 30),
+        ];
+
+        let res = slots_valid_at(versions, NaiveDateTime::default() + Duration::seconds(7));
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[&Bytes::from("0x01")].ordinal, 1);
+        assert_eq!(res[&Bytes::from("0x02")].ordinal, 4);
+    }
+}
@@ -0,0 +1,79 @@
+//! Periodic flagging of inactive protocol components.
+//!
+//! Components whose state and balances did not change for a configured time are marked inactive
+//! by setting `protocol_component.inactive_since`. The flag is cleared as soon as the component
+//! changes again. Every toggle is emitted as an event on the `component_activity` target, so it
+//! can be routed separately from the regular logs.
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection};
+use tracing::{debug, info};
+use tycho_common::{models::Chain, storage::StorageError};
+
+use super::{orm::ComponentActivityRow, scheduler::PeriodicTask, PostgresGateway};
+
+#[derive(Debug, Clone)]
+pub struct ComponentActivityConfig {
+    /// Components without state or balance changes for this long are flagged inactive.
+    pub inactive_after: chrono::Duration,
+    /// Pause between analysis runs.
+    pub interval: std::time::Duration,
+}
+
+impl Default for ComponentActivityConfig {
+    fn default() -> Self {
+        Self {
+            inactive_after: chrono::Duration::days(30),
+            interval: std::time::Duration::from_secs(3600),
+        }
+    }
+}
+
+pub(crate) struct ComponentActivityMonitor {
+    pool: Pool<AsyncPgConnection>,
+    gateway: PostgresGateway,
+    chain: Chain,
+    config: ComponentActivityConfig,
+}
+
+impl ComponentActivityMonitor {
+    pub(crate) fn new(
+        pool: Pool<AsyncPgConnection>,
+        gateway: PostgresGateway,
+        chain: Chain,
+        config: ComponentActivityConfig,
+    ) -> Self {
+        Self { pool, gateway, chain, config }
+    }
+
+    /// Updates the activity flags once and returns the toggled components.
+    async fn analyse(&self) -> Result<Vec<ComponentActivityRow>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let cutoff = Utc::now().naive_utc() - self.config.inactive_after;
+        self.gateway
+            .update_component_activity(&self.chain, cutoff, &mut conn)
+            .await
+    }
+}
+
+#[async_trait]
+impl PeriodicTask for ComponentActivityMonitor {
+    async fn run_once(&mut self) -> Result<(), StorageError> {
+        let toggled = self.analyse().await?;
+        for row in &toggled {
+            info!(
+                target: "component_activity",
+                chain = %self.chain,
+                component_id = %row.external_id,
+                active = row.inactive_since.is_none(),
+                inactive_since = ?row.inactive_since,
+                "Component activity changed"
+            );
+        }
+        debug!(n_toggled = toggled.len(), "Updated component activity");
+        Ok(())
+    }
+}
@@ -1,28 +1,33 @@
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet},
     slice,
+    sync::atomic::{AtomicBool, Ordering as AtomicOrdering},
 };
 
 use chrono::{NaiveDateTime, Utc};
 use diesel::{
+    pg::Pg,
     prelude::*,
     upsert::{excluded, on_constraint},
 };
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use itertools::Itertools;
+use metrics::counter;
 use tracing::{debug, error, instrument, Level};
 use tycho_common::{
     keccak256,
     models::{
         contract::{Account, AccountBalance, AccountDelta},
-        AccountToContractStoreDeltas, Address, Balance, Chain, ChangeType, Code, ContractId,
-        ContractStoreDeltas, PaginationParams, StoreKey, StoreVal, TxHash,
+        AccountToContractStoreDeltas, Address, Balance, Chain, ChangeType, Code, CodeHash,
+        ContractId, ContractStoreDeltas, PaginationParams, StoreKey, StoreVal, TxHash,
     },
-    storage::{BlockOrTimestamp, StorageError, Version, WithTotal},
+    storage::{BlockOrTimestamp, ContractFilter, SortOrder, StorageError, Version, WithTotal},
     Bytes,
 };
 
 use super::{
+    audit::{record_operation, AuditRecord},
+    batching::batches,
     maybe_lookup_block_ts, maybe_lookup_version_ts, orm, schema, storage_error_from_diesel,
     versioning::{apply_partitioned_versioning, apply_versioning, VersioningEntry},
     PostgresError, PostgresGateway, WithOrdinal, WithTxHash, MAX_TS, MAX_VERSION_TS,
@@ -35,6 +40,177 @@ struct CreatedOrDeleted<T> {
     restored: HashMap<Address, T>,
 }
 
+/// Builds the query for accounts of a chain alive at `version_ts` matching the given filters.
+///
+/// Used for both fetching the accounts and counting them, so the two stay consistent.
+fn filtered_accounts_query<'a>(
+    chain_db_id: i64,
+    addresses: Option<&'a [Address]>,
+    version_ts: NaiveDateTime,
+    has_code: Option<bool>,
+    code_hash: Option<&'a CodeHash>,
+    native_balance: Option<&NativeBalanceRange>,
+) -> schema::account::BoxedQuery<'a, Pg> {
+    use schema::account::dsl::*;
+    let mut q = account
+        .filter(chain_id.eq(chain_db_id))
+        .filter(
+            created_at
+                .is_null() // can be null if the account was initially added as a token
+                .or(created_at.le(version_ts)),
+        )
+        .filter(
+            deleted_at
+                .is_null()
+                .or(deleted_at.gt(version_ts)),
+        )
+        .into_boxed();
+
+    if let Some(addresses) = addresses {
+        q = q.filter(address.eq_any(addresses));
+    }
+    match has_code {
+        Some(true) => q = q.filter(id.eq_any(accounts_with_code_query(version_ts, code_hash))),
+        Some(false) => {
+            q = q.filter(id.ne_all(accounts_with_code_query(version_ts, None)));
+            // accounts without code can never match a code hash
+            if let Some(hash) = code_hash {
+                q = q.filter(id.eq_any(accounts_with_code_query(version_ts, Some(hash))));
+            }
+        }
+        None => {}
+    }
+    if let Some(range) = native_balance {
+        q = q.filter(id.eq_any(accounts_with_native_balance_query(version_ts, range)));
+    }
+    q
+}
+
+/// Inclusive range of native balances accounts have to hold.
+struct NativeBalanceRange {
+    native_token_id: i64,
+    min: Option<Balance>,
+    max: Option<Balance>,
+}
+
+/// Selects the ids of accounts whose native balance at `version_ts` lies within `range`.
+///
+/// Compares the numeric shadow column of the balance, the bounds are decoded by the same database
+/// function that maintains it.
+fn accounts_with_native_balance_query<'a>(
+    version_ts: NaiveDateTime,
+    range: &NativeBalanceRange,
+) -> schema::account_balance::BoxedQuery<'a, Pg, diesel::sql_types::BigInt> {
+    use diesel::{dsl::sql, sql_types::Bool};
+    use schema::account_balance::dsl::*;
+    let mut q = account_balance
+        .filter(token_id.eq(range.native_token_id))
+        .filter(valid_from.le(version_ts))
+        .filter(
+            valid_to
+                .is_null()
+                .or(valid_to.gt(version_ts)),
+        )
+        .select(account_id)
+        .into_boxed();
+    if let Some(min) = &range.min {
+        q = q.filter(
+            sql::<Bool>("balance_numeric >= bytea_to_numeric(")
+                .bind::<diesel::sql_types::Bytea, _>(min.clone())
+                .sql(")"),
+        );
+    }
+    if let Some(max) = &range.max {
+        q = q.filter(
+            sql::<Bool>("balance_numeric <= bytea_to_numeric(")
+                .bind::<diesel::sql_types::Bytea, _>(max.clone())
+                .sql(")"),
+        );
+    }
+    q
+}
+
+/// Selects the ids of accounts that have code at `version_ts`, optionally with a specific hash.
+fn accounts_with_code_query<'a>(
+    version_ts: NaiveDateTime,
+    code_hash: Option<&'a CodeHash>,
+) -> schema::contract_code::BoxedQuery<'a, Pg, diesel::sql_types::BigInt> {
+    use schema::contract_code::dsl::*;
+    let mut q = contract_code
+        .filter(valid_from.le(version_ts))
+        .filter(
+            valid_to
+                .is_null()
+                .or(valid_to.gt(version_ts)),
+        )
+        .select(account_id)
+        .into_boxed();
+    if let Some(code_hash) = code_hash {
+        q = q.filter(hash.eq(code_hash));
+    }
+    q
+}
+
+/// Drops writes of zero to slots without a current value, since a missing slot reads as zero.
+///
+/// Slots that are also set to a non zero value within the batch keep all their writes, the zero
+/// may clear that value again.
+async fn drop_redundant_zero_slots<O>(
+    entries: Vec<WithOrdinal<VersioningEntry<orm::NewSlot>, O>>,
+    conn: &mut AsyncPgConnection,
+) -> Result<Vec<WithOrdinal<VersioningEntry<orm::NewSlot>, O>>, StorageError> {
+    let mut zero = HashSet::new();
+    let mut non_zero = HashSet::new();
+    for entry in &entries {
+        if let VersioningEntry::Update(slot) = &entry.entity {
+            let is_zero = slot
+                .value
+                .as_ref()
+                .is_none_or(|value| value.iter().all(|byte| *byte == 0));
+            let id = (slot.account_id, slot.slot.clone());
+            if is_zero {
+                zero.insert(id);
+            } else {
+                non_zero.insert(id);
+            }
+        }
+    }
+    zero.retain(|id| !non_zero.contains(id));
+    if zero.is_empty() {
+        return Ok(entries);
+    }
+
+    let account_ids: HashSet<i64> = zero.iter().map(|(id, _)| *id).collect();
+    let slots: HashSet<&Bytes> = zero
+        .iter()
+        .map(|(_, slot)| slot)
+        .collect();
+    let stored: HashSet<(i64, Bytes)> = schema::contract_storage_default::table
+        .filter(schema::contract_storage_default::account_id.eq_any(account_ids))
+        .filter(schema::contract_storage_default::slot.eq_any(slots))
+        .select((
+            schema::contract_storage_default::account_id,
+            schema::contract_storage_default::slot,
+        ))
+        .get_results::<(i64, Bytes)>(conn)
+        .await
+        .map_err(PostgresError::from)?
+        .into_iter()
+        .collect();
+    zero.retain(|id| !stored.contains(id));
+
+    let n_before = entries.len();
+    let entries = entries
+        .into_iter()
+        .filter(|entry| match &entry.entity {
+            VersioningEntry::Update(slot) => !zero.contains(&(slot.account_id, slot.slot.clone())),
+            VersioningEntry::Deletion(_) => true,
+        })
+        .collect::<Vec<_>>();
+    counter!("storage_skipped_zero_slot_writes").increment((n_before - entries.len()) as u64);
+    Ok(entries)
+}
+
 // Private methods
 impl PostgresGateway {
     /// Retrieves the changes in balance for all accounts of a chain.
@@ -173,14 +349,12 @@ impl PostgresGateway {
                         .gt(target_version_ts)
                         .or(valid_to.is_null()),
                 )
-                .select((account_id, code))
+                .select((account_id, code, cold_ref))
                 .order_by((account_id, valid_from.desc(), schema::transaction::index.desc()))
                 .distinct_on(account_id)
-                .get_results::<(i64, Code)>(conn)
+                .get_results::<(i64, Code, Option<String>)>(conn)
                 .await
                 .map_err(PostgresError::from)?
-                .into_iter()
-                .collect::<HashMap<i64, Code>>()
         } else {
             let changed_account_ids = contract_code
                 .inner_join(schema::account::table.inner_join(schema::chain::table))
@@ -200,16 +374,23 @@ impl PostgresGateway {
                         .gt(target_version_ts)
                         .or(valid_to.is_null()),
                 )
-                .select((account_id, code))
+                .select((account_id, code, cold_ref))
                 .order_by((account_id, valid_from.asc(), schema::transaction::index.asc()))
                 .distinct_on(account_id)
-                .get_results::<(i64, Code)>(conn)
+                .get_results::<(i64, Code, Option<String>)>(conn)
                 .await
                 .map_err(PostgresError::from)?
-                .into_iter()
-                .collect::<HashMap<i64, Code>>()
         };
-        Ok(res)
+
+        let mut codes = HashMap::with_capacity(res.len());
+        for (acc_id, acc_code, code_ref) in res {
+            codes.insert(
+                acc_id,
+                self.resolve_code(acc_code, code_ref.as_deref())
+                    .await?,
+            );
+        }
+        Ok(codes)
     }
 
     /// Retrieves the changes in slots for all accounts of a chain.
@@ -425,7 +606,15 @@ impl PostgresGateway {
             // Restore full state delta at from target version for accounts that were deleted
             let version = Some(Version::from_ts(*target_version_ts));
             let restored: HashMap<Address, AccountDelta> = self
-                .get_contracts(chain, Some(&deleted_addresses), version.as_ref(), true, None, conn)
+                .get_contracts(
+                    chain,
+                    Some(&deleted_addresses),
+                    version.as_ref(),
+                    true,
+                    None,
+                    None,
+                    conn,
+                )
                 .await
                 .map_err(PostgresError::from)?
                 .entity
@@ -564,6 +753,11 @@ impl PostgresGateway {
             }
         }
 
+        // `load` alone resolves to diesel's `RunQueryDsl::load`
+        if AtomicBool::load(&self.skip_zero_slot_writes, AtomicOrdering::Relaxed) {
+            new_entries = drop_redundant_zero_slots(new_entries, conn).await?;
+        }
+
         debug!(n = new_entries.len(), "Inserting slots");
         new_entries.sort_by_cached_key(|b| b.ordinal);
         let sorted = new_entries
@@ -577,7 +771,7 @@ impl PostgresGateway {
             .map(orm::NewSlotLatest::from)
             .collect::<Vec<_>>();
 
-        for chunk in latest.chunks(1_000) {
+        for chunk in batches(&latest) {
             diesel::insert_into(schema::contract_storage_default::table)
                 .values(chunk)
                 .on_conflict(on_constraint("contract_storage_default_unique_pk"))
@@ -603,7 +797,7 @@ impl PostgresGateway {
                 .map_err(PostgresError::from)?;
         }
 
-        for chunk in to_archive.chunks(1_000) {
+        for chunk in batches(&to_archive) {
             diesel::insert_into(schema::contract_storage::table)
                 .values(chunk)
                 .execute(conn)
@@ -640,10 +834,10 @@ impl PostgresGateway {
             None => Utc::now().naive_utc(),
         };
 
-        let slots = {
+        let chain_id = self.get_chain_id(chain)?;
+        let mut slots = {
             use schema::{account, contract_storage::dsl::*};
 
-            let chain_id = self.get_chain_id(chain)?;
             let mut q = contract_storage
                 .inner_join(account::table)
                 .filter(account::chain_id.eq(chain_id))
@@ -665,6 +859,10 @@ impl PostgresGateway {
                 .await
                 .map_err(PostgresError::from)?
         };
+        slots.extend(
+            self.get_cold_contract_slots(chain_id, contracts, version_ts, conn)
+                .await?,
+        );
         let accounts = orm::Account::get_addresses_by_id(slots.iter().map(|(cid, _, _)| cid), conn)
             .await
             .map_err(PostgresError::from)?
@@ -719,6 +917,16 @@ impl PostgresGateway {
             Some(version) => maybe_lookup_version_ts(version, conn).await?,
             None => Utc::now().naive_utc(),
         };
+        if let Some(deleted_at) = account_orm
+            .deleted_at
+            .filter(|deleted_at| *deleted_at <= version_ts)
+        {
+            return Err(StorageError::Deleted(
+                "Account".to_string(),
+                hex::encode(&id.address),
+                deleted_at,
+            ));
+        }
         let chain = id.chain;
 
         let mut all_balances = self
@@ -768,7 +976,8 @@ impl PostgresGateway {
             HashMap::new(),
             native_balance.balance,
             account_balances.clone(),
-            code_orm.code,
+            self.resolve_code(code_orm.code, code_orm.cold_ref.as_deref())
+                .await?,
             code_orm.hash,
             // TODO: remove balance_modify_tx from Account
             Bytes::zero(32),
@@ -790,6 +999,30 @@ impl PostgresGateway {
         Ok(account)
     }
 
+    /// Returns the code with `code_hash`, from any account on `chain` and any of its versions.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn get_code_by_hash(
+        &self,
+        chain: &Chain,
+        code_hash: &CodeHash,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Code, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let code_orm = schema::contract_code::table
+            .inner_join(schema::account::table)
+            .filter(schema::account::chain_id.eq(chain_id))
+            .filter(schema::contract_code::hash.eq(code_hash))
+            .select(orm::ContractCode::as_select())
+            .first::<orm::ContractCode>(conn)
+            .await
+            .map_err(|err| {
+                storage_error_from_diesel(err, "ContractCode", &hex::encode(code_hash), None)
+            })?;
+        self.resolve_code(code_orm.code, code_orm.cold_ref.as_deref())
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
     #[instrument(level = Level::DEBUG, skip(self, ids, conn))]
     pub async fn get_contracts(
         &self,
@@ -797,6 +1030,7 @@ impl PostgresGateway {
         ids: Option<&[Address]>,
         version: Option<&Version>,
         include_slots: bool,
+        filter: Option<&ContractFilter>,
         pagination_params: Option<&PaginationParams>,
         conn: &mut AsyncPgConnection,
     ) -> Result<WithTotal<Vec<Account>>, StorageError> {
@@ -805,45 +1039,64 @@ impl PostgresGateway {
             Some(version) => maybe_lookup_version_ts(version, conn).await?,
             None => Utc::now().naive_utc(),
         };
+        let filter = filter.cloned().unwrap_or_default();
+        // If no specific IDs are requested, only accounts that have code are returned unless the
+        // caller asks otherwise.
+        let has_code = filter
+            .has_code
+            .or_else(|| (ids.is_none() || filter.code_hash.is_some()).then_some(true));
+        let native_token_id = if filter.min_balance.is_some() ||
+            filter.max_balance.is_some() ||
+            filter.balance_order.is_some()
+        {
+            Some(self.get_native_token_id(chain)?)
+        } else {
+            None
+        };
+        let native_balance = native_token_id
+            .filter(|_| filter.min_balance.is_some() || filter.max_balance.is_some())
+            .map(|native_token_id| NativeBalanceRange {
+                native_token_id,
+                min: filter.min_balance.clone(),
+                max: filter.max_balance.clone(),
+            });
 
         let accounts = {
-            use schema::account::dsl::*;
-            let mut q = account
-                .filter(chain_id.eq(chain_db_id))
-                .filter(
-                    created_at
-                        .is_null() // can be null if the account was initially added as a token
-                        .or(created_at.le(version_ts)),
-                )
-                .filter(
-                    deleted_at
-                        .is_null()
-                        .or(deleted_at.gt(version_ts)),
-                )
-                .order_by(id)
-                .select(orm::Account::as_select())
-                .into_boxed();
-
-            // if user passed any contract ids filter by those
-            // else get all contracts
-            if let Some(contract_ids) = ids {
-                q = q.filter(address.eq_any(contract_ids));
-            } else {
-                // If no specific IDs requested, only get accounts that have code
-                // This ensures pagination works correctly with the filtered results
-                q = q.filter(
-                    id.eq_any(
-                        schema::contract_code::table
-                            .filter(schema::contract_code::valid_from.le(version_ts))
-                            .filter(
-                                schema::contract_code::valid_to
-                                    .is_null()
-                                    .or(schema::contract_code::valid_to.gt(version_ts)),
-                            )
-                            .select(schema::contract_code::account_id),
-                    ),
-                );
-            }
+            let mut q = filtered_accounts_query(
+                chain_db_id,
+                ids,
+                version_ts,
+                has_code,
+                filter.code_hash.as_ref(),
+                native_balance.as_ref(),
+            )
+            .select(orm::Account::as_select());
+            q = match (filter.balance_order, native_token_id) {
+                (Some(order), Some(native_token_id)) => {
+                    use diesel::{
+                        dsl::sql,
+                        sql_types::{Nullable, Numeric, Timestamptz},
+                    };
+                    let direction = match order {
+                        SortOrder::Ascending => "ASC",
+                        SortOrder::Descending => "DESC",
+                    };
+                    // Correlated on the outer account, accounts without a native balance at
+                    // `version_ts` sort as NULL.
+                    let native_balance = sql::<Nullable<Numeric>>(&format!(
+                        "(SELECT ab.balance_numeric FROM account_balance ab \
+                         WHERE ab.account_id = account.id AND ab.token_id = {native_token_id} \
+                         AND ab.valid_from <= "
+                    ))
+                    .bind::<Timestamptz, _>(version_ts)
+                    .sql(" AND (ab.valid_to IS NULL OR ab.valid_to > ")
+                    .bind::<Timestamptz, _>(version_ts)
+                    .sql(&format!(")) {direction} NULLS LAST"));
+                    q.order_by(native_balance)
+                        .then_order_by(schema::account::id)
+                }
+                _ => q.order_by(schema::account::id),
+            };
 
             // Apply pagination if provided
             if let Some(pagination) = pagination_params {
@@ -852,11 +1105,13 @@ impl PostgresGateway {
                     .offset(pagination.offset());
             }
 
-            q.get_results::<orm::Account>(conn)
+            let timer = self.time_query("get_contracts", &q);
+            let accounts = q
+                .get_results::<orm::Account>(conn)
                 .await
-                .map_err(PostgresError::from)?
-                .into_iter()
-                .collect::<Vec<_>>()
+                .map_err(PostgresError::from)?;
+            timer.finish(accounts.len());
+            accounts
         };
 
         let mut all_balances = self
@@ -869,9 +1124,11 @@ impl PostgresGateway {
             .map(|a| a.id)
             .collect::<HashSet<_>>();
 
-        let codes = {
+        // Maps account ids to their code, code hash and code transaction. If the code was not
+        // requested, only its hash is loaded and offloaded code is not fetched.
+        let mut code_map: HashMap<i64, (Code, CodeHash, Bytes)> = {
             use schema::contract_code::dsl::*;
-            contract_code
+            let query = contract_code
                 .inner_join(schema::transaction::table)
                 .filter(account_id.eq_any(&account_ids))
                 .filter(valid_from.le(version_ts))
@@ -881,40 +1138,45 @@ impl PostgresGateway {
                         .or(valid_to.gt(version_ts)),
                 )
                 .order_by((account_id, schema::transaction::index.desc()))
-                .select((orm::ContractCode::as_select(), schema::transaction::hash))
-                .distinct_on(account_id)
-                .get_results::<(orm::ContractCode, Bytes)>(conn)
-                .await
-                .map_err(PostgresError::from)?
-                .into_iter()
-                .map(|(entity, tx)| WithTxHash { entity, tx: Some(tx) })
-                .collect::<Vec<_>>()
-        };
-
-        // Create a map of account_id to code for efficient lookup
-        let code_map: HashMap<i64, WithTxHash<orm::ContractCode>> = codes
-            .into_iter()
-            .map(|code| (code.entity.account_id, code))
-            .collect();
-
-        // Since we already filtered accounts to only include those with code in the initial query,
-        // we can use accounts directly. For specific IDs, we still need to verify all accounts have
-        // code.
-        let filtered_accounts = if ids.is_some() {
-            // If specific IDs were requested, all accounts must have code
-            if accounts.len() != code_map.len() {
-                return Err(StorageError::Unexpected(format!(
-                    "Some accounts were missing code. Got {} accounts and {} code entries.",
-                    accounts.len(),
-                    code_map.len(),
-                )));
+                .distinct_on(account_id);
+            if filter.skip_code {
+                query
+                    .select((account_id, hash, schema::transaction::hash))
+                    .get_results::<(i64, CodeHash, Bytes)>(conn)
+                    .await
+                    .map_err(PostgresError::from)?
+                    .into_iter()
+                    .map(|(account, code_hash, tx)| (account, (Code::default(), code_hash, tx)))
+                    .collect()
+            } else {
+                let codes = query
+                    .select((orm::ContractCode::as_select(), schema::transaction::hash))
+                    .get_results::<(orm::ContractCode, Bytes)>(conn)
+                    .await
+                    .map_err(PostgresError::from)?;
+                let mut code_map = HashMap::with_capacity(codes.len());
+                for (mut entity, tx) in codes {
+                    if let Some(key) = entity.cold_ref.take() {
+                        entity.code = self
+                            .resolve_code(Code::default(), Some(&key))
+                            .await?;
+                    }
+                    code_map.insert(entity.account_id, (entity.code, entity.hash, tx));
+                }
+                code_map
             }
-            accounts
-        } else {
-            // If no specific IDs were requested, accounts are already filtered to have code
-            accounts
         };
 
+        // Accounts were already filtered on code in the initial query, except if specific IDs were
+        // requested without a code filter. In that case all accounts must have code.
+        if has_code.is_none() && accounts.len() != code_map.len() {
+            return Err(StorageError::Unexpected(format!(
+                "Some accounts were missing code. Got {} accounts and {} code entries.",
+                accounts.len(),
+                code_map.len(),
+            )));
+        }
+
         let slots = if include_slots {
             Some(
                 self.get_contract_slots(chain, ids, version, conn)
@@ -924,20 +1186,21 @@ impl PostgresGateway {
             None
         };
 
-        let res = filtered_accounts
+        let res = accounts
             .into_iter()
             .map(|account| -> Result<Account, StorageError> {
-                let code = code_map
-                    .get(&account.id)
-                    .ok_or_else(|| {
-                        StorageError::Unexpected(format!(
+                let (code, code_hash, code_tx) = match code_map.remove(&account.id) {
+                    Some(code) => code,
+                    None if has_code == Some(false) => {
+                        (Code::default(), keccak256([]).into(), Bytes::zero(32))
+                    }
+                    None => {
+                        return Err(StorageError::Unexpected(format!(
                             "Code not found for account id: {}",
                             account.id
-                        ))
-                    })?;
-
-                // Note: it is safe to call unwrap here since above we always wrap it into Some
-                let code_tx = code.tx.clone().unwrap();
+                        )))
+                    }
+                };
 
                 let balances = all_balances
                     .get_mut(&account.address)
@@ -963,8 +1226,8 @@ impl PostgresGateway {
                     HashMap::new(),
                     native_balance.balance,
                     balances.clone(),
-                    code.entity.code.clone(),
-                    code.entity.hash.clone(),
+                    code,
+                    code_hash,
                     // TODO: remove balance_modify_tx from Account
                     Bytes::zero(32),
                     code_tx,
@@ -985,50 +1248,21 @@ impl PostgresGateway {
             })
             .collect::<Result<Vec<_>, _>>()?;
 
-        // TODO: Try to reduce the duplication
         // Calculate total count based on whether pagination was used
         let total_count = if pagination_params.is_some() {
             // If pagination was used, we need to query the total count
-            use schema::account::dsl::*;
-            let mut count_q = account
-                .filter(chain_id.eq(chain_db_id))
-                .filter(
-                    created_at
-                        .is_null() // can be null if the account was initially added as a token
-                        .or(created_at.le(version_ts)),
-                )
-                .filter(
-                    deleted_at
-                        .is_null()
-                        .or(deleted_at.gt(version_ts)),
-                )
-                .select(diesel::dsl::count(id))
-                .into_boxed();
-
-            // Apply contract id filters if provided
-            if let Some(contract_ids) = ids {
-                count_q = count_q.filter(address.eq_any(contract_ids));
-            } else {
-                // If no specific IDs requested, only count accounts that have code
-                // This matches the filtering logic applied to the main query
-                count_q = count_q.filter(
-                    id.eq_any(
-                        schema::contract_code::table
-                            .filter(schema::contract_code::valid_from.le(version_ts))
-                            .filter(
-                                schema::contract_code::valid_to
-                                    .is_null()
-                                    .or(schema::contract_code::valid_to.gt(version_ts)),
-                            )
-                            .select(schema::contract_code::account_id),
-                    ),
-                );
-            }
-
-            count_q
-                .get_result::<i64>(conn)
-                .await
-                .map_err(PostgresError::from)?
+            filtered_accounts_query(
+                chain_db_id,
+                ids,
+                version_ts,
+                has_code,
+                filter.code_hash.as_ref(),
+                native_balance.as_ref(),
+            )
+            .select(diesel::dsl::count(schema::account::id))
+            .get_result::<i64>(conn)
+            .await
+            .map_err(PostgresError::from)?
         } else {
             // If no pagination, use the length of the result
             res.len() as i64
@@ -1216,11 +1450,13 @@ impl PostgresGateway {
                 .map(|b| b.entity)
                 .collect::<Vec<_>>();
             apply_versioning::<_, orm::AccountBalance>(&mut sorted, conn).await?;
-            diesel::insert_into(schema::account_balance::table)
-                .values(&sorted)
-                .execute(conn)
-                .await
-                .map_err(PostgresError::from)?;
+            for chunk in batches(&sorted) {
+                diesel::insert_into(schema::account_balance::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .await
+                    .map_err(PostgresError::from)?;
+            }
         }
         if !code_data.is_empty() {
             code_data.sort_by_cached_key(|b| b.ordinal);
@@ -1229,11 +1465,13 @@ impl PostgresGateway {
                 .map(|b| b.entity)
                 .collect::<Vec<_>>();
             apply_versioning::<_, orm::ContractCode>(&mut sorted, conn).await?;
-            diesel::insert_into(schema::contract_code::table)
-                .values(&sorted)
-                .execute(conn)
-                .await
-                .map_err(PostgresError::from)?;
+            for chunk in batches(&sorted) {
+                diesel::insert_into(schema::contract_code::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .await
+                    .map_err(PostgresError::from)?;
+            }
         }
 
         if !slot_data.is_empty() {
@@ -1262,10 +1500,10 @@ impl PostgresGateway {
                     Some("Transaction".to_owned()),
                 )
             })?;
-        let block_ts = schema::block::table
+        let (block_ts, block_number) = schema::block::table
             .filter(schema::block::id.eq(tx.block_id))
-            .select(schema::block::ts)
-            .first::<NaiveDateTime>(conn)
+            .select((schema::block::ts, schema::block::number))
+            .first::<(NaiveDateTime, i64)>(conn)
             .await
             .map_err(PostgresError::from)?;
         if let Some(tx_id) = account.deletion_tx {
@@ -1279,36 +1517,63 @@ impl PostgresGateway {
             // Noop if called twice on deleted contract
             return Ok(());
         };
-        diesel::update(schema::account::table.filter(schema::account::id.eq(account.id)))
-            .set((schema::account::deletion_tx.eq(tx.id), schema::account::deleted_at.eq(block_ts)))
-            .execute(conn)
-            .await
-            .map_err(PostgresError::from)?;
-        diesel::update(
+        let mut affected =
+            diesel::update(schema::account::table.filter(schema::account::id.eq(account.id)))
+                .set((
+                    schema::account::deletion_tx.eq(tx.id),
+                    schema::account::deleted_at.eq(block_ts),
+                ))
+                .execute(conn)
+                .await
+                .map_err(PostgresError::from)?;
+        // Only versions still valid at deletion end there, earlier versions keep their validity
+        // so historical queries return the state prior to deletion.
+        affected += diesel::update(
             schema::contract_storage::table
-                .filter(schema::contract_storage::account_id.eq(account.id)),
+                .filter(schema::contract_storage::account_id.eq(account.id))
+                .filter(schema::contract_storage::valid_to.gt(block_ts)),
         )
         .set(schema::contract_storage::valid_to.eq(block_ts))
         .execute(conn)
         .await
         .map_err(PostgresError::from)?;
 
-        diesel::update(
+        affected += diesel::update(
             schema::account_balance::table
-                .filter(schema::account_balance::account_id.eq(account.id)),
+                .filter(schema::account_balance::account_id.eq(account.id))
+                .filter(
+                    schema::account_balance::valid_to
+                        .is_null()
+                        .or(schema::account_balance::valid_to.gt(block_ts)),
+                ),
         )
         .set(schema::account_balance::valid_to.eq(block_ts))
         .execute(conn)
         .await
         .map_err(PostgresError::from)?;
 
-        diesel::update(
-            schema::contract_code::table.filter(schema::contract_code::account_id.eq(account.id)),
+        affected += diesel::update(
+            schema::contract_code::table
+                .filter(schema::contract_code::account_id.eq(account.id))
+                .filter(
+                    schema::contract_code::valid_to
+                        .is_null()
+                        .or(schema::contract_code::valid_to.gt(block_ts)),
+                ),
         )
         .set(schema::contract_code::valid_to.eq(block_ts))
         .execute(conn)
         .await
         .map_err(PostgresError::from)?;
+
+        record_operation(
+            AuditRecord::new("delete_contract", affected as i64)
+                .chain(&id.chain)
+                .target(id.address.to_string())
+                .block_range(block_number, block_number),
+            conn,
+        )
+        .await?;
         Ok(())
     }
 
@@ -1414,29 +1679,19 @@ impl PostgresGateway {
             .iter()
             .map(|b| b.token.clone())
             .collect::<Vec<_>>();
-        let token_ids: HashMap<Address, i64> = schema::token::table
-            .inner_join(schema::account::table)
-            .select((schema::account::address, schema::token::id))
-            .filter(schema::account::address.eq_any(&token_addresses))
-            .load::<(Address, i64)>(conn)
-            .await
-            .map_err(PostgresError::from)?
-            .into_iter()
-            .collect();
+        let token_ids = self
+            .get_token_ids(&token_addresses, conn)
+            .await?;
 
         // fetch linked transactions
-        let modify_txs = account_balances
-            .iter()
-            .map(|b| b.modify_tx.clone())
-            .collect::<Vec<_>>();
-        let modify_txs_refs = modify_txs.iter().collect::<Vec<_>>();
-        let transaction_ids_and_ts =
-            orm::Transaction::ids_and_ts_by_hash(modify_txs_refs.as_ref(), conn)
-                .await
-                .map_err(PostgresError::from)?
-                .into_iter()
-                .map(|(db_id, hash, index, ts)| (hash, (db_id, index, ts)))
-                .collect::<HashMap<TxHash, (i64, i64, NaiveDateTime)>>();
+        let transaction_ids_and_ts = self
+            .get_transaction_ids_and_ts(
+                account_balances
+                    .iter()
+                    .map(|b| &b.modify_tx),
+                conn,
+            )
+            .await?;
 
         // fetch linked accounts
         let account_addresses = account_balances
@@ -1497,16 +1752,46 @@ impl PostgresGateway {
                 .map(|b| b.entity)
                 .collect::<Vec<_>>();
             apply_versioning::<_, orm::AccountBalance>(&mut sorted, conn).await?;
-            diesel::insert_into(schema::account_balance::table)
-                .values(&sorted)
-                .execute(conn)
-                .await
-                .map_err(|err| storage_error_from_diesel(err, "AccountBalance", "batch", None))?;
+            for chunk in batches(&sorted) {
+                diesel::insert_into(schema::account_balance::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .await
+                    .map_err(|err| {
+                        storage_error_from_diesel(err, "AccountBalance", "batch", None)
+                    })?;
+            }
         }
 
         Ok(())
     }
 
+    /// Returns the subset of `addresses` stored as accounts on `chain` and not deleted yet.
+    #[instrument(level = Level::DEBUG, skip(self, addresses, conn))]
+    pub async fn get_tracked_addresses(
+        &self,
+        chain: &Chain,
+        addresses: &[Address],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashSet<Address>, StorageError> {
+        use schema::account::dsl;
+        let chain_db_id = self.get_chain_id(chain)?;
+        let now = Utc::now().naive_utc();
+        let tracked = dsl::account
+            .filter(dsl::chain_id.eq(chain_db_id))
+            .filter(dsl::address.eq_any(addresses))
+            .filter(
+                dsl::deleted_at
+                    .is_null()
+                    .or(dsl::deleted_at.gt(now)),
+            )
+            .select(dsl::address)
+            .load::<Address>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(tracked.into_iter().collect())
+    }
+
     pub async fn get_account_balances(
         &self,
         chain: &Chain,
@@ -1644,7 +1929,7 @@ mod test {
     use diesel_async::AsyncConnection;
     use rstest::rstest;
     use tycho_common::{
-        models::{FinancialType, ImplementationType},
+        models::{contract::AccountBuilder, FinancialType, ImplementationType},
         storage::{BlockIdentifier, VersionKind},
     };
 
@@ -1853,119 +2138,37 @@ mod test {
     }
 
     fn account_c0(version: u64) -> Account {
-        match version {
-            1 => Account::new(
-                Chain::Ethereum,
-                "0x6b175474e89094c44da98b954eedeac495271d0f"
-                    .parse()
-                    .unwrap(),
-                "account0".to_owned(),
-                contract_slots([(1, 5), (2, 1), (0, 1)])
-                    .into_iter()
-                    .map(|(k, v)| (k, v.unwrap_or(Bytes::from("0x00"))))
-                    .collect(),
-                Bytes::from(100u64).lpad(32, 0),
-                vec![
-                    (
-                        "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
-                            .parse()
-                            .unwrap(),
-                        AccountBalance::new(
-                            "0x6b175474e89094c44da98b954eedeac495271d0f"
-                                .parse()
-                                .unwrap(),
-                            "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
-                                .parse()
-                                .unwrap(),
-                            Bytes::from(1000_i32.to_be_bytes()).lpad(32, 0),
-                            Bytes::zero(32),
-                        ),
-                    ),
-                    (
-                        "C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
-                            .parse()
-                            .unwrap(),
-                        AccountBalance::new(
-                            "0x6b175474e89094c44da98b954eedeac495271d0f"
-                                .parse()
-                                .unwrap(),
-                            "C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
-                                .parse()
-                                .unwrap(),
-                            Bytes::from(2000_i32.to_be_bytes()).lpad(32, 0),
-                            Bytes::zero(32),
-                        ),
-                    ),
-                ]
-                .into_iter()
-                .collect(),
-                Bytes::from("C0C0C0"),
-                "0x106781541fd1c596ade97569d584baf47e3347d3ac67ce7757d633202061bdc4"
-                    .parse()
-                    .unwrap(),
+        let (slots, native_balance) = match version {
+            1 => (contract_slots([(1, 5), (2, 1), (0, 1)]), 100u64),
+            2 => (contract_slots([(6, 30), (5, 25), (1, 3), (2, 1), (0, 2)]), 101u64),
+            _ => panic!("No version found"),
+        };
+        let address = Bytes::from("0x6b175474e89094c44da98b954eedeac495271d0f");
+        let token_balance = |token: &str, balance: i32| {
+            AccountBalance::new(
+                address.clone(),
+                token.parse().unwrap(),
+                Bytes::from(balance.to_be_bytes()).lpad(32, 0),
                 Bytes::zero(32),
-                "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945"
-                    .parse()
-                    .unwrap(),
-                None,
-            ),
-            2 => Account::new(
-                Chain::Ethereum,
-                "0x6b175474e89094c44da98b954eedeac495271d0f"
-                    .parse()
-                    .unwrap(),
-                "account0".to_owned(),
-                contract_slots([(6, 30), (5, 25), (1, 3), (2, 1), (0, 2)])
+            )
+        };
+        AccountBuilder::new(Chain::Ethereum, address.clone())
+            .title("account0")
+            .slots(
+                slots
                     .into_iter()
                     .map(|(k, v)| (k, v.unwrap_or(Bytes::from("0x00"))))
                     .collect(),
-                Bytes::from(101u64).lpad(32, 0),
-                vec![
-                    (
-                        "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
-                            .parse()
-                            .unwrap(),
-                        AccountBalance::new(
-                            "0x6b175474e89094c44da98b954eedeac495271d0f"
-                                .parse()
-                                .unwrap(),
-                            "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
-                                .parse()
-                                .unwrap(),
-                            Bytes::from(1000_i32.to_be_bytes()).lpad(32, 0),
-                            Bytes::zero(32),
-                        ),
-                    ),
-                    (
-                        "C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
-                            .parse()
-                            .unwrap(),
-                        AccountBalance::new(
-                            "0x6b175474e89094c44da98b954eedeac495271d0f"
-                                .parse()
-                                .unwrap(),
-                            "C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
-                                .parse()
-                                .unwrap(),
-                            Bytes::from(2000_i32.to_be_bytes()).lpad(32, 0),
-                            Bytes::zero(32),
-                        ),
-                    ),
-                ]
-                .into_iter()
-                .collect(),
-                Bytes::from("C0C0C0"),
-                "0x106781541fd1c596ade97569d584baf47e3347d3ac67ce7757d633202061bdc4"
-                    .parse()
-                    .unwrap(),
-                Bytes::zero(32),
-                "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945"
-                    .parse()
-                    .unwrap(),
-                None,
-            ),
-            _ => panic!("No version found"),
-        }
+            )
+            .native_balance(Bytes::from(native_balance).lpad(32, 0))
+            .token_balance(token_balance("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", 1000))
+            .token_balance(token_balance("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", 2000))
+            .code(Bytes::from("C0C0C0"))
+            .code_modify_tx(Bytes::from(
+                "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945",
+            ))
+            .build()
+            .unwrap()
     }
 
     fn contract_slots(data: impl IntoIterator<Item = (i32, i32)>) -> HashMap<Bytes, Option<Bytes>> {
@@ -1983,56 +2186,48 @@ mod test {
 
     fn account_c1(version: u64) -> Account {
         match version {
-            2 => Account::new(
+            2 => AccountBuilder::new(
                 Chain::Ethereum,
-                "0x73bce791c239c8010cd3c857d96580037ccdd0ee"
-                    .parse()
-                    .unwrap(),
-                "c1".to_owned(),
+                Bytes::from("0x73bce791c239c8010cd3c857d96580037ccdd0ee"),
+            )
+            .title("c1")
+            .slots(
                 contract_slots([(1, 255), (0, 128)])
                     .into_iter()
                     .map(|(k, v)| (k, v.unwrap_or(Bytes::from("0x00"))))
                     .collect(),
-                Bytes::from(50u64).lpad(32, 0),
-                HashMap::new(),
-                Bytes::from("C1C1C1"),
-                "0xa04b84acdf586a694085997f32c4aa11c2726a7f7e0b677a27d44d180c08e07f"
-                    .parse()
-                    .unwrap(),
-                Bytes::zero(32),
-                "0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7"
-                    .parse()
-                    .unwrap(),
-                None,
-            ),
+            )
+            .native_balance(Bytes::from(50u64).lpad(32, 0))
+            .code(Bytes::from("C1C1C1"))
+            .code_modify_tx(Bytes::from(
+                "0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7",
+            ))
+            .build()
+            .unwrap(),
             _ => panic!("No version found"),
         }
     }
 
     fn account_c2(version: u64) -> Account {
         match version {
-            1 => Account::new(
+            1 => AccountBuilder::new(
                 Chain::Ethereum,
-                "0x94a3f312366b8d0a32a00986194053c0ed0cddb1"
-                    .parse()
-                    .unwrap(),
-                "c2".to_owned(),
+                Bytes::from("0x94a3f312366b8d0a32a00986194053c0ed0cddb1"),
+            )
+            .title("c2")
+            .slots(
                 contract_slots([(1, 2), (2, 4)])
                     .into_iter()
                     .map(|(k, v)| (k, v.unwrap_or(Bytes::from("0x00"))))
                     .collect(),
-                Bytes::from(25u64).lpad(32, 0),
-                HashMap::new(),
-                Bytes::from("C2C2C2"),
-                "0x7eb1e0ed9d018991eed6077f5be45b52347f6e5870728809d368ead5b96a1e96"
-                    .parse()
-                    .unwrap(),
-                Bytes::zero(32),
-                "0x794f7df7a3fe973f1583fbb92536f9a8def3a89902439289315326c04068de54"
-                    .parse()
-                    .unwrap(),
-                None,
-            ),
+            )
+            .native_balance(Bytes::from(25u64).lpad(32, 0))
+            .code(Bytes::from("C2C2C2"))
+            .code_modify_tx(Bytes::from(
+                "0x794f7df7a3fe973f1583fbb92536f9a8def3a89902439289315326c04068de54",
+            ))
+            .build()
+            .unwrap(),
             _ => panic!("No version found"),
         }
     }
@@ -2060,6 +2255,86 @@ mod test {
         assert_eq!(result, expected);
     }
 
+    #[tokio::test]
+    async fn test_drop_redundant_zero_slots() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[(blk[0], 1i64, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945")],
+        )
+        .await;
+        let account = db_fixtures::insert_account(
+            &mut conn,
+            "6B175474E89094C44Da98b954EedeAC495271d0F",
+            "account0",
+            chain_id,
+            Some(txn[0]),
+        )
+        .await;
+        let ts = db_fixtures::yesterday_midnight();
+        db_fixtures::insert_slots(&mut conn, account, txn[0], &ts, None, &[(0, 1, None)]).await;
+        let write = |key: u64, value: Option<u64>| {
+            let encode = |v: u64| Bytes::from(format!("{v:064x}").as_str());
+            WithOrdinal::new(
+                VersioningEntry::Update(orm::NewSlot {
+                    slot: encode(key),
+                    value: value.map(encode),
+                    previous_value: None,
+                    account_id: account,
+                    modify_tx: txn[0],
+                    ordinal: 0,
+                    valid_from: ts,
+                    valid_to: MAX_TS,
+                }),
+                key,
+            )
+        };
+
+        let kept = drop_redundant_zero_slots(
+            vec![
+                // clears a stored slot
+                write(0, Some(0)),
+                // zero for a slot without a value
+                write(1, None),
+                write(2, Some(0)),
+                // set and cleared again within the batch
+                write(3, Some(5)),
+                write(3, None),
+            ],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            kept.iter()
+                .map(|entry| entry.ordinal)
+                .collect::<Vec<_>>(),
+            vec![0, 3, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_code_by_hash() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let code = Bytes::from("C1C1C1");
+        let gateway = EVMGateway::from_connection(&mut conn).await;
+
+        let result = gateway
+            .get_code_by_hash(&Chain::Ethereum, &keccak256(&code).into(), &mut conn)
+            .await
+            .unwrap();
+        let missing = gateway
+            .get_code_by_hash(&Chain::Ethereum, &Bytes::zero(32), &mut conn)
+            .await;
+
+        assert_eq!(result, code);
+        assert!(matches!(missing, Err(StorageError::NotFound(_, _))));
+    }
+
     #[rstest]
     #[case::empty(
     None,
@@ -2108,7 +2383,15 @@ mod test {
         let addresses = ids.as_deref();
 
         let results = gw
-            .get_contracts(&Chain::Ethereum, addresses, version.as_ref(), true, None, &mut conn)
+            .get_contracts(
+                &Chain::Ethereum,
+                addresses,
+                version.as_ref(),
+                true,
+                None,
+                None,
+                &mut conn,
+            )
             .await
             .unwrap()
             .entity;
@@ -2116,6 +2399,77 @@ mod test {
         assert_eq!(results, exp);
     }
 
+    #[rstest]
+    #[case::code_hash(
+        ContractFilter::default().with_code_hash(
+            "0xa04b84acdf586a694085997f32c4aa11c2726a7f7e0b677a27d44d180c08e07f".parse().unwrap()
+        ),
+        vec![account_c1(2)],
+    )]
+    #[case::min_balance(
+        ContractFilter::default().with_min_balance(Bytes::from(100u8)),
+        vec![account_c0(2)],
+    )]
+    #[case::min_balance_inclusive(
+        ContractFilter::default().with_min_balance(Bytes::from(50u64).lpad(32, 0)),
+        vec![account_c0(2), account_c1(2)],
+    )]
+    #[case::max_balance(
+        ContractFilter::default().with_max_balance(Bytes::from(99u8)),
+        vec![account_c1(2)],
+    )]
+    #[case::balance_range(
+        ContractFilter::default()
+            .with_min_balance(Bytes::from(50u8))
+            .with_max_balance(Bytes::from(101u64).lpad(32, 0)),
+        vec![account_c0(2), account_c1(2)],
+    )]
+    #[case::balance_order(
+        ContractFilter::default().with_balance_order(SortOrder::Ascending),
+        vec![account_c1(2), account_c0(2)],
+    )]
+    #[case::code_hash_without_code(
+        ContractFilter::default()
+            .with_code_hash(
+                "0xa04b84acdf586a694085997f32c4aa11c2726a7f7e0b677a27d44d180c08e07f".parse().unwrap()
+            )
+            .with_has_code(false),
+        vec![],
+    )]
+    #[case::skip_code(
+        ContractFilter::default()
+            .with_code_hash(
+                "0xa04b84acdf586a694085997f32c4aa11c2726a7f7e0b677a27d44d180c08e07f".parse().unwrap()
+            )
+            .with_skip_code(),
+        vec![Account { code: Bytes::default(), ..account_c0(2) }],
+    )]
+    #[tokio::test]
+    async fn test_get_contracts_filtered(
+        #[case] filter: ContractFilter,
+        #[case] exp: Vec<Account>,
+    ) {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let results = gw
+            .get_contracts(
+                &Chain::Ethereum,
+                None,
+                None,
+                true,
+                Some(&filter),
+                Some(&PaginationParams { page: 0, page_size: 10 }),
+                &mut conn,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(results.total, Some(exp.len() as i64));
+        assert_eq!(results.entity, exp);
+    }
+
     #[rstest]
     #[case::empty(
     None,
@@ -2173,6 +2527,7 @@ mod test {
                 addresses,
                 version.as_ref(),
                 true,
+                None,
                 Some(&PaginationParams { page: 0, page_size: 1 }),
                 &mut conn,
             )
@@ -2376,6 +2731,75 @@ mod test {
         assert_eq!(res, (Some(block_ts), Some(block_ts), Some(block_ts)));
     }
 
+    #[tokio::test]
+    async fn test_get_contract_deletion_boundary() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let address = Bytes::from("6B175474E89094C44Da98b954EedeAC495271d0F");
+        let id = ContractId::new(Chain::Ethereum, address.clone());
+        let deletion_tx = "36984d97c02a98614086c0f9e9c4e97f7e0911f6f136b3c8a76d37d6d524d1e5";
+        let (block_id, deleted_at) = schema::block::table
+            .filter(schema::block::number.eq(3))
+            .select((schema::block::id, schema::block::ts))
+            .first::<(i64, NaiveDateTime)>(&mut conn)
+            .await
+            .expect("blockquery succeeded");
+        db_fixtures::insert_txns(&mut conn, &[(block_id, 1, deletion_tx)]).await;
+        let before_deletion = gw
+            .get_contract(&id, None, true, &mut conn)
+            .await
+            .expect("contract exists");
+
+        gw.delete_contract(&id, &Bytes::from(deletion_tx), &mut conn)
+            .await
+            .expect("deletion succeeded");
+
+        // Versions that ended before the deletion keep their validity.
+        let balance_validity = schema::account_balance::table
+            .inner_join(schema::account::table)
+            .inner_join(
+                schema::token::table.on(schema::token::id.eq(schema::account_balance::token_id)),
+            )
+            .filter(schema::account::address.eq(&address))
+            .filter(schema::token::symbol.eq("ETH"))
+            .order_by((schema::account_balance::valid_from, schema::account_balance::valid_to))
+            .select(schema::account_balance::valid_to)
+            .get_results::<MaybeTS>(&mut conn)
+            .await
+            .expect("balance query succeeded");
+        assert_eq!(
+            balance_validity,
+            vec![
+                Some(db_fixtures::yesterday_midnight()),
+                Some(db_fixtures::yesterday_half_past_midnight()),
+                Some(deleted_at)
+            ]
+        );
+
+        let last_block = Version(
+            BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 2))),
+            VersionKind::Last,
+        );
+        let res = gw
+            .get_contract(&id, Some(&last_block), true, &mut conn)
+            .await
+            .expect("contract exists before deletion");
+        assert_eq!(res, before_deletion);
+
+        let at_deletion = Version(BlockOrTimestamp::Timestamp(deleted_at), VersionKind::Last);
+        for version in [Some(&at_deletion), None] {
+            let err = gw
+                .get_contract(&id, version, true, &mut conn)
+                .await
+                .expect_err("contract is deleted");
+            assert_eq!(
+                err,
+                StorageError::Deleted("Account".to_string(), hex::encode(&address), deleted_at)
+            );
+        }
+    }
+
     fn bytes32(v: u8) -> Bytes {
         let mut arr = [0; 32];
         arr[31] = v;
@@ -2565,6 +2989,56 @@ mod test {
         assert_eq!(fetched_slot_data, slot_data_tx_1);
     }
 
+    #[tokio::test]
+    async fn test_upsert_slots_exceeding_bind_param_limit() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[(blk[0], 1i64, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945")],
+        )
+        .await;
+        db_fixtures::insert_account(
+            &mut conn,
+            "6B175474E89094C44Da98b954EedeAC495271d0F",
+            "Account1",
+            chain_id,
+            Some(txn[0]),
+        )
+        .await;
+        // 100k slots bind 800k parameters, far above what a single statement accepts
+        let slot_data = (0..100_000u64)
+            .map(|i| {
+                let key = Bytes::from(i.to_be_bytes().to_vec()).lpad(32, 0);
+                (key.clone(), Some(key))
+            })
+            .collect::<ContractStoreDeltas>();
+        let input_slots = [(
+            txn[0],
+            [(Bytes::from("6B175474E89094C44Da98b954EedeAC495271d0F"), slot_data.clone())]
+                .into_iter()
+                .collect(),
+        )]
+        .into_iter()
+        .collect();
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        gw.upsert_slots(input_slots, &mut conn)
+            .await
+            .unwrap();
+
+        let fetched_slot_data = schema::contract_storage::table
+            .select((schema::contract_storage::slot, schema::contract_storage::value))
+            .filter(schema::contract_storage::valid_to.eq(MAX_TS))
+            .get_results(&mut conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect::<ContractStoreDeltas>();
+        assert_eq!(fetched_slot_data, slot_data);
+    }
+
     #[tokio::test]
     async fn test_upsert_slots_invalidate_db_side_records() {
         let mut conn = setup_db().await;
@@ -2958,6 +3432,43 @@ mod test {
         assert_eq!(inserted_data.balance, Bytes::from(2000_i32.to_be_bytes()));
     }
 
+    #[tokio::test]
+    async fn test_get_tracked_addresses() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let c0 = Bytes::from_str("6B175474E89094C44Da98b954EedeAC495271d0F").unwrap();
+        let deleted_c2 = Bytes::from_str("94a3F312366b8D0a32A00986194053C0ed0CdDb1").unwrap();
+        let unknown = Bytes::from_str("0000000000000000000000000000000000000001").unwrap();
+
+        let tracked = gw
+            .get_tracked_addresses(&Chain::Ethereum, &[c0.clone(), deleted_c2, unknown], &mut conn)
+            .await
+            .expect("retrieving tracked addresses failed");
+
+        assert_eq!(tracked, HashSet::from([c0]));
+    }
+
+    #[tokio::test]
+    async fn test_get_tracked_addresses_deleted_in_future() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let c0 = Bytes::from_str("6B175474E89094C44Da98b954EedeAC495271d0F").unwrap();
+        diesel::update(schema::account::table.filter(schema::account::address.eq(&c0)))
+            .set(schema::account::deleted_at.eq(MAX_TS))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let tracked = gw
+            .get_tracked_addresses(&Chain::Ethereum, &[c0.clone()], &mut conn)
+            .await
+            .expect("retrieving tracked addresses failed");
+
+        assert_eq!(tracked, HashSet::from([c0]));
+    }
+
     #[tokio::test]
     async fn test_get_account_balances() {
         let mut conn = setup_db().await;
@@ -0,0 +1,254 @@
+//! Soft quotas on the size of the largest tables.
+//!
+//! A monitor periodically samples the on-disk size of every table with a budget, including its
+//! partitions, indices and toast data, and exports size and growth rate as metrics. A table above
+//! [`WARN_RATIO`] of its budget is logged as a warning, a table over budget as an error together
+//! with the `db_table_budget_exceeded` gauge, which is what alerts should fire on.
+//!
+//! Budgets are soft: writes are never rejected. If enforcement is enabled, the gateway skips
+//! writes of zero to storage slots that hold no value yet while any budget is exceeded. Such
+//! writes don't change what is read, since a missing slot reads as zero, but they still take a
+//! row each.
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use diesel::{
+    sql_types::{BigInt, Text},
+    QueryableByName,
+};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use metrics::{counter, gauge};
+use tracing::{debug, error, warn};
+use tycho_common::storage::StorageError;
+
+use super::{scheduler::PeriodicTask, PostgresError};
+
+/// Share of a budget above which a table is reported as close to its budget.
+pub const WARN_RATIO: f64 = 0.9;
+
+/// Maximum size of a table, parsed from `<table>=<size>` with an optional `KB`, `MB`, `GB` or
+/// `TB` suffix, e.g. `contract_storage=2TB`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableBudget {
+    pub table: String,
+    pub max_bytes: u64,
+}
+
+impl FromStr for TableBudget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (table, size) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <table>=<size>, got {s}"))?;
+        let size = size.trim().to_ascii_uppercase();
+        let (digits, unit) = size.split_at(
+            size.find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(size.len()),
+        );
+        let factor: u64 = match unit.trim() {
+            "" | "B" => 1,
+            "KB" => 1 << 10,
+            "MB" => 1 << 20,
+            "GB" => 1 << 30,
+            "TB" => 1 << 40,
+            other => return Err(format!("Unknown size unit {other} in {s}")),
+        };
+        let value: u64 = digits
+            .parse()
+            .map_err(|_| format!("Invalid size in {s}"))?;
+        Ok(Self { table: table.trim().to_string(), max_bytes: value * factor })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetStatus {
+    Ok,
+    NearLimit,
+    Exceeded,
+}
+
+impl TableBudget {
+    pub fn status(&self, size_bytes: u64) -> BudgetStatus {
+        if size_bytes > self.max_bytes {
+            BudgetStatus::Exceeded
+        } else if size_bytes as f64 > self.max_bytes as f64 * WARN_RATIO {
+            BudgetStatus::NearLimit
+        } else {
+            BudgetStatus::Ok
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DbGrowthConfig {
+    pub budgets: Vec<TableBudget>,
+    /// Skip redundant zero slot writes while any budget is exceeded.
+    pub enforce: bool,
+    /// Pause between size samples.
+    pub interval: Duration,
+}
+
+impl Default for DbGrowthConfig {
+    fn default() -> Self {
+        Self { budgets: Vec::new(), enforce: false, interval: Duration::from_secs(600) }
+    }
+}
+
+#[derive(QueryableByName, Debug)]
+struct TableSize {
+    #[diesel(sql_type = BigInt)]
+    size: i64,
+}
+
+/// Total size of `table` in bytes, summed over all its partitions.
+async fn table_size(table: &str, conn: &mut AsyncPgConnection) -> Result<u64, StorageError> {
+    let row = diesel::sql_query(
+        "SELECT coalesce(sum(pg_total_relation_size(relid)), 0)::bigint AS size
+        FROM pg_partition_tree($1::regclass)",
+    )
+    .bind::<Text, _>(table)
+    .get_result::<TableSize>(conn)
+    .await
+    .map_err(PostgresError::from)?;
+    Ok(row.size as u64)
+}
+
+pub(crate) struct DbGrowthMonitor {
+    pool: Pool<AsyncPgConnection>,
+    config: DbGrowthConfig,
+    /// Shared with the gateway, see [`super::PostgresGateway::skip_zero_slot_writes`].
+    skip_zero_slot_writes: Arc<AtomicBool>,
+    last_samples: HashMap<String, (Instant, u64)>,
+}
+
+impl DbGrowthMonitor {
+    pub(crate) fn new(
+        pool: Pool<AsyncPgConnection>,
+        config: DbGrowthConfig,
+        skip_zero_slot_writes: Arc<AtomicBool>,
+    ) -> Self {
+        Self { pool, config, skip_zero_slot_writes, last_samples: HashMap::new() }
+    }
+
+    /// Samples all budgeted tables, returns whether any of them is over budget.
+    async fn check_budgets(&mut self) -> Result<bool, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let mut exceeded = false;
+        for budget in &self.config.budgets {
+            let size = table_size(&budget.table, &mut conn).await?;
+            let now = Instant::now();
+            let table = budget.table.clone();
+            gauge!("db_table_size_bytes", "table" => table.clone()).set(size as f64);
+            gauge!("db_table_budget_bytes", "table" => table.clone()).set(budget.max_bytes as f64);
+            if let Some((sampled_at, previous)) = self
+                .last_samples
+                .insert(table.clone(), (now, size))
+            {
+                let hours = now
+                    .duration_since(sampled_at)
+                    .as_secs_f64() /
+                    3600.0;
+                let growth = (size as f64 - previous as f64) / hours;
+                gauge!("db_table_growth_bytes_per_hour", "table" => table.clone()).set(growth);
+                debug!(table, size, growth_per_hour = growth, "Sampled table size");
+            }
+
+            let status = budget.status(size);
+            gauge!("db_table_budget_exceeded", "table" => table.clone())
+                .set(if status == BudgetStatus::Exceeded { 1.0 } else { 0.0 });
+            match status {
+                BudgetStatus::Ok => {}
+                BudgetStatus::NearLimit => {
+                    warn!(table, size, max_bytes = budget.max_bytes, "Table close to its budget")
+                }
+                BudgetStatus::Exceeded => {
+                    error!(table, size, max_bytes = budget.max_bytes, "Table exceeds its budget");
+                    counter!("db_table_budget_alerts", "table" => table).increment(1);
+                    exceeded = true;
+                }
+            }
+        }
+        Ok(exceeded)
+    }
+}
+
+#[async_trait]
+impl PeriodicTask for DbGrowthMonitor {
+    async fn run_once(&mut self) -> Result<(), StorageError> {
+        let exceeded = self.check_budgets().await?;
+        let strict = exceeded && self.config.enforce;
+        if self
+            .skip_zero_slot_writes
+            .swap(strict, Ordering::Relaxed) !=
+            strict
+        {
+            warn!(strict, "Changed skipping of zero slot writes");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_table_budget() {
+        assert_eq!(
+            "contract_storage=2TB".parse::<TableBudget>(),
+            Ok(TableBudget { table: "contract_storage".to_string(), max_bytes: 2 << 40 })
+        );
+        assert_eq!(
+            "protocol_state=512".parse::<TableBudget>(),
+            Ok(TableBudget { table: "protocol_state".to_string(), max_bytes: 512 })
+        );
+        assert!("contract_storage"
+            .parse::<TableBudget>()
+            .is_err());
+        assert!("contract_storage=2PB"
+            .parse::<TableBudget>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_budget_status() {
+        let budget = TableBudget { table: "contract_storage".to_string(), max_bytes: 100 };
+
+        assert_eq!(budget.status(90), BudgetStatus::Ok);
+        assert_eq!(budget.status(91), BudgetStatus::NearLimit);
+        assert_eq!(budget.status(101), BudgetStatus::Exceeded);
+    }
+
+    #[tokio::test]
+    async fn test_table_size() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+
+        // Partitioned tables report the size of their partitions
+        let partitioned = table_size("contract_storage", &mut conn)
+            .await
+            .unwrap();
+        let plain = table_size("account", &mut conn)
+            .await
+            .unwrap();
+
+        assert!(partitioned > 0);
+        assert!(plain > 0);
+    }
+}
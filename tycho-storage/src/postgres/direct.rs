@@ -1,4 +1,8 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::RangeInclusive,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
@@ -11,22 +15,26 @@ use tycho_common::{
     models::{
         self,
         blockchain::{
-            Block, EntryPoint, EntryPointWithTracingParams, TracedEntryPoint, TracingParams,
-            TracingResult, Transaction,
+            Block, BlockDigest, EntryPoint, EntryPointWithTracingParams, FinalityStatus,
+            TracedEntryPoint, TracingParams, TracingResult, Transaction,
         },
-        contract::{Account, AccountBalance, AccountDelta},
+        contract::{Account, AccountBalance, AccountDelta, SlotAnnotation},
         protocol::{
-            ComponentBalance, ProtocolComponent, ProtocolComponentState,
-            ProtocolComponentStateDelta, QualityRange,
+            BalanceSample, ComponentBalance, ComponentEvent, ComponentLatestBalance,
+            ComponentLatestState, ProtocolComponent, ProtocolComponentState,
+            ProtocolComponentStateDelta, ProtocolComponentWithContracts, ProtocolDeployment,
+            ProtocolStatesDelta, ProtocolSystemSummary, QualityRange, SampleInterval,
         },
-        token::Token,
-        Address, Chain, ComponentId, ContractId, EntryPointId, ExtractionState, PaginationParams,
-        ProtocolType, TxHash,
+        token::{Token, TokenOverride},
+        Address, AttrStoreKey, Balance, Chain, Code, CodeHash, ComponentCursor, ComponentId,
+        ContractId, EntryPointId, ExtractionState, PaginationParams, ProtocolType, StoreVal,
+        TxHash,
     },
     storage::{
-        BlockIdentifier, BlockOrTimestamp, ChainGateway, ContractStateGateway, EntryPointFilter,
-        EntryPointGateway, ExtractionStateGateway, Gateway, ProtocolGateway, StorageError, Version,
-        WithTotal,
+        AdminAction, AnalyticsRows, ApiKeyUsage, AuditEntry, BlockIdentifier, BlockOrTimestamp,
+        ChainGateway, ContractFilter, ContractStateGateway, EntryPointFilter, EntryPointGateway,
+        ExtractionStateGateway, Gateway, IdempotencyClaim, IdempotentResponse, ProtocolGateway,
+        StorageError, Version, WithTotal,
     },
     Bytes,
 };
@@ -75,8 +83,9 @@ impl DirectGateway {
             .await?;
         let protocol_delta = self
             .state_gateway
-            .get_protocol_states_delta(chain, start_version, end_version, &mut db)
-            .await?;
+            .get_protocol_states_delta(chain, start_version, Some(end_version), &mut db)
+            .await?
+            .deltas;
         let balance_deltas = self
             .state_gateway
             .get_balance_deltas(chain, start_version, end_version, &mut db)
@@ -84,6 +93,41 @@ impl DirectGateway {
 
         Ok((accounts_delta, protocol_delta, balance_deltas))
     }
+
+    /// Atomically writes all changes of a single block.
+    #[instrument(skip_all, fields(block_number = block.number))]
+    pub async fn apply_block_changes(
+        &self,
+        block: &Block,
+        txs: &[Transaction],
+        contract_deltas: &[(TxHash, AccountDelta)],
+        protocol_deltas: &[(TxHash, ProtocolComponentStateDelta)],
+        balances: &[ComponentBalance],
+    ) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async move {
+                self.state_gateway
+                    .apply_block_changes(
+                        &self.chain,
+                        block,
+                        txs,
+                        contract_deltas,
+                        protocol_deltas,
+                        balances,
+                        conn,
+                    )
+                    .await
+                    .map_err(PostgresError::from)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(|PostgresError(err)| err)
+    }
 }
 
 #[async_trait]
@@ -109,6 +153,19 @@ impl ExtractionStateGateway for DirectGateway {
             .await?;
         Ok(())
     }
+    #[instrument(skip_all)]
+    async fn get_extractor_block_numbers(
+        &self,
+        chain: &Chain,
+    ) -> Result<HashMap<String, u64>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_extractor_block_numbers(chain, &mut conn)
+            .await
+    }
 }
 
 #[async_trait]
@@ -136,6 +193,75 @@ impl ChainGateway for DirectGateway {
             .await
     }
 
+    #[instrument(skip_all)]
+    async fn get_finalized_block(&self, chain: &Chain) -> Result<Block, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_finalized_block(chain, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_blocks_at(
+        &self,
+        chains: &[Chain],
+        ts: NaiveDateTime,
+    ) -> Result<HashMap<Chain, Block>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_blocks_at(chains, ts, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_block_finality(
+        &self,
+        chain: &Chain,
+        status: FinalityStatus,
+        up_to: i64,
+    ) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .update_block_finality(chain, status, up_to, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn upsert_block_digests(&self, new: &[BlockDigest]) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .upsert_block_digests(new, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_block_digests(
+        &self,
+        chain: &Chain,
+        start_block: i64,
+        end_block: i64,
+    ) -> Result<Vec<BlockDigest>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_block_digests(chain, start_block, end_block, &mut conn)
+            .await
+    }
+
     async fn upsert_tx(&self, new: &[Transaction]) -> Result<(), StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
@@ -168,6 +294,197 @@ impl ChainGateway for DirectGateway {
             .revert_state(to, &mut conn)
             .await
     }
+
+    #[instrument(skip_all)]
+    async fn quarantine_range(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        start_block: i64,
+        end_block: i64,
+    ) -> Result<i64, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                let repair_id = self
+                    .state_gateway
+                    .quarantine_range(chain, protocol_system, start_block, end_block, conn)
+                    .await?;
+                Result::<i64, PostgresError>::Ok(repair_id)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
+
+    #[instrument(skip_all)]
+    async fn restore_quarantined_range(&self, repair_id: i64) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                self.state_gateway
+                    .restore_quarantined_range(repair_id, conn)
+                    .await?;
+                Result::<(), PostgresError>::Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
+
+    #[instrument(skip_all)]
+    async fn verify_range_continuity(&self, repair_id: i64) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .verify_range_continuity(repair_id, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn run_analytics_query(
+        &self,
+        query: &str,
+        row_limit: usize,
+        timeout: Duration,
+    ) -> Result<AnalyticsRows, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let res = conn
+            .build_transaction()
+            .read_only()
+            .run(|conn| {
+                async move {
+                    self.state_gateway
+                        .run_analytics_query(query, row_limit, timeout, conn)
+                        .await
+                        .map_err(PostgresError::from)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(|PostgresError(err)| err);
+        // Session level locks survive the transaction and would stay with the pooled connection.
+        PostgresGateway::release_advisory_locks(&mut conn).await?;
+        res
+    }
+
+    #[instrument(skip_all)]
+    async fn record_admin_action(&self, action: &AdminAction) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .record_admin_action(action, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_audit_log(
+        &self,
+        operation: Option<&str>,
+        since: Option<NaiveDateTime>,
+        pagination_params: Option<&PaginationParams>,
+    ) -> Result<WithTotal<Vec<AuditEntry>>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_audit_log(operation, since, pagination_params, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn claim_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        action: &str,
+    ) -> Result<IdempotencyClaim, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                let claim = self
+                    .state_gateway
+                    .claim_idempotency_key(api_key, key, action, conn)
+                    .await?;
+                Result::<IdempotencyClaim, PostgresError>::Ok(claim)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
+
+    #[instrument(skip_all)]
+    async fn complete_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .complete_idempotency_key(api_key, key, response, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn release_idempotency_key(&self, api_key: &str, key: &str) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .release_idempotency_key(api_key, key, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn add_api_key_usage(
+        &self,
+        month: &str,
+        usage: &[ApiKeyUsage],
+    ) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .add_api_key_usage(month, usage, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_api_key_usage(&self, month: &str) -> Result<Vec<ApiKeyUsage>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_api_key_usage(month, &mut conn)
+            .await
+    }
 }
 
 #[async_trait]
@@ -195,6 +512,7 @@ impl ContractStateGateway for DirectGateway {
         addresses: Option<&[Address]>,
         version: Option<&Version>,
         include_slots: bool,
+        filter: Option<&ContractFilter>,
         pagination_params: Option<&PaginationParams>,
     ) -> Result<WithTotal<Vec<Account>>, StorageError> {
         let mut conn =
@@ -202,7 +520,30 @@ impl ContractStateGateway for DirectGateway {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_contracts(chain, addresses, version, include_slots, pagination_params, &mut conn)
+            .get_contracts(
+                chain,
+                addresses,
+                version,
+                include_slots,
+                filter,
+                pagination_params,
+                &mut conn,
+            )
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_code_by_hash(
+        &self,
+        chain: &Chain,
+        code_hash: &CodeHash,
+    ) -> Result<Code, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_code_by_hash(chain, code_hash, &mut conn)
             .await
     }
 
@@ -259,7 +600,11 @@ impl ContractStateGateway for DirectGateway {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_accounts_delta(chain, start_version, end_version, &mut conn)
+            .run_with_deadline(&mut conn, |conn| {
+                self.state_gateway
+                    .get_accounts_delta(chain, start_version, end_version, conn)
+                    .scope_boxed()
+            })
             .await
     }
 
@@ -293,6 +638,58 @@ impl ContractStateGateway for DirectGateway {
             .get_account_balances(chain, addresses, version, false, &mut conn)
             .await
     }
+
+    #[instrument(skip_all)]
+    async fn get_tracked_addresses(
+        &self,
+        chain: &Chain,
+        addresses: &[Address],
+    ) -> Result<HashSet<Address>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_tracked_addresses(chain, addresses, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_slot_annotations(
+        &self,
+        chain: &Chain,
+        addresses: &[Address],
+    ) -> Result<Vec<SlotAnnotation>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_slot_annotations(chain, addresses, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn upsert_slot_annotations(
+        &self,
+        annotations: &[SlotAnnotation],
+    ) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                self.state_gateway
+                    .upsert_slot_annotations(annotations, conn)
+                    .await?;
+                Result::<(), PostgresError>::Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
 }
 
 #[async_trait]
@@ -315,6 +712,47 @@ impl ProtocolGateway for DirectGateway {
             .await
     }
 
+    #[instrument(skip_all)]
+    async fn get_protocol_components_after(
+        &self,
+        chain: &Chain,
+        system: Option<String>,
+        ids: Option<&[&str]>,
+        min_tvl: Option<f64>,
+        cursor: &ComponentCursor,
+        page_size: i64,
+    ) -> Result<WithTotal<Vec<ProtocolComponent>>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_protocol_components_after(
+                chain, system, ids, min_tvl, cursor, page_size, &mut conn,
+            )
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_components_with_contracts(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        version: Option<&Version>,
+    ) -> Result<Vec<ProtocolComponentWithContracts>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        // Components and contracts must be read from the same snapshot.
+        PostgresGateway::run_in_snapshot(&mut conn, |conn| {
+            self.state_gateway
+                .get_components_with_contracts(chain, ids, version, conn)
+                .scope_boxed()
+        })
+        .await
+    }
+
     #[instrument(skip_all)]
     async fn get_token_owners(
         &self,
@@ -454,6 +892,33 @@ impl ProtocolGateway for DirectGateway {
         Ok(())
     }
 
+    #[instrument(skip_all)]
+    async fn add_component_events(&self, events: &[ComponentEvent]) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .add_component_events(events, &self.chain, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_component_events(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        blocks: RangeInclusive<i64>,
+    ) -> Result<Vec<ComponentEvent>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_component_events(chain, ids, blocks, &mut conn)
+            .await
+    }
+
     #[instrument(skip_all)]
     async fn add_tokens(&self, tokens: &[Token]) -> Result<(), StorageError> {
         let mut conn =
@@ -500,15 +965,23 @@ impl ProtocolGateway for DirectGateway {
         &self,
         chain: &Chain,
         start_version: Option<&BlockOrTimestamp>,
-        end_version: &BlockOrTimestamp,
-    ) -> Result<Vec<ProtocolComponentStateDelta>, StorageError> {
+        end_version: Option<&BlockOrTimestamp>,
+    ) -> Result<ProtocolStatesDelta, StorageError> {
         let mut conn =
             self.pool.get().await.map_err(|e| {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
-        self.state_gateway
-            .get_protocol_states_delta(chain, start_version, end_version, &mut conn)
-            .await
+        // The end block must be resolved from the same snapshot the changes are read from.
+        PostgresGateway::run_in_snapshot(&mut conn, |conn| {
+            self.state_gateway
+                .run_with_deadline(conn, |conn| {
+                    self.state_gateway
+                        .get_protocol_states_delta(chain, start_version, end_version, conn)
+                        .scope_boxed()
+                })
+                .scope_boxed()
+        })
+        .await
     }
 
     #[instrument(skip_all)]
@@ -523,7 +996,11 @@ impl ProtocolGateway for DirectGateway {
                 StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
             })?;
         self.state_gateway
-            .get_balance_deltas(chain, start_version, target_version, &mut conn)
+            .run_with_deadline(&mut conn, |conn| {
+                self.state_gateway
+                    .get_balance_deltas(chain, start_version, target_version, conn)
+                    .scope_boxed()
+            })
             .await
     }
 
@@ -543,6 +1020,143 @@ impl ProtocolGateway for DirectGateway {
             .await
     }
 
+    #[instrument(skip_all)]
+    async fn get_balance_history(
+        &self,
+        chain: &Chain,
+        component_id: &str,
+        token: &Address,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        interval: SampleInterval,
+    ) -> Result<Vec<BalanceSample>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .run_with_deadline(&mut conn, |conn| {
+                self.state_gateway
+                    .get_balance_history(chain, component_id, token, from, to, interval, conn)
+                    .scope_boxed()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_inactive_components(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+    ) -> Result<HashMap<ComponentId, NaiveDateTime>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_inactive_components(chain, ids, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_component_extractors(
+        &self,
+        chain: &Chain,
+        ids: Option<&[&str]>,
+        extractor: Option<&str>,
+    ) -> Result<HashMap<ComponentId, String>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_component_extractors(chain, ids, extractor, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_component_ids_by_tokens(
+        &self,
+        chain: &Chain,
+        tokens: &[Address],
+        ids: Option<&[&str]>,
+    ) -> Result<Vec<ComponentId>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_component_ids_by_tokens(chain, tokens, ids, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_attribute_range(
+        &self,
+        chain: &Chain,
+        component_id: &str,
+        prefix: &str,
+        range: RangeInclusive<i64>,
+        at: Option<&Version>,
+    ) -> Result<BTreeMap<i64, HashMap<AttrStoreKey, StoreVal>>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_attribute_range(chain, component_id, prefix, range, at, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_protocol_deployment(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+    ) -> Result<ProtocolDeployment, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_protocol_deployment(chain, protocol_system, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn rename_protocol_state_attribute(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        component_ids: Option<&[&str]>,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<i64, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                let renamed = self
+                    .state_gateway
+                    .rename_protocol_state_attribute(
+                        chain,
+                        protocol_system,
+                        component_ids,
+                        old_name,
+                        new_name,
+                        conn,
+                    )
+                    .await?;
+                Result::<i64, PostgresError>::Ok(renamed)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
+
     #[instrument(skip_all)]
     async fn get_token_prices(&self, chain: &Chain) -> Result<HashMap<Bytes, f64>, StorageError> {
         let mut conn =
@@ -554,6 +1168,104 @@ impl ProtocolGateway for DirectGateway {
             .await
     }
 
+    #[instrument(skip_all)]
+    async fn get_protocol_data_modified_at(
+        &self,
+        chain: &Chain,
+    ) -> Result<Option<NaiveDateTime>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_protocol_data_modified_at(chain, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_token_overrides(
+        &self,
+        chain: &Chain,
+        addresses: Option<&[&Address]>,
+    ) -> Result<Vec<TokenOverride>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_token_overrides(chain, addresses, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn upsert_token_overrides(
+        &self,
+        overrides: &[TokenOverride],
+    ) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        conn.transaction(|conn| {
+            async {
+                self.state_gateway
+                    .upsert_token_overrides(overrides, conn)
+                    .await?;
+                Result::<(), PostgresError>::Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(StorageError::from)
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_token_overrides(
+        &self,
+        chain: &Chain,
+        addresses: &[&Address],
+    ) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .delete_token_overrides(chain, addresses, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn add_token_supplies(
+        &self,
+        chain: &Chain,
+        supplies: &HashMap<Address, Balance>,
+        ts: NaiveDateTime,
+    ) -> Result<(), StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .add_token_supplies(chain, supplies, ts, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_token_supplies(
+        &self,
+        chain: &Chain,
+        tokens: &[Address],
+        at: Option<&Version>,
+    ) -> Result<HashMap<Address, Balance>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_token_supplies(chain, tokens, at, &mut conn)
+            .await
+    }
+
     /// TODO: add to transaction instead
     #[instrument(skip_all)]
     async fn upsert_component_tvl(
@@ -581,6 +1293,20 @@ impl ProtocolGateway for DirectGateway {
             .await
     }
 
+    #[instrument(skip_all)]
+    async fn get_protocol_system_summaries(
+        &self,
+        chain: &Chain,
+    ) -> Result<Vec<ProtocolSystemSummary>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_protocol_system_summaries(chain, &mut conn)
+            .await
+    }
+
     #[instrument(skip_all)]
     async fn get_component_tvls(
         &self,
@@ -597,6 +1323,38 @@ impl ProtocolGateway for DirectGateway {
             .get_component_tvls(chain, system, ids, pagination_params, &mut conn)
             .await
     }
+
+    #[instrument(skip_all)]
+    async fn get_component_latest_states(
+        &self,
+        chain: &Chain,
+        system: Option<&str>,
+        ids: Option<&[&str]>,
+    ) -> Result<Vec<ComponentLatestState>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_component_latest_states(chain, system, ids, &mut conn)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_component_latest_balances(
+        &self,
+        chain: &Chain,
+        system: Option<&str>,
+        ids: Option<&[&str]>,
+    ) -> Result<Vec<ComponentLatestBalance>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        self.state_gateway
+            .get_component_latest_balances(chain, system, ids, &mut conn)
+            .await
+    }
 }
 
 #[async_trait]
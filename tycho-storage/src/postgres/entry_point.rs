@@ -7,7 +7,7 @@ use tycho_common::{
         blockchain::{
             EntryPoint, EntryPointWithTracingParams, TracedEntryPoint, TracingParams, TracingResult,
         },
-        Chain, ComponentId, EntryPointId, PaginationParams,
+        component_id_candidates, Chain, ComponentId, EntryPointId, PaginationParams,
     },
     storage::{EntryPointFilter, StorageError, WithTotal},
     Bytes,
@@ -316,6 +316,10 @@ impl PostgresGateway {
             .into_boxed();
 
         if let Some(component_ids) = filter.component_ids {
+            let component_ids: Vec<_> = component_ids
+                .iter()
+                .flat_map(|id| component_id_candidates(id))
+                .collect();
             component_query = component_query.filter(pc::external_id.eq_any(component_ids));
         }
 
@@ -401,6 +405,10 @@ impl PostgresGateway {
             .into_boxed();
 
         if let Some(component_ids) = filter.component_ids {
+            let component_ids: Vec<_> = component_ids
+                .iter()
+                .flat_map(|id| component_id_candidates(id))
+                .collect();
             component_query = component_query.filter(pc::external_id.eq_any(component_ids));
         }
 
@@ -1,8 +1,21 @@
-use diesel::{ExpressionMethods, QueryDsl};
+use std::collections::HashMap;
+
+use chrono::Duration;
+use diesel::{
+    dsl::{now, sql},
+    expression::SqlLiteral,
+    sql_types::Timestamptz,
+    upsert::excluded,
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper,
+};
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
-use tycho_common::models::{Chain, ExtractionState};
+use tracing::{info, warn};
+use tycho_common::{
+    models::{blockchain::Block, Chain, ExtractionState},
+    Bytes,
+};
 
-use super::{orm, schema, storage_error_from_diesel, PostgresGateway, StorageError};
+use super::{orm, schema, storage_error_from_diesel, PostgresError, PostgresGateway, StorageError};
 
 impl PostgresGateway {
     pub async fn get_state(
@@ -15,13 +28,22 @@ impl PostgresGateway {
 
         match orm::ExtractionState::by_name(name, block_chain_id, conn).await {
             Ok(Some((orm_state, block_hash))) => {
+                let packages = match orm_state.substreams_packages {
+                    Some(packages) => serde_json::from_value(packages).map_err(|err| {
+                        StorageError::DecodeError(format!(
+                            "Invalid substreams packages of extractor {name}: {err}"
+                        ))
+                    })?,
+                    None => Vec::new(),
+                };
                 let state = ExtractionState::new(
                     orm_state.name,
                     *chain,
                     orm_state.attributes,
                     &orm_state.cursor.unwrap_or_default(),
                     block_hash,
-                );
+                )
+                .with_substreams_packages(packages);
                 Ok(state)
             }
             Ok(None) => Err(StorageError::NotFound("ExtractionState".to_owned(), name.to_owned())),
@@ -41,6 +63,14 @@ impl PostgresGateway {
             .get_result::<i64>(conn)
             .await
             .map_err(|err| storage_error_from_diesel(err, "ExtractionState", &state.name, None))?;
+        // without packages the stored ones are left untouched
+        let packages = if state.substreams_packages.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_value(&state.substreams_packages).map_err(|err| {
+                StorageError::Unexpected(format!("Failed to encode substreams packages: {err}"))
+            })?)
+        };
         match orm::ExtractionState::by_name(&state.name, block_chain_id, conn).await {
             Ok(Some(_)) => {
                 let update_form = orm::ExtractionStateForm {
@@ -48,6 +78,7 @@ impl PostgresGateway {
                     cursor: Some(&state.cursor),
                     modified_ts: Some(chrono::Utc::now().naive_utc()),
                     block_id: Some(block_id),
+                    substreams_packages: packages,
                 };
                 let update_query = diesel::update(schema::extraction_state::dsl::extraction_state)
                     .filter(schema::extraction_state::name.eq(&state.name))
@@ -70,6 +101,7 @@ impl PostgresGateway {
                     cursor: Some(&state.cursor),
                     modified_ts: chrono::Utc::now().naive_utc(),
                     block_id,
+                    substreams_packages: packages,
                 };
                 let query = diesel::insert_into(schema::extraction_state::dsl::extraction_state)
                     .values(&orm_state);
@@ -88,19 +120,262 @@ impl PostgresGateway {
         }
         Ok(())
     }
+
+    /// Checks that `extractor` may write `block` on top of the block its stored state refers to.
+    ///
+    /// The block must come after the stored one and extend it if it directly follows it, gaps are
+    /// accepted as packages may skip blocks. Fails with `OutOfOrderBlock` otherwise, writing it
+    /// would version rows out of order. Extractors without stored state may write any block.
+    pub(crate) async fn check_block_order(
+        &self,
+        extractor: &str,
+        chain: &Chain,
+        block: &Block,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let stored = schema::extraction_state::table
+            .inner_join(schema::block::table)
+            .filter(schema::extraction_state::name.eq(extractor))
+            .filter(schema::extraction_state::chain_id.eq(chain_id))
+            .select((schema::block::number, schema::block::hash))
+            .first::<(i64, Bytes)>(conn)
+            .await
+            .optional()
+            .map_err(PostgresError::from)?;
+        let Some((number, hash)) = stored else {
+            return Ok(());
+        };
+        let number = number as u64;
+        if block.number <= number || (block.number == number + 1 && block.parent_hash != hash) {
+            return Err(StorageError::OutOfOrderBlock(extractor.to_string(), block.number, number));
+        }
+        Ok(())
+    }
+
+    pub async fn get_extractor_block_numbers(
+        &self,
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<String, u64>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let rows = orm::ExtractionState::block_numbers_by_chain(chain_id, conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(rows
+            .into_iter()
+            .map(|(name, number)| (name, number as u64))
+            .collect())
+    }
+
+    /// Acquires the lease on an extractor identity for `instance_id`.
+    ///
+    /// The lease is granted if the identity is unclaimed, already held by `instance_id` or the
+    /// previous holder's lease expired. Otherwise a `DuplicateEntry` error is returned and the
+    /// caller must not start writing for this extractor.
+    ///
+    /// Lease timestamps are taken from the database clock, so clock skew between the competing
+    /// instances can't make a lease expire early.
+    pub async fn acquire_extractor_lease(
+        &self,
+        name: &str,
+        chain: &Chain,
+        instance_id: &str,
+        ttl: Duration,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::extractor_instance::dsl;
+        let chain_db_id = self.get_chain_id(chain)?;
+
+        let previous = self
+            .get_extractor_instance(chain_db_id, name, conn)
+            .await?;
+
+        // The conflict update only applies if the existing lease may be taken over, so at most one
+        // of multiple racing instances can succeed.
+        let upsert = diesel::insert_into(dsl::extractor_instance)
+            .values((
+                dsl::name.eq(name),
+                dsl::chain_id.eq(chain_db_id),
+                dsl::instance_id.eq(instance_id),
+                dsl::acquired_ts.eq(now),
+                dsl::heartbeat_ts.eq(now),
+                dsl::lease_expires_ts.eq(expires_after(ttl)),
+            ))
+            .on_conflict((dsl::chain_id, dsl::name))
+            .do_update()
+            .set((
+                dsl::instance_id.eq(excluded(dsl::instance_id)),
+                dsl::acquired_ts.eq(excluded(dsl::acquired_ts)),
+                dsl::heartbeat_ts.eq(excluded(dsl::heartbeat_ts)),
+                dsl::lease_expires_ts.eq(excluded(dsl::lease_expires_ts)),
+            ));
+        // `QueryDsl::filter` doesn't apply to upserts, it's a WHERE on the conflict update here.
+        let acquired = diesel::query_dsl::methods::FilterDsl::filter(
+            upsert,
+            dsl::lease_expires_ts
+                .le(now)
+                .or(dsl::instance_id.eq(excluded(dsl::instance_id))),
+        )
+        .execute(conn)
+        .await
+        .map_err(|err| storage_error_from_diesel(err, "ExtractorInstance", name, None))?;
+
+        if acquired == 0 {
+            let holder = self
+                .get_extractor_instance(chain_db_id, name, conn)
+                .await?;
+            let (holder_id, expires) = holder
+                .map(|h| (h.instance_id, h.lease_expires_ts.to_string()))
+                .unwrap_or_default();
+            return Err(StorageError::DuplicateEntry(
+                "ExtractorInstance".to_owned(),
+                format!("{name} on {chain} (held by {holder_id} until {expires})"),
+            ));
+        }
+
+        match previous {
+            Some(prev) if prev.instance_id != instance_id => {
+                warn!(
+                    extractor = name,
+                    %chain,
+                    previous_instance = prev.instance_id,
+                    last_heartbeat = %prev.heartbeat_ts,
+                    "Took over expired extractor lease"
+                );
+            }
+            _ => info!(extractor = name, %chain, instance_id, "Acquired extractor lease"),
+        }
+        Ok(())
+    }
+
+    /// Extends the lease of `instance_id` on an extractor identity.
+    ///
+    /// Fails if the lease is no longer held by `instance_id`, e.g. because it expired and was taken
+    /// over by another instance in the meantime.
+    pub async fn renew_extractor_lease(
+        &self,
+        name: &str,
+        chain: &Chain,
+        instance_id: &str,
+        ttl: Duration,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::extractor_instance::dsl;
+        let chain_db_id = self.get_chain_id(chain)?;
+
+        let renewed = diesel::update(dsl::extractor_instance)
+            .filter(dsl::chain_id.eq(chain_db_id))
+            .filter(dsl::name.eq(name))
+            .filter(dsl::instance_id.eq(instance_id))
+            .set((dsl::heartbeat_ts.eq(now), dsl::lease_expires_ts.eq(expires_after(ttl))))
+            .execute(conn)
+            .await
+            .map_err(|err| storage_error_from_diesel(err, "ExtractorInstance", name, None))?;
+
+        if renewed == 0 {
+            return Err(StorageError::Unexpected(format!(
+                "Lease on extractor {name} on {chain} is no longer held by {instance_id}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Releases the lease of `instance_id` so another instance can take over immediately.
+    pub async fn release_extractor_lease(
+        &self,
+        name: &str,
+        chain: &Chain,
+        instance_id: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::extractor_instance::dsl;
+        let chain_db_id = self.get_chain_id(chain)?;
+
+        diesel::update(dsl::extractor_instance)
+            .filter(dsl::chain_id.eq(chain_db_id))
+            .filter(dsl::name.eq(name))
+            .filter(dsl::instance_id.eq(instance_id))
+            .set(dsl::lease_expires_ts.eq(now))
+            .execute(conn)
+            .await
+            .map_err(|err| storage_error_from_diesel(err, "ExtractorInstance", name, None))?;
+        Ok(())
+    }
+
+    /// Fails unless `instance_id` holds an unexpired lease on an extractor identity.
+    ///
+    /// The lease is locked until the end of the caller's transaction, so it can't be taken over
+    /// while the writes of that transaction commit.
+    pub async fn check_extractor_lease(
+        &self,
+        name: &str,
+        chain: &Chain,
+        instance_id: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::extractor_instance::dsl;
+        let chain_db_id = self.get_chain_id(chain)?;
+
+        let held = dsl::extractor_instance
+            .filter(dsl::chain_id.eq(chain_db_id))
+            .filter(dsl::name.eq(name))
+            .filter(dsl::instance_id.eq(instance_id))
+            .filter(dsl::lease_expires_ts.gt(now))
+            .select(dsl::id)
+            .for_share()
+            .first::<i64>(conn)
+            .await
+            .optional()
+            .map_err(PostgresError::from)?;
+
+        if held.is_none() {
+            return Err(StorageError::Unexpected(format!(
+                "Lease on extractor {name} on {chain} is no longer held by {instance_id}"
+            )));
+        }
+        Ok(())
+    }
+
+    async fn get_extractor_instance(
+        &self,
+        chain_db_id: i64,
+        name: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Option<orm::ExtractorInstance>, StorageError> {
+        use schema::extractor_instance::dsl;
+        Ok(dsl::extractor_instance
+            .filter(dsl::chain_id.eq(chain_db_id))
+            .filter(dsl::name.eq(name))
+            .select(orm::ExtractorInstance::as_select())
+            .first(conn)
+            .await
+            .optional()
+            .map_err(PostgresError::from)?)
+    }
+}
+
+/// Database time `ttl` after the start of the current transaction.
+fn expires_after(ttl: Duration) -> SqlLiteral<Timestamptz> {
+    sql(&format!("now() + interval '{} milliseconds'", ttl.num_milliseconds()))
 }
 
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
 
+    use chrono::NaiveDateTime;
     use diesel::prelude::*;
     use diesel_async::{AsyncConnection, RunQueryDsl};
-    use tycho_common::Bytes;
+    use rstest::rstest;
+    use tycho_common::models::SubstreamsPackage;
 
     use super::*;
     use crate::postgres::db_fixtures;
 
+    const BLOCK_1_HASH: &str = "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6";
+    const BLOCK_2_HASH: &str = "0xb495a1d7e6663152ae92708da4843337b958146015a2802f4193a410044698c9";
+
     async fn setup_db() -> AsyncPgConnection {
         // Creates a DB connecton
         // Creates a chain entry in the DB
@@ -136,6 +411,7 @@ mod test {
             version: "0.1.0",
             modified_ts: chrono::Utc::now().naive_utc(),
             block_id: *block_ids.last().unwrap(),
+            substreams_packages: None,
         };
 
         diesel::insert_into(schema::extraction_state::table)
@@ -203,6 +479,54 @@ mod test {
         assert_eq!(state.chain, Chain::Ethereum);
     }
 
+    #[tokio::test]
+    async fn test_get_extractor_block_numbers() {
+        let mut conn = setup_db().await;
+        let gateway = get_dgw(&mut conn).await;
+
+        let res = gateway
+            .get_extractor_block_numbers(&Chain::Ethereum, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(res, HashMap::from([("setup_extractor".to_string(), 2)]));
+    }
+
+    #[rstest]
+    #[case::unknown_extractor("other_extractor", 1, BLOCK_1_HASH, true)]
+    #[case::next_block("setup_extractor", 3, BLOCK_2_HASH, true)]
+    #[case::after_gap("setup_extractor", 5, "0xff", true)]
+    #[case::stored_block("setup_extractor", 2, BLOCK_1_HASH, false)]
+    #[case::earlier_block("setup_extractor", 1, "0x00", false)]
+    #[case::other_parent("setup_extractor", 3, "0xff", false)]
+    #[tokio::test]
+    async fn test_check_block_order(
+        #[case] extractor: &str,
+        #[case] number: u64,
+        #[case] parent_hash: &str,
+        #[case] accepted: bool,
+    ) {
+        let mut conn = setup_db().await;
+        let gateway = get_dgw(&mut conn).await;
+        let block = Block::new(
+            number,
+            Chain::Ethereum,
+            Bytes::from("0xaa").lpad(32, 0),
+            Bytes::from(parent_hash).lpad(32, 0),
+            NaiveDateTime::default(),
+        );
+
+        let res = gateway
+            .check_block_order(extractor, &Chain::Ethereum, &block, &mut conn)
+            .await;
+
+        if accepted {
+            res.unwrap();
+        } else {
+            assert!(matches!(res, Err(StorageError::OutOfOrderBlock(_, n, 2)) if n == number));
+        }
+    }
+
     #[tokio::test]
 
     async fn test_get_non_existing_state() {
@@ -246,4 +570,101 @@ mod test {
             "20".to_owned().into_bytes()
         );
     }
+
+    #[tokio::test]
+    async fn test_save_state_keeps_substreams_packages() {
+        let mut conn = setup_db().await;
+        let gateway = get_dgw(&mut conn).await;
+        let block_hash =
+            Bytes::from_str("88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6")
+                .unwrap();
+        let packages = vec![SubstreamsPackage {
+            name: "ethereum_uniswap_v2".to_string(),
+            version: "v0.3.0".to_string(),
+            module_name: "map_pool_events".to_string(),
+            module_hash: Bytes::from("0xabcd"),
+            from_block: None,
+        }];
+        let state = ExtractionState::new(
+            "setup_extractor".to_string(),
+            Chain::Ethereum,
+            None,
+            "20".as_bytes(),
+            block_hash.clone(),
+        )
+        .with_substreams_packages(packages.clone());
+        gateway
+            .save_state(&state, &mut conn)
+            .await
+            .unwrap();
+
+        // a cursor update without packages leaves the stored ones untouched
+        let state = ExtractionState::new(
+            "setup_extractor".to_string(),
+            Chain::Ethereum,
+            None,
+            "30".as_bytes(),
+            block_hash,
+        );
+        gateway
+            .save_state(&state, &mut conn)
+            .await
+            .unwrap();
+
+        let res = gateway
+            .get_state("setup_extractor", &Chain::Ethereum, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(res.cursor, "30".as_bytes());
+        assert_eq!(res.substreams_packages, packages);
+    }
+
+    #[tokio::test]
+    async fn test_extractor_lease() {
+        let mut conn = setup_db().await;
+        let gateway = get_dgw(&mut conn).await;
+        let ttl = Duration::seconds(60);
+
+        gateway
+            .acquire_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-a", ttl, &mut conn)
+            .await
+            .expect("Failed to acquire unclaimed lease");
+        // re-acquiring our own lease is fine, e.g. after a restart of the extractor task
+        gateway
+            .acquire_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-a", ttl, &mut conn)
+            .await
+            .expect("Failed to re-acquire own lease");
+
+        let err = gateway
+            .acquire_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-b", ttl, &mut conn)
+            .await
+            .expect_err("Acquired a lease held by another instance");
+        assert!(matches!(err, StorageError::DuplicateEntry(..)));
+
+        gateway
+            .renew_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-a", ttl, &mut conn)
+            .await
+            .expect("Failed to renew lease");
+        gateway
+            .release_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-a", &mut conn)
+            .await
+            .expect("Failed to release lease");
+
+        gateway
+            .acquire_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-b", ttl, &mut conn)
+            .await
+            .expect("Failed to take over released lease");
+        gateway
+            .renew_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-a", ttl, &mut conn)
+            .await
+            .expect_err("Renewed a lease that was taken over");
+        gateway
+            .check_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-b", &mut conn)
+            .await
+            .expect("Lease of the new holder not found");
+        gateway
+            .check_extractor_lease("uniswap_v2", &Chain::Ethereum, "instance-a", &mut conn)
+            .await
+            .expect_err("Lease still held after it was taken over");
+    }
 }
@@ -0,0 +1,161 @@
+//! Fault injection for testing the recovery of storage clients.
+//!
+//! A [`FaultInjector`] decides for every call it sees whether to let it pass, fail it or fail it
+//! with a simulated serialization conflict, optionally delaying it first. Decisions are
+//! deterministic: with a rate of 50% every other call fails, starting with the first one, so
+//! tests can rely on the exact sequence of failures.
+//!
+//! Only available in tests and with the `fault-injection` feature.
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use tracing::debug;
+use tycho_common::storage::StorageError;
+
+/// Message of the error postgres raises if a repeatable read transaction conflicts with a
+/// concurrent one.
+pub const SERIALIZATION_FAILURE: &str = "could not serialize access due to concurrent update";
+
+/// What a [`FaultInjector`] does to the calls it sees.
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    failure_percent: u64,
+    conflict_percent: u64,
+    delay: Option<Duration>,
+    operations: Option<HashSet<String>>,
+}
+
+impl FaultConfig {
+    /// Fails `percent` of the calls with an unexpected error.
+    pub fn fail(mut self, percent: u8) -> Self {
+        self.failure_percent = u64::from(percent.min(100));
+        self
+    }
+
+    /// Fails `percent` of the calls with a serialization conflict, checked before other failures.
+    pub fn conflict(mut self, percent: u8) -> Self {
+        self.conflict_percent = u64::from(percent.min(100));
+        self
+    }
+
+    /// Delays every call by `delay`, including the failed ones.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Restricts faults to the named operations, other calls always pass without delay.
+    pub fn only(mut self, operations: &[&str]) -> Self {
+        self.operations = Some(
+            operations
+                .iter()
+                .map(|op| op.to_string())
+                .collect(),
+        );
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    config: FaultConfig,
+    calls: AtomicU64,
+    faults: AtomicU64,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Self {
+        Self { config, ..Default::default() }
+    }
+
+    /// Applies the configured faults to a call of `operation`.
+    pub async fn inject(&self, operation: &str) -> Result<(), StorageError> {
+        if let Some(operations) = &self.config.operations {
+            if !operations.contains(operation) {
+                return Ok(());
+            }
+        }
+        if let Some(delay) = self.config.delay {
+            tokio::time::sleep(delay).await;
+        }
+        let call = self
+            .calls
+            .fetch_add(1, Ordering::Relaxed) +
+            1;
+        let err = if hits(call, self.config.conflict_percent) {
+            StorageError::Unexpected(format!("DieselError: {SERIALIZATION_FAILURE}"))
+        } else if hits(call, self.config.failure_percent) {
+            StorageError::Unexpected(format!("Injected fault in {operation}"))
+        } else {
+            return Ok(());
+        };
+        debug!(operation, call, %err, "Injecting storage fault");
+        self.faults
+            .fetch_add(1, Ordering::Relaxed);
+        Err(err)
+    }
+
+    /// Calls of the targeted operations seen so far.
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// Calls failed so far.
+    pub fn faults(&self) -> u64 {
+        self.faults.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether the `call`th call is one of the `percent` hit ones, spreading hits evenly over the
+/// calls.
+fn hits(call: u64, percent: u64) -> bool {
+    (call * percent).div_ceil(100) > ((call - 1) * percent).div_ceil(100)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_inject_spreads_faults_over_calls() {
+        let injector = FaultInjector::new(
+            FaultConfig::default()
+                .fail(50)
+                .conflict(25)
+                .only(&["write"]),
+        );
+
+        let mut results = Vec::new();
+        for _ in 0..8 {
+            results.push(
+                injector
+                    .inject("write")
+                    .await
+                    .map_err(|e| e.to_string()),
+            );
+        }
+        let other = injector.inject("read").await;
+
+        let conflict =
+            Err(format!("Unexpected storage error: DieselError: {SERIALIZATION_FAILURE}"));
+        let failure = Err("Unexpected storage error: Injected fault in write".to_string());
+        assert_eq!(
+            results,
+            vec![
+                conflict.clone(),
+                Ok(()),
+                failure.clone(),
+                Ok(()),
+                conflict,
+                Ok(()),
+                failure,
+                Ok(())
+            ]
+        );
+        assert!(other.is_ok());
+        assert_eq!((injector.calls(), injector.faults()), (8, 4));
+    }
+}
@@ -0,0 +1,964 @@
+//! In-memory caches of the latest committed contract and protocol component states.
+//!
+//! Reads of contracts and component states at the latest version are by far the most common
+//! requests served by the RPC. These caches keep the head state of recently read contracts,
+//! respectively components, in memory so these requests don't need to hit the database.
+//!
+//! Entries are only ever derived from committed data: they are filled by reads and kept up to date
+//! by applying contract, state and balance writes once the corresponding database transaction was
+//! committed. Every write bumps a generation counter before it is sent and again once committed,
+//! so fills racing with a commit are discarded instead of overwriting fresher entries with stale
+//! state. Fills that slip in while a write is in flight may still hold the state from before it,
+//! so once committed, the entries of every key the write touched are evicted unless the write was
+//! applied to them in place.
+//!
+//! Each cache is bounded by a [`HeadCacheBudget`] on the estimated size of its entries. Once a
+//! fill or write exceeds it, the least recently used entries are evicted and read from the
+//! database again when requested. A block touching many large contracts therefore only shrinks
+//! the cache instead of growing it without bounds.
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    mem,
+    num::NonZeroUsize,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+use metrics::{counter, gauge};
+use tokio::sync::Mutex;
+use tracing::trace;
+use tycho_common::{
+    models::{
+        contract::{Account, AccountBalance},
+        protocol::{ComponentBalance, ProtocolComponentState, ProtocolComponentStateDelta},
+        Address, Chain, ChangeType, ComponentId,
+    },
+    Bytes,
+};
+
+use super::cache::WriteOp;
+
+type HeadKey = (Chain, Address);
+type StateKey = (Chain, ComponentId);
+
+/// Default memory budget of the component state cache, in MB.
+pub const COMPONENT_STATE_CACHE_DEFAULT_MB: usize = 16;
+
+/// Memory bound of a head state cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadCacheBudget {
+    /// Upper bound on the estimated size of all cached entries, in bytes.
+    pub max_bytes: usize,
+    /// Entries cached for longer than this are dropped on their next read and read from the
+    /// database again. Unbounded if `None`.
+    pub max_age: Option<Duration>,
+}
+
+impl HeadCacheBudget {
+    pub fn from_mb(max_mb: usize) -> Self {
+        Self { max_bytes: max_mb << 20, max_age: None }
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+}
+
+impl Default for HeadCacheBudget {
+    fn default() -> Self {
+        Self::from_mb(256)
+    }
+}
+
+/// Estimated heap and inline size of a cached account.
+fn estimated_size(account: &Account) -> usize {
+    // Each slot is a map entry of two `Bytes`, plus their heap allocations
+    let slots: usize = account
+        .slots
+        .iter()
+        .map(|(slot, value)| 2 * mem::size_of::<Bytes>() + slot.len() + value.len())
+        .sum();
+    let token_balances = account.token_balances.len() *
+        (mem::size_of::<Address>() + mem::size_of::<AccountBalance>());
+    mem::size_of::<Account>() +
+        slots +
+        token_balances +
+        account.address.len() +
+        account.title.len() +
+        account.native_balance.len() +
+        account.code.len() +
+        account.code_hash.len() +
+        account.balance_modify_tx.len() +
+        account.code_modify_tx.len()
+}
+
+/// Estimated heap and inline size of a cached component state.
+fn estimated_state_size(state: &ProtocolComponentState) -> usize {
+    let attributes: usize = state
+        .attributes
+        .iter()
+        .map(|(name, value)| {
+            mem::size_of::<String>() + mem::size_of::<Bytes>() + name.len() + value.len()
+        })
+        .sum();
+    let balances: usize = state
+        .balances
+        .iter()
+        .map(|(token, balance)| 2 * mem::size_of::<Bytes>() + token.len() + balance.len())
+        .sum();
+    mem::size_of::<ProtocolComponentState>() + state.component_id.len() + attributes + balances
+}
+
+/// Gauges exported for the entries of a cache.
+struct Gauges {
+    bytes: &'static str,
+    high_water_bytes: &'static str,
+    entries: &'static str,
+}
+
+const ACCOUNT_GAUGES: Gauges = Gauges {
+    bytes: "head_cache_bytes",
+    high_water_bytes: "head_cache_high_water_bytes",
+    entries: "head_cache_accounts",
+};
+
+const COMPONENT_STATE_GAUGES: Gauges = Gauges {
+    bytes: "head_cache_component_state_bytes",
+    high_water_bytes: "head_cache_component_state_high_water_bytes",
+    entries: "head_cache_component_states",
+};
+
+struct CachedEntry<V> {
+    value: V,
+    size: usize,
+    filled_at: Instant,
+}
+
+/// Cached entries and the bytes they take.
+struct Entries<K: Hash + Eq, V> {
+    lru: LruCache<K, CachedEntry<V>>,
+    used_bytes: usize,
+    high_water_bytes: usize,
+    size_of: fn(&V) -> usize,
+    gauges: Gauges,
+}
+
+impl<K: Hash + Eq + Clone, V> Entries<K, V> {
+    fn new(capacity: NonZeroUsize, size_of: fn(&V) -> usize, gauges: Gauges) -> Self {
+        Self { lru: LruCache::new(capacity), used_bytes: 0, high_water_bytes: 0, size_of, gauges }
+    }
+
+    /// Returns the entry of `key`, unless it was filled longer than the budget's max age ago.
+    /// Expired entries are evicted.
+    fn get(&mut self, key: &K, budget: &HeadCacheBudget) -> Option<&V> {
+        let filled_at = self.lru.get(key)?.filled_at;
+        if budget
+            .max_age
+            .is_some_and(|max_age| filled_at.elapsed() > max_age)
+        {
+            self.remove(key);
+            counter!("head_cache_evictions", "cause" => "expired").increment(1);
+            return None;
+        }
+        self.lru
+            .peek(key)
+            .map(|entry| &entry.value)
+    }
+
+    fn insert(&mut self, key: K, value: V, budget: &HeadCacheBudget) {
+        let size = (self.size_of)(&value);
+        if size > budget.max_bytes {
+            // Would evict everything else and still not fit
+            self.remove(&key);
+            counter!("head_cache_evictions", "cause" => "oversized").increment(1);
+            return;
+        }
+        let entry = CachedEntry { value, size, filled_at: Instant::now() };
+        self.used_bytes += size;
+        if let Some((replaced_key, replaced)) = self.lru.push(key.clone(), entry) {
+            self.used_bytes -= replaced.size;
+            if replaced_key != key {
+                counter!("head_cache_evictions", "cause" => "capacity").increment(1);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.lru.pop(key) {
+            self.used_bytes -= entry.size;
+        }
+    }
+
+    /// Updates the size of an entry that was modified in place.
+    fn resize(&mut self, key: &K) {
+        if let Some(entry) = self.lru.peek_mut(key) {
+            let size = (self.size_of)(&entry.value);
+            self.used_bytes = self.used_bytes - entry.size + size;
+            entry.size = size;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.lru.clear();
+        self.used_bytes = 0;
+    }
+
+    /// Evicts the least recently used entries until the cache fits into `budget`.
+    fn enforce(&mut self, budget: &HeadCacheBudget) {
+        while self.used_bytes > budget.max_bytes {
+            match self.lru.pop_lru() {
+                Some((_, entry)) => {
+                    self.used_bytes -= entry.size;
+                    counter!("head_cache_evictions", "cause" => "budget").increment(1);
+                }
+                None => break,
+            }
+        }
+        self.high_water_bytes = self
+            .high_water_bytes
+            .max(self.used_bytes);
+        gauge!(self.gauges.bytes).set(self.used_bytes as f64);
+        gauge!(self.gauges.high_water_bytes).set(self.high_water_bytes as f64);
+        gauge!(self.gauges.entries).set(self.lru.len() as f64);
+    }
+}
+
+/// The parts of a write batch concerning a cache, captured before the batch is sent to the db.
+#[derive(Debug)]
+pub(crate) struct PendingWrite<K> {
+    /// Writes to entries that were cached at the time, applied in place once committed.
+    ops: Vec<WriteOp>,
+    /// Every key written by the batch.
+    touched: HashSet<K>,
+}
+
+pub(crate) struct HeadStateCache {
+    accounts: Mutex<Entries<HeadKey, Account>>,
+    budget: HeadCacheBudget,
+    generation: AtomicU64,
+}
+
+impl HeadStateCache {
+    pub(crate) fn new(capacity: NonZeroUsize, budget: HeadCacheBudget) -> Self {
+        Self {
+            accounts: Mutex::new(Entries::new(capacity, estimated_size, ACCOUNT_GAUGES)),
+            budget,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Current generation, to be passed to [`HeadStateCache::fill`] after reading from the db.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Returns the cached accounts, only if all requested addresses are cached.
+    ///
+    /// Accounts older than the budget's max age count as not cached and are evicted.
+    pub(crate) async fn get_all(
+        &self,
+        chain: Chain,
+        addresses: &[Address],
+    ) -> Option<Vec<Account>> {
+        let mut accounts = self.accounts.lock().await;
+        let mut res = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            let account = accounts.get(&(chain, address.clone()), &self.budget)?;
+            res.push(account.clone());
+        }
+        Some(res)
+    }
+
+    /// Inserts accounts read from the db, unless a write was committed since `generation`.
+    pub(crate) async fn fill(&self, generation: u64, new: &[Account]) {
+        let mut accounts = self.accounts.lock().await;
+        if self.generation() != generation {
+            trace!(generation, "Skipping head cache fill, state was updated concurrently");
+            return;
+        }
+        for account in new {
+            accounts.insert(
+                (account.chain, account.address.clone()),
+                account.clone(),
+                &self.budget,
+            );
+        }
+        accounts.enforce(&self.budget);
+    }
+
+    /// Captures the accounts written by `ops`, to be passed to
+    /// [`HeadStateCache::apply_committed`] once the batch was committed.
+    ///
+    /// Only the parts of `ops` touching cached accounts are cloned, to avoid copying the full set
+    /// of operations of large batches.
+    pub(crate) async fn prepare_write(&self, ops: &[WriteOp]) -> PendingWrite<Address> {
+        let accounts = self.accounts.lock().await;
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+        // Balances don't carry a chain, so keys are matched by address only.
+        let mut touched = HashSet::new();
+        for op in ops {
+            match op {
+                WriteOp::UpdateContracts(deltas) => {
+                    touched.extend(
+                        deltas
+                            .iter()
+                            .map(|(_, delta)| delta.address.clone()),
+                    );
+                }
+                WriteOp::InsertContract(new) => {
+                    touched.extend(
+                        new.iter()
+                            .map(|account| account.address.clone()),
+                    );
+                }
+                WriteOp::InsertAccountBalances(balances) => {
+                    touched.extend(
+                        balances
+                            .iter()
+                            .map(|b| b.account.clone()),
+                    );
+                }
+                _ => {}
+            }
+        }
+        let accounts = &accounts.lru;
+        if accounts.is_empty() {
+            return PendingWrite { ops: Vec::new(), touched };
+        }
+        let is_cached =
+            |chain: Chain, address: &Address| accounts.contains(&(chain, address.clone()));
+        // Balances don't carry a chain, so they are matched by address only.
+        let cached_addresses: HashSet<&Address> = accounts
+            .iter()
+            .map(|((_, address), _)| address)
+            .collect();
+
+        let ops = ops
+            .iter()
+            .filter_map(|op| match op {
+                WriteOp::UpdateContracts(deltas) => {
+                    let deltas = deltas
+                        .iter()
+                        .filter(|(_, delta)| is_cached(delta.chain, &delta.address))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    (!deltas.is_empty()).then_some(WriteOp::UpdateContracts(deltas))
+                }
+                WriteOp::InsertContract(new) => {
+                    let new = new
+                        .iter()
+                        .filter(|account| is_cached(account.chain, &account.address))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    (!new.is_empty()).then_some(WriteOp::InsertContract(new))
+                }
+                WriteOp::InsertAccountBalances(balances) => {
+                    let balances = balances
+                        .iter()
+                        .filter(|b| cached_addresses.contains(&b.account))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    (!balances.is_empty()).then_some(WriteOp::InsertAccountBalances(balances))
+                }
+                _ => None,
+            })
+            .collect();
+        PendingWrite { ops, touched }
+    }
+
+    /// Applies a committed write to the cached accounts.
+    ///
+    /// Slot and native balance updates of accounts that were cached when the write was prepared
+    /// are applied in place. Any other account touched by the write (code changes, deletions,
+    /// re-creations, token balance changes and accounts filled while the write was in flight) is
+    /// evicted, it is reloaded from the db on the next read.
+    pub(crate) async fn apply_committed(&self, write: &PendingWrite<Address>) {
+        let mut accounts = self.accounts.lock().await;
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+
+        let mut evict = HashSet::new();
+        let mut updated = HashSet::new();
+        for op in &write.ops {
+            match op {
+                WriteOp::UpdateContracts(deltas) => {
+                    for (_, delta) in deltas {
+                        let key = (delta.chain, delta.address.clone());
+                        if delta.change != ChangeType::Update || delta.code.is_some() {
+                            evict.insert(key);
+                        } else if let Some(entry) = accounts.lru.peek_mut(&key) {
+                            if entry.value.apply_delta(delta).is_err() {
+                                evict.insert(key);
+                            } else {
+                                accounts.resize(&key);
+                                updated.insert(key);
+                            }
+                        }
+                    }
+                }
+                WriteOp::InsertContract(new) => {
+                    evict.extend(
+                        new.iter()
+                            .map(|account| (account.chain, account.address.clone())),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        evict.extend(
+            accounts
+                .lru
+                .iter()
+                .map(|(key, _)| key)
+                .filter(|key| write.touched.contains(&key.1) && !updated.contains(*key))
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        for key in evict {
+            accounts.remove(&key);
+        }
+        // Slot updates may have grown accounts beyond the budget
+        accounts.enforce(&self.budget);
+    }
+
+    /// Evicts a single account.
+    pub(crate) async fn evict(&self, chain: Chain, address: &Address) {
+        let mut accounts = self.accounts.lock().await;
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+        accounts.remove(&(chain, address.clone()));
+    }
+
+    /// Drops all cached accounts, e.g. after a revert.
+    pub(crate) async fn clear(&self) {
+        let mut accounts = self.accounts.lock().await;
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+        accounts.clear();
+    }
+}
+
+/// Latest states of protocol components, including their balances.
+pub(crate) struct ComponentStateCache {
+    states: Mutex<Entries<StateKey, ProtocolComponentState>>,
+    budget: HeadCacheBudget,
+    generation: AtomicU64,
+}
+
+impl ComponentStateCache {
+    pub(crate) fn new(capacity: NonZeroUsize, budget: HeadCacheBudget) -> Self {
+        Self {
+            states: Mutex::new(Entries::new(
+                capacity,
+                estimated_state_size,
+                COMPONENT_STATE_GAUGES,
+            )),
+            budget,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Current generation, to be passed to [`ComponentStateCache::fill`] after reading from the
+    /// db.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Returns the cached states, only if all requested components are cached.
+    pub(crate) async fn get_all(
+        &self,
+        chain: Chain,
+        ids: &[ComponentId],
+    ) -> Option<Vec<ProtocolComponentState>> {
+        let mut states = self.states.lock().await;
+        let mut res = Vec::with_capacity(ids.len());
+        for id in ids {
+            let state = states.get(&(chain, id.clone()), &self.budget)?;
+            res.push(state.clone());
+        }
+        Some(res)
+    }
+
+    /// Inserts states read from the db together with their balances, unless a write was
+    /// committed since `generation`.
+    pub(crate) async fn fill(&self, generation: u64, chain: Chain, new: &[ProtocolComponentState]) {
+        let mut states = self.states.lock().await;
+        if self.generation() != generation {
+            trace!(
+                generation,
+                "Skipping component state cache fill, state was updated concurrently"
+            );
+            return;
+        }
+        for state in new {
+            states.insert((chain, state.component_id.clone()), state.clone(), &self.budget);
+        }
+        states.enforce(&self.budget);
+    }
+
+    /// Captures the components written by `ops`, to be passed to
+    /// [`ComponentStateCache::apply_committed`] once the batch was committed.
+    ///
+    /// Only the parts of `ops` touching cached components are cloned, to avoid copying the full
+    /// set of operations of large batches.
+    pub(crate) async fn prepare_write(&self, ops: &[WriteOp]) -> PendingWrite<ComponentId> {
+        let states = self.states.lock().await;
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+        // Deltas and balances don't carry a chain, so keys are matched by component id only.
+        let mut touched = HashSet::new();
+        for op in ops {
+            match op {
+                WriteOp::UpsertProtocolState(deltas) => {
+                    touched.extend(
+                        deltas
+                            .iter()
+                            .map(|(_, delta)| delta.component_id.clone()),
+                    );
+                }
+                WriteOp::InsertComponentBalances(balances) => {
+                    touched.extend(
+                        balances
+                            .iter()
+                            .map(|b| b.component_id.clone()),
+                    );
+                }
+                _ => {}
+            }
+        }
+        if states.lru.is_empty() {
+            return PendingWrite { ops: Vec::new(), touched };
+        }
+        let cached_ids: HashSet<&ComponentId> = states
+            .lru
+            .iter()
+            .map(|((_, id), _)| id)
+            .collect();
+
+        let ops = ops
+            .iter()
+            .filter_map(|op| match op {
+                WriteOp::UpsertProtocolState(deltas) => {
+                    let deltas = deltas
+                        .iter()
+                        .filter(|(_, delta)| cached_ids.contains(&delta.component_id))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    (!deltas.is_empty()).then_some(WriteOp::UpsertProtocolState(deltas))
+                }
+                WriteOp::InsertComponentBalances(balances) => {
+                    let balances = balances
+                        .iter()
+                        .filter(|b| cached_ids.contains(&b.component_id))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    (!balances.is_empty()).then_some(WriteOp::InsertComponentBalances(balances))
+                }
+                _ => None,
+            })
+            .collect();
+        PendingWrite { ops, touched }
+    }
+
+    /// Applies a committed write to the cached states.
+    ///
+    /// State and balance updates of components that were cached when the write was prepared are
+    /// applied in place. Any other component touched by the write, i.e. one filled while the write
+    /// was in flight, is evicted and reloaded from the db on the next read.
+    pub(crate) async fn apply_committed(&self, write: &PendingWrite<ComponentId>) {
+        let mut states = self.states.lock().await;
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+
+        let mut deltas: HashMap<&ComponentId, Vec<&ProtocolComponentStateDelta>> = HashMap::new();
+        let mut balances: HashMap<&ComponentId, Vec<&ComponentBalance>> = HashMap::new();
+        for op in &write.ops {
+            match op {
+                WriteOp::UpsertProtocolState(new) => {
+                    for (_, delta) in new {
+                        deltas
+                            .entry(&delta.component_id)
+                            .or_default()
+                            .push(delta);
+                    }
+                }
+                WriteOp::InsertComponentBalances(new) => {
+                    for balance in new {
+                        balances
+                            .entry(&balance.component_id)
+                            .or_default()
+                            .push(balance);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let touched = states
+            .lru
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|(_, id)| write.touched.contains(id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in touched {
+            if !deltas.contains_key(&key.1) && !balances.contains_key(&key.1) {
+                // Filled while the write was in flight, may predate it
+                states.remove(&key);
+                continue;
+            }
+            let Some(entry) = states.lru.peek_mut(&key) else {
+                continue;
+            };
+            let applied = deltas
+                .get(&key.1)
+                .into_iter()
+                .flatten()
+                .try_for_each(|delta| entry.value.apply_state_delta(delta));
+            for balance in balances
+                .get(&key.1)
+                .into_iter()
+                .flatten()
+            {
+                entry
+                    .value
+                    .balances
+                    .insert(balance.token.clone(), balance.balance.clone());
+            }
+            if applied.is_err() {
+                states.remove(&key);
+            } else {
+                states.resize(&key);
+            }
+        }
+        // Updates may have grown states beyond the budget
+        states.enforce(&self.budget);
+    }
+
+    /// Evicts the states of the given components.
+    pub(crate) async fn evict(&self, chain: Chain, ids: &[ComponentId]) {
+        let mut states = self.states.lock().await;
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+        for id in ids {
+            states.remove(&(chain, id.clone()));
+        }
+    }
+
+    /// Drops all cached states, e.g. after a revert.
+    pub(crate) async fn clear(&self) {
+        let mut states = self.states.lock().await;
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+        states.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use tycho_common::{
+        models::{contract::AccountDelta, protocol::ComponentBalance},
+        Bytes,
+    };
+
+    use super::*;
+
+    fn account(address: &str) -> Account {
+        Account::new(
+            Chain::Ethereum,
+            Bytes::from(address),
+            "test".to_string(),
+            HashMap::from([(Bytes::from("0x01"), Bytes::from("0x01"))]),
+            Bytes::from("0x00"),
+            HashMap::new(),
+            Bytes::from("0x00"),
+            Bytes::from("0x00"),
+            Bytes::from("0x00"),
+            Bytes::from("0x00"),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_fill_and_apply() {
+        let cache = HeadStateCache::new(NonZeroUsize::new(10).unwrap(), HeadCacheBudget::default());
+        let addresses = vec![Bytes::from("0xaa"), Bytes::from("0xbb")];
+
+        cache
+            .fill(cache.generation(), &[account("0xaa")])
+            .await;
+        assert!(cache
+            .get_all(Chain::Ethereum, &addresses)
+            .await
+            .is_none());
+
+        cache
+            .fill(cache.generation(), &[account("0xbb")])
+            .await;
+        let delta = AccountDelta::new(
+            Chain::Ethereum,
+            Bytes::from("0xaa"),
+            HashMap::from([
+                (Bytes::from("0x01"), Some(Bytes::from("0x03"))),
+                (Bytes::from("0x02"), Some(Bytes::from("0x02"))),
+            ]),
+            Some(Bytes::from("0x64")),
+            None,
+            ChangeType::Update,
+        );
+        let ops = vec![
+            WriteOp::UpdateContracts(vec![(Bytes::from("0xff"), delta)]),
+            WriteOp::UpdateContracts(vec![(
+                Bytes::from("0xff"),
+                AccountDelta::new(
+                    Chain::Ethereum,
+                    Bytes::from("0xcc"),
+                    HashMap::new(),
+                    None,
+                    None,
+                    ChangeType::Update,
+                ),
+            )]),
+        ];
+        let write = cache.prepare_write(&ops).await;
+        assert_eq!(write.ops, ops[..1]);
+        cache.apply_committed(&write).await;
+
+        let res = cache
+            .get_all(Chain::Ethereum, &addresses)
+            .await
+            .expect("all accounts cached");
+        assert_eq!(
+            res[0].slots,
+            HashMap::from([
+                (Bytes::from("0x01"), Bytes::from("0x03")),
+                (Bytes::from("0x02"), Bytes::from("0x02"))
+            ])
+        );
+        assert_eq!(res[0].native_balance, Bytes::from("0x64"));
+        assert_eq!(res[1], account("0xbb"));
+    }
+
+    #[tokio::test]
+    async fn test_stale_fill_is_discarded() {
+        let cache = HeadStateCache::new(NonZeroUsize::new(10).unwrap(), HeadCacheBudget::default());
+        let generation = cache.generation();
+
+        cache.clear().await;
+        cache
+            .fill(generation, &[account("0xaa")])
+            .await;
+
+        assert!(cache
+            .get_all(Chain::Ethereum, &[Bytes::from("0xaa")])
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fill_during_write_is_evicted() {
+        let cache = HeadStateCache::new(NonZeroUsize::new(10).unwrap(), HeadCacheBudget::default());
+        let ops = vec![WriteOp::UpdateContracts(vec![(
+            Bytes::from("0xff"),
+            AccountDelta::new(
+                Chain::Ethereum,
+                Bytes::from("0xaa"),
+                HashMap::from([(Bytes::from("0x01"), Some(Bytes::from("0x03")))]),
+                None,
+                None,
+                ChangeType::Update,
+            ),
+        )])];
+
+        let write = cache.prepare_write(&ops).await;
+        // A read of the state before the commit lands while the write is in flight.
+        cache
+            .fill(cache.generation(), &[account("0xaa")])
+            .await;
+        cache.apply_committed(&write).await;
+
+        assert!(cache
+            .get_all(Chain::Ethereum, &[Bytes::from("0xaa")])
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_budget_evicts_least_recently_used() {
+        let size = estimated_size(&account("0xaa"));
+        let budget = HeadCacheBudget { max_bytes: 2 * size, max_age: None };
+        let cache = HeadStateCache::new(NonZeroUsize::new(10).unwrap(), budget);
+
+        cache
+            .fill(cache.generation(), &[account("0xaa"), account("0xbb")])
+            .await;
+        // Reading 0xaa makes 0xbb the least recently used account.
+        assert!(cache
+            .get_all(Chain::Ethereum, &[Bytes::from("0xaa")])
+            .await
+            .is_some());
+        cache
+            .fill(cache.generation(), &[account("0xcc")])
+            .await;
+
+        let accounts = cache.accounts.lock().await;
+        assert_eq!(accounts.used_bytes, 2 * size);
+        assert_eq!(accounts.high_water_bytes, 2 * size);
+        assert!(!accounts
+            .lru
+            .contains(&(Chain::Ethereum, Bytes::from("0xbb"))));
+    }
+
+    #[tokio::test]
+    async fn test_expired_accounts_are_not_served() {
+        let budget = HeadCacheBudget::default().with_max_age(Duration::ZERO);
+        let cache = HeadStateCache::new(NonZeroUsize::new(10).unwrap(), budget);
+
+        cache
+            .fill(cache.generation(), &[account("0xaa")])
+            .await;
+
+        assert!(cache
+            .get_all(Chain::Ethereum, &[Bytes::from("0xaa")])
+            .await
+            .is_none());
+        assert_eq!(cache.accounts.lock().await.used_bytes, 0);
+    }
+
+    fn component_state(id: &str) -> ProtocolComponentState {
+        ProtocolComponentState::new(
+            id,
+            HashMap::from([
+                ("reserve0".to_string(), Bytes::from("0x01")),
+                ("fee".to_string(), Bytes::from("0x1e")),
+            ]),
+            HashMap::from([(Bytes::from("0xaa"), Bytes::from("0x01"))]),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_component_states_fill_and_apply() {
+        let cache =
+            ComponentStateCache::new(NonZeroUsize::new(10).unwrap(), HeadCacheBudget::default());
+        let ids = vec!["pool_a".to_string(), "pool_b".to_string()];
+        cache
+            .fill(
+                cache.generation(),
+                Chain::Ethereum,
+                &[component_state("pool_a"), component_state("pool_b")],
+            )
+            .await;
+
+        let pool_a_delta = ProtocolComponentStateDelta::new(
+            "pool_a",
+            HashMap::from([("reserve0".to_string(), Bytes::from("0x02"))]),
+            HashSet::from(["fee".to_string()]),
+        );
+        let balances = WriteOp::InsertComponentBalances(vec![ComponentBalance::new(
+            Bytes::from("0xbb"),
+            Bytes::from("0x05"),
+            5.0,
+            Bytes::from("0xff"),
+            "pool_b",
+        )]);
+        let ops = vec![
+            WriteOp::UpsertProtocolState(vec![
+                (Bytes::from("0xff"), pool_a_delta.clone()),
+                (
+                    Bytes::from("0xff"),
+                    ProtocolComponentStateDelta::new("pool_c", HashMap::new(), HashSet::new()),
+                ),
+            ]),
+            balances.clone(),
+        ];
+        let write = cache.prepare_write(&ops).await;
+        assert_eq!(
+            write.ops,
+            vec![WriteOp::UpsertProtocolState(vec![(Bytes::from("0xff"), pool_a_delta)]), balances]
+        );
+        cache.apply_committed(&write).await;
+
+        let res = cache
+            .get_all(Chain::Ethereum, &ids)
+            .await
+            .expect("all states cached");
+        assert_eq!(
+            res[0].attributes,
+            HashMap::from([("reserve0".to_string(), Bytes::from("0x02"))])
+        );
+        assert_eq!(
+            res[1].balances,
+            HashMap::from([
+                (Bytes::from("0xaa"), Bytes::from("0x01")),
+                (Bytes::from("0xbb"), Bytes::from("0x05"))
+            ])
+        );
+        assert!(cache
+            .get_all(Chain::Starknet, &ids)
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_component_state_fill_during_write_is_evicted() {
+        let cache =
+            ComponentStateCache::new(NonZeroUsize::new(10).unwrap(), HeadCacheBudget::default());
+        let ops = vec![WriteOp::UpsertProtocolState(vec![(
+            Bytes::from("0xff"),
+            ProtocolComponentStateDelta::new(
+                "pool_a",
+                HashMap::from([("reserve0".to_string(), Bytes::from("0x02"))]),
+                HashSet::new(),
+            ),
+        )])];
+
+        let write = cache.prepare_write(&ops).await;
+        // A read of the state before the commit lands while the write is in flight.
+        cache
+            .fill(cache.generation(), Chain::Ethereum, &[component_state("pool_a")])
+            .await;
+        cache.apply_committed(&write).await;
+
+        assert!(cache
+            .get_all(Chain::Ethereum, &["pool_a".to_string()])
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_component_states_evict() {
+        let cache =
+            ComponentStateCache::new(NonZeroUsize::new(10).unwrap(), HeadCacheBudget::default());
+        let generation = cache.generation();
+
+        cache
+            .evict(Chain::Ethereum, &["pool_b".to_string()])
+            .await;
+        cache
+            .fill(generation, Chain::Ethereum, &[component_state("pool_a")])
+            .await;
+        assert!(cache
+            .get_all(Chain::Ethereum, &["pool_a".to_string()])
+            .await
+            .is_none());
+
+        cache
+            .fill(cache.generation(), Chain::Ethereum, &[component_state("pool_a")])
+            .await;
+        cache
+            .evict(Chain::Ethereum, &["pool_a".to_string()])
+            .await;
+        assert!(cache
+            .get_all(Chain::Ethereum, &["pool_a".to_string()])
+            .await
+            .is_none());
+        assert_eq!(cache.states.lock().await.used_bytes, 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! Read-through cache of database ids looked up by the write paths.
+//!
+//! Every block writes balances, components and states referencing tokens and transactions by
+//! their hash or address, which have to be resolved to database ids first. The same tokens and
+//! transactions are resolved over and over, by several operations of a block and by consecutive
+//! blocks, so the resolved ids are kept in memory.
+//!
+//! Token ids never change once assigned. Transaction ids do change if a reverted transaction is
+//! inserted again, so transactions are only cached for the block being written and the whole cache
+//! is dropped on reverts and failed writes.
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    num::NonZeroUsize,
+    sync::Mutex,
+};
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use lru::LruCache;
+use metrics::counter;
+use tycho_common::{
+    models::{Address, TxHash},
+    storage::StorageError,
+};
+
+use super::{orm, schema, PostgresError, PostgresGateway};
+
+const TOKEN_ID_CACHE_CAPACITY: usize = 50_000;
+const TX_ID_CACHE_CAPACITY: usize = 10_000;
+
+/// Db id, index and block timestamp of a transaction.
+pub(crate) type TxIdAndTs = (i64, i64, NaiveDateTime);
+
+pub(crate) struct IdCache {
+    tokens: Mutex<LruCache<Address, i64>>,
+    transactions: Mutex<LruCache<TxHash, TxIdAndTs>>,
+}
+
+impl Default for IdCache {
+    fn default() -> Self {
+        Self {
+            tokens: Mutex::new(LruCache::new(
+                NonZeroUsize::new(TOKEN_ID_CACHE_CAPACITY).expect("capacity is non zero"),
+            )),
+            transactions: Mutex::new(LruCache::new(
+                NonZeroUsize::new(TX_ID_CACHE_CAPACITY).expect("capacity is non zero"),
+            )),
+        }
+    }
+}
+
+impl IdCache {
+    /// Forgets the transactions of previous blocks, called before a new block is written.
+    pub(crate) fn start_block(&self) {
+        self.transactions
+            .lock()
+            .expect("id cache lock poisoned")
+            .clear();
+    }
+
+    /// Forgets all ids, they may refer to rows that were deleted or never committed.
+    pub(crate) fn clear(&self) {
+        self.tokens
+            .lock()
+            .expect("id cache lock poisoned")
+            .clear();
+        self.start_block();
+    }
+}
+
+/// Splits `keys` into the cached entries and the keys that have to be loaded.
+fn lookup<K, V>(
+    cache: &Mutex<LruCache<K, V>>,
+    name: &'static str,
+    keys: impl IntoIterator<Item = K>,
+) -> (HashMap<K, V>, Vec<K>)
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    let mut cache = cache
+        .lock()
+        .expect("id cache lock poisoned");
+    let mut found = HashMap::new();
+    let mut missing = Vec::new();
+    for key in keys.into_iter().collect::<HashSet<_>>() {
+        match cache.get(&key) {
+            Some(value) => {
+                found.insert(key, value.clone());
+            }
+            None => missing.push(key),
+        }
+    }
+    counter!("storage_id_cache_hits", "cache" => name).increment(found.len() as u64);
+    counter!("storage_id_cache_misses", "cache" => name).increment(missing.len() as u64);
+    (found, missing)
+}
+
+fn fill<K, V>(cache: &Mutex<LruCache<K, V>>, loaded: &HashMap<K, V>)
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    let mut cache = cache
+        .lock()
+        .expect("id cache lock poisoned");
+    for (key, value) in loaded {
+        cache.put(key.clone(), value.clone());
+    }
+}
+
+impl PostgresGateway {
+    /// Resolves token addresses to their db ids, unknown tokens are left out.
+    pub(crate) async fn get_token_ids(
+        &self,
+        addresses: impl IntoIterator<Item = &Address>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<Address, i64>, StorageError> {
+        let (mut found, missing) =
+            lookup(&self.id_cache.tokens, "token", addresses.into_iter().cloned());
+        if missing.is_empty() {
+            return Ok(found);
+        }
+        let loaded: HashMap<Address, i64> = schema::token::table
+            .inner_join(schema::account::table)
+            .select((schema::account::address, schema::token::id))
+            .filter(schema::account::address.eq_any(&missing))
+            .load::<(Address, i64)>(conn)
+            .await
+            .map_err(PostgresError::from)?
+            .into_iter()
+            .collect();
+        fill(&self.id_cache.tokens, &loaded);
+        found.extend(loaded);
+        Ok(found)
+    }
+
+    /// Resolves transaction hashes to their db id, index and block timestamp, unknown
+    /// transactions are left out.
+    pub(crate) async fn get_transaction_ids_and_ts(
+        &self,
+        hashes: impl IntoIterator<Item = &TxHash>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<TxHash, TxIdAndTs>, StorageError> {
+        let (mut found, missing) =
+            lookup(&self.id_cache.transactions, "transaction", hashes.into_iter().cloned());
+        if missing.is_empty() {
+            return Ok(found);
+        }
+        let missing = missing.iter().collect::<Vec<_>>();
+        let loaded: HashMap<TxHash, TxIdAndTs> =
+            orm::Transaction::ids_and_ts_by_hash(&missing, conn)
+                .await
+                .map_err(PostgresError::from)?
+                .into_iter()
+                .map(|(db_id, hash, index, ts)| (hash, (db_id, index, ts)))
+                .collect();
+        fill(&self.id_cache.transactions, &loaded);
+        found.extend(loaded);
+        Ok(found)
+    }
+
+    /// Drops all cached ids, to be called once writes were rolled back or state was reverted.
+    pub(crate) fn clear_id_cache(&self) {
+        self.id_cache.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tycho_common::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_lookup_splits_cached_and_missing_keys() {
+        let cache = IdCache::default();
+        let (known, unknown) = (Bytes::from("0x01"), Bytes::from("0x02"));
+        fill(&cache.tokens, &HashMap::from([(known.clone(), 7)]));
+
+        let (found, missing) = lookup(&cache.tokens, "token", [known.clone(), unknown.clone()]);
+        cache.clear();
+        let (_, missing_after_clear) = lookup(&cache.tokens, "token", [known.clone()]);
+
+        assert_eq!(found, HashMap::from([(known.clone(), 7)]));
+        assert_eq!(missing, vec![unknown]);
+        assert_eq!(missing_after_clear, vec![known]);
+    }
+}
@@ -0,0 +1,289 @@
+//! Idempotency keys of admin requests.
+//!
+//! A key is claimed before the action runs and completed with its response afterwards. The row
+//! of a claimed key has no status until it is completed, which is how concurrent duplicates are
+//! told apart from finished ones. Keys older than [`IDEMPOTENCY_KEY_RETENTION`] are replaced when
+//! claimed again. Claims without status are only held for [`IDEMPOTENCY_CLAIM_LEASE`] after they
+//! were last modified, so a claim left behind by a crashed server doesn't block retries for the
+//! whole retention.
+use chrono::Utc;
+use diesel::{
+    dsl::sql, sql_types::Timestamptz, BoolExpressionMethods, ExpressionMethods, QueryDsl,
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tracing::instrument;
+use tycho_common::storage::{
+    IdempotencyClaim, IdempotentResponse, StorageError, IDEMPOTENCY_CLAIM_LEASE,
+    IDEMPOTENCY_KEY_RETENTION,
+};
+
+use super::{schema, PostgresError, PostgresGateway};
+
+impl PostgresGateway {
+    #[instrument(skip(self, conn))]
+    pub async fn claim_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        action: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<IdempotencyClaim, StorageError> {
+        use schema::admin_idempotency_key::dsl;
+
+        let expired_before = Utc::now().naive_utc() - IDEMPOTENCY_KEY_RETENTION;
+        // Compared to the database clock, which sets `modified_ts`.
+        let claim_expired_before = sql::<Timestamptz>(&format!(
+            "now() - interval '{} milliseconds'",
+            IDEMPOTENCY_CLAIM_LEASE.as_millis()
+        ));
+        diesel::delete(
+            dsl::admin_idempotency_key
+                .filter(dsl::api_key.eq(api_key))
+                .filter(dsl::key.eq(key))
+                .filter(
+                    dsl::inserted_ts
+                        .lt(expired_before)
+                        .or(dsl::status
+                            .is_null()
+                            .and(dsl::modified_ts.lt(claim_expired_before))),
+                ),
+        )
+        .execute(conn)
+        .await
+        .map_err(PostgresError::from)?;
+
+        let inserted = diesel::insert_into(dsl::admin_idempotency_key)
+            .values((dsl::api_key.eq(api_key), dsl::key.eq(key), dsl::action.eq(action)))
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        if inserted == 1 {
+            return Ok(IdempotencyClaim::Claimed);
+        }
+
+        let (recorded_action, status, content_type, body) = dsl::admin_idempotency_key
+            .filter(dsl::api_key.eq(api_key))
+            .filter(dsl::key.eq(key))
+            .select((dsl::action, dsl::status, dsl::content_type, dsl::body))
+            .first::<(String, Option<i32>, Option<String>, Option<Vec<u8>>)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(match status {
+            _ if recorded_action != action => IdempotencyClaim::ActionMismatch,
+            None => IdempotencyClaim::InProgress,
+            Some(status) => IdempotencyClaim::Completed(IdempotentResponse {
+                status: status as u16,
+                content_type,
+                body: body.unwrap_or_default(),
+            }),
+        })
+    }
+
+    #[instrument(skip(self, response, conn))]
+    pub async fn complete_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        response: &IdempotentResponse,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::admin_idempotency_key::dsl;
+
+        let updated = diesel::update(
+            dsl::admin_idempotency_key
+                .filter(dsl::api_key.eq(api_key))
+                .filter(dsl::key.eq(key))
+                .filter(dsl::status.is_null()),
+        )
+        .set((
+            dsl::status.eq(i32::from(response.status)),
+            dsl::content_type.eq(&response.content_type),
+            dsl::body.eq(&response.body),
+        ))
+        .execute(conn)
+        .await
+        .map_err(PostgresError::from)?;
+        if updated == 0 {
+            return Err(StorageError::NotFound("IdempotencyKey".to_string(), key.to_string()));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self, conn))]
+    pub async fn release_idempotency_key(
+        &self,
+        api_key: &str,
+        key: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::admin_idempotency_key::dsl;
+
+        diesel::delete(
+            dsl::admin_idempotency_key
+                .filter(dsl::api_key.eq(api_key))
+                .filter(dsl::key.eq(key))
+                .filter(dsl::status.is_null()),
+        )
+        .execute(conn)
+        .await
+        .map_err(PostgresError::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel::OptionalExtension;
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+
+    async fn recorded_status(
+        api_key: &str,
+        key: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Option<Option<i32>> {
+        use schema::admin_idempotency_key::dsl;
+
+        dsl::admin_idempotency_key
+            .filter(dsl::api_key.eq(api_key))
+            .filter(dsl::key.eq(key))
+            .select(dsl::status)
+            .first::<Option<i32>>(conn)
+            .await
+            .optional()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_keys() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        let action = "POST /v1/admin/component_filter";
+        let response = IdempotentResponse {
+            status: 200,
+            content_type: Some("application/json".to_string()),
+            body: b"{}".to_vec(),
+        };
+
+        let first = gw
+            .claim_idempotency_key("ops", "retry-1", action, &mut conn)
+            .await
+            .unwrap();
+        let concurrent = gw
+            .claim_idempotency_key("ops", "retry-1", action, &mut conn)
+            .await
+            .unwrap();
+        let other_key = gw
+            .claim_idempotency_key("dashboard", "retry-1", action, &mut conn)
+            .await
+            .unwrap();
+        gw.complete_idempotency_key("ops", "retry-1", &response, &mut conn)
+            .await
+            .unwrap();
+        let duplicate = gw
+            .claim_idempotency_key("ops", "retry-1", action, &mut conn)
+            .await
+            .unwrap();
+        let mismatch = gw
+            .claim_idempotency_key("ops", "retry-1", "POST /v1/admin/tracked_contracts", &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(first, IdempotencyClaim::Claimed);
+        assert_eq!(concurrent, IdempotencyClaim::InProgress);
+        assert_eq!(other_key, IdempotencyClaim::Claimed);
+        assert_eq!(duplicate, IdempotencyClaim::Completed(response.clone()));
+        assert_eq!(mismatch, IdempotencyClaim::ActionMismatch);
+        // Completed keys can't be released or completed again
+        gw.release_idempotency_key("ops", "retry-1", &mut conn)
+            .await
+            .unwrap();
+        assert!(gw
+            .complete_idempotency_key("ops", "retry-1", &response, &mut conn)
+            .await
+            .is_err());
+        assert_eq!(recorded_status("ops", "retry-1", &mut conn).await, Some(Some(200)));
+    }
+
+    #[tokio::test]
+    async fn test_release_idempotency_key() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        let action = "POST /v1/admin/component_filter";
+
+        gw.claim_idempotency_key("ops", "retry-2", action, &mut conn)
+            .await
+            .unwrap();
+        gw.release_idempotency_key("ops", "retry-2", &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(recorded_status("ops", "retry-2", &mut conn).await, None);
+        assert_eq!(
+            gw.claim_idempotency_key("ops", "retry-2", action, &mut conn)
+                .await
+                .unwrap(),
+            IdempotencyClaim::Claimed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_claim_idempotency_key_takes_over_expired_claims() {
+        use schema::admin_idempotency_key::dsl;
+
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        let action = "POST /v1/admin/component_filter";
+        let last_modified = Utc::now().naive_utc() - IDEMPOTENCY_CLAIM_LEASE * 2;
+        diesel::insert_into(dsl::admin_idempotency_key)
+            .values(
+                [("retry-3", None), ("retry-4", Some(200))]
+                    .into_iter()
+                    .map(|(key, status)| {
+                        (
+                            dsl::api_key.eq("ops"),
+                            dsl::key.eq(key),
+                            dsl::action.eq(action),
+                            dsl::status.eq(status),
+                            dsl::modified_ts.eq(last_modified),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let abandoned = gw
+            .claim_idempotency_key("ops", "retry-3", action, &mut conn)
+            .await
+            .unwrap();
+        let completed = gw
+            .claim_idempotency_key("ops", "retry-4", action, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(abandoned, IdempotencyClaim::Claimed);
+        assert!(matches!(completed, IdempotencyClaim::Completed(_)));
+    }
+}
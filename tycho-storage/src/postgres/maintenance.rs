@@ -0,0 +1,786 @@
+//! Database maintenance run from the command line rather than by a running indexer.
+//!
+//! Each function opens its own connection from a database url. [`migrate`] and [`prune_history`]
+//! modify the database and should not run while extractors write to it; [`check`] and
+//! [`check_integrity`] only read.
+//! [`collect_orphaned_accounts`] is meant to run periodically next to the extractors, the
+//! gateway schedules it with an [`OrphanedAccountCollector`] if configured.
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    prelude::*,
+    sql_types::{Array, BigInt, Text, Timestamptz},
+};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use diesel_migrations::MigrationHarness;
+use metrics::gauge;
+use tracing::{info, warn};
+use tycho_common::storage::StorageError;
+
+use super::{
+    audit::{record_operation, AuditRecord},
+    scheduler::PeriodicTask,
+    schema, schema_check, PostgresError, MIGRATIONS,
+};
+
+/// Ids of the accounts still in use: contracts held by a component that was not deleted,
+/// contracts tracked by an extractor, tokens and accounts called by traced entry points.
+const REFERENCED_ACCOUNTS: &str = r#"
+    SELECT cc.account_id FROM protocol_component_holds_contract h
+    JOIN contract_code cc ON cc.id = h.contract_code_id
+    JOIN protocol_component pc ON pc.id = h.protocol_component_id
+    WHERE pc.deleted_at IS NULL OR pc.deleted_at > now()
+    UNION SELECT a.id FROM tracked_contract tc
+    JOIN account a ON a.chain_id = tc.chain_id AND a.address = tc.address
+    WHERE tc.until_block IS NULL
+    UNION SELECT account_id FROM token
+    UNION SELECT account_id FROM entry_point_tracing_params_calls_account"#;
+
+/// Tables with versioned data of an account. They are cleared explicitly before the account is
+/// deleted, so a batch doesn't cascade into an unbounded number of rows.
+const ACCOUNT_DATA_TABLES: [&str; 4] =
+    ["contract_storage", "contract_storage_cold", "contract_code", "account_balance"];
+
+/// Versioned tables with the columns identifying an entity, versions of the same entity must not
+/// overlap.
+const VERSIONED_TABLES: [(&str, &str); 5] = [
+    ("protocol_state", "protocol_component_id, attribute_name"),
+    ("component_balance", "protocol_component_id, token_id"),
+    ("contract_storage", "account_id, slot"),
+    ("account_balance", "account_id, token_id"),
+    ("contract_code", "account_id"),
+];
+
+/// Number of violating rows listed per check in an [`IntegrityReport`].
+const INTEGRITY_SAMPLE_SIZE: i64 = 10;
+
+/// Invariants across entities that foreign keys don't enforce, each query selects an `id`
+/// describing every violating row.
+const ENTITY_CHECKS: [(&str, &str); 4] = [
+    (
+        "component_without_tokens",
+        "SELECT pc.external_id AS id FROM protocol_component pc
+        WHERE (pc.deleted_at IS NULL OR pc.deleted_at > now()) AND NOT EXISTS (
+            SELECT 1 FROM protocol_component_holds_token h
+            WHERE h.protocol_component_id = pc.id)",
+    ),
+    (
+        "component_balance_without_component_or_token",
+        "SELECT concat_ws(':', cb.protocol_component_id, cb.token_id) AS id
+        FROM component_balance cb
+        LEFT JOIN protocol_component pc ON pc.id = cb.protocol_component_id
+        LEFT JOIN token t ON t.id = cb.token_id
+        WHERE pc.id IS NULL OR t.id IS NULL",
+    ),
+    (
+        "component_balance_of_unheld_token",
+        "SELECT DISTINCT concat_ws(':', cb.protocol_component_id, cb.token_id) AS id
+        FROM component_balance cb
+        WHERE NOT EXISTS (
+            SELECT 1 FROM protocol_component_holds_token h
+            WHERE h.protocol_component_id = cb.protocol_component_id
+            AND h.token_id = cb.token_id)",
+    ),
+    (
+        "protocol_state_without_component",
+        "SELECT concat_ws(':', ps.protocol_component_id, ps.attribute_name) AS id
+        FROM protocol_state ps
+        LEFT JOIN protocol_component pc ON pc.id = ps.protocol_component_id
+        WHERE pc.id IS NULL",
+    ),
+];
+
+/// Result of a single integrity check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityCheck {
+    pub name: String,
+    pub violations: i64,
+    /// Up to [`INTEGRITY_SAMPLE_SIZE`] violating rows, identified by their key columns.
+    pub sample: Vec<String>,
+}
+
+/// Outcome of a [`check_integrity`] run, one entry per check.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    pub checks: Vec<IntegrityCheck>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.violations == 0)
+    }
+
+    /// Checks that found at least one violation.
+    pub fn failed(&self) -> impl Iterator<Item = &IntegrityCheck> {
+        self.checks
+            .iter()
+            .filter(|check| check.violations > 0)
+    }
+}
+
+#[derive(QueryableByName, Debug)]
+struct Violation {
+    #[diesel(sql_type = BigInt)]
+    total: i64,
+    #[diesel(sql_type = Text)]
+    id: String,
+}
+
+/// Outcome of a single [`collect_orphaned_accounts`] run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrphanedAccounts {
+    /// Accounts found unreferenced for the first time.
+    pub marked: usize,
+    /// Previously orphaned accounts that are referenced again.
+    pub revived: usize,
+    /// Accounts deleted after the grace period.
+    pub deleted: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct OrphanedAccountsConfig {
+    /// Time an account has to stay unreferenced before it is deleted.
+    pub grace_period: Duration,
+    /// Number of accounts deleted per database transaction.
+    pub batch_size: i64,
+    /// Pause between collection runs.
+    pub interval: std::time::Duration,
+}
+
+impl Default for OrphanedAccountsConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: Duration::days(7),
+            batch_size: 1000,
+            interval: std::time::Duration::from_secs(6 * 3600),
+        }
+    }
+}
+
+/// Runs [`collect_orphaned_accounts`] as a scheduled task.
+pub(crate) struct OrphanedAccountCollector {
+    db_url: String,
+    config: OrphanedAccountsConfig,
+}
+
+impl OrphanedAccountCollector {
+    pub(crate) fn new(db_url: &str, config: OrphanedAccountsConfig) -> Self {
+        Self { db_url: db_url.to_string(), config }
+    }
+}
+
+#[async_trait]
+impl PeriodicTask for OrphanedAccountCollector {
+    async fn run_once(&mut self) -> Result<(), StorageError> {
+        collect_orphaned_accounts(&self.db_url, self.config.grace_period, self.config.batch_size)
+            .await
+            .map(|_| ())
+    }
+}
+
+#[derive(QueryableByName, Debug)]
+struct AccountId {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+}
+
+/// Applies pending migrations and verifies the resulting schema.
+pub async fn migrate(db_url: &str) -> Result<(), StorageError> {
+    super::connect(db_url, None, None)
+        .await
+        .map(|_| ())
+}
+
+/// Verifies the database is fully migrated and its schema matches the one the indexer expects.
+pub async fn check(db_url: &str) -> Result<(), StorageError> {
+    let mut conn = PgConnection::establish(db_url)
+        .map_err(|err| StorageError::Unexpected(format!("Failed to connect: {err}")))?;
+    let pending = conn
+        .pending_migrations(MIGRATIONS)
+        .map_err(|err| StorageError::Unexpected(format!("Failed to list migrations: {err}")))?;
+    if !pending.is_empty() {
+        let names = pending
+            .iter()
+            .map(|m| m.name().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(StorageError::Unexpected(format!(
+            "{} migrations are pending: {names}",
+            pending.len()
+        )));
+    }
+
+    let mut conn = AsyncPgConnection::establish(db_url)
+        .await
+        .map_err(|err| StorageError::Unexpected(format!("Failed to connect: {err}")))?;
+    schema_check::check_schema(&mut conn).await
+}
+
+/// Validates invariants across entities that the schema doesn't enforce on its own.
+///
+/// Checks that every component holds tokens, that component balances and protocol states
+/// reference existing components and tokens, and that versions of each entity in the versioned
+/// tables have `valid_from <= valid_to` and don't overlap. Violations are logged and exported
+/// as the `db_integrity_violations` gauge, labeled by check.
+pub async fn check_integrity(db_url: &str) -> Result<IntegrityReport, StorageError> {
+    let mut conn = AsyncPgConnection::establish(db_url)
+        .await
+        .map_err(|err| StorageError::Unexpected(format!("Failed to connect: {err}")))?;
+    let report = run_integrity_checks(&mut conn).await?;
+    for check in &report.checks {
+        gauge!("db_integrity_violations", "check" => check.name.clone())
+            .set(check.violations as f64);
+        if check.violations > 0 {
+            warn!(
+                check = check.name,
+                violations = check.violations,
+                sample = ?check.sample,
+                "Integrity check failed"
+            );
+        } else {
+            info!(check = check.name, "Integrity check passed");
+        }
+    }
+    Ok(report)
+}
+
+async fn run_integrity_checks(
+    conn: &mut AsyncPgConnection,
+) -> Result<IntegrityReport, StorageError> {
+    let mut queries = ENTITY_CHECKS
+        .iter()
+        .map(|(name, query)| (name.to_string(), query.to_string()))
+        .collect::<Vec<_>>();
+    for (table, entity) in VERSIONED_TABLES {
+        queries.push((
+            format!("{table}_inverted_validity"),
+            format!(
+                "SELECT concat_ws(':', {entity}, valid_from) AS id FROM {table}
+                WHERE valid_to < valid_from"
+            ),
+        ));
+        queries.push((
+            format!("{table}_overlapping_versions"),
+            format!(
+                "SELECT concat_ws(':', {entity}, valid_from) AS id FROM (
+                    SELECT {entity}, valid_from,
+                    lag(valid_to) OVER (PARTITION BY {entity} ORDER BY valid_from) AS prev_valid_to,
+                    lag(valid_from) OVER (PARTITION BY {entity} ORDER BY valid_from) AS prev_valid_from
+                    FROM {table}
+                ) versions
+                WHERE prev_valid_to IS NULL AND prev_valid_from IS NOT NULL
+                OR prev_valid_to > valid_from"
+            ),
+        ));
+    }
+
+    let mut checks = Vec::with_capacity(queries.len());
+    for (name, query) in queries {
+        let rows = diesel::sql_query(format!(
+            "SELECT count(*) OVER () AS total, id FROM ({query}) violations ORDER BY id LIMIT $1"
+        ))
+        .bind::<BigInt, _>(INTEGRITY_SAMPLE_SIZE)
+        .load::<Violation>(conn)
+        .await
+        .map_err(PostgresError::from)?;
+        checks.push(IntegrityCheck {
+            name,
+            violations: rows
+                .as_slice()
+                .first()
+                .map_or(0, |row| row.total),
+            sample: rows
+                .into_iter()
+                .map(|row| row.id)
+                .collect(),
+        });
+    }
+    Ok(IntegrityReport { checks })
+}
+
+/// Deletes all versions of contract storage, protocol state and balances that were superseded
+/// before `before`.
+///
+/// Current versions are never deleted, so the latest state stays intact, but deltas and state at
+/// versions older than `before` can no longer be served. Returns the number of deleted rows per
+/// table.
+pub async fn prune_history(
+    db_url: &str,
+    before: NaiveDateTime,
+) -> Result<Vec<(&'static str, usize)>, StorageError> {
+    let mut conn = AsyncPgConnection::establish(db_url)
+        .await
+        .map_err(|err| StorageError::Unexpected(format!("Failed to connect: {err}")))?;
+    let deleted = delete_versions_before(before, &mut conn).await?;
+    for (table, n_deleted) in &deleted {
+        info!(table, n_deleted, %before, "Pruned history");
+    }
+    let total = deleted
+        .iter()
+        .map(|(_, n)| *n as i64)
+        .sum();
+    record_operation(
+        AuditRecord::new("prune_history", total)
+            .target(format!("versions superseded before {before}")),
+        &mut conn,
+    )
+    .await?;
+    Ok(deleted)
+}
+
+/// Deletes accounts that are no longer referenced by any component, tracked contract, token or
+/// traced entry point.
+///
+/// Unreferenced accounts are marked first and only deleted once they stayed unreferenced for
+/// `grace_period`, which gives extractors time to reference a contract again, e.g. when a
+/// component is recreated. Accounts are deleted `batch_size` at a time, each batch together with
+/// its versioned data in a single transaction.
+pub async fn collect_orphaned_accounts(
+    db_url: &str,
+    grace_period: Duration,
+    batch_size: i64,
+) -> Result<OrphanedAccounts, StorageError> {
+    let mut conn = AsyncPgConnection::establish(db_url)
+        .await
+        .map_err(|err| StorageError::Unexpected(format!("Failed to connect: {err}")))?;
+    let now = Utc::now().naive_utc();
+    let (marked, revived) = mark_orphaned_accounts(now, &mut conn).await?;
+    let deleted = delete_orphaned_accounts(now - grace_period, batch_size, &mut conn).await?;
+    let res = OrphanedAccounts { marked, revived, deleted };
+    info!(?res, %grace_period, "Collected orphaned accounts");
+    Ok(res)
+}
+
+/// Sets `orphaned_since` of newly unreferenced accounts to `now` and clears it for accounts that
+/// are referenced again. Returns the number of marked and revived accounts.
+async fn mark_orphaned_accounts(
+    now: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> Result<(usize, usize), StorageError> {
+    let marked = diesel::sql_query(format!(
+        "UPDATE account SET orphaned_since = $1
+        WHERE orphaned_since IS NULL AND id NOT IN ({REFERENCED_ACCOUNTS})"
+    ))
+    .bind::<Timestamptz, _>(now)
+    .execute(conn)
+    .await
+    .map_err(PostgresError::from)?;
+    let revived = diesel::sql_query(format!(
+        "UPDATE account SET orphaned_since = NULL
+        WHERE orphaned_since IS NOT NULL AND id IN ({REFERENCED_ACCOUNTS})"
+    ))
+    .execute(conn)
+    .await
+    .map_err(PostgresError::from)?;
+    Ok((marked, revived))
+}
+
+/// Deletes accounts orphaned before `cutoff` that are still unreferenced, in batches.
+async fn delete_orphaned_accounts(
+    cutoff: NaiveDateTime,
+    batch_size: i64,
+    conn: &mut AsyncPgConnection,
+) -> Result<usize, StorageError> {
+    let mut deleted = 0;
+    loop {
+        let ids: Vec<i64> = diesel::sql_query(format!(
+            "SELECT id FROM account
+            WHERE orphaned_since < $1 AND id NOT IN ({REFERENCED_ACCOUNTS})
+            ORDER BY id LIMIT $2"
+        ))
+        .bind::<Timestamptz, _>(cutoff)
+        .bind::<BigInt, _>(batch_size)
+        .load::<AccountId>(conn)
+        .await
+        .map_err(PostgresError::from)?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
+        if ids.is_empty() {
+            return Ok(deleted);
+        }
+
+        deleted += conn
+            .transaction(|conn| {
+                async {
+                    for table in ACCOUNT_DATA_TABLES {
+                        diesel::sql_query(format!(
+                            "DELETE FROM {table} WHERE account_id = ANY($1)"
+                        ))
+                        .bind::<Array<BigInt>, _>(&ids)
+                        .execute(conn)
+                        .await
+                        .map_err(PostgresError::from)?;
+                    }
+                    let n_deleted = diesel::delete(
+                        schema::account::table.filter(schema::account::id.eq_any(&ids)),
+                    )
+                    .execute(conn)
+                    .await
+                    .map_err(PostgresError::from)?;
+                    record_operation(
+                        AuditRecord::new("collect_orphaned_accounts", n_deleted as i64)
+                            .target(format!("accounts orphaned before {cutoff}")),
+                        conn,
+                    )
+                    .await
+                    .map_err(PostgresError::from)?;
+                    Ok::<_, PostgresError>(n_deleted)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(|PostgresError(err)| err)?;
+        info!(deleted, "Deleted orphaned accounts");
+    }
+}
+
+async fn delete_versions_before(
+    before: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> Result<Vec<(&'static str, usize)>, StorageError> {
+    let contract_storage = diesel::delete(
+        schema::contract_storage::table.filter(schema::contract_storage::valid_to.lt(before)),
+    )
+    .execute(conn)
+    .await
+    .map_err(PostgresError::from)?;
+    let protocol_state = diesel::delete(
+        schema::protocol_state::table.filter(schema::protocol_state::valid_to.lt(before)),
+    )
+    .execute(conn)
+    .await
+    .map_err(PostgresError::from)?;
+    let component_balance = diesel::delete(
+        schema::component_balance::table.filter(schema::component_balance::valid_to.lt(before)),
+    )
+    .execute(conn)
+    .await
+    .map_err(PostgresError::from)?;
+    let account_balance = diesel::delete(
+        schema::account_balance::table.filter(schema::account_balance::valid_to.lt(before)),
+    )
+    .execute(conn)
+    .await
+    .map_err(PostgresError::from)?;
+
+    Ok(vec![
+        ("contract_storage", contract_storage),
+        ("protocol_state", protocol_state),
+        ("component_balance", component_balance),
+        ("account_balance", account_balance),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use tycho_common::Bytes;
+
+    use super::*;
+    use crate::postgres::{db_fixtures, MAX_TS};
+
+    async fn setup_db() -> AsyncPgConnection {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        conn
+    }
+
+    #[tokio::test]
+    async fn test_delete_versions_before() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[
+                (
+                    blk[0],
+                    1i64,
+                    "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945",
+                ),
+                (
+                    blk[1],
+                    1i64,
+                    "0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7",
+                ),
+            ],
+        )
+        .await;
+        let account = db_fixtures::insert_account(
+            &mut conn,
+            "6B175474E89094C44Da98b954EedeAC495271d0F",
+            "account0",
+            chain_id,
+            Some(txn[0]),
+        )
+        .await;
+        let ts = db_fixtures::yesterday_midnight();
+        let ts_p1 = db_fixtures::yesterday_half_past_midnight();
+        db_fixtures::insert_slots(&mut conn, account, txn[0], &ts, Some(&ts_p1), &[(0, 1, None)])
+            .await;
+        db_fixtures::insert_slots(&mut conn, account, txn[1], &ts_p1, None, &[(0, 2, Some(1))])
+            .await;
+
+        let deleted = delete_versions_before(Utc::now().naive_utc(), &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(deleted[0], ("contract_storage", 1));
+        let remaining: Vec<i64> = schema::contract_storage::table
+            .filter(schema::contract_storage::account_id.eq(account))
+            .select(schema::contract_storage::modify_tx)
+            .get_results(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(remaining, vec![txn[1]]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_orphaned_accounts() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[(blk[0], 1i64, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945")],
+        )
+        .await;
+        let (token_account, _) = db_fixtures::insert_token(
+            &mut conn,
+            chain_id,
+            "C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+            "WETH",
+            18,
+            None,
+        )
+        .await;
+        let orphan = db_fixtures::insert_account(
+            &mut conn,
+            "6B175474E89094C44Da98b954EedeAC495271d0F",
+            "account0",
+            chain_id,
+            Some(txn[0]),
+        )
+        .await;
+        let ts = db_fixtures::yesterday_midnight();
+        db_fixtures::insert_slots(&mut conn, orphan, txn[0], &ts, None, &[(0, 1, None)]).await;
+        let now = Utc::now().naive_utc();
+
+        let marked = mark_orphaned_accounts(now, &mut conn)
+            .await
+            .unwrap();
+        let deleted_in_grace_period = delete_orphaned_accounts(now, 10, &mut conn)
+            .await
+            .unwrap();
+        let deleted = delete_orphaned_accounts(now + Duration::seconds(1), 10, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(marked, (1, 0));
+        assert_eq!(deleted_in_grace_period, 0);
+        assert_eq!(deleted, 1);
+        let remaining: Vec<i64> = schema::account::table
+            .select(schema::account::id)
+            .get_results(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(remaining, vec![token_account]);
+        let n_slots: i64 = schema::contract_storage::table
+            .filter(schema::contract_storage::account_id.eq(orphan))
+            .count()
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(n_slots, 0);
+    }
+
+    #[tokio::test]
+    async fn test_collect_orphaned_accounts_keeps_contracts_of_reverted_components() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[(blk[0], 1i64, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945")],
+        )
+        .await;
+        let system_id = db_fixtures::insert_protocol_system(&mut conn, "ambient".to_owned()).await;
+        let type_id = db_fixtures::insert_protocol_type(&mut conn, "pool", None, None, None).await;
+        let account = db_fixtures::insert_account(
+            &mut conn,
+            "6B175474E89094C44Da98b954EedeAC495271d0F",
+            "account0",
+            chain_id,
+            Some(txn[0]),
+        )
+        .await;
+        let code_id =
+            db_fixtures::insert_contract_code(&mut conn, account, txn[0], Bytes::from("C0C0C0"))
+                .await;
+        let component_id = db_fixtures::insert_protocol_component(
+            &mut conn,
+            "pool_0",
+            chain_id,
+            system_id,
+            type_id,
+            txn[0],
+            None,
+            Some(vec![code_id]),
+        )
+        .await;
+        // A revert undeleting the component may leave the maximal timestamp as deletion time
+        diesel::update(schema::protocol_component::table.find(component_id))
+            .set(schema::protocol_component::deleted_at.eq(MAX_TS))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let now = Utc::now().naive_utc();
+
+        let marked = mark_orphaned_accounts(now, &mut conn)
+            .await
+            .unwrap();
+        let deleted = delete_orphaned_accounts(now + Duration::seconds(1), 10, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(marked, (0, 0));
+        assert_eq!(deleted, 0);
+        let n_code: i64 = schema::contract_code::table
+            .filter(schema::contract_code::account_id.eq(account))
+            .count()
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(n_code, 1);
+    }
+
+    #[tokio::test]
+    async fn test_collect_orphaned_accounts_keeps_tracked_contracts() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[(blk[0], 1i64, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945")],
+        )
+        .await;
+        let tracked = db_fixtures::insert_account(
+            &mut conn,
+            "6B175474E89094C44Da98b954EedeAC495271d0F",
+            "account0",
+            chain_id,
+            Some(txn[0]),
+        )
+        .await;
+        let untracked = db_fixtures::insert_account(
+            &mut conn,
+            "C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+            "account1",
+            chain_id,
+            Some(txn[0]),
+        )
+        .await;
+        // The second contract is no longer tracked
+        for (account, until_block) in [(tracked, None), (untracked, Some(20i64))] {
+            let address: Bytes = schema::account::table
+                .find(account)
+                .select(schema::account::address)
+                .first(&mut conn)
+                .await
+                .unwrap();
+            diesel::insert_into(schema::tracked_contract::table)
+                .values((
+                    schema::tracked_contract::extractor.eq("uniswap_v2"),
+                    schema::tracked_contract::chain_id.eq(chain_id),
+                    schema::tracked_contract::address.eq(address),
+                    schema::tracked_contract::from_block.eq(10),
+                    schema::tracked_contract::until_block.eq(until_block),
+                ))
+                .execute(&mut conn)
+                .await
+                .unwrap();
+        }
+
+        let marked = mark_orphaned_accounts(Utc::now().naive_utc(), &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(marked, (1, 0));
+        let orphaned: Vec<i64> = schema::account::table
+            .filter(schema::account::orphaned_since.is_not_null())
+            .select(schema::account::id)
+            .get_results(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(orphaned, vec![untracked]);
+    }
+
+    #[tokio::test]
+    async fn test_run_integrity_checks() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[(blk[0], 1i64, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945")],
+        )
+        .await;
+        let system_id = db_fixtures::insert_protocol_system(&mut conn, "ambient".to_owned()).await;
+        let type_id = db_fixtures::insert_protocol_type(&mut conn, "pool", None, None, None).await;
+        db_fixtures::insert_protocol_component(
+            &mut conn, "pool_0", chain_id, system_id, type_id, txn[0], None, None,
+        )
+        .await;
+        let account = db_fixtures::insert_account(
+            &mut conn,
+            "6B175474E89094C44Da98b954EedeAC495271d0F",
+            "account0",
+            chain_id,
+            Some(txn[0]),
+        )
+        .await;
+        // The first version stays valid past the start of the second one
+        db_fixtures::insert_slots(
+            &mut conn,
+            account,
+            txn[0],
+            &db_fixtures::yesterday_midnight(),
+            Some(&db_fixtures::yesterday_one_am()),
+            &[(0, 1, None)],
+        )
+        .await;
+        db_fixtures::insert_slots(
+            &mut conn,
+            account,
+            txn[0],
+            &db_fixtures::yesterday_half_past_midnight(),
+            None,
+            &[(0, 2, Some(1))],
+        )
+        .await;
+
+        let report = run_integrity_checks(&mut conn)
+            .await
+            .unwrap();
+
+        assert!(!report.is_ok());
+        let failed = report
+            .failed()
+            .map(|check| (check.name.as_str(), check.violations))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            failed,
+            vec![("component_without_tokens", 1), ("contract_storage_overlapping_versions", 1)]
+        );
+        assert_eq!(report.checks[0].sample, vec!["pool_0".to_string()]);
+    }
+}
@@ -18,9 +18,10 @@
 //! startup.
 //!
 //!
-//! Note: A removed enum can be ignored safely even though it might instigate a
-//! panic if an associated entity still exists in the database and retrieved
-//! with a codebase which no longer presents the enum value.
+//! Note: A removed enum member can be ignored safely. Its row is kept as a
+//! deprecated value: reading an entity that still references it fails with a
+//! `StorageError::Unsupported` instead of panicking, and every such read is
+//! counted in the `storage_deprecated_enum_reads` metric.
 //!
 //! ### Timestamps
 //!
@@ -128,32 +129,76 @@
 //! into a single transaction. This guarantees preservation of valid state
 //! throughout the application lifetime, even if the process panics during
 //! database operations.
-use std::{collections::HashMap, hash::Hash, ops::Deref, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    ops::Deref,
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
 
 use chrono::NaiveDateTime;
-use diesel::prelude::*;
+use diesel::{
+    pg::{Pg, PgQueryBuilder},
+    prelude::*,
+    query_builder::{QueryBuilder, QueryFragment},
+};
 use diesel_async::{
-    pooled_connection::{deadpool::Pool, AsyncDieselConnectionManager},
-    AsyncPgConnection, RunQueryDsl,
+    pooled_connection::{deadpool::Pool, AsyncDieselConnectionManager, ManagerConfig},
+    scoped_futures::{ScopedBoxFuture, ScopedFutureExt},
+    AsyncConnection, AsyncPgConnection, RunQueryDsl, SimpleAsyncConnection,
 };
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use tracing::{debug, info};
+use metrics::counter;
+use tracing::{debug, info, warn};
 use tycho_common::{
     models::{Chain, TxHash},
     storage::{BlockIdentifier, BlockOrTimestamp, StorageError, Version, VersionKind},
 };
 use unicode_segmentation::UnicodeSegmentation;
 
+use self::{
+    cold_storage::{ColdStorage, ColdStore},
+    id_cache::IdCache,
+};
+
+mod analytics;
+mod api_key_usage;
+mod audit;
+mod batching;
+#[cfg(feature = "bench")]
+pub mod bench;
 pub mod builder;
 pub mod cache;
 mod chain;
+pub mod cold_storage;
+pub mod component_activity;
 mod contract;
+pub mod db_growth;
 pub mod direct;
 mod entry_point;
 mod extraction_state;
+#[cfg(any(test, feature = "fault-injection"))]
+pub mod fault_injection;
+pub mod head_cache;
+mod id_cache;
+mod idempotency;
+pub mod maintenance;
+pub mod notify;
 mod orm;
 mod protocol;
+mod quarantined_message;
+#[cfg(test)]
+mod query_plan;
+mod references;
+mod repair;
+pub mod scheduler;
 mod schema;
+mod schema_check;
+mod slot_annotation;
+pub mod storage_compaction;
+mod tracked_contract;
 mod versioning;
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations/");
@@ -178,6 +223,8 @@ lazy_static! {
 pub(crate) struct ValueIdTableCache<E> {
     map_id: HashMap<E, i64>,
     map_enum: HashMap<i64, E>,
+    /// Names of rows that don't parse into `E`, e.g. members removed from the enum.
+    deprecated: HashMap<i64, String>,
 }
 
 /// Provides caching for enum and its database ID relationships.
@@ -193,12 +240,27 @@ where
     /// # Arguments
     ///
     /// * `entries` - A slice of tuples ideally obtained from a database query.
+    ///
+    /// Names that are not a valid `E` are kept as deprecated values.
     pub fn from_tuples(entries: Vec<(i64, String)>) -> Self {
-        let mut cache = Self { map_id: HashMap::new(), map_enum: HashMap::new() };
+        let mut cache =
+            Self { map_id: HashMap::new(), map_enum: HashMap::new(), deprecated: HashMap::new() };
         for (id_, name_) in entries {
-            let val = E::from_str(&name_).expect("valid enum value");
-            cache.map_id.insert(val.clone(), id_);
-            cache.map_enum.insert(id_, val);
+            match E::from_str(&name_) {
+                Ok(val) => {
+                    cache.map_id.insert(val.clone(), id_);
+                    cache.map_enum.insert(id_, val);
+                }
+                Err(err) => {
+                    warn!(
+                        id = id_,
+                        name = name_,
+                        ?err,
+                        "Unknown enum value, treating it as deprecated"
+                    );
+                    cache.deprecated.insert(id_, name_);
+                }
+            }
         }
         cache
     }
@@ -217,12 +279,18 @@ where
     }
 
     /// Retrieves the corresponding enum variant for a database ID. Returns a StorageError
-    /// if the database ID is not found in the cache.
+    /// if the database ID is not found in the cache or refers to a deprecated value.
     ///
     /// # Arguments
     ///
     /// * `id` - The database ID to lookup.
     fn try_get_value(&self, id: &i64) -> Result<E, StorageError> {
+        if let Some(name) = self.deprecated.get(id) {
+            counter!("storage_deprecated_enum_reads", "value" => name.clone()).increment(1);
+            return Err(StorageError::Unsupported(format!(
+                "Deprecated enum value `{name}` with id {id}"
+            )));
+        }
         self.map_enum
             .get(id)
             .cloned()
@@ -339,10 +407,23 @@ struct PostgresError(StorageError);
 
 impl From<diesel::result::Error> for PostgresError {
     fn from(value: diesel::result::Error) -> Self {
+        if is_statement_timeout(&value) {
+            return PostgresError(StorageError::Timeout(value.to_string()));
+        }
         PostgresError(StorageError::Unexpected(format!("DieselError: {value}")))
     }
 }
 
+/// Whether the statement was cancelled by postgres for exceeding the `statement_timeout`.
+fn is_statement_timeout(err: &diesel::result::Error) -> bool {
+    // Diesel does not expose the SQLSTATE of query cancellations, so the message is checked.
+    matches!(
+        err,
+        diesel::result::Error::DatabaseError(_, info)
+            if info.message().contains("statement timeout")
+    )
+}
+
 impl From<PostgresError> for StorageError {
     fn from(value: PostgresError) -> Self {
         value.0
@@ -403,6 +484,7 @@ fn storage_error_from_diesel(
             }
             PostgresError(StorageError::NotFound(entity.to_owned(), id.to_owned()))
         }
+        ref err if is_statement_timeout(err) => PostgresError(StorageError::Timeout(err_string)),
         _ => PostgresError(StorageError::Unexpected(err_string)),
     }
 }
@@ -442,6 +524,33 @@ async fn maybe_lookup_version_ts(
     maybe_lookup_block_ts(&version.0, conn).await
 }
 
+/// Times a query started via [`PostgresGateway::time_query`].
+pub(crate) struct QueryTimer {
+    name: &'static str,
+    /// The slow query threshold and the SQL of the query, if slow queries are logged.
+    slow: Option<(Duration, String)>,
+    start: Instant,
+}
+
+impl QueryTimer {
+    /// Logs the query if it exceeded the slow query threshold.
+    pub(crate) fn finish(self, n_rows: usize) {
+        let Some((threshold, sql)) = self.slow else {
+            return;
+        };
+        let elapsed = self.start.elapsed();
+        if elapsed > threshold {
+            warn!(
+                query = self.name,
+                duration_ms = elapsed.as_millis() as u64,
+                n_rows,
+                sql,
+                "SlowQuery"
+            );
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct PostgresGateway {
     protocol_system_id_cache: Arc<ProtocolSystemEnumCache>,
@@ -453,6 +562,17 @@ pub(crate) struct PostgresGateway {
     /// be updated once an extractor has crossed it, but has not yet crossed the new
     /// horizon (aka it should never move faster than an extractor).
     retention_horizon: NaiveDateTime,
+    /// Read-through access to offloaded contract data, if tiering is enabled.
+    cold_storage: Option<Arc<ColdStorage>>,
+    /// Statements of expensive reads run via [`PostgresGateway::run_with_deadline`] are
+    /// cancelled after this duration.
+    query_deadline: Option<Duration>,
+    /// Reads timed via [`PostgresGateway::time_query`] taking longer than this are logged.
+    slow_query_threshold: Option<Duration>,
+    /// Set while a table exceeds its budget, see [`db_growth`]. Shared by all clones.
+    skip_zero_slot_writes: Arc<AtomicBool>,
+    /// Token and transaction ids resolved by the write paths. Shared by all clones.
+    id_cache: Arc<IdCache>,
 }
 
 impl PostgresGateway {
@@ -467,9 +587,120 @@ impl PostgresGateway {
             chain_id_cache: chain_cache,
             native_token_id_cache: native_token_cache,
             retention_horizon,
+            cold_storage: None,
+            query_deadline: None,
+            slow_query_threshold: None,
+            skip_zero_slot_writes: Arc::new(AtomicBool::new(false)),
+            id_cache: Arc::new(IdCache::default()),
         }
     }
 
+    pub fn with_cold_store(mut self, store: Arc<dyn ColdStore>) -> Self {
+        self.cold_storage = Some(Arc::new(ColdStorage::new(store)));
+        self
+    }
+
+    pub fn with_query_deadline(mut self, deadline: Duration) -> Self {
+        self.query_deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_slow_query_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// Starts timing `query`, finish the returned timer once it was loaded.
+    ///
+    /// Queries taking longer than the slow query threshold are logged with their SQL. Only the
+    /// shape of the statement is logged, bind values are left out since they may be large lists
+    /// of ids.
+    pub(crate) fn time_query<Q>(&self, name: &'static str, query: &Q) -> QueryTimer
+    where
+        Q: QueryFragment<Pg>,
+    {
+        let slow = self
+            .slow_query_threshold
+            .map(|threshold| {
+                let mut sql = PgQueryBuilder::default();
+                let sql = match query.to_sql(&mut sql, &Pg) {
+                    Ok(()) => sql.finish(),
+                    Err(err) => format!("<failed to build sql: {err}>"),
+                };
+                (threshold, sql)
+            });
+        QueryTimer { name, slow, start: Instant::now() }
+    }
+
+    /// Runs `f` with the query deadline applied to each of its statements.
+    ///
+    /// The deadline is enforced by postgres through a transaction local `statement_timeout`, so
+    /// exceeding it cancels the statement server side and fails with `StorageError::Timeout`.
+    /// Without a configured deadline `f` runs as is.
+    pub(crate) async fn run_with_deadline<'a, R, F>(
+        &self,
+        conn: &mut AsyncPgConnection,
+        f: F,
+    ) -> Result<R, StorageError>
+    where
+        F: for<'r> FnOnce(
+                &'r mut AsyncPgConnection,
+            ) -> ScopedBoxFuture<'a, 'r, Result<R, StorageError>>
+            + Send
+            + 'a,
+        R: Send + 'a,
+    {
+        let Some(deadline) = self.query_deadline else {
+            return f(conn).await;
+        };
+        conn.transaction(|conn| {
+            async move {
+                conn.batch_execute(&format!(
+                    "SET LOCAL statement_timeout = {}",
+                    deadline.as_millis()
+                ))
+                .await?;
+                f(conn)
+                    .await
+                    .map_err(PostgresError::from)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(|PostgresError(err)| err)
+    }
+
+    /// Runs `f` in a read only, repeatable read transaction.
+    ///
+    /// All statements issued by `f` read from the same snapshot, so results combined from several
+    /// queries stay consistent while blocks are written concurrently.
+    pub(crate) async fn run_in_snapshot<'a, R, F>(
+        conn: &mut AsyncPgConnection,
+        f: F,
+    ) -> Result<R, StorageError>
+    where
+        F: for<'r> FnOnce(
+                &'r mut AsyncPgConnection,
+            ) -> ScopedBoxFuture<'a, 'r, Result<R, StorageError>>
+            + Send
+            + 'a,
+        R: Send + 'a,
+    {
+        conn.build_transaction()
+            .read_only()
+            .repeatable_read()
+            .run(|conn| {
+                async move {
+                    f(conn)
+                        .await
+                        .map_err(PostgresError::from)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(|PostgresError(err)| err)
+    }
+
     #[allow(dead_code)]
     pub async fn from_connection(conn: &mut AsyncPgConnection) -> Self {
         let chain_cache = ChainEnumCache::from_connection(conn)
@@ -589,6 +820,8 @@ impl PostgresGateway {
 /// # Arguments
 ///
 /// - `db_url`: A string slice that holds the URL of the database to connect to.
+/// - `statement_timeout`: If set, every pooled connection cancels statements running longer than
+///   it.
 ///
 /// # Returns
 ///
@@ -596,13 +829,41 @@ impl PostgresGateway {
 ///
 /// - `Ok`: Contains a `Pool` of `AsyncPgConnection`s if the connection was established
 ///   successfully.
-/// - `Err`: Contains a `StorageError` if there was an issue creating the connection pool.
-async fn connect(db_url: &str) -> Result<Pool<AsyncPgConnection>, StorageError> {
-    let config = AsyncDieselConnectionManager::<AsyncPgConnection>::new(db_url);
-    let pool = Pool::builder(config)
+/// - `Err`: Contains a `StorageError` if there was an issue creating the connection pool, or if the
+///   migrated database schema doesn't match `schema.rs`.
+async fn connect(
+    db_url: &str,
+    statement_timeout: Option<Duration>,
+    pool_size: Option<usize>,
+) -> Result<Pool<AsyncPgConnection>, StorageError> {
+    let mut manager_config = ManagerConfig::default();
+    if let Some(timeout) = statement_timeout {
+        manager_config.custom_setup = Box::new(move |url| {
+            let url = url.to_string();
+            Box::pin(async move {
+                let mut conn = AsyncPgConnection::establish(&url).await?;
+                conn.batch_execute(&format!("SET statement_timeout = {}", timeout.as_millis()))
+                    .await
+                    .map_err(diesel::ConnectionError::CouldntSetupConfiguration)?;
+                Ok(conn)
+            })
+        });
+    }
+    let config =
+        AsyncDieselConnectionManager::<AsyncPgConnection>::new_with_config(db_url, manager_config);
+    let mut builder = Pool::builder(config);
+    if let Some(size) = pool_size {
+        builder = builder.max_size(size);
+    }
+    let pool = builder
         .build()
         .map_err(|err| StorageError::Unexpected(err.to_string()))?;
     run_migrations(db_url);
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|err| StorageError::Unexpected(format!("Failed to retrieve connection: {err}")))?;
+    schema_check::check_schema(&mut conn).await?;
     Ok(pool)
 }
 
@@ -1425,3 +1686,20 @@ pub mod db_fixtures {
         .expect("calculating fixture component tvl failed");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_value_id_table_cache_deprecated_value() {
+        let cache = ChainEnumCache::from_tuples(vec![
+            (1, "ethereum".to_string()),
+            (2, "removed_chain".to_string()),
+        ]);
+
+        assert_eq!(cache.try_get_value(&1).unwrap(), Chain::Ethereum);
+        assert!(matches!(cache.try_get_value(&2), Err(StorageError::Unsupported(_))));
+        assert!(matches!(cache.try_get_value(&3), Err(StorageError::NotFound(_, _))));
+    }
+}
@@ -0,0 +1,83 @@
+//! Postgres notifications for services consuming changes straight from the database.
+//!
+//! If enabled via [`GatewayBuilder::set_notify_changes`](super::builder::GatewayBuilder), the
+//! write executor sends a [`ChangeNotification`] on the chain's [`channel`] within every
+//! database transaction it commits. Postgres only delivers notifications once their transaction
+//! committed, so listeners never learn about changes that were rolled back.
+//!
+//! Notifications are not persisted. Anything sent while a consumer is disconnected is lost, so
+//! consumers should resync from the database after (re)connecting. See
+//! `examples/listen_changes.rs` for a consumer.
+use std::collections::BTreeMap;
+
+use diesel::sql_types::Text;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use tycho_common::{models::Chain, storage::StorageError, Bytes};
+
+use super::PostgresError;
+
+/// Name of the channel changes of `chain` are announced on, e.g. `tycho_changes_ethereum`.
+pub fn channel(chain: &Chain) -> String {
+    format!("tycho_changes_{chain}")
+}
+
+/// Payload announcing a committed write.
+///
+/// A write may cover several blocks while syncing, the entity counts are summed over all of them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChangeNotification {
+    pub start_block: u64,
+    pub end_block: u64,
+    pub end_block_hash: Bytes,
+    /// Name of the extractor that wrote the changes, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extractor: Option<String>,
+    /// Number of written entities by write operation, e.g. `{"UpsertProtocolState": 12}`.
+    pub entities: BTreeMap<String, usize>,
+}
+
+/// Queues `notification` on the channel of `chain`, it is sent once the current transaction
+/// commits.
+pub(super) async fn notify(
+    chain: &Chain,
+    notification: &ChangeNotification,
+    conn: &mut AsyncPgConnection,
+) -> Result<(), PostgresError> {
+    let payload = serde_json::to_string(notification).map_err(|err| {
+        PostgresError(StorageError::Unexpected(format!(
+            "Failed to serialize change notification: {err}"
+        )))
+    })?;
+    diesel::sql_query("SELECT pg_notify($1, $2)")
+        .bind::<Text, _>(channel(chain))
+        .bind::<Text, _>(payload)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_change_notification_payload() {
+        let notification = ChangeNotification {
+            start_block: 1,
+            end_block: 2,
+            end_block_hash: Bytes::from("0x02"),
+            extractor: None,
+            entities: BTreeMap::from([("UpsertBlock".to_string(), 2)]),
+        };
+
+        let payload = serde_json::to_string(&notification).unwrap();
+
+        assert_eq!(channel(&Chain::Ethereum), "tycho_changes_ethereum");
+        assert_eq!(
+            payload,
+            r#"{"start_block":1,"end_block":2,"end_block_hash":"0x02","entities":{"UpsertBlock":2}}"#
+        );
+        assert_eq!(serde_json::from_str::<ChangeNotification>(&payload).unwrap(), notification);
+    }
+}
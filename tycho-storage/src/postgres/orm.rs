@@ -29,14 +29,16 @@ use tycho_common::{
 
 use super::{
     schema::{
-        account, account_balance, block, chain, component_balance, component_balance_default,
-        component_tvl, contract_code, contract_storage, contract_storage_default,
+        account, account_balance, block, block_digest, block_range_repair, chain,
+        component_balance, component_balance_default, component_event, component_tvl,
+        contract_code, contract_storage, contract_storage_default,
         debug_protocol_component_has_entry_point_tracing_params, entry_point,
         entry_point_tracing_params, entry_point_tracing_params_calls_account,
-        entry_point_tracing_result, extraction_state, protocol_component,
+        entry_point_tracing_result, extraction_state, extractor_instance, protocol_component,
         protocol_component_holds_contract, protocol_component_holds_token,
         protocol_component_uses_entry_point, protocol_state, protocol_state_default,
-        protocol_system, protocol_type, token, transaction,
+        protocol_state_index, protocol_system, protocol_type, token, token_override, token_supply,
+        transaction,
     },
     versioning::{StoredVersionedRow, VersionedRow},
     PostgresError, MAX_TS, MAX_VERSION_TS,
@@ -92,6 +94,9 @@ pub struct ExtractionState {
 
     /// Timestamp when this entry was last modified.
     pub modified_ts: NaiveDateTime,
+
+    /// Substreams packages the extractor ran, oldest first.
+    pub substreams_packages: Option<serde_json::Value>,
 }
 
 impl ExtractionState {
@@ -125,6 +130,20 @@ impl ExtractionState {
             .await
             .optional()
     }
+
+    /// Retrieves the names of the extractors of a chain together with the number of the block
+    /// their state refers to.
+    pub async fn block_numbers_by_chain(
+        chain_id: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> QueryResult<Vec<(String, i64)>> {
+        extraction_state::table
+            .inner_join(block::table)
+            .filter(extraction_state::chain_id.eq(chain_id))
+            .select((extraction_state::name, block::number))
+            .load::<(String, i64)>(conn)
+            .await
+    }
 }
 
 #[derive(Insertable)]
@@ -138,6 +157,7 @@ pub struct NewExtractionState<'a> {
     pub cursor: Option<&'a [u8]>,
     pub attributes: Option<&'a serde_json::Value>,
     pub modified_ts: NaiveDateTime,
+    pub substreams_packages: Option<serde_json::Value>,
 }
 
 #[derive(AsChangeset, Debug)]
@@ -147,6 +167,37 @@ pub struct ExtractionStateForm<'a> {
     pub attributes: Option<&'a serde_json::Value>,
     pub modified_ts: Option<NaiveDateTime>,
     pub block_id: Option<i64>,
+    pub substreams_packages: Option<serde_json::Value>,
+}
+
+/// Lease on an extractor identity held by a single running instance.
+#[derive(Identifiable, Queryable, Selectable, Debug)]
+#[diesel(table_name = extractor_instance)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ExtractorInstance {
+    pub id: i64,
+    pub name: String,
+    pub chain_id: i64,
+    pub instance_id: String,
+    pub acquired_ts: NaiveDateTime,
+    pub heartbeat_ts: NaiveDateTime,
+    pub lease_expires_ts: NaiveDateTime,
+    pub inserted_ts: NaiveDateTime,
+    pub modified_ts: NaiveDateTime,
+}
+
+/// A repair of a block range of a single protocol system.
+#[derive(Identifiable, Queryable, Selectable, Debug)]
+#[diesel(table_name = block_range_repair)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct BlockRangeRepair {
+    pub id: i64,
+    pub chain_id: i64,
+    pub protocol_system: String,
+    pub start_block: i64,
+    pub end_block: i64,
+    pub restored_ts: Option<NaiveDateTime>,
+    pub inserted_ts: NaiveDateTime,
 }
 
 #[derive(Identifiable, Queryable, Associations, Selectable)]
@@ -163,6 +214,7 @@ pub struct Block {
     pub ts: NaiveDateTime,
     pub inserted_ts: NaiveDateTime,
     pub modified_ts: NaiveDateTime,
+    pub extra: Option<serde_json::Value>,
 }
 
 impl Block {
@@ -210,6 +262,21 @@ impl Block {
             BlockIdentifier::Latest(chain) => Self::most_recent(*chain, conn).await,
         }
     }
+
+    /// The highest block of `chain` marked as finalized.
+    pub async fn most_recent_finalized(
+        chain: models::Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> QueryResult<Block> {
+        block::table
+            .inner_join(chain::table)
+            .filter(chain::name.eq(chain.to_string()))
+            .filter(block::finality_status.eq(FinalityStatus::Finalized))
+            .order(block::number.desc())
+            .select(Block::as_select())
+            .first::<Block>(conn)
+            .await
+    }
 }
 
 #[derive(Insertable)]
@@ -222,6 +289,33 @@ pub struct NewBlock {
     pub main: bool,
     pub number: i64,
     pub ts: NaiveDateTime,
+    pub extra: Option<serde_json::Value>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = block_digest)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewBlockDigest {
+    pub block_id: i64,
+    pub digest: Bytes,
+}
+
+#[derive(Debug, DbEnum, Clone, Copy, PartialEq)]
+#[ExistingTypePath = "crate::postgres::schema::sql_types::FinalityStatus"]
+pub enum FinalityStatus {
+    Pending,
+    Safe,
+    Finalized,
+}
+
+impl From<models::blockchain::FinalityStatus> for FinalityStatus {
+    fn from(value: models::blockchain::FinalityStatus) -> Self {
+        match value {
+            models::blockchain::FinalityStatus::Pending => Self::Pending,
+            models::blockchain::FinalityStatus::Safe => Self::Safe,
+            models::blockchain::FinalityStatus::Finalized => Self::Finalized,
+        }
+    }
 }
 
 #[derive(Identifiable, Queryable, Associations, Selectable, Debug)]
@@ -341,6 +435,15 @@ impl From<models::ImplementationType> for ImplementationType {
     }
 }
 
+impl From<ImplementationType> for models::ImplementationType {
+    fn from(value: ImplementationType) -> Self {
+        match value {
+            ImplementationType::Vm => Self::Vm,
+            ImplementationType::Custom => Self::Custom,
+        }
+    }
+}
+
 #[derive(Identifiable, Queryable, Selectable)]
 #[diesel(table_name = protocol_type)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -536,12 +639,17 @@ pub struct NewProtocolType {
     pub implementation: ImplementationType,
 }
 impl ProtocolType {
-    pub async fn id_by_name(name: &String, conn: &mut AsyncPgConnection) -> QueryResult<i64> {
+    /// Fetches the ids of the given protocol types, keyed by name. Unknown names are omitted.
+    pub async fn ids_by_name(
+        names: &[&str],
+        conn: &mut AsyncPgConnection,
+    ) -> QueryResult<HashMap<String, i64>> {
         protocol_type::table
-            .filter(protocol_type::name.eq(name))
-            .select(protocol_type::id)
-            .first::<i64>(conn)
+            .filter(protocol_type::name.eq_any(names))
+            .select((protocol_type::name, protocol_type::id))
+            .load::<(String, i64)>(conn)
             .await
+            .map(|rows| rows.into_iter().collect())
     }
 }
 #[derive(Identifiable, Queryable, Associations, Selectable, Clone, Debug, PartialEq)]
@@ -579,9 +687,13 @@ pub struct NewProtocolComponent {
     pub creation_tx: i64,
     pub created_at: NaiveDateTime,
     pub attributes: Option<serde_json::Value>,
+    pub pair_key: Option<String>,
 }
 
 impl NewProtocolComponent {
+    /// The pair key is derived from `tokens` here, so every inserted row has the canonical key
+    /// regardless of the order the tokens are listed in.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         external_id: &str,
         chain_id: i64,
@@ -590,6 +702,7 @@ impl NewProtocolComponent {
         creation_tx: i64,
         created_at: NaiveDateTime,
         attributes: &HashMap<String, Bytes>,
+        tokens: &[Address],
     ) -> Self {
         let attributes =
             (!attributes.is_empty()).then(|| serde_json::to_value(attributes).unwrap());
@@ -601,6 +714,7 @@ impl NewProtocolComponent {
             creation_tx,
             created_at,
             attributes,
+            pair_key: Some(models::protocol::ProtocolComponent::pair_key(tokens)),
         }
     }
 }
@@ -657,7 +771,50 @@ pub struct ProtocolState {
     pub modified_ts: NaiveDateTime,
 }
 
+type ComponentStates = diesel::helper_types::InnerJoinOn<
+    protocol_state::table,
+    protocol_component::table,
+    diesel::dsl::Eq<protocol_state::protocol_component_id, protocol_component::id>,
+>;
+
 impl ProtocolState {
+    /// States joined with their component that are valid at `version_ts`, or the current states
+    /// if `None`.
+    ///
+    /// Both version bounds are bound for latest and historical reads alike, so all of them share
+    /// one SQL text and thereby one prepared statement per connection.
+    pub(crate) fn valid_at<'a>(
+        version_ts: Option<NaiveDateTime>,
+    ) -> diesel::helper_types::IntoBoxed<'a, ComponentStates, Pg> {
+        let version_ts = version_ts.unwrap_or(*MAX_VERSION_TS);
+        protocol_state::table
+            .inner_join(
+                protocol_component::table
+                    .on(protocol_state::protocol_component_id.eq(protocol_component::id)),
+            )
+            .filter(protocol_state::valid_to.gt(version_ts))
+            .filter(protocol_state::valid_from.le(version_ts))
+            .into_boxed()
+    }
+
+    /// Components of `chain_id` with at least one state valid at `version_ts`, or a current state
+    /// if `None`.
+    pub(crate) fn components_with_state<'a>(
+        chain_id: &'a i64,
+        version_ts: Option<NaiveDateTime>,
+    ) -> protocol_component::BoxedQuery<'a, Pg> {
+        let version_ts = version_ts.unwrap_or(*MAX_VERSION_TS);
+        protocol_component::table
+            .filter(protocol_component::chain_id.eq(chain_id))
+            .filter(exists(
+                protocol_state::table
+                    .filter(protocol_state::protocol_component_id.eq(protocol_component::id))
+                    .filter(protocol_state::valid_to.gt(version_ts))
+                    .filter(protocol_state::valid_from.le(version_ts)),
+            ))
+            .into_boxed()
+    }
+
     /// Used to fetch the full state of a component at a given version, filtered by component ids.
     ///
     /// Retrieves all matching protocol states and their component id, filtered by component id.
@@ -699,22 +856,8 @@ impl ProtocolState {
         };
 
         // Main query to get ProtocolStates for the selected component external IDs
-        let mut query = protocol_state::table
-            .inner_join(
-                protocol_component::table
-                    .on(protocol_state::protocol_component_id.eq(protocol_component::id)),
-            )
+        let res = Self::valid_at(version_ts)
             .filter(protocol_component::id.eq_any(component_query))
-            .filter(protocol_state::valid_to.gt(version_ts.unwrap_or(*MAX_VERSION_TS)))
-            .into_boxed();
-
-        // Apply additional filtering by timestamp if provided
-        if let Some(ts) = version_ts {
-            query = query.filter(protocol_state::valid_from.le(ts));
-        }
-
-        // Fetch the results
-        let res = query
             .order_by(protocol_component::external_id)
             .select((Self::as_select(), protocol_component::external_id))
             .get_results::<(Self, String)>(conn)
@@ -782,22 +925,8 @@ impl ProtocolState {
         };
 
         // Main query to get ProtocolStates for the selected components
-        let mut query = protocol_state::table
-            .inner_join(
-                protocol_component::table
-                    .on(protocol_state::protocol_component_id.eq(protocol_component::id)),
-            )
+        let res = Self::valid_at(version_ts)
             .filter(protocol_component::id.eq_any(component_query))
-            .filter(protocol_state::valid_to.gt(version_ts.unwrap_or(*MAX_VERSION_TS)))
-            .into_boxed();
-
-        // Apply additional filtering by timestamp if provided
-        if let Some(ts) = version_ts {
-            query = query.filter(protocol_state::valid_from.le(ts));
-        }
-
-        // Fetch the results
-        let res = query
             .order_by(protocol_state::protocol_component_id)
             .select((Self::as_select(), protocol_component::external_id))
             .get_results::<(Self, String)>(conn)
@@ -820,49 +949,18 @@ impl ProtocolState {
         pagination_params: Option<&PaginationParams>,
         conn: &mut AsyncPgConnection,
     ) -> WithTotal<QueryResult<Vec<(Self, ComponentId)>>> {
-        let mut count_query = protocol_component::table
-            .filter(protocol_component::chain_id.eq(chain_id))
-            .filter(exists(
-                protocol_state::table
-                    .filter(protocol_state::protocol_component_id.eq(protocol_component::id))
-                    .filter(protocol_state::valid_to.gt(version_ts.unwrap_or(*MAX_VERSION_TS))),
-            ))
-            .into_boxed();
-
-        // Step 1: Get IDs of components that have associated states
-        let mut component_ids_query = protocol_component::table
-            .filter(protocol_component::chain_id.eq(chain_id))
-            .filter(exists(
-                protocol_state::table
-                    .filter(protocol_state::protocol_component_id.eq(protocol_component::id))
-                    .filter(protocol_state::valid_to.gt(version_ts.unwrap_or(*MAX_VERSION_TS))),
-            ))
+        // Step 1: Get IDs of components that have states valid at the version
+        let mut component_ids_query = Self::components_with_state(chain_id, version_ts)
             .select(protocol_component::id)
-            .order_by(protocol_component::id)
-            .into_boxed();
-
-        // Step 2: Conditionally apply the `valid_from` filter within EXISTS
-        if let Some(ts) = version_ts {
-            component_ids_query = component_ids_query.filter(exists(
-                protocol_state::table
-                    .filter(protocol_state::protocol_component_id.eq(protocol_component::id))
-                    .filter(protocol_state::valid_from.le(ts)),
-            ));
-
-            count_query = count_query.filter(exists(
-                protocol_state::table
-                    .filter(protocol_state::protocol_component_id.eq(protocol_component::id))
-                    .filter(protocol_state::valid_from.le(ts)),
-            ));
-        }
+            .order_by(protocol_component::id);
 
-        // Step 3: Apply pagination and fetch total count
+        // Step 2: Apply pagination and fetch total count
         let count: Option<i64> = if let Some(pagination) = pagination_params {
             component_ids_query = component_ids_query
                 .limit(pagination.page_size)
                 .offset(pagination.page * pagination.page_size);
             Some(
-                count_query
+                Self::components_with_state(chain_id, version_ts)
                     .count()
                     .get_result::<i64>(conn)
                     .await
@@ -889,23 +987,9 @@ impl ProtocolState {
             return WithTotal { entity: Ok(Vec::new()), total: Some(0) };
         }
 
-        // Step 4: Fetch all ProtocolStates for the selected components
-        let mut query = protocol_state::table
-            .inner_join(
-                protocol_component::table
-                    .on(protocol_state::protocol_component_id.eq(protocol_component::id)),
-            )
+        // Step 3: Fetch all ProtocolStates for the selected components
+        let res = Self::valid_at(version_ts)
             .filter(protocol_component::id.eq_any(&component_ids))
-            .filter(protocol_state::valid_to.gt(version_ts.unwrap_or(*MAX_VERSION_TS)))
-            .into_boxed();
-
-        // Apply additional filtering by timestamp if provided
-        if let Some(ts) = version_ts {
-            query = query.filter(protocol_state::valid_from.le(ts));
-        }
-
-        // Fetch the results
-        let res = query
             .order_by(protocol_state::protocol_component_id)
             .select((Self::as_select(), protocol_component::external_id))
             .get_results::<(Self, String)>(conn)
@@ -1207,6 +1291,58 @@ impl From<NewProtocolState> for NewProtocolStateLatest {
     }
 }
 
+#[derive(Insertable, Clone, Debug, PartialEq)]
+#[diesel(table_name = protocol_state_index)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewProtocolStateIndex {
+    pub protocol_component_id: i64,
+    pub attribute_name: String,
+    pub index_prefix: String,
+    pub index_key: i64,
+}
+
+/// An attribute value found through the protocol state index.
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct IndexedAttributeRow {
+    #[diesel(sql_type = sql_types::BigInt)]
+    pub index_key: i64,
+    #[diesel(sql_type = sql_types::Varchar)]
+    pub attribute_name: String,
+    #[diesel(sql_type = sql_types::Binary)]
+    pub attribute_value: Bytes,
+}
+
+#[derive(Insertable, Clone, Debug, PartialEq)]
+#[diesel(table_name = component_event)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewComponentEvent {
+    pub protocol_component_id: i64,
+    pub transaction_id: i64,
+    pub log_index: i64,
+    pub address: Address,
+    pub topics: Vec<Option<Bytes>>,
+    pub data: Bytes,
+}
+
+#[derive(Insertable, Clone, Debug, PartialEq)]
+#[diesel(table_name = token_supply)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTokenSupply {
+    pub token_id: i64,
+    pub total_supply: Balance,
+    pub ts: NaiveDateTime,
+}
+
+#[derive(Insertable, Clone, Debug, PartialEq)]
+#[diesel(table_name = token_override)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTokenOverride<'a> {
+    pub token_id: i64,
+    pub symbol: Option<&'a str>,
+    pub display_decimals: Option<i32>,
+    pub logo_uri: Option<&'a str>,
+}
+
 #[derive(Identifiable, Queryable, Associations, Selectable, Debug, PartialEq)]
 #[diesel(belongs_to(Chain))]
 #[diesel(belongs_to(Transaction, foreign_key = creation_tx))]
@@ -1469,6 +1605,7 @@ pub struct ContractCode {
     pub valid_to: Option<NaiveDateTime>,
     pub inserted_ts: NaiveDateTime,
     pub modified_ts: NaiveDateTime,
+    pub cold_ref: Option<String>,
 }
 
 impl ContractCode {
@@ -1801,6 +1938,139 @@ impl ComponentTVL {
     }
 }
 
+/// A row of the sampled component balance history query.
+#[derive(QueryableByName, Debug)]
+pub struct BalanceSampleRow {
+    #[diesel(sql_type = sql_types::Timestamptz)]
+    pub ts: NaiveDateTime,
+    #[diesel(sql_type = Double)]
+    pub open: f64,
+    #[diesel(sql_type = Double)]
+    pub high: f64,
+    #[diesel(sql_type = Double)]
+    pub low: f64,
+    #[diesel(sql_type = Double)]
+    pub close: f64,
+}
+
+impl From<BalanceSampleRow> for models::protocol::BalanceSample {
+    fn from(value: BalanceSampleRow) -> Self {
+        Self {
+            ts: value.ts,
+            open: value.open,
+            high: value.high,
+            low: value.low,
+            close: value.close,
+        }
+    }
+}
+
+/// A row of the `analytics.component_latest_state` view.
+#[derive(QueryableByName, Debug)]
+pub struct ComponentLatestStateRow {
+    #[diesel(sql_type = sql_types::Text)]
+    pub component_id: String,
+    #[diesel(sql_type = sql_types::Varchar)]
+    pub protocol_system: String,
+    #[diesel(sql_type = sql_types::Varchar)]
+    pub attribute_name: String,
+    #[diesel(sql_type = sql_types::Binary)]
+    pub attribute_value: Bytes,
+    #[diesel(sql_type = sql_types::Timestamptz)]
+    pub modified_ts: NaiveDateTime,
+}
+
+impl From<ComponentLatestStateRow> for models::protocol::ComponentLatestState {
+    fn from(value: ComponentLatestStateRow) -> Self {
+        Self {
+            component_id: value.component_id,
+            protocol_system: value.protocol_system,
+            attribute_name: value.attribute_name,
+            attribute_value: value.attribute_value,
+            modified_ts: value.modified_ts,
+        }
+    }
+}
+
+/// A row of the `analytics.component_latest_balance` view.
+#[derive(QueryableByName, Debug)]
+pub struct ComponentLatestBalanceRow {
+    #[diesel(sql_type = sql_types::Text)]
+    pub component_id: String,
+    #[diesel(sql_type = sql_types::Varchar)]
+    pub protocol_system: String,
+    #[diesel(sql_type = sql_types::Binary)]
+    pub token: Address,
+    #[diesel(sql_type = sql_types::Varchar)]
+    pub symbol: String,
+    #[diesel(sql_type = sql_types::Integer)]
+    pub decimals: i32,
+    #[diesel(sql_type = sql_types::Binary)]
+    pub balance: Balance,
+    #[diesel(sql_type = Double)]
+    pub balance_float: f64,
+    #[diesel(sql_type = sql_types::Timestamptz)]
+    pub modified_ts: NaiveDateTime,
+}
+
+impl From<ComponentLatestBalanceRow> for models::protocol::ComponentLatestBalance {
+    fn from(value: ComponentLatestBalanceRow) -> Self {
+        Self {
+            component_id: value.component_id,
+            protocol_system: value.protocol_system,
+            token: value.token,
+            symbol: value.symbol,
+            decimals: value.decimals as u32,
+            balance: value.balance,
+            balance_float: value.balance_float,
+            modified_ts: value.modified_ts,
+        }
+    }
+}
+
+/// A component whose activity flag was toggled by the activity analysis.
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct ComponentActivityRow {
+    #[diesel(sql_type = sql_types::Varchar)]
+    pub external_id: String,
+    /// Set if the component became inactive, `None` if it became active again.
+    #[diesel(sql_type = sql_types::Nullable<sql_types::Timestamptz>)]
+    pub inactive_since: Option<NaiveDateTime>,
+}
+
+/// A row of the protocol system overview query.
+#[derive(QueryableByName, Debug)]
+pub struct ProtocolSystemSummaryRow {
+    #[diesel(sql_type = sql_types::Varchar)]
+    pub name: String,
+    #[diesel(sql_type = sql_types::BigInt)]
+    pub component_count: i64,
+    #[diesel(sql_type = sql_types::Array<crate::postgres::schema::sql_types::ImplementationType>)]
+    pub implementation_types: Vec<ImplementationType>,
+    #[diesel(sql_type = sql_types::Nullable<sql_types::BigInt>)]
+    pub last_updated_block: Option<i64>,
+    #[diesel(sql_type = sql_types::Nullable<sql_types::Timestamptz>)]
+    pub last_updated_ts: Option<NaiveDateTime>,
+}
+
+impl From<ProtocolSystemSummaryRow> for models::protocol::ProtocolSystemSummary {
+    fn from(value: ProtocolSystemSummaryRow) -> Self {
+        Self {
+            name: value.name,
+            component_count: value.component_count as u64,
+            implementation_types: value
+                .implementation_types
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            last_updated_block: value
+                .last_updated_block
+                .map(|number| number as u64),
+            last_updated_ts: value.last_updated_ts,
+        }
+    }
+}
+
 #[derive(Debug, DbEnum, Clone, PartialEq, Eq, Hash)]
 #[ExistingTypePath = "crate::postgres::schema::sql_types::EntryPointTracingType"]
 pub enum EntryPointTracingType {
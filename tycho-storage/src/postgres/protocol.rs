@@ -1,8 +1,13 @@
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::RangeInclusive,
+    slice,
+};
 
 use chrono::{NaiveDateTime, Utc};
 use diesel::{
     prelude::*,
+    sql_types::{Array, BigInt, Timestamptz, Varchar},
     upsert::{excluded, on_constraint},
 };
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
@@ -10,25 +15,73 @@ use itertools::Itertools;
 use tracing::{error, instrument, trace, warn, Level};
 use tycho_common::{
     models::{
+        component_id_candidates,
+        contract::Account,
         protocol::{
-            ComponentBalance, ProtocolComponent, ProtocolComponentState,
-            ProtocolComponentStateDelta, QualityRange,
+            BalanceSample, ComponentBalance, ComponentEvent, ProtocolComponent,
+            ProtocolComponentState, ProtocolComponentStateDelta, ProtocolComponentWithContracts,
+            ProtocolDeployment, ProtocolStatesDelta, ProtocolSystemSummary, QualityRange,
+            SampleInterval,
         },
-        token::Token,
-        Address, Balance, Chain, ChangeType, ComponentId, FinancialType, ImplementationType,
-        PaginationParams, ProtocolType, StoreVal, TxHash,
+        token::{Token, TokenOverride},
+        Address, AttrStoreKey, Balance, Chain, ChangeType, ComponentCursor, ComponentId,
+        FinancialType, ImplementationType, PaginationParams, ProtocolType, StoreVal, TxHash,
     },
-    storage::{BlockOrTimestamp, StorageError, Version, WithTotal},
+    storage::{BlockIdentifier, BlockOrTimestamp, StorageError, Version, WithTotal},
     Bytes,
 };
 
 use super::{
+    audit::{record_operation, AuditRecord},
+    batching::{batches, param_chunks},
     maybe_lookup_block_ts, maybe_lookup_version_ts, orm, schema, storage_error_from_diesel,
     truncate_to_byte_limit,
     versioning::{apply_partitioned_versioning, VersioningEntry},
     PostgresError, PostgresGateway, WithOrdinal, WithTxHash, MAX_TS, MAX_VERSION_TS,
 };
 
+/// Attribute schema entry listing the attribute name prefixes that are indexed numerically.
+///
+/// A protocol type with `{"indexed_attributes": ["ticks/"]}` indexes e.g.
+/// `ticks/-887220/net-liquidity` under the key `-887220`: the segment following the prefix, up to
+/// the next `/`, must be an integer.
+pub const INDEXED_ATTRIBUTES_KEY: &str = "indexed_attributes";
+
+/// Expands component ids received as filters to the ids they match, see
+/// [`component_id_candidates`].
+fn component_id_filter(ids: Option<&[&str]>) -> Option<Vec<ComponentId>> {
+    ids.map(|ids| {
+        ids.iter()
+            .flat_map(|id| component_id_candidates(id))
+            .unique()
+            .collect()
+    })
+}
+
+fn indexed_prefixes(attribute_schema: &serde_json::Value) -> Vec<String> {
+    attribute_schema
+        .get(INDEXED_ATTRIBUTES_KEY)
+        .and_then(serde_json::Value::as_array)
+        .map(|prefixes| {
+            prefixes
+                .iter()
+                .filter_map(|p| p.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the first prefix matching `attribute` together with the integer key following it.
+fn parse_index_key<'a>(attribute: &str, prefixes: &'a [String]) -> Option<(&'a str, i64)> {
+    prefixes.iter().find_map(|prefix| {
+        let key = attribute
+            .strip_prefix(prefix.as_str())?
+            .split('/')
+            .next()?;
+        Some((prefix.as_str(), key.parse().ok()?))
+    })
+}
+
 // Private methods
 impl PostgresGateway {
     /// # Decoding ProtocolStates from database results.
@@ -134,6 +187,68 @@ impl PostgresGateway {
         }
     }
 
+    /// Adds the given attributes to the protocol state index if their component's protocol type
+    /// declares them as indexed.
+    ///
+    /// Index entries are never removed here: range queries join the versioned state, so an
+    /// attribute that got deleted (or reverted) no longer shows up in their results.
+    async fn _update_state_index(
+        &self,
+        updated: &[(i64, &str)],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        if updated.is_empty() {
+            return Ok(());
+        }
+        let component_ids = updated
+            .iter()
+            .map(|(id, _)| *id)
+            .unique()
+            .collect::<Vec<_>>();
+        let prefixes: HashMap<i64, Vec<String>> = schema::protocol_component::table
+            .inner_join(schema::protocol_type::table)
+            .filter(schema::protocol_component::id.eq_any(&component_ids))
+            .filter(schema::protocol_type::attribute_schema.is_not_null())
+            .select((
+                schema::protocol_component::id,
+                schema::protocol_type::attribute_schema.assume_not_null(),
+            ))
+            .load::<(i64, serde_json::Value)>(conn)
+            .await
+            .map_err(PostgresError::from)?
+            .into_iter()
+            .map(|(id, attribute_schema)| (id, indexed_prefixes(&attribute_schema)))
+            .filter(|(_, prefixes)| !prefixes.is_empty())
+            .collect();
+        if prefixes.is_empty() {
+            return Ok(());
+        }
+
+        let entries = updated
+            .iter()
+            .filter_map(|(component_id, attribute)| {
+                let (prefix, key) = parse_index_key(attribute, prefixes.get(component_id)?)?;
+                Some(orm::NewProtocolStateIndex {
+                    protocol_component_id: *component_id,
+                    attribute_name: attribute.to_string(),
+                    index_prefix: prefix.to_string(),
+                    index_key: key,
+                })
+            })
+            .unique_by(|e| (e.protocol_component_id, e.attribute_name.clone()))
+            .collect::<Vec<_>>();
+        if !entries.is_empty() {
+            trace!(n_entries = entries.len(), "Extending protocol state index!");
+            diesel::insert_into(schema::protocol_state_index::table)
+                .values(&entries)
+                .on_conflict_do_nothing()
+                .execute(conn)
+                .await
+                .map_err(PostgresError::from)?;
+        }
+        Ok(())
+    }
+
     #[instrument(level = Level::DEBUG, skip(self, ids, conn))]
     pub async fn get_protocol_components(
         &self,
@@ -145,6 +260,11 @@ impl PostgresGateway {
         conn: &mut AsyncPgConnection,
     ) -> Result<WithTotal<Vec<ProtocolComponent>>, StorageError> {
         use super::schema::{protocol_component::dsl::*, transaction::dsl::*};
+        let ids = component_id_filter(ids);
+        let ids: Option<Vec<&str>> = ids
+            .as_ref()
+            .map(|ids| ids.iter().map(String::as_str).collect());
+        let ids = ids.as_deref();
         let chain_id_value = self.get_chain_id(chain)?;
 
         let mut count_query = protocol_component
@@ -225,10 +345,97 @@ impl PostgresGateway {
                 .offset(pagination.offset());
         }
 
+        let timer = self.time_query("get_protocol_components", &query);
         let orm_protocol_components = query
             .load::<(orm::ProtocolComponent, TxHash)>(conn)
             .await
-            .map_err(PostgresError::from)?
+            .map_err(PostgresError::from)?;
+        timer.finish(orm_protocol_components.len());
+        let orm_protocol_components = orm_protocol_components
+            .into_iter()
+            .map(|(pc, txh)| (pc, Some(txh)))
+            .collect();
+
+        let res = self
+            .build_protocol_components(orm_protocol_components, chain, conn)
+            .await?;
+
+        Ok(WithTotal { entity: res, total: Some(count) })
+    }
+
+    /// Keyset paginated variant of [`Self::get_protocol_components`] pinned to a block.
+    ///
+    /// Components are ordered by their external id, so a page can resume after the last id of
+    /// the previous one, and filtered by the block of their creation transaction.
+    #[instrument(level = Level::DEBUG, skip(self, ids, conn))]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_protocol_components_after(
+        &self,
+        chain: &Chain,
+        system: Option<String>,
+        ids: Option<&[&str]>,
+        min_tvl: Option<f64>,
+        cursor: &ComponentCursor,
+        page_size: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<WithTotal<Vec<ProtocolComponent>>, StorageError> {
+        use super::schema::{block, protocol_component::dsl::*, transaction};
+        let ids = component_id_filter(ids);
+        let chain_id_value = self.get_chain_id(chain)?;
+        let protocol_system = system
+            .as_ref()
+            .map(|ps| self.get_protocol_system_id(ps))
+            .transpose()?;
+        let block_number = cursor.block_number as i64;
+
+        let mut count_query = protocol_component
+            .inner_join(transaction::table.on(creation_tx.eq(transaction::id)))
+            .inner_join(block::table.on(transaction::block_id.eq(block::id)))
+            .left_join(schema::component_tvl::table)
+            .filter(chain_id.eq(chain_id_value))
+            .filter(block::number.le(block_number))
+            .into_boxed();
+        let mut query = protocol_component
+            .inner_join(transaction::table.on(creation_tx.eq(transaction::id)))
+            .inner_join(block::table.on(transaction::block_id.eq(block::id)))
+            .left_join(schema::component_tvl::table)
+            .filter(chain_id.eq(chain_id_value))
+            .filter(block::number.le(block_number))
+            .select((orm::ProtocolComponent::as_select(), transaction::hash))
+            .into_boxed();
+        if let Some(ps) = protocol_system {
+            count_query = count_query.filter(protocol_system_id.eq(ps));
+            query = query.filter(protocol_system_id.eq(ps));
+        }
+        if let Some(external_ids) = &ids {
+            count_query = count_query.filter(external_id.eq_any(external_ids));
+            query = query.filter(external_id.eq_any(external_ids));
+        }
+        if let Some(thr) = min_tvl {
+            count_query = count_query.filter(schema::component_tvl::tvl.gt(thr));
+            query = query.filter(schema::component_tvl::tvl.gt(thr));
+        }
+
+        let count = count_query
+            .count()
+            .get_result::<i64>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+
+        if let Some(after) = &cursor.after {
+            query = query.filter(external_id.gt(after));
+        }
+        let query = query
+            .order_by(external_id)
+            .limit(page_size);
+
+        let timer = self.time_query("get_protocol_components_after", &query);
+        let orm_protocol_components = query
+            .load::<(orm::ProtocolComponent, TxHash)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        timer.finish(orm_protocol_components.len());
+        let orm_protocol_components = orm_protocol_components
             .into_iter()
             .map(|(pc, txh)| (pc, Some(txh)))
             .collect();
@@ -240,6 +447,62 @@ impl PostgresGateway {
         Ok(WithTotal { entity: res, total: Some(count) })
     }
 
+    /// Retrieves protocol components together with the state of all contracts they hold.
+    ///
+    /// To read components and contracts from the same snapshot, the caller should run this within
+    /// a repeatable read transaction.
+    #[instrument(level = Level::DEBUG, skip(self, ids, conn))]
+    pub async fn get_components_with_contracts(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        version: Option<&Version>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<ProtocolComponentWithContracts>, StorageError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let components = self
+            .get_protocol_components(chain, None, Some(ids), None, None, conn)
+            .await?
+            .entity;
+        let addresses = components
+            .iter()
+            .flat_map(|c| c.contract_addresses.iter().cloned())
+            .unique()
+            .collect::<Vec<_>>();
+        let contracts = if addresses.is_empty() {
+            Vec::new()
+        } else {
+            self.get_contracts(chain, Some(&addresses), version, true, None, None, conn)
+                .await?
+                .entity
+        };
+
+        let contracts_by_address: HashMap<Address, Account> = contracts
+            .into_iter()
+            .map(|account| (account.address.clone(), account))
+            .collect();
+        Ok(components
+            .into_iter()
+            .map(|component| {
+                let contracts = component
+                    .contract_addresses
+                    .iter()
+                    .filter_map(|address| {
+                        let account = contracts_by_address.get(address).cloned();
+                        if account.is_none() {
+                            warn!(component_id = %component.id, %address, "Contract of component not found");
+                        }
+                        account
+                    })
+                    .collect();
+                ProtocolComponentWithContracts { component, contracts }
+            })
+            .collect())
+    }
+
     #[instrument(level = Level::DEBUG, skip(self, orm_protocol_components, conn))]
     async fn build_protocol_components(
         &self,
@@ -266,6 +529,8 @@ impl PostgresGateway {
                     schema::protocol_component_holds_token::protocol_component_id
                         .eq_any(protocol_component_ids.clone()),
                 )
+                // Tokens are listed in their canonical order, by address
+                .order_by(schema::account::address)
                 .load::<(i64, Address)>(conn)
                 .await
                 .map_err(PostgresError::from)?;
@@ -390,6 +655,12 @@ impl PostgresGateway {
             .filter(schema::component_balance::balance_float.ge(min_balance.unwrap_or(0f64)))
             .filter(schema::component_balance::valid_to.eq(MAX_TS))
             .filter(schema::component_balance::token_id.eq_any(token_ids.keys()))
+            // Largest balance first, so the first owner seen per token is the one to keep.
+            .order_by(
+                schema::component_balance::balance_numeric
+                    .desc()
+                    .nulls_last(),
+            )
             .get_results::<(i64, String, Bytes)>(conn)
             .await
             .map_err(PostgresError::from)?
@@ -397,14 +668,7 @@ impl PostgresGateway {
             .for_each(|(tid, cid, bal)| {
                 if let Some(address) = token_ids.get(&tid) {
                     res.entry(address.clone())
-                        .and_modify(|v| {
-                            // Bytes uses lexicographical order which in case lengths
-                            // are equal, is equivalent to big-endian numerical order.
-                            if v.1.len() == bal.len() && v.1 < bal {
-                                *v = (cid.clone(), bal.clone());
-                            }
-                        })
-                        .or_insert_with(|| (cid, bal));
+                        .or_insert((cid, bal));
                 }
             });
 
@@ -418,26 +682,42 @@ impl PostgresGateway {
     ) -> Result<(), StorageError> {
         use super::schema::{
             account::dsl::*, protocol_component::dsl::*, protocol_component_holds_contract::dsl::*,
-            protocol_component_holds_token::dsl::*, token::dsl::*,
+            protocol_component_holds_token::dsl::*,
         };
         let mut values: Vec<orm::NewProtocolComponent> = Vec::with_capacity(new.len());
         let tx_hashes: Vec<TxHash> = new
             .iter()
             .map(|pc| pc.creation_tx.clone())
             .collect();
-        let tx_hash_id_mapping: HashMap<TxHash, i64> =
-            orm::Transaction::ids_by_hash(&tx_hashes, conn)
-                .await
-                .map_err(PostgresError::from)?;
-        let pt_id = orm::ProtocolType::id_by_name(&new[0].protocol_type_name, conn)
+        let tx_hash_id_mapping: HashMap<TxHash, i64> = self
+            .get_transaction_ids_and_ts(&tx_hashes, conn)
+            .await?
+            .into_iter()
+            .map(|(hash, (db_id, _, _))| (hash, db_id))
+            .collect();
+        // Extractors indexing several pool types send components of different types in one batch.
+        let pt_names: Vec<&str> = new
+            .iter()
+            .map(|pc| pc.protocol_type_name.as_str())
+            .unique()
+            .collect();
+        let pt_ids = orm::ProtocolType::ids_by_name(&pt_names, conn)
             .await
-            .map_err(|err| {
-                storage_error_from_diesel(err, "ProtocolType", &new[0].protocol_type_name, None)
-            })?;
+            .map_err(PostgresError::from)?;
         for pc in new {
+            pc.validate_id()
+                .map_err(|err| StorageError::Unexpected(err.to_string()))?;
             let txh = tx_hash_id_mapping
                 .get::<TxHash>(&pc.creation_tx.clone())
                 .ok_or(StorageError::DecodeError("TxHash not found".to_string()))?;
+            let pt_id = *pt_ids
+                .get(&pc.protocol_type_name)
+                .ok_or_else(|| {
+                    StorageError::NotFound(
+                        "ProtocolType".to_string(),
+                        pc.protocol_type_name.clone(),
+                    )
+                })?;
 
             let new_pc = orm::NewProtocolComponent::new(
                 &pc.id,
@@ -447,6 +727,7 @@ impl PostgresGateway {
                 *txh,
                 pc.created_at,
                 &pc.static_attributes,
+                &pc.tokens,
             );
             values.push(new_pc);
         }
@@ -514,16 +795,9 @@ impl PostgresGateway {
             })
             .collect::<Vec<(i64, Address)>>();
 
-        let token_add_by_id: HashMap<Address, i64> = token
-            .inner_join(account)
-            .select((schema::account::address, schema::token::id))
-            .filter(schema::account::address.eq_any(token_addresses))
-            .into_boxed()
-            .load::<(Address, i64)>(conn)
-            .await
-            .map_err(|err| storage_error_from_diesel(err, "Token", "Several Chains", None))?
-            .into_iter()
-            .collect();
+        let token_add_by_id = self
+            .get_token_ids(&token_addresses, conn)
+            .await?;
 
         let protocol_component_token_junction: Result<
             Vec<orm::NewProtocolComponentHoldsToken>,
@@ -624,11 +898,20 @@ impl PostgresGateway {
             .map(|c| c.id.to_string())
             .collect();
 
-        diesel::update(protocol_component.filter(external_id.eq_any(ids_to_delete)))
+        let deleted = diesel::update(protocol_component.filter(external_id.eq_any(&ids_to_delete)))
             .set(deleted_at.eq(block_ts))
             .execute(conn)
             .await
             .map_err(PostgresError::from)?;
+        if let Some(first) = to_delete.first() {
+            record_operation(
+                AuditRecord::new("delete_protocol_components", deleted as i64)
+                    .chain(&first.chain)
+                    .target(ids_to_delete.join(", ")),
+                conn,
+            )
+            .await?;
+        }
         Ok(())
     }
 
@@ -691,6 +974,11 @@ impl PostgresGateway {
         pagination_params: Option<&PaginationParams>,
         conn: &mut AsyncPgConnection,
     ) -> Result<WithTotal<Vec<ProtocolComponentState>>, StorageError> {
+        let ids = component_id_filter(ids);
+        let ids: Option<Vec<&str>> = ids
+            .as_ref()
+            .map(|ids| ids.iter().map(String::as_str).collect());
+        let ids = ids.as_deref();
         let chain_db_id = self.get_chain_id(chain)?;
         let version_ts = match &at {
             Some(version) => Some(maybe_lookup_version_ts(version, conn).await?),
@@ -764,19 +1052,9 @@ impl PostgresGateway {
             .map(|(tx, delta)| WithTxHash { entity: delta, tx: Some(tx.to_owned()) })
             .collect::<Vec<_>>();
 
-        let txns: HashMap<TxHash, (i64, i64, NaiveDateTime)> =
-            orm::Transaction::ids_and_ts_by_hash(
-                new.iter()
-                    .filter_map(|u| u.tx.as_ref())
-                    .collect::<Vec<&TxHash>>()
-                    .as_slice(),
-                conn,
-            )
-            .await
-            .map_err(PostgresError::from)?
-            .into_iter()
-            .map(|(id, hash, index, ts)| (hash, (id, index, ts)))
-            .collect();
+        let txns = self
+            .get_transaction_ids_and_ts(new.iter().filter_map(|u| u.tx.as_ref()), conn)
+            .await?;
 
         let components: HashMap<String, i64> = orm::ProtocolComponent::ids_by_external_ids(
             new.iter()
@@ -793,6 +1071,7 @@ impl PostgresGateway {
         .collect();
 
         let mut state_data = Vec::new();
+        let mut updated = Vec::new();
         for state in new {
             let tx = state
                 .tx
@@ -829,6 +1108,14 @@ impl PostgresGateway {
                     }),
             );
 
+            updated.extend(
+                state
+                    .entity
+                    .updated_attributes
+                    .keys()
+                    .map(|attribute| (component_db_id, attribute.as_str())),
+            );
+
             state_data.extend(
                 state
                     .deleted_attributes
@@ -853,41 +1140,45 @@ impl PostgresGateway {
             let (latest, to_archive, to_delete) =
                 apply_partitioned_versioning(&sorted, self.retention_horizon, conn).await?;
             trace!(records=?&to_archive, "Inserting archival records!");
-            diesel::insert_into(schema::protocol_state::table)
-                .values(&to_archive)
-                .execute(conn)
-                .await
-                .map_err(PostgresError::from)?;
+            for chunk in batches(&to_archive) {
+                diesel::insert_into(schema::protocol_state::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .await
+                    .map_err(PostgresError::from)?;
+            }
             let latest: Vec<orm::NewProtocolStateLatest> = latest
                 .into_iter()
                 .map(Into::into)
                 .collect();
             trace!(new_state=?&latest, "Updating active state!");
-            diesel::insert_into(schema::protocol_state_default::table)
-                .values(&latest)
-                .on_conflict(on_constraint("protocol_state_default_unique_pk"))
-                .do_update()
-                .set((
-                    schema::protocol_state_default::attribute_value
-                        .eq(excluded(schema::protocol_state_default::attribute_value)),
-                    schema::protocol_state_default::previous_value
-                        .eq(excluded(schema::protocol_state_default::previous_value)),
-                    schema::protocol_state_default::modify_tx
-                        .eq(excluded(schema::protocol_state_default::modify_tx)),
-                    schema::protocol_state_default::valid_from
-                        .eq(excluded(schema::protocol_state_default::valid_from)),
-                ))
-                .execute(conn)
-                .await
-                .map_err(PostgresError::from)?;
-            // remove deleted attributes from the default table
-            if !to_delete.is_empty() {
+            for chunk in batches(&latest) {
+                diesel::insert_into(schema::protocol_state_default::table)
+                    .values(chunk)
+                    .on_conflict(on_constraint("protocol_state_default_unique_pk"))
+                    .do_update()
+                    .set((
+                        schema::protocol_state_default::attribute_value
+                            .eq(excluded(schema::protocol_state_default::attribute_value)),
+                        schema::protocol_state_default::previous_value
+                            .eq(excluded(schema::protocol_state_default::previous_value)),
+                        schema::protocol_state_default::modify_tx
+                            .eq(excluded(schema::protocol_state_default::modify_tx)),
+                        schema::protocol_state_default::valid_from
+                            .eq(excluded(schema::protocol_state_default::valid_from)),
+                    ))
+                    .execute(conn)
+                    .await
+                    .map_err(PostgresError::from)?;
+            }
+            // remove deleted attributes from the default table, each binds two parameters
+            for chunk in param_chunks(&to_delete, 2) {
                 let mut delete_query =
                     diesel::delete(schema::protocol_state_default::table).into_boxed();
-                for (component_id, attr_name) in to_delete {
+                for (component_id, attr_name) in chunk {
                     delete_query = delete_query.or_filter(
                         schema::protocol_state_default::protocol_component_id
-                            .eq(component_id)
+                            .eq(*component_id)
                             .and(schema::protocol_state_default::attribute_name.eq(attr_name)),
                     );
                 }
@@ -897,6 +1188,8 @@ impl PostgresGateway {
                     .map_err(PostgresError::from)?;
             }
         }
+        self._update_state_index(&updated, conn)
+            .await?;
         Ok(())
     }
 
@@ -965,11 +1258,13 @@ impl PostgresGateway {
                 .offset(pagination.offset());
         }
 
+        let query = query.order(schema::token::id.asc());
+        let timer = self.time_query("get_tokens", &query);
         let results = query
-            .order(schema::token::id.asc())
             .load::<(orm::Token, Address)>(conn)
             .await
             .map_err(|err| storage_error_from_diesel(err, "Token", &chain.to_string(), None))?;
+        timer.finish(results.len());
 
         let tokens: Vec<Token> = results
             .into_iter()
@@ -1132,35 +1427,23 @@ impl PostgresGateway {
         chain: &Chain,
         conn: &mut AsyncPgConnection,
     ) -> Result<(), StorageError> {
-        use super::schema::{account::dsl::*, token::dsl::*};
-
         let chain_db_id = self.get_chain_id(chain)?;
         let token_addresses: Vec<Address> = component_balances
             .iter()
             .map(|component_balance| component_balance.token.clone())
             .collect();
-        let token_ids: HashMap<Address, i64> = token
-            .inner_join(account)
-            .select((schema::account::address, schema::token::id))
-            .filter(schema::account::address.eq_any(&token_addresses))
-            .load::<(Address, i64)>(conn)
-            .await
-            .map_err(PostgresError::from)?
-            .into_iter()
-            .collect();
+        let token_ids = self
+            .get_token_ids(&token_addresses, conn)
+            .await?;
 
-        let modify_txs = component_balances
-            .iter()
-            .map(|component_balance| component_balance.modify_tx.clone())
-            .collect::<Vec<TxHash>>();
-        let txn_hashes = modify_txs.iter().collect::<Vec<_>>();
-        let transaction_ids_and_ts: HashMap<TxHash, (i64, i64, NaiveDateTime)> =
-            orm::Transaction::ids_and_ts_by_hash(txn_hashes.as_ref(), conn)
-                .await
-                .map_err(PostgresError::from)?
-                .into_iter()
-                .map(|(db_id, hash, index, ts)| (hash, (db_id, index, ts)))
-                .collect();
+        let transaction_ids_and_ts = self
+            .get_transaction_ids_and_ts(
+                component_balances
+                    .iter()
+                    .map(|component_balance| &component_balance.modify_tx),
+                conn,
+            )
+            .await?;
 
         let external_ids: Vec<&str> = component_balances
             .iter()
@@ -1216,35 +1499,43 @@ impl PostgresGateway {
             let (latest, to_archive, _) =
                 apply_partitioned_versioning(&sorted, self.retention_horizon, conn).await?;
 
-            diesel::insert_into(schema::component_balance::table)
-                .values(&to_archive)
-                .execute(conn)
-                .await
-                .map_err(|err| storage_error_from_diesel(err, "ComponentBalance", "batch", None))?;
+            for chunk in batches(&to_archive) {
+                diesel::insert_into(schema::component_balance::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .await
+                    .map_err(|err| {
+                        storage_error_from_diesel(err, "ComponentBalance", "batch", None)
+                    })?;
+            }
 
             let latest = latest
                 .into_iter()
                 .map(orm::NewComponentBalanceLatest::from)
                 .collect::<Vec<_>>();
-            diesel::insert_into(schema::component_balance_default::table)
-                .values(&latest)
-                .on_conflict(on_constraint("component_balance_default_unique_pk"))
-                .do_update()
-                .set((
-                    schema::component_balance_default::new_balance
-                        .eq(excluded(schema::component_balance_default::new_balance)),
-                    schema::component_balance_default::balance_float
-                        .eq(excluded(schema::component_balance_default::balance_float)),
-                    schema::component_balance_default::previous_value
-                        .eq(excluded(schema::component_balance_default::previous_value)),
-                    schema::component_balance_default::modify_tx
-                        .eq(excluded(schema::component_balance_default::modify_tx)),
-                    schema::component_balance_default::valid_from
-                        .eq(excluded(schema::component_balance_default::valid_from)),
-                ))
-                .execute(conn)
-                .await
-                .map_err(|err| storage_error_from_diesel(err, "ComponentBalance", "batch", None))?;
+            for chunk in batches(&latest) {
+                diesel::insert_into(schema::component_balance_default::table)
+                    .values(chunk)
+                    .on_conflict(on_constraint("component_balance_default_unique_pk"))
+                    .do_update()
+                    .set((
+                        schema::component_balance_default::new_balance
+                            .eq(excluded(schema::component_balance_default::new_balance)),
+                        schema::component_balance_default::balance_float
+                            .eq(excluded(schema::component_balance_default::balance_float)),
+                        schema::component_balance_default::previous_value
+                            .eq(excluded(schema::component_balance_default::previous_value)),
+                        schema::component_balance_default::modify_tx
+                            .eq(excluded(schema::component_balance_default::modify_tx)),
+                        schema::component_balance_default::valid_from
+                            .eq(excluded(schema::component_balance_default::valid_from)),
+                    ))
+                    .execute(conn)
+                    .await
+                    .map_err(|err| {
+                        storage_error_from_diesel(err, "ComponentBalance", "batch", None)
+                    })?;
+            }
         }
         Ok(())
     }
@@ -1367,6 +1658,11 @@ impl PostgresGateway {
         // NOTE: the returned ComponentBalances have a default value for tx_hash as it is assumed
         // the caller does not need them. It is planned for `modify_tx` to be removed from
         // the ComponentBalance
+        let ids = component_id_filter(ids);
+        let ids: Option<Vec<&str>> = ids
+            .as_ref()
+            .map(|ids| ids.iter().map(String::as_str).collect());
+        let ids = ids.as_deref();
 
         let version_ts = match &at {
             Some(version) => Some(maybe_lookup_version_ts(version, conn).await?),
@@ -1479,47 +1775,530 @@ impl PostgresGateway {
         Ok(balances)
     }
 
+    /// Samples the balance of a component's token at fixed intervals.
+    ///
+    /// Each interval is resolved with lateral subqueries that walk the
+    /// `(protocol_component_id, token_id, valid_to)` index, so the cost grows with the number of
+    /// samples and the versions inside each interval rather than with the whole history.
+    #[allow(clippy::too_many_arguments)]
     #[instrument(level = Level::DEBUG, skip(self, conn))]
-    pub async fn get_protocol_states_delta(
+    pub async fn get_balance_history(
         &self,
         chain: &Chain,
-        start_version: Option<&BlockOrTimestamp>,
-        end_version: &BlockOrTimestamp,
+        component_id: &str,
+        token: &Address,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        interval: SampleInterval,
         conn: &mut AsyncPgConnection,
-    ) -> Result<Vec<ProtocolComponentStateDelta>, StorageError> {
-        let start_ts = match start_version {
-            Some(version) => maybe_lookup_block_ts(version, conn).await?,
-            None => Utc::now().naive_utc(),
+    ) -> Result<Vec<BalanceSample>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let candidates = component_id_candidates(component_id);
+        let candidates: Vec<&str> = candidates
+            .iter()
+            .map(String::as_str)
+            .collect();
+        // Prefer the component stored under the id exactly as given.
+        let pc_id = orm::ProtocolComponent::ids_by_external_ids(&candidates, chain_id, conn)
+            .await
+            .map_err(PostgresError::from)?
+            .into_iter()
+            .min_by_key(|(_, external_id)| external_id != component_id)
+            .map(|(id, _)| id)
+            .ok_or_else(|| {
+                StorageError::NotFound("ProtocolComponent".to_string(), component_id.to_string())
+            })?;
+        let token_id = schema::token::table
+            .inner_join(schema::account::table)
+            .filter(schema::account::address.eq(token))
+            .filter(schema::account::chain_id.eq(chain_id))
+            .select(schema::token::id)
+            .first::<i64>(conn)
+            .await
+            .map_err(|err| storage_error_from_diesel(err, "Token", &token.to_string(), None))?;
+
+        // Skip the intervals before the first balance was recorded, sampling them would scan
+        // the whole history without finding anything.
+        let first_ts = schema::component_balance::table
+            .filter(schema::component_balance::protocol_component_id.eq(pc_id))
+            .filter(schema::component_balance::token_id.eq(token_id))
+            .select(diesel::dsl::min(schema::component_balance::valid_from))
+            .first::<Option<NaiveDateTime>>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        let step = interval.duration();
+        let start = match first_ts {
+            None => return Ok(Vec::new()),
+            Some(first) if first > from => {
+                let skipped = (first - from).num_seconds() / step.num_seconds();
+                from + step * skipped as i32
+            }
+            Some(_) => from,
         };
-        let end_ts = maybe_lookup_block_ts(end_version, conn).await?;
-
-        if start_ts <= end_ts {
-            // Going forward
-            //                  ]     changes to update   ]
-            // -----------------|--------------------------|
-            //                start                     target
-            // We query for state updates between start and target version. We also query for
-            // deleted states between start and target version. We then merge the two
-            // sets of results.
+        if start > to {
+            return Ok(Vec::new());
+        }
 
-            let chain_db_id = self.get_chain_id(chain)?;
+        let step_sql = match interval {
+            SampleInterval::Hourly => "1 hour",
+            SampleInterval::Daily => "1 day",
+        };
+        // open: first version still valid at the interval start or created within it.
+        // close: version valid right before the interval end.
+        // high/low: versions that ended within the interval, plus the closing one.
+        let query = format!(
+            r#"
+            SELECT b.ts,
+                   o.balance_float AS open,
+                   GREATEST(r.high, c.balance_float) AS high,
+                   LEAST(r.low, c.balance_float) AS low,
+                   c.balance_float AS close
+            FROM generate_series($3, $4, interval '{step_sql}') AS b(ts)
+            CROSS JOIN LATERAL (
+                SELECT balance_float FROM component_balance
+                WHERE protocol_component_id = $1 AND token_id = $2
+                    AND valid_to > b.ts AND valid_from < b.ts + interval '{step_sql}'
+                ORDER BY valid_to ASC
+                LIMIT 1
+            ) o
+            CROSS JOIN LATERAL (
+                SELECT balance_float FROM component_balance
+                WHERE protocol_component_id = $1 AND token_id = $2
+                    AND valid_to >= b.ts + interval '{step_sql}'
+                    AND valid_from < b.ts + interval '{step_sql}'
+                ORDER BY valid_to ASC
+                LIMIT 1
+            ) c
+            LEFT JOIN LATERAL (
+                SELECT max(balance_float) AS high, min(balance_float) AS low
+                FROM component_balance
+                WHERE protocol_component_id = $1 AND token_id = $2
+                    AND valid_to > b.ts AND valid_to <= b.ts + interval '{step_sql}'
+            ) r ON TRUE
+            ORDER BY b.ts
+            "#
+        );
+        let samples = diesel::sql_query(query)
+            .bind::<BigInt, _>(pc_id)
+            .bind::<BigInt, _>(token_id)
+            .bind::<Timestamptz, _>(start)
+            .bind::<Timestamptz, _>(to)
+            .load::<orm::BalanceSampleRow>(conn)
+            .await
+            .map_err(PostgresError::from)?;
 
-            // fetch updated component attributes
-            let state_updates =
-                orm::ProtocolState::forward_deltas_by_chain(chain_db_id, start_ts, end_ts, conn)
-                    .await
-                    .map_err(|err| {
-                        storage_error_from_diesel(
-                            err,
-                            "ProtocolStates",
-                            chain.to_string().as_str(),
-                            None,
-                        )
-                    })?;
+        Ok(samples
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
 
-            // fetch deleted component attributes
-            let deleted_attrs = orm::ProtocolState::deleted_attributes_by_chain(
-                chain_db_id,
+    /// Flags components without state or balance changes since `cutoff` as inactive and clears
+    /// the flag of flagged components that changed again.
+    ///
+    /// A component's last change is the latest of its creation and the start of its most recent
+    /// state and balance versions. Flagged components keep that timestamp in `inactive_since`.
+    /// Deleted components are left untouched.
+    ///
+    /// Returns the components whose flag was toggled.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn update_component_activity(
+        &self,
+        chain: &Chain,
+        cutoff: NaiveDateTime,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<orm::ComponentActivityRow>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        // Toggles components whose flag disagrees with their last change:
+        // active ones that did not change since the cutoff and flagged ones that did.
+        let query = r#"
+            WITH last_change AS (
+                SELECT pc.id,
+                       GREATEST(
+                           pc.created_at,
+                           (SELECT max(ps.valid_from) FROM protocol_state ps
+                            WHERE ps.protocol_component_id = pc.id),
+                           (SELECT max(cb.valid_from) FROM component_balance cb
+                            WHERE cb.protocol_component_id = pc.id)
+                       ) AS ts
+                FROM protocol_component pc
+                WHERE pc.chain_id = $1 AND (pc.deleted_at IS NULL OR pc.deleted_at > now())
+            )
+            UPDATE protocol_component pc
+            SET inactive_since = CASE WHEN lc.ts < $2 THEN lc.ts END
+            FROM last_change lc
+            WHERE pc.id = lc.id AND (pc.inactive_since IS NULL) = (lc.ts < $2)
+            RETURNING pc.external_id, pc.inactive_since
+            "#;
+        let toggled = diesel::sql_query(query)
+            .bind::<BigInt, _>(chain_id)
+            .bind::<Timestamptz, _>(cutoff)
+            .load::<orm::ComponentActivityRow>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(toggled)
+    }
+
+    /// Returns the components among `ids` currently flagged inactive, with the timestamp of their
+    /// last change.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn get_inactive_components(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<ComponentId, NaiveDateTime>, StorageError> {
+        use schema::protocol_component::dsl;
+        let chain_id = self.get_chain_id(chain)?;
+        let inactive = dsl::protocol_component
+            .filter(dsl::chain_id.eq(chain_id))
+            .filter(dsl::external_id.eq_any(ids))
+            .filter(dsl::inactive_since.is_not_null())
+            .select((dsl::external_id, dsl::inactive_since.assume_not_null()))
+            .load::<(String, NaiveDateTime)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(inactive.into_iter().collect())
+    }
+
+    /// Returns the extractor that created each component, optionally restricted to the given ids
+    /// and to components created by `extractor`.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn get_component_extractors(
+        &self,
+        chain: &Chain,
+        ids: Option<&[&str]>,
+        extractor: Option<&str>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<ComponentId, String>, StorageError> {
+        use schema::protocol_component::dsl;
+        let ids = component_id_filter(ids);
+        let ids: Option<Vec<&str>> = ids
+            .as_ref()
+            .map(|ids| ids.iter().map(String::as_str).collect());
+        let ids = ids.as_deref();
+        let chain_id = self.get_chain_id(chain)?;
+        let mut query = dsl::protocol_component
+            .filter(dsl::chain_id.eq(chain_id))
+            .filter(dsl::extractor.is_not_null())
+            .select((dsl::external_id, dsl::extractor.assume_not_null()))
+            .into_boxed();
+        if let Some(ids) = ids {
+            query = query.filter(dsl::external_id.eq_any(ids));
+        }
+        if let Some(extractor) = extractor {
+            query = query.filter(dsl::extractor.eq(extractor));
+        }
+        let extractors = query
+            .load::<(String, String)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(extractors.into_iter().collect())
+    }
+
+    /// Returns the ids of the components holding exactly `tokens`, looked up by their pair key.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn get_component_ids_by_tokens(
+        &self,
+        chain: &Chain,
+        tokens: &[Address],
+        ids: Option<&[&str]>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<ComponentId>, StorageError> {
+        use schema::protocol_component::dsl;
+        let ids = component_id_filter(ids);
+        let chain_id = self.get_chain_id(chain)?;
+        let mut query = dsl::protocol_component
+            .filter(dsl::chain_id.eq(chain_id))
+            .filter(dsl::pair_key.eq(ProtocolComponent::pair_key(tokens)))
+            .select(dsl::external_id)
+            .order_by(dsl::external_id)
+            .into_boxed();
+        if let Some(ids) = &ids {
+            query = query.filter(dsl::external_id.eq_any(ids));
+        }
+        Ok(query
+            .load::<String>(conn)
+            .await
+            .map_err(PostgresError::from)?)
+    }
+
+    /// Renames the protocol state attribute `old_name` to `new_name` across all versions of the
+    /// given components of `protocol_system`, or of all its components if no ids are given.
+    ///
+    /// Fails if any of the components already has a version of `new_name`, since the histories
+    /// of both names can't be merged. Entries of the attribute in `protocol_state_index` keep their
+    /// prefix and key. The rename is recorded in `protocol_state_attribute_rename`. Should be
+    /// called within a transaction.
+    ///
+    /// Returns the number of renamed versions.
+    #[instrument(skip(self, conn))]
+    pub async fn rename_protocol_state_attribute(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        component_ids: Option<&[&str]>,
+        old_name: &str,
+        new_name: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<i64, StorageError> {
+        if old_name == new_name {
+            return Err(StorageError::Unexpected(format!(
+                "Can't rename attribute {old_name} to itself"
+            )));
+        }
+        let chain_id = self.get_chain_id(chain)?;
+        let component_ids = component_id_filter(component_ids);
+        let mut query = schema::protocol_component::table
+            .inner_join(schema::protocol_system::table)
+            .filter(schema::protocol_component::chain_id.eq(chain_id))
+            .filter(schema::protocol_system::name.eq(protocol_system))
+            .select((schema::protocol_component::id, schema::protocol_component::external_id))
+            .into_boxed();
+        if let Some(ids) = &component_ids {
+            query = query.filter(schema::protocol_component::external_id.eq_any(ids));
+        }
+        let components: HashMap<i64, String> = query
+            .load::<(i64, String)>(conn)
+            .await
+            .map_err(PostgresError::from)?
+            .into_iter()
+            .collect();
+        if let Some(missing) = component_ids
+            .iter()
+            .flatten()
+            .find(|id| {
+                !components
+                    .values()
+                    .any(|external_id| external_id == *id)
+            })
+        {
+            return Err(StorageError::NotFound("ProtocolComponent".to_string(), missing.clone()));
+        }
+        let internal_ids = components.keys().collect::<Vec<_>>();
+
+        let conflicts = schema::protocol_state::table
+            .filter(schema::protocol_state::protocol_component_id.eq_any(&internal_ids))
+            .filter(schema::protocol_state::attribute_name.eq(new_name))
+            .select(schema::protocol_state::protocol_component_id)
+            .distinct()
+            .load::<i64>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        if !conflicts.is_empty() {
+            let conflicting = conflicts
+                .iter()
+                .filter_map(|id| components.get(id))
+                .sorted()
+                .join(", ");
+            return Err(StorageError::DuplicateEntry(
+                "ProtocolState".to_string(),
+                format!("{new_name} of {conflicting}"),
+            ));
+        }
+
+        let renamed = diesel::update(
+            schema::protocol_state::table
+                .filter(schema::protocol_state::protocol_component_id.eq_any(&internal_ids))
+                .filter(schema::protocol_state::attribute_name.eq(old_name)),
+        )
+        .set(schema::protocol_state::attribute_name.eq(new_name))
+        .execute(conn)
+        .await
+        .map_err(PostgresError::from)? as i64;
+        diesel::update(
+            schema::protocol_state_index::table
+                .filter(schema::protocol_state_index::protocol_component_id.eq_any(&internal_ids))
+                .filter(schema::protocol_state_index::attribute_name.eq(old_name)),
+        )
+        .set(schema::protocol_state_index::attribute_name.eq(new_name))
+        .execute(conn)
+        .await
+        .map_err(PostgresError::from)?;
+
+        diesel::insert_into(schema::protocol_state_attribute_rename::table)
+            .values((
+                schema::protocol_state_attribute_rename::chain_id.eq(chain_id),
+                schema::protocol_state_attribute_rename::protocol_system.eq(protocol_system),
+                schema::protocol_state_attribute_rename::component_ids.eq(component_ids),
+                schema::protocol_state_attribute_rename::old_name.eq(old_name),
+                schema::protocol_state_attribute_rename::new_name.eq(new_name),
+                schema::protocol_state_attribute_rename::renamed_versions.eq(renamed),
+            ))
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(renamed)
+    }
+
+    /// Returns the attributes of a component indexed under `prefix` with a key within `range`,
+    /// grouped by key.
+    ///
+    /// Only attributes declared as indexed by the component's protocol type can be found, see
+    /// [`INDEXED_ATTRIBUTES_KEY`]. If no version is given, the latest state is queried.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn get_attribute_range(
+        &self,
+        chain: &Chain,
+        component_id: &str,
+        prefix: &str,
+        range: RangeInclusive<i64>,
+        at: Option<&Version>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<BTreeMap<i64, HashMap<AttrStoreKey, StoreVal>>, StorageError> {
+        let candidates = component_id_candidates(component_id);
+        let chain_id = self.get_chain_id(chain)?;
+        let version_ts = match at {
+            Some(version) => maybe_lookup_version_ts(version, conn).await?,
+            None => *MAX_VERSION_TS,
+        };
+        let query = r#"
+            SELECT idx.index_key, ps.attribute_name, ps.attribute_value
+            FROM protocol_state_index idx
+            JOIN protocol_component pc ON pc.id = idx.protocol_component_id
+            JOIN protocol_state ps
+                ON ps.protocol_component_id = idx.protocol_component_id
+                AND ps.attribute_name = idx.attribute_name
+            WHERE pc.chain_id = $1
+                AND pc.external_id = ANY($2)
+                AND idx.index_prefix = $3
+                AND idx.index_key BETWEEN $4 AND $5
+                AND ps.valid_from <= $6
+                AND ps.valid_to > $6
+            "#;
+        let rows = diesel::sql_query(query)
+            .bind::<BigInt, _>(chain_id)
+            .bind::<Array<Varchar>, _>(&candidates)
+            .bind::<Varchar, _>(prefix)
+            .bind::<BigInt, _>(*range.start())
+            .bind::<BigInt, _>(*range.end())
+            .bind::<Timestamptz, _>(version_ts)
+            .load::<orm::IndexedAttributeRow>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+
+        let mut attributes: BTreeMap<i64, HashMap<AttrStoreKey, StoreVal>> = BTreeMap::new();
+        for row in rows {
+            attributes
+                .entry(row.index_key)
+                .or_default()
+                .insert(row.attribute_name, row.attribute_value);
+        }
+        Ok(attributes)
+    }
+
+    /// Returns all components of `protocol_system` on `chain`, together with the names of their
+    /// current state attributes.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn get_protocol_deployment(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<ProtocolDeployment, StorageError> {
+        let components = self
+            .get_protocol_components(
+                chain,
+                Some(protocol_system.to_string()),
+                None,
+                None,
+                None,
+                conn,
+            )
+            .await?
+            .entity;
+        let chain_id = self.get_chain_id(chain)?;
+        let protocol_system_id = self.get_protocol_system_id(&protocol_system.to_string())?;
+        let names = schema::protocol_state::table
+            .inner_join(schema::protocol_component::table)
+            .filter(schema::protocol_component::chain_id.eq(chain_id))
+            .filter(schema::protocol_component::protocol_system_id.eq(protocol_system_id))
+            .filter(schema::protocol_state::valid_to.eq(MAX_TS))
+            .select((
+                schema::protocol_component::external_id,
+                schema::protocol_state::attribute_name,
+            ))
+            .distinct()
+            .load::<(String, String)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+
+        let mut state_attributes: HashMap<ComponentId, HashSet<AttrStoreKey>> = HashMap::new();
+        for (component_id, name) in names {
+            state_attributes
+                .entry(component_id)
+                .or_default()
+                .insert(name);
+        }
+        Ok(ProtocolDeployment { chain: *chain, components, state_attributes })
+    }
+
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn get_protocol_states_delta(
+        &self,
+        chain: &Chain,
+        start_version: Option<&BlockOrTimestamp>,
+        end_version: Option<&BlockOrTimestamp>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<ProtocolStatesDelta, StorageError> {
+        let start_ts = match start_version {
+            Some(version) => maybe_lookup_block_ts(version, conn).await?,
+            None => Utc::now().naive_utc(),
+        };
+        let end_block = match end_version {
+            Some(BlockOrTimestamp::Block(id)) => self.get_block(id, conn).await?,
+            Some(BlockOrTimestamp::Timestamp(ts)) => self
+                .get_blocks_at(slice::from_ref(chain), *ts, conn)
+                .await?
+                .remove(chain)
+                .ok_or_else(|| {
+                    StorageError::NotFound("Block".to_string(), format!("{chain} at {ts}"))
+                })?,
+            None => {
+                let head = self
+                    .get_block(&BlockIdentifier::Latest(*chain), conn)
+                    .await?;
+                if start_ts > head.ts {
+                    return Err(StorageError::InvalidVersionRange(format!(
+                        "start version at {start_ts} lies after the latest {chain} block {} at {}",
+                        head.number, head.ts
+                    )));
+                }
+                head
+            }
+        };
+        let end_ts = match end_version {
+            Some(BlockOrTimestamp::Timestamp(ts)) => *ts,
+            _ => end_block.ts,
+        };
+
+        if start_ts <= end_ts {
+            // Going forward
+            //                  ]     changes to update   ]
+            // -----------------|--------------------------|
+            //                start                     target
+            // We query for state updates between start and target version. We also query for
+            // deleted states between start and target version. We then merge the two
+            // sets of results.
+
+            let chain_db_id = self.get_chain_id(chain)?;
+
+            // fetch updated component attributes
+            let state_updates =
+                orm::ProtocolState::forward_deltas_by_chain(chain_db_id, start_ts, end_ts, conn)
+                    .await
+                    .map_err(|err| {
+                        storage_error_from_diesel(
+                            err,
+                            "ProtocolStates",
+                            chain.to_string().as_str(),
+                            None,
+                        )
+                    })?;
+
+            // fetch deleted component attributes
+            let deleted_attrs = orm::ProtocolState::deleted_attributes_by_chain(
+                chain_db_id,
                 start_ts,
                 end_ts,
                 conn,
@@ -1591,7 +2370,7 @@ impl PostgresGateway {
 
                 protocol_states_delta.push(state_delta);
             }
-            Ok(protocol_states_delta)
+            Ok(ProtocolStatesDelta { deltas: protocol_states_delta, end_block })
         } else {
             // Going backwards
             //                  ]     changes to revert    ]
@@ -1652,7 +2431,7 @@ impl PostgresGateway {
                 deltas.push(state_delta);
             }
 
-            Ok(deltas)
+            Ok(ProtocolStatesDelta { deltas, end_block })
         }
     }
 
@@ -1675,89 +2454,522 @@ impl PostgresGateway {
             .collect::<HashMap<_, _>>())
     }
 
-    pub async fn upsert_component_tvl(
+    /// Returns when tokens, token overrides or protocol components of `chain` were last inserted or
+    /// modified, `None` if the chain has none.
+    pub async fn get_protocol_data_modified_at(
         &self,
         chain: &Chain,
-        tvl_values: &HashMap<String, f64>,
         conn: &mut AsyncPgConnection,
-    ) -> Result<(), StorageError> {
+    ) -> Result<Option<NaiveDateTime>, StorageError> {
         let chain_id = self.get_chain_id(chain)?;
-        let external_ids = tvl_values
-            .keys()
-            .map(|s| s.as_str())
-            .collect::<Vec<_>>();
-        let external_db_id_map =
-            orm::ProtocolComponent::ids_by_external_ids(&external_ids, chain_id, conn)
-                .await
-                .map_err(PostgresError::from)?
-                .into_iter()
-                .map(|(a, b)| (b, a))
-                .collect::<HashMap<_, _>>();
-
-        let upsert_map: HashMap<_, _> = tvl_values
-            .iter()
-            .filter_map(|(component_id, v)| {
-                if let Some(db_id) = external_db_id_map.get(component_id) {
-                    Some((*db_id, *v))
-                } else {
-                    warn!(?component_id, "Tried to upsert tvl for unknown component!");
-                    None
-                }
-            })
-            .collect();
-        orm::ComponentTVL::upsert_many(&upsert_map)
-            .execute(conn)
+        let tokens = schema::token::table
+            .inner_join(schema::account::table)
+            .filter(schema::account::chain_id.eq(chain_id))
+            .select(diesel::dsl::max(schema::token::modified_ts))
+            .first::<Option<NaiveDateTime>>(conn)
             .await
             .map_err(PostgresError::from)?;
-        Ok(())
+        let overrides = schema::token_override::table
+            .inner_join(schema::token::table.inner_join(schema::account::table))
+            .filter(schema::account::chain_id.eq(chain_id))
+            .select(diesel::dsl::max(schema::token_override::modified_ts))
+            .first::<Option<NaiveDateTime>>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        let components = schema::protocol_component::table
+            .filter(schema::protocol_component::chain_id.eq(chain_id))
+            .select(diesel::dsl::max(schema::protocol_component::modified_ts))
+            .first::<Option<NaiveDateTime>>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok([tokens, overrides, components]
+            .into_iter()
+            .flatten()
+            .max())
     }
 
-    pub async fn get_protocol_systems(
+    /// Returns the metadata overrides of the chain's tokens, sorted by token address.
+    pub async fn get_token_overrides(
         &self,
         chain: &Chain,
-        pagination_params: Option<&PaginationParams>,
-    ) -> Result<WithTotal<Vec<String>>, StorageError> {
-        if !self.chain_id_cache.value_exists(chain) {
-            return Err(StorageError::NotFound("Chain".to_string(), chain.to_string()));
+        addresses: Option<&[&Address]>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<TokenOverride>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let mut query = schema::token_override::table
+            .inner_join(schema::token::table.inner_join(schema::account::table))
+            .select((
+                schema::account::address,
+                schema::token_override::symbol,
+                schema::token_override::display_decimals,
+                schema::token_override::logo_uri,
+            ))
+            .filter(schema::account::chain_id.eq(chain_id))
+            .order_by(schema::account::address)
+            .into_boxed();
+        if let Some(addresses) = addresses {
+            query = query.filter(schema::account::address.eq_any(addresses));
         }
-        let all_protocol_systems: Vec<String> = self
-            .protocol_system_id_cache
-            .map_enum
-            .values()
-            .cloned()
-            .sorted()
-            .collect();
-
-        let total = all_protocol_systems.len() as i64;
-        let paginated_protocol_systems = if let Some(params) = pagination_params {
-            all_protocol_systems
-                .into_iter()
-                .skip(params.offset() as usize)
-                .take(params.page_size as usize)
-                .collect()
-        } else {
-            all_protocol_systems
-        };
-
-        Ok(WithTotal { total: Some(total), entity: paginated_protocol_systems })
+        Ok(query
+            .get_results::<(Address, Option<String>, Option<i32>, Option<String>)>(conn)
+            .await
+            .map_err(|err| {
+                storage_error_from_diesel(err, "TokenOverride", &chain.to_string(), None)
+            })?
+            .into_iter()
+            .map(|(address, symbol, display_decimals, logo_uri)| TokenOverride {
+                chain: *chain,
+                address,
+                symbol,
+                display_decimals: display_decimals.map(|decimals| decimals as u32),
+                logo_uri,
+            })
+            .collect())
     }
 
-    pub async fn get_component_tvls(
+    /// Stores metadata overrides, replacing existing overrides of the same tokens. Fails if any
+    /// of the tokens is unknown.
+    pub async fn upsert_token_overrides(
         &self,
-        chain: &Chain,
-        system: Option<String>,
-        component_ids: Option<&[&str]>,
-        pagination_params: Option<&PaginationParams>,
+        overrides: &[TokenOverride],
         conn: &mut AsyncPgConnection,
-    ) -> Result<WithTotal<HashMap<String, f64>>, StorageError> {
-        use schema::{component_tvl::dsl as ct, protocol_component::dsl as pc};
-
-        if !self.chain_id_cache.value_exists(chain) {
-            return Err(StorageError::NotFound("Chain".to_string(), chain.to_string()));
-        }
-
-        let chain_id_val = self.get_chain_id(chain)?;
-
+    ) -> Result<(), StorageError> {
+        use schema::token_override::dsl::*;
+        for (chain, chain_overrides) in overrides
+            .iter()
+            .into_group_map_by(|o| o.chain)
+        {
+            let chain_id = self.get_chain_id(&chain)?;
+            let token_ids: HashMap<Address, i64> = schema::token::table
+                .inner_join(schema::account::table)
+                .select((schema::account::address, schema::token::id))
+                .filter(schema::account::chain_id.eq(chain_id))
+                .filter(
+                    schema::account::address.eq_any(
+                        chain_overrides
+                            .iter()
+                            .map(|o| &o.address),
+                    ),
+                )
+                .get_results::<(Address, i64)>(conn)
+                .await
+                .map_err(|err| storage_error_from_diesel(err, "Token", &chain.to_string(), None))?
+                .into_iter()
+                .collect();
+
+            let new_overrides = chain_overrides
+                .iter()
+                .map(|o| {
+                    let tid = token_ids
+                        .get(&o.address)
+                        .ok_or_else(|| {
+                            StorageError::NotFound("Token".to_string(), o.address.to_string())
+                        })?;
+                    Ok(orm::NewTokenOverride {
+                        token_id: *tid,
+                        symbol: o.symbol.as_deref(),
+                        display_decimals: o
+                            .display_decimals
+                            .map(|decimals| decimals as i32),
+                        logo_uri: o.logo_uri.as_deref(),
+                    })
+                })
+                .collect::<Result<Vec<_>, StorageError>>()?;
+
+            diesel::insert_into(token_override)
+                .values(&new_overrides)
+                .on_conflict(token_id)
+                .do_update()
+                .set((
+                    symbol.eq(excluded(symbol)),
+                    display_decimals.eq(excluded(display_decimals)),
+                    logo_uri.eq(excluded(logo_uri)),
+                ))
+                .execute(conn)
+                .await
+                .map_err(|err| {
+                    storage_error_from_diesel(err, "TokenOverride", &chain.to_string(), None)
+                })?;
+        }
+        Ok(())
+    }
+
+    pub async fn delete_token_overrides(
+        &self,
+        chain: &Chain,
+        addresses: &[&Address],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let token_ids = schema::token::table
+            .inner_join(schema::account::table)
+            .select(schema::token::id)
+            .filter(schema::account::chain_id.eq(chain_id))
+            .filter(schema::account::address.eq_any(addresses));
+        let deleted = diesel::delete(
+            schema::token_override::table
+                .filter(schema::token_override::token_id.eq_any(token_ids)),
+        )
+        .execute(conn)
+        .await
+        .map_err(|err| storage_error_from_diesel(err, "TokenOverride", &chain.to_string(), None))?;
+        record_operation(
+            AuditRecord::new("delete_token_overrides", deleted as i64)
+                .chain(chain)
+                .target(addresses.iter().join(", ")),
+            conn,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Stores total supply snapshots of tokens observed at `ts`. Tokens unknown to the db are
+    /// ignored; a second snapshot for the same token and timestamp replaces the first.
+    pub async fn add_token_supplies(
+        &self,
+        chain: &Chain,
+        supplies: &HashMap<Address, Balance>,
+        ts: NaiveDateTime,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let token_ids: HashMap<Address, i64> = schema::token::table
+            .inner_join(schema::account::table)
+            .select((schema::account::address, schema::token::id))
+            .filter(schema::account::chain_id.eq(chain_id))
+            .filter(schema::account::address.eq_any(supplies.keys()))
+            .get_results::<(Address, i64)>(conn)
+            .await
+            .map_err(|err| storage_error_from_diesel(err, "Token", &chain.to_string(), None))?
+            .into_iter()
+            .collect();
+
+        let new_supplies = supplies
+            .iter()
+            .filter_map(|(address, supply)| {
+                let Some(tid) = token_ids.get(address) else {
+                    warn!(?address, "Skipping supply snapshot of unknown token");
+                    return None;
+                };
+                Some(orm::NewTokenSupply { token_id: *tid, total_supply: supply.clone(), ts })
+            })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(schema::token_supply::table)
+            .values(&new_supplies)
+            .on_conflict((schema::token_supply::token_id, schema::token_supply::ts))
+            .do_update()
+            .set(
+                schema::token_supply::total_supply.eq(excluded(schema::token_supply::total_supply)),
+            )
+            .execute(conn)
+            .await
+            .map_err(|err| {
+                storage_error_from_diesel(err, "TokenSupply", &chain.to_string(), None)
+            })?;
+        Ok(())
+    }
+
+    /// Returns the latest total supply snapshot of each token taken at or before `at`. Tokens
+    /// without a snapshot are missing from the result.
+    pub async fn get_token_supplies(
+        &self,
+        chain: &Chain,
+        tokens: &[Address],
+        at: Option<&Version>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<Address, Balance>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let version_ts = match at {
+            Some(version) => maybe_lookup_version_ts(version, conn).await?,
+            None => *MAX_VERSION_TS,
+        };
+        Ok(schema::token_supply::table
+            .inner_join(schema::token::table.inner_join(schema::account::table))
+            .select((schema::account::address, schema::token_supply::total_supply))
+            .filter(schema::account::chain_id.eq(chain_id))
+            .filter(schema::account::address.eq_any(tokens))
+            .filter(schema::token_supply::ts.le(version_ts))
+            .distinct_on(schema::token_supply::token_id)
+            .order_by((schema::token_supply::token_id, schema::token_supply::ts.desc()))
+            .get_results::<(Address, Balance)>(conn)
+            .await
+            .map_err(|err| storage_error_from_diesel(err, "TokenSupply", &chain.to_string(), None))?
+            .into_iter()
+            .collect())
+    }
+
+    /// Stores event logs of components. Logs that are already stored are ignored, so replaying a
+    /// block is harmless.
+    pub async fn add_component_events(
+        &self,
+        events: &[ComponentEvent],
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let chain_id = self.get_chain_id(chain)?;
+        let tx_hashes = events
+            .iter()
+            .map(|e| e.tx_hash.clone())
+            .collect::<Vec<_>>();
+        let tx_ids = orm::Transaction::ids_by_hash(&tx_hashes, conn).await?;
+        let external_ids = events
+            .iter()
+            .map(|e| e.component_id.as_str())
+            .collect::<Vec<_>>();
+        let component_ids: HashMap<String, i64> =
+            orm::ProtocolComponent::ids_by_external_ids(&external_ids, chain_id, conn)
+                .await
+                .map_err(PostgresError::from)?
+                .into_iter()
+                .map(|(component_id, external_id)| (external_id, component_id))
+                .collect();
+
+        let new_events = events
+            .iter()
+            .map(|event| {
+                let transaction_id = tx_ids
+                    .get(&event.tx_hash)
+                    .ok_or_else(|| {
+                        StorageError::NotFound("Transaction".to_string(), event.tx_hash.to_string())
+                    })?;
+                let protocol_component_id = component_ids
+                    .get(&event.component_id)
+                    .ok_or_else(|| {
+                        StorageError::NotFound(
+                            "ProtocolComponent".to_string(),
+                            event.component_id.clone(),
+                        )
+                    })?;
+                Ok(orm::NewComponentEvent {
+                    protocol_component_id: *protocol_component_id,
+                    transaction_id: *transaction_id,
+                    log_index: event.log_index.into(),
+                    address: event.address.clone(),
+                    topics: event
+                        .topics
+                        .iter()
+                        .cloned()
+                        .map(Some)
+                        .collect(),
+                    data: event.data.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+
+        diesel::insert_into(schema::component_event::table)
+            .values(&new_events)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .await
+            .map_err(|err| {
+                storage_error_from_diesel(err, "ComponentEvent", &chain.to_string(), None)
+            })?;
+        Ok(())
+    }
+
+    /// Returns the event logs of the given components emitted in blocks within `blocks`, ordered
+    /// by block and log index.
+    pub async fn get_component_events(
+        &self,
+        chain: &Chain,
+        ids: &[&str],
+        blocks: RangeInclusive<i64>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<ComponentEvent>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let rows = schema::component_event::table
+            .inner_join(schema::protocol_component::table)
+            .inner_join(schema::transaction::table.inner_join(schema::block::table))
+            .filter(schema::protocol_component::chain_id.eq(chain_id))
+            .filter(schema::protocol_component::external_id.eq_any(ids))
+            .filter(schema::block::number.between(*blocks.start(), *blocks.end()))
+            .order_by((schema::block::number, schema::component_event::log_index))
+            .select((
+                schema::protocol_component::external_id,
+                schema::transaction::hash,
+                schema::component_event::log_index,
+                schema::component_event::address,
+                schema::component_event::topics,
+                schema::component_event::data,
+            ))
+            .get_results::<(String, TxHash, i64, Address, Vec<Option<Bytes>>, Bytes)>(conn)
+            .await
+            .map_err(|err| {
+                storage_error_from_diesel(err, "ComponentEvent", &chain.to_string(), None)
+            })?;
+
+        rows.into_iter()
+            .map(|(component_id, tx_hash, log_index, address, topics, data)| {
+                let log_index = u32::try_from(log_index).map_err(|_| {
+                    StorageError::DecodeError(format!("Invalid log index {log_index}"))
+                })?;
+                Ok(ComponentEvent::new(
+                    &component_id,
+                    tx_hash,
+                    log_index,
+                    address,
+                    topics.into_iter().flatten().collect(),
+                    data,
+                ))
+            })
+            .collect()
+    }
+
+    pub async fn upsert_component_tvl(
+        &self,
+        chain: &Chain,
+        tvl_values: &HashMap<String, f64>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let external_ids = tvl_values
+            .keys()
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>();
+        let external_db_id_map =
+            orm::ProtocolComponent::ids_by_external_ids(&external_ids, chain_id, conn)
+                .await
+                .map_err(PostgresError::from)?
+                .into_iter()
+                .map(|(a, b)| (b, a))
+                .collect::<HashMap<_, _>>();
+
+        let upsert_map: HashMap<_, _> = tvl_values
+            .iter()
+            .filter_map(|(component_id, v)| {
+                if let Some(db_id) = external_db_id_map.get(component_id) {
+                    Some((*db_id, *v))
+                } else {
+                    warn!(?component_id, "Tried to upsert tvl for unknown component!");
+                    None
+                }
+            })
+            .collect();
+        orm::ComponentTVL::upsert_many(&upsert_map)
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+
+    pub async fn get_protocol_systems(
+        &self,
+        chain: &Chain,
+        pagination_params: Option<&PaginationParams>,
+    ) -> Result<WithTotal<Vec<String>>, StorageError> {
+        if !self.chain_id_cache.value_exists(chain) {
+            return Err(StorageError::NotFound("Chain".to_string(), chain.to_string()));
+        }
+        let all_protocol_systems: Vec<String> = self
+            .protocol_system_id_cache
+            .map_enum
+            .values()
+            .cloned()
+            .sorted()
+            .collect();
+
+        let total = all_protocol_systems.len() as i64;
+        let paginated_protocol_systems = if let Some(params) = pagination_params {
+            all_protocol_systems
+                .into_iter()
+                .skip(params.offset() as usize)
+                .take(params.page_size as usize)
+                .collect()
+        } else {
+            all_protocol_systems
+        };
+
+        Ok(WithTotal { total: Some(total), entity: paginated_protocol_systems })
+    }
+
+    /// Returns an overview of every protocol system on `chain`, ordered by name.
+    ///
+    /// The last update considers component creations and the current versions of protocol states
+    /// and component balances. Changes to contract storage of VM components are not included.
+    #[instrument(level = Level::DEBUG, skip(self, conn))]
+    pub async fn get_protocol_system_summaries(
+        &self,
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<ProtocolSystemSummary>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let query = r#"
+            WITH components AS (
+                SELECT pc.protocol_system_id,
+                       count(*) AS component_count,
+                       array_agg(DISTINCT pt.implementation) AS implementation_types
+                FROM protocol_component pc
+                JOIN protocol_type pt ON pt.id = pc.protocol_type_id
+                WHERE pc.chain_id = $1 AND (pc.deleted_at IS NULL OR pc.deleted_at > now())
+                GROUP BY pc.protocol_system_id
+            ),
+            updates AS (
+                SELECT pc.protocol_system_id,
+                       max(b.number) AS block_number,
+                       max(b.ts) AS block_ts
+                FROM (
+                    SELECT protocol_component_id, modify_tx AS tx_id
+                    FROM protocol_state WHERE valid_to = $2
+                    UNION ALL
+                    SELECT protocol_component_id, modify_tx
+                    FROM component_balance WHERE valid_to = $2
+                    UNION ALL
+                    SELECT id, creation_tx FROM protocol_component
+                ) u
+                JOIN protocol_component pc ON pc.id = u.protocol_component_id
+                JOIN "transaction" t ON t.id = u.tx_id
+                JOIN block b ON b.id = t.block_id
+                WHERE pc.chain_id = $1
+                GROUP BY pc.protocol_system_id
+            )
+            SELECT ps.name,
+                   coalesce(c.component_count, 0) AS component_count,
+                   coalesce(c.implementation_types, '{}') AS implementation_types,
+                   u.block_number AS last_updated_block,
+                   u.block_ts AS last_updated_ts
+            FROM protocol_system ps
+            LEFT JOIN components c ON c.protocol_system_id = ps.id
+            LEFT JOIN updates u ON u.protocol_system_id = ps.id
+            ORDER BY ps.name
+            "#;
+        let rows = diesel::sql_query(query)
+            .bind::<BigInt, _>(chain_id)
+            .bind::<Timestamptz, _>(MAX_TS)
+            .load::<orm::ProtocolSystemSummaryRow>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+
+        Ok(rows
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    pub async fn get_component_tvls(
+        &self,
+        chain: &Chain,
+        system: Option<String>,
+        component_ids: Option<&[&str]>,
+        pagination_params: Option<&PaginationParams>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<WithTotal<HashMap<String, f64>>, StorageError> {
+        use schema::{component_tvl::dsl as ct, protocol_component::dsl as pc};
+        let ids = component_id_filter(component_ids);
+        let ids: Option<Vec<&str>> = ids
+            .as_ref()
+            .map(|ids| ids.iter().map(String::as_str).collect());
+        let component_ids = ids.as_deref();
+
+        if !self.chain_id_cache.value_exists(chain) {
+            return Err(StorageError::NotFound("Chain".to_string(), chain.to_string()));
+        }
+
+        let chain_id_val = self.get_chain_id(chain)?;
+
         let mut query = ct::component_tvl
             .inner_join(pc::protocol_component)
             .filter(pc::chain_id.eq(chain_id_val))
@@ -1812,12 +3024,13 @@ impl PostgresGateway {
 
 #[cfg(test)]
 mod test {
-    use std::{slice, str::FromStr};
+    use std::str::FromStr;
 
+    use chrono::DateTime;
     use diesel_async::AsyncConnection;
     use rstest::rstest;
     use serde_json::json;
-    use tycho_common::storage::BlockIdentifier;
+    use tycho_common::models::protocol::ProtocolComponentBuilder;
 
     use super::*;
     use crate::postgres::db_fixtures;
@@ -2449,6 +3662,59 @@ mod test {
         assert_eq!(deleted_state.valid_to, older_state.valid_to);
     }
 
+    #[tokio::test]
+    async fn test_get_attribute_range() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gateway = EVMGateway::from_connection(&mut conn).await;
+        let chain = Chain::Ethereum;
+        diesel::update(schema::protocol_type::table.filter(schema::protocol_type::name.eq("Pool")))
+            .set(
+                schema::protocol_type::attribute_schema
+                    .eq(serde_json::json!({ "indexed_attributes": ["ticks/"] })),
+            )
+            .execute(&mut conn)
+            .await
+            .expect("Failed to set attribute schema");
+
+        let value = Bytes::from(1u8);
+        let mut ticks = protocol_state_delta();
+        ticks.updated_attributes = [
+            "ticks/-10/net-liquidity",
+            "ticks/5/net-liquidity",
+            "ticks/5/fee-growth",
+            "ticks/100/net-liquidity",
+            "ticks/current/net-liquidity",
+        ]
+        .into_iter()
+        .map(|attr| (attr.to_string(), value.clone()))
+        .collect();
+        let mut removal = protocol_state_delta();
+        removal.updated_attributes = HashMap::new();
+        removal.deleted_attributes = HashSet::from(["ticks/5/net-liquidity".to_string()]);
+        let tx_1 =
+            Bytes::from_str("0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7")
+                .unwrap();
+        let tx_2 =
+            Bytes::from_str("0x50449de1973d86f21bfafa7c72011854a7e33a226709dc3e2e4edcca34188388")
+                .unwrap();
+        gateway
+            .update_protocol_states(&chain, &[(tx_1, &ticks), (tx_2, &removal)], &mut conn)
+            .await
+            .expect("Failed to update protocol states");
+
+        let res = gateway
+            .get_attribute_range(&chain, "state3", "ticks/", -10..=99, None, &mut conn)
+            .await
+            .expect("Failed to get attribute range");
+
+        let expected = BTreeMap::from([
+            (-10, HashMap::from([("ticks/-10/net-liquidity".to_string(), value.clone())])),
+            (5, HashMap::from([("ticks/5/fee-growth".to_string(), value.clone())])),
+        ]);
+        assert_eq!(res, expected);
+    }
+
     #[tokio::test]
     async fn test_get_balance_deltas() {
         let mut conn = setup_db().await;
@@ -2611,8 +3877,11 @@ mod test {
         assert_eq!(result, expected_backward_deltas);
     }
 
+    #[rstest]
+    #[case::to_block(Some(BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 2)))))]
+    #[case::to_head(None)]
     #[tokio::test]
-    async fn test_get_protocol_states_delta_forward() {
+    async fn test_get_protocol_states_delta_forward(#[case] end_version: Option<BlockOrTimestamp>) {
         let mut conn = setup_db().await;
         setup_data(&mut conn).await;
 
@@ -2700,14 +3969,15 @@ mod test {
             .get_protocol_states_delta(
                 &Chain::Ethereum,
                 Some(&BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 1)))),
-                &BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 2))),
+                end_version.as_ref(),
                 &mut conn,
             )
             .await
             .unwrap();
 
         // asserts
-        assert_eq!(result, expected)
+        assert_eq!(result.deltas, expected);
+        assert_eq!(result.end_block.number, 2);
     }
 
     #[tokio::test]
@@ -2804,14 +4074,29 @@ mod test {
             .get_protocol_states_delta(
                 &Chain::Ethereum,
                 Some(&BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 2)))),
-                &BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 1))),
+                Some(&BlockOrTimestamp::Block(BlockIdentifier::Number((Chain::Ethereum, 1)))),
                 &mut conn,
             )
             .await
             .unwrap();
 
         // asserts
-        assert_eq!(result, expected)
+        assert_eq!(result.deltas, expected);
+        assert_eq!(result.end_block.number, 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_protocol_states_delta_start_after_head() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gateway = EVMGateway::from_connection(&mut conn).await;
+        let start = BlockOrTimestamp::Timestamp(Utc::now().naive_utc());
+
+        let res = gateway
+            .get_protocol_states_delta(&Chain::Ethereum, Some(&start), None, &mut conn)
+            .await;
+
+        assert!(matches!(res, Err(StorageError::InvalidVersionRange(_))), "{res:?}");
     }
 
     #[tokio::test]
@@ -3268,18 +4553,19 @@ mod test {
         db_fixtures::insert_protocol_type(&mut conn, "Test_Type_2", None, None, None).await;
         let protocol_system = "ambient".to_string();
         let chain = Chain::Ethereum;
-        let original_component = ProtocolComponent::new(
+        let original_component = ProtocolComponentBuilder::new(
             "test_contract_id",
             &protocol_system,
             &protocol_type_name_1,
             chain,
-            vec![Bytes::from(WETH)],
-            vec![Bytes::from(WETH)],
-            HashMap::new(),
-            ChangeType::Creation,
-            Bytes::from("0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945"),
-            Default::default(),
-        );
+        )
+        .tokens(vec![Bytes::from(WETH)])
+        .contract_addresses(vec![Bytes::from(WETH)])
+        .creation_tx(Bytes::from(
+            "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945",
+        ))
+        .build()
+        .unwrap();
 
         gw.add_protocol_components(slice::from_ref(&original_component), &mut conn)
             .await
@@ -3357,19 +4643,67 @@ mod test {
         assert!(contract.is_ok())
     }
 
-    fn create_test_protocol_component(id: &str) -> ProtocolComponent {
-        ProtocolComponent::new(
-            id,
-            "ambient",
-            "type_id_1",
-            Chain::Ethereum,
-            vec![],
-            vec![],
-            HashMap::new(),
-            ChangeType::Creation,
-            Bytes::from("0x0000000000000000000000000000000000000000000000000000000011121314"),
-            NaiveDateTime::from_timestamp_opt(1000, 0).unwrap(),
+    #[tokio::test]
+    async fn test_add_protocol_components_mixed_types() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let type_1_id =
+            db_fixtures::insert_protocol_type(&mut conn, "Test_Type_1", None, None, None).await;
+        let type_2_id =
+            db_fixtures::insert_protocol_type(&mut conn, "Test_Type_2", None, None, None).await;
+        let component = |id: &str, protocol_type: &str| {
+            ProtocolComponentBuilder::new(id, "ambient", protocol_type, Chain::Ethereum)
+                .creation_tx(Bytes::from(
+                    "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945",
+                ))
+                .build()
+                .unwrap()
+        };
+
+        gw.add_protocol_components(
+            &[component("pool_1", "Test_Type_1"), component("pool_2", "Test_Type_2")],
+            &mut conn,
         )
+        .await
+        .expect("adding components failed");
+        let unknown = gw
+            .add_protocol_components(&[component("pool_3", "Unknown_Type")], &mut conn)
+            .await;
+
+        let inserted: HashMap<String, i64> = schema::protocol_component::table
+            .filter(schema::protocol_component::external_id.eq_any(["pool_1", "pool_2"]))
+            .select((
+                schema::protocol_component::external_id,
+                schema::protocol_component::protocol_type_id,
+            ))
+            .load::<(String, i64)>(&mut conn)
+            .await
+            .expect("failed to get inserted data")
+            .into_iter()
+            .collect();
+        assert_eq!(
+            inserted,
+            HashMap::from([("pool_1".to_string(), type_1_id), ("pool_2".to_string(), type_2_id)])
+        );
+        assert_eq!(
+            unknown,
+            Err(StorageError::NotFound("ProtocolType".to_string(), "Unknown_Type".to_string()))
+        );
+    }
+
+    fn create_test_protocol_component(id: &str) -> ProtocolComponent {
+        ProtocolComponentBuilder::new(id, "ambient", "type_id_1", Chain::Ethereum)
+            .creation_tx(Bytes::from(
+                "0x0000000000000000000000000000000000000000000000000000000011121314",
+            ))
+            .created_at(
+                DateTime::from_timestamp(1000, 0)
+                    .unwrap()
+                    .naive_utc(),
+            )
+            .build()
+            .unwrap()
     }
 
     #[tokio::test]
@@ -3428,6 +4762,64 @@ mod test {
         assert_eq!(result.total, Some(3));
     }
 
+    #[tokio::test]
+    async fn test_get_protocol_components_after() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let ids = |res: &WithTotal<Vec<ProtocolComponent>>| {
+            res.entity
+                .iter()
+                .map(|c| c.id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let first = gw
+            .get_protocol_components_after(
+                &Chain::Ethereum,
+                None,
+                None,
+                None,
+                &ComponentCursor::new(1, None),
+                2,
+                &mut conn,
+            )
+            .await
+            .unwrap();
+        let second = gw
+            .get_protocol_components_after(
+                &Chain::Ethereum,
+                None,
+                None,
+                None,
+                &ComponentCursor::new(1, Some("state1".to_string())),
+                2,
+                &mut conn,
+            )
+            .await
+            .unwrap();
+        // All components were created in block 1
+        let before_creation = gw
+            .get_protocol_components_after(
+                &Chain::Ethereum,
+                None,
+                None,
+                None,
+                &ComponentCursor::new(0, None),
+                2,
+                &mut conn,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&first), ["no_tvl", "state1"]);
+        assert_eq!(first.total, Some(3));
+        assert_eq!(ids(&second), ["state3"]);
+        assert_eq!(second.total, Some(3));
+        assert!(before_creation.entity.is_empty());
+        assert_eq!(before_creation.total, Some(0));
+    }
+
     #[rstest]
     #[case::get_one(Some("zigzag".to_string()))]
     #[case::get_none(Some("ambient".to_string()))]
@@ -3464,220 +4856,798 @@ mod test {
         }
     }
 
-    #[rstest]
-    #[case::get_one("state1".to_string())]
-    #[case::get_none("state2".to_string())]
+    #[rstest]
+    #[case::get_one("state1".to_string())]
+    #[case::get_none("state2".to_string())]
+    #[tokio::test]
+    async fn test_get_protocol_components_with_external_id_only(#[case] external_id: String) {
+        let mut conn = setup_db().await;
+        let (_, tx_hashes) = setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let temp_ids_array = [external_id.as_str()];
+        let ids = Some(temp_ids_array.as_slice());
+        let chain = Chain::Ethereum;
+
+        let result = gw
+            .get_protocol_components(&chain, None, ids, None, None, &mut conn)
+            .await
+            .unwrap()
+            .entity;
+
+        match external_id.as_str() {
+            "state1" => {
+                assert_eq!(result.len(), 1);
+
+                let pc = &result[0];
+                assert_eq!(pc.id, external_id.to_string());
+                assert_eq!(pc.protocol_system, "ambient");
+                assert_eq!(pc.chain, Chain::Ethereum);
+                assert_eq!(pc.creation_tx, Bytes::from(tx_hashes[0].as_str()));
+            }
+            "state2" => {
+                assert_eq!(result.len(), 0)
+            }
+            _ => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_protocol_components_with_system_and_ids() {
+        let mut conn = setup_db().await;
+        let (_, tx_hashes) = setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let system = "ambient".to_string();
+        let ids = Some(["state1", "state2"].as_slice());
+        let chain = Chain::Ethereum;
+        let result = gw
+            .get_protocol_components(&chain, Some(system), ids, None, None, &mut conn)
+            .await;
+
+        let components = result.unwrap().entity;
+        assert_eq!(components.len(), 1);
+
+        let pc = &components[0];
+        assert_eq!(pc.id, "state1".to_string());
+        assert_eq!(pc.protocol_system, "ambient");
+        assert_eq!(pc.chain, Chain::Ethereum);
+        assert_eq!(pc.creation_tx, Bytes::from(tx_hashes[0].as_str()));
+    }
+
+    #[rstest]
+    #[case::stored_lowercase("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")]
+    #[case::stored_checksummed("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")]
+    #[tokio::test]
+    async fn test_get_protocol_components_with_checksummed_id(#[case] stored_id: &str) {
+        let mut conn = setup_db().await;
+        let (chain_id, _) = setup_data(&mut conn).await;
+        let tx_id = schema::transaction::table
+            .select(schema::transaction::id)
+            .first::<i64>(&mut conn)
+            .await
+            .unwrap();
+        let system_id =
+            db_fixtures::insert_protocol_system(&mut conn, "uniswap_v2".to_owned()).await;
+        let type_id =
+            db_fixtures::insert_protocol_type(&mut conn, "uniswap_v2_pool", None, None, None).await;
+        db_fixtures::insert_protocol_component(
+            &mut conn, stored_id, chain_id, system_id, type_id, tx_id, None, None,
+        )
+        .await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let components = gw
+            .get_protocol_components(
+                &Chain::Ethereum,
+                None,
+                Some(&["0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"]),
+                None,
+                None,
+                &mut conn,
+            )
+            .await
+            .unwrap()
+            .entity;
+
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].id, stored_id);
+    }
+
+    #[rstest]
+    #[case::ethereum(Chain::Ethereum, & ["state1", "state3", "no_tvl"])]
+    #[case::starknet(Chain::Starknet, & ["state2"])]
+    #[tokio::test]
+    async fn test_get_protocol_components_with_chain_filter(
+        #[case] chain: Chain,
+        #[case] exp_ids: &[&str],
+    ) {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let exp = exp_ids
+            .iter()
+            .map(|&s| s.to_owned())
+            .collect::<HashSet<_>>();
+
+        let components = gw
+            .get_protocol_components(&chain, None, None, None, None, &mut conn)
+            .await
+            .expect("failed retrieving components")
+            .entity
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<HashSet<_>>();
+
+        assert_eq!(components, exp);
+    }
+
+    #[rstest]
+    #[case::empty(Some(10.0), & [])]
+    #[case::all(None, & ["state1", "state3", "no_tvl"])]
+    #[case::with_tvl(Some(0.0), & ["state1", "state3"])]
+    #[case::with_tvl(Some(1.0), & ["state1"])]
+    #[tokio::test]
+    async fn test_get_protocol_components_with_min_tvl(
+        #[case] min_tvl: Option<f64>,
+        #[case] exp_ids: &[&str],
+    ) {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let exp = exp_ids
+            .iter()
+            .map(|&s| s.to_owned())
+            .collect::<HashSet<_>>();
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let res = gw
+            .get_protocol_components(&Chain::Ethereum, None, None, min_tvl, None, &mut conn)
+            .await
+            .expect("failed retrieving components")
+            .entity
+            .into_iter()
+            .map(|comp| comp.id)
+            .collect::<HashSet<_>>();
+
+        assert_eq!(res, exp);
+    }
+
+    #[tokio::test]
+    async fn test_get_components_with_contracts() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let res = gw
+            .get_components_with_contracts(
+                &Chain::Ethereum,
+                &["state1", "unknown"],
+                Some(&Version::from_block_number(Chain::Ethereum, 1)),
+                &mut conn,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].component.id, "state1");
+        assert_eq!(res[0].contracts.len(), 1);
+        assert_eq!(res[0].contracts[0].address, Bytes::from(WETH));
+        assert_eq!(res[0].contracts[0].code, Bytes::from_str("C0C0C0").unwrap());
+    }
+
+    #[rstest]
+    #[case::dai(&[DAI], HashMap::from([
+        (Bytes::from("0x6b175474e89094c44da98b954eedeac495271d0f"), (
+            "state3".to_string(),
+            Bytes::from("0x00000000000000000000000000000000000000000000006c6b935b8bbd400000")
+        ))]))]
+    #[case::weth(&[WETH], HashMap::from([
+        (Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"), (
+            "state1".to_string(),
+            Bytes::from("0x0000000000000000000000000000000000000000000000000de0b6b3a7640000")
+        ))]))]
+    #[tokio::test]
+    async fn test_get_token_owners(
+        #[case] tokens: &[&str],
+        #[case] exp: HashMap<Address, (String, Bytes)>,
+    ) {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let tokens = tokens
+            .iter()
+            .map(|s| Bytes::from(*s))
+            .collect::<Vec<_>>();
+
+        let res = gw
+            .get_token_owners(&Chain::Ethereum, &tokens, Some(1.0), &mut conn)
+            .await
+            .expect("retrieving components failed");
+
+        assert_eq!(res, exp);
+    }
+
+    #[tokio::test]
+    async fn test_get_token_prices() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let exp = [(Bytes::from(WETH), 1.0), (Bytes::from(USDC), 0.0005)]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let prices = gw
+            .get_token_prices(&Chain::Ethereum, &mut conn)
+            .await
+            .expect("retrieving token prices failed!");
+
+        assert_eq!(prices, exp);
+    }
+
+    #[tokio::test]
+    async fn test_get_protocol_data_modified_at() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        db_fixtures::insert_chain(&mut conn, "arbitrum").await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let ethereum = gw
+            .get_protocol_data_modified_at(&Chain::Ethereum, &mut conn)
+            .await
+            .unwrap();
+        let arbitrum = gw
+            .get_protocol_data_modified_at(&Chain::Arbitrum, &mut conn)
+            .await
+            .unwrap();
+
+        assert!(ethereum.is_some());
+        assert_eq!(arbitrum, None);
+    }
+
+    #[tokio::test]
+    async fn test_token_overrides() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let weth = Bytes::from(WETH);
+        let usdc = Bytes::from(USDC);
+        let token_override = |address: &Bytes, symbol: &str| TokenOverride {
+            chain: Chain::Ethereum,
+            address: address.clone(),
+            symbol: Some(symbol.to_string()),
+            display_decimals: None,
+            logo_uri: None,
+        };
+        gw.upsert_token_overrides(
+            &[token_override(&weth, "WETH.e"), token_override(&usdc, "USDC.e")],
+            &mut conn,
+        )
+        .await
+        .expect("upserting overrides failed");
+        let replacement = TokenOverride {
+            display_decimals: Some(6),
+            logo_uri: Some("https://example.com/weth.png".to_string()),
+            ..token_override(&weth, "WETH")
+        };
+        gw.upsert_token_overrides(std::slice::from_ref(&replacement), &mut conn)
+            .await
+            .expect("replacing override failed");
+        gw.delete_token_overrides(&Chain::Ethereum, &[&usdc], &mut conn)
+            .await
+            .expect("deleting override failed");
+        let unknown = gw
+            .upsert_token_overrides(&[token_override(&Bytes::from("0x01"), "X")], &mut conn)
+            .await;
+
+        let overrides = gw
+            .get_token_overrides(&Chain::Ethereum, None, &mut conn)
+            .await
+            .expect("retrieving overrides failed");
+
+        assert_eq!(overrides, vec![replacement]);
+        assert!(matches!(unknown, Err(StorageError::NotFound(_, _))));
+    }
+
+    #[tokio::test]
+    async fn test_token_supplies() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let weth = Bytes::from(WETH);
+        let usdc = Bytes::from(USDC);
+        let t0 = "2020-01-01T00:00:00"
+            .parse::<NaiveDateTime>()
+            .unwrap();
+        let t1 = "2020-01-02T00:00:00"
+            .parse::<NaiveDateTime>()
+            .unwrap();
+        gw.add_token_supplies(
+            &Chain::Ethereum,
+            &HashMap::from([
+                (weth.clone(), Bytes::from("0x64")),
+                (usdc.clone(), Bytes::from("0x07")),
+            ]),
+            t0,
+            &mut conn,
+        )
+        .await
+        .expect("adding supplies failed");
+        gw.add_token_supplies(
+            &Chain::Ethereum,
+            &HashMap::from([(weth.clone(), Bytes::from("0xc8"))]),
+            t1,
+            &mut conn,
+        )
+        .await
+        .expect("adding supplies failed");
+
+        let latest = gw
+            .get_token_supplies(&Chain::Ethereum, &[weth.clone(), usdc.clone()], None, &mut conn)
+            .await
+            .expect("retrieving supplies failed");
+        let historical = gw
+            .get_token_supplies(
+                &Chain::Ethereum,
+                &[weth.clone(), usdc.clone()],
+                Some(&Version::from_ts(t0)),
+                &mut conn,
+            )
+            .await
+            .expect("retrieving supplies failed");
+
+        assert_eq!(
+            latest,
+            HashMap::from([
+                (weth.clone(), Bytes::from("0xc8")),
+                (usdc.clone(), Bytes::from("0x07"))
+            ])
+        );
+        assert_eq!(
+            historical,
+            HashMap::from([(weth, Bytes::from("0x64")), (usdc, Bytes::from("0x07"))])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_component_balances() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+        let exp: HashMap<_, _> = [
+            (
+                "state1",
+                Bytes::from(WETH),
+                ComponentBalance::new(
+                    Bytes::from(WETH),
+                    Balance::from(10u128.pow(18)).lpad(32, 0),
+                    1e18,
+                    Bytes::zero(32),
+                    "state1",
+                ),
+            ),
+            (
+                "state1",
+                Bytes::from(USDC),
+                ComponentBalance::new(
+                    Bytes::from(USDC),
+                    Balance::from(2000 * 10u128.pow(6)).lpad(32, 0),
+                    2000000000.0,
+                    Bytes::zero(32),
+                    "state1",
+                ),
+            ),
+        ]
+        .into_iter()
+        .group_by(|e| e.0)
+        .into_iter()
+        .map(|(cid, group)| {
+            (
+                cid.to_owned(),
+                group
+                    .map(|(_, addr, bal)| (addr, bal))
+                    .collect::<HashMap<_, _>>(),
+            )
+        })
+        .collect();
+
+        let res = gw
+            .get_component_balances(&Chain::Ethereum, Some(&["state1"]), None, &mut conn)
+            .await
+            .expect("retrieving balances failed!");
+
+        assert_eq!(res, exp);
+    }
+
+    #[tokio::test]
+    async fn test_get_balance_history() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let protocol_component_id = schema::protocol_component::table
+            .filter(schema::protocol_component::external_id.eq("state3"))
+            .select(schema::protocol_component::id)
+            .first::<i64>(&mut conn)
+            .await
+            .expect("Failed to fetch protocol component id");
+        let weth_id = schema::token::table
+            .filter(schema::token::symbol.eq("WETH"))
+            .select(schema::token::id)
+            .first::<i64>(&mut conn)
+            .await
+            .expect("Failed to fetch token id");
+        let tx_hash =
+            Bytes::from_str("0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7")
+                .expect("valid txhash");
+        let (txn_id, ts) = schema::transaction::table
+            .inner_join(schema::block::table)
+            .filter(schema::transaction::hash.eq(tx_hash.to_vec()))
+            .select((schema::transaction::id, schema::block::ts))
+            .first::<(i64, NaiveDateTime)>(&mut conn)
+            .await
+            .expect("Failed to fetch transaction id");
+        // WETH balance changes from 1e18 to 2e18 half an hour into the first sample.
+        diesel::update(
+            schema::component_balance::table
+                .filter(schema::component_balance::protocol_component_id.eq(protocol_component_id))
+                .filter(schema::component_balance::token_id.eq(weth_id)),
+        )
+        .set(schema::component_balance::valid_to.eq(ts))
+        .execute(&mut conn)
+        .await
+        .expect("updating valid_to failed");
+        db_fixtures::insert_component_balance(
+            &mut conn,
+            Balance::from(2 * 10u128.pow(18)).lpad(32, 0),
+            Balance::from(10u128.pow(18)).lpad(32, 0),
+            2e18,
+            weth_id,
+            txn_id,
+            protocol_component_id,
+            None,
+        )
+        .await;
+        let midnight = db_fixtures::yesterday_midnight();
+
+        let res = gw
+            .get_balance_history(
+                &Chain::Ethereum,
+                "state3",
+                &Bytes::from(WETH),
+                midnight - chrono::Duration::hours(2),
+                midnight + chrono::Duration::hours(1),
+                SampleInterval::Hourly,
+                &mut conn,
+            )
+            .await
+            .expect("retrieving balance history failed");
+
+        let exp = vec![
+            BalanceSample { ts: midnight, open: 1e18, high: 2e18, low: 1e18, close: 2e18 },
+            BalanceSample {
+                ts: midnight + chrono::Duration::hours(1),
+                open: 2e18,
+                high: 2e18,
+                low: 2e18,
+                close: 2e18,
+            },
+        ];
+        assert_eq!(res, exp);
+    }
+
     #[tokio::test]
-    async fn test_get_protocol_components_with_external_id_only(#[case] external_id: String) {
+    async fn test_add_and_get_component_events() {
         let mut conn = setup_db().await;
-        let (_, tx_hashes) = setup_data(&mut conn).await;
+        setup_data(&mut conn).await;
         let gw = EVMGateway::from_connection(&mut conn).await;
+        let transfer_topic =
+            Bytes::from("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+        let events = vec![
+            ComponentEvent::new(
+                "state3",
+                Bytes::from("0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7"),
+                4,
+                Bytes::from(WETH),
+                vec![transfer_topic.clone()],
+                Bytes::from("0x02"),
+            ),
+            ComponentEvent::new(
+                "state3",
+                Bytes::from("0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945"),
+                7,
+                Bytes::from(WETH),
+                vec![transfer_topic],
+                Bytes::from("0x01"),
+            ),
+        ];
 
-        let temp_ids_array = [external_id.as_str()];
-        let ids = Some(temp_ids_array.as_slice());
-        let chain = Chain::Ethereum;
-
-        let result = gw
-            .get_protocol_components(&chain, None, ids, None, None, &mut conn)
+        gw.add_component_events(&events, &Chain::Ethereum, &mut conn)
             .await
-            .unwrap()
-            .entity;
+            .expect("inserting events failed");
+        // Reprocessing the same logs must not duplicate them.
+        gw.add_component_events(&events, &Chain::Ethereum, &mut conn)
+            .await
+            .expect("inserting events again failed");
 
-        match external_id.as_str() {
-            "state1" => {
-                assert_eq!(result.len(), 1);
+        let res = gw
+            .get_component_events(&Chain::Ethereum, &["state3"], 1..=2, &mut conn)
+            .await
+            .expect("retrieving events failed");
+        assert_eq!(res, vec![events[1].clone(), events[0].clone()]);
 
-                let pc = &result[0];
-                assert_eq!(pc.id, external_id.to_string());
-                assert_eq!(pc.protocol_system, "ambient");
-                assert_eq!(pc.chain, Chain::Ethereum);
-                assert_eq!(pc.creation_tx, Bytes::from(tx_hashes[0].as_str()));
-            }
-            "state2" => {
-                assert_eq!(result.len(), 0)
-            }
-            _ => {}
-        }
+        let res = gw
+            .get_component_events(&Chain::Ethereum, &["state3"], 2..=2, &mut conn)
+            .await
+            .expect("retrieving events failed");
+        assert_eq!(res, vec![events[0].clone()]);
     }
 
     #[tokio::test]
-    async fn test_get_protocol_components_with_system_and_ids() {
+    async fn test_run_with_deadline_times_out() {
+        use diesel_async::scoped_futures::ScopedFutureExt;
+
         let mut conn = setup_db().await;
-        let (_, tx_hashes) = setup_data(&mut conn).await;
-        let gw = EVMGateway::from_connection(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn)
+            .await
+            .with_query_deadline(std::time::Duration::from_millis(50));
 
-        let system = "ambient".to_string();
-        let ids = Some(["state1", "state2"].as_slice());
-        let chain = Chain::Ethereum;
-        let result = gw
-            .get_protocol_components(&chain, Some(system), ids, None, None, &mut conn)
+        let res = gw
+            .run_with_deadline(&mut conn, |conn| {
+                async move {
+                    diesel::sql_query("SELECT pg_sleep(1)")
+                        .execute(conn)
+                        .await
+                        .map_err(|err| StorageError::from(PostgresError::from(err)))?;
+                    Ok(())
+                }
+                .scope_boxed()
+            })
             .await;
 
-        let components = result.unwrap().entity;
-        assert_eq!(components.len(), 1);
-
-        let pc = &components[0];
-        assert_eq!(pc.id, "state1".to_string());
-        assert_eq!(pc.protocol_system, "ambient");
-        assert_eq!(pc.chain, Chain::Ethereum);
-        assert_eq!(pc.creation_tx, Bytes::from(tx_hashes[0].as_str()));
+        assert!(matches!(res, Err(StorageError::Timeout(_))), "{res:?}");
     }
 
-    #[rstest]
-    #[case::ethereum(Chain::Ethereum, & ["state1", "state3", "no_tvl"])]
-    #[case::starknet(Chain::Starknet, & ["state2"])]
     #[tokio::test]
-    async fn test_get_protocol_components_with_chain_filter(
-        #[case] chain: Chain,
-        #[case] exp_ids: &[&str],
-    ) {
+    async fn test_update_component_activity() {
         let mut conn = setup_db().await;
         setup_data(&mut conn).await;
         let gw = EVMGateway::from_connection(&mut conn).await;
-        let exp = exp_ids
+
+        // Nothing changed after now, so every component becomes inactive.
+        let now = Utc::now().naive_utc();
+        let flagged = gw
+            .update_component_activity(&Chain::Ethereum, now, &mut conn)
+            .await
+            .expect("updating component activity failed");
+        let flagged_ids = flagged
             .iter()
-            .map(|&s| s.to_owned())
+            .map(|row| row.external_id.as_str())
             .collect::<HashSet<_>>();
+        assert_eq!(flagged_ids, HashSet::from(["state1", "state2", "state3", "no_tvl"]));
+        assert!(flagged
+            .iter()
+            .all(|row| row.inactive_since < Some(now)));
 
-        let components = gw
-            .get_protocol_components(&chain, None, None, None, None, &mut conn)
+        // Flags are only toggled once.
+        let res = gw
+            .update_component_activity(&Chain::Ethereum, now, &mut conn)
             .await
-            .expect("failed retrieving components")
-            .entity
-            .into_iter()
-            .map(|c| c.id)
-            .collect::<HashSet<_>>();
+            .expect("updating component activity failed");
+        assert!(res.is_empty());
 
-        assert_eq!(components, exp);
+        let inactive = gw
+            .get_inactive_components(&Chain::Ethereum, &["state1", "unknown"], &mut conn)
+            .await
+            .expect("retrieving inactive components failed");
+        assert_eq!(inactive.keys().collect::<Vec<_>>(), vec!["state1"]);
+
+        // Every component changed after the epoch, so all of them become active again.
+        let epoch = NaiveDateTime::default();
+        let res = gw
+            .update_component_activity(&Chain::Ethereum, epoch, &mut conn)
+            .await
+            .expect("updating component activity failed");
+        assert_eq!(res.len(), flagged.len());
+        assert!(res
+            .iter()
+            .all(|row| row.inactive_since.is_none()));
     }
 
-    #[rstest]
-    #[case::empty(Some(10.0), & [])]
-    #[case::all(None, & ["state1", "state3", "no_tvl"])]
-    #[case::with_tvl(Some(0.0), & ["state1", "state3"])]
-    #[case::with_tvl(Some(1.0), & ["state1"])]
     #[tokio::test]
-    async fn test_get_protocol_components_with_min_tvl(
-        #[case] min_tvl: Option<f64>,
-        #[case] exp_ids: &[&str],
-    ) {
+    async fn test_get_component_extractors() {
         let mut conn = setup_db().await;
         setup_data(&mut conn).await;
-        let exp = exp_ids
-            .iter()
-            .map(|&s| s.to_owned())
-            .collect::<HashSet<_>>();
         let gw = EVMGateway::from_connection(&mut conn).await;
+        db_fixtures::insert_protocol_type(&mut conn, "Test_Type_1", None, None, None).await;
+        let component = ProtocolComponentBuilder::new(
+            "extracted_component",
+            "ambient",
+            "Test_Type_1",
+            Chain::Ethereum,
+        )
+        .tokens(vec![Bytes::from(WETH)])
+        .creation_tx(Bytes::from(
+            "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945",
+        ))
+        .build()
+        .unwrap();
+        // The writer sets this for the duration of an extractor's transaction.
+        diesel::sql_query("SELECT set_config('tycho.extractor', 'vm:ambient', true)")
+            .execute(&mut conn)
+            .await
+            .expect("setting extractor failed");
 
-        let res = gw
-            .get_protocol_components(&Chain::Ethereum, None, None, min_tvl, None, &mut conn)
+        gw.add_protocol_components(slice::from_ref(&component), &mut conn)
             .await
-            .expect("failed retrieving components")
-            .entity
-            .into_iter()
-            .map(|comp| comp.id)
-            .collect::<HashSet<_>>();
+            .expect("adding components failed");
 
-        assert_eq!(res, exp);
+        let all = gw
+            .get_component_extractors(&Chain::Ethereum, None, None, &mut conn)
+            .await
+            .expect("retrieving component extractors failed");
+        assert_eq!(
+            all,
+            HashMap::from([("extracted_component".to_string(), "vm:ambient".to_string())])
+        );
+        let filtered = gw
+            .get_component_extractors(
+                &Chain::Ethereum,
+                Some(&["extracted_component", "state1"]),
+                Some("uniswap_v2"),
+                &mut conn,
+            )
+            .await
+            .expect("retrieving component extractors failed");
+        assert!(filtered.is_empty());
     }
 
-    #[rstest]
-    #[case::dai(&[DAI], HashMap::from([
-        (Bytes::from("0x6b175474e89094c44da98b954eedeac495271d0f"), (
-            "state3".to_string(),
-            Bytes::from("0x00000000000000000000000000000000000000000000006c6b935b8bbd400000")
-        ))]))]
-    #[case::weth(&[WETH], HashMap::from([
-        (Bytes::from("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"), (
-            "state1".to_string(),
-            Bytes::from("0x0000000000000000000000000000000000000000000000000de0b6b3a7640000")
-        ))]))]
     #[tokio::test]
-    async fn test_get_token_owners(
-        #[case] tokens: &[&str],
-        #[case] exp: HashMap<Address, (String, Bytes)>,
-    ) {
+    async fn test_get_component_ids_by_tokens() {
         let mut conn = setup_db().await;
         setup_data(&mut conn).await;
         let gw = EVMGateway::from_connection(&mut conn).await;
-        let tokens = tokens
-            .iter()
-            .map(|s| Bytes::from(*s))
-            .collect::<Vec<_>>();
+        db_fixtures::insert_protocol_type(&mut conn, "Test_Type_1", None, None, None).await;
+        let component =
+            ProtocolComponentBuilder::new("weth_usdc", "ambient", "Test_Type_1", Chain::Ethereum)
+                .tokens(vec![Bytes::from(WETH), Bytes::from(USDC)])
+                .creation_tx(Bytes::from(
+                    "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945",
+                ))
+                .build()
+                .unwrap();
+        gw.add_protocol_components(slice::from_ref(&component), &mut conn)
+            .await
+            .expect("adding components failed");
 
-        let res = gw
-            .get_token_owners(&Chain::Ethereum, &tokens, Some(1.0), &mut conn)
+        let pair_key = schema::protocol_component::table
+            .filter(schema::protocol_component::external_id.eq("weth_usdc"))
+            .select(schema::protocol_component::pair_key)
+            .first::<Option<String>>(&mut conn)
+            .await
+            .expect("retrieving pair key failed");
+        let reversed = gw
+            .get_component_ids_by_tokens(
+                &Chain::Ethereum,
+                &[Bytes::from(USDC), Bytes::from(WETH)],
+                None,
+                &mut conn,
+            )
+            .await
+            .expect("retrieving components by tokens failed");
+        let subset = gw
+            .get_component_ids_by_tokens(&Chain::Ethereum, &[Bytes::from(WETH)], None, &mut conn)
+            .await
+            .expect("retrieving components by tokens failed");
+        let stored = gw
+            .get_protocol_components(
+                &Chain::Ethereum,
+                None,
+                Some(&["weth_usdc"]),
+                None,
+                None,
+                &mut conn,
+            )
             .await
             .expect("retrieving components failed");
 
-        assert_eq!(res, exp);
+        assert_eq!(
+            pair_key.as_deref(),
+            Some(
+                "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48-0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"
+            )
+        );
+        assert_eq!(reversed, vec!["weth_usdc".to_string()]);
+        assert!(subset.is_empty());
+        assert_eq!(stored.entity[0].tokens, vec![Bytes::from(USDC), Bytes::from(WETH)]);
     }
 
     #[tokio::test]
-    async fn test_get_token_prices() {
+    async fn test_rename_protocol_state_attribute() {
         let mut conn = setup_db().await;
         setup_data(&mut conn).await;
         let gw = EVMGateway::from_connection(&mut conn).await;
-        let exp = [(Bytes::from(WETH), 1.0), (Bytes::from(USDC), 0.0005)]
-            .into_iter()
-            .collect::<HashMap<_, _>>();
 
-        let prices = gw
-            .get_token_prices(&Chain::Ethereum, &mut conn)
+        let renamed = gw
+            .rename_protocol_state_attribute(
+                &Chain::Ethereum,
+                "ambient",
+                Some(&["state1"]),
+                "reserve1",
+                "reserves_1",
+                &mut conn,
+            )
             .await
-            .expect("retrieving token prices failed!");
+            .expect("renaming attribute failed");
+        let duplicate = gw
+            .rename_protocol_state_attribute(
+                &Chain::Ethereum,
+                "ambient",
+                None,
+                "reserve2",
+                "reserves_1",
+                &mut conn,
+            )
+            .await;
+        let unknown = gw
+            .rename_protocol_state_attribute(
+                &Chain::Ethereum,
+                "ambient",
+                Some(&["state2"]),
+                "reserve2",
+                "reserves_2",
+                &mut conn,
+            )
+            .await;
 
-        assert_eq!(prices, exp);
+        // Both versions of reserve1 are renamed, including the superseded one
+        assert_eq!(renamed, 2);
+        let names = schema::protocol_state::table
+            .select(schema::protocol_state::attribute_name)
+            .order_by(schema::protocol_state::attribute_name)
+            .load::<String>(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(names, vec!["reserve2", "reserves_1", "reserves_1"]);
+        let recorded = schema::protocol_state_attribute_rename::table
+            .select((
+                schema::protocol_state_attribute_rename::old_name,
+                schema::protocol_state_attribute_rename::new_name,
+                schema::protocol_state_attribute_rename::renamed_versions,
+            ))
+            .load::<(String, String, i64)>(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(recorded, vec![("reserve1".to_string(), "reserves_1".to_string(), 2)]);
+        assert!(matches!(duplicate, Err(StorageError::DuplicateEntry(_, _))), "{duplicate:?}");
+        // state2 belongs to another protocol system
+        assert!(matches!(unknown, Err(StorageError::NotFound(_, _))), "{unknown:?}");
     }
 
     #[tokio::test]
-    async fn test_get_component_balances() {
+    async fn test_get_protocol_deployment() {
         let mut conn = setup_db().await;
         setup_data(&mut conn).await;
         let gw = EVMGateway::from_connection(&mut conn).await;
-        let exp: HashMap<_, _> = [
-            (
-                "state1",
-                Bytes::from(WETH),
-                ComponentBalance::new(
-                    Bytes::from(WETH),
-                    Balance::from(10u128.pow(18)).lpad(32, 0),
-                    1e18,
-                    Bytes::zero(32),
-                    "state1",
-                ),
-            ),
-            (
-                "state1",
-                Bytes::from(USDC),
-                ComponentBalance::new(
-                    Bytes::from(USDC),
-                    Balance::from(2000 * 10u128.pow(6)).lpad(32, 0),
-                    2000000000.0,
-                    Bytes::zero(32),
-                    "state1",
-                ),
-            ),
-        ]
-        .into_iter()
-        .group_by(|e| e.0)
-        .into_iter()
-        .map(|(cid, group)| {
-            (
-                cid.to_owned(),
-                group
-                    .map(|(_, addr, bal)| (addr, bal))
-                    .collect::<HashMap<_, _>>(),
-            )
-        })
-        .collect();
 
-        let res = gw
-            .get_component_balances(&Chain::Ethereum, Some(&["state1"]), None, &mut conn)
+        let deployment = gw
+            .get_protocol_deployment(&Chain::Ethereum, "ambient", &mut conn)
             .await
-            .expect("retrieving balances failed!");
+            .expect("loading deployment failed");
 
-        assert_eq!(res, exp);
+        assert_eq!(deployment.chain, Chain::Ethereum);
+        assert!(deployment
+            .components
+            .iter()
+            .all(|c| c.protocol_system == "ambient"));
+        assert!(!deployment
+            .components
+            .iter()
+            .any(|c| c.id == "state2"));
+        // Superseded versions don't add names
+        assert_eq!(
+            deployment.state_attributes["state1"],
+            HashSet::from(["reserve1".to_string(), "reserve2".to_string()])
+        );
     }
 
     #[tokio::test]
@@ -3857,6 +5827,40 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_get_protocol_system_summaries() {
+        let mut conn = setup_db().await;
+        setup_data(&mut conn).await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let res = gw
+            .get_protocol_system_summaries(&Chain::Ethereum, &mut conn)
+            .await
+            .expect("retrieving protocol system summaries failed");
+
+        assert_eq!(
+            res,
+            vec![
+                ProtocolSystemSummary {
+                    name: "ambient".to_string(),
+                    component_count: 3,
+                    implementation_types: vec![ImplementationType::Custom],
+                    // latest change is the reserve1 update in the second block
+                    last_updated_block: Some(2),
+                    last_updated_ts: Some(db_fixtures::yesterday_half_past_midnight()),
+                },
+                // only has components on starknet
+                ProtocolSystemSummary {
+                    name: "zigzag".to_string(),
+                    component_count: 0,
+                    implementation_types: vec![],
+                    last_updated_block: None,
+                    last_updated_ts: None,
+                },
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_get_protocol_systems_with_pagination() {
         let mut conn = setup_db().await;
@@ -0,0 +1,145 @@
+//! Block messages extractors could not decode.
+//!
+//! Messages are keyed by extractor, chain, block number and payload type. A message received again
+//! for the same block, e.g. after a restart or a reorg, replaces the stored one.
+use diesel::{upsert::excluded, ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tracing::instrument;
+use tycho_common::{
+    models::{blockchain::QuarantinedMessage, BlockHash, Chain},
+    storage::StorageError,
+    Bytes,
+};
+
+use super::{schema, PostgresError, PostgresGateway};
+
+impl PostgresGateway {
+    /// Returns the messages quarantined by `extractor`, ordered by block number.
+    #[instrument(skip(self, conn))]
+    pub async fn get_quarantined_messages(
+        &self,
+        extractor: &str,
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<QuarantinedMessage>, StorageError> {
+        use schema::quarantined_message::dsl;
+
+        let chain_id = self.get_chain_id(chain)?;
+        let rows = dsl::quarantined_message
+            .filter(dsl::chain_id.eq(chain_id))
+            .filter(dsl::extractor.eq(extractor))
+            .order_by((dsl::block_number, dsl::type_url))
+            .select((
+                dsl::block_number,
+                dsl::block_hash,
+                dsl::type_url,
+                dsl::payload,
+                dsl::kind,
+                dsl::error,
+            ))
+            .get_results::<(i64, Option<BlockHash>, String, Bytes, String, String)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(rows
+            .into_iter()
+            .map(|(block_number, block_hash, type_url, payload, kind, error)| {
+                QuarantinedMessage {
+                    block_number: block_number as u64,
+                    block_hash,
+                    type_url,
+                    payload,
+                    kind,
+                    error,
+                }
+            })
+            .collect())
+    }
+
+    /// Stores a message `extractor` could not decode, replacing a message stored for the same
+    /// block and payload type.
+    #[instrument(skip(self, message, conn), fields(block_number = message.block_number))]
+    pub async fn upsert_quarantined_message(
+        &self,
+        extractor: &str,
+        chain: &Chain,
+        message: &QuarantinedMessage,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::quarantined_message::dsl;
+
+        let chain_id = self.get_chain_id(chain)?;
+        diesel::insert_into(dsl::quarantined_message)
+            .values((
+                dsl::extractor.eq(extractor),
+                dsl::chain_id.eq(chain_id),
+                dsl::block_number.eq(message.block_number as i64),
+                dsl::block_hash.eq(&message.block_hash),
+                dsl::type_url.eq(&message.type_url),
+                dsl::payload.eq(&message.payload),
+                dsl::kind.eq(&message.kind),
+                dsl::error.eq(&message.error),
+            ))
+            .on_conflict((dsl::chain_id, dsl::extractor, dsl::block_number, dsl::type_url))
+            .do_update()
+            .set((
+                dsl::block_hash.eq(excluded(dsl::block_hash)),
+                dsl::payload.eq(excluded(dsl::payload)),
+                dsl::kind.eq(excluded(dsl::kind)),
+                dsl::error.eq(excluded(dsl::error)),
+            ))
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+    use crate::postgres::db_fixtures;
+
+    fn message(block_number: u64, payload: &str) -> QuarantinedMessage {
+        QuarantinedMessage {
+            block_number,
+            block_hash: Some(Bytes::from(vec![block_number as u8; 32])),
+            type_url: "type.googleapis.com/tycho.evm.v1.BlockChanges".to_string(),
+            payload: Bytes::from(payload),
+            kind: "invalid_length".to_string(),
+            error: "invalid length: balance".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quarantined_messages() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+
+        for msg in [message(2, "0x02"), message(1, "0x01"), message(2, "0x0202")] {
+            gw.upsert_quarantined_message("uniswap_v2", &Chain::Ethereum, &msg, &mut conn)
+                .await
+                .unwrap();
+        }
+
+        let quarantined = gw
+            .get_quarantined_messages("uniswap_v2", &Chain::Ethereum, &mut conn)
+            .await
+            .unwrap();
+        let other_extractor = gw
+            .get_quarantined_messages("uniswap_v3", &Chain::Ethereum, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(quarantined, vec![message(1, "0x01"), message(2, "0x0202")]);
+        assert!(other_extractor.is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! Plan checks of the hottest versioned queries.
+//!
+//! On a dataset as small as the test fixtures the planner prefers sequential scans no matter
+//! which indices exist. The checks therefore disable sequential scans for their transaction: a
+//! plan that still contains one has no index serving the query's predicates.
+use diesel::{
+    pg::Pg,
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
+    sql_types::Text,
+    QueryResult,
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+/// Wraps a query into `EXPLAIN`, binding the same parameters as the query itself.
+#[derive(Debug, Clone, Copy, QueryId)]
+struct Explain<Q>(Q);
+
+impl<Q: QueryFragment<Pg>> QueryFragment<Pg> for Explain<Q> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        out.push_sql("EXPLAIN ");
+        self.0.walk_ast(out.reborrow())
+    }
+}
+
+impl<Q> Query for Explain<Q> {
+    type SqlType = Text;
+}
+
+/// Returns the plan of `query`, one line per node.
+async fn explain<Q>(query: Q, conn: &mut AsyncPgConnection) -> QueryResult<String>
+where
+    Q: QueryFragment<Pg> + QueryId + Send,
+{
+    let lines = Explain(query)
+        .load::<String>(conn)
+        .await?;
+    Ok(lines.join("\n"))
+}
+
+mod test {
+    use chrono::NaiveDateTime;
+    use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+    use diesel_async::AsyncConnection;
+    use tycho_common::Bytes;
+
+    use super::*;
+    use crate::postgres::{db_fixtures, orm, schema};
+
+    async fn setup_db() -> AsyncPgConnection {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        conn
+    }
+
+    /// Seeds two components with a current and an outdated state each.
+    async fn setup_data(conn: &mut AsyncPgConnection) -> (i64, Vec<i64>) {
+        let chain_id = db_fixtures::insert_chain(conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            conn,
+            &[
+                (
+                    blk[0],
+                    1i64,
+                    "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945",
+                ),
+                (
+                    blk[1],
+                    1i64,
+                    "0x3108322284d0a89a7accb288d1a94384d499504fe7e04441b0706c7628dee7b7",
+                ),
+            ],
+        )
+        .await;
+        let system_id = db_fixtures::insert_protocol_system(conn, "ambient".to_owned()).await;
+        let type_id = db_fixtures::insert_protocol_type(conn, "pool", None, None, None).await;
+        let mut component_ids = Vec::new();
+        for external_id in ["pool_0", "pool_1"] {
+            let component_id = db_fixtures::insert_protocol_component(
+                conn,
+                external_id,
+                chain_id,
+                system_id,
+                type_id,
+                txn[0],
+                None,
+                None,
+            )
+            .await;
+            db_fixtures::insert_protocol_state(
+                conn,
+                component_id,
+                txn[0],
+                "reserve".to_owned(),
+                Bytes::from(1u8),
+                None,
+                Some(txn[1]),
+            )
+            .await;
+            db_fixtures::insert_protocol_state(
+                conn,
+                component_id,
+                txn[1],
+                "reserve".to_owned(),
+                Bytes::from(2u8),
+                Some(Bytes::from(1u8)),
+                None,
+            )
+            .await;
+            component_ids.push(component_id);
+        }
+        diesel::sql_query("SET LOCAL enable_seqscan = off")
+            .execute(conn)
+            .await
+            .unwrap();
+        (chain_id, component_ids)
+    }
+
+    fn assert_no_seq_scan(plan: &str) {
+        assert!(!plan.contains("Seq Scan"), "plan scans sequentially:\n{plan}");
+    }
+
+    #[tokio::test]
+    async fn test_protocol_state_queries_use_indices() {
+        let mut conn = setup_db().await;
+        let (chain_id, component_ids) = setup_data(&mut conn).await;
+        let historical = Some(db_fixtures::yesterday_midnight());
+
+        for version_ts in [None::<NaiveDateTime>, historical] {
+            let states = orm::ProtocolState::valid_at(version_ts)
+                .filter(schema::protocol_component::id.eq_any(&component_ids))
+                .order_by(schema::protocol_state::protocol_component_id)
+                .select((orm::ProtocolState::as_select(), schema::protocol_component::external_id));
+            let components = orm::ProtocolState::components_with_state(&chain_id, version_ts)
+                .select(schema::protocol_component::id)
+                .order_by(schema::protocol_component::id);
+
+            assert_no_seq_scan(
+                &explain(states, &mut conn)
+                    .await
+                    .unwrap(),
+            );
+            assert_no_seq_scan(
+                &explain(components, &mut conn)
+                    .await
+                    .unwrap(),
+            );
+        }
+    }
+}
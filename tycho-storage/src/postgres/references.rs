@@ -0,0 +1,297 @@
+//! Referential pre-checks of write batches, see [`GatewayBuilder::set_strict_writes`].
+//!
+//! A write that references a transaction, account, token or component which was never stored
+//! fails somewhere in the middle of its batch, often with a diesel error that only names a foreign
+//! key. With strict writes the write executor first collects the references of all operations of
+//! a batch, drops those the batch creates itself and looks up the rest with one query per kind of
+//! entity. If any of them is missing the batch fails with [`StorageError::MissingReferences`],
+//! listing all of them, before anything is written.
+//!
+//! [`GatewayBuilder::set_strict_writes`]: super::builder::GatewayBuilder::set_strict_writes
+use std::collections::HashSet;
+
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tycho_common::{
+    models::{Address, Chain, ComponentId, TxHash},
+    storage::{MissingReferences, StorageError},
+};
+
+use super::{cache::WriteOp, orm, schema, PostgresError, PostgresGateway};
+
+#[derive(Debug, Default, PartialEq)]
+struct References {
+    txs: HashSet<TxHash>,
+    accounts: HashSet<Address>,
+    tokens: HashSet<Address>,
+    components: HashSet<ComponentId>,
+}
+
+impl References {
+    /// References of `self` that are not part of `other`.
+    fn difference(self, other: &References) -> References {
+        References {
+            txs: &self.txs - &other.txs,
+            accounts: &self.accounts - &other.accounts,
+            tokens: &self.tokens - &other.tokens,
+            components: &self.components - &other.components,
+        }
+    }
+}
+
+/// Collects the references `ops` depend on, without the entities they create themselves.
+fn unresolved_references(ops: &[WriteOp]) -> References {
+    let mut required = References::default();
+    let mut created = References::default();
+    for op in ops {
+        match op {
+            WriteOp::UpsertBlock(_) |
+            WriteOp::UpsertBlockDigests(_) |
+            WriteOp::SaveExtractionState(_) |
+            WriteOp::UpsertTracedEntryPoints(_) => {}
+            WriteOp::UpsertTx(txs) => created
+                .txs
+                .extend(txs.iter().map(|tx| tx.hash.clone())),
+            WriteOp::InsertContract(accounts) => {
+                for account in accounts {
+                    created
+                        .accounts
+                        .insert(account.address.clone());
+                    required
+                        .txs
+                        .extend(account.creation_tx.clone());
+                }
+            }
+            WriteOp::UpdateContracts(deltas) => {
+                for (tx, delta) in deltas {
+                    required.txs.insert(tx.clone());
+                    required
+                        .accounts
+                        .insert(delta.address.clone());
+                }
+            }
+            WriteOp::InsertAccountBalances(balances) => {
+                for balance in balances {
+                    required
+                        .txs
+                        .insert(balance.modify_tx.clone());
+                    required
+                        .accounts
+                        .insert(balance.account.clone());
+                    required
+                        .tokens
+                        .insert(balance.token.clone());
+                }
+            }
+            WriteOp::InsertProtocolComponents(components) => {
+                for component in components {
+                    created
+                        .components
+                        .insert(component.id.clone());
+                    required
+                        .txs
+                        .insert(component.creation_tx.clone());
+                    required
+                        .tokens
+                        .extend(component.tokens.iter().cloned());
+                    required.accounts.extend(
+                        component
+                            .contract_addresses
+                            .iter()
+                            .cloned(),
+                    );
+                }
+            }
+            WriteOp::InsertTokens(tokens) => {
+                // Tokens are stored together with their account.
+                for token in tokens {
+                    created
+                        .tokens
+                        .insert(token.address.clone());
+                    created
+                        .accounts
+                        .insert(token.address.clone());
+                }
+            }
+            WriteOp::UpdateTokens(tokens) => required
+                .tokens
+                .extend(tokens.iter().map(|t| t.address.clone())),
+            WriteOp::InsertComponentBalances(balances) => {
+                for balance in balances {
+                    required
+                        .txs
+                        .insert(balance.modify_tx.clone());
+                    required
+                        .tokens
+                        .insert(balance.token.clone());
+                    required
+                        .components
+                        .insert(balance.component_id.clone());
+                }
+            }
+            WriteOp::UpsertProtocolState(deltas) => {
+                for (tx, delta) in deltas {
+                    required.txs.insert(tx.clone());
+                    required
+                        .components
+                        .insert(delta.component_id.clone());
+                }
+            }
+            WriteOp::InsertComponentEvents(events) => {
+                for event in events {
+                    required
+                        .txs
+                        .insert(event.tx_hash.clone());
+                    required
+                        .components
+                        .insert(event.component_id.clone());
+                }
+            }
+            WriteOp::InsertEntryPoints(entry_points) => required
+                .components
+                .extend(entry_points.keys().cloned()),
+            WriteOp::InsertEntryPointTracingParams(params) => required.components.extend(
+                params
+                    .values()
+                    .flatten()
+                    .filter_map(|(_, component_id)| component_id.clone()),
+            ),
+        }
+    }
+    required.difference(&created)
+}
+
+/// Values of `required` not contained in `stored`, sorted.
+fn missing<T: Ord + Clone + std::hash::Hash>(
+    required: &HashSet<T>,
+    stored: impl IntoIterator<Item = T>,
+) -> Vec<T> {
+    let stored: HashSet<T> = stored.into_iter().collect();
+    let mut missing = required
+        .difference(&stored)
+        .cloned()
+        .collect::<Vec<_>>();
+    missing.sort();
+    missing
+}
+
+impl PostgresGateway {
+    /// Fails with [`StorageError::MissingReferences`] if `ops` reference entities that are
+    /// neither stored nor created by `ops`.
+    pub(crate) async fn check_references(
+        &self,
+        chain: &Chain,
+        ops: &[WriteOp],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let references = unresolved_references(ops);
+        let chain_id = self.get_chain_id(chain)?;
+
+        let mut report = MissingReferences::default();
+        if !references.txs.is_empty() {
+            let hashes = references
+                .txs
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            let stored = orm::Transaction::ids_by_hash(&hashes, conn).await?;
+            report.transactions = missing(&references.txs, stored.into_keys());
+        }
+        if !references.accounts.is_empty() {
+            let stored = schema::account::table
+                .filter(schema::account::chain_id.eq(chain_id))
+                .filter(schema::account::address.eq_any(&references.accounts))
+                .select(schema::account::address)
+                .get_results::<Address>(conn)
+                .await
+                .map_err(PostgresError::from)?;
+            report.accounts = missing(&references.accounts, stored);
+        }
+        if !references.tokens.is_empty() {
+            let stored = schema::token::table
+                .inner_join(schema::account::table)
+                .filter(schema::account::chain_id.eq(chain_id))
+                .filter(schema::account::address.eq_any(&references.tokens))
+                .select(schema::account::address)
+                .get_results::<Address>(conn)
+                .await
+                .map_err(PostgresError::from)?;
+            report.tokens = missing(&references.tokens, stored);
+        }
+        if !references.components.is_empty() {
+            let ids = references
+                .components
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            let stored = orm::ProtocolComponent::ids_by_external_ids(&ids, chain_id, conn)
+                .await
+                .map_err(PostgresError::from)?;
+            report.components = missing(
+                &references.components,
+                stored
+                    .into_iter()
+                    .map(|(_, external_id)| external_id),
+            );
+        }
+
+        if report.is_empty() {
+            Ok(())
+        } else {
+            Err(StorageError::MissingReferences(report))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use tycho_common::{
+        models::{
+            blockchain::Transaction,
+            protocol::{ComponentBalance, ProtocolComponent, ProtocolComponentStateDelta},
+        },
+        Bytes,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_unresolved_references() {
+        let tx = Transaction { hash: Bytes::from("0x01"), ..Default::default() };
+        let component = ProtocolComponent {
+            id: "pool_a".to_string(),
+            tokens: vec![Bytes::from("0xaa")],
+            creation_tx: tx.hash.clone(),
+            ..Default::default()
+        };
+        let ops = vec![
+            WriteOp::UpsertTx(vec![tx]),
+            WriteOp::InsertProtocolComponents(vec![component]),
+            WriteOp::InsertComponentBalances(vec![ComponentBalance::new(
+                Bytes::from("0xaa"),
+                Bytes::from("0x10"),
+                16.0,
+                Bytes::from("0x02"),
+                "pool_a",
+            )]),
+            WriteOp::UpsertProtocolState(vec![(
+                Bytes::from("0x01"),
+                ProtocolComponentStateDelta::new("pool_b", HashMap::new(), HashSet::new()),
+            )]),
+        ];
+
+        let res = unresolved_references(&ops);
+
+        assert_eq!(
+            res,
+            References {
+                txs: HashSet::from([Bytes::from("0x02")]),
+                tokens: HashSet::from([Bytes::from("0xaa")]),
+                components: HashSet::from(["pool_b".to_string()]),
+                ..Default::default()
+            }
+        );
+    }
+}
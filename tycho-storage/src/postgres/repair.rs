@@ -0,0 +1,572 @@
+//! Selective re-extraction of a block range for a single protocol system.
+//!
+//! A repair rewrites the versioned state a protocol system wrote within a block range, without
+//! touching any other protocol system. It runs in three steps around a replay of the range:
+//!
+//! 1. [`PostgresGateway::quarantine_range`] moves all state versions written in or after the range
+//!    into `quarantined_row` and makes the versions that were valid before the range current again.
+//!    Versions written after the range are marked as parked.
+//! 2. The extractor replays the range, its versioning builds on the reopened state.
+//! 3. [`PostgresGateway::restore_quarantined_range`] puts the parked versions back and closes the
+//!    replayed versions at the first parked version of each entity.
+//!
+//! [`PostgresGateway::verify_range_continuity`] then checks blocks and state versions across both
+//! boundaries of the range. Quarantined versions from within the range are kept for inspection.
+//!
+//! Only protocol state, component balances, contract storage and account balances are rewritten.
+//! Components, accounts and contract code keep their rows, the replay upserts them. Contracts are
+//! scoped through the components holding them, so state of a contract shared with another
+//! protocol system is rewritten as well.
+use chrono::Utc;
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Timestamptz, Varchar},
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use itertools::Itertools;
+use tracing::{info, instrument};
+use tycho_common::{
+    models::Chain,
+    storage::{BlockIdentifier, StorageError},
+};
+
+use super::{
+    audit::{record_operation, AuditRecord},
+    orm, schema, PostgresError, PostgresGateway, MAX_TS,
+};
+
+/// Ids of the components of a protocol system. Expects the chain id as `$1` and the name of the
+/// protocol system as `$2`.
+const COMPONENT_SCOPE: &str = r#"
+    SELECT pc.id FROM protocol_component pc
+    JOIN protocol_system ps ON ps.id = pc.protocol_system_id
+    WHERE pc.chain_id = $1 AND ps.name = $2"#;
+
+/// Ids of the accounts held by the components of a protocol system. Expects the same parameters
+/// as [`COMPONENT_SCOPE`].
+const ACCOUNT_SCOPE: &str = r#"
+    SELECT cc.account_id FROM protocol_component_holds_contract h
+    JOIN contract_code cc ON cc.id = h.contract_code_id
+    JOIN protocol_component pc ON pc.id = h.protocol_component_id
+    JOIN protocol_system ps ON ps.id = pc.protocol_system_id
+    WHERE pc.chain_id = $1 AND ps.name = $2"#;
+
+/// A versioned table rewritten by a repair.
+struct RepairedTable {
+    name: &'static str,
+    /// Column linking a row to the repaired protocol system.
+    scope_column: &'static str,
+    /// Query selecting the values of `scope_column` that belong to the protocol system.
+    scope: &'static str,
+    /// Columns identifying the entity a version belongs to.
+    entity: &'static [&'static str],
+}
+
+const REPAIRED_TABLES: [RepairedTable; 4] = [
+    RepairedTable {
+        name: "protocol_state",
+        scope_column: "protocol_component_id",
+        scope: COMPONENT_SCOPE,
+        entity: &["protocol_component_id", "attribute_name"],
+    },
+    RepairedTable {
+        name: "component_balance",
+        scope_column: "protocol_component_id",
+        scope: COMPONENT_SCOPE,
+        entity: &["protocol_component_id", "token_id"],
+    },
+    RepairedTable {
+        name: "contract_storage",
+        scope_column: "account_id",
+        scope: ACCOUNT_SCOPE,
+        entity: &["account_id", "slot"],
+    },
+    RepairedTable {
+        name: "account_balance",
+        scope_column: "account_id",
+        scope: ACCOUNT_SCOPE,
+        entity: &["account_id", "token_id"],
+    },
+];
+
+impl RepairedTable {
+    /// Condition matching rows of the aliases `t` and `s` that belong to the same entity.
+    fn same_entity(&self) -> String {
+        self.entity
+            .iter()
+            .map(|col| format!("t.{col} = s.{col}"))
+            .join(" AND ")
+    }
+}
+
+#[derive(QueryableByName, Debug)]
+struct BrokenLink {
+    #[diesel(sql_type = BigInt)]
+    number: i64,
+}
+
+#[derive(QueryableByName, Debug)]
+struct OverlapCount {
+    #[diesel(sql_type = BigInt)]
+    overlaps: i64,
+}
+
+impl PostgresGateway {
+    /// Sets the state a protocol system wrote from `start_block` onwards aside.
+    ///
+    /// Versions written within `start_block..=end_block` are quarantined, versions written later
+    /// are parked until [`Self::restore_quarantined_range`] is called. Versions that were valid at
+    /// the block preceding the range become current again, so the range can be replayed on top
+    /// of them.
+    ///
+    /// Returns the id of the newly created repair. Should be called within a transaction.
+    #[instrument(skip(self, conn))]
+    pub async fn quarantine_range(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        start_block: i64,
+        end_block: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<i64, StorageError> {
+        if start_block > end_block {
+            return Err(StorageError::InvalidBlockRange());
+        }
+        let chain_id = self.get_chain_id(chain)?;
+        let start_ts = orm::Block::by_id(&BlockIdentifier::Number((*chain, start_block)), conn)
+            .await
+            .map_err(PostgresError::from)?
+            .ts;
+        let end_ts = orm::Block::by_id(&BlockIdentifier::Number((*chain, end_block)), conn)
+            .await
+            .map_err(PostgresError::from)?
+            .ts;
+
+        let repair_id = diesel::insert_into(schema::block_range_repair::table)
+            .values((
+                schema::block_range_repair::chain_id.eq(chain_id),
+                schema::block_range_repair::protocol_system.eq(protocol_system),
+                schema::block_range_repair::start_block.eq(start_block),
+                schema::block_range_repair::end_block.eq(end_block),
+            ))
+            .returning(schema::block_range_repair::id)
+            .get_result::<i64>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+
+        let mut total_moved = 0;
+        for table in REPAIRED_TABLES.iter() {
+            let moved = diesel::sql_query(format!(
+                r#"
+                WITH moved AS (
+                    DELETE FROM {name}
+                    WHERE {scope_column} IN ({scope}) AND valid_from >= $3
+                    RETURNING *
+                )
+                INSERT INTO quarantined_row (repair_id, table_name, parked, row_data)
+                SELECT $5, '{name}', moved.valid_from > $4, to_jsonb(moved) FROM moved
+                "#,
+                name = table.name,
+                scope_column = table.scope_column,
+                scope = table.scope,
+            ))
+            .bind::<BigInt, _>(chain_id)
+            .bind::<Varchar, _>(protocol_system)
+            .bind::<Timestamptz, _>(start_ts)
+            .bind::<Timestamptz, _>(end_ts)
+            .bind::<BigInt, _>(repair_id)
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+
+            // Versions closed from the first repaired block onwards were valid right before it.
+            let reopened = diesel::sql_query(format!(
+                r#"
+                UPDATE {name} SET valid_to = $4
+                WHERE {scope_column} IN ({scope}) AND valid_to >= $3 AND valid_to < $4
+                "#,
+                name = table.name,
+                scope_column = table.scope_column,
+                scope = table.scope,
+            ))
+            .bind::<BigInt, _>(chain_id)
+            .bind::<Varchar, _>(protocol_system)
+            .bind::<Timestamptz, _>(start_ts)
+            .bind::<Timestamptz, _>(MAX_TS)
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+            info!(table = table.name, moved, reopened, "Quarantined versions");
+            total_moved += moved as i64;
+        }
+        record_operation(
+            AuditRecord::new("quarantine_range", total_moved)
+                .chain(chain)
+                .target(protocol_system)
+                .block_range(start_block, end_block),
+            conn,
+        )
+        .await?;
+        Ok(repair_id)
+    }
+
+    /// Restores the versions parked by [`Self::quarantine_range`].
+    ///
+    /// The current version of every entity with parked versions is closed at the first parked
+    /// version, then the parked versions are inserted again. Fails if the repair was already
+    /// restored. Should be called within a transaction.
+    #[instrument(skip(self, conn))]
+    pub async fn restore_quarantined_range(
+        &self,
+        repair_id: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let repair = Self::get_repair(repair_id, conn).await?;
+        if let Some(ts) = repair.restored_ts {
+            return Err(StorageError::Unexpected(format!(
+                "Repair {repair_id} was already restored at {ts}"
+            )));
+        }
+
+        let mut total_restored = 0;
+        for table in REPAIRED_TABLES.iter() {
+            let parked = format!(
+                r#"
+                SELECT (jsonb_populate_record(NULL::{name}, row_data)).*
+                FROM quarantined_row
+                WHERE repair_id = $1 AND table_name = '{name}' AND parked
+                "#,
+                name = table.name,
+            );
+            // Closing happens in a separate statement: the replayed and the parked current
+            // versions would otherwise briefly share the same primary key.
+            diesel::sql_query(format!(
+                r#"
+                WITH parked AS ({parked})
+                UPDATE {name} t SET valid_to = s.valid_from
+                FROM (
+                    SELECT {entity}, min(valid_from) AS valid_from FROM parked GROUP BY {entity}
+                ) s
+                WHERE {same_entity} AND t.valid_to = $2
+                "#,
+                name = table.name,
+                entity = table.entity.join(", "),
+                same_entity = table.same_entity(),
+            ))
+            .bind::<BigInt, _>(repair_id)
+            .bind::<Timestamptz, _>(MAX_TS)
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+
+            let restored =
+                diesel::sql_query(format!("INSERT INTO {name} {parked}", name = table.name))
+                    .bind::<BigInt, _>(repair_id)
+                    .execute(conn)
+                    .await
+                    .map_err(PostgresError::from)?;
+            info!(table = table.name, restored, "Restored parked versions");
+            total_restored += restored as i64;
+        }
+
+        diesel::update(schema::block_range_repair::table.find(repair_id))
+            .set(schema::block_range_repair::restored_ts.eq(Some(Utc::now().naive_utc())))
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        let chain = self.get_chain(&repair.chain_id)?;
+        record_operation(
+            AuditRecord::new("restore_quarantined_range", total_restored)
+                .chain(&chain)
+                .target(repair.protocol_system)
+                .block_range(repair.start_block, repair.end_block),
+            conn,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Checks that a repaired range connects to the history around it.
+    ///
+    /// Every block from the one preceding the range up to the one following it must reference
+    /// its predecessor as parent. No two versions of an entity of the repaired protocol system
+    /// may overlap from the start of the range onwards.
+    #[instrument(skip(self, conn))]
+    pub async fn verify_range_continuity(
+        &self,
+        repair_id: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        let repair = Self::get_repair(repair_id, conn).await?;
+        let chain = self.get_chain(&repair.chain_id)?;
+        let start_ts =
+            orm::Block::by_id(&BlockIdentifier::Number((chain, repair.start_block)), conn)
+                .await
+                .map_err(PostgresError::from)?
+                .ts;
+
+        let broken_links = diesel::sql_query(
+            r#"
+            SELECT b.number FROM block b
+            JOIN block p ON p.chain_id = b.chain_id AND p.number = b.number - 1
+            WHERE b.chain_id = $1 AND b.number BETWEEN $2 AND $3 AND b.parent_hash <> p.hash
+            ORDER BY b.number
+            "#,
+        )
+        .bind::<BigInt, _>(repair.chain_id)
+        .bind::<BigInt, _>(repair.start_block)
+        .bind::<BigInt, _>(repair.end_block + 1)
+        .load::<BrokenLink>(conn)
+        .await
+        .map_err(PostgresError::from)?;
+        if !broken_links.is_empty() {
+            return Err(StorageError::Unexpected(format!(
+                "Repair {repair_id}: blocks {:?} don't link to their parent",
+                broken_links
+                    .iter()
+                    .map(|link| link.number)
+                    .collect::<Vec<_>>()
+            )));
+        }
+
+        for table in REPAIRED_TABLES.iter() {
+            let count = diesel::sql_query(format!(
+                r#"
+                SELECT count(*) AS overlaps FROM (
+                    SELECT valid_to,
+                           lead(valid_from) OVER (PARTITION BY {entity} ORDER BY valid_from) AS next
+                    FROM {name}
+                    WHERE {scope_column} IN ({scope}) AND valid_to >= $3
+                ) v
+                WHERE v.next < v.valid_to
+                "#,
+                name = table.name,
+                entity = table.entity.join(", "),
+                scope_column = table.scope_column,
+                scope = table.scope,
+            ))
+            .bind::<BigInt, _>(repair.chain_id)
+            .bind::<Varchar, _>(&repair.protocol_system)
+            .bind::<Timestamptz, _>(start_ts)
+            .get_result::<OverlapCount>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+            if count.overlaps > 0 {
+                return Err(StorageError::Unexpected(format!(
+                    "Repair {repair_id}: found {} overlapping versions in {}",
+                    count.overlaps, table.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_repair(
+        repair_id: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<orm::BlockRangeRepair, StorageError> {
+        schema::block_range_repair::table
+            .find(repair_id)
+            .select(orm::BlockRangeRepair::as_select())
+            .first::<orm::BlockRangeRepair>(conn)
+            .await
+            .optional()
+            .map_err(PostgresError::from)?
+            .ok_or_else(|| {
+                StorageError::NotFound("BlockRangeRepair".to_string(), repair_id.to_string())
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDateTime;
+    use diesel_async::AsyncConnection;
+    use tycho_common::Bytes;
+
+    use super::*;
+    use crate::postgres::db_fixtures::{self, yesterday_midnight};
+
+    async fn setup_db() -> AsyncPgConnection {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        conn
+    }
+
+    /// Inserts 10 blocks a minute apart with a transaction each, and a component of every given
+    /// protocol system whose `value` attribute changes in every block.
+    async fn setup_data(conn: &mut AsyncPgConnection, systems: &[&str]) -> Vec<NaiveDateTime> {
+        let chain_id = db_fixtures::insert_chain(conn, "ethereum").await;
+        diesel::sql_query(
+            r#"
+            INSERT INTO block (hash, parent_hash, main, number, ts, chain_id)
+            SELECT int8send(n), int8send(n - 1), true, n, $2 + n * interval '1 minute', $1
+            FROM generate_series(0, 9) n
+            "#,
+        )
+        .bind::<BigInt, _>(chain_id)
+        .bind::<Timestamptz, _>(yesterday_midnight())
+        .execute(conn)
+        .await
+        .unwrap();
+        diesel::sql_query(
+            r#"
+            INSERT INTO "transaction" (hash, "from", "to", index, block_id)
+            SELECT int8send(number), '\x00', '\x00', 0, id FROM block WHERE chain_id = $1
+            "#,
+        )
+        .bind::<BigInt, _>(chain_id)
+        .execute(conn)
+        .await
+        .unwrap();
+        let first_tx = schema::transaction::table
+            .inner_join(schema::block::table)
+            .filter(schema::block::number.eq(0))
+            .select(schema::transaction::id)
+            .first::<i64>(conn)
+            .await
+            .unwrap();
+        let type_id = db_fixtures::insert_protocol_type(conn, "pool", None, None, None).await;
+        for system in systems {
+            let system_id = db_fixtures::insert_protocol_system(conn, system.to_string()).await;
+            let component_id = db_fixtures::insert_protocol_component(
+                conn, system, chain_id, system_id, type_id, first_tx, None, None,
+            )
+            .await;
+            diesel::sql_query(
+                r#"
+                INSERT INTO protocol_state (protocol_component_id, attribute_name, attribute_value,
+                                            modify_tx, modified_ts, valid_from, valid_to)
+                SELECT $1, 'value', int8send(b.number), t.id, b.ts, b.ts,
+                       COALESCE(lead(b.ts) OVER (ORDER BY b.number), $3)
+                FROM block b
+                JOIN "transaction" t ON t.block_id = b.id
+                WHERE b.chain_id = $2
+                "#,
+            )
+            .bind::<BigInt, _>(component_id)
+            .bind::<BigInt, _>(chain_id)
+            .bind::<Timestamptz, _>(MAX_TS)
+            .execute(conn)
+            .await
+            .unwrap();
+        }
+        schema::block::table
+            .order_by(schema::block::number)
+            .select(schema::block::ts)
+            .get_results(conn)
+            .await
+            .unwrap()
+    }
+
+    /// Returns `(valid_from, valid_to, value)` of all versions of a component's `value` attribute.
+    async fn history(
+        conn: &mut AsyncPgConnection,
+        component: &str,
+    ) -> Vec<(NaiveDateTime, NaiveDateTime, Bytes)> {
+        schema::protocol_state::table
+            .inner_join(schema::protocol_component::table)
+            .filter(schema::protocol_component::external_id.eq(component))
+            .order_by(schema::protocol_state::valid_from)
+            .select((
+                schema::protocol_state::valid_from,
+                schema::protocol_state::valid_to,
+                schema::protocol_state::attribute_value,
+            ))
+            .get_results(conn)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_repair_range() {
+        let mut conn = setup_db().await;
+        let ts = setup_data(&mut conn, &["repaired", "untouched"]).await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        let untouched = history(&mut conn, "untouched").await;
+
+        let repair_id = gw
+            .quarantine_range(&Chain::Ethereum, "repaired", 3, 5, &mut conn)
+            .await
+            .unwrap();
+
+        let history_quarantined = history(&mut conn, "repaired").await;
+        assert_eq!(history_quarantined.len(), 3);
+        assert_eq!(
+            history_quarantined[2],
+            (ts[2], MAX_TS, Bytes::from(2i64.to_be_bytes().to_vec()))
+        );
+        let parked = schema::quarantined_row::table
+            .filter(schema::quarantined_row::repair_id.eq(repair_id))
+            .select(schema::quarantined_row::parked)
+            .get_results::<bool>(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(parked.iter().filter(|p| **p).count(), 4);
+        assert_eq!(parked.iter().filter(|p| !**p).count(), 3);
+
+        // Replays the range with a single corrected version in block 4.
+        let component_id = schema::protocol_component::table
+            .filter(schema::protocol_component::external_id.eq("repaired"))
+            .select(schema::protocol_component::id)
+            .first::<i64>(&mut conn)
+            .await
+            .unwrap();
+        let tx_id = schema::transaction::table
+            .inner_join(schema::block::table)
+            .filter(schema::block::number.eq(4))
+            .select(schema::transaction::id)
+            .first::<i64>(&mut conn)
+            .await
+            .unwrap();
+        diesel::update(
+            schema::protocol_state::table
+                .filter(schema::protocol_state::protocol_component_id.eq(component_id))
+                .filter(schema::protocol_state::valid_to.eq(MAX_TS)),
+        )
+        .set(schema::protocol_state::valid_to.eq(ts[4]))
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        db_fixtures::insert_protocol_state(
+            &mut conn,
+            component_id,
+            tx_id,
+            "value".to_string(),
+            Bytes::from("0xff"),
+            None,
+            None,
+        )
+        .await;
+
+        gw.restore_quarantined_range(repair_id, &mut conn)
+            .await
+            .unwrap();
+        gw.verify_range_continuity(repair_id, &mut conn)
+            .await
+            .unwrap();
+
+        let value = |n: i64| Bytes::from(n.to_be_bytes().to_vec());
+        let exp = vec![
+            (ts[0], ts[1], value(0)),
+            (ts[1], ts[2], value(1)),
+            (ts[2], ts[4], value(2)),
+            (ts[4], ts[6], Bytes::from("0xff")),
+            (ts[6], ts[7], value(6)),
+            (ts[7], ts[8], value(7)),
+            (ts[8], ts[9], value(8)),
+            (ts[9], MAX_TS, value(9)),
+        ];
+        assert_eq!(history(&mut conn, "repaired").await, exp);
+        assert_eq!(history(&mut conn, "untouched").await, untouched);
+        assert!(gw
+            .restore_quarantined_range(repair_id, &mut conn)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,568 @@
+//! Periodic background tasks of the gateway.
+//!
+//! Maintenance jobs register on a [`Scheduler`] with a [`Schedule`], either a fixed interval or a
+//! cron expression evaluated in UTC. Every run is delayed by a random jitter of up to the
+//! configured maximum, so replicas started together don't query the database at the same time.
+//!
+//! Singleton tasks change shared data and must only run on a single replica. With leader election
+//! enabled, the replica holding a Postgres advisory lock on a dedicated session runs them, all
+//! others skip their runs. Postgres releases the lock once that session ends, so another replica
+//! takes over at its next run if the leader dies.
+//!
+//! Runs are exported as the `scheduler_task_runs` counter, labeled by task and status, and the
+//! `scheduler_task_duration_seconds` histogram.
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike, Utc};
+use diesel::{
+    sql_types::{Bool, Integer, Text},
+    QueryableByName,
+};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use metrics::{counter, gauge, histogram};
+use rand::Rng;
+use tokio::{sync::Mutex, task::JoinHandle, time::Instant};
+use tracing::{debug, error, info, warn};
+use tycho_common::storage::StorageError;
+
+use super::PostgresError;
+
+/// First key of the advisory lock held by the leader, the second one is a hash of its name.
+const SCHEDULER_LOCK_NAMESPACE: i32 = 0x736368;
+
+/// Cron schedules without a run within this many days are considered to never run.
+const MAX_CRON_LOOKAHEAD_DAYS: i64 = 5 * 366;
+
+/// A job run by the [`Scheduler`].
+#[async_trait]
+pub trait PeriodicTask: Send + 'static {
+    /// Runs the task once. A failed run is logged, the task runs again at its next scheduled time.
+    async fn run_once(&mut self) -> Result<(), StorageError>;
+}
+
+/// When a task runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Runs right away and then with this pause between the end of a run and the next one.
+    Every(Duration),
+    /// Runs at every minute matching the expression.
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    /// Delay from `now` until the next run, `None` if the schedule has no future runs.
+    fn next_delay(&self, now: NaiveDateTime, first_run: bool) -> Option<Duration> {
+        match self {
+            Schedule::Every(_) if first_run => Some(Duration::ZERO),
+            Schedule::Every(interval) => Some(*interval),
+            Schedule::Cron(cron) => cron.next_after(now).map(|next| {
+                (next - now)
+                    .to_std()
+                    .unwrap_or_default()
+            }),
+        }
+    }
+}
+
+/// Parses a cron expression, one of the shortcuts `@hourly`, `@daily` and `@weekly`, or an
+/// interval as `@every <n><unit>` with unit `s`, `m`, `h` or `d`, e.g. `@every 30m`.
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let expression = match s {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            _ => {
+                if let Some(interval) = s.strip_prefix("@every") {
+                    return parse_interval(interval.trim()).map(Schedule::Every);
+                }
+                s
+            }
+        };
+        expression.parse().map(Schedule::Cron)
+    }
+}
+
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    let (value, unit) = s.split_at(
+        s.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(s.len()),
+    );
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("Invalid interval {s}"))?;
+    let factor = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        other => return Err(format!("Unknown interval unit {other} in {s}")),
+    };
+    if value == 0 {
+        return Err(format!("Interval {s} must not be zero"));
+    }
+    Ok(Duration::from_secs(value * factor))
+}
+
+/// A cron expression with the five fields minute, hour, day of month, month and day of week.
+///
+/// Fields accept `*`, single values, ranges `a-b`, steps `*/n`, `a/n` or `a-b/n` and comma
+/// separated lists of these. Days of week count from 0 for Sunday, 7 is Sunday as well. Like in
+/// Vixie cron, a day matches if either day field does when both of them are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("Expected 5 fields in cron expression {s}, got {}", fields.len()));
+        };
+        let mut weekday_bits = parse_cron_field(weekdays, 0, 7)?;
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits = (weekday_bits & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse_cron_field(minutes, 0, 59)?,
+            hours: parse_cron_field(hours, 0, 23)?,
+            days: parse_cron_field(days, 1, 31)?,
+            months: parse_cron_field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        })
+    }
+}
+
+/// Parses a cron field into a bit set of the matching values.
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let parse_value = |s: &str| {
+        s.parse::<u32>()
+            .ok()
+            .filter(|v| (min..=max).contains(v))
+            .ok_or_else(|| format!("Invalid value {s} in cron field {field}, expected {min}-{max}"))
+    };
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("Invalid step {step} in cron field {field}"))?;
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start)?, parse_value(end)?)
+        } else {
+            let value = parse_value(range)?;
+            (value, if step.is_some() { max } else { value })
+        };
+        if start > end {
+            return Err(format!("Invalid range {range} in cron field {field}"));
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1)) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn contains(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+impl CronSchedule {
+    /// First minute matching the expression strictly after `after`, `None` if there is none
+    /// within about five years.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut next = after
+            .date()
+            .and_hms_opt(after.hour(), after.minute(), 0)? +
+            chrono::Duration::minutes(1);
+        let limit = after + chrono::Duration::days(MAX_CRON_LOOKAHEAD_DAYS);
+        while next <= limit {
+            let date = next.date();
+            if !contains(self.months, date.month()) {
+                next = if date.month() == 12 {
+                    NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)?
+                } else {
+                    NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)?
+                }
+                .and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(date) {
+                next = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !contains(self.hours, next.hour()) {
+                next = date.and_hms_opt(next.hour(), 0, 0)? + chrono::Duration::hours(1);
+            } else if !contains(self.minutes, next.minute()) {
+                next += chrono::Duration::minutes(1);
+            } else {
+                return Some(next);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = contains(self.days, date.day());
+        let weekday = contains(self.weekdays, date.weekday().num_days_from_sunday());
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+/// Schedule of a task, parsed from `<task>=<schedule>`, e.g. `storage_compaction=0 3 * * *`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskSchedule {
+    pub task: String,
+    pub schedule: Schedule,
+}
+
+impl FromStr for TaskSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (task, schedule) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <task>=<schedule>, got {s}"))?;
+        Ok(Self { task: task.trim().to_string(), schedule: schedule.parse()? })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerConfig {
+    /// Schedules replacing the default interval of a task.
+    pub schedules: Vec<TaskSchedule>,
+    /// Maximum random delay added before every run.
+    pub max_jitter: Duration,
+    /// Runs singleton tasks only on the replica elected as leader, see [`LeaderElection`].
+    pub leader_election: bool,
+}
+
+/// Leadership among replicas, held as a session level advisory lock on a dedicated connection.
+pub struct LeaderElection {
+    database_url: String,
+    name: String,
+    conn: Option<AsyncPgConnection>,
+    leader: bool,
+}
+
+#[derive(QueryableByName)]
+struct LockResult {
+    #[diesel(sql_type = Bool)]
+    locked: bool,
+}
+
+impl LeaderElection {
+    /// Competes for the leadership named `name` with all replicas using the same database.
+    pub fn new(database_url: &str, name: &str) -> Self {
+        Self {
+            database_url: database_url.to_string(),
+            name: name.to_string(),
+            conn: None,
+            leader: false,
+        }
+    }
+
+    /// Whether this replica leads, tries to become the leader if it doesn't yet.
+    pub async fn is_leader(&mut self) -> bool {
+        match self.refresh().await {
+            Ok(leader) => {
+                if leader != self.leader {
+                    info!(name = self.name, leader, "Scheduler leadership changed");
+                }
+                self.leader = leader;
+            }
+            Err(err) => {
+                if self.leader {
+                    warn!(name = self.name, error = %err, "Lost scheduler leadership");
+                } else {
+                    warn!(name = self.name, error = %err, "Failed to acquire scheduler leadership");
+                }
+                // Closing the session releases the lock, if it is still held.
+                self.conn = None;
+                self.leader = false;
+            }
+        }
+        gauge!("scheduler_leader", "scheduler" => self.name.clone()).set(if self.leader {
+            1.0
+        } else {
+            0.0
+        });
+        self.leader
+    }
+
+    async fn refresh(&mut self) -> Result<bool, StorageError> {
+        if self.conn.is_none() {
+            self.leader = false;
+            self.conn = Some(
+                AsyncPgConnection::establish(&self.database_url)
+                    .await
+                    .map_err(|err| StorageError::Unexpected(format!("Failed to connect: {err}")))?,
+            );
+        }
+        let conn = self
+            .conn
+            .as_mut()
+            .expect("connection was established");
+        if self.leader {
+            // The lock lives as long as the session, so a working session still holds it.
+            diesel::sql_query("SELECT 1")
+                .execute(conn)
+                .await
+                .map_err(PostgresError::from)?;
+            return Ok(true);
+        }
+        let res = diesel::sql_query("SELECT pg_try_advisory_lock($1, hashtext($2)) AS locked")
+            .bind::<Integer, _>(SCHEDULER_LOCK_NAMESPACE)
+            .bind::<Text, _>(&self.name)
+            .get_result::<LockResult>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(res.locked)
+    }
+}
+
+struct ScheduledTask {
+    name: String,
+    schedule: Schedule,
+    singleton: bool,
+    task: Box<dyn PeriodicTask>,
+}
+
+/// Runs registered [`PeriodicTask`]s according to their schedules.
+pub struct Scheduler {
+    config: SchedulerConfig,
+    leader: Option<Arc<Mutex<LeaderElection>>>,
+    tasks: Vec<ScheduledTask>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler, `leader` is used to elect the replica running singleton tasks if
+    /// leader election is enabled in `config`.
+    pub fn new(config: SchedulerConfig, leader: LeaderElection) -> Self {
+        let leader = config
+            .leader_election
+            .then(|| Arc::new(Mutex::new(leader)));
+        Self { config, leader, tasks: Vec::new() }
+    }
+
+    /// Registers `task` as `name`, running at the configured schedule for `name` or `default`.
+    ///
+    /// Singleton tasks only run on the leader, if leader election is enabled.
+    pub fn register(
+        &mut self,
+        name: &str,
+        default: Schedule,
+        singleton: bool,
+        task: impl PeriodicTask,
+    ) {
+        let schedule = self
+            .config
+            .schedules
+            .iter()
+            .find(|s| s.task == name)
+            .map_or(default, |s| s.schedule.clone());
+        info!(task = name, ?schedule, singleton, "Scheduled task");
+        self.tasks.push(ScheduledTask {
+            name: name.to_string(),
+            schedule,
+            singleton,
+            task: Box::new(task),
+        });
+    }
+
+    /// Spawns a loop running each registered task.
+    pub fn run(self) -> Vec<JoinHandle<()>> {
+        for configured in &self.config.schedules {
+            if !self
+                .tasks
+                .iter()
+                .any(|t| t.name == configured.task)
+            {
+                warn!(task = configured.task, "Ignoring schedule of unknown or disabled task");
+            }
+        }
+        self.tasks
+            .into_iter()
+            .map(|task| tokio::spawn(run_task(task, self.config.max_jitter, self.leader.clone())))
+            .collect()
+    }
+}
+
+async fn run_task(
+    mut task: ScheduledTask,
+    max_jitter: Duration,
+    leader: Option<Arc<Mutex<LeaderElection>>>,
+) {
+    let mut first_run = true;
+    loop {
+        let Some(delay) = task
+            .schedule
+            .next_delay(Utc::now().naive_utc(), first_run)
+        else {
+            error!(task = task.name, "Schedule has no future runs, stopping task");
+            return;
+        };
+        first_run = false;
+        tokio::time::sleep(delay + jitter(max_jitter)).await;
+
+        if let (true, Some(leader)) = (task.singleton, &leader) {
+            if !leader.lock().await.is_leader().await {
+                debug!(task = task.name, "Skipping singleton task, not the leader");
+                counter!("scheduler_task_runs", "task" => task.name.clone(), "status" => "skipped")
+                    .increment(1);
+                continue;
+            }
+        }
+
+        let started = Instant::now();
+        let res = task.task.run_once().await;
+        histogram!("scheduler_task_duration_seconds", "task" => task.name.clone())
+            .record(started.elapsed().as_secs_f64());
+        let status = match res {
+            Ok(()) => "ok",
+            Err(err) => {
+                error!(task = task.name, error = %err, "Scheduled task failed");
+                "error"
+            }
+        };
+        counter!("scheduler_task_runs", "task" => task.name.clone(), "status" => status)
+            .increment(1);
+    }
+}
+
+fn jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    rand::thread_rng().gen_range(Duration::ZERO..=max)
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn ts(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[rstest]
+    #[case::every_minute("* * * * *", "2024-01-01 10:15:30", "2024-01-01 10:16:00")]
+    #[case::hourly("@hourly", "2024-01-01 10:00:00", "2024-01-01 11:00:00")]
+    #[case::daily_at_three("0 3 * * *", "2024-01-01 10:15:00", "2024-01-02 03:00:00")]
+    #[case::steps("*/20 * * * *", "2024-01-01 10:41:00", "2024-01-01 11:00:00")]
+    #[case::list_and_range("5,35 8-9 * * *", "2024-01-01 09:40:00", "2024-01-02 08:05:00")]
+    #[case::end_of_year("0 0 1 1 *", "2024-12-31 23:59:00", "2025-01-01 00:00:00")]
+    #[case::sunday_as_seven("0 0 * * 7", "2024-01-01 00:00:00", "2024-01-07 00:00:00")]
+    #[case::either_day_field("0 0 15 * 1", "2024-01-02 00:00:00", "2024-01-08 00:00:00")]
+    #[case::leap_day("0 12 29 2 *", "2024-03-01 00:00:00", "2028-02-29 12:00:00")]
+    fn test_cron_next_after(#[case] expression: &str, #[case] after: &str, #[case] next: &str) {
+        let Schedule::Cron(cron) = expression.parse::<Schedule>().unwrap() else {
+            panic!("not a cron schedule");
+        };
+
+        assert_eq!(cron.next_after(ts(after)), Some(ts(next)));
+    }
+
+    #[test]
+    fn test_cron_never_runs() {
+        let cron = "0 0 31 2 *"
+            .parse::<CronSchedule>()
+            .unwrap();
+
+        assert_eq!(cron.next_after(ts("2024-01-01 00:00:00")), None);
+    }
+
+    #[rstest]
+    #[case::too_few_fields("* * * *")]
+    #[case::out_of_range("60 * * * *")]
+    #[case::inverted_range("* 5-3 * * *")]
+    #[case::zero_step("*/0 * * * *")]
+    #[case::garbage("a * * * *")]
+    #[case::zero_interval("@every 0s")]
+    #[case::unknown_unit("@every 5w")]
+    fn test_parse_schedule_invalid(#[case] s: &str) {
+        assert!(s.parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn test_parse_task_schedule() {
+        assert_eq!(
+            "storage_compaction=@every 90m".parse::<TaskSchedule>(),
+            Ok(TaskSchedule {
+                task: "storage_compaction".to_string(),
+                schedule: Schedule::Every(Duration::from_secs(5400))
+            })
+        );
+        assert!("storage_compaction"
+            .parse::<TaskSchedule>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_next_delay() {
+        let now = ts("2024-01-01 10:15:30");
+        let every = Schedule::Every(Duration::from_secs(60));
+        let cron = "0 * * * *".parse::<Schedule>().unwrap();
+
+        assert_eq!(every.next_delay(now, true), Some(Duration::ZERO));
+        assert_eq!(every.next_delay(now, false), Some(Duration::from_secs(60)));
+        assert_eq!(cron.next_delay(now, true), Some(Duration::from_secs(44 * 60 + 30)));
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let max = Duration::from_millis(50);
+
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+        assert!((0..100).all(|_| jitter(max) <= max));
+    }
+
+    #[tokio::test]
+    async fn test_leader_election() {
+        let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let mut first = LeaderElection::new(&db_url, "test_leader_election");
+        let mut second = LeaderElection::new(&db_url, "test_leader_election");
+
+        assert!(first.is_leader().await);
+        assert!(!second.is_leader().await);
+        assert!(first.is_leader().await);
+
+        // The lock is released once the server notices the closed session.
+        drop(first);
+        let mut took_over = false;
+        for _ in 0..50 {
+            if second.is_leader().await {
+                took_over = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(took_over);
+    }
+}
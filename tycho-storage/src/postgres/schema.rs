@@ -12,6 +12,7 @@ diesel::table! {
         inserted_ts -> Timestamptz,
         valid_from -> Timestamptz,
         valid_to -> Timestamptz,
+        balance_numeric -> Nullable<Numeric>,
     }
 }
 
@@ -26,6 +27,7 @@ diesel::table! {
         inserted_ts -> Timestamptz,
         valid_from -> Timestamptz,
         valid_to -> Timestamptz,
+        balance_numeric -> Nullable<Numeric>,
     }
 }
 
@@ -70,6 +72,8 @@ diesel::table! {
         inserted_ts -> Timestamptz,
         modified_ts -> Timestamptz,
         protocol_component_id -> Int8,
+        #[max_length = 255]
+        extractor -> Nullable<Varchar>,
     }
 }
 
@@ -84,6 +88,8 @@ diesel::table! {
         inserted_ts -> Timestamptz,
         modified_ts -> Timestamptz,
         protocol_component_id -> Int8,
+        #[max_length = 255]
+        extractor -> Nullable<Varchar>,
     }
 }
 
@@ -108,6 +114,10 @@ pub mod sql_types {
     #[diesel(postgres_type(name = "entry_point_tracing_type"))]
     pub struct EntryPointTracingType;
 
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "finality_status"))]
+    pub struct FinalityStatus;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "financial_type"))]
     pub struct FinancialType;
@@ -130,6 +140,7 @@ diesel::table! {
         deletion_tx -> Nullable<Int8>,
         inserted_ts -> Timestamptz,
         modified_ts -> Timestamptz,
+        orphaned_since -> Nullable<Timestamptz>,
     }
 }
 
@@ -144,10 +155,78 @@ diesel::table! {
         inserted_ts -> Timestamptz,
         modified_ts -> Timestamptz,
         token_id -> Int8,
+        balance_numeric -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    admin_audit_log (id) {
+        id -> Int8,
+        #[max_length = 255]
+        api_key -> Varchar,
+        #[max_length = 32]
+        role -> Varchar,
+        #[max_length = 255]
+        action -> Varchar,
+        status -> Int4,
+        inserted_ts -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    admin_idempotency_key (id) {
+        id -> Int8,
+        #[max_length = 255]
+        api_key -> Varchar,
+        #[max_length = 255]
+        key -> Varchar,
+        #[max_length = 255]
+        action -> Varchar,
+        status -> Nullable<Int4>,
+        #[max_length = 255]
+        content_type -> Nullable<Varchar>,
+        body -> Nullable<Bytea>,
+        inserted_ts -> Timestamptz,
+        modified_ts -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    api_key_usage (id) {
+        id -> Int8,
+        #[max_length = 255]
+        api_key -> Varchar,
+        #[max_length = 7]
+        month -> Varchar,
+        requests -> Int8,
+        rows_returned -> Int8,
+        bytes_returned -> Int8,
+        inserted_ts -> Timestamptz,
+        modified_ts -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Int8,
+        #[max_length = 64]
+        operation -> Varchar,
+        #[max_length = 255]
+        actor -> Varchar,
+        #[max_length = 255]
+        chain -> Nullable<Varchar>,
+        target -> Nullable<Text>,
+        rows_affected -> Int8,
+        start_block -> Nullable<Int8>,
+        end_block -> Nullable<Int8>,
+        inserted_ts -> Timestamptz,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::FinalityStatus;
+
     block (id) {
         id -> Int8,
         hash -> Bytea,
@@ -158,6 +237,30 @@ diesel::table! {
         inserted_ts -> Timestamptz,
         modified_ts -> Timestamptz,
         chain_id -> Int8,
+        finality_status -> FinalityStatus,
+        extra -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    block_digest (block_id) {
+        block_id -> Int8,
+        digest -> Bytea,
+        inserted_ts -> Timestamptz,
+        modified_ts -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    block_range_repair (id) {
+        id -> Int8,
+        chain_id -> Int8,
+        #[max_length = 255]
+        protocol_system -> Varchar,
+        start_block -> Int8,
+        end_block -> Int8,
+        restored_ts -> Nullable<Timestamptz>,
+        inserted_ts -> Timestamptz,
     }
 }
 
@@ -171,6 +274,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    component_event (id) {
+        id -> Int8,
+        protocol_component_id -> Int8,
+        transaction_id -> Int8,
+        log_index -> Int8,
+        address -> Bytea,
+        topics -> Array<Nullable<Bytea>>,
+        data -> Bytea,
+        inserted_ts -> Timestamptz,
+    }
+}
+
 diesel::table! {
     component_tvl (id) {
         id -> Int8,
@@ -192,6 +308,18 @@ diesel::table! {
         valid_to -> Nullable<Timestamptz>,
         inserted_ts -> Timestamptz,
         modified_ts -> Timestamptz,
+        cold_ref -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    contract_storage_cold (id) {
+        id -> Int8,
+        account_id -> Int8,
+        valid_from -> Timestamptz,
+        valid_to -> Timestamptz,
+        object_key -> Text,
+        inserted_ts -> Timestamptz,
     }
 }
 
@@ -258,6 +386,23 @@ diesel::table! {
         inserted_ts -> Timestamptz,
         modified_ts -> Timestamptz,
         block_id -> Int8,
+        substreams_packages -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    extractor_instance (id) {
+        id -> Int8,
+        #[max_length = 255]
+        name -> Varchar,
+        chain_id -> Int8,
+        #[max_length = 255]
+        instance_id -> Varchar,
+        acquired_ts -> Timestamptz,
+        heartbeat_ts -> Timestamptz,
+        lease_expires_ts -> Timestamptz,
+        inserted_ts -> Timestamptz,
+        modified_ts -> Timestamptz,
     }
 }
 
@@ -276,6 +421,10 @@ diesel::table! {
         modified_ts -> Timestamptz,
         protocol_type_id -> Int8,
         protocol_system_id -> Int8,
+        inactive_since -> Nullable<Timestamptz>,
+        #[max_length = 255]
+        extractor -> Nullable<Varchar>,
+        pair_key -> Nullable<Text>,
     }
 }
 
@@ -304,6 +453,29 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    protocol_state_attribute_rename (id) {
+        id -> Int8,
+        chain_id -> Int8,
+        #[max_length = 255]
+        protocol_system -> Varchar,
+        component_ids -> Nullable<Array<Nullable<Text>>>,
+        old_name -> Varchar,
+        new_name -> Varchar,
+        renamed_versions -> Int8,
+        inserted_ts -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    protocol_state_index (protocol_component_id, attribute_name) {
+        protocol_component_id -> Int8,
+        attribute_name -> Varchar,
+        index_prefix -> Varchar,
+        index_key -> Int8,
+    }
+}
+
 diesel::table! {
     protocol_system (id) {
         id -> Int8,
@@ -331,6 +503,50 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    quarantined_message (id) {
+        id -> Int8,
+        #[max_length = 255]
+        extractor -> Varchar,
+        chain_id -> Int8,
+        block_number -> Int8,
+        block_hash -> Nullable<Bytea>,
+        type_url -> Text,
+        payload -> Bytea,
+        #[max_length = 255]
+        kind -> Varchar,
+        error -> Text,
+        inserted_ts -> Timestamptz,
+        modified_ts -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    quarantined_row (id) {
+        id -> Int8,
+        repair_id -> Int8,
+        #[max_length = 255]
+        table_name -> Varchar,
+        parked -> Bool,
+        row_data -> Jsonb,
+        inserted_ts -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    slot_annotation (id) {
+        id -> Int8,
+        chain_id -> Int8,
+        address -> Bytea,
+        slot -> Bytea,
+        #[max_length = 255]
+        label -> Varchar,
+        description -> Nullable<Text>,
+        inserted_ts -> Timestamptz,
+        modified_ts -> Timestamptz,
+    }
+}
+
 diesel::table! {
     token (id) {
         id -> Int8,
@@ -346,6 +562,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    token_override (id) {
+        id -> Int8,
+        token_id -> Int8,
+        #[max_length = 255]
+        symbol -> Nullable<Varchar>,
+        display_decimals -> Nullable<Int4>,
+        logo_uri -> Nullable<Text>,
+        inserted_ts -> Timestamptz,
+        modified_ts -> Timestamptz,
+    }
+}
+
 diesel::table! {
     token_price (id) {
         id -> Int8,
@@ -356,6 +585,29 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    token_supply (token_id, ts) {
+        token_id -> Int8,
+        total_supply -> Bytea,
+        ts -> Timestamptz,
+        inserted_ts -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    tracked_contract (id) {
+        id -> Int8,
+        #[max_length = 255]
+        extractor -> Varchar,
+        chain_id -> Int8,
+        address -> Bytea,
+        from_block -> Int8,
+        until_block -> Nullable<Int8>,
+        inserted_ts -> Timestamptz,
+        modified_ts -> Timestamptz,
+    }
+}
+
 diesel::table! {
     transaction (id) {
         id -> Int8,
@@ -374,9 +626,14 @@ diesel::joinable!(account_balance -> account (account_id));
 diesel::joinable!(account_balance -> token (token_id));
 diesel::joinable!(account_balance -> transaction (modify_tx));
 diesel::joinable!(block -> chain (chain_id));
+diesel::joinable!(block_digest -> block (block_id));
+diesel::joinable!(block_range_repair -> chain (chain_id));
+diesel::joinable!(component_event -> protocol_component (protocol_component_id));
+diesel::joinable!(component_event -> transaction (transaction_id));
 diesel::joinable!(component_tvl -> protocol_component (protocol_component_id));
 diesel::joinable!(contract_code -> account (account_id));
 diesel::joinable!(contract_code -> transaction (modify_tx));
+diesel::joinable!(contract_storage_cold -> account (account_id));
 diesel::joinable!(debug_protocol_component_has_entry_point_tracing_params -> entry_point_tracing_params (entry_point_tracing_params_id));
 diesel::joinable!(debug_protocol_component_has_entry_point_tracing_params -> protocol_component (protocol_component_id));
 diesel::joinable!(entry_point_tracing_params -> entry_point (entry_point_id));
@@ -386,6 +643,7 @@ diesel::joinable!(entry_point_tracing_result -> block (detection_block));
 diesel::joinable!(entry_point_tracing_result -> entry_point_tracing_params (entry_point_tracing_params_id));
 diesel::joinable!(extraction_state -> block (block_id));
 diesel::joinable!(extraction_state -> chain (chain_id));
+diesel::joinable!(extractor_instance -> chain (chain_id));
 diesel::joinable!(protocol_component -> chain (chain_id));
 diesel::joinable!(protocol_component -> protocol_system (protocol_system_id));
 diesel::joinable!(protocol_component -> protocol_type (protocol_type_id));
@@ -395,8 +653,16 @@ diesel::joinable!(protocol_component_holds_token -> protocol_component (protocol
 diesel::joinable!(protocol_component_holds_token -> token (token_id));
 diesel::joinable!(protocol_component_uses_entry_point -> entry_point (entry_point_id));
 diesel::joinable!(protocol_component_uses_entry_point -> protocol_component (protocol_component_id));
+diesel::joinable!(protocol_state_attribute_rename -> chain (chain_id));
+diesel::joinable!(protocol_state_index -> protocol_component (protocol_component_id));
+diesel::joinable!(quarantined_message -> chain (chain_id));
+diesel::joinable!(quarantined_row -> block_range_repair (repair_id));
+diesel::joinable!(slot_annotation -> chain (chain_id));
 diesel::joinable!(token -> account (account_id));
+diesel::joinable!(token_override -> token (token_id));
 diesel::joinable!(token_price -> token (token_id));
+diesel::joinable!(token_supply -> token (token_id));
+diesel::joinable!(tracked_contract -> chain (chain_id));
 diesel::joinable!(transaction -> block (block_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
@@ -410,23 +676,40 @@ diesel::allow_tables_to_appear_in_same_query!(
     // Tables generated by the Diesel CLI
     account,
     account_balance,
+    admin_audit_log,
+    admin_idempotency_key,
+    api_key_usage,
+    audit_log,
     block,
+    block_digest,
+    block_range_repair,
     chain,
+    component_event,
     component_tvl,
     contract_code,
+    contract_storage_cold,
     debug_protocol_component_has_entry_point_tracing_params,
     entry_point,
     entry_point_tracing_params,
     entry_point_tracing_params_calls_account,
     entry_point_tracing_result,
     extraction_state,
+    extractor_instance,
     protocol_component,
     protocol_component_holds_contract,
     protocol_component_holds_token,
     protocol_component_uses_entry_point,
+    protocol_state_attribute_rename,
+    protocol_state_index,
     protocol_system,
     protocol_type,
+    quarantined_message,
+    quarantined_row,
+    slot_annotation,
     token,
+    token_override,
     token_price,
+    token_supply,
+    tracked_contract,
     transaction,
 );
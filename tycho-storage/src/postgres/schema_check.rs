@@ -0,0 +1,482 @@
+//! Detects drift between the diesel table definitions and the live database schema.
+//!
+//! If `schema.rs` and the migrations disagree, diesel only fails once an affected query runs,
+//! usually with an error that doesn't name the offending column. This module compares the
+//! declared columns of every table against `information_schema.columns` at startup and reports
+//! all differences at once.
+//!
+//! Columns that only exist in the database are logged but tolerated, diesel never selects them.
+use std::{
+    any::type_name,
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use diesel::{
+    sql_query,
+    sql_types::{Bool, Text},
+    Column, Expression, QueryableByName, Table,
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tracing::{info, warn};
+use tycho_common::storage::StorageError;
+
+use super::schema;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ExpectedColumn {
+    name: &'static str,
+    /// Accepted `udt_name`s of the column, e.g. `varchar` and `text` for diesel's `Text`.
+    udt_names: Vec<String>,
+    nullable: bool,
+}
+
+impl ExpectedColumn {
+    fn of<C>() -> Self
+    where
+        C: Column + Expression,
+    {
+        let (nullable, udt_names) = sql_type_to_udt_names(type_name::<C::SqlType>());
+        Self { name: C::NAME, udt_names, nullable }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+struct LiveColumn {
+    #[diesel(sql_type = Text)]
+    table_name: String,
+    #[diesel(sql_type = Text)]
+    column_name: String,
+    #[diesel(sql_type = Text)]
+    udt_name: String,
+    #[diesel(sql_type = Bool)]
+    nullable: bool,
+}
+
+/// A single difference between the diesel definitions and the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaDrift {
+    MissingTable { table: String },
+    MissingColumn { table: String, column: String },
+    TypeMismatch { table: String, column: String, expected: Vec<String>, actual: String },
+    NullabilityMismatch { table: String, column: String, expected_nullable: bool },
+}
+
+impl fmt::Display for SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaDrift::MissingTable { table } => write!(f, "table `{table}` is missing"),
+            SchemaDrift::MissingColumn { table, column } => {
+                write!(f, "column `{table}.{column}` is missing")
+            }
+            SchemaDrift::TypeMismatch { table, column, expected, actual } => write!(
+                f,
+                "column `{table}.{column}` has type `{actual}`, expected `{}`",
+                expected.join("` or `")
+            ),
+            SchemaDrift::NullabilityMismatch { table, column, expected_nullable } => {
+                let (expected, actual) =
+                    if *expected_nullable { ("NULL", "NOT NULL") } else { ("NOT NULL", "NULL") };
+                write!(f, "column `{table}.{column}` is {actual}, expected {expected}")
+            }
+        }
+    }
+}
+
+/// Maps a diesel sql type name to its nullability and the matching postgres `udt_name`s.
+///
+/// Custom types fall back to the snake cased type name, which matches the names generated by the
+/// diesel CLI.
+fn sql_type_to_udt_names(sql_type: &str) -> (bool, Vec<String>) {
+    let (nullable, inner) = match strip_wrapper(sql_type, "Nullable") {
+        Some(inner) => (true, inner),
+        None => (false, sql_type),
+    };
+    if let Some(element) = strip_wrapper(inner, "Array") {
+        let (_, element_names) = sql_type_to_udt_names(element);
+        return (
+            nullable,
+            element_names
+                .into_iter()
+                .map(|name| format!("_{name}"))
+                .collect(),
+        );
+    }
+
+    let short_name = inner
+        .rsplit("::")
+        .next()
+        .unwrap_or(inner);
+    let names: &[&str] = match short_name {
+        "BigInt" => &["int8"],
+        "Integer" => &["int4"],
+        "SmallInt" => &["int2"],
+        "Double" => &["float8"],
+        "Float" => &["float4"],
+        "Numeric" => &["numeric"],
+        "Bool" => &["bool"],
+        "Text" => &["text", "varchar", "bpchar"],
+        "Binary" => &["bytea"],
+        "Timestamptz" => &["timestamptz"],
+        "Timestamp" => &["timestamp"],
+        "Date" => &["date"],
+        "Jsonb" => &["jsonb"],
+        "Json" => &["json"],
+        custom => return (nullable, vec![to_snake_case(custom)]),
+    };
+    (
+        nullable,
+        names
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+/// Strips `Wrapper<...>` from a fully qualified type name, returning the inner type.
+fn strip_wrapper<'a>(type_name: &'a str, wrapper: &str) -> Option<&'a str> {
+    let (outer, rest) = type_name.split_once('<')?;
+    if outer.rsplit("::").next() != Some(wrapper) {
+        return None;
+    }
+    rest.strip_suffix('>')
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut res = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                res.push('_');
+            }
+            res.push(c.to_ascii_lowercase());
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// Column tuples of diesel tables, i.e. `Table::AllColumns`.
+trait ColumnSet {
+    fn expected_columns() -> Vec<ExpectedColumn>;
+}
+
+macro_rules! impl_column_set {
+    ($($col:ident),+) => {
+        impl<$($col: Column + Expression),+> ColumnSet for ($($col,)+) {
+            fn expected_columns() -> Vec<ExpectedColumn> {
+                vec![$(ExpectedColumn::of::<$col>()),+]
+            }
+        }
+    };
+}
+
+impl_column_set!(C1);
+impl_column_set!(C1, C2);
+impl_column_set!(C1, C2, C3);
+impl_column_set!(C1, C2, C3, C4);
+impl_column_set!(C1, C2, C3, C4, C5);
+impl_column_set!(C1, C2, C3, C4, C5, C6);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8, C9);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12, C13);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12, C13, C14);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12, C13, C14, C15);
+impl_column_set!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12, C13, C14, C15, C16);
+
+fn table_columns<T>() -> Vec<ExpectedColumn>
+where
+    T: Table,
+    T::AllColumns: ColumnSet,
+{
+    T::AllColumns::expected_columns()
+}
+
+macro_rules! expected_tables {
+    ($($table:ident),+ $(,)?) => {
+        vec![$((stringify!($table), table_columns::<schema::$table::table>())),+]
+    };
+}
+
+/// All tables declared in `schema.rs`, with their columns.
+fn expected_schema() -> Vec<(&'static str, Vec<ExpectedColumn>)> {
+    expected_tables!(
+        component_balance,
+        component_balance_default,
+        contract_storage,
+        contract_storage_default,
+        protocol_state,
+        protocol_state_default,
+        account,
+        account_balance,
+        admin_audit_log,
+        admin_idempotency_key,
+        api_key_usage,
+        audit_log,
+        block,
+        block_digest,
+        block_range_repair,
+        chain,
+        component_event,
+        component_tvl,
+        contract_code,
+        contract_storage_cold,
+        debug_protocol_component_has_entry_point_tracing_params,
+        entry_point,
+        entry_point_tracing_params,
+        entry_point_tracing_params_calls_account,
+        entry_point_tracing_result,
+        extraction_state,
+        extractor_instance,
+        protocol_component,
+        protocol_component_holds_contract,
+        protocol_component_holds_token,
+        protocol_component_uses_entry_point,
+        protocol_state_attribute_rename,
+        protocol_state_index,
+        protocol_system,
+        protocol_type,
+        quarantined_message,
+        quarantined_row,
+        slot_annotation,
+        token,
+        token_override,
+        token_price,
+        token_supply,
+        tracked_contract,
+        transaction,
+    )
+}
+
+fn diff_schema(
+    expected: &[(&'static str, Vec<ExpectedColumn>)],
+    live: &[LiveColumn],
+) -> Vec<SchemaDrift> {
+    let mut live_tables: BTreeMap<&str, HashMap<&str, &LiveColumn>> = BTreeMap::new();
+    for column in live {
+        live_tables
+            .entry(column.table_name.as_str())
+            .or_default()
+            .insert(column.column_name.as_str(), column);
+    }
+
+    let mut drift = Vec::new();
+    for (table, columns) in expected {
+        let Some(mut live_columns) = live_tables.remove(table) else {
+            drift.push(SchemaDrift::MissingTable { table: table.to_string() });
+            continue;
+        };
+        for column in columns {
+            let Some(live_column) = live_columns.remove(column.name) else {
+                drift.push(SchemaDrift::MissingColumn {
+                    table: table.to_string(),
+                    column: column.name.to_string(),
+                });
+                continue;
+            };
+            if !column
+                .udt_names
+                .contains(&live_column.udt_name)
+            {
+                drift.push(SchemaDrift::TypeMismatch {
+                    table: table.to_string(),
+                    column: column.name.to_string(),
+                    expected: column.udt_names.clone(),
+                    actual: live_column.udt_name.clone(),
+                });
+            }
+            if column.nullable != live_column.nullable {
+                drift.push(SchemaDrift::NullabilityMismatch {
+                    table: table.to_string(),
+                    column: column.name.to_string(),
+                    expected_nullable: column.nullable,
+                });
+            }
+        }
+        for column in live_columns.keys() {
+            warn!(table, column, "Column exists in database but not in schema.rs");
+        }
+    }
+    drift
+}
+
+/// Compares the diesel table definitions against the live database schema.
+///
+/// Returns an error listing every difference if the two don't match.
+pub async fn check_schema(conn: &mut AsyncPgConnection) -> Result<(), StorageError> {
+    let live: Vec<LiveColumn> = sql_query(
+        r#"
+        SELECT table_name::text AS table_name,
+               column_name::text AS column_name,
+               udt_name::text AS udt_name,
+               is_nullable = 'YES' AS nullable
+        FROM information_schema.columns
+        WHERE table_schema = current_schema()
+        "#,
+    )
+    .load(conn)
+    .await
+    .map_err(|err| StorageError::Unexpected(format!("Failed to introspect db schema: {err}")))?;
+
+    let drift = diff_schema(&expected_schema(), &live);
+    if drift.is_empty() {
+        info!("Database schema matches schema.rs");
+        return Ok(());
+    }
+
+    let report = drift
+        .iter()
+        .map(|d| format!("  - {d}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(StorageError::Unexpected(format!(
+        "Database schema does not match schema.rs ({} differences):\n{report}",
+        drift.len()
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+
+    fn live(table: &str, column: &str, udt_name: &str, nullable: bool) -> LiveColumn {
+        LiveColumn {
+            table_name: table.to_string(),
+            column_name: column.to_string(),
+            udt_name: udt_name.to_string(),
+            nullable,
+        }
+    }
+
+    #[test]
+    fn test_sql_type_to_udt_names() {
+        let token_columns = table_columns::<schema::token::table>();
+        let gas = token_columns
+            .iter()
+            .find(|c| c.name == "gas")
+            .unwrap();
+        assert_eq!(gas.udt_names, vec!["_int8".to_string()]);
+        assert!(!gas.nullable);
+
+        let protocol_type_columns = table_columns::<schema::protocol_type::table>();
+        let financial_type = protocol_type_columns
+            .iter()
+            .find(|c| c.name == "financial_type")
+            .unwrap();
+        assert_eq!(financial_type.udt_names, vec!["financial_type".to_string()]);
+
+        let (nullable, names) =
+            sql_type_to_udt_names(type_name::<diesel::sql_types::Nullable<Text>>());
+        assert!(nullable);
+        assert_eq!(names, vec!["text", "varchar", "bpchar"]);
+    }
+
+    /// Table and column names of every `table!` block in `schema.rs`, in declaration order.
+    fn declared_tables() -> Vec<(String, Vec<String>)> {
+        let mut tables = Vec::new();
+        let mut lines = include_str!("schema.rs").lines();
+        while let Some(line) = lines.next() {
+            if line.trim() != "diesel::table! {" {
+                continue;
+            }
+            let mut table: Option<(String, Vec<String>)> = None;
+            for line in lines.by_ref() {
+                let line = line.trim();
+                if line == "}" {
+                    break;
+                }
+                if line.is_empty() || line.starts_with("use ") || line.starts_with("#[") {
+                    continue;
+                }
+                let name = line
+                    .split(|c: char| c.is_whitespace() || c == '(')
+                    .next()
+                    .unwrap()
+                    .to_string();
+                match table.as_mut() {
+                    None => table = Some((name, Vec::new())),
+                    Some((_, columns)) if line.contains("->") => columns.push(name),
+                    Some(_) => {}
+                }
+            }
+            tables.push(table.expect("table! block without a table"));
+        }
+        tables
+    }
+
+    #[test]
+    fn test_expected_schema_covers_schema_rs() {
+        let mut declared = declared_tables();
+        declared.sort();
+        let mut expected = expected_schema()
+            .into_iter()
+            .map(|(table, columns)| {
+                (
+                    table.to_string(),
+                    columns
+                        .iter()
+                        .map(|c| c.name.to_string())
+                        .collect(),
+                )
+            })
+            .collect::<Vec<(String, Vec<String>)>>();
+        expected.sort();
+
+        assert_eq!(expected, declared, "expected_schema() is out of sync with schema.rs");
+    }
+
+    #[test]
+    fn test_diff_schema() {
+        let expected = vec![
+            ("chain", table_columns::<schema::chain::table>()),
+            ("token_price", table_columns::<schema::token_price::table>()),
+        ];
+        let live = vec![
+            live("chain", "id", "int8", false),
+            live("chain", "name", "varchar", false),
+            live("chain", "inserted_ts", "timestamp", false),
+            live("chain", "modified_ts", "timestamptz", true),
+            live("chain", "extra", "text", true),
+        ];
+
+        let drift = diff_schema(&expected, &live);
+
+        assert_eq!(
+            drift,
+            vec![
+                SchemaDrift::TypeMismatch {
+                    table: "chain".to_string(),
+                    column: "inserted_ts".to_string(),
+                    expected: vec!["timestamptz".to_string()],
+                    actual: "timestamp".to_string(),
+                },
+                SchemaDrift::NullabilityMismatch {
+                    table: "chain".to_string(),
+                    column: "modified_ts".to_string(),
+                    expected_nullable: false,
+                },
+                SchemaDrift::MissingTable { table: "token_price".to_string() },
+            ]
+        );
+        assert_eq!(drift[1].to_string(), "column `chain.modified_ts` is NULL, expected NOT NULL");
+    }
+
+    #[tokio::test]
+    async fn test_check_schema() {
+        let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+
+        check_schema(&mut conn)
+            .await
+            .expect("schema.rs should match the migrations");
+    }
+}
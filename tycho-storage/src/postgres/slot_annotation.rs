@@ -0,0 +1,146 @@
+//! Annotations of contract storage slots.
+//!
+//! Annotations are keyed by chain, address and slot rather than by account, so they can be
+//! loaded for contracts that are not indexed yet and survive a contract being deleted and
+//! recreated.
+use diesel::{upsert::excluded, ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use itertools::Itertools;
+use tracing::instrument;
+use tycho_common::{
+    models::{contract::SlotAnnotation, Address, Chain},
+    storage::StorageError,
+    Bytes,
+};
+
+use super::{schema, PostgresError, PostgresGateway};
+
+impl PostgresGateway {
+    #[instrument(skip_all)]
+    pub async fn get_slot_annotations(
+        &self,
+        chain: &Chain,
+        addresses: &[Address],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<SlotAnnotation>, StorageError> {
+        use schema::slot_annotation::dsl;
+
+        let chain_id = self.get_chain_id(chain)?;
+        let rows = dsl::slot_annotation
+            .filter(dsl::chain_id.eq(chain_id))
+            .filter(dsl::address.eq_any(addresses))
+            .order_by((dsl::address, dsl::slot))
+            .select((dsl::address, dsl::slot, dsl::label, dsl::description))
+            .get_results::<(Address, Bytes, String, Option<String>)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(rows
+            .into_iter()
+            .map(|(address, slot, label, description)| SlotAnnotation {
+                chain: *chain,
+                address,
+                slot,
+                label,
+                description,
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all, fields(n_annotations = annotations.len()))]
+    pub async fn upsert_slot_annotations(
+        &self,
+        annotations: &[SlotAnnotation],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::slot_annotation::dsl;
+
+        for (chain, chain_annotations) in annotations
+            .iter()
+            .into_group_map_by(|a| a.chain)
+        {
+            let chain_id = self.get_chain_id(&chain)?;
+            let rows = chain_annotations
+                .iter()
+                .map(|a| {
+                    (
+                        dsl::chain_id.eq(chain_id),
+                        dsl::address.eq(&a.address),
+                        dsl::slot.eq(&a.slot),
+                        dsl::label.eq(&a.label),
+                        dsl::description.eq(&a.description),
+                    )
+                })
+                .collect::<Vec<_>>();
+            diesel::insert_into(dsl::slot_annotation)
+                .values(&rows)
+                .on_conflict((dsl::chain_id, dsl::address, dsl::slot))
+                .do_update()
+                .set((
+                    dsl::label.eq(excluded(dsl::label)),
+                    dsl::description.eq(excluded(dsl::description)),
+                ))
+                .execute(conn)
+                .await
+                .map_err(PostgresError::from)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{slice, str::FromStr};
+
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+    use crate::postgres::db_fixtures;
+
+    fn annotation(address: &Address, slot: u64, label: &str) -> SlotAnnotation {
+        SlotAnnotation {
+            chain: Chain::Ethereum,
+            address: address.clone(),
+            slot: Bytes::from(slot).lpad(32, 0),
+            label: label.to_string(),
+            description: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slot_annotations() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        let pool = Address::from_str("0x6b175474e89094c44da98b954eedeac495271d0f").unwrap();
+        let other = Address::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+
+        gw.upsert_slot_annotations(
+            &[
+                annotation(&pool, 1, "fee"),
+                annotation(&pool, 0, "owner"),
+                annotation(&other, 0, "x"),
+            ],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        let mut renamed = annotation(&pool, 1, "fee_bps");
+        renamed.description = Some("Swap fee in basis points".to_string());
+        gw.upsert_slot_annotations(slice::from_ref(&renamed), &mut conn)
+            .await
+            .unwrap();
+
+        let res = gw
+            .get_slot_annotations(&Chain::Ethereum, slice::from_ref(&pool), &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(res, vec![annotation(&pool, 0, "owner"), renamed]);
+    }
+}
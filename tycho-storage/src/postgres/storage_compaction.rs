@@ -0,0 +1,542 @@
+//! Periodic compaction of contract storage deletion rows.
+//!
+//! Clearing a storage slot is stored as a version with a `NULL` value. Slots of churny mappings
+//! are cleared and set again over and over, so these rows pile up although they are only needed
+//! to serve deltas ending while the slot was cleared. Once such a row was superseded by a new
+//! value before the finality horizon it is deleted: reads at any version still find no value for
+//! the slot, and the superseding row keeps `NULL` as its previous value, so deltas starting after
+//! the horizon are unaffected.
+//!
+//! Protocol systems with an [`AttributeRetentionPolicy`] additionally have their attribute
+//! history downsampled: of all versions of an attribute starting within the same time bucket
+//! only the last one is kept, stretched back to the start of the first. Reads within a bucket
+//! then return the value the attribute had at the end of the bucket. Only versions that ended
+//! before the policy's horizon are downsampled, recent history keeps every version.
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*,
+    sql_types::{Array, BigInt, Bytea, Double, Nullable, Text, Timestamptz},
+};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use tracing::{debug, error, info};
+use tycho_common::{
+    models::{Address, Chain},
+    storage::StorageError,
+};
+
+use super::{scheduler::PeriodicTask, PostgresError, PostgresGateway};
+
+/// Downsampling of the attribute history of a protocol system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeRetentionPolicy {
+    /// Width of the time buckets of which only the last version is kept.
+    pub granularity: chrono::Duration,
+    /// Versions ending less than this before the last finalized block are kept as is.
+    pub min_age: chrono::Duration,
+    /// Attributes to downsample, all attributes of the system if empty.
+    pub attributes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StorageCompactionConfig {
+    /// Whether to compact contract storage deletion rows.
+    pub compact_deleted_slots: bool,
+    /// Deletion rows superseded less than this before the last finalized block are kept.
+    pub min_age: chrono::Duration,
+    /// Maximum number of rows deleted per statement.
+    pub batch_size: i64,
+    /// Pause between compaction runs.
+    pub interval: std::time::Duration,
+    /// Attribute downsampling policies by protocol system.
+    pub attribute_retention: HashMap<String, AttributeRetentionPolicy>,
+}
+
+impl Default for StorageCompactionConfig {
+    fn default() -> Self {
+        Self {
+            compact_deleted_slots: true,
+            min_age: chrono::Duration::days(1),
+            batch_size: 10_000,
+            interval: std::time::Duration::from_secs(3600),
+            attribute_retention: HashMap::new(),
+        }
+    }
+}
+
+#[derive(QueryableByName, Debug)]
+struct CompactedAccount {
+    #[diesel(sql_type = Bytea)]
+    address: Address,
+    #[diesel(sql_type = BigInt)]
+    n_deleted: i64,
+}
+
+#[derive(QueryableByName, Debug)]
+struct DownsampledVersions {
+    #[diesel(sql_type = BigInt)]
+    n_deleted: i64,
+}
+
+impl PostgresGateway {
+    /// Downsamples the attribute history of `protocol_system` to one version per `granularity`
+    /// wide bucket. Only versions ending at or before `until` are considered, and only buckets
+    /// of attributes with a version that ended after `since` are revisited. Versions separated
+    /// by a deletion of the attribute are never merged. Returns the number of deleted versions.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn downsample_protocol_states(
+        &self,
+        chain: &Chain,
+        protocol_system: &str,
+        since: Option<NaiveDateTime>,
+        until: NaiveDateTime,
+        granularity: chrono::Duration,
+        attributes: &[String],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<i64, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let query = r#"
+            WITH touched AS (
+                SELECT ps.protocol_component_id AS component_id, ps.attribute_name AS name,
+                    min(date_bin(make_interval(secs => $4), ps.valid_from, TIMESTAMPTZ 'epoch'))
+                        AS from_bucket
+                FROM protocol_state ps
+                JOIN protocol_component pc ON pc.id = ps.protocol_component_id
+                JOIN protocol_system sys ON sys.id = pc.protocol_system_id
+                WHERE pc.chain_id = $1 AND sys.name = $2 AND ps.valid_to <= $3
+                    AND ($5::timestamptz IS NULL OR ps.valid_to > $5)
+                    AND (cardinality($6::text[]) = 0 OR ps.attribute_name = ANY($6))
+                GROUP BY ps.protocol_component_id, ps.attribute_name
+            ),
+            scoped AS (
+                SELECT ps.protocol_component_id AS component_id, ps.attribute_name AS name,
+                    ps.valid_from, ps.valid_to, ps.previous_value,
+                    date_bin(make_interval(secs => $4), ps.valid_from, TIMESTAMPTZ 'epoch')
+                        AS bucket,
+                    ps.valid_from IS DISTINCT FROM lag(ps.valid_to) OVER (
+                        PARTITION BY ps.protocol_component_id, ps.attribute_name
+                        ORDER BY ps.valid_from
+                    ) AS starts_run
+                FROM protocol_state ps
+                JOIN touched t
+                    ON t.component_id = ps.protocol_component_id AND t.name = ps.attribute_name
+                WHERE ps.valid_from >= t.from_bucket AND ps.valid_to <= $3
+            ),
+            runs AS (
+                SELECT *, count(*) FILTER (WHERE starts_run) OVER (
+                    PARTITION BY component_id, name ORDER BY valid_from
+                ) AS run
+                FROM scoped
+            ),
+            groups AS (
+                SELECT component_id, name, min(valid_from) AS first_from,
+                    max(valid_to) AS last_to,
+                    (array_agg(previous_value ORDER BY valid_from))[1] AS first_previous
+                FROM runs
+                GROUP BY component_id, name, bucket, run
+                HAVING count(*) > 1
+            ),
+            deleted AS (
+                DELETE FROM protocol_state ps
+                USING groups g
+                WHERE ps.protocol_component_id = g.component_id AND ps.attribute_name = g.name
+                    AND ps.valid_from >= g.first_from AND ps.valid_to < g.last_to
+                RETURNING 1
+            ),
+            kept AS (
+                UPDATE protocol_state ps
+                SET valid_from = g.first_from, previous_value = g.first_previous
+                FROM groups g
+                WHERE ps.protocol_component_id = g.component_id AND ps.attribute_name = g.name
+                    AND ps.valid_to = g.last_to
+                RETURNING 1
+            )
+            SELECT (SELECT count(*) FROM deleted) AS n_deleted
+            "#;
+        let downsampled = diesel::sql_query(query)
+            .bind::<BigInt, _>(chain_id)
+            .bind::<Text, _>(protocol_system)
+            .bind::<Timestamptz, _>(until)
+            .bind::<Double, _>(granularity.num_seconds() as f64)
+            .bind::<Nullable<Timestamptz>, _>(since)
+            .bind::<Array<Text>, _>(attributes)
+            .get_result::<DownsampledVersions>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(downsampled.n_deleted)
+    }
+
+    /// Deletes up to `batch_size` storage rows of `chain` that cleared a slot and were superseded
+    /// by a new value before `cutoff`. Returns the number of deleted rows per account.
+    pub(crate) async fn compact_deleted_slots(
+        &self,
+        chain: &Chain,
+        cutoff: NaiveDateTime,
+        batch_size: i64,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<Address, i64>, StorageError> {
+        let chain_id = self.get_chain_id(chain)?;
+        let query = r#"
+            WITH compacted AS (
+                DELETE FROM contract_storage cs
+                USING (
+                    SELECT c.account_id, c.slot, c.valid_to
+                    FROM contract_storage c
+                    JOIN account a ON a.id = c.account_id
+                    WHERE a.chain_id = $1 AND c.value IS NULL AND c.valid_to < $2
+                        AND EXISTS (
+                            SELECT 1 FROM contract_storage n
+                            WHERE n.account_id = c.account_id AND n.slot = c.slot
+                                AND n.valid_from = c.valid_to AND n.value IS NOT NULL
+                        )
+                    LIMIT $3
+                ) batch
+                WHERE cs.account_id = batch.account_id AND cs.slot = batch.slot
+                    AND cs.valid_to = batch.valid_to
+                RETURNING cs.account_id
+            )
+            SELECT a.address, count(*) AS n_deleted
+            FROM compacted JOIN account a ON a.id = compacted.account_id
+            GROUP BY a.address
+            "#;
+        let compacted = diesel::sql_query(query)
+            .bind::<BigInt, _>(chain_id)
+            .bind::<Timestamptz, _>(cutoff)
+            .bind::<BigInt, _>(batch_size)
+            .load::<CompactedAccount>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(compacted
+            .into_iter()
+            .map(|row| (row.address, row.n_deleted))
+            .collect())
+    }
+}
+
+pub(crate) struct StorageCompactor {
+    pool: Pool<AsyncPgConnection>,
+    gateway: PostgresGateway,
+    chain: Chain,
+    config: StorageCompactionConfig,
+    /// Horizon of the last downsampling of each protocol system. Versions that ended before it
+    /// were already downsampled.
+    downsampled_until: HashMap<String, NaiveDateTime>,
+}
+
+impl StorageCompactor {
+    pub(crate) fn new(
+        pool: Pool<AsyncPgConnection>,
+        gateway: PostgresGateway,
+        chain: Chain,
+        config: StorageCompactionConfig,
+    ) -> Self {
+        Self { pool, gateway, chain, config, downsampled_until: HashMap::new() }
+    }
+
+    /// Downsamples the attribute history of every protocol system with a retention policy.
+    /// Returns the number of deleted versions per protocol system.
+    async fn downsample(&mut self) -> Result<HashMap<String, i64>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let finalized = self
+            .gateway
+            .get_finalized_block(&self.chain, &mut conn)
+            .await?;
+
+        let mut savings = HashMap::new();
+        for (system, policy) in &self.config.attribute_retention {
+            let horizon = finalized.ts - policy.min_age;
+            let since = self
+                .downsampled_until
+                .get(system)
+                .copied();
+            if since.is_some_and(|since| since >= horizon) {
+                continue;
+            }
+            let n_deleted = self
+                .gateway
+                .downsample_protocol_states(
+                    &self.chain,
+                    system,
+                    since,
+                    horizon,
+                    policy.granularity,
+                    &policy.attributes,
+                    &mut conn,
+                )
+                .await?;
+            self.downsampled_until
+                .insert(system.clone(), horizon);
+            savings.insert(system.clone(), n_deleted);
+        }
+        Ok(savings)
+    }
+
+    /// Compacts batches until no compactable row is left. Returns the number of deleted rows per
+    /// account.
+    async fn compact(&self) -> Result<HashMap<Address, i64>, StorageError> {
+        let mut conn =
+            self.pool.get().await.map_err(|e| {
+                StorageError::Unexpected(format!("Failed to retrieve connection: {e}"))
+            })?;
+        let finalized = self
+            .gateway
+            .get_finalized_block(&self.chain, &mut conn)
+            .await?;
+        let cutoff = finalized.ts - self.config.min_age;
+
+        let mut savings: HashMap<Address, i64> = HashMap::new();
+        loop {
+            let batch = self
+                .gateway
+                .compact_deleted_slots(&self.chain, cutoff, self.config.batch_size, &mut conn)
+                .await?;
+            let n_deleted: i64 = batch.values().sum();
+            for (address, n) in batch {
+                *savings.entry(address).or_default() += n;
+            }
+            if n_deleted < self.config.batch_size {
+                return Ok(savings);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PeriodicTask for StorageCompactor {
+    async fn run_once(&mut self) -> Result<(), StorageError> {
+        if self.config.compact_deleted_slots {
+            // The downsampling doesn't depend on the compaction, so it runs regardless.
+            match self.compact().await {
+                Ok(savings) => {
+                    for (address, n_deleted) in &savings {
+                        info!(
+                            chain = %self.chain,
+                            %address,
+                            n_deleted,
+                            "Compacted deleted storage slots"
+                        );
+                    }
+                    debug!(
+                        n_accounts = savings.len(),
+                        n_deleted = savings.values().sum::<i64>(),
+                        "Compacted contract storage"
+                    );
+                }
+                Err(err) => {
+                    error!(error = %err, "Failed to compact contract storage");
+                }
+            }
+        }
+        let savings = self.downsample().await?;
+        for (system, n_deleted) in &savings {
+            info!(chain = %self.chain, system, n_deleted, "Downsampled protocol state history");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+    use crate::postgres::{db_fixtures, schema, MAX_TS};
+
+    type EVMGateway = PostgresGateway;
+
+    async fn setup_db() -> AsyncPgConnection {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        conn
+    }
+
+    #[tokio::test]
+    async fn test_compact_deleted_slots() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[(blk[0], 1i64, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945")],
+        )
+        .await;
+        let address = "6b175474e89094c44da98b954eedeac495271d0f";
+        let account_id =
+            db_fixtures::insert_account(&mut conn, address, "account", chain_id, Some(txn[0]))
+                .await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let ts = |hour: u32| {
+            chrono::NaiveDate::from_ymd_opt(2020, 1, 1)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+        };
+        // Slot 1 is cleared and set again, slot 2 stays cleared
+        let versions = [
+            (1u8, Some(1u8), None, ts(0), ts(1)),
+            (1, None, Some(1), ts(1), ts(2)),
+            (1, Some(2), None, ts(2), MAX_TS),
+            (2, Some(1), None, ts(0), ts(1)),
+            (2, None, Some(1), ts(1), MAX_TS),
+        ];
+        let rows = versions
+            .iter()
+            .map(|(slot, value, previous_value, valid_from, valid_to)| {
+                (
+                    schema::contract_storage::slot.eq(vec![*slot]),
+                    schema::contract_storage::value.eq(value.map(|v| vec![v])),
+                    schema::contract_storage::previous_value.eq(previous_value.map(|v| vec![v])),
+                    schema::contract_storage::account_id.eq(account_id),
+                    schema::contract_storage::modify_tx.eq(txn[0]),
+                    schema::contract_storage::ordinal.eq(0),
+                    schema::contract_storage::valid_from.eq(*valid_from),
+                    schema::contract_storage::valid_to.eq(*valid_to),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(schema::contract_storage::table)
+            .values(&rows)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let before_horizon = gw
+            .compact_deleted_slots(&Chain::Ethereum, ts(2), 10, &mut conn)
+            .await
+            .unwrap();
+        let compacted = gw
+            .compact_deleted_slots(&Chain::Ethereum, ts(3), 10, &mut conn)
+            .await
+            .unwrap();
+        let remaining = schema::contract_storage::table
+            .filter(schema::contract_storage::account_id.eq(account_id))
+            .filter(schema::contract_storage::value.is_null())
+            .select(schema::contract_storage::slot)
+            .get_results::<Vec<u8>>(&mut conn)
+            .await
+            .unwrap();
+
+        assert!(before_horizon.is_empty());
+        assert_eq!(compacted, HashMap::from([(Address::from(address), 1)]));
+        assert_eq!(remaining, vec![vec![2u8]]);
+    }
+
+    #[tokio::test]
+    async fn test_downsample_protocol_states() {
+        let mut conn = setup_db().await;
+        let chain_id = db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let blk = db_fixtures::insert_blocks(&mut conn, chain_id).await;
+        let txn = db_fixtures::insert_txns(
+            &mut conn,
+            &[(blk[0], 1i64, "0xbb7e16d797a9e2fbc537e30f91ed3d27a254dd9578aa4c3af3e5f0d3e8130945")],
+        )
+        .await;
+        let system_id = db_fixtures::insert_protocol_system(&mut conn, "ambient".to_string()).await;
+        let type_id = db_fixtures::insert_protocol_type(&mut conn, "pool", None, None, None).await;
+        let component_id = db_fixtures::insert_protocol_component(
+            &mut conn, "pool_1", chain_id, system_id, type_id, txn[0], None, None,
+        )
+        .await;
+        let gw = EVMGateway::from_connection(&mut conn).await;
+
+        let ts = |day: u32, hour: u32| {
+            chrono::NaiveDate::from_ymd_opt(2020, 1, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+        };
+        let versions = [
+            ("price", 1u8, None, ts(1, 0), ts(1, 6)),
+            ("price", 2, Some(1u8), ts(1, 6), ts(1, 12)),
+            ("price", 3, Some(2), ts(1, 12), ts(2, 3)),
+            ("price", 4, Some(3), ts(2, 3), ts(2, 5)),
+            ("price", 5, Some(4), ts(2, 5), MAX_TS),
+            ("liquidity", 1, None, ts(1, 0), ts(1, 6)),
+            ("liquidity", 2, Some(1), ts(1, 6), MAX_TS),
+        ];
+        let rows = versions
+            .iter()
+            .map(|(name, value, previous_value, valid_from, valid_to)| {
+                (
+                    schema::protocol_state::protocol_component_id.eq(component_id),
+                    schema::protocol_state::attribute_name.eq(*name),
+                    schema::protocol_state::attribute_value.eq(vec![*value]),
+                    schema::protocol_state::previous_value.eq(previous_value.map(|v| vec![v])),
+                    schema::protocol_state::modify_tx.eq(txn[0]),
+                    schema::protocol_state::valid_from.eq(*valid_from),
+                    schema::protocol_state::valid_to.eq(*valid_to),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(schema::protocol_state::table)
+            .values(&rows)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let attributes = vec!["price".to_string()];
+
+        // The second run merges versions that ended since the first into the same bucket
+        let first = gw
+            .downsample_protocol_states(
+                &Chain::Ethereum,
+                "ambient",
+                None,
+                ts(2, 0),
+                chrono::Duration::days(1),
+                &attributes,
+                &mut conn,
+            )
+            .await
+            .unwrap();
+        let second = gw
+            .downsample_protocol_states(
+                &Chain::Ethereum,
+                "ambient",
+                Some(ts(2, 0)),
+                ts(2, 6),
+                chrono::Duration::days(1),
+                &attributes,
+                &mut conn,
+            )
+            .await
+            .unwrap();
+        let remaining = schema::protocol_state::table
+            .filter(schema::protocol_state::protocol_component_id.eq(component_id))
+            .order_by((schema::protocol_state::attribute_name, schema::protocol_state::valid_from))
+            .select((
+                schema::protocol_state::attribute_name,
+                schema::protocol_state::attribute_value,
+                schema::protocol_state::previous_value,
+                schema::protocol_state::valid_from,
+                schema::protocol_state::valid_to,
+            ))
+            .get_results::<(String, Vec<u8>, Option<Vec<u8>>, NaiveDateTime, NaiveDateTime)>(
+                &mut conn,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!((first, second), (1, 1));
+        assert_eq!(
+            remaining,
+            vec![
+                ("liquidity".to_string(), vec![1], None, ts(1, 0), ts(1, 6)),
+                ("liquidity".to_string(), vec![2], Some(vec![1]), ts(1, 6), MAX_TS),
+                ("price".to_string(), vec![3], None, ts(1, 0), ts(2, 3)),
+                ("price".to_string(), vec![4], Some(vec![3]), ts(2, 3), ts(2, 5)),
+                ("price".to_string(), vec![5], Some(vec![4]), ts(2, 5), MAX_TS),
+            ]
+        );
+    }
+}
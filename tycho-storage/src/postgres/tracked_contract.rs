@@ -0,0 +1,149 @@
+//! Contracts tracked by extractors.
+//!
+//! Rows are keyed by extractor, chain and address rather than by account, so contracts can be
+//! tracked before they are indexed. The orphaned account collection keeps accounts of contracts
+//! that are still tracked.
+use diesel::{upsert::excluded, ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tracing::instrument;
+use tycho_common::{
+    models::{contract::TrackedContract, Address, Chain},
+    storage::StorageError,
+};
+
+use super::{schema, PostgresError, PostgresGateway};
+
+impl PostgresGateway {
+    #[instrument(skip(self, conn))]
+    pub async fn get_tracked_contracts(
+        &self,
+        extractor: &str,
+        chain: &Chain,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<TrackedContract>, StorageError> {
+        use schema::tracked_contract::dsl;
+
+        let chain_id = self.get_chain_id(chain)?;
+        let rows = dsl::tracked_contract
+            .filter(dsl::chain_id.eq(chain_id))
+            .filter(dsl::extractor.eq(extractor))
+            .order_by(dsl::address)
+            .select((dsl::address, dsl::from_block, dsl::until_block))
+            .get_results::<(Address, i64, Option<i64>)>(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(rows
+            .into_iter()
+            .map(|(address, from_block, until_block)| TrackedContract {
+                address,
+                from_block: from_block as u64,
+                until_block: until_block.map(|block| block as u64),
+            })
+            .collect())
+    }
+
+    /// Stores the tracking windows of `contracts`, replacing the stored windows of the same
+    /// contracts.
+    #[instrument(skip(self, contracts, conn), fields(n_contracts = contracts.len()))]
+    pub async fn upsert_tracked_contracts(
+        &self,
+        extractor: &str,
+        chain: &Chain,
+        contracts: &[TrackedContract],
+        conn: &mut AsyncPgConnection,
+    ) -> Result<(), StorageError> {
+        use schema::tracked_contract::dsl;
+
+        if contracts.is_empty() {
+            return Ok(());
+        }
+        let chain_id = self.get_chain_id(chain)?;
+        let rows = contracts
+            .iter()
+            .map(|c| {
+                (
+                    dsl::extractor.eq(extractor),
+                    dsl::chain_id.eq(chain_id),
+                    dsl::address.eq(&c.address),
+                    dsl::from_block.eq(c.from_block as i64),
+                    dsl::until_block.eq(c.until_block.map(|block| block as i64)),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(dsl::tracked_contract)
+            .values(&rows)
+            .on_conflict((dsl::chain_id, dsl::extractor, dsl::address))
+            .do_update()
+            .set((
+                dsl::from_block.eq(excluded(dsl::from_block)),
+                dsl::until_block.eq(excluded(dsl::until_block)),
+            ))
+            .execute(conn)
+            .await
+            .map_err(PostgresError::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use diesel_async::AsyncConnection;
+
+    use super::*;
+    use crate::postgres::db_fixtures;
+
+    #[tokio::test]
+    async fn test_tracked_contracts() {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = AsyncPgConnection::establish(&db_url)
+            .await
+            .unwrap();
+        conn.begin_test_transaction()
+            .await
+            .unwrap();
+        db_fixtures::insert_chain(&mut conn, "ethereum").await;
+        let gw = PostgresGateway::from_connection(&mut conn).await;
+        let router = Address::from_str("0x6b175474e89094c44da98b954eedeac495271d0f").unwrap();
+        let vault = Address::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+
+        gw.upsert_tracked_contracts(
+            "uniswap_v2",
+            &Chain::Ethereum,
+            &[
+                TrackedContract { address: router.clone(), from_block: 10, until_block: None },
+                TrackedContract { address: vault.clone(), from_block: 5, until_block: None },
+            ],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        gw.upsert_tracked_contracts(
+            "uniswap_v2",
+            &Chain::Ethereum,
+            &[TrackedContract { address: router.clone(), from_block: 10, until_block: Some(20) }],
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        let tracked = gw
+            .get_tracked_contracts("uniswap_v2", &Chain::Ethereum, &mut conn)
+            .await
+            .unwrap();
+        let other_extractor = gw
+            .get_tracked_contracts("uniswap_v3", &Chain::Ethereum, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tracked,
+            vec![
+                TrackedContract { address: router, from_block: 10, until_block: Some(20) },
+                TrackedContract { address: vault, from_block: 5, until_block: None },
+            ]
+        );
+        assert!(other_extractor.is_empty());
+    }
+}
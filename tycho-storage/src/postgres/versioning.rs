@@ -59,7 +59,7 @@ use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use tracing::trace;
 use tycho_common::storage::StorageError;
 
-use crate::postgres::PostgresError;
+use crate::postgres::{batching::param_chunks, PostgresError};
 
 /// Trait indicating that a struct can be inserted into a versioned table.
 ///
@@ -265,8 +265,9 @@ where
     let db_rows = S::latest_versions_by_ids(end_versions.keys().cloned(), conn)
         .await
         .map_err(PostgresError::from)?;
-    if !db_rows.is_empty() {
-        build_batch_update_query(&db_rows, S::table_name(), &end_versions)
+    // each row binds its primary key and end version
+    for chunk in param_chunks(&db_rows, 2) {
+        build_batch_update_query(chunk, S::table_name(), &end_versions)
             .execute(conn)
             .await
             .map_err(PostgresError::from)?;